@@ -1,5 +1,8 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use fog_pack::{document::*, schema::NoSchema};
+use fog_pack::{
+    document::*,
+    schema::{Encoder, NoSchema},
+};
 use rand::Rng;
 use std::mem;
 use std::ops;
@@ -206,6 +209,26 @@ fn write_to_docs(logs: &[Log]) -> Vec<NewDocument> {
         .unwrap()
 }
 
+fn encode_each_log(logs: &[Log]) -> Vec<Vec<u8>> {
+    logs.iter()
+        .map(|log| {
+            let doc = NewDocument::new(None, log).unwrap();
+            let doc = NoSchema::validate_new_doc(doc).unwrap();
+            NoSchema::encode_doc(doc).unwrap().1
+        })
+        .collect()
+}
+
+fn encode_each_log_reused(encoder: &mut Encoder, logs: &[Log]) -> Vec<Vec<u8>> {
+    logs.iter()
+        .map(|log| {
+            let doc = encoder.new_doc(None, log).unwrap();
+            let doc = NoSchema::validate_new_doc(doc).unwrap();
+            encoder.encode_doc(None, doc).unwrap().1
+        })
+        .collect()
+}
+
 fn criterion_benchmark(c: &mut Criterion) {
     // Generate a whole pile of log items
     let mut rng = rand::thread_rng();
@@ -225,6 +248,13 @@ fn criterion_benchmark(c: &mut Criterion) {
 
     c.bench_function("write_logs", |b| b.iter(|| write_to_docs(black_box(&logs))));
     c.bench_function("read_logs", |b| b.iter(|| read_all_docs(black_box(&docs))));
+    c.bench_function("encode_each_log", |b| {
+        b.iter(|| encode_each_log(black_box(&logs)))
+    });
+    let mut encoder = Encoder::new();
+    c.bench_function("encode_each_log_reused", |b| {
+        b.iter(|| encode_each_log_reused(&mut encoder, black_box(&logs)))
+    });
 }
 
 criterion_group!(benches, criterion_benchmark);
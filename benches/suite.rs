@@ -0,0 +1,184 @@
+//! The main benchmark suite: encoding, decoding, validation, and signing of a few representative
+//! document shapes, along with query checking against entries.
+//!
+//! Run with `cargo bench --bench suite`. Each document shape gets its own benchmark group, so
+//! a single shape can be run with e.g. `cargo bench --bench suite -- nested`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use fog_pack::{document::*, entry::*, query::*, schema::*, types::IdentityKey, validator::*};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Log {
+    address: [u8; 4],
+    code: u16,
+    date: String,
+    request: String,
+    size: u64,
+    user: String,
+}
+
+fn generate_log<R: Rng>(rng: &mut R) -> Log {
+    const USERS: [&str; 5] = ["-", "alice", "bob", "carmen", "david"];
+    const ROUTES: [&str; 4] = ["/", "/index.html", "/api/login", "/img/logo.svg"];
+    Log {
+        address: rng.gen(),
+        code: [200, 301, 404, 500][rng.gen_range(0..4)],
+        date: format!(
+            "{:02}/Jan/20{:02}:{:02}:{:02}:{:02} +0000",
+            rng.gen_range(1..29),
+            rng.gen_range(0..24),
+            rng.gen_range(0..24),
+            rng.gen_range(0..60),
+            rng.gen_range(0..60)
+        ),
+        request: format!("GET {} HTTP/1.1", ROUTES[rng.gen_range(0..ROUTES.len())]),
+        size: rng.gen_range(0..1_000_000),
+        user: USERS[rng.gen_range(0..USERS.len())].into(),
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Nested {
+    child: Option<Box<Nested>>,
+    name: String,
+    values: Vec<u32>,
+}
+
+fn generate_nested<R: Rng>(rng: &mut R, depth: usize) -> Nested {
+    Nested {
+        child: (depth > 0).then(|| Box::new(generate_nested(rng, depth - 1))),
+        name: format!("level {}", depth),
+        values: (0..16).map(|_| rng.gen()).collect(),
+    }
+}
+
+// Each shape of document, encoded as a NewDocument ready for validation
+fn documents(schema: &Schema) -> Vec<(&'static str, NewDocument)> {
+    let mut rng = rand::thread_rng();
+    let logs: Vec<Log> = (0..1000).map(|_| generate_log(&mut rng)).collect();
+    let blob: Vec<u8> = (0..(256 * 1024))
+        .map(|i| (i % 251) as u8 ^ rng.gen::<u8>() & 0x3)
+        .collect();
+    let nested: Vec<Nested> = (0..8).map(|_| generate_nested(&mut rng, 32)).collect();
+    let hash = Some(schema.hash());
+    vec![
+        ("logs", NewDocument::new(hash, &logs).unwrap()),
+        (
+            "blob",
+            NewDocument::new(hash, serde_bytes::Bytes::new(&blob)).unwrap(),
+        ),
+        ("nested", NewDocument::new(hash, &nested).unwrap()),
+    ]
+}
+
+fn document_benchmark(c: &mut Criterion) {
+    let schema_doc = SchemaBuilder::new(Validator::Any).build().unwrap();
+    let schema = Schema::from_doc(&schema_doc).unwrap();
+    let key = IdentityKey::new();
+
+    for (name, new_doc) in documents(&schema) {
+        let doc = schema.validate_new_doc(new_doc.clone()).unwrap();
+        let (_, encoded) = schema.encode_doc(doc.clone()).unwrap();
+
+        let mut group = c.benchmark_group(name);
+        group.throughput(Throughput::Bytes(encoded.len() as u64));
+        group.bench_function("validate", |b| {
+            b.iter(|| schema.validate_new_doc(black_box(new_doc.clone())).unwrap())
+        });
+        group.bench_function("encode", |b| {
+            b.iter(|| schema.encode_doc(black_box(doc.clone())).unwrap())
+        });
+        group.bench_function("decode", |b| {
+            b.iter(|| schema.decode_doc(black_box(encoded.clone())).unwrap())
+        });
+        group.bench_function("sign", |b| {
+            b.iter(|| black_box(new_doc.clone()).sign(&key).unwrap())
+        });
+        group.finish();
+    }
+}
+
+fn query_benchmark(c: &mut Criterion) {
+    let log_validator = MapValidator::new()
+        .req_add("address", Validator::Any)
+        .req_add("code", IntValidator::new().query(true).ord(true).build())
+        .req_add("date", StrValidator::new().build())
+        .req_add("request", StrValidator::new().build())
+        .req_add("size", IntValidator::new().query(true).ord(true).build())
+        .req_add("user", StrValidator::new().query(true).build())
+        .query(true)
+        .build();
+    let schema_doc = SchemaBuilder::new(Validator::Any)
+        .entry_add("log", log_validator, None)
+        .build()
+        .unwrap();
+    let schema = Schema::from_doc(&schema_doc).unwrap();
+    let parent = NewDocument::new(Some(schema.hash()), "log parent").unwrap();
+    let parent = schema.validate_new_doc(parent).unwrap();
+
+    let mut rng = rand::thread_rng();
+    let entries: Vec<Entry> = (0..1000)
+        .map(|_| {
+            let entry = NewEntry::new("log", &parent, generate_log(&mut rng)).unwrap();
+            schema
+                .validate_new_entry(entry)
+                .unwrap()
+                .complete()
+                .unwrap()
+        })
+        .collect();
+
+    let queries = [
+        (
+            "code",
+            MapValidator::new()
+                .req_add("code", IntValidator::new().min(400).build())
+                .build(),
+        ),
+        (
+            "user",
+            MapValidator::new()
+                .req_add("user", StrValidator::new().in_add("alice").build())
+                .build(),
+        ),
+    ];
+
+    let mut group = c.benchmark_group("query");
+    group.throughput(Throughput::Elements(entries.len() as u64));
+    for (name, validator) in queries {
+        let encoded = schema
+            .encode_query(NewQuery::new("log", validator.clone()))
+            .unwrap();
+        let query = schema.decode_query(encoded.clone()).unwrap();
+        group.bench_with_input(BenchmarkId::new("check", name), &validator, |b, v| {
+            b.iter(|| {
+                schema
+                    .encode_query(NewQuery::new("log", black_box(v.clone())))
+                    .unwrap()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("decode", name), &encoded, |b, e| {
+            b.iter(|| schema.decode_query(black_box(e.clone())).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("run", name), &entries, |b, entries| {
+            b.iter(|| {
+                entries
+                    .iter()
+                    .filter(|e| {
+                        query
+                            .query(e)
+                            .ok()
+                            .and_then(|list| list.complete().ok())
+                            .is_some()
+                    })
+                    .count()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, document_benchmark, query_benchmark);
+criterion_main!(benches);
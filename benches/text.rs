@@ -0,0 +1,111 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use fog_pack::{document::*, schema::*, validator::Validator};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// A mix of ASCII and multi-byte text, so UTF-8 validation can't stay on an ASCII-only path.
+const WORDS: [&str; 12] = [
+    "lorem",
+    "ipsum",
+    "dolor",
+    "sit",
+    "amet",
+    "café",
+    "naïve",
+    "straße",
+    "日本語",
+    "текст",
+    "ελληνικά",
+    "🦀",
+];
+
+fn generate_text<R: Rng>(rng: &mut R, words: usize) -> String {
+    let mut text = String::new();
+    for _ in 0..words {
+        if !text.is_empty() {
+            text.push(' ');
+        }
+        text.push_str(WORDS[rng.gen_range(0..WORDS.len())]);
+    }
+    text
+}
+
+fn generate_doc<R: Rng>(rng: &mut R) -> BTreeMap<String, Vec<String>> {
+    (0..1000)
+        .map(|i| {
+            let key = format!("section {:04} {}", i, generate_text(rng, 3));
+            let paragraphs = (0..4).map(|_| generate_text(rng, 40)).collect();
+            (key, paragraphs)
+        })
+        .collect()
+}
+
+#[derive(Serialize, Deserialize)]
+struct Owned {
+    blobs: Vec<serde_bytes::ByteBuf>,
+    text: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Borrowed<'a> {
+    #[serde(borrow)]
+    blobs: Vec<&'a serde_bytes::Bytes>,
+    #[serde(borrow)]
+    text: Vec<&'a str>,
+}
+
+// Compare owned and borrowed deserialization of a document that went through a schema's decode,
+// which decompresses into a buffer owned by the document.
+fn borrowed_benchmark(c: &mut Criterion) {
+    let mut rng = rand::thread_rng();
+    let data = Owned {
+        blobs: (0..256)
+            .map(|_| serde_bytes::ByteBuf::from(vec![rng.gen::<u8>(); 1024]))
+            .collect(),
+        text: (0..1000).map(|_| generate_text(&mut rng, 40)).collect(),
+    };
+    let schema_doc = SchemaBuilder::new(Validator::Any).build().unwrap();
+    let schema = Schema::from_doc(&schema_doc).unwrap();
+    let doc = NewDocument::new(Some(schema.hash()), &data).unwrap();
+    let doc = schema.validate_new_doc(doc).unwrap();
+    let (_, encoded) = schema.encode_doc(doc).unwrap();
+    let doc = schema.decode_doc(encoded).unwrap();
+
+    let mut group = c.benchmark_group("schema_decoded");
+    group.bench_function("deserialize_owned", |b| {
+        b.iter(|| black_box(&doc).deserialize::<Owned>().unwrap())
+    });
+    group.bench_function("deserialize_borrowed", |b| {
+        b.iter(|| black_box(&doc).deserialize::<Borrowed>().unwrap())
+    });
+    group.finish();
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let mut rng = rand::thread_rng();
+    let data = generate_doc(&mut rng);
+    let doc = NewDocument::new(None, &data).unwrap().compression(None);
+    let doc = NoSchema::validate_new_doc(doc).unwrap();
+    let (_, encoded) = NoSchema::encode_doc(doc.clone()).unwrap();
+
+    let mut group = c.benchmark_group("text");
+    group.throughput(Throughput::Bytes(encoded.len() as u64));
+    group.bench_function("decode", |b| {
+        b.iter(|| NoSchema::decode_doc(black_box(encoded.clone())).unwrap())
+    });
+    group.bench_function("deserialize", |b| {
+        b.iter(|| {
+            black_box(&doc)
+                .deserialize::<BTreeMap<&str, Vec<&str>>>()
+                .unwrap()
+        })
+    });
+    group.bench_function("encode", |b| {
+        b.iter(|| NewDocument::new(None, black_box(&data)).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark, borrowed_benchmark);
+criterion_main!(benches);
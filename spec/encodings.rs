@@ -8,46 +8,68 @@ compression marker to indicate what compression was used, if any.
 # Compression Marker
 
 This is a single byte at the start of a Document or Entry indicating the 
-compression algorithm used, along with whether a schema hash is present in the 
-encoded Document.
+compression algorithm used, if any.
 
 ```text
 +----------+
-| 0XXXXXYY |
+| XXXXXXYY |
 +----------+
 
-XXXXX is the 5-bit compression algorithm code
+XXXXXX is the 6-bit compression algorithm code
 YY is the compression type marker
 ```
 
-Currently, only one algorithm is supported, zstd, which has a code of 0.
-
 The compression type marker can be one of the following:
 
-| Marker | Name               | Description                                 |
-| --     | --                 | --                                          |
-| 0b00   | Uncompressed       | No compression was performed                |
-| 0b01   | CompressedNoSchema | Compression without including a schema hash |
-| 0b10   | Compressed         | Compression with a schema hash included     |
-| 0b11   | DictCompressed     | Compression using a dictionary              |
+| Marker | Name           | Description                                         |
+| --     | --             | --                                                  |
+| 0b00   | Uncompressed   | No compression was performed                        |
+| 0b01   | General        | Compression with the algorithm given by XXXXXX      |
+| 0b10   | DictCompressed | Compression using a dictionary from the schema      |
+| 0b11   | (reserved)     | Never a compression marker; marks a format version  |
+
+For Uncompressed and DictCompressed, the algorithm bits are always 0. Two 
+general algorithms are currently defined: zstd, with a code of 0, and lz4, with 
+a code of 1. Dictionaries are always zstd dictionaries.
+
+# Format Version
+
+A Document or Entry may start with an optional format version byte, placed 
+before the compression marker. It's recognized by its lower 2 bits being 
+`0b11`, which a compression marker never uses.
+
+```text
++----------+
+| VVVVVV11 |
++----------+
+
+VVVVVV is the 6-bit format version
+```
+
+If there is no version byte, the format version is 0, which is the version 
+described here. Version 0 is always written without a version byte. Decoders 
+must reject any version newer than the ones they know how to read.
 
 # Documents
 
-Encoded documents always start with a compression marker byte, and are followed 
-by a 3-byte big-endian integer indicating the length of the data payload. The 
-data payload follows, and the last portion of the document are any signatures 
-appended to the document. Note that the total size of the document cannot be 
+Encoded documents always start with a compression marker byte, followed by a 
+byte holding the length of the schema hash (0 if there is no schema, and never 
+more than 127), and then the schema hash itself, encoded like the Hash type but 
+without the ext family wrapper. Next is a 3-byte little-endian integer 
+indicating the length of the data payload. The data payload follows, and the 
+last portion of the document are any signatures appended to the document. Note that the total size of the document cannot be 
 calculated from the data itself - it is assumed that an encapsulating protocol 
 will include the length of the document.
 
 ```text
-+----------+----------+----------+----------+==========+============+
-| 0XXXXXYY | ZZZZZZZZ | ZZZZZZZZ | ZZZZZZZZ |   Data   | Signatures |
-+----------+----------+----------+----------+==========+============+
++----------+----------+========+----------+----------+----------+==========+============+
+| XXXXXXYY | 0HHHHHHH | Schema | ZZZZZZZZ | ZZZZZZZZ | ZZZZZZZZ |   Data   | Signatures |
++----------+----------+========+----------+----------+----------+==========+============+
 
-- XXXXX is the 5-bit compression algorithm code
+- XXXXXX is the 6-bit compression algorithm code
 - YY is the compression type marker
-- ZZZZZZZZ_ZZZZZZZZ_ZZZZZZZZ is a 24-bit big-endian unsigned integer 
+- HHHHHHH is the length of the schema hash
+- ZZZZZZZZ_ZZZZZZZZ_ZZZZZZZZ is a 24-bit little-endian unsigned integer 
     representing the length of the Data.
 
 ```
@@ -55,19 +77,26 @@ will include the length of the document.
 The data payload depends on the compression type indicated:
 
 - Uncompressed: No compression used. The document's raw value is the data payload.
-- CompressedNoSchema: The payload is a single zstd frame, containing the 
-    compressed version of the document's raw value. The raw value should not 
-    contain the empty string as a top-level field - this would be a coding 
-    error.
-- Compressed: The payload starts with the object marker (with size), then the 
-    first field-value pair of the object, and finally a zstd frame containing 
-    the remainder of the raw value.  The first field-value pair *must* be the 
-    empty string and the hash of a schema.
-- DictCompressed: Same as Compressed, except that the zstd frame was made using 
-    a dictionary embedded in the schema used by the document.
-
-For all of the above, the zstd frame always contains the estimated decompressed 
-length.
+- General: The payload is the document's raw value, compressed with the 
+    algorithm given in the marker. For zstd, this is a single frame.
+- DictCompressed: The payload is a single zstd frame, made using the 
+    dictionary embedded in the schema used by the document.
+
+Encoders include the decompressed length in every zstd frame, but decoders 
+should not rely on it being present.
+
+A signature may be preceded by a signed timestamp. This is the byte 0xFF, a 
+single byte holding the length of the timestamp, and then the timestamp in its 
+usual 4, 8, or 12-byte encoding. When present, the signature is made over the 
+hash of the document hash followed by these bytes, rather than over the 
+document hash alone. Encoded signatures never start with 0xFF, so documents 
+without a signed timestamp are unaffected.
+
+A document signed by multiple parties instead ends with a multi-signature: the 
+byte 0xFE, a single byte holding the number of signatures (at least 2), then 
+each signature, prefixed with its length as a 2-byte little-endian integer. 
+Every signature is made over the document hash, and no signer may appear more 
+than once.
 
 # Entries
 
@@ -77,7 +106,7 @@ a query that was used to fetch/generate them. As such, an encoded entry only
 contains the entry's fog-pack value & associated signatures.
 
 Encoded entries always start with a compression marker byte, and are followed by 
-a 2-byte big-endian integer indicating the length of the data payload. The data 
+a 2-byte little-endian integer indicating the length of the data payload. The data 
 payload follows, and the last portion of the entry holds any signatures appended 
 to the entry. Not e that the total size of the entry cannot be calculated from 
 the data itself - it is assuemd that an encapsulating protocol will include the 
@@ -85,29 +114,27 @@ length of an entry.
 
 ```text
 +----------+----------+----------+==========+============+
-| 0XXXXXYY | ZZZZZZZZ | ZZZZZZZZ |   Data   | Signatures |
+| XXXXXXYY | ZZZZZZZZ | ZZZZZZZZ |   Data   | Signatures |
 +----------+----------+----------+==========+============+
 
-- XXXXX is the 5-bit compression algorithm code
+- XXXXXX is the 6-bit compression algorithm code
 - YY is the compression type marker
-- ZZZZZZZZ_ZZZZZZZZ is a 16-bit big-endian unsigned integer representing the 
+- ZZZZZZZZ_ZZZZZZZZ is a 16-bit little-endian unsigned integer representing the 
     length of the Data.
 
 ```
 
 The data payload depends on the compression type indicated:
 
-- Uncompressed: No compression used. The entry's raw value & signatures directly 
-    follow the marker byte.
-- CompressedNoSchema: A single zstd frame follows the marker byte, containing 
-    the compressed version of the entry's raw value & signatures.
-- Compressed: Invalid for entries, and should result in a decoding error.
-- DictCompressed: Same as CompressedNoSchema, except that the zstd frame was 
-    made using a dictionary embedded in the schema used by the entry's parent 
-    document. The dictionary used depends on the entry's field.
-
-For all of the above, the zstd frame always contains the estimated decompressed 
-length.
+- Uncompressed: No compression used. The entry's raw value is the data payload.
+- General: The payload is the entry's raw value, compressed with the algorithm 
+    given in the marker. For zstd, this is a single frame.
+- DictCompressed: The payload is a single zstd frame, made using a dictionary 
+    embedded in the schema used by the entry's parent document. The dictionary 
+    used depends on the entry's key.
+
+Encoders include the decompressed length in every zstd frame, but decoders 
+should not rely on it being present.
 
 # Queries
 
@@ -50,6 +50,8 @@ compression:
 - `name`: A brief string to name the schema.
 - `description`: A brief string describing the purpose of the schema.
 - `version`: An integer for tracking schema versions.
+- `maintainer`: The Identity of whoever maintains the schema. Like the name and 
+    description, this is only for documentation, and has no effect on validation.
 - `entries`: An object containing validators for each allowed Entry that may be 
     attached to a Document following the schema.
 - `types`: An object containing aliased validators that may be referred to 
@@ -58,6 +60,69 @@ compression:
     Documents using the schema.
 - `entries_compress`: Optionally specifies recommended compression settings for 
     entries attached to documents using the schema.
+- `compress_threshold`: An integer. Documents and entries with fewer data bytes 
+    than this are not compressed by default. If not present, the threshold is 64.
+- `immutable`: An array of JSON Pointer strings, each naming a field of the 
+    document that can't change between revisions. A revision links to the 
+    document it replaces by holding its hash in a top-level `prev` field, and 
+    must keep the value of every immutable field the earlier document had. 
+    Fields the earlier document didn't have may be filled in.
+- `external`: An object mapping JSON Pointer strings to integer thresholds. 
+    Each names a string or binary field of the document whose values may be 
+    moved into a chain of chunk documents when longer than the threshold, 
+    leaving the hash of the first chunk in their place.
+- `weak`: An array of JSON Pointer strings, each naming a field of the document 
+    that holds weak references. Hashes within a weak field don't keep the 
+    documents they refer to alive when determining what is reachable for garbage 
+    collection.
+- `reducers`: An object mapping entry keys to the reducer used to compact 
+    entries with that key into a snapshot: one of `"append"`, `"last"`, 
+    `"merge"`, or `"patch"`. Every key must also be in `entries`.
+- `volatile`: An object mapping entry keys to arrays of JSON Pointer strings, 
+    each naming a field of those entries that may differ between submissions of 
+    the same content, like a timestamp. Volatile fields are left out when 
+    comparing entries for duplicates. Every key must also be in `entries`. 
+    An entry's semantic hash is the hash of the fog-pack encoding of the 
+    3-element array `[parent, key, data]`, where `parent` is the parent 
+    document's hash, `key` is the entry key, and `data` is the entry's data with 
+    every volatile field removed. Fields are removed deepest first, and fields 
+    within the same array are removed highest index first, so that removing one 
+    never shifts an element another names. Entries with the same semantic hash 
+    are duplicates.
+- `entry_keys`: A string validator that every key in `entries` must pass. Keys 
+    starting with `sys.` are reserved for future use by the format, and are 
+    never allowed.
+- `tombstones`: An array of entry keys whose entries are tombstones. A 
+    tombstone's data is the hash of an earlier entry it retracts, and is only 
+    honored if it is signed, and has the same signer as that entry. Unsigned 
+    entries can't be retracted. Every key must also be in `entries`, with a 
+    `Hash` validator.
+- `unsigned`: A boolean. If true, documents using the schema must not be signed, 
+    for data that is meant to be anonymous. Can't be used along with a 
+    signature threshold.
+- `parent`: The Hash of a schema this one extends. See [Extending 
+    Schemas](#extending-schemas).
+
+## Extending Schemas
+
+A schema with a `parent` extends the parent schema, and can only be used once 
+the parent schema document has been fetched. The parent's `entries` and `types` 
+are inherited, along with its `encrypted`, `weak`, `immutable`, `external`, 
+`reducers`, `volatile`, `tombstones`, and `entry_keys` settings where the 
+extending schema doesn't give its own. A document validator of `Any` inherits 
+the parent's document validator.
+
+Where both schemas give a validator for the same document, entry, or type, the 
+extending schema's validator must accept only values the parent's does, with 
+one exception: two map validators are combined field by field, so the extending 
+schema can add new fields. Fields in both must follow the same rule, and fields 
+the parent requires must stay required. The extending schema's map-level 
+settings replace the parent's.
+
+Everything else, including the name, compression settings, and signature 
+requirements, comes from the extending schema alone. A parent may extend 
+another schema in turn, up to 16 levels deep. Documents using an extending 
+schema name only its hash.
 
 ## Compression Settings
 
@@ -52,6 +52,9 @@ There is a validation format for each of the basic fog-pack types: [`Null`],
 [`Lock`], and [`Time`]. See below for documentation on building validators for 
 each of these.
 
+A [`Num`] validator accepts any of the numeric types, `Int`, `F32`, or `F64`, 
+under one set of bounds.
+
 A special validator type, `Multi`, doesn't validate a specify fog-pack value 
 type, but can be used to make a validator that matches if *any* of its 
 sub-validators pass.
@@ -65,6 +68,7 @@ queries against it.
 [`Int`]: #int
 [`F32`]: #f32
 [`F64`]: #f64
+[`Num`]: #num
 [`Bin`]: #bin
 [`Str`]: #str
 [`Obj`]: #obj
@@ -204,8 +208,8 @@ An integer validator allowing any positive value between 0 and
 ### Str
 
 Str types describe an allowed String value. Strings are always valid UTF-8, and 
-support counting their length by both the number of raw encoded bytes and the 
-number of Unicode scalar values. They have the following optional fields:
+support counting their length by the number of raw encoded bytes, the number of 
+Unicode scalar values, and the number of extended grapheme clusters. They have the following optional fields:
 
 - `default`
 - `comment`
@@ -223,6 +227,10 @@ number of Unicode scalar values. They have the following optional fields:
     Must be at least 0.
 - `min_char`: The minimum number of unicode scalar values allowed for the value.
     Must be at least 0.
+- `max_grapheme`: The maximum number of extended grapheme clusters, as defined by 
+    Unicode Standard Annex #29, allowed for the value. Must be at least 0.
+- `min_grapheme`: The minimum number of extended grapheme clusters allowed for the 
+    value. Must be at least 0.
 - `force_nfc`: Boolean. Runs the string to be validated, `in`, `nin`, and 
     `matches` through Unicode normalization to NFC before performing validation.
 - `force_nfkc`: Boolean. Runs the string to be validated, `in`, `nin`, and 
@@ -230,8 +238,8 @@ number of Unicode scalar values. They have the following optional fields:
     This overrides `force_nfc` if it is also set to true.
 - `query`: Boolean. Allows queries to use `in` and `nin`.
 - `regex`: Boolean. Allows queries to use `matches`.
-- `size`: Boolean. Allows queries to use `min_len`, `max_len`, `min_char`, and 
-    `max_char`.
+- `size`: Boolean. Allows queries to use `min_len`, `max_len`, `min_char`, 
+    `max_char`, `min_grapheme`, and `max_grapheme`.
 
 Validation fails if the value is not a string or does not meet all of the 
 optional requirements.
@@ -307,7 +315,7 @@ optional fields:
 - `ex_min`: A boolean that, if true, doesn't allow equality for the minimum 
     value. If `min` isn't present, but this is set, the minimum allowed value is 
     anything except for NaN and negative infinity.
-- `ex_min`: A boolean that, if true, doesn't allow equality for the minimum 
+- `ex_max`: A boolean that, if true, doesn't allow equality for the maximum 
     value. If `max` isn't present, but this is set, the maxixmum allowed value is 
     anything except for NaN and positive infinity.
 - `min`: The minimum allowed value, with NaN not permitted. NaN is not permitted 
@@ -335,6 +343,43 @@ validator could be:
 }
 ```
 
+### Num
+
+Num types describe an allowed number, which may be encoded as an Int, F32, or 
+F64 value. F32 values are widened to binary64 before being compared, and Int 
+values are compared exactly, without first being rounded to a binary64 value. 
+They support the following optional fields:
+
+- `default`
+- `comment`
+- `ex_min`: A boolean that, if true, doesn't allow equality for the minimum 
+    value. If `min` isn't present, but this is set, the minimum allowed value is 
+    anything except for NaN and negative infinity.
+- `ex_max`: A boolean that, if true, doesn't allow equality for the maximum 
+    value. If `max` isn't present, but this is set, the maximum allowed value is 
+    anything except for NaN and positive infinity.
+- `min`: The minimum allowed value, as a binary64 value. NaN is not permitted 
+    if this is present.
+- `max`: The maximum allowed value, as a binary64 value. NaN is not permitted 
+    if this is set.
+- `ord`: Boolean. Allows queries to use `min`, `max`, `ex_min`, and `ex_max`.
+
+Validation fails if the value is not an Int, F32, or F64, or does not meet all 
+of the optional requirements.
+
+#### Examples
+
+Say we want to accept a percentage, from producers that may send either `50` or 
+`50.0`. The validator could be:
+
+```json
+{
+    "type": "Num",
+    "min": 0.0,
+    "max": 100.0
+}
+```
+
 ### Bin
 
 Bin types decribe an allowed byte vector. The byte vector can also be 
@@ -466,6 +511,11 @@ optional fields:
     Must be at least 0.
 - `min_fields`: The minimum number of allowed field-value pairs in the object. 
     Must be at least 0.
+- `max_prefix`: An object where each field is a prefix, and each associated value 
+    is the maximum number of fields in the described object that may start with 
+    that prefix. Each value must be at least 0.
+- `req_any`: An array of arrays of strings. For each inner array, at least one of 
+    its strings must be a field in the described object.
 - `req`: An object where each field is an optional field for the described 
     object, and each associated value is the validator for that field's value in 
     the described object.
@@ -554,9 +604,14 @@ support the following optional fields:
 - `schema`: A hash or array of hashes that match various schemas. The document 
     matching a validated hash must use one of these schemas. This field is only 
     used when the validator is for an entry; it is ignored for documents.
-- `query`: Boolean. Allows queries to use `in` and `nin`.
+- `version`: An array of integers, one of which must be the hash's version. 
+    The version identifies the hash algorithm.
+- `prefix`: Binary. The hash's digest, not including its version, must start 
+    with these bytes.
+- `query`: Boolean. Allows queries to use `in`, `nin`, and `version`.
 - `link_ok`: Boolean. Allows queries to use `link`.
 - `schema_ok`: Boolean. Allows queries to use `schema`.
+- `prefix_ok`: Boolean. Allows queries to use `prefix`.
 
 Validation fails if the value is not a hash or does not meet all of the optional 
 requirements. Validation may require fetching additional documents if the tested 
@@ -666,8 +721,11 @@ have the following optional fields:
     allowed.
 - `in`: A timestamp or array of timestamps that the value must be among.
 - `nin`: A timetamp or array of timestamps that the value must not be among.
+- `precision`: One of `"Seconds"`, `"Millis"`, or `"Nanos"`. The value's 
+    sub-second part must be a whole number of this unit. Defaults to `"Nanos"`, 
+    which allows any timestamp.
 - `ord`: Boolean. Allows queries to use `min`, `max`, `ex_min`, and `ex_max`.
-- `query`: Boolean. Allows queries to use `in` and `nin`.
+- `query`: Boolean. Allows queries to use `in`, `nin`, and `precision`.
 
 Validation fails if the value is not a timestamp or does not meet all of the 
 optional requirements.
@@ -0,0 +1,208 @@
+//! Local annotations, for keeping track of where a document came from.
+//!
+//! Every [`Document`][crate::document::Document] carries a set of [`Annotations`]: metadata that
+//! belongs to this process's copy of the document rather than the document itself. Annotations
+//! are never hashed, signed, encoded, or validated, and are dropped as soon as the document is
+//! encoded. They move with the document handle through anything that takes and returns one, so
+//! there's no need to wrap documents in another struct just to remember when or where they were
+//! received.
+//!
+//! The common cases have dedicated fields: the time the document was received, the peer it came
+//! from, and a set of local tags. Anything else can be attached by type.
+//!
+//! ```
+//! # use fog_pack::{document::*, schema::*, types::*};
+//! # fn main() -> fog_pack::error::Result<()> {
+//! # let (_, encoded) = NoSchema::encode_doc(NoSchema::validate_new_doc(NewDocument::new(None, 1)?)?)?;
+//! struct Hops(u32);
+//!
+//! let mut doc = NoSchema::decode_doc(encoded)?;
+//! doc.annotations_mut()
+//!     .set_received(Timestamp::now())
+//!     .set_source("peer-7")
+//!     .tag_add("unread")
+//!     .insert(Hops(2));
+//!
+//! let doc = doc.verify()?;
+//! assert_eq!(doc.annotations().source(), Some("peer-7"));
+//! assert!(doc.annotations().has_tag("unread"));
+//! assert_eq!(doc.annotations().get::<Hops>().map(|h| h.0), Some(2));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::types::Timestamp;
+use std::{
+    any::{Any, TypeId},
+    collections::{BTreeSet, HashMap},
+    fmt,
+    sync::Arc,
+};
+
+/// Local, unhashed metadata attached to a document. See the [module-level
+/// documentation][crate::annotation].
+///
+/// Cloning annotations is cheap: values attached by type are shared between the clones.
+#[derive(Clone, Default)]
+pub struct Annotations {
+    received: Option<Timestamp>,
+    source: Option<String>,
+    tags: BTreeSet<String>,
+    extensions: HashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+}
+
+impl Annotations {
+    /// Create an empty set of annotations.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check if nothing has been annotated.
+    pub fn is_empty(&self) -> bool {
+        self.received.is_none()
+            && self.source.is_none()
+            && self.tags.is_empty()
+            && self.extensions.is_empty()
+    }
+
+    /// Get the time the document was received, if it was recorded.
+    pub fn received(&self) -> Option<Timestamp> {
+        self.received
+    }
+
+    /// Record the time the document was received.
+    pub fn set_received(&mut self, time: Timestamp) -> &mut Self {
+        self.received = Some(time);
+        self
+    }
+
+    /// Get the peer the document came from, if it was recorded.
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    /// Record the peer the document came from. How peers are named is up to the application.
+    pub fn set_source(&mut self, source: impl Into<String>) -> &mut Self {
+        self.source = Some(source.into());
+        self
+    }
+
+    /// Iterate over the local tags, in order.
+    pub fn tags(&self) -> impl Iterator<Item = &str> {
+        self.tags.iter().map(|tag| tag.as_str())
+    }
+
+    /// Check if a local tag is present.
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains(tag)
+    }
+
+    /// Add a local tag. Adding a tag that's already present does nothing.
+    pub fn tag_add(&mut self, tag: impl Into<String>) -> &mut Self {
+        self.tags.insert(tag.into());
+        self
+    }
+
+    /// Remove a local tag, returning true if it was present.
+    pub fn tag_remove(&mut self, tag: &str) -> bool {
+        self.tags.remove(tag)
+    }
+
+    /// Attach a value by its type, replacing any value of the same type already attached.
+    pub fn insert<T: Any + Send + Sync>(&mut self, value: T) -> &mut Self {
+        self.extensions.insert(TypeId::of::<T>(), Arc::new(value));
+        self
+    }
+
+    /// Get the attached value of a type, if there is one.
+    pub fn get<T: Any + Send + Sync>(&self) -> Option<&T> {
+        self.extensions
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref())
+    }
+
+    /// Remove the attached value of a type, returning true if there was one.
+    pub fn remove<T: Any + Send + Sync>(&mut self) -> bool {
+        self.extensions.remove(&TypeId::of::<T>()).is_some()
+    }
+
+    /// Remove all annotations.
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+impl fmt::Debug for Annotations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Annotations")
+            .field("received", &self.received)
+            .field("source", &self.source)
+            .field("tags", &self.tags)
+            .field("extensions", &self.extensions.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        document::NewDocument,
+        schema::{NoSchema, SchemaBuilder},
+        types::IdentityKey,
+        validator::Validator,
+    };
+
+    #[derive(Debug, PartialEq)]
+    struct Hops(u32);
+
+    #[test]
+    fn annotations() {
+        let mut annotations = Annotations::new();
+        assert!(annotations.is_empty());
+        annotations
+            .set_received(Timestamp::from_utc_secs(10))
+            .set_source("peer")
+            .tag_add("b")
+            .tag_add("a")
+            .tag_add("a")
+            .insert(Hops(1))
+            .insert(Hops(2));
+        assert_eq!(annotations.received(), Some(Timestamp::from_utc_secs(10)));
+        assert_eq!(annotations.tags().collect::<Vec<_>>(), vec!["a", "b"]);
+        assert_eq!(annotations.get::<Hops>(), Some(&Hops(2)));
+        assert_eq!(annotations.get::<u32>(), None);
+        let copy = annotations.clone();
+        assert!(annotations.tag_remove("a"));
+        assert!(!annotations.tag_remove("a"));
+        assert!(annotations.remove::<Hops>());
+        assert!(!annotations.remove::<Hops>());
+        assert_eq!(copy.get::<Hops>(), Some(&Hops(2)));
+        annotations.clear();
+        assert!(annotations.is_empty());
+    }
+
+    #[test]
+    fn travels_with_document() {
+        let doc = NewDocument::new(None, "data").unwrap();
+        let mut doc = NoSchema::validate_new_doc(doc).unwrap();
+        let hash = doc.hash().clone();
+        doc.annotations_mut().set_source("peer").insert(Hops(3));
+
+        // Annotations don't change the document, and survive operations that hand it back
+        assert_eq!(doc.hash(), &hash);
+        let doc = doc.compression(None).sign(&IdentityKey::new()).unwrap();
+        assert_eq!(doc.annotations().source(), Some("peer"));
+        assert_eq!(doc.annotations().get::<Hops>(), Some(&Hops(3)));
+
+        // ...but are never encoded
+        let (_, encoded) = NoSchema::encode_doc(doc).unwrap();
+        let doc = NoSchema::decode_doc(encoded).unwrap();
+        assert!(doc.annotations().is_empty());
+
+        // Schema documents can carry them too
+        let mut schema_doc = SchemaBuilder::new(Validator::Any).build().unwrap();
+        schema_doc.annotations_mut().tag_add("pinned");
+        assert!(schema_doc.annotations().has_tag("pinned"));
+    }
+}
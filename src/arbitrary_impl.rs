@@ -0,0 +1,96 @@
+//! [`Arbitrary`] implementations, enabled with the `arbitrary` feature.
+
+use crate::{
+    types::{Hash, Integer, Timestamp, Value},
+    MAX_DEPTH,
+};
+use arbitrary::{Arbitrary, Result, Unstructured};
+use std::collections::BTreeMap;
+
+// Keep generated values well within the nesting limit, so they can always be encoded.
+const MAX_ARBITRARY_DEPTH: usize = 8;
+const _: () = assert!(MAX_ARBITRARY_DEPTH < MAX_DEPTH);
+
+impl<'a> Arbitrary<'a> for Integer {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(if u.arbitrary()? {
+            Integer::from(u64::arbitrary(u)?)
+        } else {
+            Integer::from(i64::arbitrary(u)?)
+        })
+    }
+
+    fn size_hint(_depth: usize) -> (usize, Option<usize>) {
+        (9, Some(9))
+    }
+}
+
+impl<'a> Arbitrary<'a> for Timestamp {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let secs = i64::arbitrary(u)?;
+        let nanos = u.int_in_range(0..=999_999_999)?;
+        Ok(Timestamp::new(secs, nanos).expect("nanoseconds are in range"))
+    }
+
+    fn size_hint(_depth: usize) -> (usize, Option<usize>) {
+        (12, Some(12))
+    }
+}
+
+fn arbitrary_value(u: &mut Unstructured, depth: usize) -> Result<Value> {
+    // Only pick arrays and maps if there's room to nest further
+    let max = if depth < MAX_ARBITRARY_DEPTH { 10 } else { 8 };
+    Ok(match u.int_in_range(0..=max)? {
+        0 => Value::Null,
+        1 => Value::Bool(u.arbitrary()?),
+        2 => Value::Int(u.arbitrary()?),
+        3 => Value::Str(u.arbitrary()?),
+        4 => Value::F32(u.arbitrary()?),
+        5 => Value::F64(u.arbitrary()?),
+        6 => Value::Bin(u.arbitrary()?),
+        7 => Value::Timestamp(u.arbitrary()?),
+        8 => Value::Hash(Hash::new(<&[u8]>::arbitrary(u)?)),
+        9 => {
+            let len = u.arbitrary_len::<u8>()?;
+            let mut array = Vec::with_capacity(len);
+            for _ in 0..len {
+                array.push(arbitrary_value(u, depth + 1)?);
+            }
+            Value::Array(array)
+        }
+        _ => {
+            let len = u.arbitrary_len::<(u8, u8)>()?;
+            let mut map = BTreeMap::new();
+            for _ in 0..len {
+                map.insert(String::arbitrary(u)?, arbitrary_value(u, depth + 1)?);
+            }
+            Value::Map(map)
+        }
+    })
+}
+
+/// Generates values nested at most 8 levels deep. Floating-point values may be NaN, and
+/// Identities, stream and lock IDs, and lockboxes are never generated.
+impl<'a> Arbitrary<'a> for Value {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        arbitrary_value(u, 0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{document::NewDocument, schema::NoSchema};
+
+    #[test]
+    fn values_encode() {
+        let data: Vec<u8> = (0..4096u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+            .collect();
+        let mut u = Unstructured::new(&data);
+        while !u.is_empty() {
+            let value = Value::arbitrary(&mut u).unwrap();
+            NoSchema::validate_new_doc(NewDocument::new(None, &value).unwrap()).unwrap();
+        }
+    }
+}
@@ -0,0 +1,299 @@
+//! Identity attestations.
+//!
+//! An attestation is a document where one [`Identity`] signs a statement about another: that it
+//! has been replaced by a new key, that a key has been revoked, or that a key may act on its
+//! behalf. All attestations adhere to the standard [`attestation_schema`], so any two fog-pack
+//! systems can exchange and check them.
+//!
+//! ```
+//! # use fog_pack::{attestation::*, schema::*, types::IdentityKey};
+//! # fn main() -> fog_pack::error::Result<()> {
+//! let old_key = IdentityKey::new();
+//! let new_key = IdentityKey::new();
+//!
+//! let doc = Attestation::new(AttestationKind::Rotate, new_key.id().clone()).sign(&old_key)?;
+//! let (_, encoded) = attestation_schema().encode_doc(doc)?;
+//!
+//! let doc = attestation_schema().decode_doc(encoded)?;
+//! let signed = SignedAttestation::from_document(&doc)?;
+//! assert_eq!(signed.attester(), old_key.id());
+//! assert_eq!(
+//!     current_identity(old_key.id(), &[signed]),
+//!     Some(new_key.id().clone())
+//! );
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    document::{Document, NewDocument},
+    error::{Error, Result, ValidateError},
+    schema::{Compress, Schema, SchemaBuilder},
+    signer::Signer,
+    types::{Identity, Timestamp},
+    validator::{IdentityValidator, MapValidator, StrValidator, TimeValidator},
+};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+static SCHEMA: OnceLock<Schema> = OnceLock::new();
+
+/// Get the standard schema used by all identity attestations.
+pub fn attestation_schema() -> &'static Schema {
+    SCHEMA.get_or_init(|| {
+        let doc = SchemaBuilder::new(
+            MapValidator::new()
+                .req_add(
+                    "kind",
+                    StrValidator::new()
+                        .in_add("rotate")
+                        .in_add("revoke")
+                        .in_add("delegate")
+                        .build(),
+                )
+                .req_add("subject", IdentityValidator::new().build())
+                .req_add("time", TimeValidator::new().build())
+                .opt_add("note", StrValidator::new().build())
+                .build(),
+        )
+        .name("fog-pack identity attestation")
+        .description("A statement, signed by one Identity, about another Identity")
+        .doc_compress(Compress::None)
+        .build()
+        .expect("the attestation schema should always be valid");
+        Schema::from_doc(&doc).expect("the attestation schema should always be valid")
+    })
+}
+
+/// The kind of statement an attestation makes about its subject.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AttestationKind {
+    /// The attester has retired its key, and the subject replaces it.
+    Rotate,
+    /// The subject should no longer be trusted. Attesters may revoke themselves.
+    Revoke,
+    /// The subject may act on behalf of the attester.
+    Delegate,
+}
+
+/// A statement about an Identity, ready to be signed.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attestation {
+    /// What is being attested.
+    pub kind: AttestationKind,
+    /// The Identity the attestation is about.
+    pub subject: Identity,
+    /// When the attestation was made.
+    pub time: Timestamp,
+    /// An optional human-readable note.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub note: Option<String>,
+}
+
+impl Attestation {
+    /// Start a new attestation about `subject`, timestamped with the current time.
+    pub fn new(kind: AttestationKind, subject: Identity) -> Self {
+        Self {
+            kind,
+            subject,
+            time: Timestamp::now(),
+            note: None,
+        }
+    }
+
+    /// Attach a human-readable note.
+    pub fn note(mut self, note: impl Into<String>) -> Self {
+        self.note = Some(note.into());
+        self
+    }
+
+    /// Sign the attestation, producing a document adhering to the [`attestation_schema`].
+    pub fn sign<S: Signer + ?Sized>(&self, key: &S) -> Result<Document> {
+        let schema = attestation_schema();
+        let doc = NewDocument::new(Some(schema.hash()), self)?.sign(key)?;
+        schema.validate_new_doc(doc)
+    }
+}
+
+/// An attestation, along with the Identity that signed it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignedAttestation {
+    attester: Identity,
+    attestation: Attestation,
+}
+
+impl SignedAttestation {
+    /// Read an attestation from a document. Fails if the document doesn't use the
+    /// [`attestation_schema`], isn't signed, or hasn't had its signature verified.
+    pub fn from_document(doc: &Document) -> Result<Self> {
+        let schema = attestation_schema();
+        if doc.schema_hash() != Some(schema.hash()) {
+            return Err(Error::SchemaMismatch {
+                actual: doc.schema_hash().cloned(),
+                expected: Some(schema.hash().clone()),
+            });
+        }
+        if !doc.is_verified() {
+            return Err(Error::FailValidate(ValidateError::Other(
+                "Attestation signature hasn't been verified".into(),
+            )));
+        }
+        let attester = doc
+            .signer()
+            .ok_or_else(|| {
+                Error::FailValidate(ValidateError::Other("Attestation isn't signed".into()))
+            })?
+            .clone();
+        Ok(Self {
+            attester,
+            attestation: doc.deserialize()?,
+        })
+    }
+
+    /// Get the Identity that made the attestation.
+    pub fn attester(&self) -> &Identity {
+        &self.attester
+    }
+
+    /// Get the attestation itself.
+    pub fn attestation(&self) -> &Attestation {
+        &self.attestation
+    }
+
+    /// Get what kind of attestation this is.
+    pub fn kind(&self) -> AttestationKind {
+        self.attestation.kind
+    }
+
+    /// Get the Identity the attestation is about.
+    pub fn subject(&self) -> &Identity {
+        &self.attestation.subject
+    }
+
+    /// Check if this attestation revokes `id`. Revocations are only honored if made by the
+    /// Identity itself.
+    pub fn revokes(&self, id: &Identity) -> bool {
+        self.kind() == AttestationKind::Revoke && self.subject() == id && &self.attester == id
+    }
+
+    /// Check if this attestation is `attester` delegating to `delegate`.
+    pub fn delegates(&self, attester: &Identity, delegate: &Identity) -> bool {
+        self.kind() == AttestationKind::Delegate
+            && &self.attester == attester
+            && self.subject() == delegate
+    }
+}
+
+/// Follow key rotations from `start` to find the Identity currently in use. Returns `None` if
+/// the current Identity has revoked itself. If a key was rotated more than once, the newest
+/// rotation is followed. Rotation loops end at the first repeated Identity.
+pub fn current_identity(start: &Identity, attestations: &[SignedAttestation]) -> Option<Identity> {
+    let mut current = start.clone();
+    let mut seen = vec![current.clone()];
+    loop {
+        if attestations.iter().any(|a| a.revokes(&current)) {
+            return None;
+        }
+        let next = attestations
+            .iter()
+            .filter(|a| a.kind() == AttestationKind::Rotate && a.attester() == &current)
+            .max_by_key(|a| a.attestation().time);
+        match next {
+            Some(a) if !seen.contains(a.subject()) => {
+                current = a.subject().clone();
+                seen.push(current.clone());
+            }
+            _ => return Some(current),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{schema::NoSchema, types::IdentityKey};
+
+    fn roundtrip(doc: Document) -> SignedAttestation {
+        let schema = attestation_schema();
+        let (_, encoded) = schema.encode_doc(doc).unwrap();
+        SignedAttestation::from_document(&schema.decode_doc(encoded).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn rotation_chain() {
+        let a = IdentityKey::new();
+        let b = IdentityKey::new();
+        let c = IdentityKey::new();
+        let other = IdentityKey::new();
+        let mut list = vec![
+            roundtrip(
+                Attestation::new(AttestationKind::Rotate, b.id().clone())
+                    .sign(&a)
+                    .unwrap(),
+            ),
+            roundtrip(
+                Attestation::new(AttestationKind::Rotate, c.id().clone())
+                    .note("scheduled rotation")
+                    .sign(&b)
+                    .unwrap(),
+            ),
+            // Someone else can't rotate away our key
+            roundtrip(
+                Attestation::new(AttestationKind::Rotate, other.id().clone())
+                    .sign(&other)
+                    .unwrap(),
+            ),
+        ];
+        assert_eq!(
+            list[1].attestation().note.as_deref(),
+            Some("scheduled rotation")
+        );
+        assert_eq!(current_identity(a.id(), &list), Some(c.id().clone()));
+
+        // Revocation only counts when made by the revoked key
+        list.push(roundtrip(
+            Attestation::new(AttestationKind::Revoke, c.id().clone())
+                .sign(&other)
+                .unwrap(),
+        ));
+        assert_eq!(current_identity(a.id(), &list), Some(c.id().clone()));
+        list.push(roundtrip(
+            Attestation::new(AttestationKind::Revoke, c.id().clone())
+                .sign(&c)
+                .unwrap(),
+        ));
+        assert_eq!(current_identity(a.id(), &list), None);
+    }
+
+    #[test]
+    fn delegation() {
+        let a = IdentityKey::new();
+        let b = IdentityKey::new();
+        let att = roundtrip(
+            Attestation::new(AttestationKind::Delegate, b.id().clone())
+                .sign(&a)
+                .unwrap(),
+        );
+        assert!(att.delegates(a.id(), b.id()));
+        assert!(!att.delegates(b.id(), a.id()));
+    }
+
+    #[test]
+    fn rejects_bad_documents() {
+        let key = IdentityKey::new();
+        let schema = attestation_schema();
+
+        let unsigned = Attestation::new(AttestationKind::Revoke, key.id().clone());
+        let doc = NewDocument::new(Some(schema.hash()), &unsigned).unwrap();
+        let doc = schema.validate_new_doc(doc).unwrap();
+        assert!(SignedAttestation::from_document(&doc).is_err());
+
+        let doc = NewDocument::new(None, &unsigned)
+            .unwrap()
+            .sign(&key)
+            .unwrap();
+        let doc = NoSchema::validate_new_doc(doc).unwrap();
+        assert!(SignedAttestation::from_document(&doc).is_err());
+    }
+}
@@ -0,0 +1,165 @@
+use crate::{
+    element::{serialize_elem, Element, Parser},
+    error::{Error, Result},
+};
+
+/// What [`canonicalize_with_report`] had to fix.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Repairs {
+    /// The number of elements that weren't in their canonical encoding: integers and lengths
+    /// that didn't use the shortest encoding, and floating-point values with non-canonical bit
+    /// patterns.
+    pub reencoded: usize,
+    /// The number of maps whose keys weren't in order.
+    pub reordered_maps: usize,
+}
+
+impl Repairs {
+    /// Check if nothing needed fixing, meaning the input was already canonical.
+    pub fn is_empty(&self) -> bool {
+        self.reencoded == 0 && self.reordered_maps == 0
+    }
+}
+
+/// Re-encode a fog-pack value canonically.
+///
+/// Strict decoding rejects data that isn't canonically encoded, which is the right default, but
+/// leaves no way to recover data written by a buggy encoder. This accepts map keys in any order,
+/// integers and lengths that don't use their shortest encoding, and non-canonical floating-point
+/// values, and returns the same value in canonical form. See [`canonicalize_with_report`] to find
+/// out what was fixed.
+///
+/// The input is an encoded value, like the data inside a document, not a complete encoded
+/// document. Fails if the input isn't a single well-formed value, has a map key that isn't a
+/// string, or has the same key twice in one map.
+///
+/// ```
+/// # fn main() -> fog_pack::error::Result<()> {
+/// // {"b": 1, "a": 2}, with the 1 needlessly encoded as a UInt8
+/// let bytes = [0x82, 0xa1, b'b', 0xcc, 0x01, 0xa1, b'a', 0x02];
+/// let (fixed, repairs) = fog_pack::canonicalize_with_report(&bytes)?;
+/// assert_eq!(fixed, [0x82, 0xa1, b'a', 0x02, 0xa1, b'b', 0x01]);
+/// assert_eq!(repairs.reencoded, 1);
+/// assert_eq!(repairs.reordered_maps, 1);
+/// assert_eq!(fog_pack::canonicalize(&fixed)?, fixed);
+/// # Ok(())
+/// # }
+/// ```
+pub fn canonicalize(bytes: &[u8]) -> Result<Vec<u8>> {
+    Ok(canonicalize_with_report(bytes)?.0)
+}
+
+/// Re-encode a fog-pack value canonically, reporting what was fixed. See [`canonicalize`].
+pub fn canonicalize_with_report(bytes: &[u8]) -> Result<(Vec<u8>, Repairs)> {
+    let mut parser = Parser::new(bytes).lenient(true);
+    let mut repairs = Repairs::default();
+    let mut out = Vec::with_capacity(bytes.len());
+    canonical_value(&mut parser, &mut out, &mut repairs)?;
+    repairs.reencoded = parser.fixed();
+    parser.finish()?;
+    Ok((out, repairs))
+}
+
+fn next_elem<'a>(parser: &mut Parser<'a>) -> Result<Element<'a>> {
+    parser.next().unwrap_or_else(|| {
+        Err(Error::LengthTooShort {
+            step: "get next element",
+            actual: 0,
+            expected: 1,
+        })
+    })
+}
+
+fn canonical_value(parser: &mut Parser, out: &mut Vec<u8>, repairs: &mut Repairs) -> Result<()> {
+    match next_elem(parser)? {
+        Element::Array(len) => {
+            serialize_elem(out, Element::Array(len));
+            for _ in 0..len {
+                canonical_value(parser, out, repairs)?;
+            }
+        }
+        Element::Map(len) => {
+            let mut fields = Vec::with_capacity(len);
+            for _ in 0..len {
+                let key = match next_elem(parser)? {
+                    Element::Str(key) => key,
+                    elem => {
+                        return Err(Error::BadEncode(format!(
+                            "map keys must be strings, but got {}",
+                            elem.name()
+                        )))
+                    }
+                };
+                let mut value = Vec::new();
+                canonical_value(parser, &mut value, repairs)?;
+                fields.push((key, value));
+            }
+            if !fields.windows(2).all(|w| w[0].0 < w[1].0) {
+                repairs.reordered_maps += 1;
+                fields.sort_by(|a, b| a.0.cmp(b.0));
+                if let Some(w) = fields.windows(2).find(|w| w[0].0 == w[1].0) {
+                    return Err(Error::BadEncode(format!(
+                        "map key {:?} appears more than once",
+                        w[0].0
+                    )));
+                }
+            }
+            serialize_elem(out, Element::Map(len));
+            for (key, value) in fields {
+                serialize_elem(out, Element::Str(key));
+                out.extend_from_slice(&value);
+            }
+        }
+        elem => serialize_elem(out, elem),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{de::FogDeserializer, fog_value, ser::FogSerializer, types::Value};
+    use serde::{Deserialize, Serialize};
+
+    fn encode(value: &Value) -> Vec<u8> {
+        let mut ser = FogSerializer::default();
+        value.serialize(&mut ser).unwrap();
+        ser.finish()
+    }
+
+    #[test]
+    fn canonical_unchanged() {
+        let value = fog_value!({"a": [1, -200, 70000, "text"], "b": {"c": null}, "d": 1.5});
+        let encoded = encode(&value);
+        let (fixed, repairs) = canonicalize_with_report(&encoded).unwrap();
+        assert_eq!(fixed, encoded);
+        assert!(repairs.is_empty());
+    }
+
+    #[test]
+    fn repairs() {
+        // {"z": [-1 as Int16, "s" as Str8], "a": {"y": 0, "x": -0.0 as F32}}
+        let bytes = [
+            0x82, 0xa1, b'z', 0x92, 0xd1, 0xff, 0xff, 0xd4, 0x01, b's', 0xa1, b'a', 0x82, 0xa1,
+            b'y', 0x00, 0xa1, b'x', 0xca, 0x00, 0x00, 0x00, 0x80,
+        ];
+        assert!(Value::deserialize(&mut FogDeserializer::new(&bytes)).is_err());
+        let (fixed, repairs) = canonicalize_with_report(&bytes).unwrap();
+        assert_eq!(repairs.reencoded, 3);
+        assert_eq!(repairs.reordered_maps, 2);
+        let value = Value::deserialize(&mut FogDeserializer::new(&fixed)).unwrap();
+        assert_eq!(
+            value,
+            fog_value!({"a": {"x": 0.0f32, "y": 0}, "z": [-1, "s"]})
+        );
+        assert_eq!(fixed, encode(&value));
+
+        // Things that can't be fixed
+        let duplicate = [0x82, 0xa1, b'a', 0x00, 0xa1, b'a', 0x01];
+        assert!(canonicalize(&duplicate).is_err());
+        let int_key = [0x81, 0x00, 0x00];
+        assert!(canonicalize(&int_key).is_err());
+        assert!(canonicalize(&[0x92, 0x00]).is_err());
+        assert!(canonicalize(&[0x00, 0x00]).is_err());
+    }
+}
@@ -0,0 +1,343 @@
+//! Capability tokens, for granting other Identities permission to write entries.
+//!
+//! A capability is a signed document, using the built-in [`schema`], in which one Identity (the
+//! grantor) grants another (the grantee) permission to use a set of entry keys, optionally only
+//! under one parent document and only until an expiry time. Anyone holding the document can check
+//! that the grant is genuine, and then check whether an [`Entry`] or [`Query`] falls within it
+//! with [`Capability::permits`].
+//!
+//! A capability only proves what its grantor said. Deciding whether the grantor had the authority
+//! to say it - say, by being the signer of the parent document - is up to the application.
+//!
+//! ```
+//! # use fog_pack::{capability::*, document::*, entry::*, schema::*, types::*, validator::*};
+//! # fn main() -> fog_pack::error::Result<()> {
+//! # let schema_doc = SchemaBuilder::new(Validator::Any)
+//! #     .entry_add("post", Validator::Any, None)
+//! #     .build()?;
+//! # let schema = Schema::from_doc(&schema_doc)?;
+//! let owner = IdentityKey::new();
+//! let guest = IdentityKey::new();
+//! let board = NewDocument::new(Some(schema.hash()), "board")?.sign(&owner)?;
+//! let board = schema.validate_new_doc(board)?;
+//!
+//! // The owner lets a guest post to the board
+//! let grant = NewCapability::new(guest.id().clone())
+//!     .parent(board.hash())
+//!     .key_add("post")
+//!     .sign(&owner)?;
+//! let cap = Capability::from_doc(grant)?;
+//! assert_eq!(cap.grantor(), owner.id());
+//!
+//! let post = NewEntry::new("post", &board, "hello")?.sign(&guest)?;
+//! let post = schema.validate_new_entry(post)?.complete()?;
+//! assert!(cap.permits(&post));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    document::{Document, NewDocument},
+    entry::Entry,
+    error::{Error, Result},
+    query::Query,
+    schema::{Schema, SchemaBuilder},
+    signer::Signer,
+    types::{Hash, Identity, Timestamp},
+    validator::*,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+static SCHEMA: OnceLock<Schema> = OnceLock::new();
+
+/// Get the built-in schema that capability documents use.
+pub fn schema() -> &'static Schema {
+    SCHEMA.get_or_init(|| {
+        let validator = MapValidator::new()
+            .req_add("grantee", IdentityValidator::new().build())
+            .opt_add("parent", HashValidator::new().build())
+            .req_add(
+                "keys",
+                ArrayValidator::new()
+                    .items(StrValidator::new().build())
+                    .unique(true)
+                    .build(),
+            )
+            .opt_add("expires", TimeValidator::new().build())
+            .build();
+        let doc = SchemaBuilder::new(validator)
+            .name("fog-pack capability")
+            .description("A grant of permission to use entry keys")
+            .build()
+            .expect("built-in capability schema should be valid");
+        Schema::from_doc(&doc).expect("built-in capability schema should be valid")
+    })
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Grant {
+    grantee: Identity,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    parent: Option<Hash>,
+    keys: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires: Option<Timestamp>,
+}
+
+/// A capability that hasn't been signed yet.
+#[derive(Clone, Debug)]
+pub struct NewCapability(Grant);
+
+impl NewCapability {
+    /// Start a new grant to an Identity. It grants nothing until keys are added.
+    pub fn new(grantee: Identity) -> Self {
+        Self(Grant {
+            grantee,
+            parent: None,
+            keys: Vec::new(),
+            expires: None,
+        })
+    }
+
+    /// Only permit entries attached to this parent document.
+    pub fn parent(mut self, parent: &Hash) -> Self {
+        self.0.parent = Some(parent.clone());
+        self
+    }
+
+    /// Permit an entry key.
+    pub fn key_add(mut self, key: impl Into<String>) -> Self {
+        let key = key.into();
+        if !self.0.keys.contains(&key) {
+            self.0.keys.push(key);
+        }
+        self
+    }
+
+    /// Stop permitting anything after the given time.
+    pub fn expires(mut self, expires: Timestamp) -> Self {
+        self.0.expires = Some(expires);
+        self
+    }
+
+    /// Sign the grant, producing a capability document.
+    pub fn sign<S: Signer + ?Sized>(self, key: &S) -> Result<Document> {
+        let schema = schema();
+        let doc = NewDocument::new(Some(schema.hash()), &self.0)?.sign(key)?;
+        schema.validate_new_doc(doc)
+    }
+}
+
+/// Something a [`Capability`] can grant permission for.
+pub trait Permissible {
+    /// The entry key being used.
+    fn key(&self) -> &str;
+    /// The parent document, if known.
+    fn parent(&self) -> Option<&Hash>;
+    /// The Identity acting, if known.
+    fn actor(&self) -> Option<&Identity>;
+}
+
+impl Permissible for Entry {
+    fn key(&self) -> &str {
+        Entry::key(self)
+    }
+
+    fn parent(&self) -> Option<&Hash> {
+        Some(Entry::parent(self))
+    }
+
+    fn actor(&self) -> Option<&Identity> {
+        self.signer()
+    }
+}
+
+/// Queries don't carry a parent document or a signer, so only the entry key is checked. Whoever
+/// runs the query is responsible for knowing it came from the grantee.
+impl Permissible for Query {
+    fn key(&self) -> &str {
+        Query::key(self)
+    }
+
+    fn parent(&self) -> Option<&Hash> {
+        None
+    }
+
+    fn actor(&self) -> Option<&Identity> {
+        None
+    }
+}
+
+/// A verified capability, read from a signed capability document.
+#[derive(Clone, Debug)]
+pub struct Capability {
+    doc: Document,
+    grantor: Identity,
+    grant: Grant,
+}
+
+impl Capability {
+    /// Read a capability out of a document. Fails if the document doesn't use the capability
+    /// schema, isn't signed, or has an invalid signature.
+    pub fn from_doc(doc: Document) -> Result<Self> {
+        if doc.schema_hash() != Some(schema().hash()) {
+            return Err(Error::SchemaMismatch {
+                actual: doc.schema_hash().cloned(),
+                expected: Some(schema().hash().clone()),
+            });
+        }
+        let doc = doc.verify()?;
+        let grantor = doc.signer().cloned().ok_or(Error::BadSignature)?;
+        let grant = doc.deserialize()?;
+        Ok(Self {
+            doc,
+            grantor,
+            grant,
+        })
+    }
+
+    /// Get the capability document.
+    pub fn doc(&self) -> &Document {
+        &self.doc
+    }
+
+    /// Get the hash of the capability document.
+    pub fn hash(&self) -> &Hash {
+        self.doc.hash()
+    }
+
+    /// Get the Identity that granted this capability.
+    pub fn grantor(&self) -> &Identity {
+        &self.grantor
+    }
+
+    /// Get the Identity this capability was granted to.
+    pub fn grantee(&self) -> &Identity {
+        &self.grant.grantee
+    }
+
+    /// Get the parent document entries must be attached to, if the grant is limited to one.
+    pub fn parent(&self) -> Option<&Hash> {
+        self.grant.parent.as_ref()
+    }
+
+    /// Get the permitted entry keys.
+    pub fn keys(&self) -> &[String] {
+        &self.grant.keys
+    }
+
+    /// Get the time after which this capability permits nothing, if there is one.
+    pub fn expires(&self) -> Option<Timestamp> {
+        self.grant.expires
+    }
+
+    /// Check if the capability permits an entry or query right now. See
+    /// [`permits_at`][Self::permits_at].
+    pub fn permits<P: Permissible + ?Sized>(&self, target: &P) -> bool {
+        self.permits_at(target, Timestamp::now())
+    }
+
+    /// Check if the capability permits an entry or query at the given time. It must use one of
+    /// the permitted keys, be attached to the permitted parent if one was set, and, if it has a
+    /// signer, be signed by the grantee. Entries must always be signed.
+    pub fn permits_at<P: Permissible + ?Sized>(&self, target: &P, now: Timestamp) -> bool {
+        if matches!(self.grant.expires, Some(expires) if now > expires) {
+            return false;
+        }
+        if !self.grant.keys.iter().any(|k| k == target.key()) {
+            return false;
+        }
+        if let (Some(required), Some(parent)) = (&self.grant.parent, target.parent()) {
+            if required != parent {
+                return false;
+            }
+        }
+        match target.actor() {
+            Some(actor) => actor == &self.grant.grantee,
+            // Only queries have no way of knowing their actor
+            None => target.parent().is_none(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{entry::NewEntry, query::NewQuery, types::IdentityKey};
+
+    #[test]
+    fn permits() {
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .entry_add("post", Validator::Any, None)
+            .entry_add("admin", Validator::Any, None)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let owner = IdentityKey::new();
+        let guest = IdentityKey::new();
+        let board = NewDocument::new(Some(schema.hash()), "board").unwrap();
+        let board = schema.validate_new_doc(board).unwrap();
+        let other = NewDocument::new(Some(schema.hash()), "other").unwrap();
+        let other = schema.validate_new_doc(other).unwrap();
+        let entry = |key: &str, parent: &Document, signer: Option<&IdentityKey>| {
+            let mut entry = NewEntry::new(key, parent, "data").unwrap();
+            if let Some(signer) = signer {
+                entry = entry.sign(signer).unwrap();
+            }
+            schema
+                .validate_new_entry(entry)
+                .unwrap()
+                .complete()
+                .unwrap()
+        };
+
+        let expires = Timestamp::now() + 3600;
+        let grant = NewCapability::new(guest.id().clone())
+            .parent(board.hash())
+            .key_add("post")
+            .key_add("post")
+            .expires(expires)
+            .sign(&owner)
+            .unwrap();
+        let cap = Capability::from_doc(grant).unwrap();
+        assert_eq!(cap.grantor(), owner.id());
+        assert_eq!(cap.grantee(), guest.id());
+        assert_eq!(cap.keys(), &["post".to_owned()]);
+        assert_eq!(cap.parent(), Some(board.hash()));
+
+        assert!(cap.permits(&entry("post", &board, Some(&guest))));
+        assert!(!cap.permits(&entry("post", &board, Some(&owner))));
+        assert!(!cap.permits(&entry("post", &board, None)));
+        assert!(!cap.permits(&entry("admin", &board, Some(&guest))));
+        assert!(!cap.permits(&entry("post", &other, Some(&guest))));
+        assert!(!cap.permits_at(&entry("post", &board, Some(&guest)), expires + 1));
+
+        let query = schema
+            .decode_query(
+                schema
+                    .encode_query(NewQuery::new("post", Validator::Any))
+                    .unwrap(),
+            )
+            .unwrap();
+        assert!(cap.permits(&query));
+
+        // Only signed capability documents are accepted
+        let unsigned = NewDocument::new(
+            Some(super::schema().hash()),
+            Grant {
+                grantee: guest.id().clone(),
+                parent: None,
+                keys: vec!["post".into()],
+                expires: None,
+            },
+        )
+        .unwrap();
+        let unsigned = super::schema().validate_new_doc(unsigned).unwrap();
+        assert!(matches!(
+            Capability::from_doc(unsigned),
+            Err(Error::BadSignature)
+        ));
+        assert!(Capability::from_doc(board).is_err());
+    }
+}
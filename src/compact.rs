@@ -0,0 +1,363 @@
+//! Compaction of entry logs into snapshot documents.
+//!
+//! A feed of entries - a chat log, a list of edits, a counter's updates - grows forever unless
+//! old entries can be dropped. Compaction folds a sequence of entries into a single snapshot
+//! document, using the [`Reducer`] the schema declares for that entry key with
+//! [`entry_reducer`][crate::schema::SchemaBuilder::entry_reducer]. The snapshot records the hash
+//! of the last entry folded into it, so every node that compacts the same entries in the same
+//! order produces the same snapshot, and knows which entries it can now discard.
+//!
+//! A snapshot can itself be compacted further, by passing it along with the entries that came
+//! after it.
+//!
+//! ```
+//! # use fog_pack::{compact::*, document::*, entry::*, fog_value};
+//! # use fog_pack::{schema::*, types::*, validator::*};
+//! # fn main() -> fog_pack::error::Result<()> {
+//! let schema_doc = SchemaBuilder::new(Validator::Any)
+//!     .entry_add("edit", Validator::Any, None)
+//!     .entry_reducer("edit", Reducer::Merge)
+//!     .build()?;
+//! let schema = Schema::from_doc(&schema_doc)?;
+//! let parent = schema.validate_new_doc(NewDocument::new(Some(schema.hash()), "page")?)?;
+//!
+//! let mut edits = Vec::new();
+//! for (key, val) in [("title", "Hello"), ("body", "..."), ("title", "Hi")] {
+//!     let edit = NewEntry::new("edit", &parent, fog_value!({ key: val }))?;
+//!     edits.push(schema.validate_new_entry(edit)?.complete()?);
+//! }
+//!
+//! let snapshot = compact(&schema, parent.hash(), "edit", None, &edits)?;
+//! assert_eq!(snapshot.last(), Some(edits[2].hash()));
+//! assert_eq!(snapshot.state()["title"], Value::from("Hi"));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    document::{Document, NewDocument},
+    entry::Entry,
+    error::{Error, Result, ValidateError},
+    patch::{self, ValuePatch},
+    schema::{Schema, SchemaBuilder},
+    types::{Hash, Value},
+    validator::*,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, sync::OnceLock};
+
+/// How a sequence of entries is folded into a single value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Reducer {
+    /// Collect every entry's value into an array, in order.
+    Append,
+    /// Keep only the last entry's value.
+    Last,
+    /// Merge each entry's value into the state. Maps are merged key by key, recursively; any other
+    /// value replaces what was there.
+    Merge,
+    /// Each entry holds a [`ValuePatch`], which is applied to the state in turn.
+    Patch,
+}
+
+impl Reducer {
+    /// Get the state before any entries have been folded in.
+    pub fn initial(&self) -> Value {
+        match self {
+            Reducer::Append => Value::Array(Vec::new()),
+            Reducer::Merge => Value::Map(BTreeMap::new()),
+            Reducer::Last | Reducer::Patch => Value::Null,
+        }
+    }
+
+    /// Fold one entry into the state.
+    pub fn fold(&self, state: &mut Value, entry: &Entry) -> Result<()> {
+        match self {
+            Reducer::Append => {
+                if !state.is_array() {
+                    *state = Value::Array(Vec::new());
+                }
+                if let Value::Array(array) = state {
+                    array.push(entry.deserialize()?);
+                }
+            }
+            Reducer::Last => *state = entry.deserialize()?,
+            Reducer::Merge => merge_into(state, entry.deserialize()?),
+            Reducer::Patch => patch::merge(state, entry.deserialize::<ValuePatch>()?),
+        }
+        Ok(())
+    }
+}
+
+fn merge_into(target: &mut Value, value: Value) {
+    match (target, value) {
+        (Value::Map(target), Value::Map(map)) => {
+            for (k, v) in map {
+                merge_into(target.entry(k).or_insert(Value::Null), v);
+            }
+        }
+        (target, value) => *target = value,
+    }
+}
+
+static SCHEMA: OnceLock<Schema> = OnceLock::new();
+
+/// Get the built-in schema that snapshot documents use.
+pub fn snapshot_schema() -> &'static Schema {
+    SCHEMA.get_or_init(|| {
+        let validator = MapValidator::new()
+            .req_add("parent", HashValidator::new().build())
+            .req_add("key", StrValidator::new().build())
+            .opt_add("last", HashValidator::new().build())
+            .req_add("count", IntValidator::new().min(0u8).build())
+            .req_add("state", Validator::Any)
+            .build();
+        let doc = SchemaBuilder::new(validator)
+            .name("fog-pack snapshot")
+            .description("A sequence of entries, compacted into a single value")
+            .build()
+            .expect("built-in snapshot schema should be valid");
+        Schema::from_doc(&doc).expect("built-in snapshot schema should be valid")
+    })
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct SnapshotData {
+    parent: Hash,
+    key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last: Option<Hash>,
+    count: u64,
+    state: Value,
+}
+
+/// A snapshot of a compacted sequence of entries.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    doc: Document,
+    data: SnapshotData,
+}
+
+impl Snapshot {
+    /// Read a snapshot out of a document. Fails if the document doesn't use the
+    /// [snapshot schema][snapshot_schema].
+    pub fn from_doc(doc: Document) -> Result<Self> {
+        if doc.schema_hash() != Some(snapshot_schema().hash()) {
+            return Err(Error::SchemaMismatch {
+                actual: doc.schema_hash().cloned(),
+                expected: Some(snapshot_schema().hash().clone()),
+            });
+        }
+        let data = doc.deserialize()?;
+        Ok(Self { doc, data })
+    }
+
+    /// Get the snapshot document.
+    pub fn doc(&self) -> &Document {
+        &self.doc
+    }
+
+    /// Get the snapshot document, consuming the snapshot.
+    pub fn into_doc(self) -> Document {
+        self.doc
+    }
+
+    /// Get the parent document of the compacted entries.
+    pub fn parent(&self) -> &Hash {
+        &self.data.parent
+    }
+
+    /// Get the key of the compacted entries.
+    pub fn key(&self) -> &str {
+        &self.data.key
+    }
+
+    /// Get the hash of the last entry folded into the snapshot. Only `None` if no entries were
+    /// compacted.
+    pub fn last(&self) -> Option<&Hash> {
+        self.data.last.as_ref()
+    }
+
+    /// Get the total number of entries folded into the snapshot, including those in any earlier
+    /// snapshot it was built on.
+    pub fn count(&self) -> u64 {
+        self.data.count
+    }
+
+    /// Get the compacted state.
+    pub fn state(&self) -> &Value {
+        &self.data.state
+    }
+}
+
+/// Compact a sequence of entries into a snapshot, using the schema's reducer for the entry key.
+///
+/// The entries must be in order, all attached to `parent`, all using `key`, and all validated
+/// by `schema`. If `previous` is given, the entries are folded into it, continuing where it left
+/// off. Fails if the schema doesn't declare a reducer for the key, if any entry doesn't belong,
+/// or if an entry's value can't be folded in.
+pub fn compact<'a>(
+    schema: &Schema,
+    parent: &Hash,
+    key: &str,
+    previous: Option<&Snapshot>,
+    entries: impl IntoIterator<Item = &'a Entry>,
+) -> Result<Snapshot> {
+    let fail = |msg: String| Error::FailValidate(ValidateError::Other(msg.into()));
+    let reducer = schema
+        .reducer(key)
+        .ok_or_else(|| fail(format!("schema has no reducer for entry key {:?}", key)))?;
+    let mut data = match previous {
+        Some(previous) => {
+            if previous.parent() != parent || previous.key() != key {
+                return Err(fail(
+                    "previous snapshot is for a different parent or key".into(),
+                ));
+            }
+            previous.data.clone()
+        }
+        None => SnapshotData {
+            parent: parent.clone(),
+            key: key.to_owned(),
+            last: None,
+            count: 0,
+            state: reducer.initial(),
+        },
+    };
+    for entry in entries {
+        if entry.schema_hash() != schema.hash() {
+            return Err(Error::SchemaMismatch {
+                actual: Some(entry.schema_hash().clone()),
+                expected: Some(schema.hash().clone()),
+            });
+        }
+        if entry.parent() != parent || entry.key() != key {
+            return Err(fail(format!(
+                "entry {} doesn't belong to the sequence being compacted",
+                entry.hash()
+            )));
+        }
+        reducer.fold(&mut data.state, entry)?;
+        data.last = Some(entry.hash().clone());
+        data.count += 1;
+    }
+    let schema = snapshot_schema();
+    let doc = schema.validate_new_doc(NewDocument::new(Some(schema.hash()), &data)?)?;
+    Ok(Snapshot { doc, data })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{entry::NewEntry, fog_value};
+
+    fn entry<S: Serialize>(schema: &Schema, parent: &Document, key: &str, data: S) -> Entry {
+        let entry = NewEntry::new(key, parent, data).unwrap();
+        schema
+            .validate_new_entry(entry)
+            .unwrap()
+            .complete()
+            .unwrap()
+    }
+
+    #[test]
+    fn reducers() {
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .entry_add("append", Validator::Any, None)
+            .entry_add("last", Validator::Any, None)
+            .entry_add("merge", Validator::Any, None)
+            .entry_add("patch", Validator::Any, None)
+            .entry_add("none", Validator::Any, None)
+            .entry_reducer("append", Reducer::Append)
+            .entry_reducer("last", Reducer::Last)
+            .entry_reducer("merge", Reducer::Merge)
+            .entry_reducer("patch", Reducer::Patch)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        assert_eq!(schema.reducer("merge"), Some(Reducer::Merge));
+        assert_eq!(schema.reducer("none"), None);
+        let parent = NewDocument::new(Some(schema.hash()), "parent").unwrap();
+        let parent = schema.validate_new_doc(parent).unwrap();
+        let run = |key: &str, entries: &[Entry]| {
+            compact(&schema, parent.hash(), key, None, entries).unwrap()
+        };
+
+        let nums: Vec<Entry> = (1u8..4)
+            .map(|i| entry(&schema, &parent, "append", i))
+            .collect();
+        let snapshot = run("append", &nums);
+        assert_eq!(snapshot.state(), &fog_value!([1u8, 2u8, 3u8]));
+        assert_eq!(snapshot.count(), 3);
+        let nums: Vec<Entry> = (1u8..4)
+            .map(|i| entry(&schema, &parent, "last", i))
+            .collect();
+        assert_eq!(run("last", &nums).state(), &Value::from(3u8));
+
+        let merges = [
+            fog_value!({"a": 1, "b": {"c": 2, "d": 3}}),
+            fog_value!({"b": {"c": 4}, "e": 5}),
+        ]
+        .map(|v| entry(&schema, &parent, "merge", v));
+        assert_eq!(
+            run("merge", &merges).state(),
+            &fog_value!({"a": 1, "b": {"c": 4, "d": 3}, "e": 5})
+        );
+
+        let patches = [
+            patch::diff(&Value::Null, &fog_value!({"a": 1, "b": 2})),
+            patch::diff(&fog_value!({"a": 1, "b": 2}), &fog_value!({"a": 3})),
+        ]
+        .map(|p| entry(&schema, &parent, "patch", p));
+        assert_eq!(run("patch", &patches).state(), &fog_value!({"a": 3}));
+
+        // No reducer, or entries that don't belong
+        let none = [entry(&schema, &parent, "none", ())];
+        assert!(compact(&schema, parent.hash(), "none", None, &none).is_err());
+        assert!(compact(&schema, parent.hash(), "last", None, &none).is_err());
+    }
+
+    #[test]
+    fn incremental() {
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .entry_add("log", Validator::Any, None)
+            .entry_reducer("log", Reducer::Append)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let parent = NewDocument::new(Some(schema.hash()), "parent").unwrap();
+        let parent = schema.validate_new_doc(parent).unwrap();
+        let log: Vec<Entry> = (0u32..10)
+            .map(|i| entry(&schema, &parent, "log", i))
+            .collect();
+
+        let all = compact(&schema, parent.hash(), "log", None, &log).unwrap();
+        let first = compact(&schema, parent.hash(), "log", None, &log[..4]).unwrap();
+        assert_eq!(first.last(), Some(log[3].hash()));
+        let first = Snapshot::from_doc(first.into_doc()).unwrap();
+        let rest = compact(&schema, parent.hash(), "log", Some(&first), &log[4..]).unwrap();
+        // Compacting in steps gives the same snapshot as compacting everything at once
+        assert_eq!(rest.doc().hash(), all.doc().hash());
+        assert_eq!(rest.count(), 10);
+        assert_eq!(rest.key(), "log");
+        assert_eq!(rest.parent(), parent.hash());
+
+        // Entries must come from the schema whose reducer is used
+        let other_doc = SchemaBuilder::new(Validator::Any)
+            .description("Another schema")
+            .entry_add("log", Validator::Any, None)
+            .entry_reducer("log", Reducer::Append)
+            .build()
+            .unwrap();
+        let other = Schema::from_doc(&other_doc).unwrap();
+        assert!(matches!(
+            compact(&other, parent.hash(), "log", None, &log),
+            Err(Error::SchemaMismatch { .. })
+        ));
+
+        let empty = compact(&schema, parent.hash(), "log", None, &log[..0]).unwrap();
+        assert_eq!(empty.last(), None);
+        assert!(Snapshot::from_doc(parent).is_err());
+    }
+}
@@ -1,21 +1,31 @@
 use crate::error::{Error, Result};
 use serde::{Deserialize, Serialize};
 use serde_bytes::ByteBuf;
-use std::{convert::TryFrom, fmt};
+#[cfg(feature = "zstd")]
+use std::cell::RefCell;
+use std::{convert::TryFrom, fmt, sync::Arc};
 
 /// The compression algorithm identifier for `zstandard`.
 pub const ALGORITHM_ZSTD: u8 = 0;
 
+/// The compression algorithm identifier for `lz4`.
+pub const ALGORITHM_LZ4: u8 = 1;
+
+/// The default compression threshold: documents and entries with less data than this, in bytes,
+/// are never compressed by default, as compressing them wastes time and rarely makes them any
+/// smaller. See [`SchemaBuilder::compress_threshold`][crate::schema::SchemaBuilder::compress_threshold].
+pub const DEFAULT_COMPRESS_THRESHOLD: u32 = 64;
+
 /// Defines the compression types supported by documents & entries. Format when encoded is a single
-/// byte, with the lowest two bits indicating the actual compression type. The upper 6 bits are
-/// reserved for possible future compression formats. For now, the only allowed compression is
-/// zstd, where the upper 6 bits are 0.
+/// byte, with the lowest two bits indicating the actual compression type. For general
+/// compression, the upper 6 bits hold the compression algorithm identifier. For no compression
+/// and dictionary compression, the upper 6 bits are 0.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub(crate) enum CompressType {
     /// No compression
     None,
-    /// Standard Compression
-    General,
+    /// Standard Compression, with the algorithm identifier
+    General(u8),
     /// Dictionary compression
     Dict,
 }
@@ -24,7 +34,7 @@ impl CompressType {
     pub fn type_of(compress: &Compress) -> Self {
         match compress {
             Compress::None => CompressType::None,
-            Compress::General { .. } => CompressType::General,
+            Compress::General { algorithm, .. } => CompressType::General(*algorithm),
             Compress::Dict(_) => CompressType::Dict,
         }
     }
@@ -34,7 +44,7 @@ impl From<CompressType> for u8 {
     fn from(val: CompressType) -> u8 {
         match val {
             CompressType::None => 0,
-            CompressType::General => 1,
+            CompressType::General(algorithm) => (algorithm << 2) | 1,
             CompressType::Dict => 2,
         }
     }
@@ -45,13 +55,94 @@ impl TryFrom<u8> for CompressType {
     fn try_from(val: u8) -> Result<CompressType, u8> {
         match val {
             0 => Ok(CompressType::None),
-            1 => Ok(CompressType::General),
             2 => Ok(CompressType::Dict),
+            _ if (val & 0x3) == 1 => Ok(CompressType::General(val >> 2)),
             _ => Err(val),
         }
     }
 }
 
+/// A compression algorithm, used for general (non-dictionary) compression.
+///
+/// Every compressor has an algorithm identifier, which is stored in the upper 6 bits of the
+/// compression marker byte of any document or entry it compresses. This lets a decoder tell
+/// exactly which algorithm was used, and fail cleanly if it doesn't support it.
+///
+/// Which compressors are available depends on the enabled crate features: `zstd` (enabled by
+/// default) provides [`Zstd`], and `lz4` provides [`Lz4`]. With neither, documents & entries can
+/// still be encoded and decoded, but are never compressed. Use [`compressor`] to look up a
+/// compressor by its identifier.
+///
+/// Other algorithms can be supplied by implementing this trait and handing the compressor to
+/// [`Schema::with_compressor`][crate::schema::Schema::with_compressor]. A schema's own compressor
+/// takes precedence over the built-in one with the same identifier.
+pub trait Compressor: Send + Sync {
+    /// The algorithm identifier. Must be less than 64, or
+    /// [`Schema::with_compressor`][crate::schema::Schema::with_compressor] will panic.
+    fn algorithm(&self) -> u8;
+
+    /// Compress `src` at the given level, appending the result to `dest`. Returns `None` if
+    /// compression fails or the result isn't shorter than `src`, in which case the buffer is
+    /// discarded.
+    fn compress(&self, level: u8, dest: Vec<u8>, src: &[u8]) -> Option<Vec<u8>>;
+
+    /// Decompress `src`, appending the result to `dest`. Must fail if more than `max_len` bytes
+    /// would be appended, and must not allocate much more memory than the amount of data actually
+    /// decompressed.
+    fn decompress(&self, dest: &mut Vec<u8>, src: &[u8], max_len: usize) -> Result<()>;
+
+    /// Decompress `src` like [`decompress`][Self::decompress], calling `progress` with everything
+    /// appended to `dest` so far each time more of it is ready. If `progress` fails, decompression
+    /// stops with its error, so bad data can be rejected before the rest of it is decompressed.
+    ///
+    /// The default implementation decompresses everything at once and calls `progress` a single
+    /// time at the end.
+    fn decompress_chunked(
+        &self,
+        dest: &mut Vec<u8>,
+        src: &[u8],
+        max_len: usize,
+        progress: &mut dyn FnMut(&[u8]) -> Result<()>,
+    ) -> Result<()> {
+        let start = dest.len();
+        self.decompress(dest, src, max_len)?;
+        progress(&dest[start..])
+    }
+}
+
+/// Look up the compressor for an algorithm identifier. Returns `None` if the algorithm isn't
+/// supported, or its crate feature isn't enabled.
+pub fn compressor(algorithm: u8) -> Option<&'static dyn Compressor> {
+    match algorithm {
+        #[cfg(feature = "zstd")]
+        ALGORITHM_ZSTD => Some(&Zstd),
+        #[cfg(feature = "lz4")]
+        ALGORITHM_LZ4 => Some(&Lz4),
+        _ => None,
+    }
+}
+
+// Look up the compressor for an algorithm identifier, preferring the user-supplied one if it uses
+// that identifier.
+fn find_compressor(algorithm: u8, custom: Option<&dyn Compressor>) -> Option<&dyn Compressor> {
+    match custom {
+        Some(custom) if custom.algorithm() == algorithm => Some(custom),
+        _ => compressor(algorithm),
+    }
+}
+
+/// A user-supplied [`Compressor`], shared between every copy of the schema it was given to.
+#[derive(Clone)]
+pub(crate) struct SharedCompressor(pub(crate) Arc<dyn Compressor>);
+
+impl fmt::Debug for SharedCompressor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SharedCompressor")
+            .field(&self.0.algorithm())
+            .finish()
+    }
+}
+
 /// Compression settings for Documents and Entries.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
@@ -78,66 +169,78 @@ impl Compress {
         }
     }
 
+    /// Create a new general LZ4 Compression setting. LZ4 has no compression levels.
+    pub fn new_lz4_general() -> Self {
+        Compress::General {
+            algorithm: ALGORITHM_LZ4,
+            level: 0,
+        }
+    }
+
     /// Create a new ZStandard dictionary with the given compression level.
     pub fn new_zstd_dict(level: u8, dict: Vec<u8>) -> Self {
         Compress::Dict(Dictionary::new_zstd(level, dict))
     }
 
     /// Attempt to compress the data. Failure occurs if this shouldn't compress, compression fails,
-    /// or the result is longer than the original. On failure, the buffer is discarded.
-    pub(crate) fn compress(&self, mut dest: Vec<u8>, src: &[u8]) -> Result<Vec<u8>, ()> {
+    /// the algorithm isn't supported, or the result is longer than the original. On failure, the
+    /// buffer is discarded. `custom` is used instead of the built-in compressors if it matches
+    /// the algorithm.
+    pub(crate) fn compress(
+        &self,
+        dest: Vec<u8>,
+        src: &[u8],
+        custom: Option<&dyn Compressor>,
+    ) -> Result<Vec<u8>, ()> {
         match self {
             Compress::None => Err(()),
-            Compress::General { level, .. } => {
-                let dest_len = dest.len();
-                let max_len = zstd_safe::compress_bound(src.len());
-                dest.resize(dest_len + max_len, 0);
-                match zstd_safe::compress(&mut dest[dest_len..], src, *level as i32) {
-                    Ok(len) if len < src.len() => {
-                        dest.truncate(dest_len + len);
-                        Ok(dest)
-                    }
-                    _ => Err(()),
-                }
-            }
-            Compress::Dict(dict) => {
-                let dest_len = dest.len();
-                let max_len = zstd_safe::compress_bound(src.len());
-                dest.resize(dest_len + max_len, 0u8);
-                match &dict.0 {
-                    DictionaryPrivate::Unknown { level, .. } => {
-                        match zstd_safe::compress(&mut dest[dest_len..], src, *level as i32) {
-                            Ok(len) if len < src.len() => {
-                                dest.truncate(dest_len + len);
-                                Ok(dest)
-                            }
-                            _ => Err(()),
-                        }
-                    }
-                    DictionaryPrivate::Zstd { cdict, .. } => {
-                        let mut ctx = zstd_safe::CCtx::create();
-                        match ctx.compress_using_cdict(&mut dest[dest_len..], src, cdict) {
-                            Ok(len) if len < src.len() => {
-                                dest.truncate(dest_len + len);
-                                Ok(dest)
-                            }
-                            _ => Err(()),
-                        }
-                    }
-                }
-            }
+            Compress::General { algorithm, level } => find_compressor(*algorithm, custom)
+                .and_then(|c| c.compress(*level, dest, src))
+                .ok_or(()),
+            Compress::Dict(dict) => dict.compress(dest, src),
         }
     }
 
-    /// Attempt to decompress the data. Fails if the result in `dest` would be greater than
-    /// `max_size`, or if decompression fails.
+    /// Run a small sample through compression and decompression, so the calling thread's
+    /// compression contexts are set up before any real data needs them.
+    pub(crate) fn warm(&self, custom: Option<&dyn Compressor>) {
+        let sample = [0u8; 1024];
+        if let Ok(compressed) = self.compress(Vec::new(), &sample, custom) {
+            let marker = CompressType::type_of(self);
+            let _ = self.decompress(Vec::new(), &compressed, marker, 0, sample.len(), custom);
+        }
+    }
+
+    /// Attempt to decompress the data. Fails if the result in `dest`, plus the `extra_size` bytes
+    /// that will be appended afterwards, would be greater than `max_size`, or if decompression
+    /// fails. Memory use stays proportional to the amount of data actually decompressed. `custom`
+    /// is used instead of the built-in compressors if it matches the marker's algorithm.
     pub(crate) fn decompress(
+        &self,
+        dest: Vec<u8>,
+        src: &[u8],
+        marker: CompressType,
+        extra_size: usize,
+        max_size: usize,
+        custom: Option<&dyn Compressor>,
+    ) -> Result<Vec<u8>> {
+        self.decompress_chunked(dest, src, marker, extra_size, max_size, custom, &mut |_| {
+            Ok(())
+        })
+    }
+
+    /// Decompress the data just like [`decompress`][Self::decompress], but call `progress` with
+    /// the decompressed data as it becomes available. See [`Compressor::decompress_chunked`].
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn decompress_chunked(
         &self,
         mut dest: Vec<u8>,
         src: &[u8],
         marker: CompressType,
         extra_size: usize,
         max_size: usize,
+        custom: Option<&dyn Compressor>,
+        progress: &mut dyn FnMut(&[u8]) -> Result<()>,
     ) -> Result<Vec<u8>> {
         match marker {
             CompressType::None => {
@@ -150,78 +253,30 @@ impl Compress {
                 } else {
                     dest.reserve(src.len() + extra_size);
                     dest.extend_from_slice(src);
+                    progress(src)?;
                     Ok(dest)
                 }
             }
-            CompressType::General => {
-                // Prep for decompressed data
-                let header_len = dest.len();
-                let Ok(Some(expected_len)) = zstd_safe::get_frame_content_size(src) else {
-                    return Err(Error::FailDecompress("Compression frame header is invalid".into()));
-                };
-                if expected_len > (max_size - header_len) as u64 {
-                    return Err(Error::FailDecompress(format!(
-                        "Decompressed length {} would be larger than maximum of {}",
-                        dest.len() + src.len(),
-                        max_size
-                    )));
-                }
-                let expected_len = expected_len as usize;
-                dest.reserve(expected_len + extra_size);
-                dest.resize(header_len + expected_len, 0u8);
-
-                // Safety: Immediately before this, we reserve enough space for the header and the
-                // expected length, so setting the length is OK. The decompress function overwrites
-                // data and returns the new valid length, so no data is uninitialized after this
-                // block completes. In the event of a failure, the vec is freed, so it is never
-                // returned in an invalid state.
-                let len = zstd_safe::decompress(&mut dest[header_len..], src).map_err(|e| {
-                    Error::FailDecompress(format!("Failed Decompression, zstd error = {}", e))
+            CompressType::General(algorithm) => {
+                let compressor = find_compressor(algorithm, custom).ok_or_else(|| {
+                    Error::BadHeader(format!(
+                        "Header uses compression algorithm {}, which isn't supported",
+                        algorithm
+                    ))
                 })?;
-                dest.truncate(header_len + len);
+                let max_len = max_size.saturating_sub(dest.len() + extra_size);
+                compressor.decompress_chunked(&mut dest, src, max_len, progress)?;
+                dest.reserve_exact(extra_size);
                 Ok(dest)
             }
             CompressType::Dict => {
-                // Fetch dictionary
-                let ddict = if let Compress::Dict(Dictionary(DictionaryPrivate::Zstd {
-                    ddict,
-                    ..
-                })) = self
-                {
-                    ddict
-                } else {
+                let Compress::Dict(dict) = self else {
                     return Err(Error::BadHeader(
                             "Header uses dictionary compression, but this has no matching supported dictionary".into()));
                 };
-
-                // Prep for decompressed data
-                let header_len = dest.len();
-                let Ok(Some(expected_len)) = zstd_safe::get_frame_content_size(src) else {
-                    return Err(Error::FailDecompress("Compression frame header is invalid".into()));
-                };
-                if expected_len > (max_size - header_len) as u64 {
-                    return Err(Error::FailDecompress(format!(
-                        "Decompressed length {} would be larger than maximum of {}",
-                        dest.len() + src.len(),
-                        max_size
-                    )));
-                }
-                let expected_len = expected_len as usize;
-                dest.reserve(expected_len + extra_size);
-                dest.resize(header_len + expected_len, 0u8);
-
-                // Safety: Immediately before this, we reserve enough space for the header and the
-                // expected length, so setting the length is OK. The decompress function overwrites
-                // data and returns the new valid length, so no data is uninitialized after this
-                // block completes. In the event of a failure, the vec is freed, so it is never
-                // returned in an invalid state.
-                let mut dctx = zstd_safe::DCtx::create();
-                let len = dctx
-                    .decompress_using_ddict(&mut dest[header_len..], src, ddict)
-                    .map_err(|e| {
-                        Error::FailDecompress(format!("Failed Decompression, zstd error = {}", e))
-                    })?;
-                dest.truncate(header_len + len);
+                let max_len = max_size.saturating_sub(dest.len() + extra_size);
+                dict.decompress(&mut dest, src, max_len, progress)?;
+                dest.reserve_exact(extra_size);
                 Ok(dest)
             }
         }
@@ -237,24 +292,313 @@ impl std::default::Default for Compress {
     }
 }
 
+/// The ZStandard compressor. Requires the `zstd` feature.
+#[cfg(feature = "zstd")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Zstd;
+
+#[cfg(feature = "zstd")]
+impl Compressor for Zstd {
+    fn algorithm(&self) -> u8 {
+        ALGORITHM_ZSTD
+    }
+
+    fn compress(&self, level: u8, mut dest: Vec<u8>, src: &[u8]) -> Option<Vec<u8>> {
+        let dest_len = dest.len();
+        let max_len = zstd_safe::compress_bound(src.len());
+        dest.resize(dest_len + max_len, 0);
+        let result = with_cctx(|ctx| ctx.compress(&mut dest[dest_len..], src, level as i32));
+        match result {
+            Ok(len) if len < src.len() => {
+                dest.truncate(dest_len + len);
+                Some(dest)
+            }
+            _ => None,
+        }
+    }
+
+    fn decompress(&self, dest: &mut Vec<u8>, src: &[u8], max_len: usize) -> Result<()> {
+        decompress_frame(dest, src, None, max_len, &mut |_| Ok(()))
+    }
+
+    fn decompress_chunked(
+        &self,
+        dest: &mut Vec<u8>,
+        src: &[u8],
+        max_len: usize,
+        progress: &mut dyn FnMut(&[u8]) -> Result<()>,
+    ) -> Result<()> {
+        decompress_frame(dest, src, None, max_len, progress)
+    }
+}
+
+// Setting up a zstd context costs more than compressing a small entry, so each thread keeps one
+// compression and one decompression context and reuses them. Dictionaries are only referenced for
+// the duration of a single call, so a context never holds on to one afterwards.
+#[cfg(feature = "zstd")]
+thread_local! {
+    static CCTX: RefCell<zstd_safe::CCtx<'static>> = RefCell::new(zstd_safe::CCtx::create());
+    static DCTX: RefCell<zstd_safe::DCtx<'static>> = RefCell::new(zstd_safe::DCtx::create());
+}
+
+/// Run a function with this thread's compression context, or a fresh one if it's already in use.
+#[cfg(feature = "zstd")]
+fn with_cctx<R>(f: impl FnOnce(&mut zstd_safe::CCtx<'static>) -> R) -> R {
+    CCTX.with(|ctx| match ctx.try_borrow_mut() {
+        Ok(mut ctx) => f(&mut ctx),
+        Err(_) => f(&mut zstd_safe::CCtx::create()),
+    })
+}
+
+/// Run a function with this thread's decompression context, or a fresh one if it's already in
+/// use. The context is reset afterwards, dropping any dictionary it referenced.
+#[cfg(feature = "zstd")]
+fn with_dctx<R>(f: impl FnOnce(&mut zstd_safe::DCtx<'static>) -> R) -> R {
+    DCTX.with(|ctx| match ctx.try_borrow_mut() {
+        Ok(mut ctx) => {
+            let result = f(&mut ctx);
+            // Resetting only fails on invalid parameters, and the defaults are always valid
+            let _ = ctx.reset(zstd_safe::ResetDirective::SessionAndParameters);
+            result
+        }
+        Err(_) => f(&mut zstd_safe::DCtx::create()),
+    })
+}
+
+#[cfg(feature = "zstd")]
+fn zstd_err(code: usize) -> Error {
+    Error::FailDecompress(format!(
+        "Failed Decompression, zstd error = {}",
+        zstd_safe::get_error_name(code)
+    ))
+}
+
+/// How much data is decompressed from a zstd frame before handing it to the progress callback.
+#[cfg(feature = "zstd")]
+const DECOMPRESS_CHUNK: usize = 4096;
+
+/// Decompress a single zstd frame, appending the output to `dest`. Fails if the output would be
+/// longer than `max_len`, or if `src` holds anything besides exactly one complete frame.
+///
+/// The frame's declared content size is never trusted for allocation. Instead, the frame is
+/// decompressed in small chunks, and the output buffer grows in steps as they are appended, so a
+/// small, highly compressible input can't force a large allocation before the size limit is
+/// reached. `progress` is called with all of the output so far after each chunk.
+#[cfg(feature = "zstd")]
+fn decompress_frame(
+    dest: &mut Vec<u8>,
+    src: &[u8],
+    ddict: Option<&zstd_safe::DDict<'static>>,
+    max_len: usize,
+    progress: &mut dyn FnMut(&[u8]) -> Result<()>,
+) -> Result<()> {
+    // Fail early if the frame header already declares too much data
+    match zstd_safe::get_frame_content_size(src) {
+        Ok(Some(len)) if len > max_len as u64 => {
+            return Err(Error::FailDecompress(format!(
+                "Decompressed length {} would be larger than maximum of {}",
+                len, max_len
+            )))
+        }
+        Ok(_) => (),
+        Err(_) => {
+            return Err(Error::FailDecompress(
+                "Compression frame header is invalid".into(),
+            ))
+        }
+    }
+
+    with_dctx(|dctx| decompress_stream(dctx, dest, src, ddict, max_len, progress))
+}
+
+#[cfg(feature = "zstd")]
+fn decompress_stream(
+    dctx: &mut zstd_safe::DCtx<'static>,
+    dest: &mut Vec<u8>,
+    src: &[u8],
+    ddict: Option<&zstd_safe::DDict<'static>>,
+    max_len: usize,
+    progress: &mut dyn FnMut(&[u8]) -> Result<()>,
+) -> Result<()> {
+    if let Some(ddict) = ddict {
+        dctx.ref_ddict(ddict).map_err(zstd_err)?;
+    }
+    let start = dest.len();
+    let limit = start + max_len;
+    let mut chunk = Vec::with_capacity(DECOMPRESS_CHUNK);
+    let mut input = zstd_safe::InBuffer::around(src);
+    loop {
+        chunk.clear();
+        let remaining = {
+            let mut output = zstd_safe::OutBuffer::around(&mut chunk);
+            dctx.decompress_stream(&mut output, &mut input)
+                .map_err(zstd_err)?
+        };
+        if dest.len() + chunk.len() > limit {
+            return Err(Error::FailDecompress(format!(
+                "Decompressed length {} would be larger than maximum of {}",
+                dest.len() + chunk.len() - start,
+                max_len
+            )));
+        }
+
+        if !chunk.is_empty() {
+            // Grow the output buffer geometrically, but never past the limit
+            if dest.capacity() - dest.len() < chunk.len() {
+                let grow = chunk.len().max(dest.len()).min(limit - dest.len());
+                dest.reserve_exact(grow);
+            }
+            dest.extend_from_slice(&chunk);
+            progress(&dest[start..])?;
+        }
+
+        if remaining == 0 {
+            // The frame is complete, and it must be the only thing in the compressed data
+            if input.pos() != src.len() {
+                return Err(Error::FailDecompress(format!(
+                    "Compressed data has {} bytes after the end of the frame",
+                    src.len() - input.pos()
+                )));
+            }
+            return Ok(());
+        }
+        if input.pos() == src.len() && chunk.len() < chunk.capacity() {
+            return Err(Error::FailDecompress(
+                "Compressed data ended before the frame was complete".into(),
+            ));
+        }
+    }
+}
+
+/// The LZ4 compressor. Requires the `lz4` feature.
+///
+/// Data is stored as a 4-byte little-endian decompressed length, followed by a single LZ4 block.
+#[cfg(feature = "lz4")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Lz4;
+
+#[cfg(feature = "lz4")]
+impl Compressor for Lz4 {
+    fn algorithm(&self) -> u8 {
+        ALGORITHM_LZ4
+    }
+
+    fn compress(&self, _level: u8, mut dest: Vec<u8>, src: &[u8]) -> Option<Vec<u8>> {
+        let len = u32::try_from(src.len()).ok()?;
+        dest.extend_from_slice(&len.to_le_bytes());
+        let dest_len = dest.len();
+        let max_len = lz4_flex::block::get_maximum_output_size(src.len());
+        dest.resize(dest_len + max_len, 0);
+        match lz4_flex::block::compress_into(src, &mut dest[dest_len..]) {
+            Ok(len) if len + 4 < src.len() => {
+                dest.truncate(dest_len + len);
+                Some(dest)
+            }
+            _ => None,
+        }
+    }
+
+    fn decompress(&self, dest: &mut Vec<u8>, src: &[u8], max_len: usize) -> Result<()> {
+        if src.len() < 4 {
+            return Err(Error::FailDecompress(
+                "LZ4 data is missing its length prefix".into(),
+            ));
+        }
+        let (len, block) = src.split_at(4);
+        let len = u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize;
+        if len > max_len {
+            return Err(Error::FailDecompress(format!(
+                "Decompressed length {} would be larger than maximum of {}",
+                len, max_len
+            )));
+        }
+        // LZ4 can't expand data by more than a factor of 255, so a larger claimed length is
+        // rejected before allocating for it.
+        if len > block.len().saturating_mul(255) {
+            return Err(Error::FailDecompress(format!(
+                "Decompressed length {} is impossible for {} bytes of LZ4 data",
+                len,
+                block.len()
+            )));
+        }
+        let start = dest.len();
+        dest.resize(start + len, 0);
+        match lz4_flex::block::decompress_into(block, &mut dest[start..]) {
+            Ok(actual) if actual == len => Ok(()),
+            Ok(actual) => Err(Error::FailDecompress(format!(
+                "Decompressed length {} doesn't match the expected length {}",
+                actual, len
+            ))),
+            Err(e) => Err(Error::FailDecompress(format!(
+                "Failed Decompression, lz4 error = {}",
+                e
+            ))),
+        }
+    }
+}
+
 /// A ZStandard Compression dictionary.
 ///
 /// A new dictionary can be created by providing the desired compression level and the dictionary
-/// as a byte vector.
+/// as a byte vector. Dictionaries can only be used for compression when the `zstd` feature is
+/// enabled.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Dictionary(DictionaryPrivate);
 
 impl Dictionary {
     /// Create a new ZStandard compression dictionary.
     pub fn new_zstd(level: u8, dict: Vec<u8>) -> Self {
-        let cdict = zstd_safe::create_cdict(&dict, level as i32);
-        let ddict = zstd_safe::create_ddict(&dict);
-        Self(DictionaryPrivate::Zstd {
+        Self(DictionaryPrivate::from(DictionarySerde {
+            algorithm: ALGORITHM_ZSTD,
             level,
-            dict,
-            cdict,
-            ddict,
-        })
+            dict: ByteBuf::from(dict),
+        }))
+    }
+
+    fn compress(&self, dest: Vec<u8>, src: &[u8]) -> Result<Vec<u8>, ()> {
+        match &self.0 {
+            #[cfg(feature = "zstd")]
+            DictionaryPrivate::Unknown { level, .. } => Zstd.compress(*level, dest, src).ok_or(()),
+            #[cfg(not(feature = "zstd"))]
+            DictionaryPrivate::Unknown { .. } => Err(()),
+            #[cfg(feature = "zstd")]
+            DictionaryPrivate::Zstd { cdict, .. } => {
+                let mut dest = dest;
+                let dest_len = dest.len();
+                let max_len = zstd_safe::compress_bound(src.len());
+                dest.resize(dest_len + max_len, 0u8);
+                let result =
+                    with_cctx(|ctx| ctx.compress_using_cdict(&mut dest[dest_len..], src, cdict));
+                match result {
+                    Ok(len) if len < src.len() => {
+                        dest.truncate(dest_len + len);
+                        Ok(dest)
+                    }
+                    _ => Err(()),
+                }
+            }
+        }
+    }
+
+    fn decompress(
+        &self,
+        dest: &mut Vec<u8>,
+        src: &[u8],
+        max_len: usize,
+        progress: &mut dyn FnMut(&[u8]) -> Result<()>,
+    ) -> Result<()> {
+        match &self.0 {
+            #[cfg(feature = "zstd")]
+            DictionaryPrivate::Zstd { ddict, .. } => {
+                decompress_frame(dest, src, Some(ddict), max_len, progress)
+            }
+            _ => {
+                // Silence unused warnings when no dictionary algorithm is enabled
+                let _ = (dest, src, max_len, progress);
+                Err(Error::BadHeader(
+                    "Header uses dictionary compression, but this has no matching supported dictionary".into()))
+            }
+        }
     }
 }
 
@@ -266,6 +610,7 @@ enum DictionaryPrivate {
         level: u8,
         dict: Vec<u8>,
     },
+    #[cfg(feature = "zstd")]
     Zstd {
         level: u8,
         dict: Vec<u8>,
@@ -295,6 +640,7 @@ impl Clone for DictionaryPrivate {
                 level: *level,
                 dict: dict.clone(),
             },
+            #[cfg(feature = "zstd")]
             DictionaryPrivate::Zstd { level, dict, .. } => DictionaryPrivate::Zstd {
                 level: *level,
                 dict: dict.clone(),
@@ -313,6 +659,7 @@ impl fmt::Debug for DictionaryPrivate {
                 level,
                 dict,
             } => (algorithm, level, dict),
+            #[cfg(feature = "zstd")]
             DictionaryPrivate::Zstd { level, dict, .. } => (&ALGORITHM_ZSTD, level, dict),
         };
         fmt.debug_struct("Dictionary")
@@ -326,6 +673,7 @@ impl fmt::Debug for DictionaryPrivate {
 impl From<DictionarySerde> for DictionaryPrivate {
     fn from(value: DictionarySerde) -> Self {
         match value.algorithm {
+            #[cfg(feature = "zstd")]
             ALGORITHM_ZSTD => {
                 let cdict = zstd_safe::create_cdict(&value.dict, value.level as i32);
                 let ddict = zstd_safe::create_ddict(&value.dict);
@@ -357,6 +705,7 @@ impl From<DictionaryPrivate> for DictionarySerde {
                 level,
                 dict: ByteBuf::from(dict),
             },
+            #[cfg(feature = "zstd")]
             DictionaryPrivate::Zstd { level, dict, .. } => Self {
                 algorithm: ALGORITHM_ZSTD,
                 level,
@@ -365,3 +714,204 @@ impl From<DictionaryPrivate> for DictionarySerde {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn markers() {
+        for marker in [
+            CompressType::None,
+            CompressType::General(ALGORITHM_ZSTD),
+            CompressType::General(ALGORITHM_LZ4),
+            CompressType::General(63),
+            CompressType::Dict,
+        ] {
+            let byte: u8 = marker.into();
+            assert_eq!(CompressType::try_from(byte), Ok(marker));
+        }
+        // Zstd keeps the same marker it always had
+        assert_eq!(u8::from(CompressType::General(ALGORITHM_ZSTD)), 1);
+        assert_eq!(u8::from(CompressType::General(ALGORITHM_LZ4)), 5);
+        CompressType::try_from(3).unwrap_err();
+        CompressType::try_from(6).unwrap_err();
+        CompressType::try_from(4).unwrap_err();
+    }
+
+    #[test]
+    fn unsupported_algorithm() {
+        let data = vec![0u8; 100];
+        assert!(Compress::General {
+            algorithm: 63,
+            level: 0,
+        }
+        .compress(Vec::new(), &data, None)
+        .is_err());
+        let err = Compress::default()
+            .decompress(
+                Vec::new(),
+                &data,
+                CompressType::General(63),
+                0,
+                1 << 20,
+                None,
+            )
+            .unwrap_err();
+        assert!(matches!(err, Error::BadHeader(_)));
+    }
+
+    #[cfg(feature = "lz4")]
+    mod lz4 {
+        use super::*;
+
+        #[test]
+        fn roundtrip() {
+            let data: Vec<u8> = (0..100_000u32).map(|i| (i % 7) as u8).collect();
+            let compress = Compress::new_lz4_general();
+            let src = compress.compress(Vec::new(), &data, None).unwrap();
+            assert!(src.len() < data.len());
+            let marker = CompressType::type_of(&compress);
+            assert_eq!(u8::from(marker), 5);
+            let dest = compress
+                .decompress(vec![9u8], &src, marker, 0, 1 << 20, None)
+                .unwrap();
+            assert_eq!(dest[0], 9);
+            assert_eq!(&dest[1..], &data[..]);
+        }
+
+        #[test]
+        fn bad_lengths() {
+            let data = vec![0u8; 10_000];
+            let src = Lz4.compress(0, Vec::new(), &data).unwrap();
+            let mut dest = Vec::new();
+            Lz4.decompress(&mut dest, &src, 9_999).unwrap_err();
+            // A length too large for the compressed data is rejected without allocating
+            let mut bomb = src.clone();
+            bomb[..4].copy_from_slice(&(1u32 << 30).to_le_bytes());
+            let mut dest = Vec::new();
+            Lz4.decompress(&mut dest, &bomb, usize::MAX).unwrap_err();
+            assert!(dest.capacity() < 1 << 20);
+        }
+    }
+
+    #[cfg(feature = "zstd")]
+    mod zstd {
+        use super::*;
+
+        const ZSTD: CompressType = CompressType::General(ALGORITHM_ZSTD);
+
+        fn compressed(data: &[u8]) -> Vec<u8> {
+            let mut dest = vec![0u8; zstd_safe::compress_bound(data.len())];
+            let len = zstd_safe::compress(&mut dest[..], data, 3).unwrap();
+            dest.truncate(len);
+            dest
+        }
+
+        // Compress without recording the content size in the frame header
+        fn compressed_unsized(data: &[u8]) -> Vec<u8> {
+            let mut ctx = zstd_safe::CCtx::create();
+            ctx.set_parameter(zstd_safe::CParameter::ContentSizeFlag(false))
+                .unwrap();
+            let mut dest = Vec::with_capacity(zstd_safe::compress_bound(data.len()));
+            ctx.compress2(&mut dest, data).unwrap();
+            dest
+        }
+
+        #[test]
+        fn roundtrip() {
+            let data: Vec<u8> = (0..400_000u32).map(|i| (i % 7) as u8).collect();
+            let src = compressed(&data);
+            let header = vec![1u8, 2, 3];
+            let dest = Compress::default()
+                .decompress(header.clone(), &src, ZSTD, 0, 1 << 20, None)
+                .unwrap();
+            assert_eq!(&dest[..3], &header[..]);
+            assert_eq!(&dest[3..], &data[..]);
+        }
+
+        #[test]
+        fn size_budget() {
+            let data = vec![0u8; 1000];
+            let src = compressed(&data);
+            Compress::default()
+                .decompress(Vec::new(), &src, ZSTD, 0, 1000, None)
+                .unwrap();
+            // The limit covers the header and any extra data appended afterwards
+            Compress::default()
+                .decompress(vec![0u8; 2], &src, ZSTD, 0, 1000, None)
+                .unwrap_err();
+            Compress::default()
+                .decompress(Vec::new(), &src, ZSTD, 1, 1000, None)
+                .unwrap_err();
+        }
+
+        #[test]
+        fn bomb() {
+            // Highly compressible data well past the maximum size shouldn't be fully allocated
+            let data = vec![0u8; 4 << 20];
+            for src in [compressed(&data), compressed_unsized(&data)] {
+                let err = Compress::default()
+                    .decompress(Vec::new(), &src, ZSTD, 0, 1 << 20, None)
+                    .unwrap_err();
+                assert!(matches!(err, Error::FailDecompress(_)));
+            }
+        }
+
+        #[test]
+        fn unsized_frame() {
+            let data: Vec<u8> = (0..300_000u32).map(|i| (i % 13) as u8).collect();
+            let src = compressed_unsized(&data);
+            assert_eq!(zstd_safe::get_frame_content_size(&src).unwrap(), None);
+            let dest = Compress::default()
+                .decompress(Vec::new(), &src, ZSTD, 0, 1 << 20, None)
+                .unwrap();
+            assert_eq!(dest, data);
+        }
+
+        #[test]
+        fn chunked() {
+            let data: Vec<u8> = (0..100_000u32).map(|i| (i % 11) as u8).collect();
+            let src = compressed(&data);
+            let mut seen = Vec::new();
+            let dest = Compress::default()
+                .decompress_chunked(vec![1u8], &src, ZSTD, 0, 1 << 20, None, &mut |out| {
+                    seen.push(out.len());
+                    Ok(())
+                })
+                .unwrap();
+            assert_eq!(&dest[1..], &data[..]);
+            assert!(seen.len() > 1);
+            assert!(seen
+                .windows(2)
+                .all(|w| w[1] > w[0] && w[1] - w[0] <= DECOMPRESS_CHUNK));
+            assert_eq!(seen.last(), Some(&data.len()));
+
+            // A failing callback stops decompression right away
+            let mut calls = 0;
+            Compress::default()
+                .decompress_chunked(Vec::new(), &src, ZSTD, 0, 1 << 20, None, &mut |_| {
+                    calls += 1;
+                    Err(Error::BadEncode("stop".into()))
+                })
+                .unwrap_err();
+            assert_eq!(calls, 1);
+        }
+
+        #[test]
+        fn bad_frames() {
+            let data = vec![5u8; 1000];
+            let src = compressed(&data);
+            let decompress = |src: &[u8]| {
+                Compress::default().decompress(Vec::new(), src, ZSTD, 0, 1 << 20, None)
+            };
+            decompress(&src[..src.len() - 1]).unwrap_err();
+            let mut trailing = src.clone();
+            trailing.push(0);
+            decompress(&trailing).unwrap_err();
+            let mut doubled = src.clone();
+            doubled.extend_from_slice(&src);
+            decompress(&doubled).unwrap_err();
+        }
+    }
+}
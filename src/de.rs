@@ -9,32 +9,69 @@ use serde::de::Error as DeError;
 use serde::de::*;
 
 use crate::{
+    document::UnknownFields,
     element::*,
     error::{Error, Result},
     get_int_internal,
     integer::IntPriv,
+    path::{Path, PathSegment},
+    value::Value,
 };
 
 pub(crate) struct FogDeserializer<'a> {
     parser: Parser<'a>,
+    unknown: UnknownFields,
+    path: Path,
+    captured: Vec<(Path, Value)>,
+    check_order: bool,
 }
 
 impl<'a> FogDeserializer<'a> {
     pub(crate) fn new(buf: &'a [u8]) -> Self {
+        // Deserialization only runs on data that has already been validated, which is where the
+        // float encoding rules are enforced.
+        Self::from_parser(Parser::new(buf).raw_floats(true))
+    }
+
+    pub(crate) fn from_parser(parser: Parser<'a>) -> Self {
         Self {
-            parser: Parser::new(buf),
+            parser,
+            unknown: UnknownFields::Ignore,
+            path: Path::new(),
+            captured: Vec::new(),
+            check_order: true,
         }
     }
 
-    pub(crate) fn from_parser(parser: Parser<'a>) -> Self {
-        Self { parser }
+    /// Skip checking that map keys are in order if `validated` is set. Only for data that is
+    /// known to have passed validation already, like the contents of a
+    /// [`Document`][crate::document::Document] or [`Entry`][crate::entry::Entry] that came out of
+    /// a schema's validation or decoding.
+    pub(crate) fn validated(mut self, validated: bool) -> Self {
+        self.check_order = !validated;
+        self
+    }
+
+    /// Set how values ignored by the deserialization target are handled. Anything other than
+    /// [`UnknownFields::Ignore`] requires tracking the path to the current value.
+    pub(crate) fn with_unknown_fields(buf: &'a [u8], unknown: UnknownFields) -> Self {
+        let mut de = Self::new(buf);
+        de.unknown = unknown;
+        de
     }
 
     #[allow(dead_code)]
     pub(crate) fn with_debug(buf: &'a [u8], indent: impl Into<String>) -> Self {
-        Self {
-            parser: Parser::with_debug(buf, indent),
-        }
+        Self::from_parser(Parser::with_debug(buf, indent))
+    }
+
+    /// Take all values that were captured due to [`UnknownFields::Capture`].
+    pub(crate) fn take_captured(&mut self) -> Vec<(Path, Value)> {
+        std::mem::take(&mut self.captured)
+    }
+
+    fn tracking(&self) -> bool {
+        self.unknown != UnknownFields::Ignore
     }
 
     #[allow(dead_code)]
@@ -73,20 +110,12 @@ impl<'de, 'a> serde::Deserializer<'de> for &'a mut FogDeserializer<'de> {
             Element::Bin(v) => visitor.visit_borrowed_bytes(v),
             Element::Array(len) => visitor.visit_seq(SeqAccess::new(self, len)),
             Element::Map(len) => visitor.visit_map(MapAccess::new(self, len)),
-            Element::Timestamp(v) => visitor.visit_enum(ExtAccess::new(Element::Timestamp(v))),
-            Element::Hash(v) => visitor.visit_enum(ExtAccess::new(Element::Hash(v))),
-            Element::Identity(v) => visitor.visit_enum(ExtAccess::new(Element::Identity(v))),
-            Element::LockId(v) => visitor.visit_enum(ExtAccess::new(Element::LockId(v))),
-            Element::StreamId(v) => visitor.visit_enum(ExtAccess::new(Element::StreamId(v))),
-            Element::DataLockbox(v) => visitor.visit_enum(ExtAccess::new(Element::DataLockbox(v))),
-            Element::IdentityLockbox(v) => {
-                visitor.visit_enum(ExtAccess::new(Element::IdentityLockbox(v)))
-            }
-            Element::StreamLockbox(v) => {
-                visitor.visit_enum(ExtAccess::new(Element::StreamLockbox(v)))
+            elem => {
+                let name = elem.name();
+                visitor
+                    .visit_enum(ExtAccess::new(elem))
+                    .map_err(|e| ext_any_err(name, e))
             }
-            Element::LockLockbox(v) => visitor.visit_enum(ExtAccess::new(Element::LockLockbox(v))),
-            Element::BareIdKey(v) => visitor.visit_enum(ExtAccess::new(Element::BareIdKey(v))),
         }
     }
 
@@ -163,10 +192,39 @@ impl<'de, 'a> serde::Deserializer<'de> for &'a mut FogDeserializer<'de> {
         self.deserialize_unit(visitor)
     }
 
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.unknown {
+            UnknownFields::Ignore => self.deserialize_any(visitor),
+            UnknownFields::Reject => Err(Error::SerdeFail(format!(
+                "unknown field at \"{}\"",
+                self.path
+            ))),
+            UnknownFields::Capture => {
+                let val = Value::deserialize(&mut *self)?;
+                self.captured.push((self.path.clone(), val));
+                visitor.visit_unit()
+            }
+        }
+    }
+
     serde::forward_to_deserialize_any! {
         bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str
         string bytes byte_buf
-        seq tuple tuple_struct map struct identifier ignored_any
+        seq tuple tuple_struct map struct identifier
+    }
+}
+
+/// Serde can't buffer enums, which is how the fog-pack specialized types are presented to
+/// deserializers. Untagged enums, internally tagged enums, and structs with flattened fields all
+/// rely on that buffering, so point the user in the right direction if that's what failed.
+fn ext_any_err(name: &str, err: Error) -> Error {
+    match err {
+        Error::SerdeFail(msg) => Error::SerdeFail(format!(
+            "{} (while decoding a {} value - note that fog-pack's specialized types can't be \
+            held within untagged enums, internally tagged enums, or flattened structs)",
+            msg, name
+        )),
+        err => err,
     }
 }
 
@@ -382,11 +440,16 @@ impl<'a, 'de> serde::de::VariantAccess<'de> for EnumAccess<'a, 'de> {
 struct SeqAccess<'a, 'de> {
     de: &'a mut FogDeserializer<'de>,
     size_left: usize,
+    index: usize,
 }
 
 impl<'a, 'de> SeqAccess<'a, 'de> {
     fn new(de: &'a mut FogDeserializer<'de>, len: usize) -> Self {
-        Self { de, size_left: len }
+        Self {
+            de,
+            size_left: len,
+            index: 0,
+        }
     }
 }
 
@@ -399,6 +462,13 @@ impl<'a, 'de> serde::de::SeqAccess<'de> for SeqAccess<'a, 'de> {
     {
         if self.size_left > 0 {
             self.size_left -= 1;
+            if self.de.tracking() {
+                self.de.path.push(PathSegment::Index(self.index));
+                self.index += 1;
+                let val = seed.deserialize(&mut *self.de);
+                self.de.path.pop();
+                return Ok(Some(val?));
+            }
             let val = seed.deserialize(&mut *self.de)?;
             Ok(Some(val))
         } else {
@@ -442,6 +512,31 @@ impl<'de> Deserialize<'de> for KeyStr<'de> {
     }
 }
 
+impl<'de> KeyStr<'de> {
+    // Parse an integer key, which must be in the canonical form described in `crate::keys`
+    fn parse_int<T: std::str::FromStr>(self) -> Result<T> {
+        if !crate::keys::is_int_key(self.0) {
+            return Err(Error::SerdeFail(format!(
+                "map key {:?} isn't a canonical integer",
+                self.0
+            )));
+        }
+        self.0
+            .parse()
+            .map_err(|_| Error::SerdeFail(format!("map key {} is out of range", self.0)))
+    }
+}
+
+macro_rules! deserialize_int_key {
+    ($($method:ident => $visit:ident),*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+                visitor.$visit(self.parse_int()?)
+            }
+        )*
+    };
+}
+
 impl<'de> Deserializer<'de> for KeyStr<'de> {
     type Error = Error;
 
@@ -449,8 +544,19 @@ impl<'de> Deserializer<'de> for KeyStr<'de> {
         visitor.visit_borrowed_str(self.0)
     }
 
+    deserialize_int_key! {
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64
+    }
+
     serde::forward_to_deserialize_any! {
-        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str
+        bool f32 f64 char str
         string bytes byte_buf option unit unit_struct newtype_struct
         seq tuple tuple_struct map struct enum identifier ignored_any
     }
@@ -481,7 +587,7 @@ impl<'a, 'de> serde::de::MapAccess<'de> for MapAccess<'a, 'de> {
     {
         if self.size_left > 0 {
             self.size_left -= 1;
-            if let Some(last_str) = self.last_str {
+            if let (true, Some(last_str)) = (self.de.check_order, self.last_str) {
                 let new_str = KeyStr::deserialize(&mut *self.de)?;
                 if new_str.0 <= last_str.0 {
                     return Err(Error::SerdeFail(format!(
@@ -503,6 +609,12 @@ impl<'a, 'de> serde::de::MapAccess<'de> for MapAccess<'a, 'de> {
     where
         V: DeserializeSeed<'de>,
     {
+        if let (true, Some(key)) = (self.de.tracking(), self.last_str) {
+            self.de.path.push(PathSegment::Key(key.0.to_owned()));
+            let val = seed.deserialize(&mut *self.de);
+            self.de.path.pop();
+            return val;
+        }
         seed.deserialize(&mut *self.de)
     }
 
@@ -947,4 +1059,139 @@ mod test {
         de.parser.finish().unwrap();
         assert_eq!(dec, hash);
     }
+
+    fn roundtrip<T>(val: &T, ordered: bool) -> Result<T>
+    where
+        T: serde::Serialize + serde::de::DeserializeOwned,
+    {
+        let mut ser = crate::ser::FogSerializer::with_params(ordered);
+        val.serialize(&mut ser)?;
+        let enc = ser.finish();
+        let mut de = FogDeserializer::new(&enc);
+        let dec = T::deserialize(&mut de)?;
+        de.parser.finish()?;
+        Ok(dec)
+    }
+
+    #[test]
+    fn de_flatten() {
+        #[derive(serde::Serialize, Deserialize, PartialEq, Debug)]
+        struct Inner {
+            b: u8,
+            d: String,
+        }
+
+        #[derive(serde::Serialize, Deserialize, PartialEq, Debug)]
+        struct Outer {
+            a: i32,
+            #[serde(flatten)]
+            inner: Inner,
+            c: bool,
+        }
+
+        let val = Outer {
+            a: -5,
+            inner: Inner {
+                b: 3,
+                d: "flat".into(),
+            },
+            c: true,
+        };
+        assert_eq!(roundtrip(&val, false).unwrap(), val);
+        // Keys come out as a, b, d, c, which can't be encoded in order without buffering
+        let err = roundtrip(&val, true).unwrap_err();
+        assert!(format!("{}", err).contains("flatten"));
+    }
+
+    #[test]
+    fn de_flatten_repeated_key() {
+        #[derive(serde::Serialize, Deserialize, PartialEq, Debug)]
+        struct A {
+            x: u8,
+        }
+
+        #[derive(serde::Serialize, Deserialize, PartialEq, Debug)]
+        struct B {
+            x: u8,
+        }
+
+        #[derive(serde::Serialize, Deserialize, PartialEq, Debug)]
+        struct Both {
+            #[serde(flatten)]
+            a: A,
+            #[serde(flatten)]
+            b: B,
+        }
+
+        let err = roundtrip(
+            &Both {
+                a: A { x: 1 },
+                b: B { x: 2 },
+            },
+            false,
+        )
+        .unwrap_err();
+        assert!(format!("{}", err).contains("\"x\""));
+    }
+
+    #[test]
+    fn de_untagged() {
+        #[derive(serde::Serialize, Deserialize, PartialEq, Debug)]
+        #[serde(untagged)]
+        enum Untagged {
+            Num(u32),
+            Text(String),
+            Pair { x: u8, y: u8 },
+            List(Vec<i8>),
+        }
+
+        let cases = vec![
+            Untagged::Num(70000),
+            Untagged::Text("hi".into()),
+            Untagged::Pair { x: 1, y: 2 },
+            Untagged::List(vec![-1, 0, 1]),
+        ];
+        for val in cases {
+            assert_eq!(roundtrip(&val, false).unwrap(), val);
+            assert_eq!(roundtrip(&val, true).unwrap(), val);
+        }
+    }
+
+    #[test]
+    fn de_internally_tagged() {
+        #[derive(serde::Serialize, Deserialize, PartialEq, Debug)]
+        #[serde(tag = "type")]
+        enum Shape {
+            Circle { radius: u32 },
+            Square { side: u32 },
+        }
+
+        let val = Shape::Circle { radius: 4 };
+        assert_eq!(roundtrip(&val, false).unwrap(), val);
+        let val = Shape::Square { side: 2 };
+        assert_eq!(roundtrip(&val, false).unwrap(), val);
+        // "type" comes first, but sorts after "side"
+        let err = roundtrip(&val, true).unwrap_err();
+        assert!(format!("{}", err).contains("internally tagged"));
+    }
+
+    #[test]
+    fn de_untagged_specialized_type() {
+        use crate::Hash;
+
+        #[derive(serde::Serialize, Deserialize, PartialEq, Debug)]
+        #[serde(untagged)]
+        enum WithHash {
+            Hash(Hash),
+            Num(u32),
+        }
+
+        assert_eq!(
+            roundtrip(&WithHash::Num(4), false).unwrap(),
+            WithHash::Num(4)
+        );
+        let val = WithHash::Hash(Hash::new("untagged"));
+        let err = roundtrip(&val, false).unwrap_err();
+        assert!(format!("{}", err).contains("specialized types"));
+    }
 }
@@ -7,13 +7,21 @@ use crate::{
 #[derive(Clone, Debug)]
 pub struct DepthTracker {
     tracking: Vec<u32>,
+    limit: usize,
 }
 
 impl DepthTracker {
     /// Create a new depth tracker
     pub fn new() -> Self {
+        Self::with_limit(MAX_DEPTH)
+    }
+
+    /// Create a new depth tracker with a lower nesting limit. The limit can't be raised past
+    /// [`MAX_DEPTH`].
+    pub fn with_limit(limit: usize) -> Self {
         Self {
             tracking: Vec::new(),
+            limit: limit.min(MAX_DEPTH),
         }
     }
 
@@ -33,7 +41,7 @@ impl DepthTracker {
         }
 
         // Check to see if we hit the nesting limit
-        if self.tracking.len() > MAX_DEPTH {
+        if self.tracking.len() > self.limit {
             return Err(Error::ParseLimit("Depth limit exceeded".to_string()));
         }
 
@@ -12,20 +12,36 @@
 //! or lower. This is useful for serializing large lists that don't fit in the Document maximum
 //! size limit of 1 MiB. [`AsyncVecDocumentBuilder`] does the same, but for asynchronous Streams.
 //!
+//! Encoded documents can also be inspected in place with a [`DocumentRef`], which reads the
+//! header, hash, signer, and data straight out of a borrowed byte slice.
+//!
 
-use crate::{compress::CompressType, de::FogDeserializer, ser::FogSerializer, MAX_DOC_SIZE};
+use crate::{
+    annotation::Annotations,
+    compress::{Compress, CompressType},
+    de::FogDeserializer,
+    ser::FogSerializer,
+    VersionPolicy, FORMAT_VERSION, MAX_DOC_SIZE,
+};
 use crate::{
     element::serialize_elem,
-    error::{Error, Result},
+    entry::Entry,
+    error::{Error, Result, ValidateError},
+    schema::Schema,
+    signer::{AsyncSigner, Signer},
+    types::{Path, Timestamp, Value, ValueRef},
+    validator::Normalize,
+    writer::ElementWriter,
 };
 use byteorder::{LittleEndian, ReadBytesExt};
 use fog_crypto::{
     hash::{Hash, HashState},
-    identity::{Identity, IdentityKey},
+    identity::{Identity, IdentityKey, Signature, UnverifiedSignature},
 };
 use futures_core::{ready, FusedStream, Stream};
 use pin_project_lite::pin_project;
 use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
 use std::convert::TryInto;
 use std::{
     convert::TryFrom,
@@ -34,9 +50,77 @@ use std::{
     task::{Context, Poll},
 };
 
+/// How to handle fields in a document or entry that the deserialization target doesn't use.
+///
+/// A policy can be given for a single call with [`Document::deserialize_with`], or set on a
+/// [`Schema`][crate::schema::Schema] with
+/// [`with_unknown_fields`][crate::schema::Schema::with_unknown_fields], in which case every
+/// document and entry the schema decodes or validates uses it for
+/// [`deserialize`][Document::deserialize].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum UnknownFields {
+    /// Silently skip over unknown fields.
+    #[default]
+    Ignore,
+    /// Fail deserialization if any unknown field is present.
+    Reject,
+    /// Deserialize any unknown fields into [`Value`]s and return them.
+    Capture,
+}
+
+/// Settings for encoding a new document or entry.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EncodeOptions {
+    ordered: bool,
+    raw_floats: bool,
+    normalize: Normalize,
+}
+
+impl EncodeOptions {
+    /// Create a new set of options, with every setting at its default.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Require that all keys are already ordered, instead of sorting them during encoding. See
+    /// [`NewDocument::new_ordered`] for what this requires of the data.
+    pub fn ordered(mut self, ordered: bool) -> Self {
+        self.ordered = ordered;
+        self
+    }
+
+    /// Keep the exact bits of every floating-point value. By default, all NaNs are encoded as the
+    /// canonical quiet NaN and negative zero is encoded as positive zero, so the same data always
+    /// hashes the same way. Raw floats will only pass validation with a schema that allows them;
+    /// see [`SchemaBuilder::raw_floats`][crate::schema::SchemaBuilder::raw_floats].
+    pub fn raw_floats(mut self, raw_floats: bool) -> Self {
+        self.raw_floats = raw_floats;
+        self
+    }
+
+    /// Put every string value into the given Unicode normalization form before encoding it. This
+    /// is useful for data that comes from user input and must pass a schema with
+    /// [`StrValidator`][crate::validator::StrValidator]s that use the same normalization form.
+    /// Map keys are not normalized.
+    pub fn normalize(mut self, normalize: Normalize) -> Self {
+        self.normalize = normalize;
+        self
+    }
+
+    pub(crate) fn serializer(&self, buf: Vec<u8>) -> FogSerializer {
+        FogSerializer::from_vec(buf, self.ordered)
+            .raw_floats(self.raw_floats)
+            .normalize(self.normalize.clone())
+    }
+}
+
 /// Attempt to get the schema for a raw document. Fails if the raw byte slice doesn't conform to
 /// the right format, or if the hash is invalid.
 pub fn get_doc_schema(doc: &[u8]) -> Result<Option<Hash>> {
+    let doc = match doc.first() {
+        Some(&b) if b & 0x3 == VERSION_MARKER => &doc[1..],
+        _ => doc,
+    };
     let hash_raw = SplitDoc::split(doc)?.hash_raw;
     if hash_raw.is_empty() {
         Ok(None)
@@ -46,15 +130,198 @@ pub fn get_doc_schema(doc: &[u8]) -> Result<Option<Hash>> {
 }
 
 // Header format:
+//  0. Optionally, a format version byte: the version in the upper 6 bits, and 0b11 in the lower 2.
+//      If absent, the format version is 0. This crate only writes version 0.
 //  1. Compression Type marker
 //  2. If schema is used: one byte indicating length of hash (must be 127 or
 //      lower), then the schema hash.
 //  3. 3-byte length of data
 //  4. The data
-//  5. The optional signature
+//  5. The optional signature, which may be prefixed by a signed timestamp
 //
 //  If compressed, only the data portion is compressed, and the 3-byte length is updated
 //  accordingly
+//
+//  A signed timestamp prefix is the marker byte 0xFF, one byte holding the length of the
+//  timestamp, then the timestamp itself. When present, the signature is of the hash of the
+//  document's hash followed by this prefix, instead of the document's hash alone. Encoded
+//  signatures never start with 0xFF, so the two can't be confused.
+//
+//  A document signed by several parties instead has a multi-signature section: the marker byte
+//  0xFE, one byte holding the number of signatures (at least 2), then each signature, prefixed by
+//  its length as a 2-byte little-endian integer. Every signature is of the document's hash, and
+//  each signer may appear only once.
+
+/// Low bits of a header's first byte that mark it as a format version byte. No compression type
+/// marker ends in these bits.
+pub(crate) const VERSION_MARKER: u8 = 0x3;
+
+/// Remove the format version byte from an encoded document or entry, if it has one, and check
+/// the version against a policy. Returns the version along with the version 0 encoding.
+pub(crate) fn strip_version(mut buf: Vec<u8>, policy: &VersionPolicy) -> Result<(u8, Vec<u8>)> {
+    let version = match buf.first() {
+        Some(&b) if b & 0x3 == VERSION_MARKER => {
+            buf.remove(0);
+            b >> 2
+        }
+        _ => 0,
+    };
+    policy.check(version)?;
+    Ok((version, buf))
+}
+
+/// How much to check when rebuilding a [`Document`] or [`Entry`][crate::entry::Entry] from its
+/// raw parts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Trust {
+    /// Check that the parts match the raw data and verify any signature, failing if either is
+    /// wrong. Use this for parts read back from storage that other processes can write to.
+    #[default]
+    Verify,
+    /// Take the parts as given, without hashing the data or verifying signatures; only the
+    /// framing of the raw data is checked. Only use this for parts that came from
+    /// `into_raw_parts` in a process that already verified them, and were stored somewhere
+    /// nothing else can change them.
+    Trusted,
+}
+
+/// A parsed signature, along with the hash it still needs to be verified against.
+pub(crate) type PendingSignature = (Hash, UnverifiedSignature);
+
+const SIGNATURE_TIME_MARKER: u8 = 0xFF;
+const MULTI_SIGNATURE_MARKER: u8 = 0xFE;
+
+/// Encode a multi-signature section holding all of the given signatures.
+fn multi_signature_section(signatures: &[Signature]) -> Vec<u8> {
+    let mut section = vec![MULTI_SIGNATURE_MARKER, signatures.len() as u8];
+    for signature in signatures {
+        section.extend_from_slice(&(signature.size() as u16).to_le_bytes());
+        signature.encode_vec(&mut section);
+    }
+    section
+}
+
+/// Split a multi-signature section into each of its encoded signatures.
+fn split_multi_signature(mut raw: &[u8]) -> Result<Vec<&[u8]>> {
+    if raw.len() < 2 {
+        return Err(Error::LengthTooShort {
+            step: "get signature count",
+            actual: raw.len(),
+            expected: 2,
+        });
+    }
+    let count = raw[1] as usize;
+    if count < 2 {
+        return Err(Error::BadHeader(format!(
+            "Multi-signature must hold at least 2 signatures, marked as {}",
+            count
+        )));
+    }
+    raw = &raw[2..];
+    let mut signatures = Vec::with_capacity(count);
+    for _ in 0..count {
+        let len = raw
+            .read_u16::<LittleEndian>()
+            .map_err(|_| Error::LengthTooShort {
+                step: "get signature length",
+                actual: raw.len(),
+                expected: 2,
+            })? as usize;
+        if raw.len() < len {
+            return Err(Error::LengthTooShort {
+                step: "get signature",
+                actual: raw.len(),
+                expected: len,
+            });
+        }
+        let (signature, rem) = raw.split_at(len);
+        signatures.push(signature);
+        raw = rem;
+    }
+    if !raw.is_empty() {
+        return Err(Error::BadHeader(
+            "Multi-signature has trailing bytes".into(),
+        ));
+    }
+    Ok(signatures)
+}
+
+/// Parse a document's signature section, returning the claimed signers, the signed timestamp,
+/// and each signature along with the hash it must be verified against.
+fn parse_signatures(
+    doc_hash: &Hash,
+    raw: &[u8],
+) -> Result<(Vec<Identity>, Option<Timestamp>, Vec<PendingSignature>)> {
+    if raw.is_empty() {
+        return Ok((Vec::new(), None, Vec::new()));
+    }
+    if raw[0] == MULTI_SIGNATURE_MARKER {
+        let mut signers = Vec::new();
+        let mut pending = Vec::new();
+        for signature_raw in split_multi_signature(raw)? {
+            let unverified = UnverifiedSignature::try_from(signature_raw)?;
+            if signers.contains(unverified.signer()) {
+                return Err(Error::BadHeader(
+                    "Multi-signature has the same signer more than once".into(),
+                ));
+            }
+            signers.push(unverified.signer().clone());
+            pending.push((doc_hash.clone(), unverified));
+        }
+        return Ok((signers, None, pending));
+    }
+    let (prefix, time, signature_raw) = split_signature(raw)?;
+    let unverified = UnverifiedSignature::try_from(signature_raw)?;
+    let signer = unverified.signer().clone();
+    let signed_hash = signing_hash(doc_hash, prefix);
+    Ok((vec![signer], time, vec![(signed_hash, unverified)]))
+}
+
+/// Encode the signed timestamp prefix for a signature. Empty if there is no timestamp.
+fn signature_prefix(time: Option<Timestamp>) -> Vec<u8> {
+    match time {
+        None => Vec::new(),
+        Some(time) => {
+            let mut prefix = vec![SIGNATURE_TIME_MARKER, time.size() as u8];
+            time.encode_vec(&mut prefix);
+            prefix
+        }
+    }
+}
+
+/// Split a signature section into its signed timestamp prefix, the timestamp, and the signature.
+fn split_signature(raw: &[u8]) -> Result<(&[u8], Option<Timestamp>, &[u8])> {
+    if raw.first() != Some(&SIGNATURE_TIME_MARKER) {
+        return Ok((&[], None, raw));
+    }
+    let time_len = *raw.get(1).ok_or(Error::LengthTooShort {
+        step: "get signature timestamp length",
+        actual: raw.len(),
+        expected: 2,
+    })? as usize;
+    if raw.len() < time_len + 2 {
+        return Err(Error::LengthTooShort {
+            step: "get signature timestamp",
+            actual: raw.len(),
+            expected: time_len + 2,
+        });
+    }
+    let (prefix, signature) = raw.split_at(time_len + 2);
+    let time = Timestamp::try_from(&prefix[2..])
+        .map_err(|e| Error::BadHeader(format!("Invalid signature timestamp: {}", e)))?;
+    Ok((prefix, Some(time), signature))
+}
+
+/// Get the hash that a signature with the given prefix is made over.
+fn signing_hash(doc_hash: &Hash, prefix: &[u8]) -> Hash {
+    if prefix.is_empty() {
+        return doc_hash.clone();
+    }
+    let mut state = HashState::new();
+    state.update(doc_hash.as_ref());
+    state.update(prefix);
+    state.hash()
+}
 
 pub(crate) struct SplitDoc<'a> {
     pub compress_raw: u8,
@@ -111,17 +378,30 @@ impl<'a> SplitDoc<'a> {
 #[derive(Clone, Debug)]
 struct DocumentInner {
     buf: Vec<u8>,
-    hash_state: HashState,
+    /// Working memory for hash calculations, holding everything hashed so far. `None` if the
+    /// document was rebuilt from trusted raw parts, in which case it's set up again when signing.
+    hash_state: Option<HashState>,
     schema_hash: Option<Hash>,
-    doc_hash: Hash,
+    /// The hash of the document without its signatures. `None` if the document was rebuilt from
+    /// trusted raw parts and hasn't needed it yet.
+    doc_hash: Option<Hash>,
     this_hash: Hash,
-    signer: Option<Identity>,
+    signers: Vec<Identity>,
+    signature_time: Option<Timestamp>,
+    /// False only if the document has a signature that hasn't been checked yet
+    verified: bool,
     set_compress: Option<Option<u8>>,
+    format_version: u8,
+    annotations: Annotations,
+    unknown_fields: UnknownFields,
+    /// True only if the data is known to have passed validation, which lets deserialization skip
+    /// some checks
+    validated: bool,
 }
 
 impl DocumentInner {
     fn signer(&self) -> Option<&Identity> {
-        self.signer.as_ref()
+        self.signers.first()
     }
 
     /// Get the hash of the schema this document adheres to.
@@ -129,6 +409,27 @@ impl DocumentInner {
         self.schema_hash.as_ref()
     }
 
+    /// Set up a hash state holding the schema hash and data, but none of the signatures.
+    fn data_hash_state(&self) -> HashState {
+        let mut hash_state = HashState::new();
+        match self.schema_hash {
+            None => hash_state.update([0u8]),
+            Some(ref hash) => hash_state.update(hash),
+        }
+        hash_state.update(self.split().data);
+        hash_state
+    }
+
+    /// Get the hash of the document without its signatures, computing it if it isn't known yet.
+    fn doc_hash(&mut self) -> Hash {
+        if let Some(hash) = &self.doc_hash {
+            return hash.clone();
+        }
+        let hash = self.data_hash_state().hash();
+        self.doc_hash = Some(hash.clone());
+        hash
+    }
+
     /// Override the default compression settings. `None` will disable compression. `Some(level)`
     /// will compress with the provided level as the setting for the algorithm.
     fn compression(&mut self, setting: Option<u8>) -> &mut Self {
@@ -136,16 +437,60 @@ impl DocumentInner {
         self
     }
 
-    /// Sign the document, or or replace the existing signature if one exists already. Fails if the
-    /// signature would grow the document size beyond the maximum allowed.
-    fn sign(mut self, key: &IdentityKey) -> Result<Self> {
-        // Sign and check for size violation
-        let signature = key.sign(&self.doc_hash);
-        let new_len = if self.signer.is_some() {
-            self.buf.len() - self.split().signature_raw.len() + signature.size()
-        } else {
-            self.buf.len() + signature.size()
-        };
+    /// Sign the document, or or replace the existing signature if one exists already. The
+    /// optional timestamp is signed along with the document. Fails if the signature would grow the
+    /// document size beyond the maximum allowed.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(hash = %self.this_hash))
+    )]
+    fn sign<S: Signer + ?Sized>(mut self, key: &S, time: Option<Timestamp>) -> Result<Self> {
+        let prefix = signature_prefix(time);
+        let signature = Signer::sign(key, &signing_hash(&self.doc_hash(), &prefix))?;
+        self.finish_sign(signature, prefix, time)
+    }
+
+    /// Sign the document asynchronously, like [`sign`][Self::sign].
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(hash = %self.this_hash))
+    )]
+    async fn sign_async<S: AsyncSigner + ?Sized>(
+        mut self,
+        key: &S,
+        time: Option<Timestamp>,
+    ) -> Result<Self> {
+        let prefix = signature_prefix(time);
+        let signature = AsyncSigner::sign(key, &signing_hash(&self.doc_hash(), &prefix)).await?;
+        self.finish_sign(signature, prefix, time)
+    }
+
+    /// Attach a signature, along with its signed timestamp prefix, replacing any existing one.
+    /// Fails if the signature isn't actually of this document and prefix, as signers can be
+    /// arbitrary outside code.
+    fn finish_sign(
+        mut self,
+        signature: Signature,
+        mut prefix: Vec<u8>,
+        time: Option<Timestamp>,
+    ) -> Result<Self> {
+        let hash = signing_hash(&self.doc_hash(), &prefix);
+        let start = prefix.len();
+        signature.encode_vec(&mut prefix);
+        let signature = UnverifiedSignature::try_from(&prefix[start..])?.verify(&hash)?;
+        let signers = vec![signature.signer().clone()];
+        self.set_signatures(prefix, signers, time)
+    }
+
+    /// Replace the document's signature section with an already verified one.
+    fn set_signatures(
+        mut self,
+        section: Vec<u8>,
+        signers: Vec<Identity>,
+        time: Option<Timestamp>,
+    ) -> Result<Self> {
+        // Check for size violation
+        let new_len = self.buf.len() - self.split().signature_raw.len() + section.len();
         if new_len > MAX_DOC_SIZE {
             return Err(Error::LengthTooLong {
                 max: MAX_DOC_SIZE,
@@ -154,25 +499,21 @@ impl DocumentInner {
         }
 
         // Erase previous signature & recalculate hash, if needed
-        if self.signer.is_some() {
-            let split = SplitDoc::split(&self.buf).unwrap();
+        if !self.signers.is_empty() || self.hash_state.is_none() {
+            let split = self.split();
             let new_len = split.hash_raw.len() + split.data.len() + 5;
-            let mut hash_state = HashState::new();
-            match self.schema_hash {
-                None => hash_state.update([0u8]),
-                Some(ref hash) => hash_state.update(hash),
-            }
-            hash_state.update(split.data);
+            self.hash_state = Some(self.data_hash_state());
             self.buf.resize(new_len, 0);
-            self.hash_state = hash_state;
         }
 
         // Append the signature and update the hasher
-        let pre_len = self.buf.len();
-        signature.encode_vec(&mut self.buf);
-        self.hash_state.update(&self.buf[pre_len..]);
-        self.signer = Some(key.id().clone());
-        self.this_hash = self.hash_state.hash();
+        let hash_state = self.hash_state.as_mut().unwrap();
+        self.buf.extend_from_slice(&section);
+        hash_state.update(&section);
+        self.this_hash = hash_state.hash();
+        self.signers = signers;
+        self.signature_time = time;
+        self.verified = true;
         Ok(self)
     }
 
@@ -545,7 +886,7 @@ where
 pub struct NewDocument(DocumentInner);
 
 impl NewDocument {
-    fn new_from<F>(schema: Option<&Hash>, encoder: F) -> Result<Self>
+    pub(crate) fn new_from<F>(schema: Option<&Hash>, encoder: F) -> Result<Self>
     where
         F: FnOnce(Vec<u8>) -> Result<Vec<u8>>,
     {
@@ -589,12 +930,18 @@ impl NewDocument {
 
         Ok(NewDocument(DocumentInner {
             buf,
-            hash_state,
+            hash_state: Some(hash_state),
             this_hash,
             schema_hash: schema.cloned(),
-            doc_hash,
+            doc_hash: Some(doc_hash),
             set_compress: None,
-            signer: None,
+            signers: Vec::new(),
+            signature_time: None,
+            verified: true,
+            format_version: FORMAT_VERSION,
+            annotations: Annotations::default(),
+            unknown_fields: UnknownFields::Ignore,
+            validated: false,
         }))
     }
 
@@ -608,6 +955,42 @@ impl NewDocument {
         })
     }
 
+    /// Create a new Document from any serializable data, optionally adhering to a schema, using
+    /// the provided encoding options.
+    pub fn new_with_options<S: Serialize>(
+        schema: Option<&Hash>,
+        data: S,
+        options: &EncodeOptions,
+    ) -> Result<Self> {
+        Self::new_from(schema, |buf| {
+            // Encode the data
+            let mut ser = options.serializer(buf);
+            data.serialize(&mut ser)?;
+            Ok(ser.finish())
+        })
+    }
+
+    /// Create a new Document from data written with an [`ElementWriter`], optionally adhering to
+    /// a schema. Fails if the writer doesn't hold a complete value.
+    pub fn from_writer(schema: Option<&Hash>, writer: ElementWriter) -> Result<Self> {
+        let data = writer.finish()?;
+        Self::new_from(schema, |mut buf| {
+            buf.extend_from_slice(&data);
+            Ok(buf)
+        })
+    }
+
+    /// Create a new Document holding the same data as an entry, optionally adhering to a schema.
+    /// The data is copied over as-is, without being decoded and re-encoded. The entry's signature
+    /// isn't carried over, and the document's hash has nothing to do with the entry's, so keep
+    /// the entry's [reference][Entry::reference] if the link between them matters.
+    pub fn from_entry(schema: Option<&Hash>, entry: &Entry) -> Result<Self> {
+        Self::new_from(schema, |mut buf| {
+            buf.extend_from_slice(entry.data());
+            Ok(buf)
+        })
+    }
+
     /// Create a new Document from any serializable data whose keys are all ordered. For structs,
     /// this means all fields are declared in lexicographic order. For maps, this means a
     /// `BTreeMap` type must be used, whose keys are ordered such that they serialize to
@@ -634,10 +1017,32 @@ impl NewDocument {
     }
 
     /// Sign the document, or or replace the existing signature if one exists already. Fails if the
-    /// signature would grow the document size beyond the maximum allowed. In the event of a
-    /// failure, the document is dropped.
-    pub fn sign(self, key: &IdentityKey) -> Result<Self> {
-        Ok(Self(self.0.sign(key)?))
+    /// signature would grow the document size beyond the maximum allowed, or if the signer fails.
+    /// In the event of a failure, the document is dropped.
+    pub fn sign<S: Signer + ?Sized>(self, key: &S) -> Result<Self> {
+        Ok(Self(self.0.sign(key, None)?))
+    }
+
+    /// Sign the document along with a timestamp, which is covered by the signature and can be
+    /// retrieved with `signature_time`. Otherwise identical to [`sign`][Self::sign].
+    pub fn sign_with_time<S: Signer + ?Sized>(self, key: &S, time: Timestamp) -> Result<Self> {
+        Ok(Self(self.0.sign(key, Some(time))?))
+    }
+
+    /// Sign the document with an asynchronous signer, like a remote signing service. Otherwise
+    /// identical to [`sign`][Self::sign].
+    pub async fn sign_async<S: AsyncSigner + ?Sized>(self, key: &S) -> Result<Self> {
+        Ok(Self(self.0.sign_async(key, None).await?))
+    }
+
+    /// Sign the document along with a timestamp, using an asynchronous signer. Otherwise
+    /// identical to [`sign_with_time`][Self::sign_with_time].
+    pub async fn sign_with_time_async<S: AsyncSigner + ?Sized>(
+        self,
+        key: &S,
+        time: Timestamp,
+    ) -> Result<Self> {
+        Ok(Self(self.0.sign_async(key, Some(time)).await?))
     }
 
     /// Get what the document's hash will be, given its current state
@@ -648,6 +1053,14 @@ impl NewDocument {
     pub(crate) fn data(&self) -> &[u8] {
         self.0.data()
     }
+
+    pub(crate) fn size(&self) -> usize {
+        self.0.buf.len()
+    }
+
+    pub(crate) fn signers(&self) -> &[Identity] {
+        &self.0.signers
+    }
 }
 
 /// Holds serialized data optionally adhering to a schema.
@@ -666,6 +1079,17 @@ impl Document {
     /// Create the document from a raw byte vec without fully verifying it.
     /// After creation, if the data is untrusted, you must still run it through a validator
     pub(crate) fn new(buf: Vec<u8>) -> Result<Self> {
+        let (doc, pending) = Self::new_unverified(buf)?;
+        for (hash, unverified) in pending {
+            unverified.verify(&hash)?;
+        }
+        Ok(doc)
+    }
+
+    /// Create the document from a raw byte vec, without verifying it or its signatures. Each
+    /// signature is returned along with the hash it must be verified against, and the caller is
+    /// responsible for checking them.
+    pub(crate) fn new_unverified(buf: Vec<u8>) -> Result<(Self, Vec<PendingSignature>)> {
         if buf.len() > MAX_DOC_SIZE {
             return Err(Error::LengthTooLong {
                 max: MAX_DOC_SIZE,
@@ -690,45 +1114,231 @@ impl Document {
         hash_state.update(split.signature_raw);
         let this_hash = hash_state.hash();
 
-        let signer = if !split.signature_raw.is_empty() {
-            let unverified =
-                fog_crypto::identity::UnverifiedSignature::try_from(split.signature_raw)?;
-            let verified = unverified.verify(&doc_hash)?;
-            Some(verified.signer().clone())
-        } else {
-            None
-        };
+        let (signers, signature_time, pending) = parse_signatures(&doc_hash, split.signature_raw)?;
 
-        Ok(Self(DocumentInner {
+        let doc = Self(DocumentInner {
             buf,
             schema_hash,
-            hash_state,
+            hash_state: Some(hash_state),
             this_hash,
-            doc_hash,
-            signer,
+            doc_hash: Some(doc_hash),
+            signers,
+            signature_time,
+            verified: true,
             set_compress: None,
-        }))
+            format_version: FORMAT_VERSION,
+            annotations: Annotations::default(),
+            unknown_fields: UnknownFields::Ignore,
+            validated: false,
+        });
+        Ok((doc, pending))
+    }
+
+    /// Create a document from its encoded form, computing its hashes but deferring verification of
+    /// its signature until [`verify`][Self::verify] is called. This lets a pipeline check the
+    /// document's hash against ones it already has and discard duplicates without paying for
+    /// signature verification.
+    ///
+    /// Like [`Schema::trusted_decode_doc`][crate::schema::Schema::trusted_decode_doc], this
+    /// doesn't validate the data against a schema, so documents from untrusted sources should
+    /// still be passed through a schema's `decode_doc` once they're known to be new. Documents
+    /// compressed with a schema's dictionary can't be decoded this way, and will fail.
+    pub fn new_deferred(buf: Vec<u8>) -> Result<Self> {
+        let (version, buf) = strip_version(buf, &VersionPolicy::Supported)?;
+        let buf = crate::schema::decompress_doc(buf, &Compress::None, None, MAX_DOC_SIZE)?;
+        let (mut doc, pending) = Self::new_unverified(buf)?;
+        doc.0.verified = pending.is_empty();
+        doc.0.format_version = version;
+        Ok(doc)
+    }
+
+    /// Break the document down into its hash, the hash of its schema, its raw data, and its
+    /// signer. The raw data is the document's uncompressed internal form, not its encoding. Use
+    /// [`from_raw_parts`][Self::from_raw_parts] to rebuild the document.
+    pub fn into_raw_parts(self) -> (Hash, Option<Hash>, Vec<u8>, Option<Identity>) {
+        let signer = self.0.signer().cloned();
+        (self.0.this_hash, self.0.schema_hash, self.0.buf, signer)
+    }
+
+    /// Rebuild a document from the parts returned by [`into_raw_parts`][Self::into_raw_parts],
+    /// without going through a schema. Fails if the raw data is malformed or doesn't match the
+    /// other parts. With [`Trust::Verify`], the document's hash is recomputed and checked and its
+    /// signatures are verified. With [`Trust::Trusted`], the hash is taken as given and no hashing
+    /// is done; a signed document is left [unverified][Self::is_verified] until
+    /// [`verify`][Self::verify] is called.
+    pub fn from_raw_parts(
+        hash: Hash,
+        schema_hash: Option<Hash>,
+        raw: Vec<u8>,
+        signer: Option<Identity>,
+        trust: Trust,
+    ) -> Result<Self> {
+        let doc = match trust {
+            Trust::Verify => {
+                let (doc, pending) = Self::new_unverified(raw)?;
+                if doc.hash() != &hash || doc.schema_hash() != schema_hash.as_ref() {
+                    return Err(Error::FailValidate(ValidateError::Other(
+                        format!("raw document data doesn't match the hash {}", hash).into(),
+                    )));
+                }
+                for (hash, unverified) in pending {
+                    unverified.verify(&hash)?;
+                }
+                doc
+            }
+            Trust::Trusted => {
+                if raw.len() > MAX_DOC_SIZE {
+                    return Err(Error::LengthTooLong {
+                        max: MAX_DOC_SIZE,
+                        actual: raw.len(),
+                    });
+                }
+                let split = SplitDoc::split(&raw)?;
+                let schema_raw = schema_hash.as_ref().map_or(&[][..], |h| h.as_ref());
+                if split.hash_raw != schema_raw {
+                    return Err(Error::FailValidate(ValidateError::Other(
+                        "raw document data doesn't match the schema hash".into(),
+                    )));
+                }
+                // Only the claimed signers are needed, so the pending signatures are dropped
+                // along with the hash they would have been checked against
+                let (signers, signature_time, _) = parse_signatures(&hash, split.signature_raw)?;
+                Self(DocumentInner {
+                    buf: raw,
+                    hash_state: None,
+                    schema_hash,
+                    doc_hash: signers.is_empty().then(|| hash.clone()),
+                    this_hash: hash,
+                    verified: signers.is_empty(),
+                    signers,
+                    signature_time,
+                    set_compress: None,
+                    format_version: FORMAT_VERSION,
+                    annotations: Annotations::default(),
+                    unknown_fields: UnknownFields::Ignore,
+                    validated: false,
+                })
+            }
+        };
+        if doc.signer() != signer.as_ref() {
+            return Err(Error::FailValidate(ValidateError::Other(
+                "raw document data doesn't match the signer".into(),
+            )));
+        }
+        Ok(doc)
+    }
+
+    /// Check if the document's signature has been verified. This is only false for signed
+    /// documents created with [`new_deferred`][Self::new_deferred], rebuilt with
+    /// [`from_raw_parts`][Self::from_raw_parts] using [`Trust::Trusted`], or decoded through a
+    /// [`TrustedSchema`][crate::schema::TrustedSchema], that haven't had
+    /// [`verify`][Self::verify] called on them yet.
+    pub fn is_verified(&self) -> bool {
+        self.0.verified
+    }
+
+    /// Verify the document's signature, if it hasn't been already. Fails if the signature is
+    /// invalid, in which case the document is dropped. Documents are always verified before being
+    /// encoded by a schema.
+    pub fn verify(mut self) -> Result<Self> {
+        if !self.0.verified {
+            let doc_hash = self.0.doc_hash();
+            let (_, _, pending) = parse_signatures(&doc_hash, self.0.split().signature_raw)?;
+            for (hash, unverified) in pending {
+                unverified.verify(&hash)?;
+            }
+            self.0.verified = true;
+        }
+        Ok(self)
+    }
+
+    pub(crate) fn set_verified(&mut self, verified: bool) {
+        self.0.verified = verified;
     }
 
     pub(crate) fn data(&self) -> &[u8] {
         self.0.data()
     }
 
+    pub(crate) fn size(&self) -> usize {
+        self.0.buf.len()
+    }
+
+    pub(crate) fn raw(&self) -> &[u8] {
+        &self.0.buf
+    }
+
     /// Find all hashes in this document and return them.
     pub fn find_hashes(&self) -> Vec<Hash> {
         crate::find_hashes(self.data())
     }
 
+    /// Get the wire format version the document was decoded from. Documents that weren't decoded
+    /// are always the current [`FORMAT_VERSION`][crate::FORMAT_VERSION].
+    pub fn format_version(&self) -> u8 {
+        self.0.format_version
+    }
+
+    pub(crate) fn set_format_version(&mut self, version: u8) {
+        self.0.format_version = version;
+    }
+
+    /// Get how [`deserialize`][Self::deserialize] handles fields the target type doesn't use. This
+    /// is [`UnknownFields::Ignore`] unless the document came from a schema set up with
+    /// [`Schema::with_unknown_fields`][crate::schema::Schema::with_unknown_fields], or from
+    /// [`NoSchema::decode_doc_with_unknown_fields`][crate::schema::NoSchema::decode_doc_with_unknown_fields].
+    pub fn unknown_fields(&self) -> UnknownFields {
+        self.0.unknown_fields
+    }
+
+    pub(crate) fn set_unknown_fields(&mut self, unknown: UnknownFields) {
+        self.0.unknown_fields = unknown;
+    }
+
+    /// Mark the document's data as having passed schema or [`NoSchema`][crate::schema::NoSchema]
+    /// validation.
+    pub(crate) fn set_validated(&mut self) {
+        self.0.validated = true;
+    }
+
+    /// Get the local [annotations][crate::annotation] on this copy of the document.
+    pub fn annotations(&self) -> &Annotations {
+        &self.0.annotations
+    }
+
+    /// Get the local [annotations][crate::annotation] on this copy of the document, for changing
+    /// them. Annotations are never part of the document's data or hash.
+    pub fn annotations_mut(&mut self) -> &mut Annotations {
+        &mut self.0.annotations
+    }
+
     /// Get the hash of the schema this document adheres to.
     pub fn schema_hash(&self) -> Option<&Hash> {
         self.0.schema_hash()
     }
 
-    /// Get the Identity of the signer of this document, if the document is signed.
+    /// Get the Identity of the signer of this document, if the document is signed. If the
+    /// signature hasn't been [verified][Self::verify] yet, this is only the claimed signer.
+    ///
+    /// For documents signed by multiple parties, this is only the first signer, and says nothing
+    /// about the others. Anything checking who signed a document, like a permission check, should
+    /// use [`signers`][Self::signers] instead.
     pub fn signer(&self) -> Option<&Identity> {
         self.0.signer()
     }
 
+    /// Get the Identities of every signer of this document. This only has more than one entry for
+    /// documents completed from a [`PartialSignedDocument`].
+    pub fn signers(&self) -> &[Identity] {
+        &self.0.signers
+    }
+
+    /// Get the timestamp signed along with the document's signature, if there is one. Like the
+    /// signer, this is only a claim until the signature has been [verified][Self::verify].
+    pub fn signature_time(&self) -> Option<Timestamp> {
+        self.0.signature_time
+    }
+
     /// Get the hash of the complete document. This can change if the document is signed again with
     /// the [`sign`][Self::sign] function.
     pub fn hash(&self) -> &Hash {
@@ -736,12 +1346,45 @@ impl Document {
     }
 
     /// Attempt to deserialize the data into anything implementing `Deserialize`.
+    ///
+    /// Strings and byte sequences are always handed to the deserializer as borrows of the
+    /// document's internal buffer, so types holding `&'de str`, `&'de [u8]`, or
+    /// `Cow<'de, str>`/`Cow<'de, [u8]>` (marked with `#[serde(borrow)]`) won't copy any data. This
+    /// holds for documents produced by a schema's decode functions as well, as any decompression
+    /// is done up front.
+    ///
+    /// Fields the target type doesn't use are handled as set by [`unknown_fields`][Self::unknown_fields].
+    /// Captured fields are dropped; use [`deserialize_with`][Self::deserialize_with] to get them.
     pub fn deserialize<'de, D: Deserialize<'de>>(&'de self) -> Result<D> {
-        let buf = self.0.data();
-        let mut de = FogDeserializer::new(buf);
+        let mut de = FogDeserializer::with_unknown_fields(self.0.data(), self.0.unknown_fields)
+            .validated(self.0.validated);
         D::deserialize(&mut de)
     }
 
+    /// Deserialize the data, choosing how to handle any fields that the target type doesn't use.
+    /// This makes it possible to read documents written with a newer version of a type, while
+    /// still knowing what was skipped. Any captured values are returned alongside the target type,
+    /// each with the path to where it was found.
+    ///
+    /// Fields are only detected as unknown when the target type's `Deserialize` implementation
+    /// explicitly ignores them, which is what `#[derive(Deserialize)]` does for structs without
+    /// `#[serde(deny_unknown_fields)]`.
+    pub fn deserialize_with<'de, D: Deserialize<'de>>(
+        &'de self,
+        unknown: UnknownFields,
+    ) -> Result<(D, Vec<(Path, Value)>)> {
+        let mut de = FogDeserializer::with_unknown_fields(self.0.data(), unknown)
+            .validated(self.0.validated);
+        let val = D::deserialize(&mut de)?;
+        Ok((val, de.take_captured()))
+    }
+
+    /// Look up a single nested value inside the document, without deserializing the rest of it.
+    /// Returns `Ok(None)` if the path doesn't point to a value.
+    pub fn get_path(&self, path: &Path) -> Result<Option<ValueRef>> {
+        path.find_raw(self.0.data())
+    }
+
     /// Override the default compression settings. `None` will disable compression. `Some(level)`
     /// will compress with the provided level as the setting for the algorithm. This only has
     /// meaning when the document is re-encoded.
@@ -751,9 +1394,31 @@ impl Document {
     }
 
     /// Sign the document, or or replace the existing signature if one exists already. Fails if the
-    /// signature would grow the document size beyond the maximum allowed.
-    pub fn sign(self, key: &IdentityKey) -> Result<Self> {
-        Ok(Self(self.0.sign(key)?))
+    /// signature would grow the document size beyond the maximum allowed, or if the signer fails.
+    pub fn sign<S: Signer + ?Sized>(self, key: &S) -> Result<Self> {
+        Ok(Self(self.0.sign(key, None)?))
+    }
+
+    /// Sign the document along with a timestamp, which is covered by the signature and can be
+    /// retrieved with `signature_time`. Otherwise identical to [`sign`][Self::sign].
+    pub fn sign_with_time<S: Signer + ?Sized>(self, key: &S, time: Timestamp) -> Result<Self> {
+        Ok(Self(self.0.sign(key, Some(time))?))
+    }
+
+    /// Sign the document with an asynchronous signer, like a remote signing service. Otherwise
+    /// identical to [`sign`][Self::sign].
+    pub async fn sign_async<S: AsyncSigner + ?Sized>(self, key: &S) -> Result<Self> {
+        Ok(Self(self.0.sign_async(key, None).await?))
+    }
+
+    /// Sign the document along with a timestamp, using an asynchronous signer. Otherwise
+    /// identical to [`sign_with_time`][Self::sign_with_time].
+    pub async fn sign_with_time_async<S: AsyncSigner + ?Sized>(
+        self,
+        key: &S,
+        time: Timestamp,
+    ) -> Result<Self> {
+        Ok(Self(self.0.sign_async(key, Some(time)).await?))
     }
 
     pub(crate) fn complete(self) -> (Hash, Vec<u8>, Option<Option<u8>>) {
@@ -761,6 +1426,351 @@ impl Document {
     }
 }
 
+/// A read-only view of an encoded document, borrowing its bytes instead of copying them.
+///
+/// This reads the header of an encoded document, as produced by a schema's `encode_doc`, without
+/// allocating a new buffer, decompressing the data, or verifying the signature. It's meant for
+/// servers that mostly hand out raw documents and only sometimes need their metadata. Nothing is
+/// checked against a schema, so the data should still be passed through one before being
+/// trusted.
+///
+/// The schema hash, signer, and signature timestamp can always be read. The document's hash, its
+/// data, and signature verification all need the uncompressed data, and fail for compressed
+/// documents; those need to be decoded into a [`Document`] instead.
+///
+/// ```
+/// # use fog_pack::{document::*, schema::*, types::*};
+/// # fn main() -> fog_pack::error::Result<()> {
+/// let key = IdentityKey::new();
+/// let doc = NewDocument::new(None, "hello")?.compression(None).sign(&key)?;
+/// let doc = NoSchema::validate_new_doc(doc)?;
+/// let (hash, encoded) = NoSchema::encode_doc(doc)?;
+///
+/// let view = DocumentRef::new(&encoded)?;
+/// assert_eq!(view.hash()?, hash);
+/// assert_eq!(view.signer()?.as_ref(), Some(key.id()));
+/// assert_eq!(view.value()?, ValueRef::from("hello"));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct DocumentRef<'a> {
+    raw: &'a [u8],
+    format_version: u8,
+    compress_raw: u8,
+    hash_raw: &'a [u8],
+    data: &'a [u8],
+    signature_raw: &'a [u8],
+}
+
+impl<'a> DocumentRef<'a> {
+    /// Read the header of an encoded document. Fails if the header is malformed, the document is
+    /// too large, or the format version isn't one this crate can read.
+    pub fn new(raw: &'a [u8]) -> Result<Self> {
+        let (format_version, buf) = match raw.first() {
+            Some(&b) if b & 0x3 == VERSION_MARKER => (b >> 2, &raw[1..]),
+            _ => (0, raw),
+        };
+        VersionPolicy::Supported.check(format_version)?;
+        if buf.len() > MAX_DOC_SIZE {
+            return Err(Error::LengthTooLong {
+                max: MAX_DOC_SIZE,
+                actual: buf.len(),
+            });
+        }
+        let split = SplitDoc::split(buf)?;
+        CompressType::try_from(split.compress_raw)
+            .map_err(|m| Error::BadHeader(format!("unrecognized compression marker 0x{:x}", m)))?;
+        if !split.hash_raw.is_empty() {
+            Hash::try_from(split.hash_raw)?;
+        }
+        Ok(Self {
+            raw,
+            format_version,
+            compress_raw: split.compress_raw,
+            hash_raw: split.hash_raw,
+            data: split.data,
+            signature_raw: split.signature_raw,
+        })
+    }
+
+    /// Get the encoded document this is a view of.
+    pub fn raw(&self) -> &'a [u8] {
+        self.raw
+    }
+
+    /// Get the wire format version of the document.
+    pub fn format_version(&self) -> u8 {
+        self.format_version
+    }
+
+    /// Check if the document's data is compressed.
+    pub fn is_compressed(&self) -> bool {
+        self.compress_raw != u8::from(CompressType::None)
+    }
+
+    /// Get the hash of the schema this document adheres to.
+    pub fn schema_hash(&self) -> Option<Hash> {
+        // Checked when the view was made
+        (!self.hash_raw.is_empty()).then(|| Hash::try_from(self.hash_raw).unwrap())
+    }
+
+    /// Get the Identity of the signer of this document, if the document is signed. This is only
+    /// the claimed signer until [`verify`][Self::verify] is called. For documents signed by
+    /// multiple parties, this is only the first signer; use [`signers`][Self::signers] to get all
+    /// of them.
+    pub fn signer(&self) -> Result<Option<Identity>> {
+        if self.signature_raw.is_empty() {
+            return Ok(None);
+        }
+        let signature_raw = if self.signature_raw[0] == MULTI_SIGNATURE_MARKER {
+            split_multi_signature(self.signature_raw)?[0]
+        } else {
+            split_signature(self.signature_raw)?.2
+        };
+        let unverified = UnverifiedSignature::try_from(signature_raw)?;
+        Ok(Some(unverified.signer().clone()))
+    }
+
+    /// Get the Identities of every signer of this document, in the order they're stored. Like
+    /// [`signer`][Self::signer], these are only claims until [`verify`][Self::verify] is called.
+    pub fn signers(&self) -> Result<Vec<Identity>> {
+        let raw = if self.signature_raw.is_empty() {
+            Vec::new()
+        } else if self.signature_raw[0] == MULTI_SIGNATURE_MARKER {
+            split_multi_signature(self.signature_raw)?
+        } else {
+            vec![split_signature(self.signature_raw)?.2]
+        };
+        raw.into_iter()
+            .map(|raw| Ok(UnverifiedSignature::try_from(raw)?.signer().clone()))
+            .collect()
+    }
+
+    /// Get the timestamp signed along with the document's signature, if there is one. Like the
+    /// signer, this is only a claim until the signature has been [verified][Self::verify].
+    pub fn signature_time(&self) -> Result<Option<Timestamp>> {
+        if self.signature_raw.first() == Some(&MULTI_SIGNATURE_MARKER) {
+            return Ok(None);
+        }
+        Ok(split_signature(self.signature_raw)?.1)
+    }
+
+    fn uncompressed(&self) -> Result<&'a [u8]> {
+        if self.is_compressed() {
+            return Err(Error::BadHeader(
+                "Compressed documents must be decoded to read their data".into(),
+            ));
+        }
+        Ok(self.data)
+    }
+
+    fn doc_hash_state(&self) -> Result<HashState> {
+        let data = self.uncompressed()?;
+        let mut hash_state = HashState::new();
+        if self.hash_raw.is_empty() {
+            hash_state.update([0u8]);
+        } else {
+            hash_state.update(self.hash_raw);
+        }
+        hash_state.update(data);
+        Ok(hash_state)
+    }
+
+    /// Compute the hash of the complete document. Fails if the document is compressed.
+    pub fn hash(&self) -> Result<Hash> {
+        let mut hash_state = self.doc_hash_state()?;
+        hash_state.update(self.signature_raw);
+        Ok(hash_state.hash())
+    }
+
+    /// Verify the document's signatures. Fails if any signature is invalid, or if the document
+    /// is compressed.
+    pub fn verify(&self) -> Result<()> {
+        let doc_hash = self.doc_hash_state()?.hash();
+        let (_, _, pending) = parse_signatures(&doc_hash, self.signature_raw)?;
+        for (hash, unverified) in pending {
+            unverified.verify(&hash)?;
+        }
+        Ok(())
+    }
+
+    /// Get the document's data, borrowed from the encoded document. Fails if the document is
+    /// compressed, or the data isn't a valid fog-pack value.
+    pub fn value(&self) -> Result<ValueRef<'a>> {
+        ValueRef::deserialize(&mut FogDeserializer::new(self.uncompressed()?))
+    }
+
+    /// Look up a single nested value inside the document, without reading the rest of it.
+    /// Returns `Ok(None)` if the path doesn't point to a value. Fails if the document is
+    /// compressed.
+    pub fn get_path(&self, path: &Path) -> Result<Option<ValueRef<'a>>> {
+        path.find_raw(self.uncompressed()?)
+    }
+}
+
+/// A document that is collecting signatures from multiple parties.
+///
+/// Every party signs the same hash: that of the document without any signatures, given by
+/// [`signing_hash`][Self::signing_hash]. A partially signed document can be serialized and passed
+/// between the parties, each adding their own signature. Once enough signatures have been
+/// gathered to meet the schema's [signature threshold][crate::schema::Schema::signature_threshold],
+/// it can be completed into a [`Document`] carrying all of them.
+///
+/// ```
+/// # use fog_pack::{document::*, schema::*, types::IdentityKey, validator::Validator};
+/// # fn main() -> fog_pack::error::Result<()> {
+/// let (alice, bob) = (IdentityKey::new(), IdentityKey::new());
+/// let schema_doc = SchemaBuilder::new(Validator::Any)
+///     .signature_threshold(2)
+///     .signer_add(alice.id().clone())
+///     .signer_add(bob.id().clone())
+///     .build()?;
+/// let schema = Schema::from_doc(&schema_doc)?;
+///
+/// let doc = schema.validate_new_doc(NewDocument::new(Some(schema.hash()), "agreed")?)?;
+/// let mut partial = PartialSignedDocument::new(doc);
+/// partial.sign(&alice)?;
+/// partial.sign(&bob)?;
+/// let doc = partial.complete(&schema)?;
+/// assert_eq!(doc.signers(), &[alice.id().clone(), bob.id().clone()]);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PartialSignedDocument {
+    #[serde(with = "serde_bytes")]
+    doc: Vec<u8>,
+    signatures: Vec<ByteBuf>,
+}
+
+impl PartialSignedDocument {
+    /// Start collecting signatures for a document. Any signature the document already has is
+    /// dropped.
+    pub fn new(doc: Document) -> Self {
+        let sig_len = doc.0.split().signature_raw.len();
+        let mut buf = doc.0.buf;
+        buf.truncate(buf.len() - sig_len);
+        Self {
+            doc: buf,
+            signatures: Vec::new(),
+        }
+    }
+
+    // Decode the unsigned document. Partially signed documents may have come from elsewhere, so
+    // this fails if the document somehow has a signature already.
+    fn unsigned(&self) -> Result<Document> {
+        let doc = Document::new(self.doc.clone())?;
+        if !doc.signers().is_empty() {
+            return Err(Error::BadHeader(
+                "Partially signed document shouldn't have signatures attached".into(),
+            ));
+        }
+        Ok(doc)
+    }
+
+    /// Get the hash that every party must sign. Fails if the document is malformed, which can only
+    /// happen if this was deserialized from a bad source.
+    pub fn signing_hash(&self) -> Result<Hash> {
+        Ok(self.unsigned()?.hash().clone())
+    }
+
+    /// Get the Identities of everyone who has signed so far. The signatures are only checked as
+    /// they're added, so if this was deserialized from an untrusted source, this is only the list
+    /// of claimed signers until [`complete`][Self::complete] is called.
+    pub fn signers(&self) -> Result<Vec<Identity>> {
+        self.signatures
+            .iter()
+            .map(|raw| {
+                Ok(UnverifiedSignature::try_from(raw.as_ref())?
+                    .signer()
+                    .clone())
+            })
+            .collect()
+    }
+
+    /// Sign the document, replacing any previous signature by the same signer.
+    pub fn sign<S: Signer + ?Sized>(&mut self, key: &S) -> Result<()> {
+        let signature = Signer::sign(key, &self.signing_hash()?)?;
+        self.insert_checked(signature)
+    }
+
+    /// Sign the document with an asynchronous signer. Otherwise identical to
+    /// [`sign`][Self::sign].
+    pub async fn sign_async<S: AsyncSigner + ?Sized>(&mut self, key: &S) -> Result<()> {
+        let signature = AsyncSigner::sign(key, &self.signing_hash()?).await?;
+        self.insert_checked(signature)
+    }
+
+    /// Add an encoded signature made by another party over the
+    /// [`signing_hash`][Self::signing_hash], replacing any previous signature by the same signer.
+    /// Fails if the signature isn't valid.
+    pub fn add_signature(&mut self, signature: &[u8]) -> Result<()> {
+        let signature = UnverifiedSignature::try_from(signature)?.verify(&self.signing_hash()?)?;
+        self.insert(signature)
+    }
+
+    // Insert a signature from a signer, checking it first as signers can be arbitrary outside code
+    fn insert_checked(&mut self, signature: Signature) -> Result<()> {
+        let mut raw = Vec::new();
+        signature.encode_vec(&mut raw);
+        self.add_signature(&raw)
+    }
+
+    fn insert(&mut self, signature: Signature) -> Result<()> {
+        let signers = self.signers()?;
+        if let Some(index) = signers.iter().position(|s| s == signature.signer()) {
+            self.signatures.remove(index);
+        } else if self.signatures.len() >= u8::MAX as usize {
+            return Err(Error::LengthTooLong {
+                max: u8::MAX as usize,
+                actual: self.signatures.len() + 1,
+            });
+        }
+        let mut raw = Vec::new();
+        signature.encode_vec(&mut raw);
+        self.signatures.push(ByteBuf::from(raw));
+        Ok(())
+    }
+
+    /// Complete the document, attaching every signature. Fails if the document doesn't meet the
+    /// schema's requirements, including its signature threshold, or if any signature is invalid.
+    pub fn complete(self, schema: &Schema) -> Result<Document> {
+        if self.signatures.len() > u8::MAX as usize {
+            return Err(Error::LengthTooLong {
+                max: u8::MAX as usize,
+                actual: self.signatures.len(),
+            });
+        }
+        let doc = self.unsigned()?;
+        let hash = doc.hash().clone();
+        let mut signatures = self
+            .signatures
+            .iter()
+            .map(|raw| Ok(UnverifiedSignature::try_from(raw.as_ref())?.verify(&hash)?))
+            .collect::<Result<Vec<Signature>>>()?;
+        let signers: Vec<Identity> = signatures.iter().map(|s| s.signer().clone()).collect();
+        if (1..signers.len()).any(|i| signers[..i].contains(&signers[i])) {
+            return Err(Error::BadHeader(
+                "Partially signed document has the same signer more than once".into(),
+            ));
+        }
+        let mut doc = match signatures.len() {
+            0 => doc,
+            1 => Document(doc.0.finish_sign(signatures.remove(0), Vec::new(), None)?),
+            _ => Document(doc.0.set_signatures(
+                multi_signature_section(&signatures),
+                signers,
+                None,
+            )?),
+        };
+        schema.check_doc(&doc)?;
+        doc.set_validated();
+        doc.set_unknown_fields(schema.unknown_fields());
+        Ok(doc)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use rand::Rng;
@@ -769,6 +1779,136 @@ mod test {
 
     use super::*;
 
+    #[test]
+    fn raw_parts() {
+        use crate::{
+            entry::{Entry, NewEntry},
+            schema::SchemaBuilder,
+            validator::Validator,
+        };
+        let key = IdentityKey::new();
+        let doc = NewDocument::new(None, "data").unwrap().sign(&key).unwrap();
+        let doc = crate::schema::NoSchema::validate_new_doc(doc).unwrap();
+        let (hash, schema_hash, raw, signer) = doc.clone().into_raw_parts();
+        assert_eq!(signer.as_ref(), Some(key.id()));
+        for trust in [Trust::Verify, Trust::Trusted] {
+            let rebuilt = Document::from_raw_parts(
+                hash.clone(),
+                schema_hash.clone(),
+                raw.clone(),
+                signer.clone(),
+                trust,
+            )
+            .unwrap();
+            assert_eq!(rebuilt.hash(), doc.hash());
+            assert_eq!(rebuilt.deserialize::<&str>().unwrap(), "data");
+            // Trusted documents have their signatures checked only when asked
+            assert_eq!(rebuilt.is_verified(), trust == Trust::Verify);
+            let rebuilt = rebuilt.verify().unwrap();
+            // Rebuilt documents can still be re-signed
+            let resigned = rebuilt.sign(&IdentityKey::new()).unwrap();
+            let (_, encoded) = crate::schema::NoSchema::encode_doc(resigned).unwrap();
+            crate::schema::NoSchema::decode_doc(encoded).unwrap();
+        }
+        let other = Hash::new(b"other");
+        assert!(Document::from_raw_parts(
+            other.clone(),
+            None,
+            raw.clone(),
+            signer.clone(),
+            Trust::Verify
+        )
+        .is_err());
+        for (schema_hash, signer) in [(None, None), (Some(other.clone()), signer.clone())] {
+            assert!(Document::from_raw_parts(
+                hash.clone(),
+                schema_hash,
+                raw.clone(),
+                signer,
+                Trust::Trusted
+            )
+            .is_err());
+        }
+
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .entry_add("e", Validator::Any, None)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let parent = schema
+            .validate_new_doc(NewDocument::new(Some(schema.hash()), 1).unwrap())
+            .unwrap();
+        let entry = NewEntry::new("e", &parent, 2).unwrap().sign(&key).unwrap();
+        let entry = schema
+            .validate_new_entry(entry)
+            .unwrap()
+            .complete()
+            .unwrap();
+        let (reference, schema_hash, raw, signer) = entry.clone().into_raw_parts();
+        assert_eq!(&schema_hash, schema.hash());
+        for trust in [Trust::Verify, Trust::Trusted] {
+            let rebuilt = Entry::from_raw_parts(
+                reference.clone(),
+                schema_hash.clone(),
+                raw.clone(),
+                signer.clone(),
+                trust,
+            )
+            .unwrap();
+            assert_eq!(rebuilt.reference(), entry.reference());
+            assert_eq!(rebuilt.deserialize::<u8>().unwrap(), 2);
+            // Rebuilt entries can still be re-signed
+            let resigned = rebuilt.sign(&IdentityKey::new()).unwrap();
+            assert_ne!(resigned.hash(), entry.hash());
+        }
+        let mut wrong = reference.clone();
+        wrong.hash = other;
+        assert!(Entry::from_raw_parts(
+            wrong.clone(),
+            schema_hash.clone(),
+            raw.clone(),
+            signer.clone(),
+            Trust::Verify
+        )
+        .is_err());
+        assert!(Entry::from_raw_parts(wrong, schema_hash, raw, None, Trust::Trusted).is_err());
+    }
+
+    #[test]
+    fn promotion() {
+        use crate::{entry::NewEntry, fog_value, schema::SchemaBuilder, validator::Validator};
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .entry_add("e", Validator::Any, None)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let parent = schema
+            .validate_new_doc(NewDocument::new(Some(schema.hash()), "parent").unwrap())
+            .unwrap();
+        let data = fog_value!({"a": [1, 2], "b": "text"});
+
+        let entry = NewEntry::new("e", &parent, &data).unwrap();
+        let entry = schema
+            .validate_new_entry(entry)
+            .unwrap()
+            .complete()
+            .unwrap();
+        let doc = NewDocument::from_entry(Some(schema.hash()), &entry).unwrap();
+        let doc = schema.validate_new_doc(doc).unwrap();
+        assert_eq!(doc.deserialize::<Value>().unwrap(), data);
+        let direct = NewDocument::new(Some(schema.hash()), &data).unwrap();
+        assert_eq!(doc.hash(), schema.validate_new_doc(direct).unwrap().hash());
+
+        let back = NewEntry::from_doc("e", &parent, &doc).unwrap();
+        let back = schema.validate_new_entry(back).unwrap().complete().unwrap();
+        assert_eq!(back.hash(), entry.hash());
+
+        // Documents can hold more than entries can
+        let big = NewDocument::new(None, vec![0u8; crate::MAX_ENTRY_SIZE]).unwrap();
+        let big = crate::schema::NoSchema::validate_new_doc(big).unwrap();
+        assert!(NewEntry::from_doc("e", &parent, &big).is_err());
+    }
+
     #[test]
     fn create_new() {
         let new_doc = NewDocument::new(None, 1u8).unwrap();
@@ -912,6 +2052,108 @@ mod test {
         assert_eq!(&doc_hash, doc.hash());
         assert_eq!(val, 1u8);
         assert_eq!(doc.signer().unwrap(), key.id());
+        assert_eq!(doc.signature_time(), None);
+    }
+
+    #[test]
+    fn signature_time() {
+        let key = IdentityKey::with_rng(&mut rand::rngs::OsRng);
+        let time = Timestamp::from_utc(1_700_000_000, 5).unwrap();
+        let new_doc = NewDocument::new(None, 1u8).unwrap();
+        let plain = new_doc.clone().sign(&key).unwrap();
+        let new_doc = new_doc.sign_with_time(&key, time).unwrap();
+        assert_ne!(plain.hash(), new_doc.hash());
+
+        let (doc_hash, doc_vec, _) = Document::from_new(new_doc).complete();
+        let doc = Document::new(doc_vec.clone()).unwrap();
+        assert_eq!(&doc_hash, doc.hash());
+        assert_eq!(doc.signer().unwrap(), key.id());
+        assert_eq!(doc.signature_time(), Some(time));
+        let deferred = Document::new_deferred(doc_vec.clone()).unwrap();
+        assert_eq!(deferred.signature_time(), Some(time));
+        deferred.verify().unwrap();
+
+        // Re-signing without a time drops it
+        let doc = doc.sign(&key).unwrap();
+        assert_eq!(doc.signature_time(), None);
+        assert_eq!(doc.hash(), plain.hash());
+
+        // The timestamp is covered by the signature
+        let mut tampered = doc_vec;
+        // Header is 5 bytes, then 1 byte of data
+        assert_eq!(tampered[6], SIGNATURE_TIME_MARKER);
+        tampered[8] ^= 1;
+        assert!(Document::new(tampered).is_err());
+    }
+
+    #[test]
+    fn multi_signature() {
+        use crate::{de::FogDeserializer, schema::SchemaBuilder, validator::Validator};
+        let alice = IdentityKey::with_rng(&mut rand::rngs::OsRng);
+        let bob = IdentityKey::with_rng(&mut rand::rngs::OsRng);
+        let carol = IdentityKey::with_rng(&mut rand::rngs::OsRng);
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .signature_threshold(2)
+            .signer_add(alice.id().clone())
+            .signer_add(bob.id().clone())
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let doc = NewDocument::new(Some(schema.hash()), "agreed")
+            .unwrap()
+            .sign(&carol)
+            .unwrap();
+        let doc = schema.validate_new_doc(doc).unwrap();
+        assert!(schema.encode_doc(doc.clone()).is_err());
+
+        // Signatures from outside the signer list don't count
+        let mut partial = PartialSignedDocument::new(doc);
+        partial.sign(&alice).unwrap();
+        partial.sign(&carol).unwrap();
+        assert!(partial.clone().complete(&schema).is_err());
+
+        // Pass the partial document to another party, who signs with a separate signer
+        let mut ser = FogSerializer::default();
+        partial.serialize(&mut ser).unwrap();
+        let encoded = ser.finish();
+        let mut de = FogDeserializer::new(&encoded);
+        let mut partial = PartialSignedDocument::deserialize(&mut de).unwrap();
+        let mut raw = Vec::new();
+        bob.sign(&partial.signing_hash().unwrap())
+            .encode_vec(&mut raw);
+        partial.add_signature(&raw).unwrap();
+        partial.sign(&alice).unwrap();
+        assert_eq!(
+            partial.signers().unwrap(),
+            vec![carol.id().clone(), bob.id().clone(), alice.id().clone()]
+        );
+        assert!(partial.add_signature(&raw[1..]).is_err());
+
+        let doc = partial.complete(&schema).unwrap();
+        assert_eq!(doc.signer(), Some(carol.id()));
+        assert_eq!(doc.signers().len(), 3);
+        let (hash, encoded) = schema.encode_doc(doc).unwrap();
+        let doc = schema.decode_doc(encoded.clone()).unwrap();
+        assert_eq!(doc.hash(), &hash);
+        assert_eq!(
+            doc.signers(),
+            &[carol.id().clone(), bob.id().clone(), alice.id().clone()]
+        );
+        let view = DocumentRef::new(&encoded).unwrap();
+        assert_eq!(view.signer().unwrap(), Some(carol.id().clone()));
+        assert_eq!(
+            view.signers().unwrap(),
+            vec![carol.id().clone(), bob.id().clone(), alice.id().clone()]
+        );
+        let deferred = Document::new_deferred(encoded.clone()).unwrap();
+        assert!(!deferred.is_verified());
+        deferred.verify().unwrap();
+
+        // Corrupting any one signature fails the document
+        let mut tampered = encoded;
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        assert!(schema.decode_doc(tampered).is_err());
     }
 
     #[test]
@@ -1274,4 +2516,345 @@ mod test {
         println!("We expected a total of {} logs", logs.len());
         assert!(dec_logs == logs, "Didn't decode identically")
     }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Borrowed<'a> {
+        #[serde(with = "serde_bytes")]
+        bin: &'a [u8],
+        #[serde(borrow, with = "serde_bytes")]
+        cow_bin: std::borrow::Cow<'a, [u8]>,
+        #[serde(borrow)]
+        cow_str: std::borrow::Cow<'a, str>,
+        name: &'a str,
+    }
+
+    fn borrowed_check(doc: &Document, expected: &Borrowed) {
+        use std::borrow::Cow;
+        let dec: Borrowed = doc.deserialize().unwrap();
+        assert_eq!(&dec, expected);
+        assert!(matches!(dec.cow_bin, Cow::Borrowed(_)));
+        assert!(matches!(dec.cow_str, Cow::Borrowed(_)));
+        let range = doc.data().as_ptr_range();
+        assert!(range.contains(&dec.bin.as_ptr()));
+        assert!(range.contains(&dec.name.as_ptr()));
+    }
+
+    #[test]
+    fn borrowed_deserialize() {
+        let big = vec![0x5Au8; 4096];
+        let expected = Borrowed {
+            bin: &big,
+            cow_bin: big[..100].into(),
+            cow_str: "a borrowed string".into(),
+            name: "hello",
+        };
+        let doc = NewDocument::new(None, &expected).unwrap();
+        let doc = crate::schema::NoSchema::validate_new_doc(doc).unwrap();
+        borrowed_check(&doc, &expected);
+
+        // Make sure it still holds through encoding, compression, and decoding
+        let (_, encoded) = crate::schema::NoSchema::encode_doc(doc).unwrap();
+        let doc = crate::schema::NoSchema::decode_doc(encoded).unwrap();
+        borrowed_check(&doc, &expected);
+    }
+
+    #[test]
+    fn borrowed_deserialize_schema() {
+        use crate::{schema::*, validator::*};
+        let schema_doc = SchemaBuilder::new(Validator::Any).build().unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let big = vec![0xA5u8; 4096];
+        let expected = Borrowed {
+            bin: &big,
+            cow_bin: big[..].into(),
+            cow_str: "another borrowed string".into(),
+            name: "schema",
+        };
+        let doc = NewDocument::new(Some(schema.hash()), &expected).unwrap();
+        let doc = schema.validate_new_doc(doc).unwrap();
+        borrowed_check(&doc, &expected);
+        let (_, encoded) = schema.encode_doc(doc).unwrap();
+        let doc = schema.decode_doc(encoded).unwrap();
+        borrowed_check(&doc, &expected);
+    }
+
+    #[test]
+    fn unvalidated_order() {
+        use std::collections::BTreeMap;
+        let data: BTreeMap<&str, u8> = [("a", 1), ("b", 2)].into_iter().collect();
+        let doc = NewDocument::new(None, &data).unwrap();
+        let doc = crate::schema::NoSchema::validate_new_doc(doc).unwrap();
+        let (_, mut encoded) = crate::schema::NoSchema::encode_doc(doc).unwrap();
+
+        // Swap the keys so they're out of order
+        let ordered = [0xa1, b'a', 0x01, 0xa1, b'b', 0x02];
+        let start = encoded
+            .windows(ordered.len())
+            .position(|w| w == ordered)
+            .unwrap();
+        encoded[start..start + ordered.len()]
+            .copy_from_slice(&[0xa1, b'b', 0x02, 0xa1, b'a', 0x01]);
+        assert!(crate::schema::NoSchema::decode_doc(encoded.clone()).is_err());
+
+        // Documents that skipped validation still get their key order checked
+        let doc = Document::new_deferred(encoded).unwrap();
+        assert!(doc.deserialize::<BTreeMap<&str, u8>>().is_err());
+        assert!(doc
+            .deserialize_with::<BTreeMap<&str, u8>>(UnknownFields::Ignore)
+            .is_err());
+    }
+
+    #[test]
+    fn unknown_fields() {
+        #[derive(Serialize)]
+        struct New {
+            a: u8,
+            b: String,
+            c: Vec<Inner>,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Inner {
+            d: bool,
+            e: u8,
+        }
+
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct OldInner {
+            d: bool,
+        }
+
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Old {
+            a: u8,
+            c: Vec<OldInner>,
+        }
+
+        let new = New {
+            a: 1,
+            b: "new field".into(),
+            c: vec![Inner { d: true, e: 2 }],
+        };
+        let doc = NewDocument::new(None, &new).unwrap();
+        let doc = crate::schema::NoSchema::validate_new_doc(doc).unwrap();
+        let expected = Old {
+            a: 1,
+            c: vec![OldInner { d: true }],
+        };
+
+        let (old, captured) = doc.deserialize_with::<Old>(UnknownFields::Ignore).unwrap();
+        assert_eq!(old, expected);
+        assert!(captured.is_empty());
+
+        let err = doc
+            .deserialize_with::<Old>(UnknownFields::Reject)
+            .unwrap_err();
+        assert!(format!("{}", err).contains("/b"));
+
+        let (old, captured) = doc.deserialize_with::<Old>(UnknownFields::Capture).unwrap();
+        assert_eq!(old, expected);
+        assert_eq!(
+            captured,
+            vec![
+                (Path::new().key("b"), Value::from("new field")),
+                (Path::new().key("c").index(0).key("e"), Value::from(2u8)),
+            ]
+        );
+
+        // A policy set when decoding applies to plain deserialization
+        let (_, encoded) = crate::schema::NoSchema::encode_doc(doc).unwrap();
+        let strict =
+            crate::schema::NoSchema::decode_doc_with_unknown_fields(encoded, UnknownFields::Reject)
+                .unwrap();
+        assert_eq!(strict.unknown_fields(), UnknownFields::Reject);
+        assert!(strict.deserialize::<Old>().is_err());
+        assert!(strict.deserialize::<Value>().is_ok());
+    }
+
+    #[test]
+    fn canonical_floats() {
+        use crate::schema::*;
+        use crate::validator::*;
+        let nan32 = f32::from_bits(0x7fc0_1234);
+        let nan64 = f64::from_bits(0xfff8_0000_0000_1234);
+        let data = (nan32, -0.0f32, nan64, -0.0f64);
+
+        // Normalized by default, so it hashes the same as the canonical values
+        let doc = NewDocument::new(None, data).unwrap();
+        let canonical = NewDocument::new(None, (f32::NAN, 0.0f32, f64::NAN, 0.0f64)).unwrap();
+        assert_eq!(doc.hash(), canonical.hash());
+        let doc = NoSchema::validate_new_doc(doc).unwrap();
+        let (a, b, c, d): (f32, f32, f64, f64) = doc.deserialize().unwrap();
+        assert_eq!(a.to_bits(), 0x7fc0_0000);
+        assert_eq!(b.to_bits(), 0);
+        assert_eq!(c.to_bits(), 0x7ff8_0000_0000_0000);
+        assert_eq!(d.to_bits(), 0);
+
+        // Raw floats are rejected unless the schema allows them
+        let raw = EncodeOptions::new().raw_floats(true);
+        let doc = NewDocument::new_with_options(None, data, &raw).unwrap();
+        NoSchema::validate_new_doc(doc).unwrap_err();
+
+        let strict_doc = SchemaBuilder::new(Validator::Any).build().unwrap();
+        let strict = Schema::from_doc(&strict_doc).unwrap();
+        let doc = NewDocument::new_with_options(Some(strict.hash()), data, &raw).unwrap();
+        strict.validate_new_doc(doc).unwrap_err();
+
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .raw_floats(true)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        assert!(schema.raw_floats());
+        let doc = NewDocument::new_with_options(Some(schema.hash()), data, &raw).unwrap();
+        let doc = schema.validate_new_doc(doc).unwrap();
+        let (_, encoded) = schema.encode_doc(doc).unwrap();
+        let doc = schema.decode_doc(encoded).unwrap();
+        let (a, b, c, d): (f32, f32, f64, f64) = doc.deserialize().unwrap();
+        assert_eq!(a.to_bits(), nan32.to_bits());
+        assert_eq!(b.to_bits(), (-0.0f32).to_bits());
+        assert_eq!(c.to_bits(), nan64.to_bits());
+        assert_eq!(d.to_bits(), (-0.0f64).to_bits());
+    }
+
+    #[test]
+    fn normalize_strings() {
+        use crate::schema::NoSchema;
+        use std::collections::BTreeMap;
+        let mut data = BTreeMap::new();
+        data.insert("e\u{301}", "cafe\u{301}");
+        data.insert("ligature", "\u{fb01}le");
+
+        let options = EncodeOptions::new().normalize(Normalize::NFC);
+        let doc = NewDocument::new_with_options(None, &data, &options).unwrap();
+        let doc = NoSchema::validate_new_doc(doc).unwrap();
+        let dec: BTreeMap<String, String> = doc.deserialize().unwrap();
+        assert_eq!(dec["e\u{301}"], "caf\u{e9}");
+        assert_eq!(dec["ligature"], "\u{fb01}le");
+
+        let options = EncodeOptions::new().normalize(Normalize::NFKC);
+        let doc = NewDocument::new_with_options(None, &data, &options).unwrap();
+        let doc = NoSchema::validate_new_doc(doc).unwrap();
+        let dec: BTreeMap<String, String> = doc.deserialize().unwrap();
+        assert_eq!(dec["e\u{301}"], "caf\u{e9}");
+        assert_eq!(dec["ligature"], "file");
+
+        // Without normalization, the strings are left untouched
+        let doc = NewDocument::new(None, &data).unwrap();
+        let doc = NoSchema::validate_new_doc(doc).unwrap();
+        let dec: BTreeMap<String, String> = doc.deserialize().unwrap();
+        assert_eq!(dec["e\u{301}"], "cafe\u{301}");
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn encode_stats() {
+        use crate::{schema::*, validator::*};
+        use serde_bytes::Bytes;
+        let schema_doc = SchemaBuilder::new(Validator::Any).build().unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+
+        let data = vec![7u8; 10_000];
+        let doc = NewDocument::new(Some(schema.hash()), Bytes::new(&data)).unwrap();
+        let doc = schema.validate_new_doc(doc).unwrap();
+        let (hash, encoded, stats) = schema.encode_doc_with_stats(doc).unwrap();
+        assert!(stats.compressed);
+        assert!(!stats.dictionary);
+        assert_eq!(stats.compressed_size, encoded.len());
+        assert!(stats.uncompressed_size > 10_000);
+        assert!(stats.ratio() < 0.5);
+        let doc = schema.decode_doc(encoded).unwrap();
+        assert_eq!(doc.hash(), &hash);
+
+        // Uncompressed documents report the same size both ways
+        let doc = NewDocument::new(Some(schema.hash()), Bytes::new(&data))
+            .unwrap()
+            .compression(None);
+        let doc = schema.validate_new_doc(doc).unwrap();
+        let (_, encoded, stats) = schema.encode_doc_with_stats(doc).unwrap();
+        assert!(!stats.compressed);
+        assert_eq!(stats.compressed_size, encoded.len());
+        assert_eq!(stats.uncompressed_size, encoded.len());
+    }
+
+    #[test]
+    fn deferred_verification() {
+        use crate::schema::NoSchema;
+        let key = IdentityKey::with_rng(&mut rand::rngs::OsRng);
+        let doc = NewDocument::new(None, "deferred")
+            .unwrap()
+            .compression(None)
+            .sign(&key)
+            .unwrap();
+        let doc = NoSchema::validate_new_doc(doc).unwrap();
+        let (hash, encoded) = NoSchema::encode_doc(doc).unwrap();
+
+        let doc = Document::new_deferred(encoded.clone()).unwrap();
+        assert_eq!(doc.hash(), &hash);
+        assert!(!doc.is_verified());
+        let doc = doc.verify().unwrap();
+        assert!(doc.is_verified());
+        assert_eq!(doc.signer(), Some(key.id()));
+
+        // A bad signature is only caught once verification happens
+        let mut tampered = encoded;
+        *tampered.last_mut().unwrap() ^= 1;
+        let doc = Document::new_deferred(tampered.clone()).unwrap();
+        assert_ne!(doc.hash(), &hash);
+        doc.verify().unwrap_err();
+        let doc = Document::new_deferred(tampered).unwrap();
+        NoSchema::encode_doc(doc).unwrap_err();
+
+        // Unsigned documents have nothing to verify
+        let doc = NewDocument::new(None, 1u8).unwrap().compression(None);
+        let (_, encoded) = NoSchema::encode_doc(NoSchema::validate_new_doc(doc).unwrap()).unwrap();
+        assert!(Document::new_deferred(encoded).unwrap().is_verified());
+    }
+
+    #[test]
+    fn document_ref() {
+        use crate::schema::NoSchema;
+        let key = IdentityKey::with_rng(&mut rand::rngs::OsRng);
+        let time = Timestamp::from_secs(1_700_000_000);
+        let doc = NewDocument::new(None, crate::fog_value!({"a": [1, "two"]}))
+            .unwrap()
+            .compression(None)
+            .sign_with_time(&key, time)
+            .unwrap();
+        let doc = NoSchema::validate_new_doc(doc).unwrap();
+        let (hash, encoded) = NoSchema::encode_doc(doc).unwrap();
+
+        let view = DocumentRef::new(&encoded).unwrap();
+        assert!(!view.is_compressed());
+        assert_eq!(view.raw(), &encoded[..]);
+        assert_eq!(view.format_version(), 0);
+        assert_eq!(view.schema_hash(), None);
+        assert_eq!(view.hash().unwrap(), hash);
+        assert_eq!(view.signer().unwrap().as_ref(), Some(key.id()));
+        assert_eq!(view.signature_time().unwrap(), Some(time));
+        view.verify().unwrap();
+        let path = Path::new().key("a").index(1);
+        assert_eq!(view.get_path(&path).unwrap(), Some(ValueRef::from("two")));
+        assert_eq!(view.value().unwrap()["a"][0], ValueRef::from(1u8));
+
+        // Tampering is only caught by verification
+        let mut tampered = encoded.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        let view = DocumentRef::new(&tampered).unwrap();
+        assert_ne!(view.hash().unwrap(), hash);
+        view.verify().unwrap_err();
+        assert!(DocumentRef::new(&encoded[..3]).is_err());
+
+        // Compressed documents still give their header, but not their data
+        let doc = NewDocument::new(None, "compress ".repeat(100))
+            .unwrap()
+            .sign(&key)
+            .unwrap();
+        let (_, encoded) = NoSchema::encode_doc(NoSchema::validate_new_doc(doc).unwrap()).unwrap();
+        let view = DocumentRef::new(&encoded).unwrap();
+        assert!(view.is_compressed());
+        assert_eq!(view.signer().unwrap().as_ref(), Some(key.id()));
+        view.hash().unwrap_err();
+        view.value().unwrap_err();
+    }
 }
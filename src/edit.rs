@@ -0,0 +1,249 @@
+//! In-place editing of encoded documents.
+//!
+//! A [`DocEditor`] holds a document's encoded data and changes it directly, one path at a time.
+//! Each edit only encodes the new value and splices it in, leaving the rest of the data as it
+//! was, so making small changes to a large document doesn't mean decoding and re-encoding all of
+//! it. The document is only re-hashed once editing is [finished][DocEditor::finish].
+
+use serde::Serialize;
+
+use crate::{
+    document::{Document, NewDocument},
+    element::{serialize_elem, Element},
+    error::{Error, Result, ValidateError},
+    path::{Path, PathSegment, RawContainer, RawTarget},
+    ser::FogSerializer,
+    types::{Hash, ValueRef},
+};
+
+/// An editor for a document's data, created with [`Document::edit`].
+///
+/// Edits are addressed by [`Path`]. Map keys are always kept in order, so keys can be added in
+/// any order. Edits aren't validated as they are made: the result is a [`NewDocument`] that
+/// still has to pass through its schema.
+///
+/// ```
+/// # use fog_pack::{document::*, fog_value, schema::*, types::*};
+/// # fn main() -> fog_pack::error::Result<()> {
+/// let doc = NewDocument::new(None, fog_value!({"name": "old", "tags": ["a"]}))?;
+/// let doc = NoSchema::validate_new_doc(doc)?;
+///
+/// let mut editor = doc.edit();
+/// editor.set(&Path::new().key("name"), "new")?;
+/// editor.insert(&Path::new().key("tags").index(0), "first")?;
+/// editor.set(&Path::new().key("count"), 2)?;
+/// let doc = NoSchema::validate_new_doc(editor.finish()?)?;
+///
+/// let value: Value = doc.deserialize()?;
+/// assert_eq!(value, fog_value!({"count": 2, "name": "new", "tags": ["first", "a"]}));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct DocEditor {
+    schema: Option<Hash>,
+    data: Vec<u8>,
+}
+
+fn encode<S: Serialize>(value: S) -> Result<Vec<u8>> {
+    let mut ser = FogSerializer::default();
+    value.serialize(&mut ser)?;
+    Ok(ser.finish())
+}
+
+fn fail(msg: String) -> Error {
+    Error::FailValidate(ValidateError::Other(msg.into()))
+}
+
+impl DocEditor {
+    pub(crate) fn new(doc: &Document) -> Self {
+        Self {
+            schema: doc.schema_hash().cloned(),
+            data: doc.data().to_vec(),
+        }
+    }
+
+    /// Get the value at a path, as it currently stands.
+    pub fn get(&self, path: &Path) -> Result<Option<ValueRef>> {
+        path.find_raw(&self.data)
+    }
+
+    /// Set the value at a path, replacing any value already there. A missing map key is added,
+    /// but array items must already exist; use [`insert`][Self::insert] to add to an array. An
+    /// empty path replaces the whole document. Fails if the path's parent doesn't exist.
+    pub fn set<S: Serialize>(&mut self, path: &Path, value: S) -> Result<()> {
+        let value = encode(value)?;
+        match path.locate_raw(&self.data)? {
+            (
+                _,
+                RawTarget::Found {
+                    value_start, end, ..
+                },
+            ) => {
+                self.data.splice(value_start..end, value);
+            }
+            (Some(parent), RawTarget::Missing { at }) if parent.is_map => {
+                self.add_entry(parent, at, path, value);
+            }
+            _ => return Err(fail(format!("no array item at {}", path))),
+        }
+        Ok(())
+    }
+
+    /// Insert a value at a path. For arrays, the value is inserted at the path's index, which may
+    /// be one past the last item, and the items after it are shifted over. For maps, the key is
+    /// added, and must not already be present. Fails if the path is empty or its parent doesn't
+    /// exist.
+    pub fn insert<S: Serialize>(&mut self, path: &Path, value: S) -> Result<()> {
+        let value = encode(value)?;
+        match path.locate_raw(&self.data)? {
+            (Some(parent), RawTarget::Found { entry_start, .. }) if !parent.is_map => {
+                self.add_entry(parent, entry_start, path, value);
+            }
+            (Some(parent), RawTarget::Missing { at }) => {
+                self.add_entry(parent, at, path, value);
+            }
+            (Some(_), _) => return Err(fail(format!("map key at {} already exists", path))),
+            (None, _) => return Err(fail("can't insert at the root of a document".into())),
+        }
+        Ok(())
+    }
+
+    /// Remove the value at a path, returning true if there was one to remove. Array items after
+    /// it are shifted over. Fails if the path is empty or its parent doesn't exist.
+    pub fn remove(&mut self, path: &Path) -> Result<bool> {
+        match path.locate_raw(&self.data)? {
+            (
+                Some(parent),
+                RawTarget::Found {
+                    entry_start, end, ..
+                },
+            ) => {
+                self.data.drain(entry_start..end);
+                self.set_len(parent, parent.len - 1);
+                Ok(true)
+            }
+            (Some(_), RawTarget::Missing { .. }) => Ok(false),
+            (None, _) => Err(fail("can't remove the root of a document".into())),
+        }
+    }
+
+    fn add_entry(&mut self, parent: RawContainer, at: usize, path: &Path, value: Vec<u8>) {
+        let mut entry = Vec::with_capacity(value.len() + 8);
+        if parent.is_map {
+            match path.segments().last() {
+                Some(PathSegment::Key(key)) => serialize_elem(&mut entry, Element::Str(key)),
+                _ => unreachable!("map paths always end in a key"),
+            }
+        }
+        entry.extend_from_slice(&value);
+        self.data.splice(at..at, entry);
+        self.set_len(parent, parent.len + 1);
+    }
+
+    // Rewrite a container's header. The header comes before any of its contents, so offsets
+    // found before editing the contents are still valid.
+    fn set_len(&mut self, container: RawContainer, len: usize) {
+        let mut header = Vec::with_capacity(4);
+        let elem = if container.is_map {
+            Element::Map(len)
+        } else {
+            Element::Array(len)
+        };
+        serialize_elem(&mut header, elem);
+        self.data
+            .splice(container.start..container.header_end, header);
+    }
+
+    /// Finish editing, producing a new document with the edited data. The new document is
+    /// unsigned, uses the same schema, and must be validated like any other new document. Fails
+    /// if the data has grown past the maximum document size.
+    pub fn finish(self) -> Result<NewDocument> {
+        let data = self.data;
+        NewDocument::new_from(self.schema.as_ref(), |mut buf| {
+            buf.extend_from_slice(&data);
+            Ok(buf)
+        })
+    }
+}
+
+impl Document {
+    /// Start editing this document's data. See [`DocEditor`].
+    pub fn edit(&self) -> DocEditor {
+        DocEditor::new(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{fog_value, schema::NoSchema, types::Value};
+
+    fn doc(value: Value) -> Document {
+        NoSchema::validate_new_doc(NewDocument::new(None, value).unwrap()).unwrap()
+    }
+
+    fn check(editor: DocEditor, expected: Value) {
+        let edited = NoSchema::validate_new_doc(editor.finish().unwrap()).unwrap();
+        // Editing must produce exactly the same document as encoding from scratch
+        assert_eq!(edited.hash(), doc(expected.clone()).hash());
+        assert_eq!(edited.deserialize::<Value>().unwrap(), expected);
+    }
+
+    #[test]
+    fn edits() {
+        let original = doc(fog_value!({"b": [1, 2, 3], "d": {"x": null}}));
+        let path = |s: &str| Path::parse(s).unwrap();
+
+        let mut editor = original.edit();
+        editor.set(&path("/b/1"), "two").unwrap();
+        editor.set(&path("/a"), 0).unwrap();
+        editor.set(&path("/c"), true).unwrap();
+        editor.set(&path("/e"), "end").unwrap();
+        editor.set(&path("/d/y"), [1u8; 4]).unwrap();
+        assert_eq!(
+            editor.get(&path("/b/1")).unwrap(),
+            Some(ValueRef::Str("two"))
+        );
+        check(
+            editor,
+            fog_value!({
+                "a": 0,
+                "b": [1, "two", 3],
+                "c": true,
+                "d": {"x": null, "y": [1, 1, 1, 1]},
+                "e": "end"
+            }),
+        );
+
+        let mut editor = original.edit();
+        editor.insert(&path("/b/0"), 0).unwrap();
+        editor.insert(&path("/b/4"), 4).unwrap();
+        assert!(editor.remove(&path("/d/x")).unwrap());
+        assert!(!editor.remove(&path("/d/x")).unwrap());
+        assert!(editor.remove(&path("/b/2")).unwrap());
+        check(editor, fog_value!({"b": [0, 1, 3, 4], "d": {}}));
+
+        // Growing a container past 15 items changes the size of its header
+        let mut editor = original.edit();
+        for i in 3..20 {
+            editor.insert(&path(&format!("/b/{}", i)), i).unwrap();
+        }
+        let b: Vec<Value> = (1..20).map(|i| Value::from(i as u8)).collect();
+        check(editor, fog_value!({"b": b, "d": {"x": null}}));
+
+        let mut editor = original.edit();
+        editor.set(&Path::new(), "replaced").unwrap();
+        check(editor, Value::from("replaced"));
+
+        // Things that can't be done
+        let mut editor = original.edit();
+        assert!(editor.set(&path("/b/3"), 0).is_err());
+        assert!(editor.insert(&path("/b/5"), 0).is_err());
+        assert!(editor.insert(&path("/d/x"), 0).is_err());
+        assert!(editor.set(&path("/missing/x"), 0).is_err());
+        assert!(editor.set(&path("/b/0/x"), 0).is_err());
+        assert!(editor.remove(&Path::new()).is_err());
+        check(editor, original.deserialize().unwrap());
+    }
+}
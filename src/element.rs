@@ -1,6 +1,11 @@
 use std::{convert::TryFrom, fmt::Debug};
 
-use crate::{depth_tracking::DepthTracker, marker::*, MAX_DOC_SIZE};
+use crate::{
+    depth_tracking::DepthTracker,
+    marker::*,
+    pretty::{PrettyFormatter, PrettyOptions},
+    MAX_DOC_SIZE,
+};
 use crate::{
     error::{Error, Result},
     get_int_internal, integer, Integer, Timestamp,
@@ -17,11 +22,6 @@ use serde::de::Unexpected;
 
 use byteorder::{LittleEndian, ReadBytesExt};
 
-fn base64_encode<T: AsRef<[u8]>>(input: T, output_buf: &mut String) {
-    use base64::engine::Engine;
-    base64::engine::general_purpose::STANDARD_NO_PAD.encode_string(input, output_buf)
-}
-
 #[derive(Clone, Debug)]
 pub enum Element<'a> {
     Null,
@@ -71,6 +71,33 @@ impl<'a> Element<'a> {
         }
     }
 
+    /// Get a marker that could have been used to encode this element. Only the marker's type
+    /// matters; for sizes that don't fit in the marker, the smallest sized marker is used.
+    pub fn marker(&self) -> Marker {
+        use self::Element::*;
+        match self {
+            Null => Marker::Null,
+            Bool(false) => Marker::False,
+            Bool(true) => Marker::True,
+            Int(v) => match get_int_internal(v) {
+                integer::IntPriv::PosInt(v) if v < 128 => Marker::PosFixInt(v as u8),
+                integer::IntPriv::PosInt(_) => Marker::UInt64,
+                integer::IntPriv::NegInt(v) if v >= -32 => Marker::NegFixInt(v as i8),
+                integer::IntPriv::NegInt(_) => Marker::Int64,
+            },
+            Str(v) if v.len() < 32 => Marker::FixStr(v.len() as u8),
+            Str(_) => Marker::Str8,
+            F32(_) => Marker::F32,
+            F64(_) => Marker::F64,
+            Bin(_) => Marker::Bin8,
+            Array(len) if *len < 16 => Marker::FixArray(*len as u8),
+            Array(_) => Marker::Array8,
+            Map(len) if *len < 16 => Marker::FixMap(*len as u8),
+            Map(_) => Marker::Map8,
+            _ => Marker::Ext8,
+        }
+    }
+
     pub fn unexpected(&self) -> Unexpected {
         use self::Element::*;
         match self {
@@ -100,6 +127,33 @@ impl<'a> Element<'a> {
     }
 }
 
+const CANONICAL_NAN_F32: u32 = 0x7fc0_0000;
+const CANONICAL_NAN_F64: u64 = 0x7ff8_0000_0000_0000;
+
+/// Get the canonical form of a 32-bit float. All NaNs become the quiet NaN with no payload, and
+/// negative zero becomes positive zero. Any other value is unchanged.
+pub fn canonical_f32(v: f32) -> f32 {
+    if v.is_nan() {
+        f32::from_bits(CANONICAL_NAN_F32)
+    } else if v == 0.0 {
+        0.0
+    } else {
+        v
+    }
+}
+
+/// Get the canonical form of a 64-bit float. All NaNs become the quiet NaN with no payload, and
+/// negative zero becomes positive zero. Any other value is unchanged.
+pub fn canonical_f64(v: f64) -> f64 {
+    if v.is_nan() {
+        f64::from_bits(CANONICAL_NAN_F64)
+    } else if v == 0.0 {
+        0.0
+    } else {
+        v
+    }
+}
+
 /// Serialize an element onto a byte vector. Doesn't check if Array & Map structures make
 /// sense, just writes elements out.
 pub fn serialize_elem(buf: &mut Vec<u8>, elem: Element) {
@@ -273,165 +327,10 @@ pub fn serialize_elem(buf: &mut Vec<u8>, elem: Element) {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
-enum TrackType {
-    FirstArray(usize),
-    FirstMap(usize),
-    Array(usize),
-    Map(usize),
-}
-
-#[derive(Clone, Debug)]
-struct DebugFormatter {
-    debug: String,
-    tracker: Vec<TrackType>,
-    indent: String,
-}
-
-impl DebugFormatter {
-    fn new(indent: String) -> Self {
-        Self {
-            debug: String::new(),
-            tracker: Vec::new(),
-            indent,
-        }
-    }
-
-    fn get_str(&self) -> &str {
-        &self.debug
-    }
-
-    fn indent(&mut self) {
-        match self.tracker.last() {
-            Some(TrackType::FirstArray(_)) => (),
-            Some(TrackType::FirstMap(_)) => (),
-            _ => {
-                for _ in 0..self.tracker.len() {
-                    self.debug.push_str(&self.indent)
-                }
-            }
-        }
-    }
-
-    fn update(&mut self, elem: &Element) {
-        use std::fmt::Write;
-
-        self.indent();
-        match elem {
-            Element::Null => self.debug.push_str("null"),
-            Element::Bool(v) => self.debug.push_str(if *v { "true" } else { "false" }),
-            Element::Int(v) => write!(self.debug, "{}", v).unwrap(),
-            Element::Str(v) => write!(self.debug, "\"{}\"", v.escape_debug()).unwrap(),
-            Element::F32(v) => write!(self.debug, "{}", v).unwrap(),
-            Element::F64(v) => write!(self.debug, "{}", v).unwrap(),
-            Element::Bin(v) => {
-                self.debug.push_str("\"$fog-Bin:");
-                base64_encode(*v, &mut self.debug);
-                self.debug.push('"');
-            }
-            Element::Array(v) => {
-                self.tracker.push(TrackType::FirstArray(*v));
-                self.debug.push('[');
-            }
-            Element::Map(v) => {
-                self.tracker.push(TrackType::FirstMap(*v * 2));
-                self.debug.push('{');
-            }
-            Element::Timestamp(v) => write!(self.debug, "\"$fog-Time: {}\"", v).unwrap(),
-            Element::Hash(v) => write!(self.debug, "\"$fog-Hash:{}\"", v).unwrap(),
-            Element::Identity(v) => write!(self.debug, "\"$fog-Identity:{}\"", v).unwrap(),
-            Element::LockId(v) => write!(self.debug, "\"$fog-LockId:{}\"", v).unwrap(),
-            Element::StreamId(v) => write!(self.debug, "\"$fog-StreamId:{}\"", v).unwrap(),
-            Element::DataLockbox(v) => write!(
-                self.debug,
-                "\"$fog-DataLockbox(len={})\"",
-                v.as_bytes().len()
-            )
-            .unwrap(),
-            Element::IdentityLockbox(v) => write!(
-                self.debug,
-                "\"$fog-IdentityLockbox(len={})\"",
-                v.as_bytes().len()
-            )
-            .unwrap(),
-            Element::StreamLockbox(v) => write!(
-                self.debug,
-                "\"$fog-StreamLockbox(len={})\"",
-                v.as_bytes().len()
-            )
-            .unwrap(),
-            Element::LockLockbox(v) => write!(
-                self.debug,
-                "\"$fog-LockLockbox(len={})\"",
-                v.as_bytes().len()
-            )
-            .unwrap(),
-            Element::BareIdKey(v) => {
-                write!(self.debug, "\"$fog-BareIdKey:{}\"", v.to_base58()).unwrap()
-            }
-        }
-
-        while let Some(track) = self.tracker.pop() {
-            match track {
-                TrackType::FirstArray(size) => {
-                    if size == 0 {
-                        self.debug.push_str(" ]");
-                    } else if size == 1 {
-                        self.debug.push(' ');
-                        self.tracker.push(TrackType::FirstArray(size - 1));
-                        break;
-                    } else {
-                        self.debug.push('\n');
-                        self.tracker.push(TrackType::Array(size - 1));
-                        break;
-                    }
-                }
-                TrackType::Array(size) => {
-                    if size == 0 {
-                        self.debug.push('\n');
-                        self.indent();
-                        self.debug.push(']');
-                    } else {
-                        self.debug.push_str(",\n");
-                        self.tracker.push(TrackType::Array(size - 1));
-                        break;
-                    }
-                }
-                TrackType::FirstMap(size) => {
-                    if size == 0 {
-                        self.debug.push_str(" }");
-                    } else if size == 1 {
-                        self.debug.push_str(": ");
-                        self.tracker.push(TrackType::FirstMap(size - 1));
-                        break;
-                    } else if size == 2 {
-                        self.debug.push(' ');
-                        self.tracker.push(TrackType::FirstMap(size - 1));
-                        break;
-                    } else {
-                        self.debug.push('\n');
-                        self.tracker.push(TrackType::Map(size - 1));
-                        break;
-                    }
-                }
-                TrackType::Map(size) => {
-                    if size == 0 {
-                        self.debug.push('\n');
-                        self.indent();
-                        self.debug.push('}');
-                    } else if (size & 0x1) == 1 {
-                        self.debug.push_str(": ");
-                        self.tracker.push(TrackType::Map(size - 1));
-                        break;
-                    } else {
-                        self.debug.push_str(",\n");
-                        self.tracker.push(TrackType::Map(size - 1));
-                        break;
-                    }
-                }
-            }
-        }
-    }
+/// Check that a string is valid UTF-8. String validation dominates parsing time for text-heavy
+/// data, so this uses SIMD instructions where the target supports them.
+fn parse_str(string: &[u8]) -> Result<&str> {
+    simdutf8::compat::from_utf8(string).map_err(|e| Error::BadEncode(format!("{}", e)))
 }
 
 /// Fog-pack element parser. Return individual elements of a fog-pack sequence, and checks for
@@ -441,10 +340,14 @@ impl DebugFormatter {
 /// order.
 #[derive(Clone, Debug)]
 pub struct Parser<'a> {
-    debug: Option<DebugFormatter>,
+    debug: Option<PrettyFormatter>,
     data: &'a [u8],
+    elems: &'a [Element<'a>],
     depth_tracking: DepthTracker,
     errored: bool,
+    raw_floats: bool,
+    lenient: bool,
+    fixed: usize,
 }
 
 impl<'a> Parser<'a> {
@@ -453,8 +356,12 @@ impl<'a> Parser<'a> {
         Self {
             debug: None,
             data,
+            elems: &[],
             depth_tracking: DepthTracker::new(),
             errored: false,
+            raw_floats: false,
+            lenient: false,
+            fixed: 0,
         }
     }
 
@@ -463,22 +370,82 @@ impl<'a> Parser<'a> {
     #[allow(dead_code)]
     pub fn with_debug(data: &'a [u8], indent: impl Into<String>) -> Parser<'a> {
         Self {
-            debug: Some(DebugFormatter::new(indent.into())),
+            debug: Some(PrettyFormatter::new(PrettyOptions::new().indent(indent))),
             data,
+            elems: &[],
             depth_tracking: DepthTracker::new(),
             errored: false,
+            raw_floats: false,
+            lenient: false,
+            fixed: 0,
         }
     }
 
+    /// Turn a sequence of already-parsed elements into a new parser, which returns them in order.
+    /// Depth limits are still checked, but the elements are otherwise trusted to be valid.
+    pub(crate) fn from_elements(elems: &'a [Element<'a>]) -> Parser<'a> {
+        Self {
+            debug: None,
+            data: &[],
+            elems,
+            depth_tracking: DepthTracker::new(),
+            errored: false,
+            raw_floats: false,
+            lenient: false,
+            fixed: 0,
+        }
+    }
+
+    /// Set whether floating-point values with non-canonical bit patterns are accepted. By
+    /// default, NaN must be encoded as the canonical quiet NaN, and negative zero is rejected.
+    /// See [`canonical_f32`] and [`canonical_f64`].
+    pub fn raw_floats(mut self, raw_floats: bool) -> Self {
+        self.raw_floats = raw_floats;
+        self
+    }
+
+    /// Set whether encodings that aren't the shortest possible, and floating-point values with
+    /// non-canonical bit patterns, are accepted. Floats are turned into their canonical form. The
+    /// number of elements that weren't canonically encoded is tracked by [`fixed`][Self::fixed].
+    pub(crate) fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Get the number of non-canonical elements accepted so far by a lenient parser.
+    pub(crate) fn fixed(&self) -> usize {
+        self.fixed
+    }
+
+    /// Lower the maximum nesting depth allowed while parsing. Must be set before parsing starts.
+    /// The depth can't be raised past [`MAX_DEPTH`][crate::MAX_DEPTH].
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.depth_tracking = DepthTracker::with_limit(depth);
+        self
+    }
+
+    /// Get the number of encoded bytes not yet parsed.
+    pub(crate) fn remaining(&self) -> usize {
+        self.data.len()
+    }
+
     /// Look at what the next marker byte to be parsed will be.
     pub fn peek_marker(&self) -> Option<Marker> {
+        if let Some(elem) = self.elems.first() {
+            return Some(elem.marker());
+        }
         self.data.first().map(|n| Marker::from_u8(*n))
     }
 
     /// Call when parsing is expected to be complete. Fails if there are any bytes left inside the
     /// parser.
     pub fn finish(self) -> Result<()> {
-        if self.data.is_empty() {
+        if !self.elems.is_empty() {
+            Err(Error::BadEncode(format!(
+                "Parsing still had {} elements left",
+                self.elems.len()
+            )))
+        } else if self.data.is_empty() {
             Ok(())
         } else {
             Err(Error::BadEncode(format!(
@@ -504,12 +471,30 @@ impl<'a> Parser<'a> {
         if self.errored {
             return None;
         }
+        if let Some(elem) = self.elems.first() {
+            return Some(Ok(elem.clone()));
+        }
         let (&marker, mut data) = data.split_first()?;
-        Some(Self::get_element(&mut data, Marker::from_u8(marker)))
+        Some(Self::get_element(
+            &mut data,
+            Marker::from_u8(marker),
+            self.raw_floats,
+            self.lenient,
+        ))
     }
 
     fn parse_element(&mut self, marker: Marker) -> Result<Element<'a>> {
-        let elem = Self::get_element(&mut self.data, marker)?;
+        if self.lenient {
+            let mut probe = self.data;
+            if Self::get_element(&mut probe, marker, self.raw_floats, false).is_err() {
+                self.fixed += 1;
+            }
+        }
+        let elem = Self::get_element(&mut self.data, marker, self.raw_floats, self.lenient)?;
+        self.track_element(elem)
+    }
+
+    fn track_element(&mut self, elem: Element<'a>) -> Result<Element<'a>> {
         if let Some(ref mut debug) = self.debug {
             debug.update(&elem);
         }
@@ -520,7 +505,12 @@ impl<'a> Parser<'a> {
     // Given a retrieved marker, try to turn it into the next element, which may move through the
     // indexed data. If we can't, error. This function *does not* set the the errored flag. That's
     // up to the caller.
-    fn get_element(data: &mut &'a [u8], marker: Marker) -> Result<Element<'a>> {
+    fn get_element(
+        data: &mut &'a [u8],
+        marker: Marker,
+        raw_floats: bool,
+        lenient: bool,
+    ) -> Result<Element<'a>> {
         use self::Marker::*;
         Ok(match marker {
             Reserved => return Err(Error::BadEncode(String::from("Reserved marker found"))),
@@ -534,7 +524,7 @@ impl<'a> Parser<'a> {
                     actual: 0,
                     expected: 1,
                 })?;
-                if v < 128 {
+                if !lenient && v < 128 {
                     return Err(Error::BadEncode(format!(
                         "Got UInt8 with value = {}. This is not the shortest encoding.",
                         v
@@ -550,7 +540,7 @@ impl<'a> Parser<'a> {
                         actual: data.len(),
                         expected: 2,
                     })?;
-                if v <= u8::MAX as u16 {
+                if !lenient && v <= u8::MAX as u16 {
                     return Err(Error::BadEncode(format!(
                         "Got UInt16 with value = {}. This is not the shortest encoding.",
                         v
@@ -566,7 +556,7 @@ impl<'a> Parser<'a> {
                         actual: data.len(),
                         expected: 4,
                     })?;
-                if v <= u16::MAX as u32 {
+                if !lenient && v <= u16::MAX as u32 {
                     return Err(Error::BadEncode(format!(
                         "Got UInt32 with value = {}. This is not the shortest encoding.",
                         v
@@ -582,7 +572,7 @@ impl<'a> Parser<'a> {
                         actual: data.len(),
                         expected: 8,
                     })?;
-                if v <= u32::MAX as u64 {
+                if !lenient && v <= u32::MAX as u64 {
                     return Err(Error::BadEncode(format!(
                         "Got UInt64 with value = {}. This is not the shortest encoding.",
                         v
@@ -597,7 +587,7 @@ impl<'a> Parser<'a> {
                     actual: 0,
                     expected: 1,
                 })?;
-                if v >= -32 {
+                if !lenient && v >= -32 {
                     return Err(Error::BadEncode(format!(
                         "Got Int8 with value = {}. This is not the shortest encoding.",
                         v
@@ -613,7 +603,7 @@ impl<'a> Parser<'a> {
                         actual: data.len(),
                         expected: 2,
                     })?;
-                if v >= i8::MIN as i16 {
+                if !lenient && v >= i8::MIN as i16 {
                     return Err(Error::BadEncode(format!(
                         "Got Int16 with value = {}. This is not the shortest encoding.",
                         v
@@ -629,7 +619,7 @@ impl<'a> Parser<'a> {
                         actual: data.len(),
                         expected: 4,
                     })?;
-                if v >= i16::MIN as i32 {
+                if !lenient && v >= i16::MIN as i32 {
                     return Err(Error::BadEncode(format!(
                         "Got Int32 with value = {}. This is not the shortest encoding.",
                         v
@@ -645,7 +635,7 @@ impl<'a> Parser<'a> {
                         actual: data.len(),
                         expected: 8,
                     })?;
-                if v >= i32::MIN as i64 {
+                if !lenient && v >= i32::MIN as i64 {
                     return Err(Error::BadEncode(format!(
                         "Got Int64 with value = {}. This is not the shortest encoding.",
                         v
@@ -678,7 +668,7 @@ impl<'a> Parser<'a> {
                         actual: data.len(),
                         expected: 2,
                     })? as usize;
-                if len <= (u8::MAX as usize) {
+                if !lenient && len <= (u8::MAX as usize) {
                     return Err(Error::BadEncode(format!(
                         "Got Bin16 with length = {}. This is not the shortest encoding.",
                         len
@@ -703,7 +693,7 @@ impl<'a> Parser<'a> {
                         actual: data.len(),
                         expected: 3,
                     })? as usize;
-                if len <= (u16::MAX as usize) {
+                if !lenient && len <= (u16::MAX as usize) {
                     return Err(Error::BadEncode(format!(
                         "Got Bin24 with length = {}. This is not the shortest encoding.",
                         len
@@ -728,6 +718,15 @@ impl<'a> Parser<'a> {
                         actual: data.len(),
                         expected: 4,
                     })?;
+                if lenient {
+                    return Ok(Element::F32(canonical_f32(v)));
+                }
+                if !raw_floats && v.to_bits() != canonical_f32(v).to_bits() {
+                    return Err(Error::BadEncode(format!(
+                        "Got F32 with bits = 0x{:08x}. This is not the canonical encoding.",
+                        v.to_bits()
+                    )));
+                }
                 Element::F32(v)
             }
             F64 => {
@@ -738,6 +737,15 @@ impl<'a> Parser<'a> {
                         actual: data.len(),
                         expected: 8,
                     })?;
+                if lenient {
+                    return Ok(Element::F64(canonical_f64(v)));
+                }
+                if !raw_floats && v.to_bits() != canonical_f64(v).to_bits() {
+                    return Err(Error::BadEncode(format!(
+                        "Got F64 with bits = 0x{:016x}. This is not the canonical encoding.",
+                        v.to_bits()
+                    )));
+                }
                 Element::F64(v)
             }
             FixStr(len) => {
@@ -751,8 +759,7 @@ impl<'a> Parser<'a> {
                 }
                 let (string, new_data) = data.split_at(len);
                 *data = new_data;
-                let string =
-                    std::str::from_utf8(string).map_err(|e| Error::BadEncode(format!("{}", e)))?;
+                let string = parse_str(string)?;
                 Element::Str(string)
             }
             Str8 => {
@@ -761,7 +768,7 @@ impl<'a> Parser<'a> {
                     actual: 0,
                     expected: 1,
                 })? as usize;
-                if len <= 31 {
+                if !lenient && len <= 31 {
                     return Err(Error::BadEncode(format!(
                         "Got Str8 with length = {}. This is not the shortest encoding.",
                         len
@@ -776,8 +783,7 @@ impl<'a> Parser<'a> {
                 }
                 let (string, new_data) = data.split_at(len);
                 *data = new_data;
-                let string =
-                    std::str::from_utf8(string).map_err(|e| Error::BadEncode(format!("{}", e)))?;
+                let string = parse_str(string)?;
                 Element::Str(string)
             }
             Str16 => {
@@ -788,7 +794,7 @@ impl<'a> Parser<'a> {
                         actual: data.len(),
                         expected: 2,
                     })? as usize;
-                if len <= (u8::MAX as usize) {
+                if !lenient && len <= (u8::MAX as usize) {
                     return Err(Error::BadEncode(format!(
                         "Got Str16 with length = {}. This is not the shortest encoding.",
                         len
@@ -803,8 +809,7 @@ impl<'a> Parser<'a> {
                 }
                 let (string, new_data) = data.split_at(len);
                 *data = new_data;
-                let string =
-                    std::str::from_utf8(string).map_err(|e| Error::BadEncode(format!("{}", e)))?;
+                let string = parse_str(string)?;
                 Element::Str(string)
             }
             Str24 => {
@@ -815,7 +820,7 @@ impl<'a> Parser<'a> {
                         actual: data.len(),
                         expected: 3,
                     })? as usize;
-                if len <= (u16::MAX as usize) {
+                if !lenient && len <= (u16::MAX as usize) {
                     return Err(Error::BadEncode(format!(
                         "Got Str24 with length = {}. This is not the shortest encoding.",
                         len
@@ -830,8 +835,7 @@ impl<'a> Parser<'a> {
                 }
                 let (string, new_data) = data.split_at(len);
                 *data = new_data;
-                let string =
-                    std::str::from_utf8(string).map_err(|e| Error::BadEncode(format!("{}", e)))?;
+                let string = parse_str(string)?;
                 Element::Str(string)
             }
             FixArray(len) => Element::Array(len as usize),
@@ -841,7 +845,7 @@ impl<'a> Parser<'a> {
                     actual: 0,
                     expected: 1,
                 })? as usize;
-                if len <= 15 {
+                if !lenient && len <= 15 {
                     return Err(Error::BadEncode(format!(
                         "Got Array8 marker with length = {}. This is not the shortest encoding.",
                         len
@@ -857,7 +861,7 @@ impl<'a> Parser<'a> {
                         actual: data.len(),
                         expected: 2,
                     })? as usize;
-                if len <= u8::MAX as usize {
+                if !lenient && len <= u8::MAX as usize {
                     return Err(Error::BadEncode(format!(
                         "Got Array16 marker with length = {}. This is not the shortest encoding.",
                         len
@@ -880,7 +884,7 @@ impl<'a> Parser<'a> {
                         actual: data.len(),
                         expected: 3,
                     })? as usize;
-                if len <= u16::MAX as usize {
+                if !lenient && len <= u16::MAX as usize {
                     return Err(Error::BadEncode(format!(
                         "Got Array24 marker with length = {}. This is not the shortest encoding.",
                         len
@@ -902,7 +906,7 @@ impl<'a> Parser<'a> {
                     actual: 0,
                     expected: 1,
                 })? as usize;
-                if len <= 15 {
+                if !lenient && len <= 15 {
                     return Err(Error::BadEncode(format!(
                         "Got Map8 marker with length = {}. This is not the shortest encoding.",
                         len
@@ -918,7 +922,7 @@ impl<'a> Parser<'a> {
                         actual: data.len(),
                         expected: 2,
                     })? as usize;
-                if len <= u8::MAX as usize {
+                if !lenient && len <= u8::MAX as usize {
                     return Err(Error::BadEncode(format!(
                         "Got Map16 marker with length = {}. This is not the shortest encoding.",
                         len
@@ -941,7 +945,7 @@ impl<'a> Parser<'a> {
                         actual: data.len(),
                         expected: 3,
                     })? as usize;
-                if len <= u16::MAX as usize {
+                if !lenient && len <= u16::MAX as usize {
                     return Err(Error::BadEncode(format!(
                         "Got Map24 marker with length = {}. This is not the shortest encoding.",
                         len
@@ -972,7 +976,7 @@ impl<'a> Parser<'a> {
                         actual: data.len(),
                         expected: 2,
                     })? as usize;
-                if len <= u8::MAX as usize {
+                if !lenient && len <= u8::MAX as usize {
                     return Err(Error::BadEncode(format!(
                         "Got Ext16 marker with length = {}. This is not the shortest encoding.",
                         len
@@ -988,7 +992,7 @@ impl<'a> Parser<'a> {
                         actual: data.len(),
                         expected: 3,
                     })? as usize;
-                if len <= u16::MAX as usize {
+                if !lenient && len <= u16::MAX as usize {
                     return Err(Error::BadEncode(format!(
                         "Got Ext24 marker with length = {}. This is not the shortest encoding.",
                         len
@@ -1042,9 +1046,14 @@ impl<'a> std::iter::Iterator for Parser<'a> {
         if self.errored {
             return None;
         }
-        let (&marker, data) = self.data.split_first()?;
-        self.data = data;
-        let result = self.parse_element(Marker::from_u8(marker));
+        let result = if let Some((elem, elems)) = self.elems.split_first() {
+            self.elems = elems;
+            self.track_element(elem.clone())
+        } else {
+            let (&marker, data) = self.data.split_first()?;
+            self.data = data;
+            self.parse_element(Marker::from_u8(marker))
+        };
         if result.is_err() {
             self.errored = true;
         }
@@ -1052,10 +1061,82 @@ impl<'a> std::iter::Iterator for Parser<'a> {
     }
 }
 
+/// Incremental checker for fog-pack data that arrives a piece at a time, like the output of a
+/// decompressor. Each call to [`feed`][Self::feed] parses every element that has fully arrived,
+/// carrying the nesting depth over between calls, so malformed data is caught as soon as it shows
+/// up instead of after all of it is ready.
+///
+/// Elements cut off at the end of the data are left for the next call. Whether the data is
+/// complete, and whether it's valid for a schema, is still up to a [`Parser`] run on the result.
+#[derive(Clone, Debug)]
+pub(crate) struct Scanner {
+    depth_tracking: DepthTracker,
+    raw_floats: bool,
+    checked: usize,
+}
+
+impl Scanner {
+    /// Create a new scanner, with the same float and nesting depth settings as the [`Parser`] that
+    /// will run on the completed data.
+    pub(crate) fn new(raw_floats: bool, max_depth: usize) -> Self {
+        Self {
+            depth_tracking: DepthTracker::with_limit(max_depth),
+            raw_floats,
+            checked: 0,
+        }
+    }
+
+    /// Check every complete element in `data` that hasn't been checked yet. `data` must start with
+    /// all of the data previously given to this scanner.
+    pub(crate) fn feed(&mut self, data: &[u8]) -> Result<()> {
+        let mut rest = &data[self.checked..];
+        while let Some((&marker, mut next)) = rest.split_first() {
+            match Parser::get_element(&mut next, Marker::from_u8(marker), self.raw_floats, false) {
+                Ok(elem) => self.depth_tracking.update_elem(&elem)?,
+                Err(Error::LengthTooShort { .. }) => break,
+                Err(e) => return Err(e),
+            }
+            self.checked = data.len() - next.len();
+            rest = next;
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn scanner() {
+        let mut enc = Vec::new();
+        serialize_elem(&mut enc, Element::Array(2));
+        serialize_elem(&mut enc, Element::Str("hello world"));
+        let split = enc.len();
+        serialize_elem(&mut enc, Element::Array(1));
+        serialize_elem(&mut enc, Element::Null);
+
+        // Feeding the data in pieces accepts everything that's complete so far
+        let mut scanner = Scanner::new(false, crate::MAX_DEPTH);
+        for end in 1..=enc.len() {
+            scanner.feed(&enc[..end]).unwrap();
+        }
+        assert_eq!(scanner.checked, enc.len());
+
+        // Bad data fails as soon as it arrives
+        let mut bad = enc[..split].to_vec();
+        bad.push(0xc1);
+        bad.extend_from_slice(&enc[split..]);
+        let mut scanner = Scanner::new(false, crate::MAX_DEPTH);
+        scanner.feed(&bad[..split]).unwrap();
+        scanner.feed(&bad[..split + 1]).unwrap_err();
+
+        // Nesting depth carries over between pieces
+        let mut scanner = Scanner::new(false, 1);
+        scanner.feed(&enc[..1]).unwrap();
+        scanner.feed(&enc[..enc.len() - 1]).unwrap_err();
+    }
+
     #[test]
     fn reserved() {
         let data = [0xc1, 0x00, 0xdd, 0x00, 0xde, 0x00, 0xdf, 0x00];
@@ -1444,6 +1525,26 @@ mod test {
             );
         }
 
+        #[test]
+        fn non_canonical() {
+            for case in [f32::from_bits(0x7fc0_0001u32), -0.0] {
+                let mut enc = Vec::new();
+                serialize_elem(&mut enc, Element::F32(case));
+                let mut parser = Parser::new(enc.as_ref());
+                parser.next().unwrap().unwrap_err();
+                let mut parser = Parser::new(enc.as_ref()).raw_floats(true);
+                match parser.next().unwrap().unwrap() {
+                    Element::F32(v) => assert_eq!(v.to_bits(), case.to_bits()),
+                    _ => panic!("Element wasn't F32"),
+                }
+            }
+            assert_eq!(canonical_f32(-0.0).to_bits(), 0);
+            assert_eq!(
+                canonical_f32(f32::from_bits(0x7fc0_0001u32)).to_bits(),
+                f32::NAN.to_bits()
+            );
+        }
+
         #[test]
         fn spec() {
             let mut test_cases: Vec<(f32, Vec<u8>)> = Vec::new();
@@ -1516,6 +1617,26 @@ mod test {
             );
         }
 
+        #[test]
+        fn non_canonical() {
+            for case in [f64::from_bits(0x7ff8_0000_0000_0001u64), -0.0] {
+                let mut enc = Vec::new();
+                serialize_elem(&mut enc, Element::F64(case));
+                let mut parser = Parser::new(enc.as_ref());
+                parser.next().unwrap().unwrap_err();
+                let mut parser = Parser::new(enc.as_ref()).raw_floats(true);
+                match parser.next().unwrap().unwrap() {
+                    Element::F64(v) => assert_eq!(v.to_bits(), case.to_bits()),
+                    _ => panic!("Element wasn't F64"),
+                }
+            }
+            assert_eq!(canonical_f64(-0.0).to_bits(), 0);
+            assert_eq!(
+                canonical_f64(f64::from_bits(0x7ff8_0000_0000_0001u64)).to_bits(),
+                f64::NAN.to_bits()
+            );
+        }
+
         #[test]
         fn spec() {
             let mut test_cases: Vec<(f64, Vec<u8>)> = Vec::new();
@@ -1648,6 +1769,28 @@ mod test {
         use super::*;
         use rand::prelude::*;
 
+        #[test]
+        fn invalid_utf8() {
+            let mut long = vec![0xd4, 0xff];
+            long.extend(std::iter::repeat(b'a').take(200));
+            long.extend_from_slice("é".as_bytes());
+            long.extend(std::iter::repeat(b'a').take(53));
+            let mut parser = Parser::new(&long);
+            assert!(matches!(parser.next(), Some(Ok(Element::Str(_)))));
+
+            // Invalid bytes, a broken two-byte character, and a truncated one at the end
+            for (i, byte) in [(2usize, 0xffu8), (202, 0xff), (203, b'a'), (256, 0xc3)] {
+                let mut case = long.clone();
+                case[i] = byte;
+                let mut parser = Parser::new(&case);
+                assert!(
+                    matches!(parser.next(), Some(Err(Error::BadEncode(_)))),
+                    "Invalid UTF-8 at byte {} should fail",
+                    i
+                );
+            }
+        }
+
         #[test]
         fn roundtrip() {
             // Run through the boundary cases
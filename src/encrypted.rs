@@ -0,0 +1,418 @@
+//! Encrypted documents.
+//!
+//! An [`EncryptedDocument`] wraps a complete, encoded [`Document`] inside a
+//! [`DataLockbox`][fog_crypto::lockbox::DataLockbox], and stores it in a wrapper document that
+//! adheres to the standard [`encrypted_schema`]. The wrapper can be stored and transferred like
+//! any other document, while only holders of the right key can recover the original.
+//!
+//! Decrypting gives back the original document, verified exactly as if it had been decoded
+//! directly. As the plaintext document is unchanged by the round trip, its hash is preserved, and
+//! can still be used to address the plaintext content.
+//!
+//! ```
+//! # use fog_pack::{document::*, encrypted::*, schema::*, types::StreamKey};
+//! # fn main() -> fog_pack::error::Result<()> {
+//! let key = StreamKey::new();
+//! let doc = NoSchema::validate_new_doc(NewDocument::new(None, "private")?)?;
+//!
+//! let encrypted = EncryptedDocument::new_stream(&doc, &key)?;
+//! let (_, encoded) = encrypted_schema().encode_doc(encrypted.into_document())?;
+//!
+//! let encrypted = EncryptedDocument::from_document(encrypted_schema().decode_doc(encoded)?)?;
+//! let decrypted = encrypted.decrypt_stream(&key, None)?;
+//! assert_eq!(decrypted.hash(), doc.hash());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! # Field-level Encryption
+//!
+//! Rather than encrypting a whole document, a schema can mark individual fields as encrypted with
+//! [`SchemaBuilder::encrypted_field`]. Each encrypted field holds a `DataLockbox` with the field's
+//! actual value inside, letting a single document mix public and private data.
+//! [`encrypt_fields`] builds a new document, putting each encrypted field in a lockbox, and
+//! [`decrypt_fields`] opens them again. Decryption isn't part of deserialization itself: the
+//! document is first decoded into a [`Value`], each lockbox in it is replaced by its decrypted
+//! contents, and the result is re-encoded and deserialized into the target type. This costs a few
+//! extra copies of the data over [`Document::deserialize`], and the target type can't borrow from
+//! the document.
+
+use crate::{
+    de::FogDeserializer,
+    document::{Document, NewDocument},
+    error::{Error, Result, ValidateError},
+    schema::{Compress, NoSchema, Schema, SchemaBuilder},
+    ser::FogSerializer,
+    signer::Signer,
+    types::{Path, Value},
+    validator::{DataLockboxValidator, MapValidator},
+};
+use fog_crypto::{
+    lock::{LockId, LockKey},
+    lockbox::{DataLockbox, DataLockboxRef},
+    stream::StreamKey,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::sync::OnceLock;
+
+static SCHEMA: OnceLock<Schema> = OnceLock::new();
+
+/// Get the standard schema used by all encrypted document wrappers.
+pub fn encrypted_schema() -> &'static Schema {
+    SCHEMA.get_or_init(|| {
+        let doc = SchemaBuilder::new(
+            MapValidator::new()
+                .req_add("data", DataLockboxValidator::new().build())
+                .build(),
+        )
+        .name("fog-pack encrypted document")
+        .description("A fog-pack document, encrypted and stored in a lockbox")
+        .doc_compress(Compress::None)
+        .build()
+        .expect("the encrypted document schema should always be valid");
+        Schema::from_doc(&doc).expect("the encrypted document schema should always be valid")
+    })
+}
+
+#[derive(Serialize, Deserialize)]
+struct Wrapper<T> {
+    data: T,
+}
+
+/// A document that has been encrypted into a wrapper document.
+///
+/// The wrapper always adheres to the [`encrypted_schema`], and should be encoded & decoded with
+/// it.
+#[derive(Clone, Debug)]
+pub struct EncryptedDocument(Document);
+
+impl EncryptedDocument {
+    /// Encrypt a document with a symmetric key. Fails if the resulting wrapper document would be
+    /// larger than the maximum allowed document size.
+    #[cfg(feature = "getrandom")]
+    pub fn new_stream(doc: &Document, key: &StreamKey) -> Result<Self> {
+        Self::wrap(key.encrypt_data(&Self::plaintext(doc)))
+    }
+
+    /// Encrypt a document for the holder of a [`LockKey`]. Fails if the resulting wrapper
+    /// document would be larger than the maximum allowed document size.
+    #[cfg(feature = "getrandom")]
+    pub fn new_lock(doc: &Document, id: &LockId) -> Result<Self> {
+        Self::wrap(id.encrypt_data(&Self::plaintext(doc)))
+    }
+
+    // The document is encrypted in its complete, uncompressed form
+    #[cfg(feature = "getrandom")]
+    fn plaintext(doc: &Document) -> Vec<u8> {
+        let (_, buf, _) = doc.clone().complete();
+        buf
+    }
+
+    #[cfg(feature = "getrandom")]
+    fn wrap(lockbox: DataLockbox) -> Result<Self> {
+        let schema = encrypted_schema();
+        let doc = NewDocument::new(Some(schema.hash()), Wrapper { data: lockbox })?;
+        Ok(Self(schema.validate_new_doc(doc)?))
+    }
+
+    /// Load an encrypted document from its wrapper document. Fails if the document doesn't use
+    /// the [`encrypted_schema`].
+    pub fn from_document(doc: Document) -> Result<Self> {
+        let schema = encrypted_schema();
+        match doc.schema_hash() {
+            Some(hash) if hash == schema.hash() => Ok(Self(doc)),
+            actual => Err(Error::SchemaMismatch {
+                actual: actual.cloned(),
+                expected: Some(schema.hash().clone()),
+            }),
+        }
+    }
+
+    /// Get the wrapper document.
+    pub fn document(&self) -> &Document {
+        &self.0
+    }
+
+    /// Get the wrapper document, so it can be encoded with the [`encrypted_schema`].
+    pub fn into_document(self) -> Document {
+        self.0
+    }
+
+    /// Sign the wrapper document, or replace the existing signature if one exists already. This
+    /// doesn't change the encrypted document, which may have its own signature.
+    pub fn sign<S: Signer + ?Sized>(self, key: &S) -> Result<Self> {
+        Ok(Self(self.0.sign(key)?))
+    }
+
+    /// Decrypt the document with a symmetric key, then decode and verify it with the provided
+    /// schema, or without one if it is `None`.
+    pub fn decrypt_stream(&self, key: &StreamKey, schema: Option<&Schema>) -> Result<Document> {
+        let plaintext = key.decrypt_data(&self.lockbox()?.data)?;
+        Self::decode(plaintext, schema)
+    }
+
+    /// Decrypt the document with a [`LockKey`], then decode and verify it with the provided
+    /// schema, or without one if it is `None`.
+    pub fn decrypt_lock(&self, key: &LockKey, schema: Option<&Schema>) -> Result<Document> {
+        let plaintext = key.decrypt_data(&self.lockbox()?.data)?;
+        Self::decode(plaintext, schema)
+    }
+
+    fn lockbox(&self) -> Result<Wrapper<DataLockbox>> {
+        self.0.deserialize()
+    }
+
+    fn decode(plaintext: Vec<u8>, schema: Option<&Schema>) -> Result<Document> {
+        match schema {
+            Some(schema) => schema.decode_doc(plaintext),
+            None => NoSchema::decode_doc(plaintext),
+        }
+    }
+}
+
+/// A key to encrypt fields for, used by [`encrypt_fields`].
+#[derive(Clone, Copy, Debug)]
+pub enum EncryptKey<'a> {
+    /// Encrypt with a symmetric key
+    Stream(&'a StreamKey),
+    /// Encrypt for the holder of the matching [`LockKey`]
+    Lock(&'a LockId),
+}
+
+impl EncryptKey<'_> {
+    #[cfg(feature = "getrandom")]
+    fn encrypt(&self, data: &[u8]) -> DataLockbox {
+        match self {
+            EncryptKey::Stream(key) => key.encrypt_data(data),
+            EncryptKey::Lock(id) => id.encrypt_data(data),
+        }
+    }
+}
+
+/// A key to decrypt fields with, used by [`decrypt_fields`].
+#[derive(Clone, Copy, Debug)]
+pub enum DecryptKey<'a> {
+    /// Decrypt with a symmetric key
+    Stream(&'a StreamKey),
+    /// Decrypt with a private [`LockKey`]
+    Lock(&'a LockKey),
+}
+
+impl DecryptKey<'_> {
+    fn decrypt(&self, lockbox: &DataLockboxRef) -> Result<Vec<u8>> {
+        Ok(match self {
+            DecryptKey::Stream(key) => key.decrypt_data(lockbox)?,
+            DecryptKey::Lock(key) => key.decrypt_data(lockbox)?,
+        })
+    }
+}
+
+// Sort paths so that fields nested inside other encrypted fields come first
+fn deepest_first(schema: &Schema) -> Vec<Path> {
+    let mut paths = schema.encrypted_fields();
+    paths.sort_by_key(|p| std::cmp::Reverse(p.len()));
+    paths
+}
+
+fn to_value<S: Serialize>(data: S) -> Result<Value> {
+    let mut ser = FogSerializer::from_vec(Vec::new(), false);
+    data.serialize(&mut ser)?;
+    let buf = ser.finish();
+    Value::deserialize(&mut FogDeserializer::new(&buf))
+}
+
+/// Create a new document adhering to the given schema, encrypting every field the schema marks
+/// as encrypted. Each field's value is encoded and placed in its own
+/// [`DataLockbox`][crate::types::DataLockbox]. Encrypted fields that aren't present in the data
+/// are skipped.
+///
+/// The resulting document still needs to be validated by the schema, like any other
+/// [`NewDocument`].
+#[cfg(feature = "getrandom")]
+pub fn encrypt_fields<S: Serialize>(
+    schema: &Schema,
+    data: S,
+    key: EncryptKey,
+) -> Result<NewDocument> {
+    let mut value = to_value(data)?;
+    for path in deepest_first(schema) {
+        if let Some(field) = path.get_mut(&mut value) {
+            let mut ser = FogSerializer::from_vec(Vec::new(), false);
+            field.serialize(&mut ser)?;
+            *field = Value::DataLockbox(key.encrypt(&ser.finish()));
+        }
+    }
+    NewDocument::new(Some(schema.hash()), value)
+}
+
+/// Deserialize a document adhering to the given schema, decrypting every field the schema marks
+/// as encrypted. Fails if the document doesn't use this schema, if any encrypted field isn't a
+/// [`DataLockbox`][crate::types::DataLockbox], or if any of them can't be decrypted with the
+/// key.
+///
+/// The fields are decrypted in an intermediate [`Value`], which is then re-encoded and
+/// deserialized, so this is slower than [`Document::deserialize`] and always produces an owned
+/// result. Fields the target type doesn't use are handled as set by
+/// [`Document::unknown_fields`].
+pub fn decrypt_fields<D: DeserializeOwned>(
+    schema: &Schema,
+    doc: &Document,
+    key: DecryptKey,
+) -> Result<D> {
+    match doc.schema_hash() {
+        Some(hash) if hash == schema.hash() => (),
+        actual => {
+            return Err(Error::SchemaMismatch {
+                actual: actual.cloned(),
+                expected: Some(schema.hash().clone()),
+            })
+        }
+    }
+
+    // Open outer lockboxes before any nested inside them
+    let mut value: Value = doc.deserialize()?;
+    let mut paths = deepest_first(schema);
+    paths.reverse();
+    for path in paths {
+        let Some(field) = path.get_mut(&mut value) else {
+            continue;
+        };
+        let Some(lockbox) = field.as_data_lockbox() else {
+            return Err(Error::FailValidate(ValidateError::Other(
+                format!("encrypted field {} doesn't hold a DataLockbox", path).into(),
+            )));
+        };
+        let plaintext = key.decrypt(lockbox)?;
+        *field = Value::deserialize(&mut FogDeserializer::new(&plaintext))?;
+    }
+
+    // Re-encode so the target type sees the same data a plain document would give it
+    let mut ser = FogSerializer::from_vec(Vec::new(), false);
+    value.serialize(&mut ser)?;
+    let buf = ser.finish();
+    D::deserialize(&mut FogDeserializer::with_unknown_fields(
+        &buf,
+        doc.unknown_fields(),
+    ))
+}
+
+#[cfg(all(test, feature = "getrandom"))]
+mod test {
+    use super::*;
+    use crate::validator::Validator;
+
+    #[test]
+    fn stream_roundtrip() {
+        let key = StreamKey::new();
+        let signer = fog_crypto::identity::IdentityKey::new();
+        let doc = NewDocument::new(None, "private")
+            .unwrap()
+            .sign(&signer)
+            .unwrap();
+        let doc = NoSchema::validate_new_doc(doc).unwrap();
+
+        let encrypted = EncryptedDocument::new_stream(&doc, &key)
+            .unwrap()
+            .sign(&signer)
+            .unwrap();
+        let (_, encoded) = encrypted_schema()
+            .encode_doc(encrypted.into_document())
+            .unwrap();
+        let wrapper = encrypted_schema().decode_doc(encoded).unwrap();
+        let encrypted = EncryptedDocument::from_document(wrapper).unwrap();
+        assert_eq!(encrypted.document().signer(), Some(signer.id()));
+
+        let decrypted = encrypted.decrypt_stream(&key, None).unwrap();
+        assert_eq!(decrypted.hash(), doc.hash());
+        assert_eq!(decrypted.signer(), Some(signer.id()));
+        let val: &str = decrypted.deserialize().unwrap();
+        assert_eq!(val, "private");
+
+        // The wrong key can't decrypt it
+        encrypted
+            .decrypt_stream(&StreamKey::new(), None)
+            .unwrap_err();
+    }
+
+    #[test]
+    fn lock_roundtrip() {
+        let key = LockKey::new();
+        let schema_doc = SchemaBuilder::new(Validator::Any).build().unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let doc = NewDocument::new(Some(schema.hash()), 5u8).unwrap();
+        let doc = schema.validate_new_doc(doc).unwrap();
+
+        let encrypted = EncryptedDocument::new_lock(&doc, key.id()).unwrap();
+        let decrypted = encrypted.decrypt_lock(&key, Some(&schema)).unwrap();
+        assert_eq!(decrypted.hash(), doc.hash());
+
+        // Decoding still checks the schema
+        encrypted.decrypt_lock(&key, None).unwrap_err();
+    }
+
+    #[test]
+    fn wrong_schema() {
+        let doc = NoSchema::validate_new_doc(NewDocument::new(None, 1u8).unwrap()).unwrap();
+        EncryptedDocument::from_document(doc).unwrap_err();
+    }
+
+    #[test]
+    fn field_encryption() {
+        use crate::validator::{IntValidator, StrValidator};
+        use std::collections::BTreeMap;
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Profile {
+            email: String,
+            name: String,
+            private: BTreeMap<String, u32>,
+        }
+
+        let secret = || DataLockboxValidator::new().build();
+        let schema_doc = SchemaBuilder::new(
+            MapValidator::new()
+                .req_add("email", secret())
+                .req_add("name", StrValidator::new().build())
+                .req_add(
+                    "private",
+                    MapValidator::new()
+                        .opt_add("age", IntValidator::new().build())
+                        .opt_add("pin", secret())
+                        .build(),
+                )
+                .build(),
+        )
+        .encrypted_field(&Path::new().key("email"))
+        .encrypted_field(&Path::new().key("private").key("pin"))
+        .build()
+        .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        assert_eq!(schema.encrypted_fields().len(), 2);
+
+        let profile = Profile {
+            email: "me@example.com".into(),
+            name: "Me".into(),
+            private: [("age".to_owned(), 30), ("pin".to_owned(), 1234)]
+                .into_iter()
+                .collect(),
+        };
+        let key = StreamKey::new();
+        let doc = encrypt_fields(&schema, &profile, EncryptKey::Stream(&key)).unwrap();
+        let doc = schema.validate_new_doc(doc).unwrap();
+
+        // The plaintext isn't visible without the key
+        let raw: Value = doc.deserialize().unwrap();
+        assert!(raw["email"].is_data_lockbox());
+        assert!(raw["private"]["pin"].is_data_lockbox());
+        assert_eq!(raw["name"], Value::from("Me"));
+
+        let dec: Profile = decrypt_fields(&schema, &doc, DecryptKey::Stream(&key)).unwrap();
+        assert_eq!(dec, profile);
+        decrypt_fields::<Profile>(&schema, &doc, DecryptKey::Stream(&StreamKey::new()))
+            .unwrap_err();
+
+        // Plaintext in an encrypted field fails schema validation
+        let doc = NewDocument::new(Some(schema.hash()), &profile).unwrap();
+        schema.validate_new_doc(doc).unwrap_err();
+    }
+}
@@ -4,20 +4,24 @@
 //! document, and the key string. Once created, they can be signed and have their compression
 //! settings chosen. Entries (new or otherwise) are verified and encoded using a
 //! [`Schema`][crate::schema::Schema], which should match the schema used by the parent document.
+//! Encoded entries can be inspected in place with an [`EntryView`].
 
-use crate::error::{Error, Result};
+use crate::error::{Error, Result, ValidateError};
 use crate::{
     compress::CompressType,
     de::FogDeserializer,
-    document::Document,
+    document::{Document, EncodeOptions, PendingSignature, Trust, UnknownFields, VERSION_MARKER},
     element::{serialize_elem, Element},
     ser::FogSerializer,
-    MAX_ENTRY_SIZE,
+    signer::{AsyncSigner, Signer},
+    types::{Path, Value, ValueRef},
+    writer::ElementWriter,
+    VersionPolicy, MAX_ENTRY_SIZE,
 };
 use byteorder::{LittleEndian, ReadBytesExt};
 use fog_crypto::{
     hash::{Hash, HashState},
-    identity::{Identity, IdentityKey},
+    identity::{Identity, IdentityKey, Signature, UnverifiedSignature},
 };
 use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
@@ -67,7 +71,13 @@ impl<'a> SplitEntry<'a> {
 /// key string for the entry, and the hash of the entry itself. Note that the entry hash is still
 /// formed in a way the includes the parent & key, so changing either means the entry hash would
 /// also change.
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+///
+/// References order by parent document hash, then key, then entry hash, so all entries under one
+/// document and key sort next to each other. Hashes order by their encoded bytes. This ordering,
+/// along with the serialized form, only depends on the encoding of hashes, which is fixed by the
+/// fog-pack format, so it stays the same across crate versions and can be relied on by persistent
+/// indexes.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
 pub struct EntryRef {
     /// Hash of the parent document
     pub parent: Hash,
@@ -92,7 +102,14 @@ struct EntryInner {
     id: EntryRef,
     schema_hash: Hash,
     signer: Option<Identity>,
+    /// False only if the entry has a signature that hasn't been checked yet
+    verified: bool,
     set_compress: Option<Option<u8>>,
+    format_version: u8,
+    unknown_fields: UnknownFields,
+    /// True only if the data is known to have passed validation, which lets deserialization skip
+    /// some checks
+    validated: bool,
 }
 
 impl EntryInner {
@@ -133,8 +150,8 @@ impl EntryInner {
 
     /// Deserialize the entry's contained data into a value.
     fn deserialize<'de, D: Deserialize<'de>>(&'de self) -> Result<D> {
-        let buf = self.data();
-        let mut de = FogDeserializer::new(buf);
+        let mut de = FogDeserializer::with_unknown_fields(self.data(), self.unknown_fields)
+            .validated(self.validated);
         D::deserialize(&mut de)
     }
 
@@ -159,7 +176,30 @@ impl EntryInner {
     /// Sign the entry, or or replace the existing signature if one exists already. Fails if the
     /// signature would grow the entry size beyond the maximum allowed. In the event of a failure.
     /// the entry is dropped.
-    fn sign(mut self, key: &IdentityKey) -> Result<Self> {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(hash = %self.id.hash))
+    )]
+    fn sign<S: Signer + ?Sized>(mut self, key: &S) -> Result<Self> {
+        let (pre_sign_len, entry_hash) = self.prepare_sign();
+        let signature = Signer::sign(key, &entry_hash)?;
+        self.finish_sign(pre_sign_len, &entry_hash, signature)
+    }
+
+    /// Sign the entry asynchronously, like [`sign`][Self::sign].
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(hash = %self.id.hash))
+    )]
+    async fn sign_async<S: AsyncSigner + ?Sized>(mut self, key: &S) -> Result<Self> {
+        let (pre_sign_len, entry_hash) = self.prepare_sign();
+        let signature = AsyncSigner::sign(key, &entry_hash).await?;
+        self.finish_sign(pre_sign_len, &entry_hash, signature)
+    }
+
+    /// Load up the hash state for signing. Returns the length of the entry without a signature,
+    /// and the hash to be signed.
+    fn prepare_sign(&mut self) -> (usize, Hash) {
         // If a signature already exists, reload the hash state
         let pre_sign_len = if self.signer.is_some() {
             let split = SplitEntry::split(&self.buf).unwrap();
@@ -180,15 +220,27 @@ impl EntryInner {
             let state = Self::setup_hash_state(self.id.parent.clone(), &self.id.key, split.data);
             self.hash_state = Some(state);
         }
-        let hash_state = self.hash_state.as_mut().unwrap();
 
         // Hash state does not yet contain the signature - thus, it holds the hash we're going to
         // sign
-        let entry_hash = hash_state.hash();
+        let entry_hash = self.hash_state.as_ref().unwrap().hash();
+        (pre_sign_len, entry_hash)
+    }
+
+    /// Attach a signature of `entry_hash`, replacing any existing one. Fails if the signature
+    /// isn't actually of `entry_hash`, as signers can be arbitrary outside code.
+    fn finish_sign(
+        mut self,
+        pre_sign_len: usize,
+        entry_hash: &Hash,
+        signature: Signature,
+    ) -> Result<Self> {
+        let mut raw = Vec::with_capacity(signature.size());
+        signature.encode_vec(&mut raw);
+        let signature = UnverifiedSignature::try_from(raw.as_slice())?.verify(entry_hash)?;
 
-        // Sign and check for size violation
-        let signature = key.sign(&entry_hash);
-        let new_len = pre_sign_len + signature.size();
+        // Check for size violation
+        let new_len = pre_sign_len + raw.len();
         if new_len > MAX_ENTRY_SIZE {
             return Err(Error::LengthTooLong {
                 max: MAX_ENTRY_SIZE,
@@ -197,11 +249,13 @@ impl EntryInner {
         }
 
         // Append the signature and update the hasher
+        let hash_state = self.hash_state.as_mut().unwrap();
         self.buf.resize(pre_sign_len, 0);
-        signature.encode_vec(&mut self.buf);
+        self.buf.extend_from_slice(&raw);
         hash_state.update(&self.buf[pre_sign_len..]);
         self.id.hash = hash_state.hash();
-        self.signer = Some(key.id().clone());
+        self.signer = Some(signature.signer().clone());
+        self.verified = true;
         Ok(self)
     }
 
@@ -218,10 +272,16 @@ impl EntryInner {
 pub struct NewEntry(EntryInner);
 
 impl NewEntry {
-    fn new_from<F>(key: &str, parent: &Document, encoder: F) -> Result<Self>
+    pub(crate) fn new_from<F>(key: &str, parent: &Document, encoder: F) -> Result<Self>
     where
         F: FnOnce(Vec<u8>) -> Result<Vec<u8>>,
     {
+        if key.starts_with(crate::RESERVED_KEY_PREFIX) {
+            return Err(Error::FailValidate(ValidateError::Other(
+                format!("entry key {:?} uses a reserved prefix", key).into(),
+            )));
+        }
+
         // Serialize the data
         let buf: Vec<u8> = vec![CompressType::None.into(), 0u8, 0u8];
         let mut buf = encoder(buf)?;
@@ -245,9 +305,9 @@ impl NewEntry {
         let schema_hash = match parent.schema_hash() {
             Some(h) => h.clone(),
             None => {
-                return Err(Error::FailValidate(
+                return Err(Error::FailValidate(ValidateError::Other(
                     "Entries can only be created for documents that use a schema.".into(),
-                ))
+                )))
             }
         };
 
@@ -261,10 +321,26 @@ impl NewEntry {
             },
             schema_hash,
             signer: None,
+            verified: true,
             set_compress: None,
+            format_version: crate::FORMAT_VERSION,
+            unknown_fields: UnknownFields::Ignore,
+            validated: false,
         }))
     }
 
+    /// Create a new Entry holding the same data as a document, with a key and the parent
+    /// document. The data is copied over as-is, without being decoded and re-encoded. The
+    /// document's signature isn't carried over, and the entry's hash has nothing to do with the
+    /// document's. Fails if the data is too large for an entry; see
+    /// [`MAX_ENTRY_SIZE`][crate::MAX_ENTRY_SIZE].
+    pub fn from_doc(key: &str, parent: &Document, doc: &Document) -> Result<Self> {
+        Self::new_from(key, parent, |mut buf| {
+            buf.extend_from_slice(doc.data());
+            Ok(buf)
+        })
+    }
+
     /// Create a new Entry from any serializable data, a key, and the Hash of the parent document.
     pub fn new<S: Serialize>(key: &str, parent: &Document, data: S) -> Result<Self> {
         Self::new_from(key, parent, |buf| {
@@ -275,6 +351,32 @@ impl NewEntry {
         })
     }
 
+    /// Create a new Entry from any serializable data, a key, and the Hash of the parent document,
+    /// using the provided encoding options.
+    pub fn new_with_options<S: Serialize>(
+        key: &str,
+        parent: &Document,
+        data: S,
+        options: &EncodeOptions,
+    ) -> Result<Self> {
+        Self::new_from(key, parent, |buf| {
+            // Serialize the data
+            let mut ser = options.serializer(buf);
+            data.serialize(&mut ser)?;
+            Ok(ser.finish())
+        })
+    }
+
+    /// Create a new Entry from a key, the parent document, and data written with an
+    /// [`ElementWriter`]. Fails if the writer doesn't hold a complete value.
+    pub fn from_writer(key: &str, parent: &Document, writer: ElementWriter) -> Result<Self> {
+        let data = writer.finish()?;
+        Self::new_from(key, parent, |mut buf| {
+            buf.extend_from_slice(&data);
+            Ok(buf)
+        })
+    }
+
     /// Create a new Entry from a key, the Hash of the parent document, and any serializable data
     /// whose keys are all ordered. For structs, this means all fields are declared in
     /// lexicographic order. For maps, this means a `BTreeMap` type must be used, whose keys are
@@ -297,11 +399,17 @@ impl NewEntry {
     }
 
     /// Sign the document, or or replace the existing signature if one exists already. Fails if the
-    /// signature would grow the document size beyond the maximum allowed.
-    pub fn sign(self, key: &IdentityKey) -> Result<Self> {
+    /// signature would grow the document size beyond the maximum allowed, or if the signer fails.
+    pub fn sign<S: Signer + ?Sized>(self, key: &S) -> Result<Self> {
         Ok(Self(self.0.sign(key)?))
     }
 
+    /// Sign the entry with an asynchronous signer, like a remote signing service. Otherwise
+    /// identical to [`sign`][Self::sign].
+    pub async fn sign_async<S: AsyncSigner + ?Sized>(self, key: &S) -> Result<Self> {
+        Ok(Self(self.0.sign_async(key).await?))
+    }
+
     /// Get what the document's hash will be, given its current state
     pub fn hash(&self) -> &Hash {
         self.0.hash()
@@ -311,6 +419,17 @@ impl NewEntry {
         self.0.data()
     }
 
+    pub(crate) fn size(&self) -> usize {
+        self.0.buf.len()
+    }
+
+    /// Get how many more bytes the entry could hold before reaching
+    /// [`MAX_ENTRY_SIZE`][crate::MAX_ENTRY_SIZE]. If the entry isn't signed yet, a signature will
+    /// still need to fit in this space; see [`IdentityKey::max_signature_size`].
+    pub fn remaining_capacity(&self) -> usize {
+        MAX_ENTRY_SIZE.saturating_sub(self.0.buf.len())
+    }
+
     /// Get the hash of the Entry's parent [`Document`][crate::document::Document].
     pub fn parent(&self) -> &Hash {
         self.0.parent()
@@ -345,6 +464,19 @@ impl Entry {
         Self(entry.0)
     }
 
+    /// Check if some data would fit in an entry, after being signed by `signer` if one is given.
+    /// This serializes the data to find its size, so it's a cheap way to decide whether data
+    /// needs to be split across several entries before building any of them. Returns false if
+    /// the data can't be serialized.
+    pub fn fits<T: Serialize + ?Sized>(data: &T, signer: Option<&IdentityKey>) -> bool {
+        let mut ser = FogSerializer::default();
+        if data.serialize(&mut ser).is_err() {
+            return false;
+        }
+        let sign_len = signer.map_or(0, |k| k.max_signature_size());
+        ENTRY_PREFIX_LEN + ser.finish().len() + sign_len <= MAX_ENTRY_SIZE
+    }
+
     pub(crate) fn trusted_new(
         buf: Vec<u8>,
         key: &str,
@@ -361,8 +493,7 @@ impl Entry {
         let split = SplitEntry::split(&buf)?;
 
         let signer = if !split.signature_raw.is_empty() {
-            let unverified =
-                fog_crypto::identity::UnverifiedSignature::try_from(split.signature_raw)?;
+            let unverified = UnverifiedSignature::try_from(split.signature_raw)?;
             Some(unverified.signer().clone())
         } else {
             None
@@ -371,9 +502,9 @@ impl Entry {
         let schema_hash = match parent.schema_hash() {
             Some(h) => h.clone(),
             None => {
-                return Err(Error::FailValidate(
+                return Err(Error::FailValidate(ValidateError::Other(
                     "Entries can only be created for documents that use a schema.".into(),
-                ))
+                )))
             }
         };
 
@@ -387,11 +518,46 @@ impl Entry {
             },
             schema_hash,
             signer,
+            verified: true,
             set_compress: None,
+            format_version: crate::FORMAT_VERSION,
+            unknown_fields: UnknownFields::Ignore,
+            validated: false,
         }))
     }
 
     pub(crate) fn new(buf: Vec<u8>, key: &str, parent: &Document) -> Result<Self> {
+        let (entry, pending) = Self::new_unverified(buf, key, parent)?;
+        if let Some((hash, unverified)) = pending {
+            unverified.verify(&hash)?;
+        }
+        Ok(entry)
+    }
+
+    /// Create the entry from a raw byte vec, without verifying it or its signature. The
+    /// signature, if there is one, is returned along with the hash it must be verified against.
+    pub(crate) fn new_unverified(
+        buf: Vec<u8>,
+        key: &str,
+        parent: &Document,
+    ) -> Result<(Self, Option<PendingSignature>)> {
+        let schema_hash = match parent.schema_hash() {
+            Some(h) => h.clone(),
+            None => {
+                return Err(Error::FailValidate(ValidateError::Other(
+                    "Entries can only be created for documents that use a schema.".into(),
+                )))
+            }
+        };
+        Self::from_parts_unverified(buf, key, parent.hash(), schema_hash)
+    }
+
+    pub(crate) fn from_parts_unverified(
+        buf: Vec<u8>,
+        key: &str,
+        parent: &Hash,
+        schema_hash: Hash,
+    ) -> Result<(Self, Option<PendingSignature>)> {
         if buf.len() > MAX_ENTRY_SIZE {
             return Err(Error::LengthTooLong {
                 max: MAX_ENTRY_SIZE,
@@ -401,49 +567,155 @@ impl Entry {
 
         let split = SplitEntry::split(&buf)?;
 
-        let mut hash_state = EntryInner::setup_hash_state(parent.hash().clone(), key, split.data);
+        let mut hash_state = EntryInner::setup_hash_state(parent.clone(), key, split.data);
         let entry_hash = hash_state.hash();
         if !split.signature_raw.is_empty() {
             hash_state.update(split.signature_raw);
         }
         let this_hash = hash_state.hash();
 
-        let signer = if !split.signature_raw.is_empty() {
-            let unverified =
-                fog_crypto::identity::UnverifiedSignature::try_from(split.signature_raw)?;
-            let verified = unverified.verify(&entry_hash)?;
-            Some(verified.signer().clone())
+        let (signer, pending) = if !split.signature_raw.is_empty() {
+            let unverified = UnverifiedSignature::try_from(split.signature_raw)?;
+            let signer = unverified.signer().clone();
+            (Some(signer), Some((entry_hash, unverified)))
         } else {
-            None
+            (None, None)
         };
 
-        let schema_hash = match parent.schema_hash() {
-            Some(h) => h.clone(),
-            None => {
-                return Err(Error::FailValidate(
-                    "Entries can only be created for documents that use a schema.".into(),
-                ))
-            }
-        };
-
-        Ok(Self(EntryInner {
+        let entry = Self(EntryInner {
             buf,
             hash_state: Some(hash_state),
             id: EntryRef {
-                parent: parent.hash().to_owned(),
+                parent: parent.clone(),
                 key: key.to_owned(),
                 hash: this_hash,
             },
             schema_hash,
             signer,
+            verified: true,
             set_compress: None,
-        }))
+            format_version: crate::FORMAT_VERSION,
+            unknown_fields: UnknownFields::Ignore,
+            validated: false,
+        });
+        Ok((entry, pending))
+    }
+
+    /// Break the entry down into its reference, the hash of its parent document's schema, its
+    /// raw data, and its signer. The raw data is the entry's uncompressed internal form, not its
+    /// encoding. Use [`from_raw_parts`][Self::from_raw_parts] to rebuild the entry.
+    pub fn into_raw_parts(self) -> (EntryRef, Hash, Vec<u8>, Option<Identity>) {
+        (self.0.id, self.0.schema_hash, self.0.buf, self.0.signer)
+    }
+
+    /// Rebuild an entry from the parts returned by [`into_raw_parts`][Self::into_raw_parts],
+    /// without going through a schema or needing the parent document. Fails if the raw data is
+    /// malformed or doesn't match the signer. With [`Trust::Verify`], the entry's hash is
+    /// recomputed and checked and its signature is verified; with [`Trust::Trusted`], the
+    /// reference is taken as given and no hashing is done.
+    pub fn from_raw_parts(
+        reference: EntryRef,
+        schema_hash: Hash,
+        raw: Vec<u8>,
+        signer: Option<Identity>,
+        trust: Trust,
+    ) -> Result<Self> {
+        let entry = match trust {
+            Trust::Verify => {
+                let (entry, pending) = Self::from_parts_unverified(
+                    raw,
+                    &reference.key,
+                    &reference.parent,
+                    schema_hash,
+                )?;
+                if entry.hash() != &reference.hash {
+                    return Err(Error::FailValidate(ValidateError::Other(
+                        format!("raw entry data doesn't match the hash {}", reference.hash).into(),
+                    )));
+                }
+                if let Some((hash, unverified)) = pending {
+                    unverified.verify(&hash)?;
+                }
+                entry
+            }
+            Trust::Trusted => {
+                if raw.len() > MAX_ENTRY_SIZE {
+                    return Err(Error::LengthTooLong {
+                        max: MAX_ENTRY_SIZE,
+                        actual: raw.len(),
+                    });
+                }
+                let split = SplitEntry::split(&raw)?;
+                let raw_signer = if !split.signature_raw.is_empty() {
+                    Some(
+                        UnverifiedSignature::try_from(split.signature_raw)?
+                            .signer()
+                            .clone(),
+                    )
+                } else {
+                    None
+                };
+                Self(EntryInner {
+                    buf: raw,
+                    hash_state: None,
+                    id: reference,
+                    schema_hash,
+                    verified: raw_signer.is_none(),
+                    signer: raw_signer,
+                    set_compress: None,
+                    format_version: crate::FORMAT_VERSION,
+                    unknown_fields: UnknownFields::Ignore,
+                    validated: false,
+                })
+            }
+        };
+        if entry.signer() != signer.as_ref() {
+            return Err(Error::FailValidate(ValidateError::Other(
+                "raw entry data doesn't match the signer".into(),
+            )));
+        }
+        Ok(entry)
+    }
+
+    /// Check if the entry's signature has been verified. This is only false for signed entries
+    /// rebuilt with [`from_raw_parts`][Self::from_raw_parts] using [`Trust::Trusted`], or decoded
+    /// through a [`TrustedSchema`][crate::schema::TrustedSchema], that haven't had
+    /// [`verify`][Self::verify] called on them yet.
+    pub fn is_verified(&self) -> bool {
+        self.0.verified
+    }
+
+    /// Verify the entry's signature, if it hasn't been already. Fails if the signature is
+    /// invalid, in which case the entry is dropped. Entries are always verified before being
+    /// encoded by a schema.
+    pub fn verify(mut self) -> Result<Self> {
+        if !self.0.verified {
+            let split = SplitEntry::split(&self.0.buf)?;
+            let entry_hash =
+                EntryInner::setup_hash_state(self.0.id.parent.clone(), &self.0.id.key, split.data)
+                    .hash();
+            UnverifiedSignature::try_from(split.signature_raw)?.verify(&entry_hash)?;
+            self.0.verified = true;
+        }
+        Ok(self)
+    }
+
+    pub(crate) fn set_verified(&mut self, verified: bool) {
+        self.0.verified = verified;
     }
 
     pub(crate) fn data(&self) -> &[u8] {
         self.0.data()
     }
 
+    pub(crate) fn size(&self) -> usize {
+        self.0.buf.len()
+    }
+
+    pub(crate) fn raw(&self) -> &[u8] {
+        &self.0.buf
+    }
+
     /// Find all hashes in this entry and return them.
     pub fn find_hashes(&self) -> Vec<Hash> {
         crate::find_hashes(self.data())
@@ -454,12 +726,46 @@ impl Entry {
         self.0.parent()
     }
 
+    /// Get the hash of the Entry's parent [`Document`][crate::document::Document]. This is the same
+    /// as [`parent`][Self::parent], and is all that
+    /// [`Schema::decode_entry_with_parent_hash`][crate::schema::Schema::decode_entry_with_parent_hash]
+    /// needs to decode the entry again.
+    pub fn parent_hash(&self) -> &Hash {
+        self.0.parent()
+    }
+
     /// Get the hash of the [`Schema`][crate::schema::Schema] of the Entry's parent
     /// [`Document`][crate::document::Document].
     pub fn schema_hash(&self) -> &Hash {
         self.0.schema_hash()
     }
 
+    /// Get the wire format version the entry was decoded from. Entries that weren't decoded are
+    /// always the current [`FORMAT_VERSION`][crate::FORMAT_VERSION].
+    pub fn format_version(&self) -> u8 {
+        self.0.format_version
+    }
+
+    pub(crate) fn set_format_version(&mut self, version: u8) {
+        self.0.format_version = version;
+    }
+
+    /// Get how [`deserialize`][Self::deserialize] handles fields the target type doesn't use. This
+    /// is [`UnknownFields::Ignore`] unless the entry came from a schema set up with
+    /// [`Schema::with_unknown_fields`][crate::schema::Schema::with_unknown_fields].
+    pub fn unknown_fields(&self) -> UnknownFields {
+        self.0.unknown_fields
+    }
+
+    pub(crate) fn set_unknown_fields(&mut self, unknown: UnknownFields) {
+        self.0.unknown_fields = unknown;
+    }
+
+    /// Mark the entry's data as having passed schema validation.
+    pub(crate) fn set_validated(&mut self) {
+        self.0.validated = true;
+    }
+
     /// Get the Entry's string key.
     pub fn key(&self) -> &str {
         self.0.key()
@@ -482,10 +788,32 @@ impl Entry {
     }
 
     /// Deserialize the entry's contained data into a value.
+    ///
+    /// As with [`Document::deserialize`], strings and byte sequences are borrowed directly from
+    /// the entry's internal buffer, and fields the target type doesn't use are handled as set by
+    /// [`unknown_fields`][Self::unknown_fields].
     pub fn deserialize<'de, D: Deserialize<'de>>(&'de self) -> Result<D> {
         self.0.deserialize()
     }
 
+    /// Deserialize the entry's contained data, choosing how to handle any fields that the target
+    /// type doesn't use. See [`Document::deserialize_with`] for more.
+    pub fn deserialize_with<'de, D: Deserialize<'de>>(
+        &'de self,
+        unknown: UnknownFields,
+    ) -> Result<(D, Vec<(Path, Value)>)> {
+        let mut de = FogDeserializer::with_unknown_fields(self.0.data(), unknown)
+            .validated(self.0.validated);
+        let val = D::deserialize(&mut de)?;
+        Ok((val, de.take_captured()))
+    }
+
+    /// Look up a single nested value inside the entry, without deserializing the rest of it.
+    /// Returns `Ok(None)` if the path doesn't point to a value.
+    pub fn get_path(&self, path: &Path) -> Result<Option<ValueRef>> {
+        path.find_raw(self.0.data())
+    }
+
     /// Override the default compression settings. `None` will disable compression. `Some(level)`
     /// will compress with the provided level as the setting for the algorithm.
     pub fn compression(mut self, setting: Option<u8>) -> Self {
@@ -494,13 +822,245 @@ impl Entry {
     }
 
     /// Sign the entry, or or replace the existing signature if one exists already. Fails if the
-    /// signature would grow the entry size beyond the maximum allowed. In the event of a failure.
-    /// the entry is unmodified.
-    pub fn sign(self, key: &IdentityKey) -> Result<Self> {
+    /// signature would grow the entry size beyond the maximum allowed, or if the signer fails. In
+    /// the event of a failure. the entry is unmodified.
+    pub fn sign<S: Signer + ?Sized>(self, key: &S) -> Result<Self> {
         Ok(Self(self.0.sign(key)?))
     }
 
+    /// Sign the entry with an asynchronous signer, like a remote signing service. Otherwise
+    /// identical to [`sign`][Self::sign].
+    pub async fn sign_async<S: AsyncSigner + ?Sized>(self, key: &S) -> Result<Self> {
+        Ok(Self(self.0.sign_async(key).await?))
+    }
+
     pub(crate) fn complete(self) -> (EntryRef, Vec<u8>, Option<Option<u8>>) {
         self.0.complete()
     }
 }
+
+/// A read-only view of an encoded entry, borrowing its bytes instead of copying them.
+///
+/// This is the entry counterpart to [`DocumentRef`][crate::document::DocumentRef]; see it for
+/// what can be read from a view and when. (An [`EntryRef`] is something else: the reference
+/// triplet that names an entry.) Encoded entries don't hold their parent's hash or their key, so
+/// both must be provided to compute the entry's hash or verify its signature.
+///
+/// ```
+/// # use fog_pack::{document::*, entry::*, schema::*, types::*, validator::*};
+/// # fn main() -> fog_pack::error::Result<()> {
+/// # let schema_doc = SchemaBuilder::new(Validator::Any)
+/// #     .entry_add("note", Validator::Any, None)
+/// #     .build()?;
+/// # let schema = Schema::from_doc(&schema_doc)?;
+/// # let parent = schema.validate_new_doc(NewDocument::new(Some(schema.hash()), "parent")?)?;
+/// let entry = NewEntry::new("note", &parent, 42u8)?.compression(None);
+/// let (entry_ref, encoded, _) = schema.encode_entry(schema.validate_new_entry(entry)?.complete()?)?;
+///
+/// let view = EntryView::new(&encoded, "note", parent.hash())?;
+/// assert_eq!(view.hash()?, entry_ref.hash);
+/// assert_eq!(view.value()?, ValueRef::from(42u8));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct EntryView<'a> {
+    raw: &'a [u8],
+    key: &'a str,
+    parent: &'a Hash,
+    format_version: u8,
+    compress_raw: u8,
+    data: &'a [u8],
+    signature_raw: &'a [u8],
+}
+
+impl<'a> EntryView<'a> {
+    /// Read the header of an encoded entry with the given key, under the given parent document.
+    /// Fails if the header is malformed, the entry is too large, or the format version isn't one
+    /// this crate can read.
+    pub fn new(raw: &'a [u8], key: &'a str, parent: &'a Hash) -> Result<Self> {
+        let (format_version, buf) = match raw.first() {
+            Some(&b) if b & 0x3 == VERSION_MARKER => (b >> 2, &raw[1..]),
+            _ => (0, raw),
+        };
+        VersionPolicy::Supported.check(format_version)?;
+        if buf.len() > MAX_ENTRY_SIZE {
+            return Err(Error::LengthTooLong {
+                max: MAX_ENTRY_SIZE,
+                actual: buf.len(),
+            });
+        }
+        let split = SplitEntry::split(buf)?;
+        CompressType::try_from(split.compress_raw)
+            .map_err(|m| Error::BadHeader(format!("unrecognized compression marker 0x{:x}", m)))?;
+        Ok(Self {
+            raw,
+            key,
+            parent,
+            format_version,
+            compress_raw: split.compress_raw,
+            data: split.data,
+            signature_raw: split.signature_raw,
+        })
+    }
+
+    /// Get the encoded entry this is a view of.
+    pub fn raw(&self) -> &'a [u8] {
+        self.raw
+    }
+
+    /// Get the entry's string key.
+    pub fn key(&self) -> &'a str {
+        self.key
+    }
+
+    /// Get the hash of the entry's parent [`Document`].
+    pub fn parent(&self) -> &'a Hash {
+        self.parent
+    }
+
+    /// Get the wire format version of the entry.
+    pub fn format_version(&self) -> u8 {
+        self.format_version
+    }
+
+    /// Check if the entry's data is compressed.
+    pub fn is_compressed(&self) -> bool {
+        self.compress_raw != u8::from(CompressType::None)
+    }
+
+    /// Get the Identity of the signer of this entry, if the entry is signed. This is only the
+    /// claimed signer until [`verify`][Self::verify] is called.
+    pub fn signer(&self) -> Result<Option<Identity>> {
+        if self.signature_raw.is_empty() {
+            return Ok(None);
+        }
+        let unverified = UnverifiedSignature::try_from(self.signature_raw)?;
+        Ok(Some(unverified.signer().clone()))
+    }
+
+    fn uncompressed(&self) -> Result<&'a [u8]> {
+        if self.is_compressed() {
+            return Err(Error::BadHeader(
+                "Compressed entries must be decoded to read their data".into(),
+            ));
+        }
+        Ok(self.data)
+    }
+
+    /// Compute the hash of the complete entry. Fails if the entry is compressed.
+    pub fn hash(&self) -> Result<Hash> {
+        let mut hash_state =
+            EntryInner::setup_hash_state(self.parent.clone(), self.key, self.uncompressed()?);
+        hash_state.update(self.signature_raw);
+        Ok(hash_state.hash())
+    }
+
+    /// Verify the entry's signature, if it has one. Fails if the signature is invalid, or if the
+    /// entry is compressed.
+    pub fn verify(&self) -> Result<()> {
+        let data = self.uncompressed()?;
+        if self.signature_raw.is_empty() {
+            return Ok(());
+        }
+        let entry_hash = EntryInner::setup_hash_state(self.parent.clone(), self.key, data).hash();
+        UnverifiedSignature::try_from(self.signature_raw)?.verify(&entry_hash)?;
+        Ok(())
+    }
+
+    /// Get the entry's data, borrowed from the encoded entry. Fails if the entry is compressed,
+    /// or the data isn't a valid fog-pack value.
+    pub fn value(&self) -> Result<ValueRef<'a>> {
+        ValueRef::deserialize(&mut FogDeserializer::new(self.uncompressed()?))
+    }
+
+    /// Look up a single nested value inside the entry, without reading the rest of it. Returns
+    /// `Ok(None)` if the path doesn't point to a value. Fails if the entry is compressed.
+    pub fn get_path(&self, path: &Path) -> Result<Option<ValueRef<'a>>> {
+        path.find_raw(self.uncompressed()?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{document::NewDocument, schema::*, validator::Validator};
+    use serde_bytes::ByteBuf;
+
+    #[test]
+    fn capacity() {
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .entry_add("blob", Validator::Any, None)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let parent = NewDocument::new(Some(schema.hash()), "parent").unwrap();
+        let parent = schema.validate_new_doc(parent).unwrap();
+        let key = IdentityKey::new();
+
+        let entry = NewEntry::new("blob", &parent, ()).unwrap();
+        let empty = entry.remaining_capacity();
+        assert_eq!(empty, MAX_ENTRY_SIZE - ENTRY_PREFIX_LEN - 1);
+        let signed = entry.sign(&key).unwrap();
+        assert!(signed.remaining_capacity() < empty);
+        assert!(empty - signed.remaining_capacity() <= key.max_signature_size());
+
+        // Find the largest blob that fits, and check it against actually building entries
+        let blob = |len: usize| ByteBuf::from(vec![0u8; len]);
+        let unsigned_max = (0..MAX_ENTRY_SIZE)
+            .rev()
+            .find(|len| Entry::fits(&blob(*len), None))
+            .unwrap();
+        assert!(NewEntry::new("blob", &parent, blob(unsigned_max)).is_ok());
+        assert!(NewEntry::new("blob", &parent, blob(unsigned_max + 1)).is_err());
+        let entry = NewEntry::new("blob", &parent, blob(unsigned_max)).unwrap();
+        assert_eq!(entry.remaining_capacity(), 0);
+
+        let signed_max = (0..MAX_ENTRY_SIZE)
+            .rev()
+            .find(|len| Entry::fits(&blob(*len), Some(&key)))
+            .unwrap();
+        assert!(signed_max < unsigned_max);
+        let entry = NewEntry::new("blob", &parent, blob(signed_max)).unwrap();
+        assert!(entry.sign(&key).is_ok());
+    }
+
+    #[test]
+    fn entry_view() {
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .entry_add("note", Validator::Any, None)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let parent = NewDocument::new(Some(schema.hash()), "parent").unwrap();
+        let parent = schema.validate_new_doc(parent).unwrap();
+        let key = IdentityKey::new();
+
+        let entry = NewEntry::new("note", &parent, crate::fog_value!({"text": "hi"}))
+            .unwrap()
+            .compression(None)
+            .sign(&key)
+            .unwrap();
+        let entry = schema
+            .validate_new_entry(entry)
+            .unwrap()
+            .complete()
+            .unwrap();
+        let (entry_ref, encoded, _) = schema.encode_entry(entry).unwrap();
+
+        let view = EntryView::new(&encoded, "note", parent.hash()).unwrap();
+        assert!(!view.is_compressed());
+        assert_eq!(view.key(), "note");
+        assert_eq!(view.parent(), parent.hash());
+        assert_eq!(view.hash().unwrap(), entry_ref.hash);
+        assert_eq!(view.signer().unwrap().as_ref(), Some(key.id()));
+        view.verify().unwrap();
+        let path = Path::new().key("text");
+        assert_eq!(view.get_path(&path).unwrap(), Some(ValueRef::from("hi")));
+
+        // The wrong key gives the wrong hash, and fails verification
+        let view = EntryView::new(&encoded, "other", parent.hash()).unwrap();
+        assert_ne!(view.hash().unwrap(), entry_ref.hash);
+        view.verify().unwrap_err();
+    }
+}
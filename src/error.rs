@@ -1,49 +1,164 @@
 //! Library error types.
 //!
 use fog_crypto::{hash::Hash, CryptoError};
-use std::fmt;
+use std::{borrow::Cow, fmt};
 
 use serde::{de, ser};
 
 /// A fog-pack Result, normally returning a fog-pack [`Error`].
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
-#[allow(unused)]
-#[derive(Clone, Debug)]
-enum ValidateError {
-    /// Validation failed inside an array
-    InArray {
+/// Why data failed validation against a schema.
+///
+/// The common failures are structured and built only from static strings, so that validators
+/// don't allocate while trying out possibilities that end up failing, like the options of a
+/// [`MultiValidator`][crate::validator::MultiValidator].
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum ValidateError {
+    /// Ran out of data while expecting another value.
+    Missing {
+        /// What was expected
+        expected: &'static str,
+    },
+    /// Got a value of the wrong type.
+    WrongType {
+        /// The expected type
+        expected: &'static str,
+        /// The type that was actually found
+        actual: &'static str,
+    },
+    /// Validation failed on an element of an array.
+    AtIndex {
+        /// The index of the failing element
         index: usize,
+        /// Why it failed
         err: Box<ValidateError>,
     },
-    /// Validation failed inside a map
-    InMap {
+    /// Validation failed on a value in a map.
+    AtKey {
+        /// The key of the failing value
         key: String,
+        /// Why it failed
         err: Box<ValidateError>,
     },
-    /// Validation failed inside an enum
-    InEnum {
-        name: String,
-        err: Box<ValidateError>,
+    /// A value's size broke one of its validator's limits.
+    Limit {
+        /// The type of value, like `"Array"`
+        kind: &'static str,
+        /// The limit that was broken, like `"max_len"`
+        limit: &'static str,
+        /// The validator's setting for the limit
+        allowed: u32,
+        /// The value's actual size
+        actual: u32,
     },
-    /// Validation of an array failed at a specific index
-    FailArray { index: usize, err: String },
-    /// Validation of a map failed at a specific key
-    FailMap { key: String, err: String },
-    /// All of the available "multi" validators failed
-    FailMulti { errs: Vec<ValidateError> },
-    /// The core validation error
-    FailValue {
-        failure: String,
-        value: crate::value::Value,
+    /// Any other failure.
+    Other(Cow<'static, str>),
+    /// A failure along with a snippet of the value that caused it and a description of the
+    /// validator that rejected it. Only produced with the `diagnostics` feature.
+    Diagnostic {
+        /// Why it failed
+        err: Box<ValidateError>,
+        /// A pretty-printed, possibly truncated, rendering of the offending value
+        value: String,
+        /// The validator that rejected the value
+        validator: String,
     },
-    /// Some other fog-pack error occurred in here
-    SubError(Box<Error>),
+}
+
+impl fmt::Display for ValidateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ValidateError::Missing { expected } => {
+                write!(f, "Expected {}, but ran out of data", expected)
+            }
+            ValidateError::WrongType { expected, actual } => {
+                write!(f, "Expected {}, got {}", expected, actual)
+            }
+            ValidateError::AtIndex { index, ref err } => write!(f, "At index {}: {}", index, err),
+            ValidateError::AtKey { ref key, ref err } => write!(f, "At key {:?}: {}", key, err),
+            ValidateError::Limit {
+                kind,
+                limit,
+                allowed,
+                actual,
+            } => write!(
+                f,
+                "{} has size {}, breaking its {} of {}",
+                kind, actual, limit, allowed
+            ),
+            ValidateError::Other(ref err) => f.write_str(err),
+            ValidateError::Diagnostic {
+                ref err,
+                ref value,
+                ref validator,
+            } => write!(f, "{} (value: {}; validator: {})", err, value, validator),
+        }
+    }
+}
+
+impl std::error::Error for ValidateError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            ValidateError::AtIndex { ref err, .. }
+            | ValidateError::AtKey { ref err, .. }
+            | ValidateError::Diagnostic { ref err, .. } => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// The broad class of failure an [`Error`] belongs to.
+///
+/// Each category has a stable numeric code, which is the hundreds digit of the codes of the
+/// errors within it.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[non_exhaustive]
+pub enum ErrorCategory {
+    /// The data isn't correctly encoded fog-pack, or couldn't be converted to or from a Rust
+    /// type. Code 1.
+    Encoding,
+    /// The data hit a size or parsing limit. Code 2.
+    Limit,
+    /// The data used a different schema than expected. Code 3.
+    Schema,
+    /// The data failed validation against its schema. Code 4.
+    Validation,
+    /// A signature failed to verify. Code 5.
+    Signature,
+    /// A cryptographic operation failed. Code 6.
+    Crypto,
+    /// The data uses a version of the format that is no longer accepted. Code 7.
+    Version,
+    /// A document store failed. Code 8.
+    Store,
+}
+
+impl ErrorCategory {
+    /// Get the category's stable numeric code.
+    pub fn code(&self) -> u16 {
+        match self {
+            ErrorCategory::Encoding => 1,
+            ErrorCategory::Limit => 2,
+            ErrorCategory::Schema => 3,
+            ErrorCategory::Validation => 4,
+            ErrorCategory::Signature => 5,
+            ErrorCategory::Crypto => 6,
+            ErrorCategory::Version => 7,
+            ErrorCategory::Store => 8,
+        }
+    }
 }
 
 /// A fog-pack error. Encompasses any issues that can happen during validation,
 /// encoding, or decoding.
+///
+/// New variants may be added in the future, so applications should branch on the
+/// [`category`][Error::category], the numeric [`code`][Error::code], or the `is_*` helpers
+/// rather than matching every variant.
 #[derive(Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
 pub enum Error {
     /// Occurs when a subtype is using a version format that is no longer accepted. This is mainly
     /// for recognizing when the Cryptographic types and signatures use old, no longer accepted
@@ -61,8 +176,8 @@ pub enum Error {
     SerdeFail(String),
     /// Occurs when the header (compression marker and optional schema) failed to parse correctly.
     BadHeader(String),
-    /// Occurs when zstd compression fails, possibly due to a dictionary not being present in a
-    /// schema, a checksum failing, or any of the other zstd failure modes.
+    /// Occurs when decompression fails, possibly due to a dictionary not being present in a
+    /// schema, a checksum failing, or any of the other zstd or lz4 failure modes.
     FailDecompress(String),
     /// Document/Entry/Query was greater than maximum allowed size on decode
     LengthTooLong {
@@ -85,11 +200,13 @@ pub enum Error {
     /// Basic fog-pack encoding failure
     BadEncode(String),
     /// Schema validation failure.
-    FailValidate(String),
+    FailValidate(ValidateError),
     /// Failure within the cryptographic submodule.
     CryptoError(CryptoError),
     /// Schema or validation hit some parsing limit.
     ParseLimit(String),
+    /// A [`DocStore`][crate::store::DocStore] failed to read or write data.
+    Store(String),
 }
 
 impl fmt::Display for Error {
@@ -140,6 +257,119 @@ impl fmt::Display for Error {
             Error::FailValidate(ref err) => write!(f, "Failed validation: {}", err),
             Error::CryptoError(_) => write!(f, "Cryptographic Error"),
             Error::ParseLimit(ref err) => write!(f, "Hit parsing limit: {}", err),
+            Error::Store(ref err) => write!(f, "Document store failure: {}", err),
+        }
+    }
+}
+
+impl Error {
+    /// Get the broad class of failure this error belongs to.
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Error::SerdeFail(_)
+            | Error::BadHeader(_)
+            | Error::FailDecompress(_)
+            | Error::LengthTooShort { .. }
+            | Error::BadEncode(_) => ErrorCategory::Encoding,
+            Error::LengthTooLong { .. } | Error::ParseLimit(_) => ErrorCategory::Limit,
+            Error::SchemaMismatch { .. } => ErrorCategory::Schema,
+            Error::FailValidate(_) => ErrorCategory::Validation,
+            Error::BadSignature => ErrorCategory::Signature,
+            Error::CryptoError(_) => ErrorCategory::Crypto,
+            Error::OldVersion(_) => ErrorCategory::Version,
+            Error::Store(_) => ErrorCategory::Store,
+        }
+    }
+
+    /// Get a stable numeric code for this error. Codes never change meaning between releases.
+    /// The hundreds digit is the code of the error's [category][Error::category]:
+    ///
+    /// | Code | Error |
+    /// | ---- | ----- |
+    /// | 100  | [`BadEncode`][Error::BadEncode] |
+    /// | 101  | [`SerdeFail`][Error::SerdeFail] |
+    /// | 102  | [`BadHeader`][Error::BadHeader] |
+    /// | 103  | [`FailDecompress`][Error::FailDecompress] |
+    /// | 104  | [`LengthTooShort`][Error::LengthTooShort] |
+    /// | 200  | [`LengthTooLong`][Error::LengthTooLong] |
+    /// | 201  | [`ParseLimit`][Error::ParseLimit] |
+    /// | 300  | [`SchemaMismatch`][Error::SchemaMismatch] |
+    /// | 400  | [`FailValidate`][Error::FailValidate] |
+    /// | 500  | [`BadSignature`][Error::BadSignature] |
+    /// | 600  | [`CryptoError`][Error::CryptoError] |
+    /// | 700  | [`OldVersion`][Error::OldVersion] |
+    /// | 800  | [`Store`][Error::Store] |
+    pub fn code(&self) -> u16 {
+        match self {
+            Error::BadEncode(_) => 100,
+            Error::SerdeFail(_) => 101,
+            Error::BadHeader(_) => 102,
+            Error::FailDecompress(_) => 103,
+            Error::LengthTooShort { .. } => 104,
+            Error::LengthTooLong { .. } => 200,
+            Error::ParseLimit(_) => 201,
+            Error::SchemaMismatch { .. } => 300,
+            Error::FailValidate(_) => 400,
+            Error::BadSignature => 500,
+            Error::CryptoError(_) => 600,
+            Error::OldVersion(_) => 700,
+            Error::Store(_) => 800,
+        }
+    }
+
+    /// Check if the data was malformed: not valid fog-pack, or not convertible to or from the
+    /// requested Rust type.
+    pub fn is_encoding(&self) -> bool {
+        self.category() == ErrorCategory::Encoding
+    }
+
+    /// Check if the data was too large, or hit a parsing limit like the maximum nesting depth.
+    pub fn is_size_limit(&self) -> bool {
+        self.category() == ErrorCategory::Limit
+    }
+
+    /// Check if the data used a different schema than the one it was checked against.
+    pub fn is_schema_mismatch(&self) -> bool {
+        self.category() == ErrorCategory::Schema
+    }
+
+    /// Check if the data failed schema validation.
+    pub fn is_validation(&self) -> bool {
+        self.category() == ErrorCategory::Validation
+    }
+
+    /// Check if a signature failed to verify.
+    pub fn is_bad_signature(&self) -> bool {
+        self.category() == ErrorCategory::Signature
+    }
+
+    /// Get the validation failure, if this is one.
+    pub fn validate_error(&self) -> Option<&ValidateError> {
+        match self {
+            Error::FailValidate(err) => Some(err),
+            _ => None,
+        }
+    }
+
+    /// Mark a validation failure as having happened at an array index.
+    pub(crate) fn at_index(self, index: usize) -> Self {
+        match self {
+            Error::FailValidate(err) => Error::FailValidate(ValidateError::AtIndex {
+                index,
+                err: Box::new(err),
+            }),
+            e => e,
+        }
+    }
+
+    /// Mark a validation failure as having happened at a map key.
+    pub(crate) fn at_key(self, key: &str) -> Self {
+        match self {
+            Error::FailValidate(err) => Error::FailValidate(ValidateError::AtKey {
+                key: key.to_owned(),
+                err: Box::new(err),
+            }),
+            e => e,
         }
     }
 }
@@ -148,11 +378,18 @@ impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match *self {
             Error::CryptoError(ref err) => Some(err),
+            Error::FailValidate(ref err) => Some(err),
             _ => None,
         }
     }
 }
 
+impl std::convert::From<ValidateError> for Error {
+    fn from(e: ValidateError) -> Self {
+        Self::FailValidate(e)
+    }
+}
+
 impl std::convert::From<CryptoError> for Error {
     fn from(e: CryptoError) -> Self {
         Self::CryptoError(e)
@@ -170,3 +407,101 @@ impl de::Error for Error {
         Error::SerdeFail(msg.to_string())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn categories() {
+        let err = Error::LengthTooLong { max: 1, actual: 2 };
+        assert!(err.is_size_limit());
+        assert_eq!(err.code(), 200);
+        assert_eq!(err.code() / 100, err.category().code());
+        assert!(Error::ParseLimit("depth".into()).is_size_limit());
+
+        let err = Error::SchemaMismatch {
+            actual: None,
+            expected: None,
+        };
+        assert!(err.is_schema_mismatch());
+        assert!(!err.is_size_limit());
+        assert!(Error::BadSignature.is_bad_signature());
+        assert!(Error::SerdeFail("nope".into()).is_encoding());
+        assert_eq!(Error::Store("gone".into()).category(), ErrorCategory::Store);
+    }
+
+    #[test]
+    fn source_chain() {
+        let err = Error::FailValidate(ValidateError::Other("too big".into()))
+            .at_index(3)
+            .at_key("list");
+        assert!(err.is_validation());
+        let mut depth = 0;
+        let mut source = err.source();
+        while let Some(err) = source {
+            depth += 1;
+            source = err.source();
+        }
+        assert_eq!(depth, 3);
+        assert!(matches!(
+            err.validate_error(),
+            Some(ValidateError::AtKey { .. })
+        ));
+    }
+
+    #[cfg(not(feature = "diagnostics"))]
+    #[test]
+    fn limit() {
+        use crate::{document::NewDocument, schema::*, validator::*};
+        let schema_doc = SchemaBuilder::new(ArrayValidator::new().max_len(2).build())
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let doc = NewDocument::new(Some(schema.hash()), vec![1u8, 2, 3]).unwrap();
+        let err = schema.validate_new_doc(doc).unwrap_err();
+        let expected = ValidateError::Limit {
+            kind: "Array",
+            limit: "max_len",
+            allowed: 2,
+            actual: 3,
+        };
+        assert_eq!(err.validate_error(), Some(&expected));
+        assert_eq!(
+            expected.to_string(),
+            "Array has size 3, breaking its max_len of 2"
+        );
+    }
+
+    #[cfg(feature = "diagnostics")]
+    #[test]
+    fn diagnostics() {
+        use crate::{document::NewDocument, fog_value, schema::*, validator::*};
+        let schema_doc = SchemaBuilder::new(
+            MapValidator::new()
+                .req_add("n", IntValidator::new().max(10).comment("count").build())
+                .build(),
+        )
+        .build()
+        .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let doc = NewDocument::new(Some(schema.hash()), fog_value!({"n": 11})).unwrap();
+        let err = schema.validate_new_doc(doc).unwrap_err();
+        match err.validate_error() {
+            Some(ValidateError::AtKey { key, err }) => {
+                assert_eq!(key, "n");
+                let ValidateError::Diagnostic {
+                    value, validator, ..
+                } = err.as_ref()
+                else {
+                    panic!("expected a diagnostic, got {:?}", err);
+                };
+                assert_eq!(value, "11");
+                assert_eq!(validator, "Int (\"count\")");
+            }
+            other => panic!("expected a failure at a key, got {:?}", other),
+        }
+        assert!(err.to_string().contains("value: 11"));
+    }
+}
@@ -0,0 +1,260 @@
+//! Moving oversized strings and byte sequences out of documents.
+//!
+//! A schema can mark string or binary fields as externalizable with
+//! [`SchemaBuilder::external_field`][crate::schema::SchemaBuilder::external_field], along with a
+//! size threshold. [`externalize`] builds a document where every such value longer than its
+//! threshold has been split into a chain of chunk documents using the built-in [`chunk_schema`]
+//! shared with [`files`][crate::files], leaving the hash of the first chunk in its place.
+//! [`inflate`] does the reverse, fetching the chunks through a callback and putting the original
+//! values back. Values at or under the threshold are left inline, so small values cost nothing
+//! extra.
+//!
+//! Each chunk holds up to [`CHUNK_SIZE`] bytes and the hash of the next chunk, so values can be
+//! far larger than a single document allows. The document validator should accept both forms of
+//! an externalizable field; [`field_validator`] builds a validator that does.
+//!
+//! ```
+//! # use fog_pack::{external::*, schema::*, types::*, validator::*};
+//! # use std::collections::HashMap;
+//! # fn main() -> fog_pack::error::Result<()> {
+//! let body = Path::new().key("body");
+//! let schema_doc = SchemaBuilder::new(
+//!     MapValidator::new()
+//!         .req_add("body", field_validator(StrValidator::new().build()))
+//!         .build(),
+//! )
+//! .external_field(&body, 1024)
+//! .build()?;
+//! let schema = Schema::from_doc(&schema_doc)?;
+//!
+//! let text = "long text ".repeat(200_000);
+//! let data = fog_pack::fog_value!({ "body": text.clone() });
+//! let (doc, chunks) = externalize(&schema, &data)?;
+//! let doc = schema.validate_new_doc(doc)?;
+//! assert!(doc.deserialize::<Value>()?["body"].is_hash());
+//!
+//! let store: HashMap<_, _> = chunks.into_iter().map(|c| (c.hash().clone(), c)).collect();
+//! let value: Value = inflate(&schema, &doc, |hash| Ok(store.get(hash).cloned()))?;
+//! assert_eq!(value["body"].as_str(), Some(text.as_str()));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    de::FogDeserializer,
+    document::{Document, NewDocument},
+    error::{Error, Result, ValidateError},
+    files::{check_schema, Chunk},
+    schema::Schema,
+    ser::FogSerializer,
+    types::{Hash, Value},
+    validator::*,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::HashSet;
+
+pub use crate::files::{chunk_schema, CHUNK_SIZE};
+
+/// Build a validator for an externalizable field, accepting either a value passing `inline`, or
+/// the hash of a chunk document.
+pub fn field_validator(inline: Validator) -> Validator {
+    let chunk = HashValidator::new()
+        .schema_add(chunk_schema().hash().clone())
+        .build();
+    MultiValidator::new().push(inline).push(chunk).build()
+}
+
+fn to_value<S: Serialize + ?Sized>(data: &S) -> Result<Value> {
+    let mut ser = FogSerializer::from_vec(Vec::new(), false);
+    data.serialize(&mut ser)?;
+    let buf = ser.finish();
+    Value::deserialize(&mut FogDeserializer::new(&buf))
+}
+
+// Split data into a chain of chunks, built from the end back so each can link to the next
+fn chain(data: &[u8], text: bool, chunks: &mut Vec<Document>) -> Result<Hash> {
+    let schema = chunk_schema();
+    let mut next = None;
+    let mut built = Vec::new();
+    for piece in data.chunks(CHUNK_SIZE).rev() {
+        let chunk = Chunk {
+            data: piece.to_vec(),
+            next: next.take(),
+            text,
+        };
+        let doc = schema.validate_new_doc(NewDocument::new(Some(schema.hash()), &chunk)?)?;
+        next = Some(doc.hash().clone());
+        built.push(doc);
+    }
+    chunks.extend(built.into_iter().rev());
+    Ok(next.expect("values over the threshold always have at least one chunk"))
+}
+
+/// Create a new document adhering to the given schema, moving every externalizable string or
+/// binary value longer than its threshold out into chunk documents. Returns the document, which
+/// still needs to be validated by the schema, along with the chunk documents, which should be
+/// stored alongside it. Externalizable fields that aren't present, or aren't strings or binary,
+/// are left as-is.
+pub fn externalize<S: Serialize + ?Sized>(
+    schema: &Schema,
+    data: &S,
+) -> Result<(NewDocument, Vec<Document>)> {
+    let mut value = to_value(data)?;
+    let mut chunks = Vec::new();
+    for (path, threshold) in schema.external_fields() {
+        let Some(field) = path.get_mut(&mut value) else {
+            continue;
+        };
+        let hash = match field {
+            Value::Str(s) if s.len() > threshold as usize => {
+                chain(s.as_bytes(), true, &mut chunks)?
+            }
+            Value::Bin(b) if b.len() > threshold as usize => chain(b, false, &mut chunks)?,
+            _ => continue,
+        };
+        *field = Value::Hash(hash);
+    }
+    Ok((NewDocument::new(Some(schema.hash()), value)?, chunks))
+}
+
+/// Deserialize a document adhering to the given schema, fetching the chunks of every
+/// externalized value with `fetch` and putting the original values back. Fails if the document
+/// doesn't use this schema, if a chunk is missing, isn't a chunk document, or doesn't have the
+/// hash it was fetched by, or if a chain of chunks loops back on itself.
+pub fn inflate<D, F>(schema: &Schema, doc: &Document, mut fetch: F) -> Result<D>
+where
+    D: DeserializeOwned,
+    F: FnMut(&Hash) -> Result<Option<Document>>,
+{
+    match doc.schema_hash() {
+        Some(hash) if hash == schema.hash() => (),
+        actual => {
+            return Err(Error::SchemaMismatch {
+                actual: actual.cloned(),
+                expected: Some(schema.hash().clone()),
+            })
+        }
+    }
+
+    let mut value: Value = doc.deserialize()?;
+    for (path, _) in schema.external_fields() {
+        let Some(field) = path.get_mut(&mut value) else {
+            continue;
+        };
+        let Some(mut next) = field.as_hash().cloned() else {
+            continue;
+        };
+        let mut data = Vec::new();
+        let mut text = false;
+        let mut visited = HashSet::new();
+        loop {
+            if !visited.insert(next.clone()) {
+                return Err(Error::FailValidate(ValidateError::Other(
+                    format!("Chunk chain at {} loops back to chunk {}", path, next).into(),
+                )));
+            }
+            let chunk =
+                fetch(&next)?.ok_or_else(|| Error::Store(format!("Missing chunk {}", next)))?;
+            if chunk.hash() != &next {
+                return Err(Error::FailValidate(ValidateError::Other(
+                    format!("Chunk {} was fetched as {}", chunk.hash(), next).into(),
+                )));
+            }
+            check_schema(&chunk, chunk_schema())?;
+            let chunk: Chunk = chunk.deserialize()?;
+            data.extend_from_slice(&chunk.data);
+            text = chunk.text;
+            match chunk.next {
+                Some(hash) => next = hash,
+                None => break,
+            }
+        }
+        *field = if text {
+            Value::Str(String::from_utf8(data).map_err(|_| {
+                Error::FailValidate(ValidateError::Other(
+                    format!("externalized string at {} isn't valid UTF-8", path).into(),
+                ))
+            })?)
+        } else {
+            Value::Bin(data)
+        };
+    }
+
+    // Re-encode so the target type sees the same data a plain document would give it
+    let mut ser = FogSerializer::from_vec(Vec::new(), false);
+    value.serialize(&mut ser)?;
+    let buf = ser.finish();
+    D::deserialize(&mut FogDeserializer::new(&buf))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{schema::SchemaBuilder, types::Path};
+    use std::collections::HashMap;
+
+    #[test]
+    fn roundtrip() {
+        let text = Path::new().key("text");
+        let blob = Path::new().key("blob");
+        let schema_doc = SchemaBuilder::new(
+            MapValidator::new()
+                .req_add("text", field_validator(StrValidator::new().build()))
+                .opt_add("blob", field_validator(BinValidator::new().build()))
+                .build(),
+        )
+        .external_field(&text, 16)
+        .external_field(&blob, 16)
+        .build()
+        .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        assert_eq!(
+            schema.external_fields(),
+            vec![(blob.clone(), 16), (text.clone(), 16)]
+        );
+
+        // Small values stay inline
+        let small = crate::fog_value!({"text": "short"});
+        let (doc, chunks) = externalize(&schema, &small).unwrap();
+        assert!(chunks.is_empty());
+        let doc = schema.validate_new_doc(doc).unwrap();
+        let value: Value = inflate(&schema, &doc, |_| Ok(None)).unwrap();
+        assert_eq!(value, small);
+
+        // Large ones are chained across chunks
+        let long_text = "x".repeat(CHUNK_SIZE * 2 + 5);
+        let large = crate::fog_value!({
+            "blob": Value::Bin(vec![7u8; 17]),
+            "text": long_text.clone(),
+        });
+        let (doc, chunks) = externalize(&schema, &large).unwrap();
+        assert_eq!(chunks.len(), 4);
+        let doc = schema.validate_new_doc(doc).unwrap();
+        assert!(doc.deserialize::<Value>().unwrap()["text"].is_hash());
+        let mut store: HashMap<Hash, Document> =
+            chunks.into_iter().map(|c| (c.hash().clone(), c)).collect();
+        let value: Value = inflate(&schema, &doc, |h| Ok(store.get(h).cloned())).unwrap();
+        assert_eq!(value, large);
+
+        // Chunks must be the chunk asked for
+        let first = doc.deserialize::<Value>().unwrap()["text"]
+            .as_hash()
+            .unwrap()
+            .clone();
+        let (_, others) = externalize(&schema, &large).unwrap();
+        let swapped = others.into_iter().find(|c| c.hash() != &first).unwrap();
+        let original = store.insert(first.clone(), swapped).unwrap();
+        assert!(matches!(
+            inflate::<Value, _>(&schema, &doc, |h| Ok(store.get(h).cloned())),
+            Err(Error::FailValidate(_))
+        ));
+        store.insert(first.clone(), original);
+
+        // Missing chunks and non-chunk documents are caught
+        store.remove(&first);
+        assert!(inflate::<Value, _>(&schema, &doc, |h| Ok(store.get(h).cloned())).is_err());
+        let other = schema.validate_new_doc(externalize(&schema, &small).unwrap().0);
+        store.insert(first, other.unwrap());
+        assert!(inflate::<Value, _>(&schema, &doc, |h| Ok(store.get(h).cloned())).is_err());
+    }
+}
@@ -0,0 +1,635 @@
+//! A C interface to fog-pack.
+//!
+//! Enabled with the `ffi` feature. This exposes the core operations - compiling schemas, and
+//! creating, validating, encoding, and decoding documents & entries - through a stable C ABI, so
+//! other languages can bind to fog-pack without reimplementing it. Build a shared or static
+//! library with `cargo rustc --release --features ffi --crate-type cdylib` (or `staticlib`).
+//!
+//! # Conventions
+//!
+//! - Schemas, documents, and entries are opaque handles, created by the library and released
+//!     with their matching `_free` function. Handles are never consumed by other calls.
+//! - Every fallible function returns a [`FogStatus`], and writes its result through an out
+//!     pointer only on success. On failure, [`fog_last_error`] describes what went wrong.
+//! - Byte sequences are returned in a [`FogBuffer`], which must be released with
+//!     [`fog_buffer_free`].
+//! - Data going in and out of documents & entries is the raw fog-pack encoding of a single value,
+//!     exactly what [`fog_document_data`] returns. A schema pointer may be null wherever a
+//!     document has no schema.
+//! - Strings going in are NUL-terminated UTF-8. Hashes coming out are base58 strings in a
+//!     [`FogBuffer`], without a NUL terminator.
+//!
+//! No function here is safe to call with dangling or misaligned pointers, and handles aren't
+//! synchronized: a handle may be shared between threads only if no thread frees it while
+//! others use it.
+
+use crate::{
+    de::FogDeserializer,
+    document::{Document, NewDocument},
+    entry::{Entry, NewEntry},
+    error::Error,
+    schema::{NoSchema, Schema},
+    types::{Hash, ValueRef},
+};
+use serde::Deserialize;
+use std::{
+    cell::RefCell,
+    ffi::{c_char, CStr, CString},
+    mem::ManuallyDrop,
+    panic::{catch_unwind, AssertUnwindSafe},
+    ptr, slice,
+};
+
+/// The result of an FFI call.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FogStatus {
+    /// The call succeeded.
+    Ok = 0,
+    /// A required pointer was null.
+    NullPointer = 1,
+    /// A string argument wasn't valid UTF-8.
+    InvalidUtf8 = 2,
+    /// Fog-pack data couldn't be read, or had trailing bytes.
+    BadEncode = 3,
+    /// A document or entry used a different schema than the one provided.
+    SchemaMismatch = 4,
+    /// The header of encoded data was malformed.
+    BadHeader = 5,
+    /// Decompression failed.
+    FailDecompress = 6,
+    /// Data was larger than allowed.
+    LengthTooLong = 7,
+    /// Data ended early.
+    LengthTooShort = 8,
+    /// A signature didn't verify.
+    BadSignature = 9,
+    /// Data didn't pass validation against the schema.
+    FailValidate = 10,
+    /// An entry can only finish validation once other documents are provided, which isn't
+    /// supported through this interface.
+    NeedsDocuments = 11,
+    /// A cryptographic operation failed.
+    CryptoError = 12,
+    /// Some parsing limit was hit.
+    ParseLimit = 13,
+    /// Data used a format version that is no longer accepted.
+    OldVersion = 14,
+    /// Any other failure, including panics within the library.
+    Other = 15,
+}
+
+impl From<&Error> for FogStatus {
+    fn from(e: &Error) -> Self {
+        match e {
+            Error::OldVersion(_) => FogStatus::OldVersion,
+            Error::SchemaMismatch { .. } => FogStatus::SchemaMismatch,
+            Error::SerdeFail(_) => FogStatus::BadEncode,
+            Error::BadHeader(_) => FogStatus::BadHeader,
+            Error::FailDecompress(_) => FogStatus::FailDecompress,
+            Error::LengthTooLong { .. } => FogStatus::LengthTooLong,
+            Error::LengthTooShort { .. } => FogStatus::LengthTooShort,
+            Error::BadSignature => FogStatus::BadSignature,
+            Error::BadEncode(_) => FogStatus::BadEncode,
+            Error::FailValidate(_) => FogStatus::FailValidate,
+            Error::CryptoError(_) => FogStatus::CryptoError,
+            Error::ParseLimit(_) => FogStatus::ParseLimit,
+            Error::Store(_) => FogStatus::Other,
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+struct Failure(FogStatus, String);
+
+impl From<Error> for Failure {
+    fn from(e: Error) -> Self {
+        Failure(FogStatus::from(&e), e.to_string())
+    }
+}
+
+type FfiResult<T> = std::result::Result<T, Failure>;
+
+// Run an FFI call, recording any failure for `fog_last_error`. Panics are caught rather than
+// unwinding into foreign code.
+fn run(f: impl FnOnce() -> FfiResult<()>) -> FogStatus {
+    let result = catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|_| {
+        Err(Failure(
+            FogStatus::Other,
+            "fog-pack panicked during the call".into(),
+        ))
+    });
+    match result {
+        Ok(()) => FogStatus::Ok,
+        Err(Failure(status, msg)) => {
+            let msg = CString::new(msg.replace('\0', " ")).unwrap_or_default();
+            LAST_ERROR.with(|e| *e.borrow_mut() = Some(msg));
+            status
+        }
+    }
+}
+
+unsafe fn get<'a, T>(ptr: *const T) -> FfiResult<&'a T> {
+    ptr.as_ref()
+        .ok_or_else(|| Failure(FogStatus::NullPointer, "a required pointer was null".into()))
+}
+
+unsafe fn put<T>(out: *mut T, val: T) -> FfiResult<()> {
+    if out.is_null() {
+        return Err(Failure(
+            FogStatus::NullPointer,
+            "the output pointer was null".into(),
+        ));
+    }
+    out.write(val);
+    Ok(())
+}
+
+unsafe fn bytes<'a>(data: *const u8, len: usize) -> FfiResult<&'a [u8]> {
+    if len == 0 {
+        return Ok(&[]);
+    }
+    get(data).map(|d| slice::from_raw_parts(d, len))
+}
+
+unsafe fn string<'a>(s: *const c_char) -> FfiResult<&'a str> {
+    get(s)?;
+    CStr::from_ptr(s).to_str().map_err(|_| {
+        Failure(
+            FogStatus::InvalidUtf8,
+            "a string argument wasn't valid UTF-8".into(),
+        )
+    })
+}
+
+unsafe fn schema<'a>(schema: *const FogSchema) -> Option<&'a Schema> {
+    schema.as_ref().map(|s| &s.0)
+}
+
+// Read a single fog-pack value. Trailing data is caught by `check_whole`.
+fn value(data: &[u8]) -> FfiResult<ValueRef> {
+    let mut de = FogDeserializer::new(data);
+    let value = ValueRef::deserialize(&mut de)?;
+    Ok(value)
+}
+
+// Check that re-encoding a value used all of the provided data.
+fn check_whole(data: &[u8], used: &[u8]) -> FfiResult<()> {
+    if data.len() != used.len() {
+        return Err(Failure(
+            FogStatus::BadEncode,
+            "data contained more than a single fog-pack value".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// A compiled schema.
+pub struct FogSchema(Schema);
+
+/// A validated document.
+pub struct FogDocument(Document);
+
+/// A validated entry.
+pub struct FogEntry(Entry);
+
+/// A byte sequence owned by the library. Release it with [`fog_buffer_free`].
+#[repr(C)]
+#[derive(Debug)]
+pub struct FogBuffer {
+    /// Pointer to the first byte.
+    pub data: *mut u8,
+    /// Number of bytes.
+    pub len: usize,
+    /// Allocated capacity. Don't modify this.
+    pub capacity: usize,
+}
+
+impl From<Vec<u8>> for FogBuffer {
+    fn from(v: Vec<u8>) -> Self {
+        let mut v = ManuallyDrop::new(v);
+        FogBuffer {
+            data: v.as_mut_ptr(),
+            len: v.len(),
+            capacity: v.capacity(),
+        }
+    }
+}
+
+fn hash_buffer(hash: &Hash) -> FogBuffer {
+    FogBuffer::from(hash.to_string().into_bytes())
+}
+
+/// Get a description of the last failure on the calling thread, or null if nothing has failed.
+/// The string remains valid until the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn fog_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+/// Release a buffer returned by the library.
+///
+/// # Safety
+///
+/// The buffer must have come from this library, unmodified, and not have been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn fog_buffer_free(buf: FogBuffer) {
+    if !buf.data.is_null() {
+        drop(Vec::from_raw_parts(buf.data, buf.len, buf.capacity));
+    }
+}
+
+/// Compile a schema from a schema document.
+///
+/// # Safety
+///
+/// `doc` must be a valid document handle, and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn fog_schema_from_document(
+    doc: *const FogDocument,
+    out: *mut *mut FogSchema,
+) -> FogStatus {
+    run(|| {
+        let schema = Schema::from_doc(&get(doc)?.0)?;
+        put(out, Box::into_raw(Box::new(FogSchema(schema))))
+    })
+}
+
+/// Get the hash of a schema.
+///
+/// # Safety
+///
+/// `schema` must be a valid schema handle, and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn fog_schema_hash(
+    schema: *const FogSchema,
+    out: *mut FogBuffer,
+) -> FogStatus {
+    run(|| put(out, hash_buffer(get(schema)?.0.hash())))
+}
+
+/// Release a schema.
+///
+/// # Safety
+///
+/// `schema` must be null or a schema handle that hasn't been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn fog_schema_free(schema: *mut FogSchema) {
+    if !schema.is_null() {
+        drop(Box::from_raw(schema));
+    }
+}
+
+/// Create and validate a new document from a raw fog-pack value. If `schema` is null, the
+/// document has no schema.
+///
+/// # Safety
+///
+/// `schema` must be null or a valid schema handle, `data` must point to `len` readable bytes,
+/// and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn fog_document_new(
+    schema: *const FogSchema,
+    data: *const u8,
+    len: usize,
+    out: *mut *mut FogDocument,
+) -> FogStatus {
+    run(|| {
+        let schema = self::schema(schema);
+        let data = bytes(data, len)?;
+        let doc = NewDocument::new(schema.map(|s| s.hash()), value(data)?)?;
+        check_whole(data, doc.data())?;
+        let doc = match schema {
+            Some(schema) => schema.validate_new_doc(doc)?,
+            None => NoSchema::validate_new_doc(doc)?,
+        };
+        put(out, Box::into_raw(Box::new(FogDocument(doc))))
+    })
+}
+
+/// Encode a document. If `schema` is null, the document must not have a schema.
+///
+/// # Safety
+///
+/// `schema` must be null or a valid schema handle, `doc` must be a valid document handle, and
+/// `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn fog_document_encode(
+    schema: *const FogSchema,
+    doc: *const FogDocument,
+    out: *mut FogBuffer,
+) -> FogStatus {
+    run(|| {
+        let doc = get(doc)?.0.clone();
+        let (_, encoded) = match self::schema(schema) {
+            Some(schema) => schema.encode_doc(doc)?,
+            None => NoSchema::encode_doc(doc)?,
+        };
+        put(out, FogBuffer::from(encoded))
+    })
+}
+
+/// Decode and validate a document. If `schema` is null, the document must not have a schema.
+///
+/// # Safety
+///
+/// `schema` must be null or a valid schema handle, `data` must point to `len` readable bytes,
+/// and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn fog_document_decode(
+    schema: *const FogSchema,
+    data: *const u8,
+    len: usize,
+    out: *mut *mut FogDocument,
+) -> FogStatus {
+    run(|| {
+        let data = bytes(data, len)?.to_vec();
+        let doc = match self::schema(schema) {
+            Some(schema) => schema.decode_doc(data)?,
+            None => NoSchema::decode_doc(data)?,
+        };
+        put(out, Box::into_raw(Box::new(FogDocument(doc))))
+    })
+}
+
+/// Get the hash of a document.
+///
+/// # Safety
+///
+/// `doc` must be a valid document handle, and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn fog_document_hash(
+    doc: *const FogDocument,
+    out: *mut FogBuffer,
+) -> FogStatus {
+    run(|| put(out, hash_buffer(get(doc)?.0.hash())))
+}
+
+/// Get the raw fog-pack value held by a document.
+///
+/// # Safety
+///
+/// `doc` must be a valid document handle, and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn fog_document_data(
+    doc: *const FogDocument,
+    out: *mut FogBuffer,
+) -> FogStatus {
+    run(|| put(out, FogBuffer::from(get(doc)?.0.data().to_vec())))
+}
+
+/// Release a document.
+///
+/// # Safety
+///
+/// `doc` must be null or a document handle that hasn't been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn fog_document_free(doc: *mut FogDocument) {
+    if !doc.is_null() {
+        drop(Box::from_raw(doc));
+    }
+}
+
+/// Create and validate a new entry from a raw fog-pack value. Fails with
+/// [`FogStatus::NeedsDocuments`] if the schema needs other documents to validate the entry.
+///
+/// # Safety
+///
+/// `schema` and `parent` must be valid handles, `key` must be a NUL-terminated string, `data`
+/// must point to `len` readable bytes, and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn fog_entry_new(
+    schema: *const FogSchema,
+    key: *const c_char,
+    parent: *const FogDocument,
+    data: *const u8,
+    len: usize,
+    out: *mut *mut FogEntry,
+) -> FogStatus {
+    run(|| {
+        let schema = &get(schema)?.0;
+        let data = bytes(data, len)?;
+        let entry = NewEntry::new(string(key)?, &get(parent)?.0, value(data)?)?;
+        check_whole(data, entry.data())?;
+        let entry = complete(schema.validate_new_entry(entry)?)?;
+        put(out, Box::into_raw(Box::new(FogEntry(entry))))
+    })
+}
+
+fn complete(checklist: crate::validator::DataChecklist<Entry>) -> FfiResult<Entry> {
+    let mut checklist = checklist;
+    if checklist.iter().next().is_some() {
+        return Err(Failure(
+            FogStatus::NeedsDocuments,
+            "entry validation needs other documents".into(),
+        ));
+    }
+    Ok(checklist.complete()?)
+}
+
+/// Encode an entry.
+///
+/// # Safety
+///
+/// `schema` and `entry` must be valid handles, and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn fog_entry_encode(
+    schema: *const FogSchema,
+    entry: *const FogEntry,
+    out: *mut FogBuffer,
+) -> FogStatus {
+    run(|| {
+        let (_, encoded, _) = get(schema)?.0.encode_entry(get(entry)?.0.clone())?;
+        put(out, FogBuffer::from(encoded))
+    })
+}
+
+/// Decode and validate an entry attached to a parent document. Fails with
+/// [`FogStatus::NeedsDocuments`] if the schema needs other documents to validate the entry.
+///
+/// # Safety
+///
+/// `schema` and `parent` must be valid handles, `key` must be a NUL-terminated string, `data`
+/// must point to `len` readable bytes, and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn fog_entry_decode(
+    schema: *const FogSchema,
+    key: *const c_char,
+    parent: *const FogDocument,
+    data: *const u8,
+    len: usize,
+    out: *mut *mut FogEntry,
+) -> FogStatus {
+    run(|| {
+        let data = bytes(data, len)?.to_vec();
+        let checklist = get(schema)?
+            .0
+            .decode_entry(data, string(key)?, &get(parent)?.0)?;
+        put(out, Box::into_raw(Box::new(FogEntry(complete(checklist)?))))
+    })
+}
+
+/// Get the hash of an entry.
+///
+/// # Safety
+///
+/// `entry` must be a valid entry handle, and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn fog_entry_hash(entry: *const FogEntry, out: *mut FogBuffer) -> FogStatus {
+    run(|| put(out, hash_buffer(get(entry)?.0.hash())))
+}
+
+/// Get the raw fog-pack value held by an entry.
+///
+/// # Safety
+///
+/// `entry` must be a valid entry handle, and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn fog_entry_data(entry: *const FogEntry, out: *mut FogBuffer) -> FogStatus {
+    run(|| put(out, FogBuffer::from(get(entry)?.0.data().to_vec())))
+}
+
+/// Release an entry.
+///
+/// # Safety
+///
+/// `entry` must be null or an entry handle that hasn't been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn fog_entry_free(entry: *mut FogEntry) {
+    if !entry.is_null() {
+        drop(Box::from_raw(entry));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{schema::SchemaBuilder, ser::FogSerializer, validator::*};
+    use serde::Serialize;
+
+    fn encode<S: Serialize>(value: S) -> Vec<u8> {
+        let mut ser = FogSerializer::default();
+        value.serialize(&mut ser).unwrap();
+        ser.finish()
+    }
+
+    unsafe fn take(buf: FogBuffer) -> Vec<u8> {
+        let v = slice::from_raw_parts(buf.data, buf.len).to_vec();
+        fog_buffer_free(buf);
+        v
+    }
+
+    #[test]
+    fn roundtrip() {
+        unsafe {
+            // Compile a schema from a schemaless document
+            let schema_doc = SchemaBuilder::new(IntValidator::new().build())
+                .entry_add("e", StrValidator::new().build(), None)
+                .build()
+                .unwrap();
+            let (_, encoded) = NoSchema::encode_doc(schema_doc).unwrap();
+            let mut doc = ptr::null_mut();
+            assert_eq!(
+                fog_document_decode(ptr::null(), encoded.as_ptr(), encoded.len(), &mut doc),
+                FogStatus::Ok
+            );
+            let mut schema = ptr::null_mut();
+            assert_eq!(fog_schema_from_document(doc, &mut schema), FogStatus::Ok);
+            fog_document_free(doc);
+
+            // Documents
+            let data = encode(5u8);
+            let mut doc = ptr::null_mut();
+            assert_eq!(
+                fog_document_new(schema, data.as_ptr(), data.len(), &mut doc),
+                FogStatus::Ok
+            );
+            let mut buf = FogBuffer::from(Vec::new());
+            assert_eq!(fog_document_data(doc, &mut buf), FogStatus::Ok);
+            assert_eq!(take(buf), data);
+            let mut buf = FogBuffer::from(Vec::new());
+            assert_eq!(fog_document_encode(schema, doc, &mut buf), FogStatus::Ok);
+            let encoded = take(buf);
+            let mut decoded = ptr::null_mut();
+            assert_eq!(
+                fog_document_decode(schema, encoded.as_ptr(), encoded.len(), &mut decoded),
+                FogStatus::Ok
+            );
+            assert_eq!((*decoded).0.hash(), (*doc).0.hash());
+            fog_document_free(decoded);
+
+            // Entries
+            let data = encode("hello");
+            let key = CString::new("e").unwrap();
+            let mut entry = ptr::null_mut();
+            assert_eq!(
+                fog_entry_new(
+                    schema,
+                    key.as_ptr(),
+                    doc,
+                    data.as_ptr(),
+                    data.len(),
+                    &mut entry
+                ),
+                FogStatus::Ok
+            );
+            let mut buf = FogBuffer::from(Vec::new());
+            assert_eq!(fog_entry_encode(schema, entry, &mut buf), FogStatus::Ok);
+            let encoded = take(buf);
+            let mut decoded = ptr::null_mut();
+            assert_eq!(
+                fog_entry_decode(
+                    schema,
+                    key.as_ptr(),
+                    doc,
+                    encoded.as_ptr(),
+                    encoded.len(),
+                    &mut decoded
+                ),
+                FogStatus::Ok
+            );
+            assert_eq!((*decoded).0.hash(), (*entry).0.hash());
+            fog_entry_free(decoded);
+            fog_entry_free(entry);
+            fog_document_free(doc);
+            fog_schema_free(schema);
+        }
+    }
+
+    #[test]
+    fn failures() {
+        unsafe {
+            let mut doc = ptr::null_mut();
+            assert_eq!(
+                fog_document_new(ptr::null(), ptr::null(), 1, &mut doc),
+                FogStatus::NullPointer
+            );
+            assert!(!fog_last_error().is_null());
+
+            // Trailing data after the value
+            let mut data = encode(1u8);
+            data.push(0);
+            assert_eq!(
+                fog_document_new(ptr::null(), data.as_ptr(), data.len(), &mut doc),
+                FogStatus::BadEncode
+            );
+            assert!(doc.is_null());
+
+            let schema_doc = SchemaBuilder::new(IntValidator::new().build())
+                .build()
+                .unwrap();
+            let schema = Box::into_raw(Box::new(FogSchema(Schema::from_doc(&schema_doc).unwrap())));
+            let data = encode("not an integer");
+            assert_eq!(
+                fog_document_new(schema, data.as_ptr(), data.len(), &mut doc),
+                FogStatus::FailValidate
+            );
+            let msg = CStr::from_ptr(fog_last_error()).to_str().unwrap();
+            assert!(!msg.is_empty());
+            fog_schema_free(schema);
+        }
+    }
+}
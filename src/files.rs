@@ -0,0 +1,363 @@
+//! Standard schemas for exchanging file trees.
+//!
+//! Applications that store files and directories in fog-pack can use these schemas instead of
+//! their own, so that any other application using them can read the resulting trees without
+//! having to agree on schema hashes out of band. The schema documents are built the same way
+//! every time, so their hashes are fixed for a given [`SCHEMA_VERSION`].
+//!
+//! There are three schemas:
+//!
+//! - [`dir_schema`] - a directory, mapping file names to the hashes of files or other directories.
+//! - [`file_schema`] - a file. Small files hold their data directly; larger ones list the hashes
+//!     of their chunks, in order.
+//! - [`chunk_schema`] - a single chunk of a file's data, at most [`CHUNK_SIZE`] bytes. The same
+//!     schema is used for the chunks of values moved out of documents by
+//!     [`external`][crate::external].
+//!
+//! ```
+//! # use fog_pack::{files::*, document::*};
+//! # use std::collections::HashMap;
+//! # fn main() -> fog_pack::error::Result<()> {
+//! let data = vec![7u8; 1_000_000];
+//! let (file, chunks) = new_file(&data)?;
+//! assert_eq!(chunks.len(), 4);
+//!
+//! let mut dir = Dir::new();
+//! dir.contents.insert("data.bin".into(), file.hash().clone());
+//! let dir = dir.to_doc()?;
+//!
+//! // Read it all back
+//! let chunks: HashMap<_, _> = chunks.into_iter().map(|c| (c.hash().clone(), c)).collect();
+//! let dir = Dir::from_doc(&dir)?;
+//! assert_eq!(dir.contents["data.bin"], *file.hash());
+//! let read = read_file(&file, |hash| Ok(chunks.get(hash).cloned()))?;
+//! assert_eq!(read, data);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    document::{Document, NewDocument},
+    error::{Error, Result, ValidateError},
+    schema::{Schema, SchemaBuilder},
+    types::{Hash, Timestamp},
+    validator::*,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, sync::OnceLock};
+
+/// The version of the file tree schemas. This is stored in each schema document, and changes
+/// whenever the schemas do.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The name of the directory schema.
+pub const DIR_SCHEMA_NAME: &str = "fog-pack directory";
+
+/// The name of the file schema.
+pub const FILE_SCHEMA_NAME: &str = "fog-pack file";
+
+/// The name of the chunk schema.
+pub const CHUNK_SCHEMA_NAME: &str = "fog-pack chunk";
+
+/// The largest a file chunk can be, and the largest file that can hold its data directly.
+pub const CHUNK_SIZE: usize = 1 << 18; // 256 kiB
+
+static DIR_SCHEMA: OnceLock<Schema> = OnceLock::new();
+static FILE_SCHEMA: OnceLock<Schema> = OnceLock::new();
+static CHUNK_SCHEMA: OnceLock<Schema> = OnceLock::new();
+
+fn build(name: &str, description: &str, validator: Validator) -> Schema {
+    let doc = SchemaBuilder::new(validator)
+        .name(name)
+        .description(description)
+        .version(SCHEMA_VERSION)
+        .build()
+        .expect("built-in file schemas should be valid");
+    Schema::from_doc(&doc).expect("built-in file schemas should be valid")
+}
+
+/// Get the directory schema.
+///
+/// A directory is a map with a `created` timestamp and a `contents` map from file names to the
+/// hashes of [files][file_schema] or other directories. File names follow Unix rules: they can't
+/// be empty, `.`, or `..`, can't contain `/` or NUL, and are at most 255 bytes long.
+pub fn dir_schema() -> &'static Schema {
+    DIR_SCHEMA.get_or_init(|| {
+        let validator = MapValidator::new()
+            .req_add("created", TimeValidator::new().build())
+            .req_add(
+                "contents",
+                MapValidator::new()
+                    .keys(
+                        StrValidator::new()
+                            .nin_add(".")
+                            .nin_add("..")
+                            .ban_char("/\0")
+                            .max_len(255)
+                            .min_len(1),
+                    )
+                    .values(HashValidator::new().build())
+                    .build(),
+            )
+            .build();
+        build(DIR_SCHEMA_NAME, "A directory of files", validator)
+    })
+}
+
+/// Get the file schema.
+///
+/// A file is a map with its total `size` in bytes, and either its `data` directly, or the
+/// hashes of its [chunks][chunk_schema] in order. Files no larger than [`CHUNK_SIZE`] hold their
+/// data directly.
+pub fn file_schema() -> &'static Schema {
+    FILE_SCHEMA.get_or_init(|| {
+        let validator = MapValidator::new()
+            .req_add("size", IntValidator::new().min(0u8).build())
+            .opt_add(
+                "data",
+                BinValidator::new().max_len(CHUNK_SIZE as u32).build(),
+            )
+            .opt_add(
+                "chunks",
+                ArrayValidator::new()
+                    .items(HashValidator::new().build())
+                    .build(),
+            )
+            .build();
+        build(FILE_SCHEMA_NAME, "A file", validator)
+    })
+}
+
+/// Get the chunk schema.
+///
+/// A chunk is a map holding up to [`CHUNK_SIZE`] bytes of `data`. Chunks of
+/// [externalized][crate::external] values also hold the hash of the `next` chunk if there is one,
+/// and a `text` flag set on chunks of strings; file chunks leave both out.
+pub fn chunk_schema() -> &'static Schema {
+    CHUNK_SCHEMA.get_or_init(|| {
+        let validator = MapValidator::new()
+            .req_add(
+                "data",
+                BinValidator::new().max_len(CHUNK_SIZE as u32).build(),
+            )
+            .opt_add("next", HashValidator::new().build())
+            .opt_add("text", BoolValidator::new().build())
+            .build();
+        build(
+            CHUNK_SCHEMA_NAME,
+            "A chunk of a file or externalized value",
+            validator,
+        )
+    })
+}
+
+/// A directory, as stored using the [directory schema][dir_schema].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Dir {
+    /// When the directory was created.
+    pub created: Timestamp,
+    /// The files and directories in this directory, by name.
+    pub contents: BTreeMap<String, Hash>,
+}
+
+impl Dir {
+    /// Create an empty directory, created now.
+    pub fn new() -> Self {
+        Self {
+            created: Timestamp::now(),
+            contents: BTreeMap::new(),
+        }
+    }
+
+    /// Turn the directory into a document. Fails if any file name isn't allowed.
+    pub fn to_doc(&self) -> Result<Document> {
+        let schema = dir_schema();
+        schema.validate_new_doc(NewDocument::new(Some(schema.hash()), self)?)
+    }
+
+    /// Read a directory out of a document. Fails if the document doesn't use the directory
+    /// schema.
+    pub fn from_doc(doc: &Document) -> Result<Self> {
+        check_schema(doc, dir_schema())?;
+        doc.deserialize()
+    }
+}
+
+impl Default for Dir {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct File {
+    size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "serde_bytes")]
+    data: Option<Vec<u8>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    chunks: Vec<Hash>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Chunk {
+    #[serde(with = "serde_bytes")]
+    pub(crate) data: Vec<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) next: Option<Hash>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) text: bool,
+}
+
+pub(crate) fn check_schema(doc: &Document, schema: &Schema) -> Result<()> {
+    if doc.schema_hash() != Some(schema.hash()) {
+        return Err(Error::SchemaMismatch {
+            actual: doc.schema_hash().cloned(),
+            expected: Some(schema.hash().clone()),
+        });
+    }
+    Ok(())
+}
+
+/// Create a file document holding some data. Data larger than [`CHUNK_SIZE`] is split into chunk
+/// documents, which are returned alongside the file document and should be stored with it.
+pub fn new_file(data: &[u8]) -> Result<(Document, Vec<Document>)> {
+    let mut file = File {
+        size: data.len() as u64,
+        data: None,
+        chunks: Vec::new(),
+    };
+    let mut chunks = Vec::new();
+    if data.len() <= CHUNK_SIZE {
+        file.data = Some(data.to_vec());
+    } else {
+        let schema = chunk_schema();
+        for chunk in data.chunks(CHUNK_SIZE) {
+            let chunk = Chunk {
+                data: chunk.to_vec(),
+                next: None,
+                text: false,
+            };
+            let chunk = NewDocument::new(Some(schema.hash()), &chunk)?;
+            let chunk = schema.validate_new_doc(chunk)?;
+            file.chunks.push(chunk.hash().clone());
+            chunks.push(chunk);
+        }
+    }
+    let schema = file_schema();
+    let file = schema.validate_new_doc(NewDocument::new(Some(schema.hash()), &file)?)?;
+    Ok((file, chunks))
+}
+
+/// Read a file's data back out, fetching each of its chunks with `fetch`. Fails if the document
+/// doesn't use the file schema, if a chunk is missing, isn't a chunk document, or doesn't have
+/// the hash it was fetched by, or if the data doesn't add up to the file's size.
+pub fn read_file<F>(file: &Document, mut fetch: F) -> Result<Vec<u8>>
+where
+    F: FnMut(&Hash) -> Result<Option<Document>>,
+{
+    check_schema(file, file_schema())?;
+    let file: File = file.deserialize()?;
+    let data = match file.data {
+        Some(data) => data,
+        None => {
+            let mut data = Vec::with_capacity(file.size.min(1 << 30) as usize);
+            for hash in file.chunks.iter() {
+                let chunk = fetch(hash)?
+                    .ok_or_else(|| Error::Store(format!("Missing file chunk {}", hash)))?;
+                if chunk.hash() != hash {
+                    return Err(Error::FailValidate(ValidateError::Other(
+                        format!("File chunk {} was fetched as {}", chunk.hash(), hash).into(),
+                    )));
+                }
+                check_schema(&chunk, chunk_schema())?;
+                data.extend_from_slice(&chunk.deserialize::<Chunk>()?.data);
+            }
+            data
+        }
+    };
+    if data.len() as u64 != file.size {
+        return Err(Error::FailValidate(ValidateError::Other(
+            format!(
+                "File should be {} bytes, but its data was {} bytes",
+                file.size,
+                data.len()
+            )
+            .into(),
+        )));
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::schema::NoSchema;
+    use std::collections::HashMap;
+
+    #[test]
+    fn files() {
+        // Small files hold their data directly
+        let (file, chunks) = new_file(b"hello").unwrap();
+        assert!(chunks.is_empty());
+        assert_eq!(read_file(&file, |_| Ok(None)).unwrap(), b"hello");
+        let (empty, _) = new_file(&[]).unwrap();
+        assert!(read_file(&empty, |_| Ok(None)).unwrap().is_empty());
+
+        let data: Vec<u8> = (0..(CHUNK_SIZE * 2 + 10)).map(|i| i as u8).collect();
+        let (file, chunks) = new_file(&data).unwrap();
+        assert_eq!(chunks.len(), 3);
+        let mut store: HashMap<Hash, Document> =
+            chunks.into_iter().map(|c| (c.hash().clone(), c)).collect();
+        assert_eq!(
+            read_file(&file, |h| Ok(store.get(h).cloned())).unwrap(),
+            data
+        );
+
+        // Chunks must be present, be chunks, and be the chunk asked for
+        let mut keys = file.deserialize::<File>().unwrap().chunks.into_iter();
+        let (first, second) = (keys.next().unwrap(), keys.next().unwrap());
+        let swapped = store[&second].clone();
+        let original = store.insert(first.clone(), swapped).unwrap();
+        assert!(matches!(
+            read_file(&file, |h| Ok(store.get(h).cloned())),
+            Err(Error::FailValidate(_))
+        ));
+        store.insert(first, original);
+        let (other, _) = new_file(b"other").unwrap();
+        let first = store.keys().next().unwrap().clone();
+        store.insert(first, other);
+        assert!(read_file(&file, |h| Ok(store.get(h).cloned())).is_err());
+        assert!(matches!(
+            read_file(&file, |_| Ok(None)),
+            Err(Error::Store(_))
+        ));
+
+        // Only file documents can be read as files
+        let doc = NoSchema::validate_new_doc(NewDocument::new(None, "file").unwrap()).unwrap();
+        assert!(matches!(
+            read_file(&doc, |_| Ok(None)),
+            Err(Error::SchemaMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn dirs() {
+        let (file, _) = new_file(b"hello").unwrap();
+        let mut dir = Dir::new();
+        dir.contents.insert("hello.txt".into(), file.hash().clone());
+        let doc = dir.to_doc().unwrap();
+        assert_eq!(doc.schema_hash(), Some(dir_schema().hash()));
+        assert_eq!(Dir::from_doc(&doc).unwrap(), dir);
+        assert!(Dir::from_doc(&file).is_err());
+
+        for bad in ["", ".", "..", "a/b", "nul\0", &"x".repeat(256)] {
+            let mut dir = Dir::new();
+            dir.contents.insert(bad.into(), file.hash().clone());
+            assert!(dir.to_doc().is_err(), "{:?} should be rejected", bad);
+        }
+
+        // Every schema is distinct
+        assert_ne!(dir_schema().hash(), file_schema().hash());
+        assert_ne!(file_schema().hash(), chunk_schema().hash());
+    }
+}
@@ -0,0 +1,420 @@
+//! Schema-aware document mutation, for negative testing.
+//!
+//! [`mutations`] takes a document that passes its schema and produces variants of it that are
+//! almost, but not quite, valid: a required field removed, an unexpected field added, a value
+//! swapped for one of the wrong type, or a length or range limit exceeded by one. Every variant
+//! returned fails validation against the schema, so they can be fed to an application's
+//! pipeline to check that it rejects them, or used to test the validators themselves.
+//!
+//! [`Mutator`] picks variants at random from a seed, for fuzzing loops that want a steady stream
+//! of them instead of the full set.
+//!
+//! ```
+//! # use fog_pack::{document::*, fog_value, fuzz::*, schema::*, validator::*};
+//! # fn main() -> fog_pack::error::Result<()> {
+//! let schema_doc = SchemaBuilder::new(
+//!     MapValidator::new()
+//!         .req_add("name", StrValidator::new().max_len(8).build())
+//!         .build(),
+//! )
+//! .build()?;
+//! let schema = Schema::from_doc(&schema_doc)?;
+//! let doc = NewDocument::new(Some(schema.hash()), fog_value!({"name": "fog"}))?;
+//! let doc = schema.validate_new_doc(doc)?;
+//!
+//! for mutation in mutations(&schema, &doc)? {
+//!     assert!(schema.validate_new_doc(mutation.new_doc(&schema)?).is_err());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+
+use crate::{
+    document::{Document, NewDocument},
+    error::Result,
+    schema::Schema,
+    types::{Integer, Path, PathSegment, Value},
+    validator::Validator,
+    MAX_DEPTH,
+};
+
+// Limits above this aren't exceeded, so mutated documents stay small
+const MAX_LIMIT: u32 = 1 << 16;
+
+/// The kind of change a [`Mutation`] made.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MutationKind {
+    /// A required map field was removed.
+    RemoveField,
+    /// A map field the validator doesn't allow was added.
+    AddField,
+    /// A value was replaced with one of a different type.
+    SwapType,
+    /// A value was changed to be one past a length or range limit.
+    ExceedLimit,
+}
+
+/// A mutated variant of a document's data.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mutation {
+    /// What kind of change was made.
+    pub kind: MutationKind,
+    /// Where in the data the change was made. For added and removed fields, this is the path of
+    /// the field itself.
+    pub path: Path,
+    /// The complete mutated data.
+    pub value: Value,
+}
+
+impl Mutation {
+    /// Make a new document holding the mutated data, using the given schema. It still has to go
+    /// through the schema, which should reject it.
+    pub fn new_doc(&self, schema: &Schema) -> Result<NewDocument> {
+        NewDocument::new(Some(schema.hash()), &self.value)
+    }
+}
+
+enum Edit {
+    Set(Value),
+    Remove,
+}
+
+struct Walker<'a> {
+    types: BTreeMap<&'a str, &'a Validator>,
+    candidates: Vec<(MutationKind, Path, Edit)>,
+}
+
+// One value of each simple type, used for type swaps
+fn swap_values() -> [Value; 6] {
+    [
+        Value::Null,
+        Value::Bool(false),
+        Value::Int(Integer::from(0)),
+        Value::Str(String::new()),
+        Value::Array(Vec::new()),
+        Value::Map(BTreeMap::new()),
+    ]
+}
+
+fn same_type(a: &Value, b: &Value) -> bool {
+    std::mem::discriminant(a) == std::mem::discriminant(b)
+}
+
+impl<'a> Walker<'a> {
+    fn push(&mut self, kind: MutationKind, path: &Path, edit: Edit) {
+        self.candidates.push((kind, path.clone(), edit));
+    }
+
+    fn walk(&mut self, validator: &'a Validator, value: &Value, path: &mut Path, depth: usize) {
+        if depth > MAX_DEPTH {
+            return;
+        }
+        match validator {
+            Validator::Any => return,
+            Validator::Ref(name) => {
+                if let Some(v) = self.types.get(name.as_str()).copied() {
+                    self.walk(v, value, path, depth + 1);
+                }
+                return;
+            }
+            Validator::Multi(list) => {
+                for v in list.iter() {
+                    self.walk(v, value, path, depth + 1);
+                }
+            }
+            _ => (),
+        }
+
+        for swap in swap_values() {
+            if !same_type(&swap, value) {
+                self.push(MutationKind::SwapType, path, Edit::Set(swap));
+            }
+        }
+
+        match (validator, value) {
+            (Validator::Map(v), Value::Map(map)) => {
+                for key in v.req.keys().filter(|k| map.contains_key(*k)) {
+                    path.push(PathSegment::Key(key.clone()));
+                    self.push(MutationKind::RemoveField, path, Edit::Remove);
+                    path.pop();
+                }
+                if v.values.is_none() {
+                    let extra = (0..)
+                        .map(|i| format!("fuzz{}", i))
+                        .find(|k| !v.req.contains_key(k) && !v.opt.contains_key(k))
+                        .expect("some key is always free");
+                    path.push(PathSegment::Key(extra));
+                    self.push(MutationKind::AddField, path, Edit::Set(Value::Null));
+                    path.pop();
+                }
+                for (key, value) in map.iter() {
+                    let v = v
+                        .req
+                        .get(key)
+                        .or_else(|| v.opt.get(key))
+                        .or(v.values.as_deref());
+                    if let Some(v) = v {
+                        path.push(PathSegment::Key(key.clone()));
+                        self.walk(v, value, path, depth + 1);
+                        path.pop();
+                    }
+                }
+            }
+            (Validator::Array(v), Value::Array(items)) => {
+                let filler = items.last().cloned().unwrap_or_default();
+                for len in [v.max_len.checked_add(1), v.min_len.checked_sub(1)] {
+                    if let Some(len) = len.filter(|len| *len <= MAX_LIMIT) {
+                        let mut items = items.clone();
+                        items.resize(len as usize, filler.clone());
+                        self.push(
+                            MutationKind::ExceedLimit,
+                            path,
+                            Edit::Set(Value::Array(items)),
+                        );
+                    }
+                }
+                for (i, value) in items.iter().enumerate() {
+                    let v = v.prefix.get(i).unwrap_or(&v.items);
+                    path.push(PathSegment::Index(i));
+                    self.walk(v, value, path, depth + 1);
+                    path.pop();
+                }
+            }
+            (Validator::Str(v), Value::Str(s)) => {
+                for len in [v.max_len.checked_add(1), v.min_len.checked_sub(1)] {
+                    if let Some(len) = len.filter(|len| *len <= MAX_LIMIT) {
+                        let mut s = s.clone();
+                        while s.len() > len as usize {
+                            s.pop();
+                        }
+                        while s.len() < len as usize {
+                            s.push('a');
+                        }
+                        self.push(MutationKind::ExceedLimit, path, Edit::Set(Value::Str(s)));
+                    }
+                }
+            }
+            (Validator::Bin(v), Value::Bin(b)) => {
+                for len in [v.max_len.checked_add(1), v.min_len.checked_sub(1)] {
+                    if let Some(len) = len.filter(|len| *len <= MAX_LIMIT) {
+                        let mut b = b.clone();
+                        b.resize(len as usize, 0);
+                        self.push(MutationKind::ExceedLimit, path, Edit::Set(Value::Bin(b)));
+                    }
+                }
+            }
+            (Validator::Int(v), Value::Int(_)) => {
+                let one = Integer::from(1);
+                let above = if v.ex_max {
+                    Some(v.max)
+                } else {
+                    v.max.checked_add(one)
+                };
+                let below = if v.ex_min {
+                    Some(v.min)
+                } else {
+                    v.min.checked_sub(one)
+                };
+                for int in [above, below].into_iter().flatten() {
+                    self.push(MutationKind::ExceedLimit, path, Edit::Set(Value::Int(int)));
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+fn apply(root: &Value, path: &Path, edit: &Edit) -> Option<Value> {
+    let mut root = root.clone();
+    let mut parent = path.clone();
+    match (parent.pop(), edit) {
+        (None, Edit::Set(value)) => return Some(value.clone()),
+        (None, Edit::Remove) => return None,
+        (Some(last), edit) => {
+            let target = parent.get_mut(&mut root)?;
+            match (target, last, edit) {
+                (Value::Map(map), PathSegment::Key(key), Edit::Set(value)) => {
+                    map.insert(key, value.clone());
+                }
+                (Value::Map(map), PathSegment::Key(key), Edit::Remove) => {
+                    map.remove(&key)?;
+                }
+                (Value::Array(items), PathSegment::Index(i), Edit::Set(value)) => {
+                    *items.get_mut(i)? = value.clone();
+                }
+                _ => return None,
+            }
+        }
+    }
+    Some(root)
+}
+
+/// Produce every mutated variant of a document that fails validation against its schema, in a
+/// deterministic order. Parts of the document validated by [`Validator::Any`] aren't mutated.
+///
+/// Each value gets at most one type swap: the first replacement type the schema rejects.
+/// Mutations that happen to still pass validation, like swapping a value inside a
+/// [`Validator::Multi`] for a type another option accepts, are left out.
+pub fn mutations(schema: &Schema, doc: &Document) -> Result<Vec<Mutation>> {
+    let value: Value = doc.deserialize()?;
+    Ok(mutate_value(schema, schema.doc_validator(), &value, |v| {
+        schema.validate_value(&v.as_ref()).is_err()
+    }))
+}
+
+fn mutate_value<'a>(
+    schema: &'a Schema,
+    validator: &'a Validator,
+    value: &Value,
+    fails: impl Fn(&Value) -> bool,
+) -> Vec<Mutation> {
+    let mut walker = Walker {
+        types: schema.types().collect(),
+        candidates: Vec::new(),
+    };
+    walker.walk(validator, value, &mut Path::new(), 0);
+
+    let mut mutations: Vec<Mutation> = Vec::new();
+    for (kind, path, edit) in walker.candidates {
+        let swapped = |m: &Mutation| m.kind == MutationKind::SwapType && m.path == path;
+        if kind == MutationKind::SwapType && mutations.iter().any(swapped) {
+            continue;
+        }
+        let Some(mutated) = apply(value, &path, &edit) else {
+            continue;
+        };
+        if mutated != *value && fails(&mutated) && !mutations.iter().any(|m| m.value == mutated) {
+            mutations.push(Mutation {
+                kind,
+                path,
+                value: mutated,
+            });
+        }
+    }
+    mutations
+}
+
+/// Picks random mutations of documents, for fuzzing loops.
+///
+/// The mutator is a small deterministic random number generator: the same seed always picks the
+/// same mutations from the same documents, so failures can be reproduced.
+#[derive(Clone, Debug)]
+pub struct Mutator {
+    state: u64,
+}
+
+impl Mutator {
+    /// Create a mutator from a seed.
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    // SplitMix64
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Pick one random mutation of a document, as produced by [`mutations`]. Returns `None` if
+    /// the document can't be mutated into an invalid one, as when its schema accepts anything.
+    pub fn mutate(&mut self, schema: &Schema, doc: &Document) -> Result<Option<Mutation>> {
+        let mut all = mutations(schema, doc)?;
+        if all.is_empty() {
+            return Ok(None);
+        }
+        let pick = (self.next_u64() % all.len() as u64) as usize;
+        Ok(Some(all.swap_remove(pick)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{fog_value, schema::SchemaBuilder, validator::*};
+
+    fn schema() -> Schema {
+        let schema_doc = SchemaBuilder::new(
+            MapValidator::new()
+                .req_add("id", IntValidator::new().min(0).max(100).build())
+                .req_add("name", Validator::new_ref("Name"))
+                .opt_add(
+                    "tags",
+                    ArrayValidator::new()
+                        .items(StrValidator::new().build())
+                        .max_len(2)
+                        .build(),
+                )
+                .opt_add("extra", Validator::Any)
+                .build(),
+        )
+        .type_add("Name", StrValidator::new().min_len(1).max_len(4).build())
+        .build()
+        .unwrap();
+        Schema::from_doc(&schema_doc).unwrap()
+    }
+
+    #[test]
+    fn mutations_fail() {
+        let schema = schema();
+        let value = fog_value!({"id": 5, "name": "fog", "tags": ["a"], "extra": [1, 2]});
+        let doc = NewDocument::new(Some(schema.hash()), &value).unwrap();
+        let doc = schema.validate_new_doc(doc).unwrap();
+
+        let all = mutations(&schema, &doc).unwrap();
+        for m in all.iter() {
+            let doc = m.new_doc(&schema).unwrap();
+            assert!(schema.validate_new_doc(doc).is_err(), "{:?}", m);
+        }
+        let has = |kind: MutationKind, path: &str| {
+            let path = Path::parse(path).unwrap();
+            all.iter().any(|m| m.kind == kind && m.path == path)
+        };
+        assert!(has(MutationKind::RemoveField, "/id"));
+        assert!(has(MutationKind::RemoveField, "/name"));
+        assert!(!has(MutationKind::RemoveField, "/tags"));
+        assert!(has(MutationKind::AddField, "/fuzz0"));
+        assert!(has(MutationKind::SwapType, ""));
+        assert!(has(MutationKind::SwapType, "/id"));
+        assert!(has(MutationKind::ExceedLimit, "/id"));
+        assert!(has(MutationKind::ExceedLimit, "/name"));
+        assert!(has(MutationKind::ExceedLimit, "/tags"));
+        assert!(!all.iter().any(|m| m.path.to_string().starts_with("/extra")));
+        let name_limits: Vec<_> = all
+            .iter()
+            .filter(|m| m.kind == MutationKind::ExceedLimit && m.path.to_string() == "/name")
+            .map(|m| m.value.pointer("/name").cloned())
+            .collect();
+        assert_eq!(
+            name_limits,
+            vec![Some(Value::from("fogaa")), Some(Value::from(""))]
+        );
+        assert_eq!(mutations(&schema, &doc).unwrap(), all);
+    }
+
+    #[test]
+    fn mutator() {
+        let schema = schema();
+        let doc = NewDocument::new(Some(schema.hash()), fog_value!({"id": 5, "name": "fog"}));
+        let doc = schema.validate_new_doc(doc.unwrap()).unwrap();
+        let pick = |seed| {
+            let mut mutator = Mutator::new(seed);
+            (0..8)
+                .map(|_| mutator.mutate(&schema, &doc).unwrap().unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(pick(1), pick(1));
+        assert_ne!(pick(1), pick(2));
+
+        let any = SchemaBuilder::new(Validator::Any).build().unwrap();
+        let any = Schema::from_doc(&any).unwrap();
+        let doc = any
+            .validate_new_doc(NewDocument::new(Some(any.hash()), 1).unwrap())
+            .unwrap();
+        assert!(Mutator::new(0).mutate(&any, &doc).unwrap().is_none());
+    }
+}
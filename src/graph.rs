@@ -0,0 +1,562 @@
+//! Traversal of the references between documents.
+//!
+//! Documents refer to other documents by hash, forming a graph. [`walk`] follows those references
+//! out from a starting document, fetching each document it finds and recording what links to
+//! what, and returns the resulting [`RefGraph`]. This is the basis for pinning a document along
+//! with everything it depends on, and for replicating a set of documents.
+//!
+//! For garbage collection, [`reachable`] computes everything reachable from a set of root
+//! documents, without recording the links between them. It honors fields a schema declares as
+//! weak references, so every store can agree on what is safe to delete.
+//!
+//! ```
+//! # use fog_pack::{document::*, graph::*, schema::*, store::*};
+//! # fn main() -> fog_pack::error::Result<()> {
+//! let mut store = MemoryStore::new();
+//! let leaf = NoSchema::validate_new_doc(NewDocument::new(None, "leaf")?)?;
+//! let leaf = store.put_doc(leaf)?;
+//! let root = NoSchema::validate_new_doc(NewDocument::new(None, vec![&leaf])?)?;
+//! let root = store.put_doc(root)?;
+//!
+//! let graph = walk(&root, |hash| store.get_doc(hash), &WalkPolicy::new())?;
+//! assert!(graph.contains(&leaf));
+//! assert_eq!(graph.links(&root), Some(&[leaf][..]));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    document::Document,
+    error::{Error, Result, ValidateError},
+    schema::Schema,
+    types::{Hash, Path, PathSegment, ValueRef},
+};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+// Call `fetch`, and make sure the document it returns is the one that was asked for.
+fn fetch_checked<F>(fetch: &mut F, hash: &Hash) -> Result<Option<Document>>
+where
+    F: FnMut(&Hash) -> Result<Option<Document>>,
+{
+    let doc = fetch(hash)?;
+    if let Some(doc) = &doc {
+        if doc.hash() != hash {
+            return Err(Error::FailValidate(ValidateError::Other(
+                format!(
+                    "fetched document {} doesn't match the requested hash {}",
+                    doc.hash(),
+                    hash
+                )
+                .into(),
+            )));
+        }
+    }
+    Ok(doc)
+}
+
+/// The order in which a [`walk`] visits documents.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WalkOrder {
+    /// Visit every document at one depth before moving on to the next. Each document's recorded
+    /// depth is the length of the shortest path to it.
+    #[default]
+    BreadthFirst,
+    /// Follow each reference as far as possible before moving on to the next one. Each
+    /// document's recorded depth is the depth it was first reached at, which may not be the
+    /// shortest.
+    DepthFirst,
+}
+
+/// Controls how a [`walk`] follows references.
+#[derive(Clone, Debug, Default)]
+pub struct WalkPolicy {
+    order: WalkOrder,
+    max_depth: Option<usize>,
+    schemas: Option<Vec<Option<Hash>>>,
+    follow_schemas: bool,
+}
+
+impl WalkPolicy {
+    /// Create a policy that walks breadth-first, with no depth limit or schema filter.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the order documents are visited in.
+    pub fn order(mut self, order: WalkOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Stop following references past the given depth. The starting document is at depth 0;
+    /// documents at the maximum depth are visited, but their references aren't followed.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Only visit documents using the given schema, or documents with no schema if `None`. May
+    /// be called multiple times to allow several schemas. Documents using any other schema are
+    /// skipped entirely: they aren't added to the graph, and their references aren't followed.
+    /// The starting document is always visited.
+    pub fn schema_add(mut self, schema: Option<&Hash>) -> Self {
+        self.schemas
+            .get_or_insert_with(Vec::new)
+            .push(schema.cloned());
+        self
+    }
+
+    /// Also follow the reference from each document to its schema document.
+    pub fn follow_schemas(mut self, follow_schemas: bool) -> Self {
+        self.follow_schemas = follow_schemas;
+        self
+    }
+
+    fn allows(&self, doc: &Document) -> bool {
+        match &self.schemas {
+            Some(schemas) => schemas.iter().any(|s| s.as_ref() == doc.schema_hash()),
+            None => true,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    depth: usize,
+    links: Vec<Hash>,
+}
+
+/// The documents reached by a [`walk`], and the references between them.
+#[derive(Clone, Debug)]
+pub struct RefGraph {
+    root: Hash,
+    order: Vec<Hash>,
+    nodes: HashMap<Hash, Node>,
+    missing: Vec<Hash>,
+    skipped: Vec<Hash>,
+}
+
+impl RefGraph {
+    /// Get the hash of the starting document.
+    pub fn root(&self) -> &Hash {
+        &self.root
+    }
+
+    /// Get the number of documents visited.
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    /// Check if no documents were visited, which only happens if the starting document couldn't
+    /// be fetched.
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// Check if a document was visited.
+    pub fn contains(&self, hash: &Hash) -> bool {
+        self.nodes.contains_key(hash)
+    }
+
+    /// Iterate over the hashes of every visited document, in the order they were visited.
+    pub fn docs(&self) -> impl Iterator<Item = &Hash> {
+        self.order.iter()
+    }
+
+    /// Get the references found in a visited document, in the order they appear. Includes the
+    /// document's schema first, if the walk followed schemas. References that weren't followed
+    /// because of the depth limit are still listed.
+    pub fn links(&self, hash: &Hash) -> Option<&[Hash]> {
+        self.nodes.get(hash).map(|n| n.links.as_slice())
+    }
+
+    /// Get the depth a visited document was reached at.
+    pub fn depth(&self, hash: &Hash) -> Option<usize> {
+        self.nodes.get(hash).map(|n| n.depth)
+    }
+
+    /// Get the hashes of referenced documents that couldn't be fetched.
+    pub fn missing(&self) -> &[Hash] {
+        &self.missing
+    }
+
+    /// Get the hashes of referenced documents that were skipped by the policy's schema filter.
+    pub fn skipped(&self) -> &[Hash] {
+        &self.skipped
+    }
+}
+
+/// Walk the graph of references starting from the document with hash `start`.
+///
+/// `fetch` is called once for each distinct hash reached, and should return the matching
+/// document, or `None` if it isn't available. Missing documents are recorded in the graph rather
+/// than ending the walk; any error from `fetch` ends the walk immediately, as does a document
+/// whose hash doesn't match the one requested. References that form a cycle are recorded, but
+/// each document is only visited once.
+pub fn walk<F>(start: &Hash, mut fetch: F, policy: &WalkPolicy) -> Result<RefGraph>
+where
+    F: FnMut(&Hash) -> Result<Option<Document>>,
+{
+    let mut graph = RefGraph {
+        root: start.clone(),
+        order: Vec::new(),
+        nodes: HashMap::new(),
+        missing: Vec::new(),
+        skipped: Vec::new(),
+    };
+    let mut seen = HashSet::new();
+    let mut pending = VecDeque::new();
+    pending.push_back((start.clone(), 0));
+    seen.insert(start.clone());
+
+    while let Some((hash, depth)) = match policy.order {
+        WalkOrder::BreadthFirst => pending.pop_front(),
+        WalkOrder::DepthFirst => pending.pop_back(),
+    } {
+        let doc = match fetch_checked(&mut fetch, &hash)? {
+            Some(doc) => doc,
+            None => {
+                graph.missing.push(hash);
+                continue;
+            }
+        };
+        if depth > 0 && !policy.allows(&doc) {
+            graph.skipped.push(hash);
+            continue;
+        }
+
+        let mut links = Vec::new();
+        if policy.follow_schemas {
+            links.extend(doc.schema_hash().cloned());
+        }
+        for link in doc.find_hashes() {
+            if !links.contains(&link) {
+                links.push(link);
+            }
+        }
+
+        if policy.max_depth.is_none_or(|max| depth < max) {
+            let next = links
+                .iter()
+                .filter(|link| seen.insert((*link).clone()))
+                .map(|link| (link.clone(), depth + 1));
+            match policy.order {
+                WalkOrder::BreadthFirst => pending.extend(next),
+                // Reverse so that the first reference is the first to be visited
+                WalkOrder::DepthFirst => {
+                    let next: Vec<_> = next.collect();
+                    pending.extend(next.into_iter().rev());
+                }
+            }
+        }
+
+        graph.order.push(hash.clone());
+        graph.nodes.insert(hash, Node { depth, links });
+    }
+    Ok(graph)
+}
+
+/// The set of documents reachable from a set of roots, from [`reachable`].
+#[derive(Clone, Debug, Default)]
+pub struct Reachable {
+    hashes: HashSet<Hash>,
+    missing: Vec<Hash>,
+}
+
+impl Reachable {
+    /// Check if a document is reachable. Anything not reachable may be garbage collected.
+    pub fn contains(&self, hash: &Hash) -> bool {
+        self.hashes.contains(hash)
+    }
+
+    /// Get the number of reachable documents, including missing ones.
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    /// Check if nothing is reachable.
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    /// Iterate over every reachable hash, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = &Hash> {
+        self.hashes.iter()
+    }
+
+    /// Get the hashes of reachable documents that couldn't be fetched. These are still counted as
+    /// reachable.
+    pub fn missing(&self) -> &[Hash] {
+        &self.missing
+    }
+}
+
+// Collect the hashes in a value, skipping over any weak fields.
+fn strong_hashes(value: &ValueRef, path: &mut Path, weak: &[Path], out: &mut Vec<Hash>) {
+    if weak.iter().any(|w| w == path) {
+        return;
+    }
+    match value {
+        ValueRef::Hash(h) => out.push(h.clone()),
+        ValueRef::Array(array) => {
+            for (i, v) in array.iter().enumerate() {
+                path.push(PathSegment::Index(i));
+                strong_hashes(v, path, weak, out);
+                path.pop();
+            }
+        }
+        ValueRef::Map(map) => {
+            for (k, v) in map.iter() {
+                path.push(PathSegment::Key((*k).to_owned()));
+                strong_hashes(v, path, weak, out);
+                path.pop();
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Find every document reachable from a set of root documents.
+///
+/// This is the standard reachability rule for garbage collecting fog-pack documents: a document
+/// is reachable if it is a root, or if a reachable document refers to it. A document refers to
+/// its schema document, and to every hash in its data except those within fields its schema
+/// marks as [weak][crate::schema::SchemaBuilder::weak_field]. Weak references let a document
+/// point at others without keeping them alive.
+///
+/// `fetch` should return the document with the given hash, or `None` if it isn't available.
+/// Missing documents are still counted as reachable. If a document's schema is missing, every
+/// hash in the document is treated as a strong reference. A document whose hash doesn't match the
+/// one requested ends the search with an error.
+///
+/// `fetch` is called once for each reachable hash, with one exception: a schema document that
+/// was already visited as an ordinary document before any document using it was found is
+/// fetched a second time to load its weak fields.
+pub fn reachable<'a, F>(
+    roots: impl IntoIterator<Item = &'a Hash>,
+    mut fetch: F,
+) -> Result<Reachable>
+where
+    F: FnMut(&Hash) -> Result<Option<Document>>,
+{
+    let mut set = Reachable::default();
+    let mut pending: Vec<Hash> = Vec::new();
+    for root in roots {
+        if set.hashes.insert(root.clone()) {
+            pending.push(root.clone());
+        }
+    }
+    // Weak fields for each schema seen so far, or `None` if the schema couldn't be loaded
+    let mut weak_fields: HashMap<Hash, Option<Vec<Path>>> = HashMap::new();
+    // Schema documents fetched for their weak fields that haven't been visited yet
+    let mut fetched: HashMap<Hash, Option<Document>> = HashMap::new();
+
+    while let Some(hash) = pending.pop() {
+        let doc = match fetched.remove(&hash) {
+            Some(doc) => doc,
+            None => fetch_checked(&mut fetch, &hash)?,
+        };
+        let doc = match doc {
+            Some(doc) => doc,
+            None => {
+                set.missing.push(hash);
+                continue;
+            }
+        };
+
+        let mut links = Vec::new();
+        let weak = match doc.schema_hash() {
+            Some(schema) => {
+                links.push(schema.clone());
+                if !weak_fields.contains_key(schema) {
+                    let schema_doc = fetch_checked(&mut fetch, schema)?;
+                    let fields = schema_doc
+                        .as_ref()
+                        .and_then(|schema_doc| Schema::from_doc(schema_doc).ok())
+                        .map(|schema| schema.weak_fields());
+                    weak_fields.insert(schema.clone(), fields);
+                    // Hold on to the document for when it's visited, so it's only fetched once
+                    fetched.insert(schema.clone(), schema_doc);
+                }
+                weak_fields[schema].as_deref().unwrap_or_default()
+            }
+            None => &[],
+        };
+        if weak.is_empty() {
+            links.extend(doc.find_hashes());
+        } else {
+            let value: ValueRef = doc.deserialize()?;
+            strong_hashes(&value, &mut Path::new(), weak, &mut links);
+        }
+
+        for link in links {
+            if set.hashes.insert(link.clone()) {
+                pending.push(link);
+            }
+        }
+    }
+    Ok(set)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        document::NewDocument,
+        schema::{NoSchema, Schema, SchemaBuilder},
+        store::{DocStore, MemoryStore},
+        validator::Validator,
+    };
+
+    fn put<S: serde::Serialize>(store: &mut MemoryStore, data: S) -> Hash {
+        let doc = NoSchema::validate_new_doc(NewDocument::new(None, data).unwrap()).unwrap();
+        store.put_doc(doc).unwrap()
+    }
+
+    #[test]
+    fn walks() {
+        let mut store = MemoryStore::new();
+        // root -> a -> c
+        //      -> b -> c, missing
+        let c = put(&mut store, "c");
+        let a = put(&mut store, vec![&c]);
+        let missing = NoSchema::validate_new_doc(NewDocument::new(None, "gone").unwrap())
+            .unwrap()
+            .hash()
+            .clone();
+        let b = put(&mut store, vec![&c, &missing]);
+        let root = put(&mut store, vec![&a, &b]);
+
+        let graph = walk(&root, |h| store.get_doc(h), &WalkPolicy::new()).unwrap();
+        assert_eq!(graph.root(), &root);
+        assert_eq!(
+            graph.docs().cloned().collect::<Vec<_>>(),
+            vec![root.clone(), a.clone(), b.clone(), c.clone()]
+        );
+        assert_eq!(graph.depth(&c), Some(2));
+        assert_eq!(graph.links(&b), Some(&[c.clone(), missing.clone()][..]));
+        assert_eq!(graph.missing(), &[missing]);
+
+        let policy = WalkPolicy::new().order(WalkOrder::DepthFirst);
+        let graph = walk(&root, |h| store.get_doc(h), &policy).unwrap();
+        assert_eq!(
+            graph.docs().cloned().collect::<Vec<_>>(),
+            vec![root.clone(), a.clone(), c.clone(), b.clone()]
+        );
+
+        let policy = WalkPolicy::new().max_depth(1);
+        let graph = walk(&root, |h| store.get_doc(h), &policy).unwrap();
+        assert_eq!(graph.len(), 3);
+        assert!(!graph.contains(&c));
+        assert!(graph.missing().is_empty());
+    }
+
+    #[test]
+    fn repeats_and_schemas() {
+        let mut store = MemoryStore::new();
+        let schema_doc = SchemaBuilder::new(Validator::Any).build().unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        store.put_doc(schema_doc).unwrap();
+
+        // Content addressing rules out true cycles, but one document can be reached many ways
+        let a = put(&mut store, "a");
+        let b = NewDocument::new(Some(schema.hash()), vec![&a]).unwrap();
+        let b = store.put_doc(schema.validate_new_doc(b).unwrap()).unwrap();
+        let root = put(&mut store, vec![&a, &b, &a]);
+
+        let graph = walk(&root, |h| store.get_doc(h), &WalkPolicy::new()).unwrap();
+        assert_eq!(graph.len(), 3);
+        assert_eq!(graph.links(&root), Some(&[a.clone(), b.clone()][..]));
+        assert!(!graph.contains(schema.hash()));
+
+        let policy = WalkPolicy::new().follow_schemas(true);
+        let graph = walk(&root, |h| store.get_doc(h), &policy).unwrap();
+        assert!(graph.contains(schema.hash()));
+        assert_eq!(
+            graph.links(&b),
+            Some(&[schema.hash().clone(), a.clone()][..])
+        );
+
+        let policy = WalkPolicy::new().schema_add(None);
+        let graph = walk(&root, |h| store.get_doc(h), &policy).unwrap();
+        assert!(graph.contains(&a));
+        assert!(!graph.contains(&b));
+        assert_eq!(graph.skipped(), &[b]);
+    }
+
+    #[test]
+    fn reachability() {
+        let mut store = MemoryStore::new();
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .weak_field(&Path::new().key("seen"))
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        assert_eq!(schema.weak_fields(), vec![Path::new().key("seen")]);
+        store.put_doc(schema_doc).unwrap();
+
+        #[derive(serde::Serialize)]
+        struct Links<'a> {
+            keep: Vec<&'a Hash>,
+            seen: Vec<&'a Hash>,
+        }
+        let kept = put(&mut store, "kept");
+        let weak = put(&mut store, "weak");
+        let both = put(&mut store, "both");
+        let links = Links {
+            keep: vec![&kept, &both],
+            seen: vec![&weak, &both],
+        };
+        let doc = NewDocument::new(Some(schema.hash()), &links).unwrap();
+        let doc = store
+            .put_doc(schema.validate_new_doc(doc).unwrap())
+            .unwrap();
+        let other = put(&mut store, "other root");
+        let gone = NoSchema::validate_new_doc(NewDocument::new(None, "gone").unwrap())
+            .unwrap()
+            .hash()
+            .clone();
+
+        let set = reachable([&doc, &other, &gone], |h| store.get_doc(h)).unwrap();
+        for hash in [&doc, &other, &gone, &kept, &both, schema.hash()] {
+            assert!(set.contains(hash));
+        }
+        assert!(!set.contains(&weak));
+        assert_eq!(set.len(), 6);
+        assert_eq!(set.missing(), &[gone]);
+
+        // Without the schema, every reference is strong
+        let set = reachable([&doc], |h| {
+            if h == schema.hash() {
+                Ok(None)
+            } else {
+                store.get_doc(h)
+            }
+        })
+        .unwrap();
+        assert!(set.contains(&weak));
+        assert_eq!(set.missing(), &[schema.hash().clone()]);
+
+        // The schema document is only fetched once
+        let mut calls = HashMap::new();
+        reachable([&doc], |h| {
+            *calls.entry(h.clone()).or_insert(0) += 1;
+            store.get_doc(h)
+        })
+        .unwrap();
+        assert!(calls.values().all(|&n| n == 1));
+        assert_eq!(calls.len(), 4);
+    }
+
+    #[test]
+    fn wrong_fetch() {
+        let mut store = MemoryStore::new();
+        let a = put(&mut store, "a");
+        let b = put(&mut store, "b");
+        let root = put(&mut store, vec![&a]);
+        // A fetch that hands back the wrong document ends the traversal
+        let fetch = |h: &Hash| store.get_doc(if h == &a { &b } else { h });
+        assert!(walk(&root, fetch, &WalkPolicy::new()).is_err());
+        assert!(reachable([&root], fetch).is_err());
+    }
+}
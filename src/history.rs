@@ -0,0 +1,243 @@
+//! Verification of document revision histories.
+//!
+//! A revised document links to the one it replaces by holding that document's hash in its
+//! top-level [`REVISION_FIELD`][crate::schema::REVISION_FIELD]. [`verify_chain`] checks a whole
+//! sequence of revisions, oldest first: that each document links to the one before it, uses the
+//! same schema, keeps the values of the schema's
+//! [immutable fields][crate::schema::SchemaBuilder::immutable_field], and is signed by the same
+//! set of Identities as the one before it, with verified signatures. The link and immutable field
+//! checks are the same ones [`Schema::check_revision`] makes. Instead of stopping at the first
+//! problem, it returns a [`ChainReport`] listing every revision along with anything wrong with it,
+//! so an edit history can be shown with the bad revisions marked.
+//!
+//! ```
+//! # use fog_pack::{document::*, history::*, schema::*, types::*, validator::*};
+//! # fn main() -> fog_pack::error::Result<()> {
+//! let schema = Schema::from_doc(&SchemaBuilder::new(Validator::Any).build()?)?;
+//! let key = IdentityKey::new();
+//! let first = NewDocument::new(Some(schema.hash()), fog_pack::fog_value!({"text": "draft"}))?;
+//! let first = schema.validate_new_doc(first.sign(&key)?)?;
+//! let second = NewDocument::new(
+//!     Some(schema.hash()),
+//!     fog_pack::fog_value!({"prev": first.hash().clone(), "text": "final"}),
+//! )?;
+//! let second = schema.validate_new_doc(second.sign(&key)?)?;
+//!
+//! let report = verify_chain(&schema, [&first, &second])?;
+//! assert!(report.is_valid());
+//! assert_eq!(report.head().map(|r| &r.hash), Some(second.hash()));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    document::Document,
+    error::Result,
+    schema::Schema,
+    types::{Hash, Identity, Path, Timestamp},
+};
+
+/// A problem with one revision in a chain, found by [`verify_chain`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChainProblem {
+    /// The document doesn't use the chain's schema.
+    WrongSchema {
+        /// The schema the document actually uses.
+        actual: Option<Hash>,
+    },
+    /// The document doesn't link to the revision before it.
+    BrokenLink {
+        /// The hash of the revision before it.
+        expected: Hash,
+        /// The hash the document actually links to, if any.
+        actual: Option<Hash>,
+    },
+    /// An immutable field had a value in the revision before, and this document changed or
+    /// removed it.
+    ImmutableChanged(Path),
+    /// The document isn't signed by the same set of Identities as the revision before it.
+    SignerChanged {
+        /// The signers of the revision before.
+        expected: Vec<Identity>,
+        /// The signers of this document.
+        actual: Vec<Identity>,
+    },
+    /// The document's signatures haven't been [verified][Document::verify], so its signers are
+    /// only claims. Signer checks against it prove nothing until it is.
+    Unverified,
+}
+
+/// One document in a revision chain, as reported by [`verify_chain`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Revision {
+    /// The hash of the document.
+    pub hash: Hash,
+    /// Who signed the document. Empty if it isn't signed.
+    pub signers: Vec<Identity>,
+    /// The time covered by the document's signature, if it has one.
+    pub signature_time: Option<Timestamp>,
+    /// Everything wrong with this revision. Empty if it's a valid revision of the one before.
+    pub problems: Vec<ChainProblem>,
+}
+
+/// The result of [`verify_chain`]: every revision in the chain, oldest first.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChainReport {
+    /// The revisions, in the order they were given.
+    pub revisions: Vec<Revision>,
+}
+
+impl ChainReport {
+    /// Check if no revision in the chain has any problems.
+    pub fn is_valid(&self) -> bool {
+        self.revisions.iter().all(|r| r.problems.is_empty())
+    }
+
+    /// Get the latest revision in the chain.
+    pub fn head(&self) -> Option<&Revision> {
+        self.revisions.last()
+    }
+
+    /// Get the index of the first revision with a problem, along with that revision.
+    pub fn first_invalid(&self) -> Option<(usize, &Revision)> {
+        self.revisions
+            .iter()
+            .enumerate()
+            .find(|(_, r)| !r.problems.is_empty())
+    }
+}
+
+/// Verify a chain of document revisions, given oldest first. See the [module
+/// documentation][self] for what is checked.
+///
+/// The first document is only checked against the schema; it may link to an earlier revision
+/// that isn't part of the chain. Fails only if a document's data can't be read.
+pub fn verify_chain<'a>(
+    schema: &Schema,
+    docs: impl IntoIterator<Item = &'a Document>,
+) -> Result<ChainReport> {
+    let mut report = ChainReport::default();
+    let mut prev: Option<&Document> = None;
+    for doc in docs {
+        let mut problems = Vec::new();
+        if doc.schema_hash() != Some(schema.hash()) {
+            problems.push(ChainProblem::WrongSchema {
+                actual: doc.schema_hash().cloned(),
+            });
+        }
+        if !doc.is_verified() {
+            problems.push(ChainProblem::Unverified);
+        }
+        if let Some(prev) = prev {
+            let (link, changed) = schema.compare_revisions(prev, doc)?;
+            if link.as_ref() != Some(prev.hash()) {
+                problems.push(ChainProblem::BrokenLink {
+                    expected: prev.hash().clone(),
+                    actual: link,
+                });
+            }
+            problems.extend(changed.into_iter().map(ChainProblem::ImmutableChanged));
+            let same_signers = doc.signers().len() == prev.signers().len()
+                && doc.signers().iter().all(|id| prev.signers().contains(id));
+            if !same_signers {
+                problems.push(ChainProblem::SignerChanged {
+                    expected: prev.signers().to_vec(),
+                    actual: doc.signers().to_vec(),
+                });
+            }
+        }
+        report.revisions.push(Revision {
+            hash: doc.hash().clone(),
+            signers: doc.signers().to_vec(),
+            signature_time: doc.signature_time(),
+            problems,
+        });
+        prev = Some(doc);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        document::NewDocument,
+        fog_value,
+        schema::{NoSchema, SchemaBuilder, TrustedSchema},
+        types::{IdentityKey, Value},
+        validator::Validator,
+    };
+
+    #[test]
+    fn chain() {
+        let id = Path::new().key("id");
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .immutable_field(&id)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let alice = IdentityKey::new();
+        let bob = IdentityKey::new();
+        let doc = |value: Value, key: &IdentityKey| {
+            let doc = NewDocument::new(Some(schema.hash()), value).unwrap();
+            schema.validate_new_doc(doc.sign(key).unwrap()).unwrap()
+        };
+
+        let first = doc(fog_value!({"id": 1u8}), &alice);
+        let second = doc(
+            fog_value!({"id": 1u8, "prev": first.hash().clone()}),
+            &alice,
+        );
+        let third = doc(
+            fog_value!({"id": 1u8, "prev": second.hash().clone()}),
+            &alice,
+        );
+        let report = verify_chain(&schema, [&first, &second, &third]).unwrap();
+        assert!(report.is_valid());
+        assert_eq!(report.revisions.len(), 3);
+        assert_eq!(report.head().unwrap().hash, *third.hash());
+        assert_eq!(report.revisions[0].signers, vec![alice.id().clone()]);
+        assert!(verify_chain(&schema, []).unwrap().is_valid());
+
+        // Every problem with a revision is reported
+        let bad = doc(fog_value!({"id": 2u8, "prev": first.hash().clone()}), &bob);
+        let report = verify_chain(&schema, [&first, &second, &bad]).unwrap();
+        assert!(!report.is_valid());
+        let (index, revision) = report.first_invalid().unwrap();
+        assert_eq!(index, 2);
+        assert_eq!(
+            revision.problems,
+            vec![
+                ChainProblem::BrokenLink {
+                    expected: second.hash().clone(),
+                    actual: Some(first.hash().clone()),
+                },
+                ChainProblem::ImmutableChanged(id),
+                ChainProblem::SignerChanged {
+                    expected: vec![alice.id().clone()],
+                    actual: vec![bob.id().clone()],
+                },
+            ]
+        );
+
+        // Signatures must be verified
+        let (_, encoded) = schema.encode_doc(second.clone()).unwrap();
+        let unverified = TrustedSchema::new(schema.clone())
+            .decode_doc(encoded)
+            .unwrap();
+        let report = verify_chain(&schema, [&first, &unverified, &third]).unwrap();
+        assert_eq!(report.revisions[1].problems, vec![ChainProblem::Unverified]);
+        assert!(report.revisions[2].problems.is_empty());
+        let verified = unverified.verify().unwrap();
+        assert!(verify_chain(&schema, [&first, &verified, &third])
+            .unwrap()
+            .is_valid());
+
+        let other = NoSchema::validate_new_doc(NewDocument::new(None, "x").unwrap()).unwrap();
+        let report = verify_chain(&schema, [&other]).unwrap();
+        assert_eq!(
+            report.revisions[0].problems,
+            vec![ChainProblem::WrongSchema { actual: None }]
+        );
+    }
+}
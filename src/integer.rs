@@ -1,7 +1,9 @@
 use std::cmp;
 use std::cmp::Ordering;
 use std::fmt::{self, Debug, Display, LowerHex, UpperHex};
+use std::num::ParseIntError;
 use std::ops;
+use std::str::FromStr;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub(crate) enum IntPriv {
@@ -14,6 +16,22 @@ pub(crate) enum IntPriv {
 /// Represents a fog-pack integer, whether signed or unsigned.
 ///
 /// A `Value` or `ValueRef` that contains integer can be constructed using `From` trait.
+///
+/// Integers convert to and from every primitive integer type, failing with `TryFrom` when the
+/// value is out of range, and compare directly against them. Ordering works across the whole
+/// range, so negative values are always less than any value only representable as a `u64`. For
+/// arithmetic that can't overflow or panic, use the `checked_*` functions.
+///
+/// ```
+/// # use fog_pack::types::Integer;
+/// let big: Integer = "18446744073709551615".parse().unwrap();
+/// assert_eq!(big, Integer::max_value());
+/// assert!(Integer::from(-1) < big);
+/// assert!(big > -1i8);
+/// assert_eq!(big.checked_add(Integer::from(1)), None);
+/// assert_eq!(big.checked_add(Integer::min_value()), Some(Integer::from(i64::MAX as u64 + 1)));
+/// assert!(u32::try_from(big).is_err());
+/// ```
 #[derive(Copy, Clone, PartialEq, Eq, Hash)]
 pub struct Integer {
     n: IntPriv,
@@ -21,14 +39,14 @@ pub struct Integer {
 
 impl Integer {
     /// Minimum possible integer that can be represented. Equivalent to `i64::min_value()`.
-    pub fn min_value() -> Integer {
+    pub const fn min_value() -> Integer {
         Integer {
             n: IntPriv::NegInt(i64::min_value()),
         }
     }
 
     /// Maximum possible integer that can be represented. Equivalent to `u64::max_value()`.
-    pub fn max_value() -> Integer {
+    pub const fn max_value() -> Integer {
         Integer {
             n: IntPriv::PosInt(u64::max_value()),
         }
@@ -78,6 +96,87 @@ impl Integer {
             IntPriv::NegInt(n) => n as u64,
         }
     }
+
+    /// Returns the integer represented as `i128`, which can hold any integer.
+    #[inline]
+    pub fn as_i128(&self) -> i128 {
+        match self.n {
+            IntPriv::PosInt(n) => n as i128,
+            IntPriv::NegInt(n) => n as i128,
+        }
+    }
+
+    /// Convert an `i128` into an integer, if it's in range.
+    #[inline]
+    fn from_i128(n: i128) -> Option<Integer> {
+        if n < 0 {
+            i64::try_from(n).ok().map(Integer::from)
+        } else {
+            u64::try_from(n).ok().map(Integer::from)
+        }
+    }
+
+    /// Returns `true` if the integer is less than zero.
+    #[inline]
+    pub fn is_negative(&self) -> bool {
+        matches!(self.n, IntPriv::NegInt(_))
+    }
+
+    /// Checked integer addition. Returns `None` if the result is out of range.
+    #[inline]
+    pub fn checked_add(self, rhs: Integer) -> Option<Integer> {
+        Self::from_i128(self.as_i128() + rhs.as_i128())
+    }
+
+    /// Checked integer subtraction. Returns `None` if the result is out of range.
+    #[inline]
+    pub fn checked_sub(self, rhs: Integer) -> Option<Integer> {
+        Self::from_i128(self.as_i128() - rhs.as_i128())
+    }
+
+    /// Checked integer multiplication. Returns `None` if the result is out of range.
+    #[inline]
+    pub fn checked_mul(self, rhs: Integer) -> Option<Integer> {
+        Self::from_i128(self.as_i128().checked_mul(rhs.as_i128())?)
+    }
+
+    /// Checked integer division, rounding towards zero. Returns `None` if `rhs` is zero.
+    #[inline]
+    pub fn checked_div(self, rhs: Integer) -> Option<Integer> {
+        Self::from_i128(self.as_i128().checked_div(rhs.as_i128())?)
+    }
+
+    /// Checked integer remainder. Returns `None` if `rhs` is zero.
+    #[inline]
+    pub fn checked_rem(self, rhs: Integer) -> Option<Integer> {
+        Self::from_i128(self.as_i128().checked_rem(rhs.as_i128())?)
+    }
+
+    /// Checked negation. Returns `None` if the result is out of range.
+    #[inline]
+    pub fn checked_neg(self) -> Option<Integer> {
+        Self::from_i128(-self.as_i128())
+    }
+
+    /// Saturating integer addition, clamping the result to the range of possible integers.
+    #[inline]
+    pub fn saturating_add(self, rhs: Integer) -> Integer {
+        Self::saturate(self.as_i128() + rhs.as_i128())
+    }
+
+    /// Saturating integer subtraction, clamping the result to the range of possible integers.
+    #[inline]
+    pub fn saturating_sub(self, rhs: Integer) -> Integer {
+        Self::saturate(self.as_i128() - rhs.as_i128())
+    }
+
+    fn saturate(n: i128) -> Integer {
+        Self::from_i128(n).unwrap_or(if n < 0 {
+            Integer::min_value()
+        } else {
+            Integer::max_value()
+        })
+    }
 }
 
 pub(crate) fn get_int_internal(val: &Integer) -> IntPriv {
@@ -162,6 +261,19 @@ impl Display for Integer {
     }
 }
 
+impl FromStr for Integer {
+    type Err = ParseIntError;
+
+    /// Parse a decimal integer, anywhere in the range from `i64::MIN` to `u64::MAX`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with('-') {
+            s.parse::<i64>().map(Integer::from)
+        } else {
+            s.parse::<u64>().map(Integer::from)
+        }
+    }
+}
+
 impl UpperHex for Integer {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         UpperHex::fmt(&self.as_bits(), fmt)
@@ -242,6 +354,72 @@ impl_try_from!(i32);
 impl_try_from!(i64);
 impl_try_from!(isize);
 
+impl From<Integer> for i128 {
+    fn from(v: Integer) -> Self {
+        v.as_i128()
+    }
+}
+
+impl TryFrom<Integer> for u128 {
+    type Error = Integer;
+    fn try_from(v: Integer) -> Result<Self, Self::Error> {
+        v.as_u64().map(u128::from).ok_or(v)
+    }
+}
+
+impl TryFrom<i128> for Integer {
+    type Error = i128;
+    fn try_from(n: i128) -> Result<Self, Self::Error> {
+        Integer::from_i128(n).ok_or(n)
+    }
+}
+
+impl TryFrom<u128> for Integer {
+    type Error = u128;
+    fn try_from(n: u128) -> Result<Self, Self::Error> {
+        u64::try_from(n).map(Integer::from).map_err(|_| n)
+    }
+}
+
+macro_rules! impl_cmp_prim {
+    ($t: ty) => {
+        impl PartialEq<$t> for Integer {
+            fn eq(&self, other: &$t) -> bool {
+                self.as_i128() == *other as i128
+            }
+        }
+
+        impl PartialEq<Integer> for $t {
+            fn eq(&self, other: &Integer) -> bool {
+                *self as i128 == other.as_i128()
+            }
+        }
+
+        impl PartialOrd<$t> for Integer {
+            fn partial_cmp(&self, other: &$t) -> Option<Ordering> {
+                Some(self.as_i128().cmp(&(*other as i128)))
+            }
+        }
+
+        impl PartialOrd<Integer> for $t {
+            fn partial_cmp(&self, other: &Integer) -> Option<Ordering> {
+                Some((*self as i128).cmp(&other.as_i128()))
+            }
+        }
+    };
+}
+
+impl_cmp_prim!(u8);
+impl_cmp_prim!(u16);
+impl_cmp_prim!(u32);
+impl_cmp_prim!(u64);
+impl_cmp_prim!(usize);
+impl_cmp_prim!(i8);
+impl_cmp_prim!(i16);
+impl_cmp_prim!(i32);
+impl_cmp_prim!(i64);
+impl_cmp_prim!(isize);
+
 use serde::{
     de::{Deserialize, Deserializer},
     ser::{Serialize, Serializer},
@@ -309,6 +487,74 @@ mod tests {
         assert_eq!(x + y, Integer::from((1u64 << 63) - 2));
     }
 
+    #[test]
+    fn checked() {
+        let max = Integer::max_value();
+        let min = Integer::min_value();
+        let one = Integer::from(1);
+        assert_eq!(max.checked_add(one), None);
+        assert_eq!(min.checked_sub(one), None);
+        assert_eq!(min.checked_add(max), Some(Integer::from(i64::MAX as u64)));
+        assert_eq!(max.checked_sub(max), Some(Integer::from(0)));
+        assert_eq!(
+            Integer::from(u32::MAX).checked_mul(Integer::from(u32::MAX)),
+            Some(Integer::from(u32::MAX as u64 * u32::MAX as u64))
+        );
+        assert_eq!(max.checked_mul(Integer::from(-1)), None);
+        assert_eq!(max.checked_div(Integer::from(0)), None);
+        assert_eq!(
+            Integer::from(-7).checked_div(Integer::from(2)),
+            Some(Integer::from(-3))
+        );
+        assert_eq!(
+            Integer::from(-7).checked_rem(Integer::from(2)),
+            Some(Integer::from(-1))
+        );
+        assert_eq!(min.checked_neg(), Some(Integer::from(1u64 << 63)));
+        assert_eq!(max.checked_neg(), None);
+        assert_eq!(max.saturating_add(one), max);
+        assert_eq!(min.saturating_sub(one), min);
+        assert!(min.is_negative() && !Integer::from(0).is_negative());
+    }
+
+    #[test]
+    fn conversions() {
+        for s in ["0", "-1", "18446744073709551615", "-9223372036854775808"] {
+            let v: Integer = s.parse().unwrap();
+            assert_eq!(v.to_string(), s);
+        }
+        assert_eq!("+5".parse::<Integer>().unwrap(), Integer::from(5));
+        assert_eq!("-0".parse::<Integer>().unwrap(), Integer::from(0u8));
+        assert!("18446744073709551616".parse::<Integer>().is_err());
+        assert!("-9223372036854775809".parse::<Integer>().is_err());
+        assert!("1.0".parse::<Integer>().is_err());
+
+        assert_eq!(i128::from(Integer::min_value()), i64::MIN as i128);
+        assert_eq!(u128::try_from(Integer::max_value()), Ok(u64::MAX as u128));
+        assert!(u128::try_from(Integer::from(-1)).is_err());
+        assert_eq!(Integer::try_from(-1i128), Ok(Integer::from(-1)));
+        assert_eq!(
+            Integer::try_from(u64::MAX as i128 + 1),
+            Err(u64::MAX as i128 + 1)
+        );
+        assert!(Integer::try_from(u128::MAX).is_err());
+        assert_eq!(u8::try_from(Integer::from(255)), Ok(255u8));
+        assert!(u8::try_from(Integer::from(256)).is_err());
+        assert!(i8::try_from(Integer::from(-129)).is_err());
+    }
+
+    #[test]
+    fn compare() {
+        let max = Integer::max_value();
+        assert!(max > i64::MAX);
+        assert!(max == u64::MAX);
+        assert!(Integer::from(-1) < 0u8);
+        assert!(Integer::from(-1) == -1i32);
+        assert!(-1i8 < Integer::from(0));
+        assert!(u64::MAX >= max);
+        assert!(Integer::from(-1) < Integer::from(1u64 << 63));
+    }
+
     #[test]
     fn sub() {
         let x = Integer::min_value();
@@ -0,0 +1,190 @@
+//! Maps keyed by non-string data.
+//!
+//! fog-pack map keys are always strings, so maps keyed by anything else store a canonical string
+//! form of each key instead. Every encoder writes a given key the same way, so the same map always
+//! encodes to the same bytes and hashes the same. The convention is:
+//!
+//! - Integers are written in decimal, with a leading `-` if negative, and without leading zeros
+//!     or a `+` sign. The serializer does this for any integer map key, and the deserializer
+//!     parses them back, rejecting keys in any other form, so maps like `BTreeMap<u64, T>` can be
+//!     used directly with [`NewDocument::new`][crate::document::NewDocument::new] and
+//!     [`NewEntry::new`][crate::entry::NewEntry::new].
+//! - Hashes are written as base58 [multibase][crate::multibase] strings. Wrap them in a
+//!     [`HashKey`] to use them as map keys.
+//!
+//! The encoded map is still ordered by the key strings, not the original keys, so integer keys
+//! don't come out in numeric order when read as a [`Value`][crate::types::Value]. Deserializing
+//! back into a `BTreeMap` puts them in order again. For the same reason, integer-keyed maps can't
+//! be passed to [`NewDocument::new_ordered`][crate::document::NewDocument::new_ordered] or
+//! [`NewEntry::new_ordered`][crate::entry::NewEntry::new_ordered], which require keys that are
+//! already in string order and fail otherwise.
+//!
+//! Schemas can require keys to follow the convention by using [`int_keys`] or [`hash_keys`] as a
+//! map validator's `keys` validator.
+//!
+//! ```
+//! # use fog_pack::{document::*, keys::*, schema::*, validator::*};
+//! # use std::collections::BTreeMap;
+//! # fn main() -> fog_pack::error::Result<()> {
+//! let schema_doc = SchemaBuilder::new(
+//!     MapValidator::new()
+//!         .keys(int_keys())
+//!         .values(StrValidator::new().build())
+//!         .build(),
+//! )
+//! .build()?;
+//! let schema = Schema::from_doc(&schema_doc)?;
+//!
+//! let mut scores: BTreeMap<u64, String> = BTreeMap::new();
+//! scores.insert(9, "nine".into());
+//! scores.insert(10, "ten".into());
+//! let doc = schema.validate_new_doc(NewDocument::new(Some(schema.hash()), &scores)?)?;
+//! let decoded: BTreeMap<u64, String> = doc.deserialize()?;
+//! assert_eq!(decoded, scores);
+//!
+//! // "10" sorts before "9", so the keys aren't in string order
+//! assert!(NewDocument::new_ordered(&scores, Some(schema.hash())).is_err());
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    multibase::{Base, Multibase},
+    types::Hash,
+    validator::StrValidator,
+};
+use regex::Regex;
+use serde::{de::Error as DeError, Deserialize, Deserializer, Serialize, Serializer};
+use std::cmp::Ordering;
+
+/// The longest canonical integer key: `-9223372036854775808` or `18446744073709551615`.
+const MAX_INT_KEY_LEN: u32 = 20;
+
+/// Check if a string is an integer key in canonical form. This doesn't check that the integer
+/// fits in any particular type.
+pub fn is_int_key(key: &str) -> bool {
+    let digits = key.strip_prefix('-').unwrap_or(key);
+    match digits.as_bytes() {
+        [] => false,
+        [b'0'] => digits.len() == key.len(),
+        [first, rest @ ..] => {
+            (b'1'..=b'9').contains(first) && rest.iter().all(|c| c.is_ascii_digit())
+        }
+    }
+}
+
+/// Build a validator that only passes integer keys in canonical form.
+pub fn int_keys() -> StrValidator {
+    StrValidator::new()
+        .matches(Regex::new("^(0|-?[1-9][0-9]*)$").expect("integer key regex should be valid"))
+        .max_len(MAX_INT_KEY_LEN)
+}
+
+/// Build a validator that only passes hash keys in canonical form. This checks the characters,
+/// but not that they decode to a valid hash.
+pub fn hash_keys() -> StrValidator {
+    StrValidator::new()
+        .matches(Regex::new("^z[1-9A-HJ-NP-Za-km-z]+$").expect("hash key regex should be valid"))
+}
+
+/// A [`struct@Hash`] used as a map key. It's written as a base58 multibase string, and can only
+/// be read back from one.
+///
+/// Hash keys are ordered by the hash's bytes.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct HashKey(pub Hash);
+
+impl PartialOrd for HashKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for HashKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.as_ref().cmp(other.0.as_ref())
+    }
+}
+
+impl From<Hash> for HashKey {
+    fn from(hash: Hash) -> Self {
+        Self(hash)
+    }
+}
+
+impl From<HashKey> for Hash {
+    fn from(key: HashKey) -> Self {
+        key.0
+    }
+}
+
+impl Serialize for HashKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0.to_multibase(Base::Base58Btc))
+    }
+}
+
+impl<'de> Deserialize<'de> for HashKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let key = String::deserialize(deserializer)?;
+        let hash = Hash::from_multibase(&key).map_err(D::Error::custom)?;
+        // Only the base58 form is canonical
+        if hash.to_multibase(Base::Base58Btc) != key {
+            return Err(D::Error::custom(format!(
+                "hash key {:?} isn't in canonical form",
+                key
+            )));
+        }
+        Ok(Self(hash))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{de::FogDeserializer, ser::FogSerializer, types::Value};
+    use std::collections::BTreeMap;
+
+    fn encode<S: Serialize>(data: &S) -> Vec<u8> {
+        let mut ser = FogSerializer::from_vec(Vec::new(), false);
+        data.serialize(&mut ser).unwrap();
+        ser.finish()
+    }
+
+    fn decode<'a, D: Deserialize<'a>>(buf: &'a [u8]) -> crate::error::Result<D> {
+        D::deserialize(&mut FogDeserializer::new(buf))
+    }
+
+    #[test]
+    fn int_keys_roundtrip() {
+        let map: BTreeMap<i64, u8> = [(-12, 1), (0, 2), (9, 3), (10, 4), (i64::MIN, 5)].into();
+        let buf = encode(&map);
+        let value: Value = decode(&buf).unwrap();
+        let keys: Vec<&str> = value.as_map().unwrap().keys().map(|k| k.as_str()).collect();
+        assert_eq!(keys, vec!["-12", "-9223372036854775808", "0", "10", "9"]);
+        assert_eq!(decode::<BTreeMap<i64, u8>>(&buf).unwrap(), map);
+        assert!(decode::<BTreeMap<u8, u8>>(&buf).is_err());
+
+        for bad in ["01", "-0", "+1", "", "-", "1a"] {
+            assert!(!is_int_key(bad), "{:?}", bad);
+            let buf = encode(&BTreeMap::from([(bad, 0u8)]));
+            assert!(decode::<BTreeMap<i32, u8>>(&buf).is_err(), "{:?}", bad);
+        }
+        for good in ["0", "-1", "18446744073709551615"] {
+            assert!(is_int_key(good), "{:?}", good);
+        }
+    }
+
+    #[test]
+    fn hash_keys_roundtrip() {
+        let a = HashKey(Hash::new(b"a"));
+        let b = HashKey(Hash::new(b"b"));
+        let map: BTreeMap<HashKey, u8> = [(a.clone(), 1), (b, 2)].into();
+        let buf = encode(&map);
+        assert_eq!(decode::<BTreeMap<HashKey, u8>>(&buf).unwrap(), map);
+
+        let hex = a.0.to_multibase(Base::Base16);
+        let buf = encode(&BTreeMap::from([(hex, 0u8)]));
+        assert!(decode::<BTreeMap<HashKey, u8>>(&buf).is_err());
+    }
+}
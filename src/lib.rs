@@ -21,9 +21,31 @@
 //!     hashes.
 //! - Documents and Entries may be **compressed with zstandard**, which does not change their
 //!     identifying hashes. Zstandard dictionaries are supported when a schema is used.
+//!     The `lz4` feature adds lz4 compression, and the default `zstd` feature may be
+//!     disabled for targets that can't build zstandard.
 //! - Documents and Entries are size-limited and have a limited nesting depth by design.
+//! - The `rayon` feature allows validating many Entries in parallel.
+//! - The `mem-stats` feature adds [memory usage accounting][mem], for checking how much memory
+//!     encoding, decoding, and validation need.
+//! - The crate builds for `wasm32-unknown-unknown` with the `zstd` feature disabled (CI checks
+//!     this with the `lz4` and `wasm` features), and the `wasm` feature adds
+//!     [JavaScript bindings][wasm] for decoding, validating, and encoding data in the browser.
+//! - The `ffi` feature adds a [C interface][ffi], for binding to fog-pack from other languages.
+//! - The `proptest` feature adds [property-testing strategies][strategy] for fog-pack values and
+//!     schemas, and the `arbitrary` feature implements `Arbitrary` for them, for fuzzing.
+//! - The `diagnostics` feature makes validation errors include a short rendering of the value
+//!     that failed and the validator that rejected it, at some cost to validation speed.
+//! - The `graphemes` feature lets string validators limit the number of grapheme clusters, for
+//!     user-facing length limits.
+//! - The `tracing` feature adds [`tracing`](https://crates.io/crates/tracing) spans around
+//!     encoding, decoding, validation, compression, and signing, at the debug level. Spans carry
+//!     the size of the data and, once known, the hash of the document or entry.
 //! - Encrypted objects are available, using the
 //!     [`fog-crypto`](https://crates.io/crates/fog-crypto) crate.
+//! - The default `getrandom` feature lets keys and encrypted objects be made with the operating
+//!     system's randomness. Closed pipelines that only move and validate data, like ones decoding
+//!     with a [`TrustedSchema`][schema::TrustedSchema], can turn off default features (turning
+//!     `zstd` back on if they compress) to drop it and its platform dependencies.
 //!
 //! # Key Concepts
 //!
@@ -196,27 +218,71 @@
 
 #![warn(missing_docs)]
 
+#[cfg(feature = "arbitrary")]
+mod arbitrary_impl;
+mod canonical;
 mod compress;
 mod de;
 mod depth_tracking;
 mod element;
 mod integer;
+mod lint;
 mod marker;
+mod ordered;
+mod path;
 mod ser;
 mod timestamp;
 mod utils;
 mod value;
 mod value_ref;
 
+pub mod annotation;
+pub mod attestation;
+pub mod capability;
+pub mod compact;
 pub mod document;
+pub mod edit;
+pub mod encrypted;
 pub mod entry;
 pub mod error;
+pub mod external;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod files;
+pub mod fuzz;
+pub mod graph;
+pub mod history;
+pub mod keys;
+#[cfg(feature = "mem-stats")]
+pub mod mem;
+pub mod multibase;
+pub mod page;
+pub mod patch;
+pub mod pin;
+pub mod pretty;
 pub mod query;
+pub mod response;
 pub mod schema;
+pub mod signer;
+pub mod store;
+#[cfg(feature = "proptest")]
+pub mod strategy;
+pub mod sync;
+pub mod test_vectors;
 pub mod validator;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod writer;
 
+pub use canonical::{canonicalize, canonicalize_with_report, Repairs};
 use types::*;
 use utils::*;
+
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::ordered::fields_ordered;
+}
+
 pub mod types {
     //! Various fog-pack content types.
     //!
@@ -248,9 +314,11 @@ pub mod types {
     //! - [`LockLockbox`][crate::types::LockLockbox] - An encrypted private key
     //!
     //! A general structure for holding fog-pack data is [`Value`][crate::types::Value]. The non-owning
-    //! version of it is [`ValueRef`][crate::types::ValueRef].
+    //! version of it is [`ValueRef`][crate::types::ValueRef]. Nested values can be picked out of
+    //! either one, or straight out of a document or entry, with a [`Path`][crate::types::Path].
     //!
     pub use crate::integer::*;
+    pub use crate::path::{Path, PathSegment};
     pub use crate::timestamp::*;
     pub use crate::value::Value;
     pub use crate::value_ref::ValueRef;
@@ -280,3 +348,269 @@ pub const MAX_ENTRY_SIZE: usize = (1usize << 16) - 1; // 64 kiB
 /// The maximum allowed size of a raw query, is 64 kiB (65535 bytes). No encoded
 /// query will ever be equal to or larger than this size.
 pub const MAX_QUERY_SIZE: usize = (1usize << 16) - 1; // 64 kiB
+/// Entry keys starting with this prefix are reserved for future use by the format itself. Schemas
+/// can't declare them, and entries can't be created with them.
+pub const RESERVED_KEY_PREFIX: &str = "sys.";
+
+/// The wire format version this crate writes, and the newest one it can read.
+///
+/// Version 0 headers carry no version at all. Later versions will start their headers with an
+/// extra byte holding the version in its upper 6 bits and `0b11` in its lower 2, a combination
+/// that no compression type uses, so data written before versioning existed always reads as
+/// version 0.
+pub const FORMAT_VERSION: u8 = 0;
+
+/// Which wire format versions to accept when decoding documents and entries. Versions newer than
+/// [`FORMAT_VERSION`] are never accepted, as this crate doesn't know how to read them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VersionPolicy {
+    /// Accept any version this crate can read.
+    #[default]
+    Supported,
+    /// Accept only the given version.
+    Exactly(u8),
+    /// Accept the given version or any older one.
+    AtMost(u8),
+}
+
+impl VersionPolicy {
+    /// Check if a format version is accepted.
+    pub fn check(&self, version: u8) -> error::Result<()> {
+        if version > FORMAT_VERSION {
+            return Err(error::Error::BadHeader(format!(
+                "Format version {} is newer than the latest supported version, {}",
+                version, FORMAT_VERSION
+            )));
+        }
+        match *self {
+            VersionPolicy::Exactly(v) if version != v => Err(error::Error::OldVersion(format!(
+                "Format version {} isn't accepted, only version {} is",
+                version, v
+            ))),
+            VersionPolicy::AtMost(v) if version > v => Err(error::Error::OldVersion(format!(
+                "Format version {} isn't accepted, only version {} and older are",
+                version, v
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Lower limits on document, entry, and query sizes and on nesting depth.
+///
+/// The format itself never allows anything beyond [`MAX_DEPTH`], [`MAX_DOC_SIZE`],
+/// [`MAX_ENTRY_SIZE`], or [`MAX_QUERY_SIZE`], and those remain the ceilings: setting a limit
+/// higher than its format maximum has no effect. A deployment that only ever handles small data
+/// can set lower limits on a [`Schema`][schema::Schema] with
+/// [`with_limits`][schema::Schema::with_limits], so that oversized or deeply nested input is
+/// rejected before any real work is spent on it.
+///
+/// Limits are enforced when validating new documents & entries and when decoding documents,
+/// entries, and queries. The size limits also bound decompression, so compressed data that would
+/// expand past them is rejected without being decompressed any further. Trusted decoding skips
+/// them, along with every other check, and only rejects format versions this crate can't read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// The maximum nesting depth of any value. Defaults to [`MAX_DEPTH`].
+    pub max_depth: usize,
+    /// The maximum size of an encoded document, in bytes. Defaults to [`MAX_DOC_SIZE`].
+    pub max_doc_size: usize,
+    /// The maximum size of an encoded entry, in bytes. Defaults to [`MAX_ENTRY_SIZE`].
+    pub max_entry_size: usize,
+    /// The maximum size of an encoded query, in bytes. Defaults to [`MAX_QUERY_SIZE`].
+    pub max_query_size: usize,
+    /// Which wire format versions to accept when decoding. Defaults to any supported version.
+    pub format_version: VersionPolicy,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_depth: MAX_DEPTH,
+            max_doc_size: MAX_DOC_SIZE,
+            max_entry_size: MAX_ENTRY_SIZE,
+            max_query_size: MAX_QUERY_SIZE,
+            format_version: VersionPolicy::Supported,
+        }
+    }
+}
+
+impl Limits {
+    /// Get the nesting depth limit, capped at [`MAX_DEPTH`].
+    pub(crate) fn depth(&self) -> usize {
+        self.max_depth.min(MAX_DEPTH)
+    }
+
+    fn check(len: usize, max: usize) -> error::Result<()> {
+        if len > max {
+            Err(error::Error::LengthTooLong { max, actual: len })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Get the document size limit, capped at [`MAX_DOC_SIZE`].
+    pub(crate) fn doc_size(&self) -> usize {
+        self.max_doc_size.min(MAX_DOC_SIZE)
+    }
+
+    /// Get the entry size limit, capped at [`MAX_ENTRY_SIZE`].
+    pub(crate) fn entry_size(&self) -> usize {
+        self.max_entry_size.min(MAX_ENTRY_SIZE)
+    }
+
+    /// Check the size of an encoded document.
+    pub(crate) fn check_doc(&self, len: usize) -> error::Result<()> {
+        Self::check(len, self.doc_size())
+    }
+
+    /// Check the size of an encoded entry.
+    pub(crate) fn check_entry(&self, len: usize) -> error::Result<()> {
+        Self::check(len, self.entry_size())
+    }
+
+    /// Check the size of an encoded query.
+    pub(crate) fn check_query(&self, len: usize) -> error::Result<()> {
+        Self::check(len, self.max_query_size.min(MAX_QUERY_SIZE))
+    }
+}
+
+/// Rules that signatures must follow, beyond being valid, when decoding documents and entries.
+///
+/// Networks can use a policy to phase out signature algorithms, reject stale documents, and stop
+/// trusting compromised identities, all at the point where data is first accepted. A policy is
+/// set on a [`Schema`][schema::Schema] with
+/// [`with_verify_policy`][schema::Schema::with_verify_policy], or passed to
+/// [`NoSchema::decode_doc_with_policy`][schema::NoSchema::decode_doc_with_policy] for documents
+/// without a schema. Like [`Limits`], it's local configuration, and doesn't change any hashes.
+///
+/// The default policy accepts every valid signature. Unsigned data always passes.
+///
+/// ```
+/// # use fog_pack::{types::*, VerifyPolicy};
+/// let compromised = IdentityKey::new();
+/// let policy = VerifyPolicy::new()
+///     .max_age(TimeDelta::from_secs(24 * 3600))
+///     .max_skew(TimeDelta::from_secs(60))
+///     .revoke(compromised.id().clone());
+/// assert!(policy.is_revoked(compromised.id()));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VerifyPolicy {
+    algorithms: Vec<u8>,
+    max_age: Option<TimeDelta>,
+    max_skew: Option<TimeDelta>,
+    revoked: Vec<Identity>,
+    now: Option<Timestamp>,
+}
+
+impl VerifyPolicy {
+    /// Create a policy that accepts every valid signature.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accept signatures from identities using the given algorithm version. May be called
+    /// multiple times to accept several versions. If never called, every version this crate can
+    /// verify is accepted.
+    pub fn algorithm_add(mut self, version: u8) -> Self {
+        if !self.algorithms.contains(&version) {
+            self.algorithms.push(version);
+        }
+        self
+    }
+
+    /// Reject signed documents whose signature timestamp is older than `max_age`. Signed
+    /// documents without a signature timestamp are rejected too, as their age can't be known.
+    /// Entries carry no signature timestamp, so this doesn't apply to them.
+    pub fn max_age(mut self, max_age: TimeDelta) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Reject signed documents whose signature timestamp is more than `max_skew` in the future,
+    /// allowing for clocks that don't quite agree.
+    pub fn max_skew(mut self, max_skew: TimeDelta) -> Self {
+        self.max_skew = Some(max_skew);
+        self
+    }
+
+    /// Reject anything signed by the given identity.
+    pub fn revoke(mut self, id: Identity) -> Self {
+        if !self.revoked.contains(&id) {
+            self.revoked.push(id);
+        }
+        self
+    }
+
+    /// Check signature timestamps against a fixed time, instead of the current time.
+    pub fn at(mut self, now: Timestamp) -> Self {
+        self.now = Some(now);
+        self
+    }
+
+    /// Check if an identity has been revoked.
+    pub fn is_revoked(&self, id: &Identity) -> bool {
+        self.revoked.contains(id)
+    }
+
+    /// Check a document's signers and signature timestamp against the policy. This is done
+    /// automatically when decoding with a schema that has a policy set.
+    pub fn check_doc(&self, doc: &document::Document) -> error::Result<()> {
+        self.check(doc.signers(), doc.signature_time(), true)
+    }
+
+    /// Check an entry's signer against the policy. This is done automatically when decoding with
+    /// a schema that has a policy set.
+    pub fn check_entry(&self, entry: &entry::Entry) -> error::Result<()> {
+        let signers = entry.signer().map(std::slice::from_ref).unwrap_or(&[]);
+        self.check(signers, None, false)
+    }
+
+    pub(crate) fn check(
+        &self,
+        signers: &[Identity],
+        time: Option<Timestamp>,
+        timed: bool,
+    ) -> error::Result<()> {
+        let fail = |msg: String| {
+            Err(error::Error::FailValidate(error::ValidateError::Other(
+                msg.into(),
+            )))
+        };
+        for signer in signers {
+            if self.is_revoked(signer) {
+                return fail(format!("Signer {} has been revoked", signer));
+            }
+            if !self.algorithms.is_empty() && !self.algorithms.contains(&signer.version()) {
+                return fail(format!(
+                    "Signer {} uses algorithm version {}, which isn't accepted",
+                    signer,
+                    signer.version()
+                ));
+            }
+        }
+        if !timed || signers.is_empty() || (self.max_age.is_none() && self.max_skew.is_none()) {
+            return Ok(());
+        }
+        let time = match time {
+            Some(time) => time,
+            None if self.max_age.is_some() => {
+                return fail("Signature has no timestamp, so its age can't be checked".into())
+            }
+            None => return Ok(()),
+        };
+        let now = self.now.unwrap_or_else(Timestamp::now);
+        if let Some(max_age) = self.max_age {
+            if time.checked_add(max_age).is_some_and(|limit| limit < now) {
+                return fail(format!("Signature from {} is too old", time));
+            }
+        }
+        if let Some(max_skew) = self.max_skew {
+            if now.checked_add(max_skew).is_some_and(|limit| time > limit) {
+                return fail(format!("Signature from {} is in the future", time));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,395 @@
+//! Checks for validators that can never pass, or look like mistakes, run while building a schema.
+
+use crate::{validator::*, MAX_DOC_SIZE};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+/// How serious a [`Lint`] is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// Probably a mistake, but the schema can still be built.
+    Warning,
+    /// The validator can never pass, so [`build`][crate::schema::SchemaBuilder::build] will fail.
+    Error,
+}
+
+/// A problem found in one of a schema's validators. See
+/// [`SchemaBuilder::lint`][crate::schema::SchemaBuilder::lint].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lint {
+    /// How serious the problem is.
+    pub severity: Severity,
+    /// Where the validator is in the schema, as `/`-separated steps from the top of the schema,
+    /// like `doc/req/name` or `types/Count/multi/1`.
+    pub location: String,
+    /// What the problem is.
+    pub message: String,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{} at {}: {}", severity, self.location, self.message)
+    }
+}
+
+/// Check every validator in a schema, given the document validator, the entry validators, and
+/// the stored types. Errors come before warnings, and otherwise lints are in the order found.
+pub(crate) fn lint<'a>(
+    doc: &Validator,
+    entries: impl IntoIterator<Item = (&'a str, &'a Validator)>,
+    types: &BTreeMap<String, Validator>,
+) -> Vec<Lint> {
+    let mut linter = Linter::default();
+    linter.walk(doc, "doc".into());
+    for (key, validator) in entries {
+        linter.walk(validator, format!("entries/{}", key));
+    }
+
+    // Follow references out to the types they use, so types only used by unused types are also
+    // reported as unused
+    let mut checked = BTreeSet::new();
+    while let Some(name) = linter.refs.iter().find(|n| !checked.contains(*n)).cloned() {
+        checked.insert(name.clone());
+        match types.get(&name) {
+            Some(validator) => linter.walk(validator, format!("types/{}", name)),
+            None => linter.warn(
+                format!("types/{}", name),
+                "is referenced, but not defined, so references to it always fail".into(),
+            ),
+        }
+    }
+    for (name, validator) in types.iter().filter(|(n, _)| !checked.contains(*n)) {
+        let location = format!("types/{}", name);
+        linter.warn(location.clone(), "is never referenced".into());
+        linter.walk(validator, location);
+    }
+
+    linter.lints.sort_by(|a, b| b.severity.cmp(&a.severity));
+    linter.lints
+}
+
+#[derive(Default)]
+struct Linter {
+    lints: Vec<Lint>,
+    refs: BTreeSet<String>,
+}
+
+impl Linter {
+    fn warn(&mut self, location: String, message: String) {
+        self.lints.push(Lint {
+            severity: Severity::Warning,
+            location,
+            message,
+        });
+    }
+
+    fn error(&mut self, location: &str, message: impl Into<String>) {
+        self.lints.push(Lint {
+            severity: Severity::Error,
+            location: location.to_owned(),
+            message: message.into(),
+        });
+    }
+
+    fn walk(&mut self, validator: &Validator, location: String) {
+        match validator {
+            Validator::Int(v) => {
+                self.bounds(&location, Some(&v.min), Some(&v.max), v.ex_min, v.ex_max);
+                if v.bits_set & v.bits_clr != 0 {
+                    self.error(&location, "bits_set and bits_clr require the same bit");
+                }
+                let ok = |i: &_| in_bounds(i, Some(&v.min), Some(&v.max), v.ex_min, v.ex_max);
+                self.in_list(&location, &v.in_list, &v.nin_list, ok);
+            }
+            Validator::F32(v) => {
+                let min = Some(&v.min).filter(|m| !m.is_nan());
+                let max = Some(&v.max).filter(|m| !m.is_nan());
+                self.bounds(&location, min, max, v.ex_min, v.ex_max);
+                let ok = |f: &_| in_bounds(f, min, max, v.ex_min, v.ex_max);
+                self.in_list(&location, &v.in_list, &v.nin_list, ok);
+            }
+            Validator::F64(v) => {
+                let min = Some(&v.min).filter(|m| !m.is_nan());
+                let max = Some(&v.max).filter(|m| !m.is_nan());
+                self.bounds(&location, min, max, v.ex_min, v.ex_max);
+                let ok = |f: &_| in_bounds(f, min, max, v.ex_min, v.ex_max);
+                self.in_list(&location, &v.in_list, &v.nin_list, ok);
+            }
+            Validator::Num(v) => {
+                let min = Some(&v.min).filter(|m| !m.is_nan());
+                let max = Some(&v.max).filter(|m| !m.is_nan());
+                self.bounds(&location, min, max, v.ex_min, v.ex_max);
+            }
+            Validator::Bin(v) => {
+                let min = Some(&v.min).filter(|m| !m.is_empty() || v.ex_min);
+                let max = Some(&v.max).filter(|m| !m.is_empty());
+                self.bounds(&location, min, max, v.ex_min, v.ex_max);
+                self.lengths(&location, v.min_len, v.max_len);
+                if v.bits_set
+                    .iter()
+                    .zip(v.bits_clr.iter())
+                    .any(|(s, c)| s & c != 0)
+                {
+                    self.error(&location, "bits_set and bits_clr require the same bit");
+                }
+                let ok = |b: &serde_bytes::ByteBuf| {
+                    in_bounds(b, min, max, v.ex_min, v.ex_max)
+                        && (v.min_len..=v.max_len).contains(&(b.len() as u32))
+                };
+                self.in_list(&location, &v.in_list, &v.nin_list, ok);
+            }
+            Validator::Str(v) => {
+                self.lengths(&location, v.min_len, v.max_len);
+                if v.min_char > v.max_char {
+                    self.error(&location, "min_char is greater than max_char");
+                }
+                if v.min_grapheme > v.max_grapheme {
+                    self.error(&location, "min_grapheme is greater than max_grapheme");
+                }
+                if v.min_grapheme > v.max_char {
+                    self.error(&location, "min_grapheme is greater than max_char");
+                }
+                let ok = |s: &String| {
+                    let chars = s.chars().count() as u32;
+                    (v.min_len..=v.max_len).contains(&(s.len() as u32))
+                        && (v.min_char..=v.max_char).contains(&chars)
+                };
+                self.in_list(&location, &v.in_list, &v.nin_list, ok);
+            }
+            Validator::Array(v) => {
+                self.lengths(&location, v.min_len, v.max_len);
+                let ok = |a: &Vec<_>| (v.min_len..=v.max_len).contains(&(a.len() as u32));
+                self.in_list(&location, &v.in_list, &v.nin_list, ok);
+                self.walk(&v.items, format!("{}/items", location));
+                for (i, validator) in v.prefix.iter().enumerate() {
+                    self.walk(validator, format!("{}/prefix/{}", location, i));
+                }
+                for (i, validator) in v.contains.iter().enumerate() {
+                    self.walk(validator, format!("{}/contains/{}", location, i));
+                }
+            }
+            Validator::Map(v) => {
+                self.lengths(&location, v.min_len, v.max_len);
+                if v.req.len() > v.max_len as usize {
+                    self.error(&location, "has more required fields than max_len allows");
+                }
+                if v.req_any.iter().any(|set| set.is_empty()) {
+                    self.error(&location, "req_any has an empty set of keys");
+                }
+                for (prefix, max) in v.max_prefix.iter() {
+                    let required = v.req.keys().filter(|k| k.starts_with(prefix.as_str()));
+                    if required.count() > *max as usize {
+                        self.error(
+                            &location,
+                            format!(
+                                "has more required fields starting with {:?} than max_prefix allows",
+                                prefix
+                            ),
+                        );
+                    }
+                }
+                if let Some(key) = v.req.keys().find(|k| v.opt.contains_key(*k)) {
+                    self.warn(
+                        location.clone(),
+                        format!("field {:?} is both required and optional", key),
+                    );
+                }
+                let ok = |m: &BTreeMap<_, _>| (v.min_len..=v.max_len).contains(&(m.len() as u32));
+                self.in_list(&location, &v.in_list, &v.nin_list, ok);
+                if let Some(keys) = &v.keys {
+                    self.walk(&Validator::Str(keys.clone()), format!("{}/keys", location));
+                }
+                if let Some(values) = &v.values {
+                    self.walk(values, format!("{}/values", location));
+                }
+                for (key, validator) in v.req.iter() {
+                    self.walk(validator, format!("{}/req/{}", location, key));
+                }
+                for (key, validator) in v.opt.iter() {
+                    self.walk(validator, format!("{}/opt/{}", location, key));
+                }
+            }
+            Validator::Time(v) => {
+                self.bounds(&location, Some(&v.min), Some(&v.max), v.ex_min, v.ex_max);
+                let ok = |t: &_| in_bounds(t, Some(&v.min), Some(&v.max), v.ex_min, v.ex_max);
+                self.in_list(&location, &v.in_list, &v.nin_list, ok);
+            }
+            Validator::Hash(v) => {
+                self.in_list(&location, &v.in_list, &v.nin_list, |_| true);
+                if let Some(link) = &v.link {
+                    self.walk(link, format!("{}/link", location));
+                }
+            }
+            Validator::Identity(v) => {
+                self.in_list(&location, &v.in_list, &v.nin_list, |_| true);
+            }
+            Validator::Ref(name) => {
+                self.refs.insert(name.clone());
+            }
+            Validator::Multi(v) => {
+                if v.0.is_empty() {
+                    self.warn(
+                        location.clone(),
+                        "has no validators, so it always fails".into(),
+                    );
+                }
+                for (i, validator) in v.0.iter().enumerate() {
+                    self.walk(validator, format!("{}/multi/{}", location, i));
+                }
+            }
+            Validator::Enum(v) => {
+                if v.var.is_empty() {
+                    self.warn(
+                        location.clone(),
+                        "has no variants, so it always fails".into(),
+                    );
+                }
+                for (name, validator) in v.var.iter() {
+                    if let Some(validator) = validator {
+                        self.walk(validator, format!("{}/var/{}", location, name));
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn bounds<T: PartialOrd>(
+        &mut self,
+        location: &str,
+        min: Option<&T>,
+        max: Option<&T>,
+        ex_min: bool,
+        ex_max: bool,
+    ) {
+        if let (Some(min), Some(max)) = (min, max) {
+            if min > max {
+                self.error(location, "min is greater than max");
+            } else if min == max && (ex_min || ex_max) {
+                self.error(location, "min equals max, but one of them is exclusive");
+            }
+        }
+    }
+
+    fn lengths(&mut self, location: &str, min_len: u32, max_len: u32) {
+        if min_len > max_len {
+            self.error(location, "min_len is greater than max_len");
+        } else if min_len as usize > MAX_DOC_SIZE {
+            self.error(location, "min_len is larger than the maximum document size");
+        }
+    }
+
+    fn in_list<T: PartialEq>(
+        &mut self,
+        location: &str,
+        in_list: &[T],
+        nin_list: &[T],
+        ok: impl Fn(&T) -> bool,
+    ) {
+        if !in_list.is_empty() && in_list.iter().all(|v| nin_list.contains(v) || !ok(v)) {
+            self.error(
+                location,
+                "every value in the `in` list is excluded by the validator's other limits",
+            );
+        }
+    }
+}
+
+fn in_bounds<T: PartialOrd>(
+    value: &T,
+    min: Option<&T>,
+    max: Option<&T>,
+    ex_min: bool,
+    ex_max: bool,
+) -> bool {
+    let above = min.is_none_or(|min| value > min || (!ex_min && value == min));
+    let below = max.is_none_or(|max| value < max || (!ex_max && value == max));
+    above && below
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::schema::SchemaBuilder;
+
+    #[test]
+    fn impossible() {
+        let fails = |validator: Validator| {
+            let err = SchemaBuilder::new(MapValidator::new().req_add("a", validator).build())
+                .build()
+                .unwrap_err();
+            assert!(err.to_string().contains("doc/req/a"), "{}", err);
+        };
+        fails(IntValidator::new().min(5).max(4).build());
+        fails(IntValidator::new().min(5).max(5).ex_max(true).build());
+        fails(IntValidator::new().in_add(1).nin_add(1).build());
+        fails(IntValidator::new().in_add(1).min(2).build());
+        fails(IntValidator::new().bits_set(1u64).bits_clr(3u64).build());
+        fails(F64Validator::new().min(1.0).max(0.5).build());
+        fails(StrValidator::new().min_len(3).max_len(2).build());
+        fails(StrValidator::new().in_add("long").max_len(2).build());
+        fails(BinValidator::new().min(vec![2]).max(vec![1]).build());
+        fails(ArrayValidator::new().min_len(2).max_len(1).build());
+        fails(MapValidator::new().min_len(u32::MAX).build());
+
+        // Nested validators are reported with their full location
+        let err = SchemaBuilder::new(Validator::Any)
+            .entry_add(
+                "post",
+                ArrayValidator::new()
+                    .items(StrValidator::new().min_char(2).max_char(1).build())
+                    .build(),
+                None,
+            )
+            .build()
+            .unwrap_err();
+        assert!(err.to_string().contains("entries/post/items"), "{}", err);
+
+        // Defaults and open-ended bounds are fine
+        assert!(SchemaBuilder::new(
+            MapValidator::new()
+                .req_add("a", IntValidator::new().in_add(1).nin_add(2).build())
+                .req_add("b", F32Validator::new().max(1.0).build())
+                .req_add("c", BinValidator::new().max(vec![1]).build())
+                .req_add("d", TimeValidator::new().build())
+                .build()
+        )
+        .build()
+        .is_ok());
+    }
+
+    #[test]
+    fn warnings() {
+        let builder = SchemaBuilder::new(
+            MapValidator::new()
+                .req_add("a", Validator::new_ref("Used"))
+                .req_add("b", Validator::new_ref("Missing"))
+                .req_add("c", Validator::Multi(MultiValidator::new()))
+                .build(),
+        )
+        .type_add("Used", Validator::new_ref("AlsoUsed"))
+        .type_add("AlsoUsed", Validator::Any)
+        .type_add("Unused", IntValidator::new().min(1).max(0).build());
+        let lints = builder.lint();
+        let found = |severity, location: &str| {
+            lints
+                .iter()
+                .any(|l| l.severity == severity && l.location == location)
+        };
+        assert_eq!(lints[0].severity, Severity::Error);
+        assert!(found(Severity::Error, "types/Unused"));
+        assert!(found(Severity::Warning, "types/Unused"));
+        assert!(found(Severity::Warning, "types/Missing"));
+        assert!(found(Severity::Warning, "doc/req/c"));
+        assert!(!lints
+            .iter()
+            .any(|l| l.location.contains("Used") && !l.location.contains("Unused")));
+        assert!(builder.build().is_err());
+    }
+}
@@ -0,0 +1,290 @@
+//! Memory usage accounting.
+//!
+//! Enabled with the `mem-stats` feature. This provides [`TrackingAlloc`], a global allocator
+//! wrapper that counts the bytes allocated by each thread, and [`measure`], which runs a closure
+//! and reports the peak transient memory it used. Together they let a deployment with a tight
+//! memory budget check how much memory encoding, decoding, and validation actually need for its
+//! data.
+//!
+//! Measurements only work if the tracking allocator is installed as the global allocator:
+//!
+//! ```
+//! # use fog_pack::{document::*, mem::*, schema::*};
+//! #[global_allocator]
+//! static ALLOC: TrackingAlloc = TrackingAlloc::new();
+//!
+//! # fn main() -> fog_pack::error::Result<()> {
+//! let doc = NewDocument::new(None, vec![0u32; 1000])?;
+//! let (doc, stats) = measure(|| NoSchema::validate_new_doc(doc));
+//! let doc = doc?;
+//! assert!(stats.peak > 0);
+//!
+//! let (_, encoded) = NoSchema::encode_doc(doc)?;
+//! let (doc, stats) = measure(|| NoSchema::decode_doc(encoded));
+//! println!("Decoding took at most {} bytes", stats.peak);
+//! # doc?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Memory is accounted per-thread, so work offloaded to other threads (by the `rayon` feature or
+//! async encoding) isn't counted by a measurement taken on the calling thread.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    // Bytes currently allocated by this thread. Memory freed by a different thread than the one
+    // that allocated it can drive this negative, so it's signed.
+    static CURRENT: Cell<isize> = const { Cell::new(0) };
+    // The highest value `CURRENT` has reached since the last reset.
+    static PEAK: Cell<isize> = const { Cell::new(0) };
+    // Number of allocations made by this thread.
+    static COUNT: Cell<usize> = const { Cell::new(0) };
+}
+
+fn record_alloc(size: usize) {
+    // `try_with` fails while the thread is being torn down; those allocations go uncounted.
+    let _ = CURRENT.try_with(|current| {
+        let now = current.get().wrapping_add(size as isize);
+        current.set(now);
+        let _ = PEAK.try_with(|peak| {
+            if now > peak.get() {
+                peak.set(now)
+            }
+        });
+    });
+    let _ = COUNT.try_with(|count| count.set(count.get().wrapping_add(1)));
+}
+
+fn record_dealloc(size: usize) {
+    let _ = CURRENT.try_with(|current| current.set(current.get().wrapping_sub(size as isize)));
+}
+
+/// A global allocator that tracks memory usage for [`measure`].
+///
+/// Wraps another allocator - the system allocator by default - and counts the bytes each thread
+/// allocates and frees. The bookkeeping is a handful of thread-local updates per allocation.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TrackingAlloc<A = System> {
+    inner: A,
+}
+
+impl TrackingAlloc<System> {
+    /// Create a tracking allocator wrapping the system allocator.
+    pub const fn new() -> Self {
+        Self { inner: System }
+    }
+}
+
+impl<A> TrackingAlloc<A> {
+    /// Create a tracking allocator wrapping some other allocator.
+    pub const fn wrap(inner: A) -> Self {
+        Self { inner }
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for TrackingAlloc<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc(layout);
+        if !ptr.is_null() {
+            INSTALLED.store(true, Ordering::Relaxed);
+            record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            INSTALLED.store(true, Ordering::Relaxed);
+            record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.dealloc(ptr, layout);
+        record_dealloc(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = self.inner.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            // Count a reallocation as the new block existing alongside the old one, as it may
+            // well have been during the copy.
+            record_alloc(new_size);
+            record_dealloc(layout.size());
+        }
+        new_ptr
+    }
+}
+
+/// Memory usage recorded by [`measure`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemStats {
+    /// The most memory, in bytes, that was allocated at any one time during the measurement,
+    /// beyond what was already allocated when it started.
+    pub peak: usize,
+    /// The memory, in bytes, still allocated when the measurement ended. This includes whatever
+    /// the measured closure returned.
+    pub retained: usize,
+    /// The number of allocations made during the measurement, including reallocations.
+    pub allocations: usize,
+}
+
+/// Check if a [`TrackingAlloc`] is installed as the global allocator. If it isn't, all
+/// measurements will be zero.
+pub fn is_tracking() -> bool {
+    if INSTALLED.load(Ordering::Relaxed) {
+        return true;
+    }
+    // Nothing may have been allocated yet, so make sure something has been.
+    drop(std::hint::black_box(Box::new(0u8)));
+    INSTALLED.load(Ordering::Relaxed)
+}
+
+/// Run a closure and measure the memory it allocates on the current thread.
+///
+/// Measurements nest: the peak of an inner measurement doesn't hide anything from the outer
+/// one.
+pub fn measure<T>(f: impl FnOnce() -> T) -> (T, MemStats) {
+    let start = CURRENT.with(|c| c.get());
+    let outer_peak = PEAK.with(|p| p.replace(start));
+    let start_count = COUNT.with(|c| c.get());
+
+    let ret = f();
+
+    let end = CURRENT.with(|c| c.get());
+    let peak = PEAK.with(|p| {
+        let peak = p.get();
+        p.set(peak.max(outer_peak));
+        peak
+    });
+    let stats = MemStats {
+        peak: peak.saturating_sub(start).max(0) as usize,
+        retained: end.saturating_sub(start).max(0) as usize,
+        allocations: COUNT.with(|c| c.get()).wrapping_sub(start_count),
+    };
+    (ret, stats)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{document::*, schema::*};
+
+    #[global_allocator]
+    static ALLOC: TrackingAlloc = TrackingAlloc::new();
+
+    #[test]
+    fn measures() {
+        assert!(is_tracking());
+        let (v, stats) = measure(|| {
+            let big = vec![0u8; 1 << 20];
+            drop(std::hint::black_box(big));
+            vec![1u8; 1024]
+        });
+        assert!(stats.peak >= 1 << 20);
+        assert!(stats.retained >= v.len());
+        assert!(stats.retained < 1 << 20);
+        assert!(stats.allocations >= 2);
+
+        // Nested measurements don't hide the inner peak from the outer one
+        let (_, outer) = measure(|| {
+            let (_, inner) = measure(|| drop(std::hint::black_box(vec![0u8; 1 << 16])));
+            assert!(inner.peak >= 1 << 16);
+        });
+        assert!(outer.peak >= 1 << 16);
+    }
+
+    #[test]
+    fn measures_decoding() {
+        let data: Vec<u64> = (0..10_000).collect();
+        let doc = NewDocument::new(None, &data).unwrap();
+        let doc = NoSchema::validate_new_doc(doc).unwrap();
+        let (_, encoded) = NoSchema::encode_doc(doc).unwrap();
+        let (doc, stats) = measure(|| NoSchema::decode_doc(encoded).unwrap());
+        // The decoded document is retained, so at least its data must be counted
+        assert!(stats.retained >= doc.data().len());
+        assert!(stats.peak >= stats.retained);
+    }
+
+    #[test]
+    fn validation_allocations() {
+        use crate::validator::*;
+        let schema_doc = SchemaBuilder::new(
+            ArrayValidator::new()
+                .items(
+                    MapValidator::new()
+                        .req_add("id", IntValidator::new().max(u32::MAX).build())
+                        .req_add("name", StrValidator::new().max_len(64).build())
+                        .build(),
+                )
+                .build(),
+        )
+        .build()
+        .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let allocations = |len: u32| {
+            let data: Vec<_> = (0..len)
+                .map(|id| crate::fog_value!({"id": id, "name": "item"}))
+                .collect();
+            let doc = NewDocument::new(Some(schema.hash()), data).unwrap();
+            let (doc, stats) = measure(|| schema.validate_new_doc(doc).unwrap());
+            drop(doc);
+            stats.allocations
+        };
+        // Validating more data doesn't allocate any more
+        assert_eq!(allocations(10), allocations(10_000));
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn entry_decode_memory() {
+        use crate::{
+            compress::{CompressType, Compressor, Zstd, ALGORITHM_ZSTD},
+            element::{serialize_elem, Element},
+            validator::Validator,
+        };
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .entry_add("e", Validator::Any, None)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let parent = NewDocument::new(Some(schema.hash()), 1u8).unwrap();
+        let parent = schema.validate_new_doc(parent).unwrap();
+
+        // Encode an unsigned entry holding the given data, compressed with zstd
+        let encode = |data: &[u8]| {
+            let header = vec![CompressType::General(ALGORITHM_ZSTD).into(), 0, 0];
+            let mut entry = Zstd.compress(3, header, data).unwrap();
+            let len = (entry.len() - 3).to_le_bytes();
+            entry[1] = len[0];
+            entry[2] = len[1];
+            entry
+        };
+        let text = "a".repeat(60_000);
+        let mut data = Vec::new();
+        serialize_elem(&mut data, Element::Str(&text));
+
+        // Decoding needs little more memory than the entry itself
+        let encoded = encode(&data);
+        let (entry, stats) = measure(|| schema.decode_entry(encoded, "e", &parent).unwrap());
+        let entry = entry.complete().unwrap();
+        assert_eq!(entry.data(), &data[..]);
+        assert!(stats.peak < 2 * crate::MAX_ENTRY_SIZE);
+
+        // Malformed data is caught in the first decompressed chunk, long before the whole entry has
+        // been expanded
+        let mut bad = vec![0xc1];
+        bad.extend_from_slice(&data);
+        let encoded = encode(&bad);
+        let (result, stats) = measure(|| schema.decode_entry(encoded, "e", &parent));
+        assert!(matches!(result, Err(crate::error::Error::BadEncode(_))));
+        assert!(stats.peak < 16 * 1024);
+    }
+}
@@ -0,0 +1,263 @@
+//! Multibase text forms of hashes and other identifiers.
+//!
+//! [Multibase](https://github.com/multiformats/multibase) strings start with a single character
+//! naming how the rest of the string is encoded, so they can be decoded without knowing the
+//! encoding ahead of time. Many content-addressed systems expect identifiers in this form. This
+//! module converts the raw bytes of a [`Hash`], [`Identity`], [`StreamId`], or [`LockId`] to and
+//! from multibase strings, and the [pretty printer][crate::pretty::PrettyOptions::multibase] can
+//! render them the same way.
+//!
+//! ```
+//! # use fog_pack::{multibase::*, types::*};
+//! # fn main() -> fog_pack::error::Result<()> {
+//! let hash = Hash::new(b"hello");
+//! let text = hash.to_multibase(Base::Base58Btc);
+//! assert!(text.starts_with('z'));
+//! assert_eq!(Hash::from_multibase(&text)?, hash);
+//! assert_eq!(Hash::from_multibase(&hash.to_multibase(Base::Base64Url))?, hash);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::error::{Error, Result};
+use base64::engine::{general_purpose, Engine};
+use fog_crypto::{hash::Hash, identity::Identity, lock::LockId, stream::StreamId};
+use std::{convert::TryFrom, fmt::Write};
+
+/// A multibase encoding.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Base {
+    /// Lowercase hexadecimal, prefixed with `f`. Uppercase hexadecimal, prefixed with `F`, is also
+    /// accepted when decoding.
+    Base16,
+    /// Base58 with the Bitcoin alphabet, prefixed with `z`.
+    Base58Btc,
+    /// Standard base64 without padding, prefixed with `m`.
+    Base64,
+    /// URL-safe base64 without padding, prefixed with `u`.
+    Base64Url,
+}
+
+impl Base {
+    /// Get the character that prefixes strings in this encoding.
+    pub fn prefix(&self) -> char {
+        match self {
+            Base::Base16 => 'f',
+            Base::Base58Btc => 'z',
+            Base::Base64 => 'm',
+            Base::Base64Url => 'u',
+        }
+    }
+
+    /// Look up an encoding by its prefix character.
+    pub fn from_prefix(prefix: char) -> Option<Self> {
+        match prefix {
+            'f' | 'F' => Some(Base::Base16),
+            'z' => Some(Base::Base58Btc),
+            'm' => Some(Base::Base64),
+            'u' => Some(Base::Base64Url),
+            _ => None,
+        }
+    }
+}
+
+const BASE58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// Encode bytes as a multibase string, appending it to `out`.
+pub fn encode_into(base: Base, bytes: &[u8], out: &mut String) {
+    out.push(base.prefix());
+    match base {
+        Base::Base16 => {
+            for b in bytes {
+                write!(out, "{:02x}", b).unwrap();
+            }
+        }
+        Base::Base58Btc => base58_encode(bytes, out),
+        Base::Base64 => general_purpose::STANDARD_NO_PAD.encode_string(bytes, out),
+        Base::Base64Url => general_purpose::URL_SAFE_NO_PAD.encode_string(bytes, out),
+    }
+}
+
+/// Encode bytes as a multibase string.
+pub fn encode(base: Base, bytes: &[u8]) -> String {
+    let mut out = String::new();
+    encode_into(base, bytes, &mut out);
+    out
+}
+
+/// Decode a multibase string, returning the encoding it used and the decoded bytes. Fails if the
+/// prefix isn't one of the supported [`Base`] encodings, or the rest of the string isn't valid
+/// in that encoding.
+pub fn decode(text: &str) -> Result<(Base, Vec<u8>)> {
+    let mut chars = text.chars();
+    let prefix = chars
+        .next()
+        .ok_or_else(|| Error::BadEncode("multibase string is empty".into()))?;
+    let base = Base::from_prefix(prefix)
+        .ok_or_else(|| Error::BadEncode(format!("unsupported multibase prefix {:?}", prefix)))?;
+    let rest = chars.as_str();
+    let bytes = match base {
+        Base::Base16 => base16_decode(rest),
+        Base::Base58Btc => base58_decode(rest),
+        Base::Base64 => general_purpose::STANDARD_NO_PAD.decode(rest).ok(),
+        Base::Base64Url => general_purpose::URL_SAFE_NO_PAD.decode(rest).ok(),
+    };
+    let bytes = bytes.ok_or_else(|| {
+        Error::BadEncode(format!("invalid {:?} multibase string {:?}", base, text))
+    })?;
+    Ok((base, bytes))
+}
+
+fn base16_decode(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 2 != 0 || !text.bytes().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn base58_encode(bytes: &[u8], out: &mut String) {
+    let zeros = bytes.iter().take_while(|b| **b == 0).count();
+    // Little-endian base58 digits of the non-zero part
+    let mut digits: Vec<u8> = Vec::with_capacity(bytes.len() * 138 / 100 + 1);
+    for &byte in &bytes[zeros..] {
+        let mut carry = byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+    out.extend(std::iter::repeat('1').take(zeros));
+    out.extend(
+        digits
+            .iter()
+            .rev()
+            .map(|d| BASE58_ALPHABET[*d as usize] as char),
+    );
+}
+
+fn base58_decode(text: &str) -> Option<Vec<u8>> {
+    let zeros = text.bytes().take_while(|c| *c == b'1').count();
+    // Little-endian bytes of the non-zero part
+    let mut bytes: Vec<u8> = Vec::with_capacity(text.len());
+    for c in text.bytes().skip(zeros) {
+        let mut carry = BASE58_ALPHABET.iter().position(|a| *a == c)? as u32;
+        for byte in bytes.iter_mut() {
+            carry += (*byte as u32) * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+    bytes.extend(std::iter::repeat(0).take(zeros));
+    bytes.reverse();
+    Some(bytes)
+}
+
+/// Identifiers that can be converted to and from multibase strings. The string holds the same
+/// bytes the identifier is encoded as inside fog-pack data.
+pub trait Multibase: Sized {
+    /// Encode as a multibase string.
+    fn to_multibase(&self, base: Base) -> String;
+
+    /// Decode from a multibase string, in any supported encoding. Fails if the string isn't valid
+    /// multibase, or doesn't hold a valid identifier of this type.
+    fn from_multibase(text: &str) -> Result<Self>;
+}
+
+impl Multibase for Hash {
+    fn to_multibase(&self, base: Base) -> String {
+        encode(base, self.as_ref())
+    }
+
+    fn from_multibase(text: &str) -> Result<Self> {
+        let (_, bytes) = decode(text)?;
+        Ok(Hash::try_from(bytes.as_slice())?)
+    }
+}
+
+macro_rules! impl_multibase {
+    ($t:ty) => {
+        impl Multibase for $t {
+            fn to_multibase(&self, base: Base) -> String {
+                let mut bytes = Vec::with_capacity(self.size());
+                self.encode_vec(&mut bytes);
+                encode(base, &bytes)
+            }
+
+            fn from_multibase(text: &str) -> Result<Self> {
+                let (_, bytes) = decode(text)?;
+                Ok(<$t>::try_from(bytes.as_slice())?)
+            }
+        }
+    };
+}
+
+impl_multibase!(Identity);
+impl_multibase!(StreamId);
+impl_multibase!(LockId);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::{IdentityKey, StreamKey};
+
+    #[test]
+    fn known_vectors() {
+        // From the multibase test vectors
+        let text = b"yes mani !";
+        assert_eq!(encode(Base::Base16, text), "f796573206d616e692021");
+        assert_eq!(encode(Base::Base58Btc, text), "z7paNL19xttacUY");
+        assert_eq!(encode(Base::Base64, text), "meWVzIG1hbmkgIQ");
+        assert_eq!(encode(Base::Base64Url, text), "ueWVzIG1hbmkgIQ");
+        let zeros = b"\x00\x00yes mani !";
+        assert_eq!(encode(Base::Base58Btc, zeros), "z117paNL19xttacUY");
+        assert_eq!(encode(Base::Base58Btc, b""), "z");
+
+        for text in [
+            "f796573206d616e692021",
+            "F796573206D616E692021",
+            "z7paNL19xttacUY",
+            "meWVzIG1hbmkgIQ",
+            "ueWVzIG1hbmkgIQ",
+        ] {
+            assert_eq!(decode(text).unwrap().1, b"yes mani !");
+        }
+        assert_eq!(decode("z117paNL19xttacUY").unwrap().1, zeros);
+
+        for bad in ["", "x1234", "f123", "fzz", "z0OIl", "m!!"] {
+            assert!(decode(bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn identifiers() {
+        let hash = Hash::new(b"data");
+        let id = IdentityKey::new().id().clone();
+        let stream = StreamKey::new().id().to_owned();
+        for base in [Base::Base16, Base::Base58Btc, Base::Base64, Base::Base64Url] {
+            let text = hash.to_multibase(base);
+            assert!(text.starts_with(base.prefix()));
+            assert_eq!(Hash::from_multibase(&text).unwrap(), hash);
+            assert_eq!(
+                Identity::from_multibase(&id.to_multibase(base)).unwrap(),
+                id
+            );
+            assert_eq!(
+                StreamId::from_multibase(&stream.to_multibase(base)).unwrap(),
+                stream
+            );
+        }
+        assert!(Hash::from_multibase(&encode(Base::Base58Btc, b"short")).is_err());
+    }
+}
@@ -0,0 +1,130 @@
+//! Compile-time checking of struct field order.
+
+/// Check that a list of field names is in strictly increasing lexicographic order. Used by
+/// [`fog_ordered!`][crate::fog_ordered].
+#[doc(hidden)]
+pub const fn fields_ordered(fields: &[&str]) -> bool {
+    let mut i = 1;
+    while i < fields.len() {
+        if !str_less(fields[i - 1], fields[i]) {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+// Byte-wise comparison of strings, which matches the lexicographic ordering used for map keys.
+const fn str_less(a: &str, b: &str) -> bool {
+    let a = a.as_bytes();
+    let b = b.as_bytes();
+    let mut i = 0;
+    while i < a.len() && i < b.len() {
+        if a[i] != b[i] {
+            return a[i] < b[i];
+        }
+        i += 1;
+    }
+    a.len() < b.len()
+}
+
+/// Declare a struct whose fields are verified to be in lexicographic order at compile time.
+///
+/// Structs serialized with [`NewDocument::new_ordered`][crate::document::NewDocument::new_ordered]
+/// or [`NewEntry::new_ordered`][crate::entry::NewEntry::new_ordered] must declare their fields
+/// in lexicographic order, or serialization will fail at runtime. Wrapping the struct definition
+/// in this macro turns that runtime failure into a compile error.
+///
+/// The check uses the Rust field names, so it can't see the effects of `#[serde(rename)]` or
+/// `#[serde(rename_all)]`. Generic structs and tuple structs aren't supported.
+///
+/// ```
+/// # use serde::Serialize;
+/// fog_pack::fog_ordered! {
+///     #[derive(Serialize)]
+///     pub struct Post {
+///         pub content: String,
+///         pub created: u64,
+///         title: Option<String>,
+///     }
+/// }
+/// ```
+///
+/// Out-of-order fields are rejected:
+///
+/// ```compile_fail
+/// # use serde::Serialize;
+/// fog_pack::fog_ordered! {
+///     #[derive(Serialize)]
+///     struct Post {
+///         title: String,
+///         content: String,
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! fog_ordered {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $($(#[$field_meta:meta])* $field_vis:vis $field:ident : $ty:ty),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $($(#[$field_meta])* $field_vis $field : $ty),*
+        }
+
+        const _: () = ::std::assert!(
+            $crate::__private::fields_ordered(&[$(::std::stringify!($field)),*]),
+            ::std::concat!(
+                "fields of `",
+                ::std::stringify!($name),
+                "` are not in lexicographic order"
+            )
+        );
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{document::NewDocument, schema::NoSchema};
+    use serde::{Deserialize, Serialize};
+
+    #[test]
+    fn ordering() {
+        assert!(fields_ordered(&[]));
+        assert!(fields_ordered(&["a"]));
+        assert!(fields_ordered(&["a", "aa", "ab", "b"]));
+        assert!(!fields_ordered(&["a", "a"]));
+        assert!(!fields_ordered(&["b", "a"]));
+        assert!(!fields_ordered(&["aa", "a"]));
+        assert!(!fields_ordered(&["a", "c", "b"]));
+        // Uppercase sorts before lowercase
+        assert!(fields_ordered(&["Z", "a"]));
+    }
+
+    fog_ordered! {
+        #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+        struct Ordered {
+            alpha: u8,
+            #[serde(default)]
+            beta: Option<String>,
+            gamma: Vec<u8>,
+        }
+    }
+
+    #[test]
+    fn encode_ordered() {
+        let val = Ordered {
+            alpha: 1,
+            beta: Some("b".into()),
+            gamma: vec![1, 2, 3],
+        };
+        let doc = NewDocument::new_ordered(&val, None).unwrap();
+        let doc = NoSchema::validate_new_doc(doc).unwrap();
+        let dec: Ordered = doc.deserialize().unwrap();
+        assert_eq!(dec, val);
+    }
+}
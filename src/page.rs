@@ -0,0 +1,220 @@
+//! Page documents, for serving large entry sets a piece at a time.
+//!
+//! [`paginate`] sorts the entries under one document and key by the value of an ordered field,
+//! then splits them into pages. Each page is a document using the built-in [`schema`], holding
+//! the hashes of its entries, the first and last field values it covers, and the hash of the page
+//! after it. A consumer only needs the hash of the first page: every other page, and every entry,
+//! can then be fetched by hash as it's needed.
+//!
+//! Pages are built deterministically. The same entries always produce the same pages with the
+//! same hashes, no matter what order they were given in or who built them, so independently
+//! built pages can be cached and deduplicated like any other document.
+//!
+//! ```
+//! # use fog_pack::{document::*, entry::*, page::*, schema::*, types::*, validator::*};
+//! # fn main() -> fog_pack::error::Result<()> {
+//! # let schema_doc = SchemaBuilder::new(Validator::Any)
+//! #     .entry_add("log", Validator::Any, None)
+//! #     .build()?;
+//! # let schema = Schema::from_doc(&schema_doc)?;
+//! # let parent = schema.validate_new_doc(NewDocument::new(Some(schema.hash()), "log")?)?;
+//! let mut entries = Vec::new();
+//! for seq in 0u32..25 {
+//!     let entry = NewEntry::new("log", &parent, seq)?;
+//!     entries.push(schema.validate_new_entry(entry)?.complete()?);
+//! }
+//!
+//! let pages = paginate(parent.hash(), "log", &Path::new(), &entries, 10)?;
+//! assert_eq!(pages.len(), 3);
+//! let first = Page::from_doc(&pages[0])?;
+//! assert_eq!(first.entries.len(), 10);
+//! assert_eq!(first.last, Value::from(9u32));
+//! assert_eq!(first.next.as_ref(), Some(pages[1].hash()));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    document::{Document, NewDocument},
+    entry::Entry,
+    error::{Error, Result},
+    schema::{Schema, SchemaBuilder},
+    sync::{ord_cmp, ord_value},
+    types::{Hash, Path, Value},
+    validator::*,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+static SCHEMA: OnceLock<Schema> = OnceLock::new();
+
+/// Get the built-in schema that page documents use.
+///
+/// The schema document is built the same way every time, so its hash is the same everywhere.
+pub fn schema() -> &'static Schema {
+    SCHEMA.get_or_init(|| {
+        let validator = MapValidator::new()
+            .req_add("parent", HashValidator::new().build())
+            .req_add("key", StrValidator::new().build())
+            .req_add("field", StrValidator::new().build())
+            .req_add("first", Validator::Any)
+            .req_add("last", Validator::Any)
+            .req_add(
+                "entries",
+                ArrayValidator::new()
+                    .items(HashValidator::new().build())
+                    .min_len(1)
+                    .build(),
+            )
+            .opt_add("next", HashValidator::new().build())
+            .build();
+        let doc = SchemaBuilder::new(validator)
+            .name("fog-pack page")
+            .description("One page of entries, ordered by a field")
+            .build()
+            .expect("built-in page schema should be valid");
+        Schema::from_doc(&doc).expect("built-in page schema should be valid")
+    })
+}
+
+/// One page of entries, read from a page document.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Page {
+    /// The parent document of the entries.
+    pub parent: Hash,
+    /// The key of the entries.
+    pub key: String,
+    /// The field the entries are ordered by, as a JSON-Pointer-style path.
+    pub field: String,
+    /// The field value of the first entry on the page.
+    pub first: Value,
+    /// The field value of the last entry on the page.
+    pub last: Value,
+    /// The hashes of the entries on the page, in order.
+    pub entries: Vec<Hash>,
+    /// The hash of the next page, if this isn't the last one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next: Option<Hash>,
+}
+
+impl Page {
+    /// Read a page out of a document. Fails if the document doesn't use the page [`schema`].
+    pub fn from_doc(doc: &Document) -> Result<Self> {
+        if doc.schema_hash() != Some(schema().hash()) {
+            return Err(Error::SchemaMismatch {
+                actual: doc.schema_hash().cloned(),
+                expected: Some(schema().hash().clone()),
+            });
+        }
+        doc.deserialize()
+    }
+}
+
+/// Split the entries under one document and key into pages of up to `page_size` entries, ordered
+/// by the value at `field`. Returns the page documents in order, so the first one is the entry
+/// point; an empty set of entries produces no pages.
+///
+/// Entries that don't match `parent` and `key`, or don't have an integer, floating-point,
+/// timestamp, or binary value at the field, are left out. Entries with the same field value are
+/// ordered by their hash, so the result never depends on the order entries were given in. Fails
+/// if a page would be too large to fit in a document.
+pub fn paginate<'a>(
+    parent: &Hash,
+    key: &str,
+    field: &Path,
+    entries: impl IntoIterator<Item = &'a Entry>,
+    page_size: usize,
+) -> Result<Vec<Document>> {
+    let mut sorted = Vec::new();
+    for entry in entries {
+        if entry.parent() != parent || entry.key() != key {
+            continue;
+        }
+        if let Some(value) = entry.get_path(field)?.and_then(ord_value) {
+            sorted.push((value, entry.hash()));
+        }
+    }
+    sorted.sort_by(|a, b| ord_cmp(&a.0, &b.0).then_with(|| a.1.as_ref().cmp(b.1.as_ref())));
+    sorted.dedup_by(|a, b| a.1 == b.1);
+
+    // Build from the last page back, so each page can link to the one after it
+    let schema = schema();
+    let mut pages = Vec::new();
+    let mut next: Option<Hash> = None;
+    for chunk in sorted.chunks(page_size.max(1)).rev() {
+        let page = Page {
+            parent: parent.clone(),
+            key: key.to_owned(),
+            field: field.to_string(),
+            first: chunk[0].0.clone(),
+            last: chunk[chunk.len() - 1].0.clone(),
+            entries: chunk.iter().map(|(_, hash)| (*hash).clone()).collect(),
+            next: next.take(),
+        };
+        let doc = schema.validate_new_doc(NewDocument::new(Some(schema.hash()), &page)?)?;
+        next = Some(doc.hash().clone());
+        pages.push(doc);
+    }
+    pages.reverse();
+    Ok(pages)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::entry::NewEntry;
+
+    #[test]
+    fn pages() {
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .entry_add("log", Validator::Any, None)
+            .entry_add("other", Validator::Any, None)
+            .build()
+            .unwrap();
+        let data_schema = Schema::from_doc(&schema_doc).unwrap();
+        let parent = NewDocument::new(Some(data_schema.hash()), "parent").unwrap();
+        let parent = data_schema.validate_new_doc(parent).unwrap();
+        let entry = |key: &str, seq: Value| {
+            let entry = NewEntry::new(key, &parent, crate::fog_value!({"seq": seq})).unwrap();
+            data_schema
+                .validate_new_entry(entry)
+                .unwrap()
+                .complete()
+                .unwrap()
+        };
+        let field = Path::new().key("seq");
+
+        let mut entries: Vec<Entry> = (0u8..7).rev().map(|i| entry("log", i.into())).collect();
+        // Ignored: wrong key, or no ordered value at the field
+        entries.push(entry("other", 1u8.into()));
+        entries.push(entry("log", "text".into()));
+
+        let pages = paginate(parent.hash(), "log", &field, &entries, 3).unwrap();
+        let pages: Vec<Page> = pages.iter().map(|d| Page::from_doc(d).unwrap()).collect();
+        assert_eq!(pages.len(), 3);
+        assert_eq!(
+            pages.iter().map(|p| p.entries.len()).collect::<Vec<_>>(),
+            vec![3, 3, 1]
+        );
+        assert_eq!(pages[0].first, Value::from(0u8));
+        assert_eq!(pages[2].last, Value::from(6u8));
+        assert_eq!(pages[1].field, "/seq");
+        assert!(pages[2].next.is_none());
+
+        // Order of the input doesn't matter
+        let first = paginate(parent.hash(), "log", &field, &entries, 3).unwrap();
+        entries.reverse();
+        let second = paginate(parent.hash(), "log", &field, &entries, 3).unwrap();
+        let hashes = |docs: &[Document]| docs.iter().map(|d| d.hash().clone()).collect::<Vec<_>>();
+        assert_eq!(hashes(&first), hashes(&second));
+        assert_eq!(
+            Page::from_doc(&first[1]).unwrap().next.as_ref(),
+            Some(first[2].hash())
+        );
+
+        assert!(paginate(parent.hash(), "log", &field, &Vec::new(), 3)
+            .unwrap()
+            .is_empty());
+        assert!(Page::from_doc(&parent).is_err());
+    }
+}
@@ -0,0 +1,196 @@
+//! Diffing and merging of fog-pack values.
+//!
+//! This follows the semantics of a JSON Merge Patch (see
+//! [RFC 7386](https://www.rfc-editor.org/rfc/rfc7386)), adapted to fog-pack. Unlike JSON, Null is
+//! an ordinary value that can be stored in a map, so removal of a map key is explicitly encoded in
+//! the [`ValuePatch`] instead of being signalled with a Null.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::value::Value;
+
+/// A set of changes that can be applied to a [`Value`].
+///
+/// Patches are produced with [`diff`] and applied with [`merge`]. A patch can be serialized, so
+/// it may be stored in a document or entry and applied elsewhere.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum ValuePatch {
+    /// Leave the value unchanged.
+    #[default]
+    Keep,
+    /// Replace the value entirely.
+    Replace(Value),
+    /// Patch individual keys of a map. A key set to `None` is removed from the map, while one set
+    /// to `Some` has that patch applied to it. If the value being patched isn't a map, it is first
+    /// replaced with an empty map.
+    Map(BTreeMap<String, Option<ValuePatch>>),
+}
+
+impl ValuePatch {
+    /// Check if the patch makes no changes.
+    pub fn is_empty(&self) -> bool {
+        matches!(self, ValuePatch::Keep)
+    }
+
+    /// Apply this patch to a value. Equivalent to calling [`merge`].
+    pub fn apply(self, target: &mut Value) {
+        merge(target, self)
+    }
+}
+
+/// Compute the patch that turns `old` into `new`.
+///
+/// Maps are compared key-by-key, so only the changed keys end up in the patch. Any other changed
+/// value is replaced in its entirety, including arrays. Applying the result to `old` with
+/// [`merge`] will always produce `new`.
+pub fn diff(old: &Value, new: &Value) -> ValuePatch {
+    match (old, new) {
+        (Value::Map(old), Value::Map(new)) => {
+            let mut patch = BTreeMap::new();
+            for key in old.keys() {
+                if !new.contains_key(key) {
+                    patch.insert(key.clone(), None);
+                }
+            }
+            for (key, new_val) in new.iter() {
+                let val_patch = match old.get(key) {
+                    Some(old_val) => diff(old_val, new_val),
+                    None => ValuePatch::Replace(new_val.clone()),
+                };
+                if !val_patch.is_empty() {
+                    patch.insert(key.clone(), Some(val_patch));
+                }
+            }
+            if patch.is_empty() {
+                ValuePatch::Keep
+            } else {
+                ValuePatch::Map(patch)
+            }
+        }
+        (old, new) if old == new => ValuePatch::Keep,
+        (_, new) => ValuePatch::Replace(new.clone()),
+    }
+}
+
+/// Apply a patch to a value.
+///
+/// When the same base value is patched by multiple writers, merging their patches in order gives
+/// last-writer-wins behavior for each individual map key.
+pub fn merge(target: &mut Value, patch: ValuePatch) {
+    match patch {
+        ValuePatch::Keep => (),
+        ValuePatch::Replace(val) => *target = val,
+        ValuePatch::Map(patch) => {
+            if !target.is_map() {
+                *target = Value::Map(BTreeMap::new());
+            }
+            let map = target.as_map_mut().unwrap();
+            for (key, val_patch) in patch {
+                match val_patch {
+                    None => {
+                        map.remove(&key);
+                    }
+                    Some(ValuePatch::Keep) => (),
+                    Some(val_patch) => merge(map.entry(key).or_default(), val_patch),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn map<const N: usize>(items: [(&str, Value); N]) -> Value {
+        Value::Map(items.into_iter().map(|(k, v)| (k.to_owned(), v)).collect())
+    }
+
+    #[test]
+    fn identical() {
+        let val = map([("a", Value::from(1u8)), ("b", Value::Null)]);
+        assert_eq!(diff(&val, &val), ValuePatch::Keep);
+        assert_eq!(diff(&Value::from("x"), &Value::from("x")), ValuePatch::Keep);
+    }
+
+    #[test]
+    fn roundtrip() {
+        let old = map([
+            ("a", Value::from(1u8)),
+            (
+                "b",
+                map([("c", Value::from("keep")), ("d", Value::from(2u8))]),
+            ),
+            ("e", Value::Array(vec![Value::from(1u8)])),
+            ("gone", Value::from(true)),
+        ]);
+        let new = map([
+            ("a", Value::Null),
+            (
+                "b",
+                map([("c", Value::from("keep")), ("f", Value::from(3u8))]),
+            ),
+            ("e", Value::Array(vec![Value::from(1u8), Value::from(2u8)])),
+            ("new", map([("x", Value::from(false))])),
+        ]);
+        let patch = diff(&old, &new);
+        let ValuePatch::Map(ref entries) = patch else {
+            panic!("Expected a map patch, got {:?}", patch);
+        };
+        assert_eq!(entries.len(), 5);
+        assert_eq!(entries["gone"], None);
+        let mut merged = old.clone();
+        merge(&mut merged, patch);
+        assert_eq!(merged, new);
+    }
+
+    #[test]
+    fn non_map_target() {
+        let mut target = Value::from(5u8);
+        let patch = ValuePatch::Map(
+            [(
+                String::from("a"),
+                Some(ValuePatch::Replace(Value::from(1u8))),
+            )]
+            .into_iter()
+            .collect(),
+        );
+        merge(&mut target, patch);
+        assert_eq!(target, map([("a", Value::from(1u8))]));
+    }
+
+    #[test]
+    fn last_writer_wins() {
+        let base = map([("a", Value::from(0u8)), ("b", Value::from(0u8))]);
+        let first = diff(
+            &base,
+            &map([("a", Value::from(1u8)), ("b", Value::from(1u8))]),
+        );
+        let second = diff(
+            &base,
+            &map([("a", Value::from(2u8)), ("b", Value::from(0u8))]),
+        );
+        let mut merged = base.clone();
+        first.apply(&mut merged);
+        second.apply(&mut merged);
+        assert_eq!(
+            merged,
+            map([("a", Value::from(2u8)), ("b", Value::from(1u8))])
+        );
+    }
+
+    #[test]
+    fn serde() {
+        use crate::{document::NewDocument, schema::NoSchema};
+        let patch = diff(
+            &map([("a", Value::from(1u8)), ("b", Value::Null)]),
+            &map([("a", Value::from("hi"))]),
+        );
+        let doc = NewDocument::new(None, &patch).unwrap();
+        let doc = NoSchema::validate_new_doc(doc).unwrap();
+        let decoded: ValuePatch = doc.deserialize().unwrap();
+        assert_eq!(decoded, patch);
+    }
+}
@@ -0,0 +1,573 @@
+//! Paths into fog-pack values.
+//!
+//! A [`Path`] is a sequence of map keys and array indices that can be used to pick a single
+//! nested value out of a [`Value`], a [`ValueRef`], or directly out of the encoded data of a
+//! [`Document`][crate::document::Document] or [`Entry`][crate::entry::Entry], without
+//! deserializing everything around it.
+//!
+//! Paths can be built up one segment at a time, or parsed from a JSON-Pointer-style string (see
+//! [RFC 6901](https://www.rfc-editor.org/rfc/rfc6901)), like `"/contents/readme"`.
+
+use std::fmt;
+
+use serde::Deserialize;
+
+use crate::{
+    de::FogDeserializer,
+    element::{Element, Parser},
+    error::{Error, Result, ValidateError},
+    value::Value,
+    value_ref::ValueRef,
+};
+
+/// A single step in a [`Path`].
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PathSegment {
+    /// Look up a key in a map. If applied to an array, the key is parsed as an array index
+    /// instead, matching the behavior of a JSON Pointer.
+    Key(String),
+    /// Look up an index in an array. Never matches a map.
+    Index(usize),
+}
+
+impl PathSegment {
+    /// Get the array index this segment refers to, if it can refer to one.
+    fn as_index(&self) -> Option<usize> {
+        match self {
+            PathSegment::Index(i) => Some(*i),
+            PathSegment::Key(k) => parse_index(k),
+        }
+    }
+}
+
+// JSON Pointer array indices are decimal with no leading zeros.
+fn parse_index(s: &str) -> Option<usize> {
+    if s.is_empty() || (s.len() > 1 && s.starts_with('0')) || !s.bytes().all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// A path to a nested value inside a fog-pack value.
+///
+/// # Example
+///
+/// ```
+/// # use fog_pack::types::*;
+/// # use std::collections::BTreeMap;
+/// let mut contents = BTreeMap::new();
+/// contents.insert(String::from("readme"), Value::from("Hello"));
+/// let mut root = BTreeMap::new();
+/// root.insert(String::from("contents"), Value::Map(contents));
+/// let root = Value::Map(root);
+///
+/// let path = Path::new().key("contents").key("readme");
+/// assert_eq!(path.get(&root), Some(&Value::from("Hello")));
+/// assert_eq!(root.pointer("/contents/readme"), Some(&Value::from("Hello")));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Path(Vec<PathSegment>);
+
+impl Path {
+    /// Create a new, empty path. An empty path refers to the root value.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse a JSON-Pointer-style path, like `"/contents/readme"`. The empty string refers to the
+    /// root value. Within a segment, `~1` is decoded as `/` and `~0` is decoded as `~`.
+    ///
+    /// Returns `None` if the path doesn't start with `/`, or if it contains an invalid `~` escape.
+    pub fn parse(pointer: &str) -> Option<Self> {
+        if pointer.is_empty() {
+            return Some(Self::new());
+        }
+        let pointer = pointer.strip_prefix('/')?;
+        let mut segments = Vec::new();
+        for token in pointer.split('/') {
+            let mut key = String::with_capacity(token.len());
+            let mut chars = token.chars();
+            while let Some(c) = chars.next() {
+                if c == '~' {
+                    match chars.next() {
+                        Some('0') => key.push('~'),
+                        Some('1') => key.push('/'),
+                        _ => return None,
+                    }
+                } else {
+                    key.push(c);
+                }
+            }
+            segments.push(PathSegment::Key(key));
+        }
+        Some(Self(segments))
+    }
+
+    /// Add a map key lookup to the end of the path.
+    pub fn key(mut self, key: impl Into<String>) -> Self {
+        self.0.push(PathSegment::Key(key.into()));
+        self
+    }
+
+    /// Add an array index lookup to the end of the path.
+    pub fn index(mut self, index: usize) -> Self {
+        self.0.push(PathSegment::Index(index));
+        self
+    }
+
+    /// Add a segment to the end of the path.
+    pub fn push(&mut self, segment: PathSegment) {
+        self.0.push(segment);
+    }
+
+    /// Remove the last segment of the path, returning it.
+    pub fn pop(&mut self) -> Option<PathSegment> {
+        self.0.pop()
+    }
+
+    /// Get the segments making up this path.
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.0
+    }
+
+    /// Get the number of segments in the path.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Check if this path is empty, and thus refers to the root value.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Look up the value this path points to.
+    pub fn get<'v>(&self, value: &'v Value) -> Option<&'v Value> {
+        self.0.iter().try_fold(value, |value, seg| match value {
+            Value::Map(map) => match seg {
+                PathSegment::Key(k) => map.get(k.as_str()),
+                PathSegment::Index(_) => None,
+            },
+            Value::Array(array) => array.get(seg.as_index()?),
+            _ => None,
+        })
+    }
+
+    /// Look up the value this path points to, returning a mutable reference to it.
+    pub fn get_mut<'v>(&self, value: &'v mut Value) -> Option<&'v mut Value> {
+        self.0.iter().try_fold(value, |value, seg| match value {
+            Value::Map(map) => match seg {
+                PathSegment::Key(k) => map.get_mut(k.as_str()),
+                PathSegment::Index(_) => None,
+            },
+            Value::Array(array) => array.get_mut(seg.as_index()?),
+            _ => None,
+        })
+    }
+
+    /// Remove the value this path points to, returning it. Removing an array element shifts the
+    /// elements after it down, as in a JSON Patch `remove`. The root value can't be removed, so
+    /// this returns `None` for an empty path.
+    pub fn remove(&self, value: &mut Value) -> Option<Value> {
+        let (last, parents) = self.0.split_last()?;
+        let parent = Path(parents.to_vec()).get_mut(value)?;
+        match parent {
+            Value::Map(map) => match last {
+                PathSegment::Key(k) => map.remove(k.as_str()),
+                PathSegment::Index(_) => None,
+            },
+            Value::Array(array) => {
+                let index = last.as_index()?;
+                (index < array.len()).then(|| array.remove(index))
+            }
+            _ => None,
+        }
+    }
+
+    /// Remove the values several paths point to. Deeper paths and later array elements are
+    /// removed first, so no removal shifts an array element that another path points to.
+    pub(crate) fn remove_all(value: &mut Value, mut paths: Vec<Path>) {
+        paths.sort_by(|a, b| b.removal_cmp(a));
+        for path in paths.iter() {
+            path.remove(value);
+        }
+    }
+
+    // Compare paths segment by segment, with array indices compared numerically, and paths
+    // ordered after every path they extend.
+    fn removal_cmp(&self, other: &Path) -> std::cmp::Ordering {
+        for (a, b) in self.0.iter().zip(other.0.iter()) {
+            let ord = match (a.as_index(), b.as_index()) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                _ => a.cmp(b),
+            };
+            if ord.is_ne() {
+                return ord;
+            }
+        }
+        self.0.len().cmp(&other.0.len())
+    }
+
+    /// Look up the value this path points to.
+    pub fn get_ref<'v, 'a>(&self, value: &'v ValueRef<'a>) -> Option<&'v ValueRef<'a>> {
+        self.0.iter().try_fold(value, |value, seg| match value {
+            ValueRef::Map(map) => match seg {
+                PathSegment::Key(k) => map.get(k.as_str()),
+                PathSegment::Index(_) => None,
+            },
+            ValueRef::Array(array) => array.get(seg.as_index()?),
+            _ => None,
+        })
+    }
+
+    /// Look up the value this path points to, directly from encoded fog-pack data. Only the
+    /// target value is deserialized; everything before it is skipped over. The data is assumed
+    /// to have already been validated.
+    pub(crate) fn find_raw<'a>(&self, data: &'a [u8]) -> Result<Option<ValueRef<'a>>> {
+        let mut parser = Parser::new(data).raw_floats(true);
+        for seg in self.0.iter() {
+            match next_elem(&mut parser)? {
+                Element::Map(len) => {
+                    let key = match seg {
+                        PathSegment::Key(k) => k.as_str(),
+                        PathSegment::Index(_) => return Ok(None),
+                    };
+                    let mut found = false;
+                    for _ in 0..len {
+                        let k = match next_elem(&mut parser)? {
+                            Element::Str(k) => k,
+                            elem => {
+                                return Err(Error::FailValidate(ValidateError::Other(
+                                    format!("expected Str for map key, got {}", elem.name()).into(),
+                                )))
+                            }
+                        };
+                        // Keys are in lexicographic order, so we can stop early
+                        if k == key {
+                            found = true;
+                            break;
+                        } else if k > key {
+                            return Ok(None);
+                        }
+                        skip_value(&mut parser)?;
+                    }
+                    if !found {
+                        return Ok(None);
+                    }
+                }
+                Element::Array(len) => {
+                    let index = match seg.as_index() {
+                        Some(index) if index < len => index,
+                        _ => return Ok(None),
+                    };
+                    for _ in 0..index {
+                        skip_value(&mut parser)?;
+                    }
+                }
+                _ => return Ok(None),
+            }
+        }
+        let mut de = FogDeserializer::from_parser(parser);
+        Ok(Some(ValueRef::deserialize(&mut de)?))
+    }
+}
+
+/// A map or array that a path passes through, located within encoded data.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct RawContainer {
+    /// Offset of the container's header
+    pub start: usize,
+    /// Offset just past the container's header
+    pub header_end: usize,
+    /// Number of items (or key-value pairs) in the container
+    pub len: usize,
+    /// Whether the container is a map
+    pub is_map: bool,
+}
+
+/// Where a path leads within encoded data.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum RawTarget {
+    /// The value exists. For map values, `entry_start` is where its key starts; otherwise it's
+    /// the same as `value_start`.
+    Found {
+        entry_start: usize,
+        value_start: usize,
+        end: usize,
+    },
+    /// The value doesn't exist, but could be added to its parent container at this offset. For
+    /// arrays, this is only the case for the index just past the end.
+    Missing { at: usize },
+}
+
+impl Path {
+    /// Locate the value this path refers to in encoded data, along with the innermost container
+    /// it is in. Fails if the path passes through a missing value or one that isn't a map or
+    /// array, or if the last segment can't refer to a new item of its container.
+    pub(crate) fn locate_raw(&self, data: &[u8]) -> Result<(Option<RawContainer>, RawTarget)> {
+        let fail = |msg: String| Error::FailValidate(ValidateError::Other(msg.into()));
+        let mut parser = Parser::new(data).raw_floats(true);
+        let pos = |parser: &Parser| data.len() - parser.remaining();
+        let mut parent = None;
+        for (depth, seg) in self.0.iter().enumerate() {
+            let start = pos(&parser);
+            let elem = next_elem(&mut parser)?;
+            let header_end = pos(&parser);
+            let mut found = None;
+            let mut insert_at = None;
+            let container = match elem {
+                Element::Map(len) => {
+                    let key = match seg {
+                        PathSegment::Key(k) => k.as_str(),
+                        PathSegment::Index(i) => {
+                            return Err(fail(format!("can't index into a map with {}", i)))
+                        }
+                    };
+                    for _ in 0..len {
+                        let entry_start = pos(&parser);
+                        let k = match next_elem(&mut parser)? {
+                            Element::Str(k) => k,
+                            elem => {
+                                return Err(fail(format!(
+                                    "expected Str for map key, got {}",
+                                    elem.name()
+                                )))
+                            }
+                        };
+                        if k == key {
+                            found = Some((entry_start, pos(&parser)));
+                            break;
+                        } else if k > key {
+                            insert_at = Some(entry_start);
+                            break;
+                        }
+                        skip_value(&mut parser)?;
+                    }
+                    RawContainer {
+                        start,
+                        header_end,
+                        len,
+                        is_map: true,
+                    }
+                }
+                Element::Array(len) => {
+                    let index = seg
+                        .as_index()
+                        .filter(|i| *i <= len)
+                        .ok_or_else(|| fail(format!("no index {:?} in array", seg)))?;
+                    for _ in 0..index {
+                        skip_value(&mut parser)?;
+                    }
+                    let at = pos(&parser);
+                    if index < len {
+                        found = Some((at, at));
+                    }
+                    RawContainer {
+                        start,
+                        header_end,
+                        len,
+                        is_map: false,
+                    }
+                }
+                elem => {
+                    return Err(fail(format!(
+                        "path {} passes through a {}",
+                        self,
+                        elem.name()
+                    )))
+                }
+            };
+            parent = Some(container);
+            let last = depth + 1 == self.0.len();
+            match found {
+                Some((entry_start, value_start)) => {
+                    if last {
+                        let mut end_parser = parser.clone();
+                        skip_value(&mut end_parser)?;
+                        return Ok((
+                            parent,
+                            RawTarget::Found {
+                                entry_start,
+                                value_start,
+                                end: pos(&end_parser),
+                            },
+                        ));
+                    }
+                }
+                // New items go before the first later key, or at the end of the container
+                None if last => {
+                    let at = insert_at.unwrap_or_else(|| pos(&parser));
+                    return Ok((parent, RawTarget::Missing { at }));
+                }
+                None => return Err(fail(format!("path {} doesn't exist", self))),
+            }
+        }
+        Ok((
+            parent,
+            RawTarget::Found {
+                entry_start: 0,
+                value_start: 0,
+                end: data.len(),
+            },
+        ))
+    }
+}
+
+fn next_elem<'a>(parser: &mut Parser<'a>) -> Result<Element<'a>> {
+    parser.next().ok_or_else(|| Error::LengthTooShort {
+        step: "get next element",
+        actual: 0,
+        expected: 1,
+    })?
+}
+
+// Skip over a single complete value, including everything nested within it.
+fn skip_value(parser: &mut Parser) -> Result<()> {
+    let mut remaining = 1usize;
+    while remaining > 0 {
+        remaining -= 1;
+        match next_elem(parser)? {
+            Element::Map(len) => remaining += 2 * len,
+            Element::Array(len) => remaining += len,
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for seg in self.0.iter() {
+            f.write_str("/")?;
+            match seg {
+                PathSegment::Key(k) => f.write_str(&k.replace('~', "~0").replace('/', "~1"))?,
+                PathSegment::Index(i) => write!(f, "{}", i)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Path {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s).ok_or_else(|| Error::BadEncode(format!("Invalid path: \"{}\"", s)))
+    }
+}
+
+impl std::iter::FromIterator<PathSegment> for Path {
+    fn from_iter<T: IntoIterator<Item = PathSegment>>(iter: T) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use crate::{document::NewDocument, schema::NoSchema};
+
+    use super::*;
+
+    fn sample() -> Value {
+        let mut contents = BTreeMap::new();
+        contents.insert(String::from("readme"), Value::from("Hello"));
+        contents.insert(
+            String::from("list"),
+            Value::Array(vec![Value::from(1u8), Value::from(vec![Value::from(true)])]),
+        );
+        contents.insert(String::from("a/b~c"), Value::from(3u8));
+        let mut root = BTreeMap::new();
+        root.insert(String::from("a"), Value::from(0u8));
+        root.insert(String::from("contents"), Value::Map(contents));
+        root.insert(String::from("z"), Value::Null);
+        Value::Map(root)
+    }
+
+    #[test]
+    fn parse_and_display() {
+        let path = Path::parse("/contents/a~1b~0c").unwrap();
+        assert_eq!(path, Path::new().key("contents").key("a/b~c"));
+        assert_eq!(path.to_string(), "/contents/a~1b~0c");
+        assert_eq!(Path::parse("").unwrap(), Path::new());
+        assert!(Path::parse("contents").is_none());
+        assert!(Path::parse("/bad~2").is_none());
+    }
+
+    #[test]
+    fn value_lookup() {
+        let val = sample();
+        assert_eq!(val.pointer(""), Some(&val));
+        assert_eq!(val.pointer("/contents/readme"), Some(&Value::from("Hello")));
+        assert_eq!(val.pointer("/contents/list/0"), Some(&Value::from(1u8)));
+        assert_eq!(val.pointer("/contents/list/1/0"), Some(&Value::from(true)));
+        assert_eq!(val.pointer("/contents/a~1b~0c"), Some(&Value::from(3u8)));
+        assert_eq!(val.pointer("/contents/list/01"), None);
+        assert_eq!(val.pointer("/contents/list/2"), None);
+        assert_eq!(val.pointer("/missing"), None);
+        let path = Path::new().key("contents").key("list").index(1).index(0);
+        assert_eq!(path.get(&val), Some(&Value::from(true)));
+        assert_eq!(Path::new().index(0).get(&val), None);
+
+        let val_ref = val.as_ref();
+        assert_eq!(
+            val_ref.pointer("/contents/readme"),
+            Some(&ValueRef::Str("Hello"))
+        );
+        assert_eq!(path.get_ref(&val_ref), Some(&ValueRef::Bool(true)));
+    }
+
+    #[test]
+    fn value_lookup_mut() {
+        let mut val = sample();
+        *val.pointer_mut("/contents/readme").unwrap() = Value::from("Goodbye");
+        assert_eq!(val["contents"]["readme"], Value::from("Goodbye"));
+    }
+
+    #[test]
+    fn value_remove() {
+        let mut val = sample();
+        let list = Path::new().key("contents").key("list");
+        assert_eq!(
+            list.clone().index(0).remove(&mut val),
+            Some(Value::from(1u8))
+        );
+        assert_eq!(val.pointer("/contents/list/0/0"), Some(&Value::from(true)));
+        assert_eq!(list.clone().index(1).remove(&mut val), None);
+        assert_eq!(
+            Path::new().key("a").remove(&mut val),
+            Some(Value::from(0u8))
+        );
+        assert_eq!(val.pointer("/a"), None);
+        assert_eq!(Path::new().key("missing").remove(&mut val), None);
+        assert_eq!(Path::new().remove(&mut val), None);
+    }
+
+    #[test]
+    fn raw_lookup() {
+        let val = sample();
+        let doc = NewDocument::new(None, &val).unwrap();
+        let doc = NoSchema::validate_new_doc(doc).unwrap();
+        let paths = [
+            "",
+            "/a",
+            "/contents",
+            "/contents/readme",
+            "/contents/list/0",
+            "/contents/list/1/0",
+            "/contents/a~1b~0c",
+            "/contents/list/2",
+            "/contents/nope",
+            "/z",
+            "/zz",
+            "/a/0",
+        ];
+        for p in paths {
+            let path = Path::parse(p).unwrap();
+            let expected = path.get(&val).map(|v| v.as_ref());
+            let actual = doc.get_path(&path).unwrap();
+            assert_eq!(actual, expected, "Path {} didn't match", p);
+        }
+    }
+}
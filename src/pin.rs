@@ -0,0 +1,378 @@
+//! Pin manifests, for sharing which documents a node should keep.
+//!
+//! A pin manifest is a document, using the built-in [`schema`], that lists root documents to keep.
+//! Each [`Pin`] can be recursive, keeping everything [reachable][crate::graph::reachable] from its
+//! root instead of just the root itself, and weak, marking documents worth keeping if there is
+//! room without stopping them from being garbage collected. A pin can also expire, after which it
+//! no longer applies. Because manifests are ordinary documents, they can be signed, stored, and
+//! sent between nodes along with everything else, so replication policies travel as data.
+//!
+//! [`pinned`] combines the pins from any number of manifests into a single [`PinSet`], and
+//! [`PinSet::kept_docs`] finds every document the set's strong pins keep.
+//!
+//! ```
+//! # use fog_pack::{document::*, pin::*, schema::NoSchema, store::*};
+//! # fn main() -> fog_pack::error::Result<()> {
+//! let mut store = MemoryStore::new();
+//! let leaf = store.put_doc(NoSchema::validate_new_doc(NewDocument::new(None, "leaf")?)?)?;
+//! let root = NoSchema::validate_new_doc(NewDocument::new(None, vec![&leaf])?)?;
+//! let root = store.put_doc(root)?;
+//!
+//! let manifest = NewPinManifest::new()
+//!     .pin_add(Pin::new(&root).recursive(true))
+//!     .build()?;
+//! let manifest = PinManifest::from_doc(manifest)?;
+//! let pins = pinned([&manifest]);
+//! let kept = pins.kept_docs(|hash| store.get_doc(hash))?;
+//! assert!(kept.contains(&root) && kept.contains(&leaf));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    document::{Document, NewDocument},
+    error::{Error, Result},
+    graph::reachable,
+    schema::{Schema, SchemaBuilder},
+    signer::Signer,
+    types::{Hash, Identity, Timestamp},
+    validator::*,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::OnceLock,
+};
+
+static SCHEMA: OnceLock<Schema> = OnceLock::new();
+
+/// Get the built-in schema that pin manifests use.
+pub fn schema() -> &'static Schema {
+    SCHEMA.get_or_init(|| {
+        let pin = MapValidator::new()
+            .req_add("root", HashValidator::new().build())
+            .opt_add("recursive", BoolValidator::new().build())
+            .opt_add("weak", BoolValidator::new().build())
+            .opt_add("expires", TimeValidator::new().build())
+            .build();
+        let validator = MapValidator::new()
+            .req_add("pins", ArrayValidator::new().items(pin).build())
+            .build();
+        let doc = SchemaBuilder::new(validator)
+            .name("fog-pack pin manifest")
+            .description("A list of documents to keep")
+            .build()
+            .expect("built-in pin manifest schema should be valid");
+        Schema::from_doc(&doc).expect("built-in pin manifest schema should be valid")
+    })
+}
+
+#[inline]
+fn is_false(v: &bool) -> bool {
+    !v
+}
+
+/// A single pinned root document.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pin {
+    root: Hash,
+    #[serde(default, skip_serializing_if = "is_false")]
+    recursive: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    weak: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires: Option<Timestamp>,
+}
+
+impl Pin {
+    /// Pin a single document, strongly and with no expiry.
+    pub fn new(root: &Hash) -> Self {
+        Self {
+            root: root.clone(),
+            recursive: false,
+            weak: false,
+            expires: None,
+        }
+    }
+
+    /// Set whether everything reachable from the root is pinned too.
+    pub fn recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    /// Set whether the pin is weak. Weak pins mark documents worth keeping, but don't stop them
+    /// from being garbage collected.
+    pub fn weak(mut self, weak: bool) -> Self {
+        self.weak = weak;
+        self
+    }
+
+    /// Stop pinning after the given time.
+    pub fn expires(mut self, expires: Timestamp) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    /// Get the pinned root document.
+    pub fn root(&self) -> &Hash {
+        &self.root
+    }
+
+    /// Check if everything reachable from the root is pinned too.
+    pub fn is_recursive(&self) -> bool {
+        self.recursive
+    }
+
+    /// Check if the pin is weak.
+    pub fn is_weak(&self) -> bool {
+        self.weak
+    }
+
+    /// Get the time after which the pin no longer applies, if there is one.
+    pub fn expiry(&self) -> Option<Timestamp> {
+        self.expires
+    }
+
+    /// Check if the pin has expired at the given time.
+    pub fn is_expired_at(&self, now: Timestamp) -> bool {
+        matches!(self.expires, Some(expires) if now > expires)
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    pins: Vec<Pin>,
+}
+
+/// A pin manifest that hasn't been turned into a document yet.
+#[derive(Clone, Debug, Default)]
+pub struct NewPinManifest(Manifest);
+
+impl NewPinManifest {
+    /// Start a new, empty manifest.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a pin to the manifest.
+    pub fn pin_add(mut self, pin: Pin) -> Self {
+        self.0.pins.push(pin);
+        self
+    }
+
+    /// Build an unsigned manifest document.
+    pub fn build(self) -> Result<Document> {
+        let schema = schema();
+        schema.validate_new_doc(NewDocument::new(Some(schema.hash()), &self.0)?)
+    }
+
+    /// Sign the manifest, producing a manifest document.
+    pub fn sign<S: Signer + ?Sized>(self, key: &S) -> Result<Document> {
+        let schema = schema();
+        let doc = NewDocument::new(Some(schema.hash()), &self.0)?.sign(key)?;
+        schema.validate_new_doc(doc)
+    }
+}
+
+/// A pin manifest, read from a manifest document.
+#[derive(Clone, Debug)]
+pub struct PinManifest {
+    doc: Document,
+    manifest: Manifest,
+}
+
+impl PinManifest {
+    /// Read a pin manifest out of a document. Fails if the document doesn't use the pin manifest
+    /// schema, or has an invalid signature.
+    pub fn from_doc(doc: Document) -> Result<Self> {
+        if doc.schema_hash() != Some(schema().hash()) {
+            return Err(Error::SchemaMismatch {
+                actual: doc.schema_hash().cloned(),
+                expected: Some(schema().hash().clone()),
+            });
+        }
+        let doc = doc.verify()?;
+        let manifest = doc.deserialize()?;
+        Ok(Self { doc, manifest })
+    }
+
+    /// Get the manifest document.
+    pub fn doc(&self) -> &Document {
+        &self.doc
+    }
+
+    /// Get the hash of the manifest document.
+    pub fn hash(&self) -> &Hash {
+        self.doc.hash()
+    }
+
+    /// Get the Identity that signed the manifest, if it was signed. Which signers to trust is up
+    /// to the application.
+    pub fn signer(&self) -> Option<&Identity> {
+        self.doc.signer()
+    }
+
+    /// Get the pins in the manifest.
+    pub fn pins(&self) -> &[Pin] {
+        &self.manifest.pins
+    }
+}
+
+/// The combined pins of a set of manifests. See [`pinned`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PinSet {
+    pins: Vec<Pin>,
+}
+
+impl PinSet {
+    /// Iterate over the combined pins, ordered by root hash, with a root's strong pin before its
+    /// weak one.
+    pub fn iter(&self) -> impl Iterator<Item = &Pin> {
+        self.pins.iter()
+    }
+
+    /// Get the number of combined pins.
+    pub fn len(&self) -> usize {
+        self.pins.len()
+    }
+
+    /// Check if nothing is pinned.
+    pub fn is_empty(&self) -> bool {
+        self.pins.is_empty()
+    }
+
+    /// Get the strong pin for a root document, if there is one.
+    pub fn strong(&self, root: &Hash) -> Option<&Pin> {
+        self.pins.iter().find(|p| p.root == *root && !p.weak)
+    }
+
+    /// Get the weak pin for a root document, if there is one.
+    pub fn weak(&self, root: &Hash) -> Option<&Pin> {
+        self.pins.iter().find(|p| p.root == *root && p.weak)
+    }
+
+    /// Turn the combined pins back into a manifest, so they can be passed on to other nodes.
+    pub fn to_manifest(&self) -> NewPinManifest {
+        NewPinManifest(Manifest {
+            pins: self.pins.clone(),
+        })
+    }
+
+    /// Find every document kept by the strong pins: the root of each one, and everything
+    /// reachable from the roots of recursive ones. Weak pins are left out.
+    ///
+    /// `fetch` is called for each document reachable from a recursive pin, as in
+    /// [`reachable`][crate::graph::reachable].
+    pub fn kept_docs<F>(&self, fetch: F) -> Result<HashSet<Hash>>
+    where
+        F: FnMut(&Hash) -> Result<Option<Document>>,
+    {
+        let strong = self.pins.iter().filter(|p| !p.weak);
+        let mut kept: HashSet<Hash> = strong.clone().map(|p| p.root.clone()).collect();
+        let recursive = strong.filter(|p| p.recursive).map(|p| &p.root);
+        kept.extend(reachable(recursive, fetch)?.iter().cloned());
+        Ok(kept)
+    }
+}
+
+/// Combine the pins of a set of manifests, as of right now. See [`pinned_at`].
+pub fn pinned<'a>(manifests: impl IntoIterator<Item = &'a PinManifest>) -> PinSet {
+    pinned_at(manifests, Timestamp::now())
+}
+
+/// Combine the pins of a set of manifests, as of the given time.
+///
+/// Expired pins are dropped. The remaining pins are combined into at most one strong and one weak
+/// pin per root: the combined pin is recursive if any of the pins it came from are, and expires
+/// at the latest of their expiry times, or never if any of them never expire.
+pub fn pinned_at<'a>(
+    manifests: impl IntoIterator<Item = &'a PinManifest>,
+    now: Timestamp,
+) -> PinSet {
+    let mut combined: HashMap<(&Hash, bool), Pin> = HashMap::new();
+    for pin in manifests.into_iter().flat_map(|m| m.pins()) {
+        if pin.is_expired_at(now) {
+            continue;
+        }
+        combined
+            .entry((&pin.root, pin.weak))
+            .and_modify(|c| {
+                c.recursive |= pin.recursive;
+                c.expires = match (c.expires, pin.expires) {
+                    (Some(a), Some(b)) => Some(a.max(b)),
+                    _ => None,
+                };
+            })
+            .or_insert_with(|| pin.clone());
+    }
+    let mut pins: Vec<Pin> = combined.into_values().collect();
+    pins.sort_by(|a, b| {
+        a.root
+            .as_ref()
+            .cmp(b.root.as_ref())
+            .then(a.weak.cmp(&b.weak))
+    });
+    PinSet { pins }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        schema::NoSchema,
+        store::{DocStore, MemoryStore},
+        types::IdentityKey,
+    };
+
+    #[test]
+    fn union() {
+        let mut store = MemoryStore::new();
+        let mut put = |data: Vec<Hash>| {
+            let doc = NoSchema::validate_new_doc(NewDocument::new(None, data).unwrap()).unwrap();
+            store.put_doc(doc).unwrap()
+        };
+        let leaf = put(vec![]);
+        let a = put(vec![leaf.clone()]);
+        let b = put(vec![]);
+        let c = put(vec![]);
+        let now = Timestamp::from_tai_secs(1000);
+
+        let key = IdentityKey::new();
+        let first = NewPinManifest::new()
+            .pin_add(Pin::new(&a).expires(now + 10))
+            .pin_add(Pin::new(&b).weak(true))
+            .pin_add(Pin::new(&c).expires(now - 10))
+            .sign(&key)
+            .unwrap();
+        let first = PinManifest::from_doc(first).unwrap();
+        assert_eq!(first.signer(), Some(key.id()));
+        let second = NewPinManifest::new()
+            .pin_add(Pin::new(&a).recursive(true).expires(now + 20))
+            .build()
+            .unwrap();
+        let second = PinManifest::from_doc(second).unwrap();
+
+        let pins = pinned_at([&first, &second], now);
+        assert_eq!(pins.len(), 2);
+        assert_eq!(
+            pins.strong(&a),
+            Some(&Pin::new(&a).recursive(true).expires(now + 20))
+        );
+        assert!(pins.strong(&b).is_none());
+        assert!(pins.weak(&b).is_some());
+        // The expired pin is dropped
+        assert!(pins.strong(&c).is_none());
+
+        let kept = pins.kept_docs(|hash| store.get_doc(hash)).unwrap();
+        assert_eq!(kept, HashSet::from([a.clone(), leaf.clone()]));
+
+        // The combined set can be passed on as a manifest of its own
+        let passed = PinManifest::from_doc(pins.to_manifest().build().unwrap()).unwrap();
+        assert_eq!(pinned_at([&passed], now), pins);
+        assert!(pinned_at([&passed], now + 30).strong(&a).is_none());
+
+        let other = NoSchema::validate_new_doc(NewDocument::new(None, "x").unwrap()).unwrap();
+        assert!(PinManifest::from_doc(other).is_err());
+    }
+}
@@ -0,0 +1,441 @@
+//! Human-readable rendering of fog-pack data.
+//!
+//! Data is rendered in a JSON-like format. Types that JSON lacks are rendered as strings with a
+//! `$fog-` prefix, like `"$fog-Bin:AAEC"` or `"$fog-Hash:..."`. The output is meant for people,
+//! not for parsing, and may change between versions. Hashes and other identifiers can be
+//! rendered as [multibase][crate::multibase] strings with [`PrettyOptions::multibase`], so they
+//! can be copied straight into other content-addressed systems.
+//!
+//! # Example
+//!
+//! ```
+//! # use fog_pack::{fog_value, pretty::{self, PrettyOptions}};
+//! let value = fog_value!({ "name": "fog-pack", "list": [1, 2] });
+//! let text = pretty::print(&value, &PrettyOptions::new()).unwrap();
+//! assert_eq!(text, "{\n  \"list\": [\n    1,\n    2\n  ],\n  \"name\": \"fog-pack\"\n}");
+//! ```
+
+use serde::Serialize;
+
+use crate::{
+    document::Document,
+    element::{Element, Parser},
+    entry::Entry,
+    error::{Error, Result},
+    multibase::{Base, Multibase},
+    ser::FogSerializer,
+};
+
+pub(crate) fn base64_encode<T: AsRef<[u8]>>(input: T, output_buf: &mut String) {
+    use base64::engine::Engine;
+    base64::engine::general_purpose::STANDARD_NO_PAD.encode_string(input, output_buf)
+}
+
+/// Options for rendering fog-pack data.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrettyOptions {
+    indent: String,
+    max_bin: Option<usize>,
+    max_depth: Option<usize>,
+    multibase: Option<Base>,
+}
+
+impl Default for PrettyOptions {
+    fn default() -> Self {
+        Self {
+            indent: String::from("  "),
+            max_bin: None,
+            max_depth: None,
+            multibase: None,
+        }
+    }
+}
+
+impl PrettyOptions {
+    /// Create a new set of options. Defaults to indenting with two spaces, with no limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the string used for each level of indentation.
+    pub fn indent(mut self, indent: impl Into<String>) -> Self {
+        self.indent = indent.into();
+        self
+    }
+
+    /// Set the maximum number of bytes of any binary value to render. Longer values are truncated,
+    /// and are rendered with their full length.
+    pub fn max_bin(mut self, max_bin: usize) -> Self {
+        self.max_bin = Some(max_bin);
+        self
+    }
+
+    /// Set the maximum nesting depth to render. Non-empty arrays and maps beyond this depth are
+    /// collapsed into a summary of how many items they hold. A depth of 0 collapses the root
+    /// value, if it's an array or map.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Render hashes, identities, stream IDs, and lock IDs as multibase strings in the given
+    /// encoding, like `"$fog-Hash:z..."`, instead of their usual form. See
+    /// [`Multibase`][crate::multibase::Multibase] for parsing them back.
+    pub fn multibase(mut self, base: Base) -> Self {
+        self.multibase = Some(base);
+        self
+    }
+}
+
+/// Render an encoded fog-pack value. Fails if the data isn't a single, validly encoded value.
+pub fn print_bytes(data: &[u8], options: &PrettyOptions) -> Result<String> {
+    let mut parser = Parser::new(data).raw_floats(true);
+    let mut formatter = PrettyFormatter::new(options.clone());
+    loop {
+        let elem = parser
+            .next()
+            .ok_or_else(|| Error::BadEncode("data ended before the value was complete".into()))??;
+        formatter.update(&elem);
+        if formatter.is_complete() {
+            break;
+        }
+    }
+    parser.finish()?;
+    Ok(formatter.debug)
+}
+
+/// Render the next value a parser would return, without advancing it. Stops early if the data
+/// runs out or is malformed, returning whatever was rendered up to that point.
+#[cfg(feature = "diagnostics")]
+pub(crate) fn print_next(mut parser: Parser, options: &PrettyOptions) -> String {
+    let mut formatter = PrettyFormatter::new(options.clone());
+    while let Some(Ok(elem)) = parser.next() {
+        formatter.update(&elem);
+        if formatter.is_complete() {
+            break;
+        }
+    }
+    formatter.debug
+}
+
+/// Render any serializable value, including [`Value`][crate::types::Value] and
+/// [`ValueRef`][crate::types::ValueRef]. Fails if the value can't be serialized.
+pub fn print<S: Serialize + ?Sized>(value: &S, options: &PrettyOptions) -> Result<String> {
+    let mut ser = FogSerializer::default();
+    value.serialize(&mut ser)?;
+    print_bytes(&ser.finish(), options)
+}
+
+/// Render the data contained within a document.
+pub fn print_document(doc: &Document, options: &PrettyOptions) -> String {
+    // Documents are always validly encoded, so this can't fail
+    print_bytes(doc.data(), options).unwrap_or_default()
+}
+
+/// Render the data contained within an entry.
+pub fn print_entry(entry: &Entry, options: &PrettyOptions) -> String {
+    // Entries are always validly encoded, so this can't fail
+    print_bytes(entry.data(), options).unwrap_or_default()
+}
+
+#[derive(Clone, Copy, Debug)]
+enum TrackType {
+    FirstArray(usize),
+    FirstMap(usize),
+    Array(usize),
+    Map(usize),
+}
+
+/// Incremental formatter, fed one element at a time.
+#[derive(Clone, Debug)]
+pub(crate) struct PrettyFormatter {
+    debug: String,
+    tracker: Vec<TrackType>,
+    options: PrettyOptions,
+    /// Number of elements left to skip over in a collapsed array or map
+    skipping: Option<usize>,
+}
+
+impl PrettyFormatter {
+    pub(crate) fn new(options: PrettyOptions) -> Self {
+        Self {
+            debug: String::new(),
+            tracker: Vec::new(),
+            options,
+            skipping: None,
+        }
+    }
+
+    pub(crate) fn get_str(&self) -> &str {
+        &self.debug
+    }
+
+    fn at_max_depth(&self) -> bool {
+        self.options
+            .max_depth
+            .is_some_and(|max| self.tracker.len() >= max)
+    }
+
+    /// Check if a complete value has been formatted.
+    fn is_complete(&self) -> bool {
+        self.tracker.is_empty() && self.skipping.is_none()
+    }
+
+    // Indent, unless the next element is on the same line as the previous one. That's the case
+    // for the first element in a single-line array or map, and for map values.
+    fn indent(&mut self) {
+        match self.tracker.last() {
+            Some(TrackType::FirstArray(_)) => (),
+            Some(TrackType::FirstMap(_)) => (),
+            Some(TrackType::Map(size)) if (size & 0x1) == 0 => (),
+            _ => self.push_indent(),
+        }
+    }
+
+    fn push_indent(&mut self) {
+        for _ in 0..self.tracker.len() {
+            self.debug.push_str(&self.options.indent)
+        }
+    }
+
+    pub(crate) fn update(&mut self, elem: &Element) {
+        use std::fmt::Write;
+
+        // Swallow the contents of collapsed arrays & maps
+        if let Some(left) = self.skipping.as_mut() {
+            *left -= 1;
+            match elem {
+                Element::Array(len) => *left += len,
+                Element::Map(len) => *left += 2 * len,
+                _ => (),
+            }
+            if *left == 0 {
+                self.skipping = None;
+                self.advance();
+            }
+            return;
+        }
+
+        self.indent();
+        match elem {
+            Element::Null => self.debug.push_str("null"),
+            Element::Bool(v) => self.debug.push_str(if *v { "true" } else { "false" }),
+            Element::Int(v) => write!(self.debug, "{}", v).unwrap(),
+            Element::Str(v) => write!(self.debug, "\"{}\"", v.escape_debug()).unwrap(),
+            Element::F32(v) => write!(self.debug, "{}", v).unwrap(),
+            Element::F64(v) => write!(self.debug, "{}", v).unwrap(),
+            Element::Bin(v) => match self.options.max_bin {
+                Some(max) if v.len() > max => {
+                    write!(self.debug, "\"$fog-Bin(len={}):", v.len()).unwrap();
+                    base64_encode(&v[..max], &mut self.debug);
+                    self.debug.push_str("...\"");
+                }
+                _ => {
+                    self.debug.push_str("\"$fog-Bin:");
+                    base64_encode(*v, &mut self.debug);
+                    self.debug.push('"');
+                }
+            },
+            Element::Array(v) if *v > 0 && self.at_max_depth() => {
+                write!(self.debug, "[ ...{} items ]", v).unwrap();
+                self.skipping = Some(*v);
+                return;
+            }
+            Element::Map(v) if *v > 0 && self.at_max_depth() => {
+                write!(self.debug, "{{ ...{} pairs }}", v).unwrap();
+                self.skipping = Some(2 * *v);
+                return;
+            }
+            Element::Array(v) => {
+                self.tracker.push(TrackType::FirstArray(*v));
+                self.debug.push('[');
+            }
+            Element::Map(v) => {
+                self.tracker.push(TrackType::FirstMap(*v * 2));
+                self.debug.push('{');
+            }
+            Element::Timestamp(v) => write!(self.debug, "\"$fog-Time: {}\"", v).unwrap(),
+            Element::Hash(v) => self.push_id("Hash", v),
+            Element::Identity(v) => self.push_id("Identity", &**v),
+            Element::LockId(v) => self.push_id("LockId", &**v),
+            Element::StreamId(v) => self.push_id("StreamId", &**v),
+            Element::DataLockbox(v) => write!(
+                self.debug,
+                "\"$fog-DataLockbox(len={})\"",
+                v.as_bytes().len()
+            )
+            .unwrap(),
+            Element::IdentityLockbox(v) => write!(
+                self.debug,
+                "\"$fog-IdentityLockbox(len={})\"",
+                v.as_bytes().len()
+            )
+            .unwrap(),
+            Element::StreamLockbox(v) => write!(
+                self.debug,
+                "\"$fog-StreamLockbox(len={})\"",
+                v.as_bytes().len()
+            )
+            .unwrap(),
+            Element::LockLockbox(v) => write!(
+                self.debug,
+                "\"$fog-LockLockbox(len={})\"",
+                v.as_bytes().len()
+            )
+            .unwrap(),
+            Element::BareIdKey(v) => {
+                write!(self.debug, "\"$fog-BareIdKey:{}\"", v.to_base58()).unwrap()
+            }
+        }
+
+        self.advance();
+    }
+
+    fn push_id<T: Multibase + std::fmt::Display>(&mut self, name: &str, id: &T) {
+        use std::fmt::Write;
+        match self.options.multibase {
+            Some(base) => write!(self.debug, "\"$fog-{}:{}\"", name, id.to_multibase(base)),
+            None => write!(self.debug, "\"$fog-{}:{}\"", name, id),
+        }
+        .unwrap()
+    }
+
+    /// Move on to the next element, closing out any arrays or maps that are complete.
+    fn advance(&mut self) {
+        while let Some(track) = self.tracker.pop() {
+            match track {
+                TrackType::FirstArray(size) => {
+                    if size == 0 {
+                        self.debug.push_str(" ]");
+                    } else if size == 1 {
+                        self.debug.push(' ');
+                        self.tracker.push(TrackType::FirstArray(size - 1));
+                        break;
+                    } else {
+                        self.debug.push('\n');
+                        self.tracker.push(TrackType::Array(size - 1));
+                        break;
+                    }
+                }
+                TrackType::Array(size) => {
+                    if size == 0 {
+                        self.debug.push('\n');
+                        self.push_indent();
+                        self.debug.push(']');
+                    } else {
+                        self.debug.push_str(",\n");
+                        self.tracker.push(TrackType::Array(size - 1));
+                        break;
+                    }
+                }
+                TrackType::FirstMap(size) => {
+                    if size == 0 {
+                        self.debug.push_str(" }");
+                    } else if size == 1 {
+                        self.debug.push_str(": ");
+                        self.tracker.push(TrackType::FirstMap(size - 1));
+                        break;
+                    } else if size == 2 {
+                        self.debug.push(' ');
+                        self.tracker.push(TrackType::FirstMap(size - 1));
+                        break;
+                    } else {
+                        self.debug.push('\n');
+                        self.tracker.push(TrackType::Map(size - 1));
+                        break;
+                    }
+                }
+                TrackType::Map(size) => {
+                    if size == 0 {
+                        self.debug.push('\n');
+                        self.push_indent();
+                        self.debug.push('}');
+                    } else if (size & 0x1) == 1 {
+                        self.debug.push_str(": ");
+                        self.tracker.push(TrackType::Map(size - 1));
+                        break;
+                    } else {
+                        self.debug.push_str(",\n");
+                        self.tracker.push(TrackType::Map(size - 1));
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fog_value;
+
+    #[test]
+    fn layout() {
+        let value = fog_value!({
+            "a": [],
+            "b": [1],
+            "c": {},
+            "d": { "e": null },
+            "f": [true, false],
+        });
+        let text = print(&value, &PrettyOptions::new()).unwrap();
+        let expected = r#"{
+  "a": [ ],
+  "b": [ 1 ],
+  "c": { },
+  "d": { "e": null },
+  "f": [
+    true,
+    false
+  ]
+}"#;
+        assert_eq!(text, expected);
+    }
+
+    #[test]
+    fn max_depth() {
+        let value = fog_value!({
+            "a": [[1, 2], { "x": [3] }],
+            "b": [],
+            "c": 4,
+        });
+        let opts = PrettyOptions::new().max_depth(1).indent("\t");
+        let text = print(&value, &opts).unwrap();
+        let expected = "{\n\t\"a\": [ ...2 items ],\n\t\"b\": [ ],\n\t\"c\": 4\n}";
+        assert_eq!(text, expected);
+
+        let opts = PrettyOptions::new().max_depth(0);
+        assert_eq!(print(&value, &opts).unwrap(), "{ ...3 pairs }");
+    }
+
+    #[test]
+    fn max_bin() {
+        let value = crate::types::Value::Bin(vec![0u8; 30]);
+        let text = print(&value, &PrettyOptions::new().max_bin(3)).unwrap();
+        assert_eq!(text, "\"$fog-Bin(len=30):AAAA...\"");
+        let text = print(&value, &PrettyOptions::new()).unwrap();
+        assert!(!text.contains("..."));
+    }
+
+    #[test]
+    fn multibase() {
+        let hash = crate::types::Hash::new(b"data");
+        let value = crate::types::Value::Hash(hash.clone());
+        let text = print(&value, &PrettyOptions::new()).unwrap();
+        assert_eq!(text, format!("\"$fog-Hash:{}\"", hash));
+        let text = print(&value, &PrettyOptions::new().multibase(Base::Base64Url)).unwrap();
+        let encoded = text
+            .strip_prefix("\"$fog-Hash:")
+            .and_then(|t| t.strip_suffix('"'))
+            .unwrap();
+        assert!(encoded.starts_with('u'));
+        assert_eq!(crate::types::Hash::from_multibase(encoded).unwrap(), hash);
+    }
+
+    #[test]
+    fn bad_bytes() {
+        print_bytes(&[0x92, 0x00], &PrettyOptions::new()).unwrap_err();
+        print_bytes(&[0x00, 0x00], &PrettyOptions::new()).unwrap_err();
+    }
+}
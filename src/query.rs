@@ -5,6 +5,13 @@
 //! [`Validator`][crate::validator::Validator] to [`NewQuery::new`]. Queries must be validated by a
 //! [Schema][crate::schema::Schema] before they can be used.
 //!
+//! A query can also carry [`QueryHints`]: soft limits from whoever made the query, like how many
+//! results they want and how stale the results can be. Whoever runs the query is free to honor
+//! them or not.
+//!
+//! A [`LinkedQuery`] extends a query to also constrain the document that a hash in each entry
+//! refers to, for when entries need to be found based on what they link to.
+//!
 
 use std::collections::BTreeMap;
 
@@ -12,9 +19,13 @@ use crate::entry::Entry;
 use crate::validator::Validator;
 use crate::{
     de::FogDeserializer,
+    document::Document,
     element::Parser,
-    error::{Error, Result},
+    error::{Error, Result, ValidateError},
+    pretty::{self, PrettyOptions},
+    schema::Schema,
     ser::FogSerializer,
+    types::{Path, TimeDelta},
     validator::{Checklist, DataChecklist},
     value_ref::ValueRef,
     MAX_QUERY_SIZE,
@@ -22,11 +33,54 @@ use crate::{
 use fog_crypto::hash::Hash;
 use serde::{Deserialize, Serialize};
 
+#[inline]
+fn is_false(v: &bool) -> bool {
+    !v
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InnerLinked {
+    #[serde(with = "serde_bytes")]
+    query: Vec<u8>,
+    field: String,
+    link: Validator,
+    schema: Hash,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 struct InnerQuery {
     key: String,
     query: Validator,
+    #[serde(skip_serializing_if = "is_false", default)]
+    tombstoned: bool,
+    #[serde(skip_serializing_if = "QueryHints::is_empty", default)]
+    hints: QueryHints,
+}
+
+/// Hints from the maker of a query about the results they want.
+///
+/// Hints are soft limits: whoever runs the query can honor them to save work, but may also ignore
+/// them, so the results should still be checked. They are encoded as part of the query, under
+/// `hints`, so they are covered by the query's [hash][Query::hash] too.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct QueryHints {
+    /// The most results wanted. Any more can be dropped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_results: Option<u32>,
+    /// How old results can be and still be useful, for when they come from a cache or a replica
+    /// instead of the latest set of entries.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<TimeDelta>,
+}
+
+impl QueryHints {
+    /// Check if no hints are set.
+    pub fn is_empty(&self) -> bool {
+        self.max_results.is_none() && self.max_age.is_none()
+    }
 }
 
 /// A new Query, ready for encoding.
@@ -52,10 +106,26 @@ impl NewQuery {
             inner: InnerQuery {
                 key: key.to_owned(),
                 query,
+                tombstoned: false,
+                hints: QueryHints::default(),
             },
         }
     }
 
+    /// Ask for entries that have been retracted by a tombstone to be included in the results. By
+    /// default, whoever runs the query is expected to remove them with
+    /// [`Schema::apply_tombstones`][crate::schema::Schema::apply_tombstones].
+    pub fn include_tombstoned(mut self, include: bool) -> Self {
+        self.inner.tombstoned = include;
+        self
+    }
+
+    /// Attach hints about the results wanted, replacing any already set.
+    pub fn hints(mut self, hints: QueryHints) -> Self {
+        self.inner.hints = hints;
+        self
+    }
+
     /// Get the validator of this query.
     pub fn validator(&self) -> &Validator {
         &self.inner.query
@@ -107,9 +177,12 @@ impl NewQuery {
         }
         let regexes = parse_validator(&self.inner.query);
         if regexes > (max_regex as usize) {
-            return Err(Error::FailValidate(format!(
-                "Found {} regexes in query, only {} allowed",
-                regexes, max_regex
+            return Err(Error::FailValidate(ValidateError::Other(
+                format!(
+                    "Found {} regexes in query, only {} allowed",
+                    regexes, max_regex
+                )
+                .into(),
             )));
         }
         let mut ser = FogSerializer::default();
@@ -126,6 +199,22 @@ impl NewQuery {
     }
 }
 
+/// A stable identifier for a [`Query`], made up of the hash of the schema that decoded it and the
+/// hash of the encoded query.
+///
+/// Identifiers order by schema hash, then query hash, with hashes ordered by their encoded bytes.
+/// The query hash covers the query's key, validator, and options in their canonical encoded form,
+/// and validator fields left at their defaults aren't encoded at all, so the same query has the
+/// same identifier across crate versions. This makes it suitable for keying query result caches
+/// and persisting in indexes.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+pub struct QueryId {
+    /// Hash of the schema the query was decoded with.
+    pub schema: Hash,
+    /// Hash of the encoded query.
+    pub query: Hash,
+}
+
 /// For querying Entries.
 ///
 /// A Query contains a single validator and a key, which may be used for querying a set of Entries.
@@ -133,9 +222,13 @@ impl NewQuery {
 ///
 /// Queries are not meant to be used without associated context; they should be provided alongside
 /// information about what Document they are being used to query.
+///
+/// Queries compare, order, and hash by their [`QueryId`]. The query hash is computed once when
+/// the query is decoded, so these are cheap.
 #[derive(Clone, Debug)]
 pub struct Query {
     inner: InnerQuery,
+    hash: Hash,
     schema: Hash,
     types: BTreeMap<String, Validator>,
 }
@@ -147,22 +240,42 @@ impl Query {
         let regex_check = ValueRef::deserialize(&mut de)?;
         let regexes = crate::count_regexes(&regex_check["query"]);
         if regexes > (max_regex as usize) {
-            return Err(Error::FailValidate(format!(
-                "Found {} regexes in query, only {} allowed",
-                regexes, max_regex
+            return Err(Error::FailValidate(ValidateError::Other(
+                format!(
+                    "Found {} regexes in query, only {} allowed",
+                    regexes, max_regex
+                )
+                .into(),
             )));
         }
 
         // Parse into an actual validator
         let mut de = FogDeserializer::new(&buf);
         let inner = InnerQuery::deserialize(&mut de)?;
+
+        // Hash the canonical encoding, which may differ from what was decoded
+        let mut ser = FogSerializer::default();
+        inner.serialize(&mut ser)?;
+        let hash = Hash::new(ser.finish());
         Ok(Self {
             inner,
+            hash,
             schema: Hash::new([]),
             types: BTreeMap::new(),
         })
     }
 
+    // Attach the schema that decoded this query, along with the types its validator can refer to
+    pub(crate) fn with_schema(
+        mut self,
+        schema: &Hash,
+        types: &BTreeMap<String, Validator>,
+    ) -> Self {
+        self.schema = schema.clone();
+        self.types = types.clone();
+        self
+    }
+
     /// Get the validator of this query.
     pub fn validator(&self) -> &Validator {
         &self.inner.query
@@ -173,19 +286,288 @@ impl Query {
         &self.inner.key
     }
 
+    /// Iterate over every stored type in the schema that decoded this query, in order, along with
+    /// its validator. These are what [`Validator::Ref`] validators in the query refer to.
+    pub fn types(&self) -> impl Iterator<Item = (&str, &Validator)> {
+        self.types
+            .iter()
+            .map(|(name, validator)| (name.as_str(), validator))
+    }
+
+    /// Get the hash of the query, which is the hash of its encoded form as produced by
+    /// [`Schema::encode_query`][crate::schema::Schema::encode_query].
+    pub fn hash(&self) -> &Hash {
+        &self.hash
+    }
+
+    /// Get the stable identifier for this query. See [`QueryId`].
+    pub fn id(&self) -> QueryId {
+        QueryId {
+            schema: self.schema.clone(),
+            query: self.hash.clone(),
+        }
+    }
+
+    /// Check if the query asks for entries retracted by a tombstone to be included in the
+    /// results. If not, results should be passed through
+    /// [`Schema::apply_tombstones`][crate::schema::Schema::apply_tombstones] before returning them.
+    pub fn include_tombstoned(&self) -> bool {
+        self.inner.tombstoned
+    }
+
+    /// Get the hints attached to the query. These don't affect which entries match it, including
+    /// in [`subsumes`][Self::subsumes].
+    pub fn hints(&self) -> &QueryHints {
+        &self.inner.hints
+    }
+
+    /// Check if every entry matching another query would also match this one, meaning this
+    /// query's results always contain the other's. A cache holding this query's results can then
+    /// answer the other query by running it over them, instead of going back to the full set of
+    /// entries.
+    ///
+    /// Both queries must be for the same key, and must have been decoded by the same schema. This
+    /// is a conservative check: it returns false whenever it can't show that the other query is
+    /// narrower, even if it actually is.
+    pub fn subsumes(&self, other: &Query) -> bool {
+        self.schema == other.schema
+            && self.inner.key == other.inner.key
+            && (self.inner.tombstoned || !other.inner.tombstoned)
+            && self
+                .inner
+                .query
+                .subsumes(&self.types, &other.inner.query, 0)
+    }
+
     /// Execute the query against a given entry and see if it potentially matches.
     ///
     /// The [`DataChecklist`] must be completed in order to fully determine if
     /// the entry matches. If the checklist completes successfully, the entry is
     /// a match for the query.
     pub fn query(&self, entry: &Entry) -> Result<DataChecklist<()>> {
-        let parser = Parser::new(entry.data());
+        let parser = Parser::new(entry.data()).raw_floats(true);
         let checklist = Some(Checklist::new(&self.schema, &self.types));
         let (_, checklist) = self.inner.query.validate(&self.types, parser, checklist)?;
         Ok(DataChecklist::from_checklist(checklist.unwrap(), ()))
     }
 }
 
+impl PartialEq for Query {
+    fn eq(&self, other: &Self) -> bool {
+        self.schema == other.schema && self.hash == other.hash
+    }
+}
+
+impl Eq for Query {}
+
+impl PartialOrd for Query {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Query {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (&self.schema, &self.hash).cmp(&(&other.schema, &other.hash))
+    }
+}
+
+impl std::hash::Hash for Query {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        std::hash::Hash::hash(&self.schema, state);
+        std::hash::Hash::hash(&self.hash, state);
+    }
+}
+
+/// Queries display as their key, validator, and options, rendered the same way
+/// [`pretty::print`] renders data.
+impl std::fmt::Display for Query {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text =
+            pretty::print(&self.inner, &PrettyOptions::new()).map_err(|_| std::fmt::Error)?;
+        f.write_str(&text)
+    }
+}
+
+/// A query on entries that also constrains a document linked to from each entry.
+///
+/// This is a single-level join: an entry matches if it passes its [`Query`], and the hash at
+/// `field` in the entry refers to a document that uses the linked schema and passes the `link`
+/// validator. The `link` validator is checked against the linked schema's document validator the
+/// same way a query is checked against an entry validator, so it can only use what that schema's
+/// query permissions allow.
+///
+/// Documents are fetched through a caller-provided resolver when running the query with
+/// [`matches`][LinkedQuery::matches], which also uses it to complete the entry query's
+/// [`DataChecklist`].
+///
+/// Linked queries can be sent elsewhere with [`encode`][LinkedQuery::encode] and read back with
+/// [`decode`][LinkedQuery::decode]. The encoding is a map holding the encoded entry `query` as
+/// binary, the `field` path as a JSON-Pointer-style string, the `link` validator, and the hash of
+/// the linked `schema`.
+#[derive(Clone, Debug)]
+pub struct LinkedQuery {
+    query: Query,
+    field: Path,
+    link: Validator,
+    schema: Hash,
+    types: BTreeMap<String, Validator>,
+}
+
+impl LinkedQuery {
+    /// Create a new linked query from a decoded entry query, the path to the hash field within
+    /// each entry, the schema linked documents must use, and the validator they must pass. Fails
+    /// if the `link` validator isn't allowed by the linked schema's query permissions.
+    pub fn new(query: Query, field: Path, linked: &Schema, link: Validator) -> Result<Self> {
+        let types: BTreeMap<String, Validator> = linked
+            .types()
+            .map(|(name, validator)| (name.to_owned(), validator.clone()))
+            .collect();
+        if !linked.doc_validator().query_check(&types, &link) {
+            return Err(Error::FailValidate(ValidateError::Other(
+                "Linked query is not allowed by the linked schema".into(),
+            )));
+        }
+        Ok(Self {
+            query,
+            field,
+            link,
+            schema: linked.hash().clone(),
+            types,
+        })
+    }
+
+    /// Get the query run against entries.
+    pub fn entry_query(&self) -> &Query {
+        &self.query
+    }
+
+    /// Get the path to the hash field within entries.
+    pub fn field(&self) -> &Path {
+        &self.field
+    }
+
+    /// Get the validator run against linked documents.
+    pub fn link(&self) -> &Validator {
+        &self.link
+    }
+
+    /// Get the hash of the schema linked documents must use.
+    pub fn link_schema(&self) -> &Hash {
+        &self.schema
+    }
+
+    /// Encode the linked query into a byte sequence. Fails if the encoding is larger than
+    /// [`MAX_QUERY_SIZE`].
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let mut ser = FogSerializer::default();
+        self.query.inner.serialize(&mut ser)?;
+        let inner = InnerLinked {
+            query: ser.finish(),
+            field: self.field.to_string(),
+            link: self.link.clone(),
+            schema: self.schema.clone(),
+        };
+        let mut ser = FogSerializer::default();
+        inner.serialize(&mut ser)?;
+        let buf = ser.finish();
+        if buf.len() > MAX_QUERY_SIZE {
+            Err(Error::LengthTooLong {
+                max: MAX_QUERY_SIZE,
+                actual: buf.len(),
+            })
+        } else {
+            Ok(buf)
+        }
+    }
+
+    /// Attempt to decode a linked query, using the schema of the entries it runs against and the
+    /// schema of the documents it links to. The entry query is decoded and checked with
+    /// [`Schema::decode_query`], and the `link` validator is checked as in
+    /// [`new`][LinkedQuery::new]. Also fails if the encoding is larger than the entry schema's
+    /// query size limit, if the `link` validator has more regexes than the linked schema allows,
+    /// or if the linked schema isn't the one the query was made for.
+    pub fn decode(entries: &Schema, linked: &Schema, buf: Vec<u8>) -> Result<Self> {
+        entries.limits().check_query(buf.len())?;
+
+        // Check to see how many regexes are in the link validator
+        let regex_check = ValueRef::deserialize(&mut FogDeserializer::new(&buf))?;
+        let regexes = crate::count_regexes(&regex_check["link"]);
+        if regexes > (linked.max_regex() as usize) {
+            return Err(Error::FailValidate(ValidateError::Other(
+                format!(
+                    "Found {} regexes in linked query, only {} allowed",
+                    regexes,
+                    linked.max_regex()
+                )
+                .into(),
+            )));
+        }
+
+        let inner = InnerLinked::deserialize(&mut FogDeserializer::new(&buf))?;
+        if &inner.schema != linked.hash() {
+            return Err(Error::SchemaMismatch {
+                actual: Some(inner.schema),
+                expected: Some(linked.hash().clone()),
+            });
+        }
+        let field = Path::parse(&inner.field).ok_or_else(|| {
+            Error::FailValidate(ValidateError::Other(
+                format!("linked query field {:?} isn't a valid path", inner.field).into(),
+            ))
+        })?;
+        let query = entries.decode_query(inner.query)?;
+        Self::new(query, field, linked, inner.link)
+    }
+
+    /// Check if an entry matches the query, fetching documents with `fetch`.
+    ///
+    /// Returns false if the entry fails its query, if the field isn't a hash, or if the linked
+    /// document doesn't use the linked schema or fails the `link` validator. Fails if a needed
+    /// document can't be found, if `fetch` returns a document other than the one asked for, or if
+    /// `fetch` fails.
+    pub fn matches<F>(&self, entry: &Entry, mut fetch: F) -> Result<bool>
+    where
+        F: FnMut(&Hash) -> Result<Option<Document>>,
+    {
+        let mut get = |hash: &Hash| {
+            let doc =
+                fetch(hash)?.ok_or_else(|| Error::Store(format!("Missing document {}", hash)))?;
+            if doc.hash() != hash {
+                return Err(Error::FailValidate(ValidateError::Other(
+                    format!("Document {} was fetched as {}", doc.hash(), hash).into(),
+                )));
+            }
+            Ok(doc)
+        };
+
+        // Run the entry query, completing its checklist
+        let mut checklist = match self.query.query(entry) {
+            Ok(checklist) => checklist,
+            Err(Error::FailValidate(_)) => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        for (hash, item) in checklist.iter() {
+            if item.check(&get(&hash)?).is_err() {
+                return Ok(false);
+            }
+        }
+        checklist.complete()?;
+
+        // Follow the link
+        let value = ValueRef::deserialize(&mut FogDeserializer::new(entry.data()))?;
+        let Some(hash) = self.field.get_ref(&value).and_then(|v| v.as_hash()) else {
+            return Ok(false);
+        };
+        let doc = get(hash)?;
+        if doc.schema_hash() != Some(&self.schema) {
+            return Ok(false);
+        }
+        let parser = Parser::new(doc.data()).raw_floats(true);
+        Ok(self.link.validate(&self.types, parser, None).is_ok())
+    }
+}
+
 #[cfg(test)]
 mod test {
     use regex::Regex;
@@ -214,6 +596,95 @@ mod test {
         assert!(Query::new(enc_query, 2).is_ok());
     }
 
+    #[test]
+    fn subsumes() {
+        let decode = |key: &str, validator: Validator, tombstoned: bool| {
+            let query = NewQuery::new(key, validator).include_tombstoned(tombstoned);
+            Query::new(query.complete(0).unwrap(), 0).unwrap()
+        };
+        let broad = MapValidator::new()
+            .req_add("name", StrValidator::new().build())
+            .build();
+        let narrow = MapValidator::new()
+            .req_add("name", StrValidator::new().in_add("a").in_add("b").build())
+            .build();
+        let q_broad = decode("test", broad.clone(), false);
+        let q_narrow = decode("test", narrow.clone(), false);
+        assert!(q_broad.subsumes(&q_narrow));
+        assert!(q_broad.subsumes(&q_broad));
+        assert!(!q_narrow.subsumes(&q_broad));
+        assert!(!q_broad.subsumes(&decode("other", narrow.clone(), false)));
+        assert!(!q_broad.subsumes(&decode("test", narrow.clone(), true)));
+        assert!(decode("test", broad, true).subsumes(&q_narrow));
+    }
+
+    #[test]
+    fn identity() {
+        use std::collections::BTreeSet;
+        let decode = |key: &str| {
+            let query = NewQuery::new(key, StrValidator::new().build());
+            Query::new(query.complete(0).unwrap(), 0).unwrap()
+        };
+        let a = decode("a");
+        let b = decode("b");
+        assert_eq!(a, decode("a"));
+        assert_ne!(a, b);
+        assert_eq!(&a.id().query, a.hash());
+        let set: BTreeSet<Query> = [a.clone(), b.clone(), a.clone()].into_iter().collect();
+        assert_eq!(set.len(), 2);
+
+        // Identifiers survive a round trip through fog-pack
+        let mut ser = FogSerializer::default();
+        a.id().serialize(&mut ser).unwrap();
+        let buf = ser.finish();
+        let id = QueryId::deserialize(&mut FogDeserializer::new(&buf)).unwrap();
+        assert_eq!(id, a.id());
+    }
+
+    #[test]
+    fn hints() {
+        let decode = |query: NewQuery| Query::new(query.complete(0).unwrap(), 0).unwrap();
+        let plain = decode(NewQuery::new("test", Validator::Any));
+        assert!(plain.hints().is_empty());
+        assert!(!plain.to_string().contains("hints"));
+
+        let hints = QueryHints {
+            max_results: Some(20),
+            max_age: Some(TimeDelta::from_secs(60)),
+        };
+        let hinted = decode(NewQuery::new("test", Validator::Any).hints(hints.clone()));
+        assert_eq!(hinted.hints(), &hints);
+        assert_ne!(hinted.id(), plain.id());
+        assert!(plain.subsumes(&hinted) && hinted.subsumes(&plain));
+
+        // Unknown hints are rejected, so re-encoding a query never changes its hash
+        let buf = NewQuery::new("test", Validator::Any)
+            .hints(hints)
+            .complete(0)
+            .unwrap();
+        let mut value = crate::types::Value::deserialize(&mut FogDeserializer::new(&buf)).unwrap();
+        value["hints"]
+            .as_map_mut()
+            .unwrap()
+            .insert("priority".into(), 1u8.into());
+        let mut ser = FogSerializer::default();
+        value.serialize(&mut ser).unwrap();
+        assert!(Query::new(ser.finish(), 0).is_err());
+    }
+
+    #[test]
+    fn display() {
+        let validator = MapValidator::new()
+            .req_add("name", StrValidator::new().in_add("a").build())
+            .build();
+        let query = NewQuery::new("test", validator).include_tombstoned(true);
+        let query = Query::new(query.complete(0).unwrap(), 0).unwrap();
+        let text = query.to_string();
+        assert!(text.contains("\"key\": \"test\""), "{}", text);
+        assert!(text.contains("\"tombstoned\": true"), "{}", text);
+        assert!(text.contains("\"in\": [\n"), "{}", text);
+    }
+
     #[test]
     fn max_regex_in_str() {
         let matches = Some(Box::new(Regex::new("[a-z]").unwrap()));
@@ -230,4 +701,113 @@ mod test {
         assert!(Query::new(enc_query.clone(), 1).is_ok());
         assert!(Query::new(enc_query, 2).is_ok());
     }
+
+    #[test]
+    fn linked() {
+        use crate::{
+            document::NewDocument,
+            entry::NewEntry,
+            fog_value,
+            schema::{NoSchema, SchemaBuilder},
+            validator::HashValidator,
+        };
+        use std::collections::HashMap;
+
+        let author_doc = SchemaBuilder::new(
+            MapValidator::new()
+                .req_add("name", StrValidator::new().query(true).build())
+                .map_ok(true)
+                .build(),
+        )
+        .build()
+        .unwrap();
+        let authors = Schema::from_doc(&author_doc).unwrap();
+        let post_doc = SchemaBuilder::new(Validator::Any)
+            .entry_add(
+                "post",
+                MapValidator::new()
+                    .req_add("author", HashValidator::new().build())
+                    .build(),
+                None,
+            )
+            .build()
+            .unwrap();
+        let posts = Schema::from_doc(&post_doc).unwrap();
+
+        let mut store = HashMap::new();
+        let mut author = |schema: Option<&Schema>, name: &str| {
+            let doc = NewDocument::new(schema.map(|s| s.hash()), fog_value!({ "name": name }));
+            let doc = match schema {
+                Some(schema) => schema.validate_new_doc(doc.unwrap()).unwrap(),
+                None => NoSchema::validate_new_doc(doc.unwrap()).unwrap(),
+            };
+            store.insert(doc.hash().clone(), doc.clone());
+            doc.hash().clone()
+        };
+        let alice = author(Some(&authors), "alice");
+        let bob = author(Some(&authors), "bob");
+        let unchecked = author(None, "alice");
+        let missing = Hash::new(b"missing");
+
+        let parent = posts
+            .validate_new_doc(NewDocument::new(Some(posts.hash()), 0u8).unwrap())
+            .unwrap();
+        let post = |author: &Hash| {
+            let entry =
+                NewEntry::new("post", &parent, fog_value!({ "author": author.clone() })).unwrap();
+            posts.validate_new_entry(entry).unwrap().complete().unwrap()
+        };
+
+        let query = NewQuery::new("post", Validator::Any);
+        let query = posts
+            .decode_query(posts.encode_query(query).unwrap())
+            .unwrap();
+        let by_alice = MapValidator::new()
+            .req_add("name", StrValidator::new().in_add("alice").build())
+            .build();
+        let linked = LinkedQuery::new(
+            query.clone(),
+            Path::new().key("author"),
+            &authors,
+            by_alice.clone(),
+        )
+        .unwrap();
+        let fetch = |hash: &Hash| Ok(store.get(hash).cloned());
+        assert!(linked.matches(&post(&alice), fetch).unwrap());
+        assert!(!linked.matches(&post(&bob), fetch).unwrap());
+        assert!(!linked.matches(&post(&unchecked), fetch).unwrap());
+        assert!(linked.matches(&post(&missing), fetch).is_err());
+
+        // Fetched documents must be the ones asked for
+        let swapped =
+            |hash: &Hash| Ok(store.get(if hash == &alice { &bob } else { hash }).cloned());
+        assert!(matches!(
+            linked.matches(&post(&alice), swapped),
+            Err(Error::FailValidate(_))
+        ));
+
+        // Linked queries survive encoding, and are checked when decoded
+        let encoded = linked.encode().unwrap();
+        let decoded = LinkedQuery::decode(&posts, &authors, encoded.clone()).unwrap();
+        assert_eq!(decoded.entry_query(), linked.entry_query());
+        assert_eq!(decoded.field(), linked.field());
+        assert_eq!(decoded.link_schema(), linked.link_schema());
+        assert!(decoded.matches(&post(&alice), fetch).unwrap());
+        assert!(!decoded.matches(&post(&bob), fetch).unwrap());
+        assert!(matches!(
+            LinkedQuery::decode(&posts, &posts, encoded),
+            Err(Error::SchemaMismatch { .. })
+        ));
+
+        // The field must hold a hash
+        let elsewhere =
+            LinkedQuery::new(query.clone(), Path::new().key("x"), &authors, by_alice).unwrap();
+        assert!(!elsewhere.matches(&post(&alice), fetch).unwrap());
+
+        // The linked schema's query permissions apply
+        let by_length = MapValidator::new()
+            .req_add("name", StrValidator::new().max_len(3).build())
+            .build();
+        assert!(LinkedQuery::new(query, Path::new().key("author"), &authors, by_length).is_err());
+    }
 }
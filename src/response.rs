@@ -0,0 +1,315 @@
+//! Query responses, for returning query results as a single verifiable unit.
+//!
+//! A [`QueryResponse`] bundles the encoded entries matching a [`Query`] together with the hash of
+//! the document they're attached to and the hash of the query itself, in a document using the
+//! built-in [`schema`]. The responder may sign it. Because a response is an ordinary document, it
+//! can be cached, relayed through other nodes, and audited later, and anyone holding it can tell
+//! exactly which query it answered and who claimed that answer.
+//!
+//! A response only says what its responder returned. Each entry still has to be decoded and
+//! validated with the parent document's schema, which [`QueryResponse::entries`] does.
+//!
+//! ```
+//! # use fog_pack::{document::*, entry::*, query::*, response::*, schema::*, types::*, validator::*};
+//! # fn main() -> fog_pack::error::Result<()> {
+//! # let schema_doc = SchemaBuilder::new(Validator::Any)
+//! #     .entry_add("post", Validator::Any, None)
+//! #     .build()?;
+//! # let schema = Schema::from_doc(&schema_doc)?;
+//! # let board = schema.validate_new_doc(NewDocument::new(Some(schema.hash()), "board")?)?;
+//! # let post = NewEntry::new("post", &board, "hello")?;
+//! # let post = schema.validate_new_entry(post)?.complete()?;
+//! let query = schema.decode_query(schema.encode_query(NewQuery::new("post", Validator::Any))?)?;
+//!
+//! // The responder answers the query
+//! let responder = IdentityKey::new();
+//! let response = NewQueryResponse::new(board.hash(), &query)
+//!     .entry_add(&schema, post)?
+//!     .sign(&responder)?;
+//! let encoded = QueryResponse::from_doc(response)?.encode()?;
+//!
+//! // The requester checks it
+//! let response = QueryResponse::decode(encoded)?;
+//! assert!(response.answers(&query));
+//! assert_eq!(response.responder(), Some(responder.id()));
+//! for entry in response.entries(&schema, &board)? {
+//!     let entry = entry.complete()?;
+//!     assert_eq!(entry.deserialize::<&str>()?, "hello");
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    document::{Document, NewDocument},
+    entry::Entry,
+    error::{Error, Result, ValidateError},
+    query::Query,
+    schema::{Schema, SchemaBuilder},
+    signer::Signer,
+    types::{Hash, Identity},
+    validator::*,
+};
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use std::sync::OnceLock;
+
+static SCHEMA: OnceLock<Schema> = OnceLock::new();
+
+/// Get the built-in schema that query response documents use.
+pub fn schema() -> &'static Schema {
+    SCHEMA.get_or_init(|| {
+        let validator = MapValidator::new()
+            .req_add("parent", HashValidator::new().build())
+            .req_add("query", HashValidator::new().build())
+            .req_add("key", StrValidator::new().build())
+            .req_add(
+                "entries",
+                ArrayValidator::new()
+                    .items(BinValidator::new().build())
+                    .build(),
+            )
+            .build();
+        let doc = SchemaBuilder::new(validator)
+            .name("fog-pack query response")
+            .description("Entries returned in answer to a query")
+            .build()
+            .expect("built-in query response schema should be valid");
+        Schema::from_doc(&doc).expect("built-in query response schema should be valid")
+    })
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Response {
+    parent: Hash,
+    query: Hash,
+    key: String,
+    entries: Vec<ByteBuf>,
+}
+
+/// A query response that is still being assembled.
+#[derive(Clone, Debug)]
+pub struct NewQueryResponse(Response);
+
+impl NewQueryResponse {
+    /// Start a response to a query made against the given parent document. It holds no entries
+    /// until they are added.
+    pub fn new(parent: &Hash, query: &Query) -> Self {
+        Self(Response {
+            parent: parent.clone(),
+            query: query.hash().clone(),
+            key: query.key().to_owned(),
+            entries: Vec::new(),
+        })
+    }
+
+    /// Add a matching entry, encoding it with its schema. Fails if the entry isn't attached to
+    /// the response's parent document, doesn't use the query's key, or can't be encoded.
+    pub fn entry_add(mut self, schema: &Schema, entry: Entry) -> Result<Self> {
+        if entry.parent() != &self.0.parent || entry.key() != self.0.key {
+            return Err(Error::FailValidate(ValidateError::Other(
+                format!(
+                    "entry {:?} on {} doesn't answer a query for {:?} on {}",
+                    entry.key(),
+                    entry.parent(),
+                    self.0.key,
+                    self.0.parent
+                )
+                .into(),
+            )));
+        }
+        let (_, encoded, _) = schema.encode_entry(entry)?;
+        self.0.entries.push(ByteBuf::from(encoded));
+        Ok(self)
+    }
+
+    /// Finish the response without signing it.
+    pub fn finish(self) -> Result<Document> {
+        schema().validate_new_doc(NewDocument::new(Some(schema().hash()), &self.0)?)
+    }
+
+    /// Finish the response, signing it as the responder.
+    pub fn sign<S: Signer + ?Sized>(self, key: &S) -> Result<Document> {
+        let doc = NewDocument::new(Some(schema().hash()), &self.0)?.sign(key)?;
+        schema().validate_new_doc(doc)
+    }
+}
+
+/// A query response, read from a query response document.
+#[derive(Clone, Debug)]
+pub struct QueryResponse {
+    doc: Document,
+    response: Response,
+}
+
+impl QueryResponse {
+    /// Read a response out of a document. Fails if the document doesn't use the query response
+    /// schema, or has an invalid signature.
+    pub fn from_doc(doc: Document) -> Result<Self> {
+        if doc.schema_hash() != Some(schema().hash()) {
+            return Err(Error::SchemaMismatch {
+                actual: doc.schema_hash().cloned(),
+                expected: Some(schema().hash().clone()),
+            });
+        }
+        let doc = doc.verify()?;
+        let response = doc.deserialize()?;
+        Ok(Self { doc, response })
+    }
+
+    /// Encode the response for sending, caching, or storing.
+    pub fn encode(self) -> Result<Vec<u8>> {
+        let (_, encoded) = schema().encode_doc(self.doc)?;
+        Ok(encoded)
+    }
+
+    /// Decode a response encoded with [`encode`][Self::encode].
+    pub fn decode(encoded: Vec<u8>) -> Result<Self> {
+        Self::from_doc(schema().decode_doc(encoded)?)
+    }
+
+    /// Get the response document.
+    pub fn doc(&self) -> &Document {
+        &self.doc
+    }
+
+    /// Get the hash of the response document.
+    pub fn hash(&self) -> &Hash {
+        self.doc.hash()
+    }
+
+    /// Get the hash of the document the query was made against.
+    pub fn parent(&self) -> &Hash {
+        &self.response.parent
+    }
+
+    /// Get the hash of the query this responds to. See [`Query::hash`].
+    pub fn query_hash(&self) -> &Hash {
+        &self.response.query
+    }
+
+    /// Get the entry key the query was made against.
+    pub fn key(&self) -> &str {
+        &self.response.key
+    }
+
+    /// Get the Identity that signed the response, if it was signed.
+    pub fn responder(&self) -> Option<&Identity> {
+        self.doc.signer()
+    }
+
+    /// Get the number of entries in the response.
+    pub fn len(&self) -> usize {
+        self.response.entries.len()
+    }
+
+    /// Check if the response holds no entries.
+    pub fn is_empty(&self) -> bool {
+        self.response.entries.is_empty()
+    }
+
+    /// Check if this is a response to the given query.
+    pub fn answers(&self, query: &Query) -> bool {
+        &self.response.query == query.hash() && self.response.key == query.key()
+    }
+
+    /// Decode the response's entries, using the schema of the parent document. Fails if the
+    /// parent document doesn't match the response, or if any entry fails to decode. As with
+    /// [`Schema::decode_entry`], each entry's checklist must be completed before it can be used.
+    pub fn entries(&self, schema: &Schema, parent: &Document) -> Result<Vec<DataChecklist<Entry>>> {
+        if parent.hash() != &self.response.parent {
+            return Err(Error::FailValidate(ValidateError::Other(
+                format!(
+                    "response is for parent document {}, not {}",
+                    self.response.parent,
+                    parent.hash()
+                )
+                .into(),
+            )));
+        }
+        self.response
+            .entries
+            .iter()
+            .map(|encoded| schema.decode_entry(encoded.to_vec(), &self.response.key, parent))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{entry::NewEntry, query::NewQuery, types::IdentityKey};
+
+    #[test]
+    fn roundtrip() {
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .entry_add("post", Validator::Any, None)
+            .entry_add("admin", Validator::Any, None)
+            .build()
+            .unwrap();
+        let data_schema = Schema::from_doc(&schema_doc).unwrap();
+        let board = NewDocument::new(Some(data_schema.hash()), "board").unwrap();
+        let board = data_schema.validate_new_doc(board).unwrap();
+        let other = NewDocument::new(Some(data_schema.hash()), "other").unwrap();
+        let other = data_schema.validate_new_doc(other).unwrap();
+        let entry = |key: &str, parent: &Document, data: u8| {
+            let entry = NewEntry::new(key, parent, data).unwrap();
+            data_schema
+                .validate_new_entry(entry)
+                .unwrap()
+                .complete()
+                .unwrap()
+        };
+        let query = |validator: Validator| {
+            let encoded = data_schema
+                .encode_query(NewQuery::new("post", validator))
+                .unwrap();
+            data_schema.decode_query(encoded).unwrap()
+        };
+        let all = query(Validator::Any);
+        let some = query(IntValidator::new().max(1u8).build());
+        assert_ne!(all.hash(), some.hash());
+
+        let response = NewQueryResponse::new(board.hash(), &all)
+            .entry_add(&data_schema, entry("post", &board, 1))
+            .unwrap()
+            .entry_add(&data_schema, entry("post", &board, 2))
+            .unwrap();
+        assert!(response
+            .clone()
+            .entry_add(&data_schema, entry("admin", &board, 3))
+            .is_err());
+        assert!(response
+            .clone()
+            .entry_add(&data_schema, entry("post", &other, 3))
+            .is_err());
+
+        let responder = IdentityKey::new();
+        let encoded = QueryResponse::from_doc(response.sign(&responder).unwrap())
+            .unwrap()
+            .encode()
+            .unwrap();
+        let decoded = QueryResponse::decode(encoded).unwrap();
+        assert!(decoded.answers(&all));
+        assert!(!decoded.answers(&some));
+        assert_eq!(decoded.parent(), board.hash());
+        assert_eq!(decoded.key(), "post");
+        assert_eq!(decoded.responder(), Some(responder.id()));
+        assert_eq!(decoded.len(), 2);
+        let values: Vec<u8> = decoded
+            .entries(&data_schema, &board)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.complete().unwrap().deserialize().unwrap())
+            .collect();
+        assert_eq!(values, vec![1, 2]);
+        assert!(decoded.entries(&data_schema, &other).is_err());
+
+        // Unsigned responses are fine, but other documents aren't responses
+        let unsigned = NewQueryResponse::new(board.hash(), &some).finish().unwrap();
+        let unsigned = QueryResponse::from_doc(unsigned).unwrap();
+        assert!(unsigned.is_empty());
+        assert_eq!(unsigned.responder(), None);
+        assert!(QueryResponse::from_doc(board).is_err());
+    }
+}
@@ -5,18 +5,29 @@
 //! be easily built from scratch using a [`SchemaBuilder`].
 //!
 use std::{
-    collections::BTreeMap,
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap, HashSet},
     convert::{TryFrom, TryInto},
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard, OnceLock},
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
 };
 
 use crate::document::*;
 use crate::entry::*;
 pub use compress::*;
-use element::Parser;
+use element::{Parser, Scanner};
+pub use lint::{Lint, Severity};
 use query::{NewQuery, Query};
 
-use crate::error::{Error, Result};
-use crate::validator::{Checklist, DataChecklist, Validator};
+use crate::compact::Reducer;
+use crate::error::{Error, Result, ValidateError};
+use crate::types::Path;
+use crate::validator::{
+    Checklist, Compiler, DataChecklist, MapValidator, Program, StrValidator, Validator,
+};
 use crate::*;
 use serde::{Deserialize, Serialize};
 
@@ -39,6 +50,20 @@ fn u8_is_zero(v: &u8) -> bool {
     *v == 0
 }
 
+#[inline]
+fn is_false(v: &bool) -> bool {
+    !v
+}
+
+#[inline]
+fn threshold_is_default(v: &u32) -> bool {
+    *v == DEFAULT_COMPRESS_THRESHOLD
+}
+
+fn default_threshold() -> u32 {
+    DEFAULT_COMPRESS_THRESHOLD
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 struct InnerSchema {
@@ -57,6 +82,45 @@ struct InnerSchema {
     version: Integer,
     #[serde(skip_serializing_if = "u8_is_zero", default)]
     max_regex: u8,
+    #[serde(skip_serializing_if = "is_false", default)]
+    raw_floats: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    encrypted: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    weak: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    immutable: Vec<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    external: BTreeMap<String, u32>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    reducers: BTreeMap<String, Reducer>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    volatile: BTreeMap<String, Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    entry_keys: Option<Box<StrValidator>>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    tombstones: Vec<String>,
+    #[serde(skip_serializing_if = "u8_is_zero", default)]
+    threshold: u8,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    signers: Vec<Identity>,
+    #[serde(skip_serializing_if = "is_false", default)]
+    unsigned: bool,
+    #[serde(
+        skip_serializing_if = "threshold_is_default",
+        default = "default_threshold"
+    )]
+    compress_threshold: u32,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    maintainer: Option<Identity>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    parent: Option<Hash>,
+    /// The validators compiled into a single program, once the schema is loaded
+    #[serde(skip)]
+    program: Program,
+    /// Where the document validator starts in `program`
+    #[serde(skip)]
+    doc_root: usize,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -65,6 +129,9 @@ struct EntrySchema {
     entry: Validator, // required
     #[serde(skip_serializing_if = "compress_is_default", default)]
     compress: Compress,
+    /// Where the entry validator starts in the schema's program
+    #[serde(skip)]
+    root: usize,
 }
 
 /// Validation for documents without a schema.
@@ -82,6 +149,14 @@ impl NoSchema {
     /// Validate a [`NewDocument`], turning it into a [`Document`]. Fails if the internal data
     /// isn't actually valid fog-pack, which can sometimes happen with a bad Serialize
     /// implementation for the data.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(hash = %doc.hash(), size = doc.size())
+        )
+    )]
     pub fn validate_new_doc(doc: NewDocument) -> Result<Document> {
         // Check that this document doesn't have a schema
         if let Some(schema) = doc.schema_hash() {
@@ -97,12 +172,45 @@ impl NoSchema {
         let (parser, _) = Validator::Any.validate(&types, parser, None)?;
         parser.finish()?;
 
-        Ok(Document::from_new(doc))
+        let mut doc = Document::from_new(doc);
+        doc.set_validated();
+        Ok(doc)
     }
 
     /// Re-encode a validated [`Document`], returning the resulting Document's hash and fully encoded
     /// format.
     pub fn encode_doc(doc: Document) -> Result<(Hash, Vec<u8>)> {
+        Self::encode_doc_scratch(doc, &mut Vec::new())
+    }
+
+    /// Encode a [`Document`] like [`encode_doc`][Self::encode_doc], without blocking an async
+    /// runtime. See [`Schema::encode_doc_async`] for details.
+    pub async fn encode_doc_async(doc: Document) -> Result<(Hash, Vec<u8>)> {
+        Self::encode_doc_async_with(doc, spawn_thread).await
+    }
+
+    /// Encode a [`Document`] like [`encode_doc`][Self::encode_doc], using `spawn` to run large
+    /// encodes on a blocking thread pool. See [`Schema::encode_doc_async_with`] for details.
+    pub async fn encode_doc_async_with<S: FnOnce(BlockingJob)>(
+        doc: Document,
+        spawn: S,
+    ) -> Result<(Hash, Vec<u8>)> {
+        if doc.data().len() < OFFLOAD_THRESHOLD {
+            return Self::encode_doc(doc);
+        }
+        offload(Box::new(move || Self::encode_doc(doc)), spawn).await
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            name = "encode_doc",
+            skip_all,
+            fields(hash = %doc.hash(), size = doc.size())
+        )
+    )]
+    fn encode_doc_scratch(doc: Document, scratch: &mut Vec<u8>) -> Result<(Hash, Vec<u8>)> {
         // Check that this document doesn't have a schema
         if let Some(schema) = doc.schema_hash() {
             return Err(Error::SchemaMismatch {
@@ -112,23 +220,84 @@ impl NoSchema {
         }
 
         // Compress the document
-        let (hash, doc, compression) = doc.complete();
-        let compression = match compression {
-            None => Compress::General {
-                algorithm: 0,
-                level: 3,
-            },
-            Some(None) => Compress::None,
-            Some(Some(level)) => Compress::General {
-                algorithm: 0,
-                level,
-            },
+        let (hash, doc, compression) = doc.verify()?.complete();
+        let (compression, threshold) = match compression {
+            None => (
+                Compress::General {
+                    algorithm: 0,
+                    level: 3,
+                },
+                DEFAULT_COMPRESS_THRESHOLD,
+            ),
+            Some(None) => (Compress::None, 0),
+            Some(Some(level)) => (
+                Compress::General {
+                    algorithm: 0,
+                    level,
+                },
+                0,
+            ),
         };
-        Ok((hash, compress_doc(doc, &compression)))
+        Ok((
+            hash,
+            compress_doc(doc, &compression, None, threshold, scratch),
+        ))
     }
 
     /// Decode a document that doesn't have a schema.
     pub fn decode_doc(doc: Vec<u8>) -> Result<Document> {
+        Self::decode_doc_with_limits(doc, &Limits::default())
+    }
+
+    /// Decode a document that doesn't have a schema, setting how
+    /// [`Document::deserialize`] handles fields that the target type doesn't use.
+    pub fn decode_doc_with_unknown_fields(
+        doc: Vec<u8>,
+        unknown: UnknownFields,
+    ) -> Result<Document> {
+        let mut doc = Self::decode_doc(doc)?;
+        doc.set_unknown_fields(unknown);
+        Ok(doc)
+    }
+
+    /// Decode a document that doesn't have a schema, rejecting it if it exceeds the given
+    /// [`Limits`].
+    pub fn decode_doc_with_limits(doc: Vec<u8>, limits: &Limits) -> Result<Document> {
+        Self::decode_doc_with_policy(doc, limits, &VerifyPolicy::default())
+    }
+
+    /// Decode a document that doesn't have a schema, rejecting it if it exceeds the given
+    /// [`Limits`] or its signatures don't follow the given [`VerifyPolicy`].
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            name = "decode_doc",
+            skip_all,
+            fields(size = doc.len(), hash = tracing::field::Empty)
+        )
+    )]
+    pub fn decode_doc_with_policy(
+        doc: Vec<u8>,
+        limits: &Limits,
+        policy: &VerifyPolicy,
+    ) -> Result<Document> {
+        let (doc, pending) = Self::decode_doc_unverified(doc, limits)?;
+        policy.check_doc(&doc)?;
+        verify_pending(pending)?;
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("hash", tracing::field::display(doc.hash()));
+        Ok(doc)
+    }
+
+    // Decode and validate a document, leaving its signature to be verified by the caller.
+    fn decode_doc_unverified(
+        doc: Vec<u8>,
+        limits: &Limits,
+    ) -> Result<(Document, Vec<PendingSignature>)> {
+        limits.check_doc(doc.len())?;
+        let (version, doc) = strip_version(doc, &limits.format_version)?;
+
         // Check for hash
         let split = SplitDoc::split(&doc)?;
         if !split.hash_raw.is_empty() {
@@ -139,21 +308,39 @@ impl NoSchema {
         }
 
         // Decompress
-        let doc = Document::new(decompress_doc(doc, &Compress::None)?)?;
+        let (mut doc, pending) = Document::new_unverified(decompress_doc(
+            doc,
+            &Compress::None,
+            None,
+            limits.doc_size(),
+        )?)?;
+        doc.set_format_version(version);
+        limits.check_doc(doc.size())?;
 
         // Validate
         let types = BTreeMap::new();
-        let parser = Parser::new(doc.data());
+        let parser = Parser::new(doc.data()).max_depth(limits.depth());
         let (parser, _) = Validator::Any.validate(&types, parser, None)?;
         parser.finish()?;
+        doc.set_validated();
 
-        Ok(doc)
+        Ok((doc, pending))
     }
 
     /// Decode a Document, skipping any checks of the data. This should only be run when the raw
     /// document has definitely been passed through validation before, i.e. if it is stored in a
     /// local database after going through [`encode_doc`][Self::encode_doc].
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(size = doc.len(), hash = tracing::field::Empty)
+        )
+    )]
     pub fn trusted_decode_doc(doc: Vec<u8>) -> Result<Document> {
+        let (version, doc) = strip_version(doc, &VersionPolicy::Supported)?;
+
         // Check for hash
         let split = SplitDoc::split(&doc)?;
         if !split.hash_raw.is_empty() {
@@ -164,26 +351,46 @@ impl NoSchema {
         }
 
         // Decompress
-        let doc = Document::new(decompress_doc(doc, &Compress::None)?)?;
+        let mut doc = Document::new(decompress_doc(doc, &Compress::None, None, MAX_DOC_SIZE)?)?;
+        doc.set_format_version(version);
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("hash", tracing::field::display(doc.hash()));
         Ok(doc)
     }
 }
 
-fn compress_doc(doc: Vec<u8>, compression: &Compress) -> Vec<u8> {
+// Compress a document. The compressed document is built in `scratch`, and the buffer holding the
+// uncompressed document takes its place, so repeated calls with the same scratch buffer don't
+// need to allocate.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(size = doc.len()))
+)]
+fn compress_doc(
+    doc: Vec<u8>,
+    compression: &Compress,
+    custom: Option<&dyn Compressor>,
+    threshold: u32,
+    scratch: &mut Vec<u8>,
+) -> Vec<u8> {
     // Skip if we aren't compressing
     if let Compress::None = compression {
         return doc;
     }
 
-    // Gather info from the raw document
+    // Gather info from the raw document, and skip compressing tiny documents
     let split = SplitDoc::split(&doc).unwrap();
+    if split.data.len() < threshold as usize {
+        return doc;
+    }
     let header_len = doc.len() - split.data.len() - split.signature_raw.len();
-    let max_len = zstd_safe::compress_bound(split.data.len());
-    let mut compress = Vec::with_capacity(doc.len() + max_len - split.data.len());
+    let mut compress = std::mem::take(scratch);
+    compress.clear();
+    compress.reserve(doc.len());
     compress.extend_from_slice(&doc[..header_len]);
 
     // Compress, update the header, append the signature
-    match compression.compress(compress, split.data) {
+    match compression.compress(compress, split.data, custom) {
         Ok(mut compress) => {
             let data_len = (compress.len() - header_len).to_le_bytes();
             compress[0] = CompressType::type_of(compression).into();
@@ -191,13 +398,23 @@ fn compress_doc(doc: Vec<u8>, compression: &Compress) -> Vec<u8> {
             compress[header_len - 2] = data_len[1];
             compress[header_len - 1] = data_len[2];
             compress.extend_from_slice(split.signature_raw);
+            *scratch = doc;
             compress
         }
         Err(()) => doc,
     }
 }
 
-fn decompress_doc(compress: Vec<u8>, compression: &Compress) -> Result<Vec<u8>> {
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(size = compress.len()))
+)]
+pub(crate) fn decompress_doc(
+    compress: Vec<u8>,
+    compression: &Compress,
+    custom: Option<&dyn Compressor>,
+    max_size: usize,
+) -> Result<Vec<u8>> {
     // Gather info from compressed vec
     let split = SplitDoc::split(&compress)?;
     let marker = CompressType::try_from(split.compress_raw)
@@ -215,7 +432,8 @@ fn decompress_doc(compress: Vec<u8>, compression: &Compress) -> Result<Vec<u8>>
         split.data,
         marker,
         split.signature_raw.len(),
-        MAX_DOC_SIZE,
+        max_size,
+        custom,
     )?;
     let data_len = (doc.len() - header_len).to_le_bytes();
     doc[0] = CompressType::None.into();
@@ -226,50 +444,79 @@ fn decompress_doc(compress: Vec<u8>, compression: &Compress) -> Result<Vec<u8>>
     Ok(doc)
 }
 
-fn compress_entry(entry: Vec<u8>, compression: &Compress) -> Vec<u8> {
+// Compress an entry, swapping buffers with `scratch` just like `compress_doc`.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(size = entry.len()))
+)]
+fn compress_entry(
+    entry: Vec<u8>,
+    compression: &Compress,
+    custom: Option<&dyn Compressor>,
+    threshold: u32,
+    scratch: &mut Vec<u8>,
+) -> Vec<u8> {
     // Skip if we aren't compressing
     if let Compress::None = compression {
         return entry;
     }
 
-    // Gather info from the raw entry
+    // Gather info from the raw entry, and skip compressing tiny entries
     let split = SplitEntry::split(&entry).unwrap();
-    let max_len = zstd_safe::compress_bound(split.data.len());
-    let mut compress = Vec::with_capacity(entry.len() + max_len - split.data.len());
+    if split.data.len() < threshold as usize {
+        return entry;
+    }
+    let mut compress = std::mem::take(scratch);
+    compress.clear();
+    compress.reserve(entry.len());
     compress.extend_from_slice(&entry[..ENTRY_PREFIX_LEN]);
 
     // Compress, update the header, append the signature
-    match compression.compress(compress, split.data) {
+    match compression.compress(compress, split.data, custom) {
         Ok(mut compress) => {
             let data_len = (compress.len() - ENTRY_PREFIX_LEN).to_le_bytes();
             compress[0] = CompressType::type_of(compression).into();
             compress[1] = data_len[0];
             compress[2] = data_len[1];
             compress.extend_from_slice(split.signature_raw);
+            *scratch = entry;
             compress
         }
         Err(()) => entry,
     }
 }
 
-fn decompress_entry(compress: Vec<u8>, compression: &Compress) -> Result<Vec<u8>> {
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(size = compress.len()))
+)]
+fn decompress_entry(
+    compress: Vec<u8>,
+    compression: &Compress,
+    custom: Option<&dyn Compressor>,
+    max_size: usize,
+    progress: &mut dyn FnMut(&[u8]) -> Result<()>,
+) -> Result<Vec<u8>> {
     // Gather info from compressed vec
     let split = SplitEntry::split(&compress)?;
     let marker = CompressType::try_from(split.compress_raw)
         .map_err(|m| Error::BadHeader(format!("unrecognized compression marker 0x{:x}", m)))?;
     if let CompressType::None = marker {
+        progress(split.data)?;
         return Ok(compress);
     }
 
     // Decompress, update the header, append the signature
     let mut entry = Vec::new();
     entry.extend_from_slice(&compress[..ENTRY_PREFIX_LEN]);
-    let mut entry = compression.decompress(
+    let mut entry = compression.decompress_chunked(
         entry,
         split.data,
         marker,
         split.signature_raw.len(),
-        MAX_ENTRY_SIZE,
+        max_size,
+        custom,
+        progress,
     )?;
     let data_len = (entry.len() - ENTRY_PREFIX_LEN).to_le_bytes();
     entry[0] = CompressType::None.into();
@@ -279,6 +526,363 @@ fn decompress_entry(compress: Vec<u8>, compression: &Compress) -> Result<Vec<u8>
     Ok(entry)
 }
 
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+fn verify_pending(pending: impl IntoIterator<Item = PendingSignature>) -> Result<()> {
+    for (hash, unverified) in pending {
+        unverified.verify(&hash)?;
+    }
+    Ok(())
+}
+
+// Verify a set of parsed signatures one at a time, returning the index of each one that failed
+// along with its error.
+fn verify_each(pending: Vec<(usize, PendingSignature)>) -> Vec<(usize, Error)> {
+    pending
+        .into_iter()
+        .filter_map(|(index, (hash, unverified))| {
+            unverified.verify(&hash).err().map(|e| (index, e.into()))
+        })
+        .collect()
+}
+
+/// An encoded document or entry to be decoded by [`decode_batch`].
+#[derive(Clone, Debug)]
+pub enum BatchItem<'a> {
+    /// An encoded document, along with its schema, or `None` if it doesn't use one.
+    Doc {
+        /// The schema the document uses
+        schema: Option<&'a Schema>,
+        /// The encoded document
+        doc: Vec<u8>,
+    },
+    /// An encoded entry, along with its schema, key, and parent document.
+    Entry {
+        /// The schema used by the entry's parent document
+        schema: &'a Schema,
+        /// The encoded entry
+        entry: Vec<u8>,
+        /// The entry's key
+        key: &'a str,
+        /// The entry's parent document
+        parent: &'a Document,
+    },
+}
+
+/// A document or entry that was successfully decoded by [`decode_batch`].
+#[derive(Clone, Debug)]
+pub enum BatchDecoded<'a> {
+    /// A decoded document
+    Doc(Document),
+    /// A decoded entry, which must still have its checklist completed, as with
+    /// [`Schema::decode_entry`].
+    Entry(DataChecklist<'a, Entry>),
+}
+
+/// Decode many documents and entries at once, checking their signatures only after everything
+/// else has been decoded and validated.
+///
+/// Each item is decoded exactly as [`Schema::decode_doc`], [`NoSchema::decode_doc`], or
+/// [`Schema::decode_entry`] would, and the results are returned in the same order as the items.
+/// A failure in one item doesn't affect any of the others. Items that fail to decode or validate
+/// never have their signatures checked, so a sync pipeline ingesting thousands of entries only
+/// spends time on the signatures of data it would otherwise accept.
+///
+/// The signatures are still verified one at a time, as fog-crypto has no batch verification, so
+/// this costs the same per signature as decoding each item on its own.
+pub fn decode_batch<'a>(
+    items: impl IntoIterator<Item = BatchItem<'a>>,
+) -> Vec<Result<BatchDecoded<'a>>> {
+    let mut decoded = Vec::new();
+    let mut pending = Vec::new();
+    for item in items {
+        let result = match item {
+            BatchItem::Doc { schema: None, doc } => {
+                NoSchema::decode_doc_unverified(doc, &Limits::default())
+                    .map(|(doc, sig)| (BatchDecoded::Doc(doc), sig))
+            }
+            BatchItem::Doc {
+                schema: Some(schema),
+                doc,
+            } => schema
+                .decode_doc_unverified(doc)
+                .map(|(doc, sig)| (BatchDecoded::Doc(doc), sig)),
+            BatchItem::Entry {
+                schema,
+                entry,
+                key,
+                parent,
+            } => schema
+                .decode_entry_unverified(entry, key, parent)
+                .map(|(entry, sig)| (BatchDecoded::Entry(entry), sig.into_iter().collect())),
+        };
+        match result {
+            Ok((item, sigs)) => {
+                for sig in sigs {
+                    pending.push((decoded.len(), sig));
+                }
+                decoded.push(Ok(item));
+            }
+            Err(e) => decoded.push(Err(e)),
+        }
+    }
+
+    for (index, err) in verify_each(pending) {
+        decoded[index] = Err(err);
+    }
+    decoded
+}
+
+/// Statistics from encoding a document or entry, for evaluating compression settings.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EncodeStats {
+    /// The size of the fully encoded data before compression, in bytes.
+    pub uncompressed_size: usize,
+    /// The size of the fully encoded data after compression, in bytes. If compression was skipped
+    /// or didn't reduce the size, this is the same as `uncompressed_size`.
+    pub compressed_size: usize,
+    /// Whether the data was compressed at all.
+    pub compressed: bool,
+    /// Whether the data was compressed using a dictionary.
+    pub dictionary: bool,
+    /// Time spent compressing the data. Always zero on `wasm32-unknown-unknown`, which has no
+    /// clock to measure with.
+    pub duration: Duration,
+}
+
+// wasm32-unknown-unknown panics on any attempt to read the clock
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+fn start_timer() -> Option<Instant> {
+    Some(Instant::now())
+}
+
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+fn start_timer() -> Option<Instant> {
+    None
+}
+
+impl EncodeStats {
+    fn new(uncompressed_size: usize, encoded: &[u8], start: Option<Instant>) -> Self {
+        let compress_type = CompressType::try_from(encoded[0]).unwrap_or(CompressType::None);
+        Self {
+            uncompressed_size,
+            compressed_size: encoded.len(),
+            compressed: compress_type != CompressType::None,
+            dictionary: compress_type == CompressType::Dict,
+            duration: start.map(|s| s.elapsed()).unwrap_or_default(),
+        }
+    }
+
+    /// Get the ratio of the compressed size to the uncompressed size. Lower is better.
+    pub fn ratio(&self) -> f64 {
+        if self.uncompressed_size == 0 {
+            1.0
+        } else {
+            self.compressed_size as f64 / self.uncompressed_size as f64
+        }
+    }
+}
+
+/// A job for a blocking thread pool, from one of the async encode functions like
+/// [`Schema::encode_doc_async_with`].
+pub type BlockingJob = Box<dyn FnOnce() + Send + 'static>;
+
+// Documents with less data than this are encoded directly by the async encode functions, as
+// handing them off costs more than encoding them.
+const OFFLOAD_THRESHOLD: usize = 64 * 1024;
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+fn spawn_thread(job: BlockingJob) {
+    std::thread::spawn(job);
+}
+
+// There are no threads on wasm32-unknown-unknown, so the job just runs immediately
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+fn spawn_thread(job: BlockingJob) {
+    job();
+}
+
+type OffloadWork<T> = Box<dyn FnOnce() -> T + Send>;
+
+struct OffloadState<T> {
+    work: Option<OffloadWork<T>>,
+    result: Option<T>,
+    finished: bool,
+    waker: Option<Waker>,
+}
+
+fn lock_state<T>(state: &Mutex<OffloadState<T>>) -> MutexGuard<OffloadState<T>> {
+    match state.lock() {
+        Ok(o) => o,
+        Err(e) => e.into_inner(),
+    }
+}
+
+// Marks the job as finished when dropped, whether or not it ran, so the waiting future always
+// wakes up.
+struct OffloadGuard<T>(Arc<Mutex<OffloadState<T>>>);
+
+impl<T> Drop for OffloadGuard<T> {
+    fn drop(&mut self) {
+        let waker = {
+            let mut state = lock_state(&self.0);
+            state.finished = true;
+            state.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+// Future for work handed off to another thread.
+struct Offload<T> {
+    state: Arc<Mutex<OffloadState<T>>>,
+}
+
+fn offload<T: Send + 'static>(work: OffloadWork<T>, spawn: impl FnOnce(BlockingJob)) -> Offload<T> {
+    let state = Arc::new(Mutex::new(OffloadState {
+        work: Some(work),
+        result: None,
+        finished: false,
+        waker: None,
+    }));
+    let guard = OffloadGuard(state.clone());
+    spawn(Box::new(move || {
+        let guard = guard;
+        let work = lock_state(&guard.0).work.take();
+        if let Some(work) = work {
+            let result = work();
+            lock_state(&guard.0).result = Some(result);
+        }
+    }));
+    Offload { state }
+}
+
+impl<T> Future for Offload<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut state = lock_state(&self.state);
+        if let Some(result) = state.result.take() {
+            return Poll::Ready(result);
+        }
+        if state.finished {
+            // The job was dropped without running, so do the work here instead
+            let work = state.work.take().expect("offloaded work panicked");
+            drop(state);
+            return Poll::Ready(work());
+        }
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+/// Reusable buffers for creating and encoding many documents & entries in a row.
+///
+/// Serializing data whose map keys must be reordered, and compressing the encoded result, both
+/// need temporary buffers. An `Encoder` holds on to these between calls, so a tight loop that
+/// creates and encodes documents or entries through the same `Encoder` stops allocating them
+/// once the buffers have grown large enough. The results are identical to those of
+/// [`NewDocument::new_with_options`], [`NewEntry::new_with_options`], [`Schema::encode_doc`],
+/// [`NoSchema::encode_doc`], and [`Schema::encode_entry`].
+///
+/// ```
+/// # use fog_pack::{document::*, schema::*};
+/// # fn main() -> fog_pack::error::Result<()> {
+/// let mut encoder = Encoder::new();
+/// let mut encoded = Vec::new();
+/// for i in 0..100u32 {
+///     let doc = encoder.new_doc(None, (i, "some data"))?;
+///     let doc = NoSchema::validate_new_doc(doc)?;
+///     encoded.push(encoder.encode_doc(None, doc)?);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Encoder {
+    options: EncodeOptions,
+    spare: ser::Spare,
+    scratch: Vec<u8>,
+}
+
+impl Encoder {
+    /// Create a new encoder with the default encoding options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new encoder that serializes data with the given encoding options.
+    pub fn with_options(options: EncodeOptions) -> Self {
+        Self {
+            options,
+            ..Self::default()
+        }
+    }
+
+    /// Get the encoding options used when serializing data.
+    pub fn options(&self) -> &EncodeOptions {
+        &self.options
+    }
+
+    // Serialize data into a buffer, using and then restoring the spare temporary storage.
+    fn serialize<S: Serialize>(&mut self, buf: Vec<u8>, data: S) -> Result<Vec<u8>> {
+        let mut ser = self
+            .options
+            .serializer(buf)
+            .with_spare(std::mem::take(&mut self.spare));
+        let result = data.serialize(&mut ser);
+        let (buf, spare) = ser.finish_with_spare();
+        self.spare = spare;
+        result.map(|_| buf)
+    }
+
+    /// Create a new Document from any serializable data, optionally adhering to a schema.
+    pub fn new_doc<S: Serialize>(&mut self, schema: Option<&Hash>, data: S) -> Result<NewDocument> {
+        NewDocument::new_from(schema, |buf| self.serialize(buf, data))
+    }
+
+    /// Create a new Entry from any serializable data, a key, and the parent document.
+    pub fn new_entry<S: Serialize>(
+        &mut self,
+        key: &str,
+        parent: &Document,
+        data: S,
+    ) -> Result<NewEntry> {
+        NewEntry::new_from(key, parent, |buf| self.serialize(buf, data))
+    }
+
+    /// Encode a [`Document`], returning the resulting Document's hash and fully encoded format.
+    /// Documents that don't use a schema are encoded by passing in `None` for the schema. Fails
+    /// if the document doesn't use the given schema.
+    pub fn encode_doc(
+        &mut self,
+        schema: Option<&Schema>,
+        doc: Document,
+    ) -> Result<(Hash, Vec<u8>)> {
+        match schema {
+            Some(schema) => {
+                let (hash, doc, _) = schema.encode_doc_scratch(doc, &mut self.scratch)?;
+                Ok((hash, doc))
+            }
+            None => NoSchema::encode_doc_scratch(doc, &mut self.scratch),
+        }
+    }
+
+    /// Encode an [`Entry`], returning the resulting Entry's reference, its fully encoded format,
+    /// and a list of Hashes of the Documents it needs for validation. Fails if the entry's parent
+    /// document doesn't use the given schema.
+    pub fn encode_entry(
+        &mut self,
+        schema: &Schema,
+        entry: Entry,
+    ) -> Result<(EntryRef, Vec<u8>, Vec<Hash>)> {
+        let (entry_ref, entry, needed_docs, _) =
+            schema.encode_entry_scratch(entry, &mut self.scratch)?;
+        Ok((entry_ref, entry, needed_docs))
+    }
+}
+
 /// Builds schemas up from Validators.
 ///
 /// A schema can be directly made from any document, but it's generally much easier to construct
@@ -287,6 +891,7 @@ fn decompress_entry(compress: Vec<u8>, compression: &Compress) -> Result<Vec<u8>
 #[derive(Clone, Debug)]
 pub struct SchemaBuilder {
     inner: InnerSchema,
+    parent: Option<Schema>,
 }
 
 impl SchemaBuilder {
@@ -303,7 +908,25 @@ impl SchemaBuilder {
                 types: BTreeMap::new(),
                 version: Integer::default(),
                 max_regex: 0,
+                raw_floats: false,
+                encrypted: Vec::new(),
+                weak: Vec::new(),
+                immutable: Vec::new(),
+                external: BTreeMap::new(),
+                reducers: BTreeMap::new(),
+                volatile: BTreeMap::new(),
+                entry_keys: None,
+                tombstones: Vec::new(),
+                threshold: 0,
+                signers: Vec::new(),
+                unsigned: false,
+                compress_threshold: DEFAULT_COMPRESS_THRESHOLD,
+                maintainer: None,
+                parent: None,
+                program: Program::default(),
+                doc_root: 0,
             },
+            parent: None,
         }
     }
 
@@ -319,6 +942,15 @@ impl SchemaBuilder {
         self
     }
 
+    /// Set the compression threshold, in bytes. Documents and entries with less data than this
+    /// skip compression entirely, unless their compression setting was explicitly overridden. The
+    /// default is [`DEFAULT_COMPRESS_THRESHOLD`]. Schemas with compression dictionaries may want a
+    /// lower threshold, as dictionaries can compress even very small data.
+    pub fn compress_threshold(mut self, bytes: u32) -> Self {
+        self.inner.compress_threshold = bytes;
+        self
+    }
+
     /// Add a new entry type to the schema, where `entry` is the key for the entry, `validator`
     /// will be used to validate each entry, and `compress` optionally overrides the default
     /// compression with a specific compression setting.
@@ -334,6 +966,7 @@ impl SchemaBuilder {
             EntrySchema {
                 entry: validator,
                 compress,
+                root: 0,
             },
         );
         self
@@ -345,6 +978,28 @@ impl SchemaBuilder {
         self
     }
 
+    /// Set the Identity of the schema's maintainer. This is only used for documentation purposes,
+    /// and doesn't mean the schema document was signed by that Identity.
+    pub fn maintainer(mut self, maintainer: Identity) -> Self {
+        self.inner.maintainer = Some(maintainer);
+        self
+    }
+
+    /// Extend a parent schema, which documents must then name when they are decoded with
+    /// [`Schema::from_doc_with_loader`]. The parent's entries, stored types, and field settings
+    /// are inherited, along with its document validator if this schema's is [`Validator::Any`].
+    ///
+    /// A validator given for something the parent already has can only tighten it, except for
+    /// maps: a map validator adds fields to the parent's map, tightening any fields the two have
+    /// in common, and its own map-level settings like `max_len` replace the parent's. Every
+    /// required field of the parent must stay required. Settings that aren't validators, like
+    /// the name, compression, and signature policy, are never inherited.
+    pub fn extends(mut self, parent: &Schema) -> Self {
+        self.inner.parent = Some(parent.hash().clone());
+        self.parent = Some(parent.clone());
+        self
+    }
+
     /// Add a new stored type to the schema.
     pub fn type_add(mut self, type_ref: &str, validator: Validator) -> Self {
         self.inner.types.insert(type_ref.to_owned(), validator);
@@ -368,122 +1023,1394 @@ impl SchemaBuilder {
         self
     }
 
-    /// Build the Schema, compiling the result into a Document
-    pub fn build(self) -> Result<Document> {
-        let doc = NewDocument::new(None, self.inner)?;
-        NoSchema::validate_new_doc(doc)
-    }
-}
-
-/// A Schema, which can be used to encode/decode a document or entry, while verifying its
-/// contents.
-///
-/// Schema are decoded from a correctly formatted [`Document`] that describes the format of other
-/// documents and their associated entries. They also include recommended compression settings for
-/// documents & entries adhering to them, which may include compression dictionaries.
-///
-/// A schema must come from a Document. To create one directly, use the [`SchemaBuilder`], then
-/// decode the resulting Document into a schema.
-#[derive(Clone, Debug)]
-pub struct Schema {
-    hash: Hash,
-    inner: InnerSchema,
-}
-
-impl Schema {
-    /// Attempt to create a schema from a given document. Fails if the document isn't a schema.
-    ///
-    /// Warnings
-    /// --------
+    /// Allow floating-point values that aren't in canonical form, for data that must preserve
+    /// NaN payloads or negative zero. By default, documents and entries are rejected if they
+    /// contain any NaN other than the canonical quiet NaN, or if they contain negative zero.
     ///
-    /// If working with external, untrusted schemas, it's advisable to use
-    /// [`Schema::from_doc_max_regex`] instead, as regular expressions are hands-down the easiest
-    /// way to exhaust memory in a system.
-    pub fn from_doc(doc: &Document) -> Result<Self> {
-        let inner = doc.deserialize()?;
-        let hash = doc.hash().clone();
-        Ok(Self { hash, inner })
+    /// Data with raw floats must be encoded with
+    /// [`EncodeOptions::raw_floats`][crate::document::EncodeOptions::raw_floats], or the values
+    /// will be converted to canonical form before they ever reach the schema.
+    pub fn raw_floats(mut self, raw_floats: bool) -> Self {
+        self.inner.raw_floats = raw_floats;
+        self
     }
 
-    /// Attempt to create a schema from a given document, first checking how many regular
-    /// expressions would be present in the schema and failing out if it's above the provided
-    /// limit.
-    ///
-    /// For a rough guide of what to set `max_regex` to, know that every regex has an
-    /// approximate max memory size of 12 MiB, so a malicious schema can use up at least
-    /// `max_regex * 12 MiB` bytes off the heap.
-    pub fn from_doc_max_regex(doc: &Document, max_regex: u8) -> Result<Self> {
-        // Count up all the regular expressions that can be in a schema
-        let regex_check: ValueRef = doc.deserialize()?;
-        let mut regexes = crate::count_regexes(&regex_check["doc"]);
-        if let Some(map) = regex_check["types"].as_map() {
-            regexes += map
-                .values()
-                .fold(0, |acc, val| acc + crate::count_regexes(val));
+    /// Mark a field of the document as encrypted. Encrypted fields hold a
+    /// [`DataLockbox`][crate::types::DataLockbox] containing the field's actual value, which is
+    /// handled by [`encrypt_fields`][crate::encrypted::encrypt_fields] and
+    /// [`decrypt_fields`][crate::encrypted::decrypt_fields]. The document validator should
+    /// expect a `DataLockbox` at this path.
+    pub fn encrypted_field(mut self, path: &Path) -> Self {
+        let path = path.to_string();
+        if !self.inner.encrypted.contains(&path) {
+            self.inner.encrypted.push(path);
         }
-        if let Some(map) = regex_check["entries"].as_map() {
-            regexes += map
-                .values()
-                .fold(0, |acc, val| acc + crate::count_regexes(&val["entry"]));
+        self
+    }
+
+    /// Mark a field of the document as holding weak references. Hashes anywhere within the field
+    /// don't keep the documents they refer to reachable; see
+    /// [`reachable`][crate::graph::reachable].
+    pub fn weak_field(mut self, path: &Path) -> Self {
+        let path = path.to_string();
+        if !self.inner.weak.contains(&path) {
+            self.inner.weak.push(path);
         }
+        self
+    }
 
-        if regexes > (max_regex as usize) {
-            return Err(Error::FailValidate(format!(
-                "Found {} regexes in Schema, only {} allowed",
-                regexes, max_regex
-            )));
+    /// Mark a field of the document as immutable. Once a revision of a document has a value in
+    /// this field, later revisions must keep the same value; see [`Schema::check_revision`].
+    /// Fields that aren't present yet can still be filled in, so they are write-once.
+    pub fn immutable_field(mut self, path: &Path) -> Self {
+        let path = path.to_string();
+        if !self.inner.immutable.contains(&path) {
+            self.inner.immutable.push(path);
         }
+        self
+    }
 
-        let inner = doc.deserialize()?;
-        let hash = doc.hash().clone();
-        Ok(Self { hash, inner })
+    /// Mark a string or binary field of the document as externalizable. Values longer than
+    /// `threshold` bytes are moved out into a chain of chunk documents, leaving the hash of the
+    /// first chunk in their place; this is handled by
+    /// [`externalize`][crate::external::externalize] and [`inflate`][crate::external::inflate].
+    /// The document validator should accept both forms at this path, which
+    /// [`external::field_validator`][crate::external::field_validator] builds.
+    pub fn external_field(mut self, path: &Path, threshold: u32) -> Self {
+        self.inner.external.insert(path.to_string(), threshold);
+        self
     }
 
-    /// Get the hash of this schema.
-    pub fn hash(&self) -> &Hash {
-        &self.hash
+    /// Declare how entries with the given key are folded together when they are
+    /// [compacted][crate::compact::compact] into a snapshot. The entry key must also be added
+    /// with [`entry_add`][Self::entry_add].
+    pub fn entry_reducer(mut self, entry: &str, reducer: Reducer) -> Self {
+        self.inner.reducers.insert(entry.to_owned(), reducer);
+        self
     }
 
-    /// Validate a [`NewDocument`], turning it into a [`Document`]. Fails if the document doesn't
-    /// use this schema, or if it doesn't meet this schema's requirements.
-    pub fn validate_new_doc(&self, doc: NewDocument) -> Result<Document> {
-        // Check that the document uses this schema
-        match doc.schema_hash() {
-            Some(hash) if hash == &self.hash => (),
-            actual => {
-                return Err(Error::SchemaMismatch {
-                    actual: actual.cloned(),
-                    expected: Some(self.hash.clone()),
-                })
-            }
+    /// Mark a field of entries with the given key as volatile, like a timestamp or a nonce that
+    /// differs between submissions of the same content. Volatile fields are left out of
+    /// [`Schema::semantic_hash`]. The entry key must also be added with
+    /// [`entry_add`][Self::entry_add].
+    pub fn volatile_field(mut self, entry: &str, path: &Path) -> Self {
+        let path = path.to_string();
+        let paths = self.inner.volatile.entry(entry.to_owned()).or_default();
+        if !paths.contains(&path) {
+            paths.push(path);
         }
+        self
+    }
 
-        // Validate the data
-        let parser = Parser::new(doc.data());
-        let (parser, _) = self.inner.doc.validate(&self.inner.types, parser, None)?;
-        parser.finish()?;
-
-        Ok(Document::from_new(doc))
+    /// Set a rule that every entry key in the schema must pass, so applications can partition
+    /// entry keys into namespaces. Keys starting with [`RESERVED_KEY_PREFIX`] are always
+    /// rejected, whether or not a rule is set.
+    pub fn entry_keys(mut self, rule: StrValidator) -> Self {
+        self.inner.entry_keys = Some(Box::new(rule));
+        self
     }
 
-    /// Encode a [`Document`], returning the resulting Document's hash and fully encoded format.
-    /// Fails if the document doesn't use this schema.
-    pub fn encode_doc(&self, doc: Document) -> Result<(Hash, Vec<u8>)> {
-        // Check that the document uses this schema
-        match doc.schema_hash() {
-            Some(hash) if hash == &self.hash => (),
-            actual => {
-                return Err(Error::SchemaMismatch {
-                    actual: actual.cloned(),
-                    expected: Some(self.hash.clone()),
-                })
-            }
+    /// Declare that entries with the given key are tombstones, each retracting an earlier entry.
+    /// The entry key must also be added with [`entry_add`][Self::entry_add], using a
+    /// [`HashValidator`][crate::validator::HashValidator], as a tombstone's data is the hash of the
+    /// entry it retracts. Only signed entries can be retracted, and only by a tombstone with the
+    /// same signer. See [`Schema::apply_tombstones`].
+    pub fn tombstone_entry(mut self, entry: &str) -> Self {
+        let entry = entry.to_owned();
+        if !self.inner.tombstones.contains(&entry) {
+            self.inner.tombstones.push(entry);
         }
+        self
+    }
+
+    /// Require documents to be signed by at least `threshold` parties. This is checked when
+    /// documents are encoded and decoded. Documents with more than one signer are assembled with a
+    /// [`PartialSignedDocument`][crate::document::PartialSignedDocument].
+    pub fn signature_threshold(mut self, threshold: u8) -> Self {
+        self.inner.threshold = threshold;
+        self
+    }
+
+    /// Add an Identity to the list of signers counted towards the signature threshold. If the
+    /// list is empty, every signer is counted.
+    pub fn signer_add(mut self, signer: Identity) -> Self {
+        if !self.inner.signers.contains(&signer) {
+            self.inner.signers.push(signer);
+        }
+        self
+    }
+
+    /// Check the schema's validators for problems, without building it. Returns every problem
+    /// found, with errors first. Errors are validators that can never pass, like an
+    /// [`IntValidator`][crate::validator::IntValidator] whose minimum is above its maximum, and
+    /// will make [`build`][Self::build] fail. Warnings are things that are probably mistakes,
+    /// like stored types that are never referenced, references to types that don't exist, or
+    /// an empty [`MultiValidator`][crate::validator::MultiValidator] that always fails.
+    ///
+    /// If the schema [extends][Self::extends] another, the combined schema is checked instead,
+    /// unless the two can't be combined.
+    pub fn lint(&self) -> Vec<Lint> {
+        match self.resolve() {
+            Ok(Some(inner)) => lint_schema(&inner),
+            _ => lint_schema(&self.inner),
+        }
+    }
+
+    // Combine the schema with its parent, if it has one
+    fn resolve(&self) -> Result<Option<InnerSchema>> {
+        self.parent
+            .as_ref()
+            .map(|parent| extend_schema(&parent.inner, self.inner.clone()))
+            .transpose()
+    }
+
+    /// Require documents to be unsigned, for data that is deliberately anonymous. Signed
+    /// documents are rejected when they are validated, encoded, or decoded. This can't be
+    /// combined with a [signature threshold][Self::signature_threshold].
+    pub fn unsigned(mut self, unsigned: bool) -> Self {
+        self.inner.unsigned = unsigned;
+        self
+    }
+
+    /// Build the Schema, compiling the result into a Document. Fails if any entry key is
+    /// reserved or doesn't pass the [entry key rule][Self::entry_keys], or if any validator can
+    /// never pass; the error names the first such validator by its location in the schema. See
+    /// [`lint`][Self::lint] for the full list of problems. A schema that
+    /// [extends][Self::extends] another also fails if it loosens any of the parent's validators.
+    pub fn build(self) -> Result<Document> {
+        let resolved = self.resolve()?;
+        let checked = resolved.as_ref().unwrap_or(&self.inner);
+        for key in checked.entries.keys() {
+            check_entry_key(checked, key)?;
+        }
+        check_signature_policy(checked)?;
+        if let Some(lint) = lint_schema(checked)
+            .into_iter()
+            .find(|lint| lint.severity == Severity::Error)
+        {
+            return Err(Error::FailValidate(ValidateError::Other(
+                lint.to_string().into(),
+            )));
+        }
+        let doc = NewDocument::new(None, self.inner)?;
+        NoSchema::validate_new_doc(doc)
+    }
+}
+
+fn check_signature_policy(inner: &InnerSchema) -> Result<()> {
+    if inner.unsigned && inner.threshold > 0 {
+        return Err(Error::FailValidate(ValidateError::Other(
+            "schema can't require documents to be both signed and unsigned".into(),
+        )));
+    }
+    Ok(())
+}
+
+fn check_entry_key(inner: &InnerSchema, key: &str) -> Result<()> {
+    if key.starts_with(RESERVED_KEY_PREFIX) {
+        return Err(Error::FailValidate(ValidateError::Other(
+            format!("entry key {:?} uses a reserved prefix", key).into(),
+        )));
+    }
+    if let Some(rule) = &inner.entry_keys {
+        rule.validate_str(key).map_err(|e| e.at_key(key))?;
+    }
+    Ok(())
+}
+
+fn check_no_parent(inner: &InnerSchema) -> Result<()> {
+    match &inner.parent {
+        Some(parent) => Err(Error::FailValidate(ValidateError::Other(
+            format!(
+                "schema extends parent schema {}, so it must be loaded with from_doc_with_loader",
+                parent
+            )
+            .into(),
+        ))),
+        None => Ok(()),
+    }
+}
+
+fn lint_schema(inner: &InnerSchema) -> Vec<Lint> {
+    let entries = inner
+        .entries
+        .iter()
+        .map(|(key, entry)| (key.as_str(), &entry.entry));
+    lint::lint(&inner.doc, entries, &inner.types)
+}
+
+// How many levels of parent schemas a schema can extend
+const MAX_SCHEMA_PARENTS: usize = 16;
+
+// Combine a parent schema with a schema extending it. Validators in both are combined with
+// `extend_validator`, anything else the parent has is inherited, and settings that aren't
+// validators come from the extending schema alone.
+fn extend_schema(parent: &InnerSchema, mut child: InnerSchema) -> Result<InnerSchema> {
+    // References in either schema are looked up in the extending schema's types first
+    let mut types = parent.types.clone();
+    types.extend(child.types.iter().map(|(k, v)| (k.clone(), v.clone())));
+
+    let mut merged = BTreeMap::new();
+    for (name, validator) in std::mem::take(&mut child.types) {
+        let validator = match parent.types.get(&name) {
+            Some(base) => extend_validator(&types, base, validator, &format!("types/{}", name), 0)?,
+            None => validator,
+        };
+        merged.insert(name, validator);
+    }
+    for (name, validator) in parent.types.iter() {
+        merged
+            .entry(name.clone())
+            .or_insert_with(|| validator.clone());
+    }
+    child.types = merged;
+
+    let doc = std::mem::replace(&mut child.doc, Validator::Any);
+    child.doc = extend_validator(&types, &parent.doc, doc, "doc", 0)?;
+    for (key, base) in parent.entries.iter() {
+        match child.entries.get_mut(key) {
+            Some(entry) => {
+                let validator = std::mem::replace(&mut entry.entry, Validator::Any);
+                let location = format!("entries/{}", key);
+                entry.entry = extend_validator(&types, &base.entry, validator, &location, 0)?;
+            }
+            None => {
+                child.entries.insert(key.clone(), base.clone());
+            }
+        }
+    }
+
+    for (list, base) in [
+        (&mut child.encrypted, &parent.encrypted),
+        (&mut child.weak, &parent.weak),
+        (&mut child.immutable, &parent.immutable),
+        (&mut child.tombstones, &parent.tombstones),
+    ] {
+        for item in base.iter() {
+            if !list.contains(item) {
+                list.push(item.clone());
+            }
+        }
+    }
+    for (path, threshold) in parent.external.iter() {
+        child.external.entry(path.clone()).or_insert(*threshold);
+    }
+    for (key, reducer) in parent.reducers.iter() {
+        child.reducers.entry(key.clone()).or_insert(*reducer);
+    }
+    for (key, base) in parent.volatile.iter() {
+        let paths = child.volatile.entry(key.clone()).or_default();
+        for path in base.iter() {
+            if !paths.contains(path) {
+                paths.push(path.clone());
+            }
+        }
+    }
+    if child.entry_keys.is_none() {
+        child.entry_keys = parent.entry_keys.clone();
+    }
+    Ok(child)
+}
+
+// Combine a parent schema's validator with the one an extending schema gives for the same spot.
+// `Any` keeps the parent's validator, references in the parent are resolved first, maps are
+// merged field by field, and any other validator must only accept values the parent's does.
+fn extend_validator(
+    types: &BTreeMap<String, Validator>,
+    base: &Validator,
+    ext: Validator,
+    location: &str,
+    depth: usize,
+) -> Result<Validator> {
+    if depth > MAX_DEPTH {
+        return Err(Error::FailValidate(ValidateError::Other(
+            format!("{} nests too deeply to extend", location).into(),
+        )));
+    }
+    match (base, ext) {
+        (_, Validator::Any) => Ok(base.clone()),
+        (Validator::Ref(name), ext) => {
+            let base = types.get(name).ok_or_else(|| {
+                Error::FailValidate(ValidateError::Other(
+                    format!("{} refers to missing type {:?}", location, name).into(),
+                ))
+            })?;
+            extend_validator(types, base, ext, location, depth + 1)
+        }
+        (Validator::Map(base), Validator::Map(ext)) => Ok(Validator::Map(Box::new(extend_map(
+            types, base, *ext, location, depth,
+        )?))),
+        (base, ext) => {
+            if base.subsumes(types, &ext, 0) {
+                Ok(ext)
+            } else {
+                Err(loosened(location))
+            }
+        }
+    }
+}
+
+fn loosened(location: &str) -> Error {
+    Error::FailValidate(ValidateError::Other(
+        format!("{} loosens the parent schema's validator", location).into(),
+    ))
+}
+
+// Merge a parent schema's map validator with an extending one. Fields are merged with
+// `extend_validator`, and new fields may be added. Map-wide settings left at their defaults are
+// inherited, and set ones must be at least as strict as the parent's. Query permissions and the
+// comment come from the extending validator alone.
+fn extend_map(
+    types: &BTreeMap<String, Validator>,
+    base: &MapValidator,
+    mut ext: MapValidator,
+    location: &str,
+    depth: usize,
+) -> Result<MapValidator> {
+    for (key, validator) in base.req.iter() {
+        let location = format!("{}/req/{}", location, key);
+        if ext.opt.contains_key(key) {
+            return Err(Error::FailValidate(ValidateError::Other(
+                format!("{} is required by the parent schema", location).into(),
+            )));
+        }
+        let merged = match ext.req.remove(key) {
+            Some(field) => extend_validator(types, validator, field, &location, depth + 1)?,
+            None => validator.clone(),
+        };
+        ext.req.insert(key.clone(), merged);
+    }
+    for (key, validator) in base.opt.iter() {
+        let location = format!("{}/opt/{}", location, key);
+        if let Some(field) = ext.req.remove(key) {
+            let merged = extend_validator(types, validator, field, &location, depth + 1)?;
+            ext.req.insert(key.clone(), merged);
+        } else {
+            let merged = match ext.opt.remove(key) {
+                Some(field) => extend_validator(types, validator, field, &location, depth + 1)?,
+                None => validator.clone(),
+            };
+            ext.opt.insert(key.clone(), merged);
+        }
+    }
+
+    // Lengths
+    if ext.max_len == u32::MAX {
+        ext.max_len = base.max_len;
+    } else if ext.max_len > base.max_len {
+        return Err(loosened(&format!("{}/max_len", location)));
+    }
+    if ext.min_len == u32::MIN {
+        ext.min_len = base.min_len;
+    } else if ext.min_len < base.min_len {
+        return Err(loosened(&format!("{}/min_len", location)));
+    }
+    for (prefix, max) in base.max_prefix.iter() {
+        match ext.max_prefix.get(prefix) {
+            Some(ext_max) if ext_max > max => {
+                return Err(loosened(&format!("{}/max_prefix/{}", location, prefix)));
+            }
+            Some(_) => (),
+            None => {
+                ext.max_prefix.insert(prefix.clone(), *max);
+            }
+        }
+    }
+
+    // Unknown keys
+    match (&base.keys, ext.keys.take()) {
+        (base_keys, None) => ext.keys = base_keys.clone(),
+        (None, Some(keys)) => ext.keys = Some(keys),
+        (Some(base_keys), Some(keys)) => {
+            let base_keys = Validator::Str(base_keys.clone());
+            if !base_keys.subsumes(types, &Validator::Str(keys.clone()), 0) {
+                return Err(loosened(&format!("{}/keys", location)));
+            }
+            ext.keys = Some(keys);
+        }
+    }
+    ext.values = match (&base.values, ext.values.take()) {
+        (base_values, None) => base_values.clone(),
+        (None, Some(_)) => return Err(loosened(&format!("{}/values", location))),
+        (Some(base_values), Some(values)) => {
+            let location = format!("{}/values", location);
+            Some(Box::new(extend_validator(
+                types,
+                base_values,
+                *values,
+                &location,
+                depth + 1,
+            )?))
+        }
+    };
+
+    // Specific values and key sets
+    if ext.in_list.is_empty() {
+        ext.in_list = base.in_list.clone();
+    } else if !base.in_list.is_empty() && !ext.in_list.iter().all(|v| base.in_list.contains(v)) {
+        return Err(loosened(&format!("{}/in", location)));
+    }
+    for value in base.nin_list.iter() {
+        if !ext.nin_list.contains(value) {
+            ext.nin_list.push(value.clone());
+        }
+    }
+    ext.same_len.extend(base.same_len.iter().cloned());
+    for set in base.req_any.iter() {
+        if !ext.req_any.contains(set) {
+            ext.req_any.push(set.clone());
+        }
+    }
+    Ok(ext)
+}
+
+// Find the first field at or under a validator that allows ordinal queries, looking through
+// required map fields in key order
+fn find_ord_field(
+    types: &BTreeMap<String, Validator>,
+    validator: &Validator,
+    path: Path,
+    depth: usize,
+) -> Option<Path> {
+    if depth > MAX_DEPTH {
+        return None;
+    }
+    let ord = match validator {
+        Validator::Int(v) => v.ord,
+        Validator::F32(v) => v.ord,
+        Validator::F64(v) => v.ord,
+        Validator::Num(v) => v.ord,
+        Validator::Time(v) => v.ord,
+        Validator::Bin(v) => v.ord,
+        Validator::Ref(name) => {
+            return find_ord_field(types, types.get(name)?, path, depth + 1);
+        }
+        Validator::Map(map) => {
+            return map.req.iter().find_map(|(field, v)| {
+                find_ord_field(types, v, path.clone().key(field.as_str()), depth + 1)
+            });
+        }
+        _ => false,
+    };
+    ord.then_some(path)
+}
+
+/// The top-level document field that links a revised document to the one it replaces, by holding
+/// the earlier document's hash. See [`Schema::check_revision`].
+pub const REVISION_FIELD: &str = "prev";
+
+/// A Schema, which can be used to encode/decode a document or entry, while verifying its
+/// contents.
+///
+/// Schema are decoded from a correctly formatted [`Document`] that describes the format of other
+/// documents and their associated entries. They also include recommended compression settings for
+/// documents & entries adhering to them, which may include compression dictionaries.
+///
+/// A schema must come from a Document. To create one directly, use the [`SchemaBuilder`], then
+/// decode the resulting Document into a schema.
+#[derive(Clone, Debug)]
+pub struct Schema {
+    hash: Hash,
+    inner: Arc<InnerSchema>,
+    limits: Limits,
+    policy: VerifyPolicy,
+    unknown: UnknownFields,
+    compressor: Option<SharedCompressor>,
+}
+
+impl Schema {
+    /// Attempt to create a schema from a given document. Fails if the document isn't a schema.
+    ///
+    /// Warnings
+    /// --------
+    ///
+    /// If working with external, untrusted schemas, it's advisable to use
+    /// [`Schema::from_doc_max_regex`] instead, as regular expressions are hands-down the easiest
+    /// way to exhaust memory in a system.
+    ///
+    /// Fails if the schema [extends][SchemaBuilder::extends] a parent schema; use
+    /// [`Schema::from_doc_with_loader`] for those.
+    pub fn from_doc(doc: &Document) -> Result<Self> {
+        let inner = doc.deserialize()?;
+        check_no_parent(&inner)?;
+        Self::from_inner(doc.hash().clone(), inner)
+    }
+
+    /// Attempt to create a schema from a given document that may [extend][SchemaBuilder::extends]
+    /// a parent schema. The parent schema's document is fetched with `load`, as is any schema it
+    /// extends in turn, up to 16 levels deep. Fails if a parent is missing, if `load` returns a
+    /// document with a different hash, or if the schema loosens any of its parent's validators.
+    ///
+    /// The resulting schema holds the combined validators, and documents using it name only its
+    /// hash, not the hashes of its parents.
+    pub fn from_doc_with_loader<F>(doc: &Document, mut load: F) -> Result<Self>
+    where
+        F: FnMut(&Hash) -> Result<Option<Document>>,
+    {
+        Self::from_doc_loading(doc, &mut load, 0)
+    }
+
+    fn from_doc_loading<F>(doc: &Document, load: &mut F, depth: usize) -> Result<Self>
+    where
+        F: FnMut(&Hash) -> Result<Option<Document>>,
+    {
+        let inner: InnerSchema = doc.deserialize()?;
+        let Some(hash) = inner.parent.clone() else {
+            return Self::from_inner(doc.hash().clone(), inner);
+        };
+        if depth >= MAX_SCHEMA_PARENTS {
+            return Err(Error::FailValidate(ValidateError::Other(
+                format!(
+                    "schema extends more than {} levels of parent schemas",
+                    MAX_SCHEMA_PARENTS
+                )
+                .into(),
+            )));
+        }
+        let parent =
+            load(&hash)?.ok_or_else(|| Error::Store(format!("Missing parent schema {}", hash)))?;
+        if parent.hash() != &hash {
+            return Err(Error::Store(format!(
+                "Loaded parent schema {} has hash {}",
+                hash,
+                parent.hash()
+            )));
+        }
+        let parent = Self::from_doc_loading(&parent, load, depth + 1)?;
+        let inner = extend_schema(&parent.inner, inner)?;
+        Self::from_inner(doc.hash().clone(), inner)
+    }
+
+    fn from_inner(hash: Hash, inner: InnerSchema) -> Result<Self> {
+        if let Some(path) = inner.encrypted.iter().find(|p| Path::parse(p).is_none()) {
+            return Err(Error::FailValidate(ValidateError::Other(
+                format!("encrypted field path {:?} isn't a valid path", path).into(),
+            )));
+        }
+        if let Some(path) = inner.external.keys().find(|p| Path::parse(p).is_none()) {
+            return Err(Error::FailValidate(ValidateError::Other(
+                format!("external field path {:?} isn't a valid path", path).into(),
+            )));
+        }
+        if let Some(path) = inner.weak.iter().find(|p| Path::parse(p).is_none()) {
+            return Err(Error::FailValidate(ValidateError::Other(
+                format!("weak field path {:?} isn't a valid path", path).into(),
+            )));
+        }
+        if let Some(path) = inner.immutable.iter().find(|p| Path::parse(p).is_none()) {
+            return Err(Error::FailValidate(ValidateError::Other(
+                format!("immutable field path {:?} isn't a valid path", path).into(),
+            )));
+        }
+        for key in inner.entries.keys() {
+            check_entry_key(&inner, key)?;
+        }
+        check_signature_policy(&inner)?;
+        if let Some(key) = inner
+            .reducers
+            .keys()
+            .find(|k| !inner.entries.contains_key(*k))
+        {
+            return Err(Error::FailValidate(ValidateError::Other(
+                format!("reducer given for unknown entry key {:?}", key).into(),
+            )));
+        }
+        for (key, paths) in inner.volatile.iter() {
+            if !inner.entries.contains_key(key) {
+                return Err(Error::FailValidate(ValidateError::Other(
+                    format!("volatile fields given for unknown entry key {:?}", key).into(),
+                )));
+            }
+            if let Some(path) = paths.iter().find(|p| Path::parse(p).is_none()) {
+                return Err(Error::FailValidate(ValidateError::Other(
+                    format!("volatile field path {:?} isn't a valid path", path).into(),
+                )));
+            }
+        }
+        for key in inner.tombstones.iter() {
+            match inner.entries.get(key) {
+                Some(EntrySchema {
+                    entry: Validator::Hash(_),
+                    ..
+                }) => (),
+                Some(_) => {
+                    return Err(Error::FailValidate(ValidateError::Other(
+                        format!("tombstone entry key {:?} must use a hash validator", key).into(),
+                    )))
+                }
+                None => {
+                    return Err(Error::FailValidate(ValidateError::Other(
+                        format!("tombstone given for unknown entry key {:?}", key).into(),
+                    )))
+                }
+            }
+        }
+
+        // Compile every validator into one program, so the types are compiled only once
+        let mut inner = inner;
+        let mut compiler = Compiler::new(&inner.types);
+        inner.doc_root = compiler.compile(&inner.doc);
+        for entry in inner.entries.values_mut() {
+            entry.root = compiler.compile(&entry.entry);
+        }
+        inner.program = compiler.finish();
+
+        Ok(Self {
+            hash,
+            inner: Arc::new(inner),
+            limits: Limits::default(),
+            policy: VerifyPolicy::default(),
+            unknown: UnknownFields::Ignore,
+            compressor: None,
+        })
+    }
+
+    /// Attempt to create a schema from a given document, first checking how many regular
+    /// expressions would be present in the schema and failing out if it's above the provided
+    /// limit.
+    ///
+    /// For a rough guide of what to set `max_regex` to, know that every regex has an
+    /// approximate max memory size of 12 MiB, so a malicious schema can use up at least
+    /// `max_regex * 12 MiB` bytes off the heap.
+    pub fn from_doc_max_regex(doc: &Document, max_regex: u8) -> Result<Self> {
+        // Count up all the regular expressions that can be in a schema
+        let regex_check: ValueRef = doc.deserialize()?;
+        let mut regexes = crate::count_regexes(&regex_check["doc"]);
+        if let Some(map) = regex_check["types"].as_map() {
+            regexes += map
+                .values()
+                .fold(0, |acc, val| acc + crate::count_regexes(val));
+        }
+        if let Some(map) = regex_check["entries"].as_map() {
+            regexes += map
+                .values()
+                .fold(0, |acc, val| acc + crate::count_regexes(&val["entry"]));
+        }
+        if regex_check["entry_keys"]["matches"].is_str() {
+            regexes += 1;
+        }
+
+        if regexes > (max_regex as usize) {
+            return Err(Error::FailValidate(ValidateError::Other(
+                format!(
+                    "Found {} regexes in Schema, only {} allowed",
+                    regexes, max_regex
+                )
+                .into(),
+            )));
+        }
+
+        let inner = doc.deserialize()?;
+        check_no_parent(&inner)?;
+        Self::from_inner(doc.hash().clone(), inner)
+    }
+
+    /// Get the hash of this schema.
+    ///
+    /// The hash identifies the schema: it is the hash of the schema document, so it stays the same
+    /// across crate versions and can be used to key caches and indexes of compiled schemas. Limits
+    /// and verification policies set on a schema aren't part of it.
+    pub fn hash(&self) -> &Hash {
+        &self.hash
+    }
+
+    /// Get the schema's name, or an empty string if it doesn't have one.
+    pub fn name(&self) -> &str {
+        &self.inner.name
+    }
+
+    /// Get the schema's description, or an empty string if it doesn't have one.
+    pub fn description(&self) -> &str {
+        &self.inner.description
+    }
+
+    /// Get the schema's version. Schemas without a version have a version of 0.
+    pub fn version(&self) -> &Integer {
+        &self.inner.version
+    }
+
+    /// Get the Identity of the schema's maintainer, if it names one. Nothing checks this against
+    /// who actually signed the schema document.
+    pub fn maintainer(&self) -> Option<&Identity> {
+        self.inner.maintainer.as_ref()
+    }
+
+    /// Get the hash of the schema this one [extends][SchemaBuilder::extends], if any. The
+    /// validators of this schema already include everything inherited from it.
+    pub fn parent(&self) -> Option<&Hash> {
+        self.inner.parent.as_ref()
+    }
+
+    /// Get the validator that documents using this schema must pass.
+    pub fn doc_validator(&self) -> &Validator {
+        &self.inner.doc
+    }
+
+    /// Iterate over every entry key in the schema, in order, along with the validator entries
+    /// with that key must pass.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &Validator)> {
+        self.inner
+            .entries
+            .iter()
+            .map(|(key, entry)| (key.as_str(), &entry.entry))
+    }
+
+    /// Get the validator for an entry key, if the key is in the schema.
+    pub fn entry_validator(&self, key: &str) -> Option<&Validator> {
+        self.inner.entries.get(key).map(|entry| &entry.entry)
+    }
+
+    /// Iterate over every stored type in the schema, in order, along with its validator. These
+    /// are what [`Validator::Ref`] validators in the schema refer to.
+    pub fn types(&self) -> impl Iterator<Item = (&str, &Validator)> {
+        self.inner
+            .types
+            .iter()
+            .map(|(name, validator)| (name.as_str(), validator))
+    }
+
+    /// Set lower [`Limits`] on the documents, entries, and queries this schema will validate or
+    /// decode. Limits are local to this copy of the schema, and don't change its hash.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Set a [`VerifyPolicy`] that signatures on decoded documents and entries must follow. Like
+    /// limits, the policy is local to this copy of the schema, and doesn't change its hash.
+    pub fn with_verify_policy(mut self, policy: VerifyPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Get the [`VerifyPolicy`] this schema enforces.
+    pub fn verify_policy(&self) -> &VerifyPolicy {
+        &self.policy
+    }
+
+    /// Set how fields that a deserialization target doesn't use are handled, for every document
+    /// and entry this schema decodes or validates; see [`Document::deserialize`]. Rejecting them
+    /// catches data written by a newer version of the schema, while ignoring them lets older
+    /// readers keep working. Like limits, this is local to this copy of the schema, and doesn't
+    /// change its hash.
+    pub fn with_unknown_fields(mut self, unknown: UnknownFields) -> Self {
+        self.unknown = unknown;
+        self
+    }
+
+    /// Get how documents and entries from this schema handle unknown fields.
+    pub fn unknown_fields(&self) -> UnknownFields {
+        self.unknown
+    }
+
+    /// Supply a [`Compressor`] for this schema to use when encoding and decoding, in place of the
+    /// built-in compressor with the same algorithm identifier. This lets targets that can't carry
+    /// the built-in compression libraries bring their own, and lets schemas use algorithms that
+    /// fog-pack doesn't ship. Every system decoding the documents & entries needs a compressor
+    /// for the same algorithm. Like limits, this is local to this copy of the schema, and doesn't
+    /// change its hash.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the compressor's [`algorithm`][Compressor::algorithm] is 64 or greater, as it
+    /// wouldn't fit in the compression marker byte.
+    pub fn with_compressor(mut self, compressor: Arc<dyn Compressor>) -> Self {
+        assert!(
+            compressor.algorithm() < 64,
+            "compressor algorithm {} must be less than 64",
+            compressor.algorithm()
+        );
+        self.compressor = Some(SharedCompressor(compressor));
+        self
+    }
+
+    /// Get the [`Compressor`] supplied with [`with_compressor`][Self::with_compressor], if any.
+    pub fn compressor(&self) -> Option<&dyn Compressor> {
+        self.compressor.as_ref().map(|c| c.0.as_ref())
+    }
+
+    /// Get the [`Limits`] this schema enforces.
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Get the most regexes a query decoded by this schema may hold.
+    pub(crate) fn max_regex(&self) -> u8 {
+        self.inner.max_regex
+    }
+
+    /// Set up the calling thread's compression contexts for this schema's compression settings,
+    /// so the first documents and entries encoded or decoded on this thread don't pay for it.
+    /// Contexts are kept per thread and reused across every schema, so this only needs to be
+    /// called once per thread, ideally on each worker thread as it starts.
+    pub fn warm(&self) {
+        self.inner.doc_compress.warm(self.compressor());
+        for entry in self.inner.entries.values() {
+            entry.compress.warm(self.compressor());
+        }
+    }
+
+    /// Get the paths of all fields in the document that this schema marks as encrypted.
+    pub fn encrypted_fields(&self) -> Vec<Path> {
+        self.inner
+            .encrypted
+            .iter()
+            .map(|p| Path::parse(p).expect("encrypted field paths are checked on creation"))
+            .collect()
+    }
+
+    /// Get the paths of all fields in the document that this schema marks as holding weak
+    /// references.
+    pub fn weak_fields(&self) -> Vec<Path> {
+        self.inner
+            .weak
+            .iter()
+            .map(|p| Path::parse(p).expect("weak field paths are checked on creation"))
+            .collect()
+    }
+
+    /// Get the paths of all fields in the document that this schema marks as immutable.
+    pub fn immutable_fields(&self) -> Vec<Path> {
+        self.inner
+            .immutable
+            .iter()
+            .map(|p| Path::parse(p).expect("immutable field paths are checked on creation"))
+            .collect()
+    }
+
+    /// Check that `new` is a valid revision of `old`. Both documents must use this schema, `new`
+    /// must hold the hash of `old` in its top-level [`REVISION_FIELD`], and every
+    /// [immutable field][SchemaBuilder::immutable_field] that has a value in `old` must have the
+    /// same value in `new`.
+    ///
+    /// Only the link between the two documents is checked; whether `old` is the latest revision,
+    /// or who may write revisions, is up to the application.
+    pub fn check_revision(&self, old: &Document, new: &Document) -> Result<()> {
+        for doc in [old, new] {
+            if doc.schema_hash() != Some(&self.hash) {
+                return Err(Error::SchemaMismatch {
+                    actual: doc.schema_hash().cloned(),
+                    expected: Some(self.hash.clone()),
+                });
+            }
+        }
+        let (link, changed) = self.compare_revisions(old, new)?;
+        if link.as_ref() != Some(old.hash()) {
+            return Err(Error::FailValidate(ValidateError::Other(
+                format!(
+                    "document {} doesn't hold {} in its {:?} field",
+                    new.hash(),
+                    old.hash(),
+                    REVISION_FIELD
+                )
+                .into(),
+            )));
+        }
+        if let Some(path) = changed.first() {
+            return Err(Error::FailValidate(ValidateError::Other(
+                format!("immutable field {} was changed", path).into(),
+            )));
+        }
+        Ok(())
+    }
+
+    // Get the hash `new` holds in its revision field, if any, along with every immutable field
+    // that has a value in `old` and was changed or removed in `new`. Shared with
+    // `history::verify_chain`, which reports these instead of failing on them.
+    pub(crate) fn compare_revisions(
+        &self,
+        old: &Document,
+        new: &Document,
+    ) -> Result<(Option<Hash>, Vec<Path>)> {
+        let link = match new.get_path(&Path::new().key(REVISION_FIELD))? {
+            Some(ValueRef::Hash(hash)) => Some(hash),
+            _ => None,
+        };
+        let mut changed = Vec::new();
+        for path in self.immutable_fields() {
+            let Some(before) = old.get_path(&path)? else {
+                continue;
+            };
+            if new.get_path(&path)?.as_ref() != Some(&before) {
+                changed.push(path);
+            }
+        }
+        Ok((link, changed))
+    }
+
+    /// Get the paths of all fields in the document that this schema marks as externalizable,
+    /// along with the size above which their values are moved out into chunk documents.
+    pub fn external_fields(&self) -> Vec<(Path, u32)> {
+        self.inner
+            .external
+            .iter()
+            .map(|(p, threshold)| {
+                let path = Path::parse(p).expect("external field paths are checked on creation");
+                (path, *threshold)
+            })
+            .collect()
+    }
+
+    /// Get the reducer used to compact entries with the given key, if the schema declares one.
+    pub fn reducer(&self, entry: &str) -> Option<Reducer> {
+        self.inner.reducers.get(entry).copied()
+    }
+
+    /// Check if entries with the given key are tombstones.
+    pub fn is_tombstone(&self, entry: &str) -> bool {
+        self.inner.tombstones.iter().any(|k| k == entry)
+    }
+
+    /// Filter a set of entries, removing every entry that has been retracted by a tombstone in the
+    /// set, along with the tombstones themselves. Entries are otherwise kept in order.
+    /// Tombstones can use a different key than the entries they retract, so the set should
+    /// include any tombstones attached to the same document.
+    ///
+    /// A tombstone only retracts an entry with the same signer, so nobody can retract an entry
+    /// they didn't write. Unsigned tombstones retract nothing, as there is no way to tell who
+    /// wrote them, so unsigned entries can't be retracted. Retraction can't be undone: an entry
+    /// stays retracted as long as any matching tombstone is present.
+    pub fn apply_tombstones<I: IntoIterator<Item = Entry>>(&self, entries: I) -> Vec<Entry> {
+        let (tombstones, entries): (Vec<Entry>, Vec<Entry>) = entries
+            .into_iter()
+            .partition(|e| e.schema_hash() == &self.hash && self.is_tombstone(e.key()));
+        let mut retracted: HashMap<Hash, Vec<&Identity>> = HashMap::new();
+        for tombstone in tombstones.iter() {
+            let Some(signer) = tombstone.signer() else {
+                continue;
+            };
+            if let Ok(hash) = tombstone.deserialize::<Hash>() {
+                retracted.entry(hash).or_default().push(signer);
+            }
+        }
+        entries
+            .into_iter()
+            .filter(|e| match (e.signer(), retracted.get(e.hash())) {
+                (Some(signer), Some(signers)) => !signers.contains(&signer),
+                _ => true,
+            })
+            .collect()
+    }
+
+    /// Get the paths of all fields in entries with the given key that this schema marks as
+    /// volatile.
+    pub fn volatile_fields(&self, entry: &str) -> Vec<Path> {
+        self.inner
+            .volatile
+            .get(entry)
+            .map_or_else(Vec::new, |paths| {
+                paths
+                    .iter()
+                    .map(|p| Path::parse(p).expect("volatile field paths are checked on creation"))
+                    .collect()
+            })
+    }
+
+    /// Compute the semantic hash of an entry: a hash of its parent, key, and data with every
+    /// [volatile field][SchemaBuilder::volatile_field] removed. Entries that only differ in their
+    /// volatile fields, or in who signed them, have the same semantic hash even though their
+    /// entry hashes differ, so stores can use it to spot duplicate submissions.
+    ///
+    /// The hash is taken over the fog-pack encoding of the array `[parent, key, data]`. Volatile
+    /// fields are removed deepest first, and from arrays in order of highest index first, so
+    /// removing one never shifts an array element that another names.
+    ///
+    /// Fails if the entry doesn't use this schema.
+    pub fn semantic_hash(&self, entry: &Entry) -> Result<Hash> {
+        if entry.schema_hash() != &self.hash {
+            return Err(Error::SchemaMismatch {
+                actual: Some(entry.schema_hash().clone()),
+                expected: Some(self.hash.clone()),
+            });
+        }
+        let mut data: Value = entry.deserialize()?;
+        Path::remove_all(&mut data, self.volatile_fields(entry.key()));
+        let mut ser = ser::FogSerializer::default();
+        (entry.parent(), entry.key(), &data).serialize(&mut ser)?;
+        Ok(Hash::new(ser.finish()))
+    }
+
+    /// Remove entries with the same [semantic hash][Schema::semantic_hash] as an entry before
+    /// them, keeping the first of each set of duplicates. Entries are otherwise kept in order.
+    ///
+    /// Fails, leaving the entries untouched, if any entry doesn't use this schema.
+    pub fn dedup_entries(&self, entries: &mut Vec<Entry>) -> Result<()> {
+        let hashes = entries
+            .iter()
+            .map(|entry| self.semantic_hash(entry))
+            .collect::<Result<Vec<Hash>>>()?;
+        let mut seen = HashSet::new();
+        let mut hashes = hashes.into_iter();
+        entries.retain(|_| seen.insert(hashes.next().expect("one hash per entry")));
+        Ok(())
+    }
+
+    /// Find the field that entries with the given key are ordered by. This is the first field
+    /// whose validator has `ord` set, searching the entry validator's required map fields in key
+    /// order and descending into nested maps and stored types. If the entry validator itself has
+    /// `ord` set, this is the empty path. Returns `None` if the key isn't in the schema or has no
+    /// such field.
+    pub fn ord_field(&self, key: &str) -> Option<Path> {
+        let validator = self.entry_validator(key)?;
+        find_ord_field(&self.inner.types, validator, Path::new(), 0)
+    }
+
+    // Get the value an entry is ordered by, failing if it has none
+    fn entry_ord_value(&self, key: &str, field: &Path, entry: &Entry) -> Result<Value> {
+        if entry.key() != key || entry.schema_hash() != &self.hash {
+            return Err(Error::FailValidate(ValidateError::Other(
+                format!(
+                    "entry {} isn't a {:?} entry for this schema",
+                    entry.hash(),
+                    key
+                )
+                .into(),
+            )));
+        }
+        entry
+            .get_path(field)?
+            .and_then(crate::sync::ord_value)
+            .ok_or_else(|| {
+                Error::FailValidate(ValidateError::Other(
+                    format!("entry {} has no ordered value at {}", entry.hash(), field).into(),
+                ))
+            })
+    }
+
+    fn entry_ord_values(&self, key: &str, entries: &[Entry]) -> Result<Vec<Value>> {
+        let field = self.ord_field(key).ok_or_else(|| {
+            Error::FailValidate(ValidateError::Other(
+                format!("entry key {:?} has no ordered field", key).into(),
+            ))
+        })?;
+        entries
+            .iter()
+            .map(|entry| self.entry_ord_value(key, &field, entry))
+            .collect()
+    }
+
+    /// Sort entries with the given key by their [ordered field][Schema::ord_field], the same way
+    /// queries and [`paginate`][crate::page::paginate] order them. Values of the same type are
+    /// compared directly, values of different types are ranked by type, and ties are broken by
+    /// comparing entry hashes, so every client and server sorts the same set the same way.
+    ///
+    /// Fails, leaving the entries untouched, if the key has no ordered field, or any entry has a
+    /// different key or schema, or lacks an orderable value in the field.
+    pub fn sort_entries(&self, key: &str, entries: &mut Vec<Entry>) -> Result<()> {
+        let values = self.entry_ord_values(key, entries)?;
+        let mut keyed: Vec<(Value, Entry)> = values.into_iter().zip(entries.drain(..)).collect();
+        keyed.sort_by(|a, b| {
+            crate::sync::ord_cmp(&a.0, &b.0)
+                .then_with(|| a.1.hash().as_ref().cmp(b.1.hash().as_ref()))
+        });
+        entries.extend(keyed.into_iter().map(|(_, entry)| entry));
+        Ok(())
+    }
+
+    /// Check that entries with the given key are in the order [`sort_entries`][Schema::sort_entries]
+    /// would put them in. Duplicate entries aren't allowed, so each entry must come strictly
+    /// after the one before it.
+    ///
+    /// Fails if the entries are out of order, or for any of the reasons `sort_entries` would.
+    pub fn check_ordered(&self, key: &str, entries: &[Entry]) -> Result<()> {
+        let values = self.entry_ord_values(key, entries)?;
+        for (i, pair) in values.windows(2).enumerate() {
+            let order = crate::sync::ord_cmp(&pair[0], &pair[1]).then_with(|| {
+                entries[i]
+                    .hash()
+                    .as_ref()
+                    .cmp(entries[i + 1].hash().as_ref())
+            });
+            if order != Ordering::Less {
+                return Err(Error::FailValidate(ValidateError::Other(
+                    format!("entry at index {} is out of order", i + 1).into(),
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the number of signers each document must have. Zero if there is no requirement.
+    pub fn signature_threshold(&self) -> u8 {
+        self.inner.threshold
+    }
+
+    /// Check if documents must be unsigned.
+    pub fn requires_unsigned(&self) -> bool {
+        self.inner.unsigned
+    }
+
+    /// Get the compression threshold: documents and entries with less data than this, in bytes,
+    /// aren't compressed by default.
+    pub fn compress_threshold(&self) -> u32 {
+        self.inner.compress_threshold
+    }
+
+    /// Get the Identities that count towards the signature threshold. If empty, every signer
+    /// counts.
+    pub fn signers(&self) -> &[Identity] {
+        &self.inner.signers
+    }
+
+    /// Check that a set of signers meets this schema's signature threshold, or that there are no
+    /// signers if documents must be unsigned.
+    pub(crate) fn check_signers(&self, signers: &[Identity]) -> Result<()> {
+        if self.inner.unsigned && !signers.is_empty() {
+            return Err(Error::FailValidate(ValidateError::Other(
+                format!(
+                    "Document must be unsigned, but has {} signatures",
+                    signers.len()
+                )
+                .into(),
+            )));
+        }
+        let count = signers
+            .iter()
+            .filter(|s| self.inner.signers.is_empty() || self.inner.signers.contains(s))
+            .count();
+        if count < self.inner.threshold as usize {
+            return Err(Error::FailValidate(ValidateError::Other(
+                format!(
+                    "Document has {} of the {} required signatures",
+                    count, self.inner.threshold
+                )
+                .into(),
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check that a document uses this schema, validate its data, and check that it meets the
+    /// signature threshold.
+    pub(crate) fn check_doc(&self, doc: &Document) -> Result<()> {
+        match doc.schema_hash() {
+            Some(hash) if hash == &self.hash => (),
+            actual => {
+                return Err(Error::SchemaMismatch {
+                    actual: actual.cloned(),
+                    expected: Some(self.hash.clone()),
+                })
+            }
+        }
+        self.limits.check_doc(doc.size())?;
+        let parser = self.parser(doc.data());
+        let parser = self.validate_doc_data(parser)?;
+        parser.finish()?;
+        self.check_signers(doc.signers())?;
+        self.policy.check_doc(doc)
+    }
+
+    /// Check if this schema allows floating-point values that aren't in canonical form.
+    pub fn raw_floats(&self) -> bool {
+        self.inner.raw_floats
+    }
+
+    fn parser<'a>(&self, data: &'a [u8]) -> Parser<'a> {
+        Parser::new(data)
+            .raw_floats(self.inner.raw_floats)
+            .max_depth(self.limits.depth())
+    }
+
+    // Validate document data against the schema's compiled document validator.
+    fn validate_doc_data<'de>(&self, parser: Parser<'de>) -> Result<Parser<'de>> {
+        let (parser, _) = self.inner.program.validate(
+            self.inner.doc_root,
+            &self.inner.doc,
+            &self.inner.types,
+            parser,
+            None,
+        )?;
+        Ok(parser)
+    }
+
+    // Get a scanner with the same settings as `parser`, for checking data as it's decompressed.
+    fn scanner(&self) -> Scanner {
+        Scanner::new(self.inner.raw_floats, self.limits.depth())
+    }
+
+    /// Load a dictionary document and use it for compressing & decompressing documents adhering
+    /// to this schema, replacing the schema's own document compression setting. Fails if the
+    /// document doesn't use the [`dictionary_schema`].
+    ///
+    /// This doesn't change the schema's hash, so every user of the schema needs to attach the same
+    /// dictionary in order to decode documents compressed with it.
+    pub fn attach_dictionary(&mut self, doc: &Document) -> Result<()> {
+        Arc::make_mut(&mut self.inner).doc_compress = Compress::Dict(dictionary_from_doc(doc)?);
+        Ok(())
+    }
+
+    /// Load a dictionary document and use it for compressing & decompressing entries with the
+    /// given key, replacing the schema's own compression setting for them. Fails if the entry key
+    /// isn't in the schema, or if the document doesn't use the [`dictionary_schema`].
+    pub fn attach_entry_dictionary(&mut self, key: &str, doc: &Document) -> Result<()> {
+        let dict = dictionary_from_doc(doc)?;
+        let entry_schema = Arc::make_mut(&mut self.inner)
+            .entries
+            .get_mut(key)
+            .ok_or_else(|| {
+                Error::FailValidate(ValidateError::Other(
+                    format!("entry key \"{:?}\" is not in schema", key).into(),
+                ))
+            })?;
+        entry_schema.compress = Compress::Dict(dict);
+        Ok(())
+    }
+
+    /// Check a value against this schema's document validator, without encoding it into a
+    /// document first. Passing this means a document holding the value will pass
+    /// [`validate_new_doc`][Self::validate_new_doc], as long as it fits within the size limits.
+    pub fn validate_value(&self, value: &ValueRef) -> Result<()> {
+        self.inner
+            .doc
+            .validate_value_with(&self.inner.types, self.limits.depth(), value)
+    }
+
+    /// Check a value against the validator for an entry key, without encoding it into an entry
+    /// first. Fails if the key isn't in the schema.
+    pub fn validate_entry_value(&self, key: &str, value: &ValueRef) -> Result<()> {
+        let entry_schema = self.inner.entries.get(key).ok_or_else(|| {
+            Error::FailValidate(ValidateError::Other(
+                format!("entry key \"{:?}\" is not in schema", key).into(),
+            ))
+        })?;
+        entry_schema
+            .entry
+            .validate_value_with(&self.inner.types, self.limits.depth(), value)
+    }
+
+    /// Validate a [`NewDocument`], turning it into a [`Document`]. Fails if the document doesn't
+    /// use this schema, or if it doesn't meet this schema's requirements.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(schema = %self.hash, hash = %doc.hash(), size = doc.size())
+        )
+    )]
+    pub fn validate_new_doc(&self, doc: NewDocument) -> Result<Document> {
+        // Check that the document uses this schema
+        match doc.schema_hash() {
+            Some(hash) if hash == &self.hash => (),
+            actual => {
+                return Err(Error::SchemaMismatch {
+                    actual: actual.cloned(),
+                    expected: Some(self.hash.clone()),
+                })
+            }
+        }
+
+        // Validate the data
+        self.limits.check_doc(doc.size())?;
+        let parser = self.parser(doc.data());
+        let parser = self.validate_doc_data(parser)?;
+        parser.finish()?;
+
+        // The signature threshold waits until encoding, as signatures can still be added, but
+        // nothing can make a signed document unsigned again
+        if self.inner.unsigned {
+            self.check_signers(doc.signers())?;
+        }
+
+        let mut doc = Document::from_new(doc);
+        doc.set_unknown_fields(self.unknown);
+        doc.set_validated();
+        Ok(doc)
+    }
+
+    /// Encode a [`Document`], returning the resulting Document's hash and fully encoded format.
+    /// Fails if the document doesn't use this schema.
+    pub fn encode_doc(&self, doc: Document) -> Result<(Hash, Vec<u8>)> {
+        let (hash, doc, _) = self.encode_doc_with_stats(doc)?;
+        Ok((hash, doc))
+    }
+
+    /// Encode a [`Document`], like [`encode_doc`][Self::encode_doc], but also return
+    /// [`EncodeStats`] describing how well it compressed.
+    pub fn encode_doc_with_stats(&self, doc: Document) -> Result<(Hash, Vec<u8>, EncodeStats)> {
+        self.encode_doc_scratch(doc, &mut Vec::new())
+    }
+
+    /// Encode a [`Document`] like [`encode_doc`][Self::encode_doc], without blocking an async
+    /// runtime. Verifying and compressing a document close to the 1 MiB limit can take
+    /// milliseconds, so documents with 64 KiB or more of data are encoded on a newly spawned
+    /// thread. Smaller documents are encoded immediately. Use
+    /// [`encode_doc_async_with`][Self::encode_doc_async_with] to run on an existing thread pool
+    /// instead.
+    pub async fn encode_doc_async(&self, doc: Document) -> Result<(Hash, Vec<u8>)> {
+        self.encode_doc_async_with(doc, spawn_thread).await
+    }
+
+    /// Encode a [`Document`] like [`encode_doc_async`][Self::encode_doc_async], but hand large
+    /// documents to `spawn`, which should run the job on a blocking thread pool (like tokio's
+    /// `spawn_blocking`). If the pool drops the job without running it, the document is encoded
+    /// by the awaiting task instead.
+    ///
+    /// ```
+    /// # use fog_pack::{document::*, schema::*};
+    /// # fn main() -> fog_pack::error::Result<()> {
+    /// # futures_executor::block_on(async {
+    /// let doc = NoSchema::validate_new_doc(NewDocument::new(None, vec![7u8; 100_000])?)?;
+    /// let (hash, encoded) = NoSchema::encode_doc_async_with(doc, |job| {
+    ///     std::thread::spawn(job);
+    /// })
+    /// .await?;
+    /// assert_eq!(NoSchema::decode_doc(encoded)?.hash(), &hash);
+    /// # Ok(())
+    /// # })
+    /// # }
+    /// ```
+    pub async fn encode_doc_async_with<S: FnOnce(BlockingJob)>(
+        &self,
+        doc: Document,
+        spawn: S,
+    ) -> Result<(Hash, Vec<u8>)> {
+        if doc.data().len() < OFFLOAD_THRESHOLD {
+            return self.encode_doc(doc);
+        }
+        let schema = self.clone();
+        offload(Box::new(move || schema.encode_doc(doc)), spawn).await
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            name = "encode_doc",
+            skip_all,
+            fields(schema = %self.hash, hash = %doc.hash(), size = doc.size())
+        )
+    )]
+    fn encode_doc_scratch(
+        &self,
+        doc: Document,
+        scratch: &mut Vec<u8>,
+    ) -> Result<(Hash, Vec<u8>, EncodeStats)> {
+        // Check that the document uses this schema
+        match doc.schema_hash() {
+            Some(hash) if hash == &self.hash => (),
+            actual => {
+                return Err(Error::SchemaMismatch {
+                    actual: actual.cloned(),
+                    expected: Some(self.hash.clone()),
+                })
+            }
+        }
+
+        self.check_signers(doc.signers())?;
 
         // Compress the document
-        let (hash, doc, compression) = doc.complete();
+        let start = start_timer();
+        let (hash, doc, compression) = doc.verify()?.complete();
+        let uncompressed_size = doc.len();
         let doc = match compression {
-            None => compress_doc(doc, &self.inner.doc_compress),
+            None => compress_doc(
+                doc,
+                &self.inner.doc_compress,
+                self.compressor(),
+                self.inner.compress_threshold,
+                scratch,
+            ),
             Some(None) => doc,
             Some(Some(level)) => compress_doc(
                 doc,
@@ -491,10 +2418,14 @@ impl Schema {
                     algorithm: 0,
                     level,
                 },
+                self.compressor(),
+                0,
+                scratch,
             ),
         };
+        let stats = EncodeStats::new(uncompressed_size, &doc, start);
 
-        Ok((hash, doc))
+        Ok((hash, doc, stats))
     }
 
     fn check_schema(&self, doc: &[u8]) -> Result<()> {
@@ -519,28 +2450,71 @@ impl Schema {
     }
 
     /// Decode a document that uses this schema.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(schema = %self.hash, size = doc.len(), hash = tracing::field::Empty)
+        )
+    )]
     pub fn decode_doc(&self, doc: Vec<u8>) -> Result<Document> {
+        let (doc, pending) = self.decode_doc_unverified(doc)?;
+        verify_pending(pending)?;
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("hash", tracing::field::display(doc.hash()));
+        Ok(doc)
+    }
+
+    // Decode and validate a document, leaving its signature to be verified by the caller.
+    fn decode_doc_unverified(&self, doc: Vec<u8>) -> Result<(Document, Vec<PendingSignature>)> {
+        self.limits.check_doc(doc.len())?;
+        let (version, doc) = strip_version(doc, &self.limits.format_version)?;
         self.check_schema(&doc)?;
 
         // Decompress
-        let doc = Document::new(decompress_doc(doc, &self.inner.doc_compress)?)?;
+        let (mut doc, pending) = Document::new_unverified(decompress_doc(
+            doc,
+            &self.inner.doc_compress,
+            self.compressor(),
+            self.limits.doc_size(),
+        )?)?;
+        doc.set_format_version(version);
+        doc.set_unknown_fields(self.unknown);
 
         // Validate
-        let parser = Parser::new(doc.data());
-        let (parser, _) = self.inner.doc.validate(&self.inner.types, parser, None)?;
-        parser.finish()?;
+        self.check_doc(&doc)?;
+        doc.set_validated();
 
-        Ok(doc)
+        Ok((doc, pending))
     }
 
     /// Decode a Document, skipping any checks of the data. This should only be run when the raw
     /// document has definitely been passed through validation before, i.e. if it is stored in a
     /// local database after going through [`encode_doc`][Self::encode_doc].
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(schema = %self.hash, size = doc.len(), hash = tracing::field::Empty)
+        )
+    )]
     pub fn trusted_decode_doc(&self, doc: Vec<u8>) -> Result<Document> {
+        let (version, doc) = strip_version(doc, &VersionPolicy::Supported)?;
         self.check_schema(&doc)?;
 
         // Decompress
-        let doc = Document::new(decompress_doc(doc, &Compress::None)?)?;
+        let mut doc = Document::new(decompress_doc(
+            doc,
+            &Compress::None,
+            self.compressor(),
+            MAX_DOC_SIZE,
+        )?)?;
+        doc.set_format_version(version);
+        doc.set_unknown_fields(self.unknown);
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("hash", tracing::field::display(doc.hash()));
         Ok(doc)
     }
 
@@ -548,6 +2522,19 @@ impl Schema {
     /// document, the parent document doesn't use this schema, or the entry doesn't meet the schema
     /// requirements. The resulting Entry is stored in a [`DataChecklist`] that must be iterated
     /// over in order to finish validation.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(
+                schema = %self.hash,
+                key = entry.key(),
+                hash = %entry.hash(),
+                size = entry.size()
+            )
+        )
+    )]
     pub fn validate_new_entry(&self, entry: NewEntry) -> Result<DataChecklist<Entry>> {
         // Check that the entry's parent document uses this schema
         if entry.schema_hash() != &self.hash {
@@ -558,27 +2545,81 @@ impl Schema {
         }
 
         // Validate the data and generate a checklist of remaining documents to check
-        let parser = Parser::new(entry.data());
+        self.limits.check_entry(entry.size())?;
+        let parser = self.parser(entry.data());
         let entry_schema = self.inner.entries.get(entry.key()).ok_or_else(|| {
-            Error::FailValidate(format!("entry key \"{:?}\" is not in schema", entry.key()))
+            Error::FailValidate(ValidateError::Other(
+                format!("entry key \"{:?}\" is not in schema", entry.key()).into(),
+            ))
         })?;
         let checklist = Some(Checklist::new(&self.hash, &self.inner.types));
-        let (parser, checklist) =
-            entry_schema
-                .entry
-                .validate(&self.inner.types, parser, checklist)?;
+        let (parser, checklist) = self.inner.program.validate(
+            entry_schema.root,
+            &entry_schema.entry,
+            &self.inner.types,
+            parser,
+            checklist,
+        )?;
         parser.finish()?;
 
-        Ok(DataChecklist::from_checklist(
-            checklist.unwrap(),
-            Entry::from_new(entry),
-        ))
+        let mut entry = Entry::from_new(entry);
+        entry.set_unknown_fields(self.unknown);
+        entry.set_validated();
+        Ok(DataChecklist::from_checklist(checklist.unwrap(), entry))
+    }
+
+    /// Validate many [`NewEntry`] values at once, spreading the work across rayon's thread pool.
+    /// Each entry is validated exactly as [`validate_new_entry`][Self::validate_new_entry] would,
+    /// and the results are returned in the same order as the entries.
+    #[cfg(feature = "rayon")]
+    pub fn validate_entries_parallel(
+        &self,
+        entries: Vec<NewEntry>,
+    ) -> Vec<Result<DataChecklist<Entry>>> {
+        use rayon::prelude::*;
+        entries
+            .into_par_iter()
+            .map(|entry| self.validate_new_entry(entry))
+            .collect()
     }
 
     /// Encode an [`Entry`], returning the resulting Entry's reference, its fully encoded format,
     /// and a list of Hashes of the Documents it needs for validation.
     /// Fails if provided the wrong parent document or the parent document doesn't use this schema.
     pub fn encode_entry(&self, entry: Entry) -> Result<(EntryRef, Vec<u8>, Vec<Hash>)> {
+        let (entry_ref, entry, needed_docs, _) = self.encode_entry_with_stats(entry)?;
+        Ok((entry_ref, entry, needed_docs))
+    }
+
+    /// Encode an [`Entry`], like [`encode_entry`][Self::encode_entry], but also return
+    /// [`EncodeStats`] describing how well it compressed. Time spent re-validating the entry is
+    /// not included in the stats.
+    pub fn encode_entry_with_stats(
+        &self,
+        entry: Entry,
+    ) -> Result<(EntryRef, Vec<u8>, Vec<Hash>, EncodeStats)> {
+        self.encode_entry_scratch(entry, &mut Vec::new())
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            name = "encode_entry",
+            skip_all,
+            fields(
+                schema = %self.hash,
+                key = entry.key(),
+                hash = %entry.hash(),
+                size = entry.size()
+            )
+        )
+    )]
+    fn encode_entry_scratch(
+        &self,
+        entry: Entry,
+        scratch: &mut Vec<u8>,
+    ) -> Result<(EntryRef, Vec<u8>, Vec<Hash>, EncodeStats)> {
         // Check that the entry's parent document uses this schema
         if entry.schema_hash() != &self.hash {
             return Err(Error::SchemaMismatch {
@@ -594,21 +2635,34 @@ impl Schema {
         // At some point, it's plausible this could be performed with a more minimal validation
         // check.
         let entry_schema = self.inner.entries.get(entry.key()).ok_or_else(|| {
-            Error::FailValidate(format!("entry key \"{:?}\" is not in schema", entry.key()))
+            Error::FailValidate(ValidateError::Other(
+                format!("entry key \"{:?}\" is not in schema", entry.key()).into(),
+            ))
         })?;
-        let parser = Parser::new(entry.data());
+        let parser = self.parser(entry.data());
         let checklist = Some(Checklist::new(&self.hash, &self.inner.types));
-        let (parser, checklist) =
-            entry_schema
-                .entry
-                .validate(&self.inner.types, parser, checklist)?;
+        let (parser, checklist) = self.inner.program.validate(
+            entry_schema.root,
+            &entry_schema.entry,
+            &self.inner.types,
+            parser,
+            checklist,
+        )?;
         parser.finish()?;
         let needed_docs: Vec<Hash> = checklist.unwrap().iter().map(|(hash, _)| hash).collect();
 
         // Compress the entry
-        let (entry_ref, entry, compression) = entry.complete();
+        let start = start_timer();
+        let (entry_ref, entry, compression) = entry.verify()?.complete();
+        let uncompressed_size = entry.len();
         let entry = match compression {
-            None => compress_entry(entry, &entry_schema.compress),
+            None => compress_entry(
+                entry,
+                &entry_schema.compress,
+                self.compressor(),
+                self.inner.compress_threshold,
+                scratch,
+            ),
             Some(None) => entry,
             Some(Some(level)) => compress_entry(
                 entry,
@@ -616,20 +2670,64 @@ impl Schema {
                     algorithm: 0,
                     level,
                 },
+                self.compressor(),
+                0,
+                scratch,
             ),
         };
+        let stats = EncodeStats::new(uncompressed_size, &entry, start);
+
+        Ok((entry_ref, entry, needed_docs, stats))
+    }
+
+    /// Decode an entry, given the key and parent Hash. Result is in a [`DataChecklist`] that must
+    /// be iterated over in order to finish verification and get the resulting Entry.
+    ///
+    /// Decompression and parsing are interleaved: the entry is decompressed a few kilobytes at a
+    /// time, and each piece is parsed as soon as it's ready, so malformed data stops decompression
+    /// right away instead of after the whole entry has been expanded. Schema validation then runs
+    /// on the complete entry, borrowing directly from the decompressed data and needing no
+    /// buffers of its own beyond the checklist of referenced documents. Memory use is therefore
+    /// bounded by the entry itself, which never exceeds [`MAX_ENTRY_SIZE`][crate::MAX_ENTRY_SIZE],
+    /// plus a small fixed working set.
+    pub fn decode_entry(
+        &self,
+        entry: Vec<u8>,
+        key: &str,
+        parent: &Document,
+    ) -> Result<DataChecklist<Entry>> {
+        let (checklist, pending) = self.decode_entry_unverified(entry, key, parent)?;
+        verify_pending(pending)?;
+        Ok(checklist)
+    }
 
-        Ok((entry_ref, entry, needed_docs))
+    /// Decode an entry, given the key and only the Hash of the parent Document. This is for
+    /// callers that track documents by hash and don't have the parent's content on hand, as
+    /// decoding an entry never needs it. Otherwise, this works exactly like
+    /// [`decode_entry`][Self::decode_entry].
+    ///
+    /// The parent isn't checked against this schema, so the caller must already know that the
+    /// parent uses it. A wrong parent hash doesn't go unnoticed for long, though: the entry's hash
+    /// covers the parent hash, so the decoded entry's hash won't match the one it was stored under
+    /// and any signature on it will fail to verify.
+    pub fn decode_entry_with_parent_hash(
+        &self,
+        entry: Vec<u8>,
+        key: &str,
+        parent: &Hash,
+    ) -> Result<DataChecklist<Entry>> {
+        let (checklist, pending) = self.decode_entry_parts_unverified(entry, key, parent)?;
+        verify_pending(pending)?;
+        Ok(checklist)
     }
 
-    /// Decode an entry, given the key and parent Hash. Result is in a [`DataChecklist`] that must
-    /// be iterated over in order to finish verification and get the resulting Entry.
-    pub fn decode_entry(
+    // Decode and validate an entry, leaving its signature to be verified by the caller.
+    fn decode_entry_unverified(
         &self,
         entry: Vec<u8>,
         key: &str,
         parent: &Document,
-    ) -> Result<DataChecklist<Entry>> {
+    ) -> Result<(DataChecklist<Entry>, Option<PendingSignature>)> {
         // Check that the entry's parent document uses this schema
         match parent.schema_hash() {
             Some(hash) if hash == &self.hash => (),
@@ -640,34 +2738,92 @@ impl Schema {
                 })
             }
         }
+        self.decode_entry_parts_unverified(entry, key, parent.hash())
+    }
 
+    // Decode and validate an entry with a parent assumed to use this schema, leaving its signature
+    // to be verified by the caller.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            name = "decode_entry",
+            skip_all,
+            fields(
+                schema = %self.hash,
+                key = %key,
+                parent = %parent,
+                size = entry.len(),
+                hash = tracing::field::Empty
+            )
+        )
+    )]
+    fn decode_entry_parts_unverified(
+        &self,
+        entry: Vec<u8>,
+        key: &str,
+        parent: &Hash,
+    ) -> Result<(DataChecklist<Entry>, Option<PendingSignature>)> {
         // Find the entry
         let entry_schema = self.inner.entries.get(key).ok_or_else(|| {
-            Error::FailValidate(format!("entry key \"{:?}\" is not in schema", key))
+            Error::FailValidate(ValidateError::Other(
+                format!("entry key \"{:?}\" is not in schema", key).into(),
+            ))
         })?;
 
-        // Decompress
-        let entry = Entry::new(
-            decompress_entry(entry, &entry_schema.compress)?,
+        // Decompress, parsing the data as it comes out so malformed data stops decompression early
+        self.limits.check_entry(entry.len())?;
+        let (version, entry) = strip_version(entry, &self.limits.format_version)?;
+        let mut scanner = self.scanner();
+        let (mut entry, pending) = Entry::from_parts_unverified(
+            decompress_entry(
+                entry,
+                &entry_schema.compress,
+                self.compressor(),
+                self.limits.entry_size(),
+                &mut |data| scanner.feed(data),
+            )?,
             key,
             parent,
+            self.hash.clone(),
         )?;
+        entry.set_format_version(version);
+        entry.set_unknown_fields(self.unknown);
+        self.limits.check_entry(entry.size())?;
+        self.policy.check_entry(&entry)?;
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("hash", tracing::field::display(entry.hash()));
 
         // Validate
-        let parser = Parser::new(entry.data());
+        let parser = self.parser(entry.data());
         let checklist = Some(Checklist::new(&self.hash, &self.inner.types));
-        let (parser, checklist) =
-            entry_schema
-                .entry
-                .validate(&self.inner.types, parser, checklist)?;
+        let (parser, checklist) = self.inner.program.validate(
+            entry_schema.root,
+            &entry_schema.entry,
+            &self.inner.types,
+            parser,
+            checklist,
+        )?;
         parser.finish()?;
+        entry.set_validated();
 
-        Ok(DataChecklist::from_checklist(checklist.unwrap(), entry))
+        Ok((
+            DataChecklist::from_checklist(checklist.unwrap(), entry),
+            pending,
+        ))
     }
 
     /// Decode a Entry, skipping most checks of the data. This should only be run when the raw
     /// entry has definitely been passed through validation before, i.e. if it is stored in a
     /// local database after going through [`encode_entry`][Self::encode_entry].
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(schema = %self.hash, key = %key, hash = %entry_hash, size = entry.len())
+        )
+    )]
     pub fn trusted_decode_entry(
         &self,
         entry: Vec<u8>,
@@ -687,16 +2843,27 @@ impl Schema {
         }
         // Find the entry
         let entry_schema = self.inner.entries.get(key).ok_or_else(|| {
-            Error::FailValidate(format!("entry key \"{:?}\" is not in schema", key))
+            Error::FailValidate(ValidateError::Other(
+                format!("entry key \"{:?}\" is not in schema", key).into(),
+            ))
         })?;
 
         // Decompress
-        let entry = Entry::trusted_new(
-            decompress_entry(entry, &entry_schema.compress)?,
+        let (version, entry) = strip_version(entry, &VersionPolicy::Supported)?;
+        let mut entry = Entry::trusted_new(
+            decompress_entry(
+                entry,
+                &entry_schema.compress,
+                self.compressor(),
+                MAX_ENTRY_SIZE,
+                &mut |_| Ok(()),
+            )?,
             key,
             parent,
             entry_hash,
         )?;
+        entry.set_format_version(version);
+        entry.set_unknown_fields(self.unknown);
         Ok(entry)
     }
 
@@ -709,15 +2876,21 @@ impl Schema {
     pub fn encode_query(&self, query: NewQuery) -> Result<Vec<u8>> {
         let key = query.key();
         let entry_schema = self.inner.entries.get(key).ok_or_else(|| {
-            Error::FailValidate(format!("entry key \"{:?}\" is not in schema", key))
+            Error::FailValidate(ValidateError::Other(
+                format!("entry key \"{:?}\" is not in schema", key).into(),
+            ))
         })?;
         if entry_schema
             .entry
             .query_check(&self.inner.types, query.validator())
         {
-            query.complete(self.inner.max_regex)
+            let query = query.complete(self.inner.max_regex)?;
+            self.limits.check_query(query.len())?;
+            Ok(query)
         } else {
-            Err(Error::FailValidate("Query is not allowed by schema".into()))
+            Err(Error::FailValidate(ValidateError::Other(
+                "Query is not allowed by schema".into(),
+            )))
         }
     }
 
@@ -726,13 +2899,23 @@ impl Schema {
     /// that isn't in the schema, or if the query isn't a valid one according to
     /// the various query permissions in the schema's validators.
     ///
+    /// The decoded query carries this schema's hash and stored types with it.
+    /// The schema hash is part of its [`QueryId`][crate::query::QueryId], so
+    /// the same encoded query decoded by two different schemas gives two
+    /// queries that aren't equal, and the types are what lets it resolve
+    /// [`Validator::Ref`] validators when run.
+    ///
     /// Queries are encoded like fog-pack documents, but without the header
     /// containing compression and schema info.
     pub fn decode_query(&self, query: Vec<u8>) -> Result<Query> {
-        let query = Query::new(query, self.inner.max_regex)?;
+        self.limits.check_query(query.len())?;
+        let query =
+            Query::new(query, self.inner.max_regex)?.with_schema(&self.hash, &self.inner.types);
         let key = query.key();
         let entry_schema = self.inner.entries.get(key).ok_or_else(|| {
-            Error::FailValidate(format!("entry key \"{:?}\" is not in schema", key))
+            Error::FailValidate(ValidateError::Other(
+                format!("entry key \"{:?}\" is not in schema", key).into(),
+            ))
         })?;
         if entry_schema
             .entry
@@ -740,7 +2923,1644 @@ impl Schema {
         {
             Ok(query)
         } else {
-            Err(Error::FailValidate("Query is not allowed by schema".into()))
+            Err(Error::FailValidate(ValidateError::Other(
+                "Query is not allowed by schema".into(),
+            )))
+        }
+    }
+}
+
+/// A [`Schema`] that checks data, but not signatures, for use in closed pipelines.
+///
+/// Decoding through a `TrustedSchema` runs the same format, size, and validation checks as the
+/// wrapped schema, but never verifies signatures, which is usually the most expensive part of
+/// decoding. This suits pipelines that only pass data between their own trusted stages and need
+/// the canonical format and validation, but not proof of who signed what. Signers are still
+/// parsed, and are only claims: decoded documents and entries report that they aren't
+/// [verified][Document::is_verified], and their signatures are verified if they are re-encoded
+/// through a `Schema`.
+///
+/// Hashes are still computed, as documents and entries are identified and linked by them, so the
+/// crypto crate is always needed. Pipelines built around a `TrustedSchema` usually don't need to
+/// make keys, though, and can turn off the default `getrandom` feature to shrink their dependency
+/// tree.
+///
+/// ```
+/// # use fog_pack::{document::*, schema::*, types::*, validator::*};
+/// # fn main() -> fog_pack::error::Result<()> {
+/// let schema_doc = SchemaBuilder::new(StrValidator::new().build()).build()?;
+/// let schema = Schema::from_doc(&schema_doc)?;
+/// let doc = NewDocument::new(Some(schema.hash()), "data")?.sign(&IdentityKey::new())?;
+/// let (_, encoded) = schema.encode_doc(schema.validate_new_doc(doc)?)?;
+///
+/// let trusted = TrustedSchema::new(schema);
+/// let doc = trusted.decode_doc(encoded)?;
+/// assert!(!doc.is_verified());
+/// assert_eq!(doc.deserialize::<&str>()?, "data");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct TrustedSchema(Schema);
+
+impl TrustedSchema {
+    /// Skip signature verification when decoding with the given schema.
+    pub fn new(schema: Schema) -> Self {
+        Self(schema)
+    }
+
+    /// Get the wrapped schema, for encoding or any other use.
+    pub fn schema(&self) -> &Schema {
+        &self.0
+    }
+
+    /// Get the wrapped schema back.
+    pub fn into_schema(self) -> Schema {
+        self.0
+    }
+
+    /// Decode a document that uses this schema, without verifying its signatures. See
+    /// [`Schema::decode_doc`].
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            name = "trusted_schema_decode_doc",
+            skip_all,
+            fields(schema = %self.0.hash, size = doc.len(), hash = tracing::field::Empty)
+        )
+    )]
+    pub fn decode_doc(&self, doc: Vec<u8>) -> Result<Document> {
+        let (mut doc, pending) = self.0.decode_doc_unverified(doc)?;
+        doc.set_verified(pending.is_empty());
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("hash", tracing::field::display(doc.hash()));
+        Ok(doc)
+    }
+
+    /// Decode an entry with the given key and parent document, without verifying its signature.
+    /// See [`Schema::decode_entry`].
+    pub fn decode_entry(
+        &self,
+        entry: Vec<u8>,
+        key: &str,
+        parent: &Document,
+    ) -> Result<DataChecklist<Entry>> {
+        let (mut checklist, pending) = self.0.decode_entry_unverified(entry, key, parent)?;
+        checklist.data_mut().set_verified(pending.is_none());
+        Ok(checklist)
+    }
+}
+
+static DICTIONARY_SCHEMA: OnceLock<Schema> = OnceLock::new();
+
+/// Get the standard schema for dictionary documents.
+///
+/// Dictionary documents hold a single compression [`Dictionary`], which lets dictionaries be
+/// content-addressed and distributed like any other document. They can be made with
+/// [`dictionary_doc`] and loaded into a schema with [`Schema::attach_dictionary`] or
+/// [`Schema::attach_entry_dictionary`].
+pub fn dictionary_schema() -> &'static Schema {
+    DICTIONARY_SCHEMA.get_or_init(|| {
+        use crate::validator::{BinValidator, IntValidator};
+        let byte = || IntValidator::new().min(0u8).max(u8::MAX).build();
+        let doc = SchemaBuilder::new(
+            MapValidator::new()
+                .req_add("algorithm", byte())
+                .req_add("dict", BinValidator::new().build())
+                .req_add("level", byte())
+                .build(),
+        )
+        .name("fog-pack dictionary")
+        .description("A compression dictionary for fog-pack documents and entries")
+        .doc_compress(Compress::None)
+        .build()
+        .expect("the dictionary schema should always be valid");
+        Schema::from_doc(&doc).expect("the dictionary schema should always be valid")
+    })
+}
+
+/// Create a dictionary document, which adheres to the [`dictionary_schema`].
+pub fn dictionary_doc(dict: &Dictionary) -> Result<Document> {
+    let schema = dictionary_schema();
+    let doc = NewDocument::new(Some(schema.hash()), dict)?;
+    schema.validate_new_doc(doc)
+}
+
+fn dictionary_from_doc(doc: &Document) -> Result<Dictionary> {
+    let schema = dictionary_schema();
+    match doc.schema_hash() {
+        Some(hash) if hash == schema.hash() => (),
+        actual => {
+            return Err(Error::SchemaMismatch {
+                actual: actual.cloned(),
+                expected: Some(schema.hash().clone()),
+            })
+        }
+    }
+    doc.deserialize()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::validator::*;
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn dictionary_documents() {
+        let dict: Vec<u8> = b"a commonly repeated phrase, for the dictionary to pick up. "
+            .iter()
+            .cycle()
+            .take(4096)
+            .copied()
+            .collect();
+        let dict_doc = dictionary_doc(&Dictionary::new_zstd(3, dict)).unwrap();
+        assert_eq!(dict_doc.schema_hash(), Some(dictionary_schema().hash()));
+
+        let schema_doc = SchemaBuilder::new(Validator::Any).build().unwrap();
+        let plain = Schema::from_doc(&schema_doc).unwrap();
+        let mut schema = plain.clone();
+        schema.attach_dictionary(&dict_doc).unwrap();
+
+        let data = "a commonly repeated phrase, for the dictionary to pick up. And a twist.";
+        let doc = NewDocument::new(Some(schema.hash()), data).unwrap();
+        let doc = schema.validate_new_doc(doc).unwrap();
+        let (hash, encoded, stats) = schema.encode_doc_with_stats(doc).unwrap();
+        assert!(stats.dictionary);
+
+        // Decoding requires the dictionary
+        plain.decode_doc(encoded.clone()).unwrap_err();
+        let doc = schema.decode_doc(encoded).unwrap();
+        assert_eq!(doc.hash(), &hash);
+        let decoded: &str = doc.deserialize().unwrap();
+        assert_eq!(decoded, data);
+
+        // Only dictionary documents can be attached
+        let mut other = plain.clone();
+        other.attach_dictionary(&schema_doc).unwrap_err();
+        other
+            .attach_entry_dictionary("missing", &dict_doc)
+            .unwrap_err();
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn compress_threshold() {
+        let data = "a".repeat(60);
+        let compressed = |schema: &Schema, doc: NewDocument| {
+            let doc = schema.validate_new_doc(doc).unwrap();
+            let (_, encoded, stats) = schema.encode_doc_with_stats(doc).unwrap();
+            schema.decode_doc(encoded).unwrap();
+            stats.compressed
+        };
+
+        let schema_doc = SchemaBuilder::new(Validator::Any).build().unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        assert_eq!(schema.compress_threshold(), DEFAULT_COMPRESS_THRESHOLD);
+        let doc = NewDocument::new(Some(schema.hash()), &data).unwrap();
+        assert!(!compressed(&schema, doc.clone()));
+        // Explicitly setting the compression level skips the threshold
+        assert!(compressed(&schema, doc.compression(Some(3))));
+
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .compress_threshold(0)
+            .entry_add("e", Validator::Any, None)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        assert_eq!(schema.compress_threshold(), 0);
+        let doc = NewDocument::new(Some(schema.hash()), &data).unwrap();
+        assert!(compressed(&schema, doc.clone()));
+
+        let parent = schema.validate_new_doc(doc).unwrap();
+        let entry = NewEntry::new("e", &parent, &data).unwrap();
+        let entry = schema
+            .validate_new_entry(entry)
+            .unwrap()
+            .complete()
+            .unwrap();
+        let (_, _, _, stats) = schema.encode_entry_with_stats(entry).unwrap();
+        assert!(stats.compressed);
+
+        // Schemaless documents use the default threshold
+        let doc = NoSchema::validate_new_doc(NewDocument::new(None, &data).unwrap()).unwrap();
+        let (_, encoded) = NoSchema::encode_doc(doc).unwrap();
+        assert_eq!(encoded[0], u8::from(CompressType::None));
+    }
+
+    #[test]
+    fn limits() {
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .entry_add("e", Validator::Any, None)
+            .build()
+            .unwrap();
+        let open = Schema::from_doc(&schema_doc).unwrap();
+        assert_eq!(open.limits(), &Limits::default());
+        let strict = open.clone().with_limits(Limits {
+            max_depth: 2,
+            max_doc_size: 256,
+            max_entry_size: 128,
+            ..Limits::default()
+        });
+        assert_eq!(strict.hash(), open.hash());
+
+        // Nesting depth
+        let nested = vec![vec![vec![1u8]]];
+        let doc = NewDocument::new(Some(open.hash()), &nested).unwrap();
+        assert!(strict.validate_new_doc(doc.clone()).is_err());
+        let doc = open.validate_new_doc(doc).unwrap();
+        let (_, encoded) = open.encode_doc(doc).unwrap();
+        assert!(strict.decode_doc(encoded).is_err());
+
+        // Document size
+        let doc = NewDocument::new(Some(open.hash()), "a".repeat(300)).unwrap();
+        assert!(matches!(
+            strict.validate_new_doc(doc.clone()),
+            Err(Error::LengthTooLong { max: 256, .. })
+        ));
+        let doc = open.validate_new_doc(doc).unwrap();
+        let (_, encoded) = open.encode_doc(doc).unwrap();
+        assert!(strict.decode_doc(encoded).is_err());
+        let doc = NewDocument::new(Some(open.hash()), "a".repeat(200)).unwrap();
+        let parent = strict.validate_new_doc(doc).unwrap();
+
+        // Entry size
+        let entry = NewEntry::new("e", &parent, "a".repeat(150)).unwrap();
+        assert!(strict.validate_new_entry(entry.clone()).is_err());
+        let entry = open.validate_new_entry(entry).unwrap().complete().unwrap();
+        let (_, encoded, _) = open.encode_entry(entry).unwrap();
+        let err = strict.decode_entry(encoded, "e", &parent).unwrap_err();
+        // Compressed data is stopped during decompression, as soon as it passes the limit
+        #[cfg(feature = "zstd")]
+        assert!(matches!(err, Error::FailDecompress(_)));
+        #[cfg(not(feature = "zstd"))]
+        assert!(matches!(err, Error::LengthTooLong { .. }));
+
+        // Limits above the format maximums are capped
+        let doc = NewDocument::new(None, &nested).unwrap();
+        let doc = NoSchema::validate_new_doc(doc).unwrap();
+        let (_, encoded) = NoSchema::encode_doc(doc).unwrap();
+        let loose = Limits {
+            max_depth: usize::MAX,
+            ..Limits::default()
+        };
+        assert_eq!(loose.depth(), MAX_DEPTH);
+        NoSchema::decode_doc_with_limits(encoded.clone(), &loose).unwrap();
+        let tight = Limits {
+            max_depth: 1,
+            ..Limits::default()
+        };
+        assert!(NoSchema::decode_doc_with_limits(encoded, &tight).is_err());
+    }
+
+    #[test]
+    fn format_version() {
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .entry_add("e", Validator::Any, None)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let doc = schema
+            .validate_new_doc(NewDocument::new(Some(schema.hash()), 1u8).unwrap())
+            .unwrap();
+        assert_eq!(doc.format_version(), FORMAT_VERSION);
+        let entry = NewEntry::new("e", &doc, 2u8).unwrap();
+        let entry = schema
+            .validate_new_entry(entry)
+            .unwrap()
+            .complete()
+            .unwrap();
+        let (entry_ref, entry, _) = schema.encode_entry(entry).unwrap();
+        let entry_hash = entry_ref.hash;
+        let (_, encoded) = schema.encode_doc(doc.clone()).unwrap();
+
+        // Version 0 can be written without a version byte, or with an explicit one
+        let mut explicit = vec![0b11];
+        explicit.extend_from_slice(&encoded);
+        assert_eq!(
+            get_doc_schema(&explicit).unwrap().as_ref(),
+            Some(schema.hash())
+        );
+        let decoded = schema.decode_doc(explicit.clone()).unwrap();
+        assert_eq!(decoded.hash(), doc.hash());
+        assert_eq!(decoded.format_version(), 0);
+        schema.trusted_decode_doc(explicit.clone()).unwrap();
+        let mut explicit_entry = vec![0b11];
+        explicit_entry.extend_from_slice(&entry);
+        let decoded = schema
+            .decode_entry(explicit_entry.clone(), "e", &doc)
+            .unwrap()
+            .complete()
+            .unwrap();
+        assert_eq!(decoded.hash(), &entry_hash);
+        schema
+            .trusted_decode_entry(explicit_entry, "e", &doc, &entry_hash)
+            .unwrap();
+
+        // Versions from the future can't be read at all
+        let mut future = vec![(1 << 2) | 0b11];
+        future.extend_from_slice(&encoded);
+        assert!(matches!(
+            schema.decode_doc(future.clone()),
+            Err(Error::BadHeader(_))
+        ));
+        assert!(matches!(
+            schema.trusted_decode_doc(future),
+            Err(Error::BadHeader(_))
+        ));
+
+        // Policies can narrow down what's accepted
+        let exact = schema.clone().with_limits(Limits {
+            format_version: VersionPolicy::Exactly(0),
+            ..Limits::default()
+        });
+        exact.decode_doc(explicit.clone()).unwrap();
+        assert!(VersionPolicy::AtMost(0).check(0).is_ok());
+        assert!(matches!(
+            VersionPolicy::Exactly(1).check(0),
+            Err(Error::OldVersion(_))
+        ));
+        let no_schema = Limits {
+            format_version: VersionPolicy::AtMost(0),
+            ..Limits::default()
+        };
+        let (_, plain) = NoSchema::encode_doc(
+            NoSchema::validate_new_doc(NewDocument::new(None, 3u8).unwrap()).unwrap(),
+        )
+        .unwrap();
+        NoSchema::decode_doc_with_limits(plain, &no_schema).unwrap();
+    }
+
+    #[test]
+    fn batch_decode() {
+        let key = fog_crypto::identity::IdentityKey::new();
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .entry_add("e", Validator::Any, None)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+
+        let doc = NewDocument::new(Some(schema.hash()), "parent").unwrap();
+        let parent = schema.validate_new_doc(doc.sign(&key).unwrap()).unwrap();
+        let (_, parent_enc) = schema.encode_doc(parent.clone()).unwrap();
+
+        let doc = NewDocument::new(None, "plain").unwrap().compression(None);
+        let plain = NoSchema::validate_new_doc(doc.sign(&key).unwrap()).unwrap();
+        let (_, plain_enc) = NoSchema::encode_doc(plain).unwrap();
+        let mut tampered = plain_enc.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+
+        let entry = NewEntry::new("e", &parent, 5u8).unwrap();
+        let entry = entry.compression(None).sign(&key).unwrap();
+        let entry = schema
+            .validate_new_entry(entry)
+            .unwrap()
+            .complete()
+            .unwrap();
+        let (_, entry_enc, _) = schema.encode_entry(entry).unwrap();
+
+        let results = decode_batch([
+            BatchItem::Doc {
+                schema: Some(&schema),
+                doc: parent_enc,
+            },
+            BatchItem::Doc {
+                schema: None,
+                doc: tampered,
+            },
+            BatchItem::Entry {
+                schema: &schema,
+                entry: entry_enc.clone(),
+                key: "e",
+                parent: &parent,
+            },
+            BatchItem::Entry {
+                schema: &schema,
+                entry: entry_enc,
+                key: "missing",
+                parent: &parent,
+            },
+            BatchItem::Doc {
+                schema: None,
+                doc: plain_enc,
+            },
+        ]);
+        assert_eq!(results.len(), 5);
+        let mut results = results.into_iter();
+        match results.next().unwrap().unwrap() {
+            BatchDecoded::Doc(doc) => assert_eq!(doc.hash(), parent.hash()),
+            BatchDecoded::Entry(_) => panic!("expected a document"),
+        }
+        assert!(matches!(
+            results.next().unwrap(),
+            Err(Error::CryptoError(_))
+        ));
+        match results.next().unwrap().unwrap() {
+            BatchDecoded::Entry(checklist) => {
+                let entry = checklist.complete().unwrap();
+                assert_eq!(entry.signer(), Some(key.id()));
+            }
+            BatchDecoded::Doc(_) => panic!("expected an entry"),
+        }
+        assert!(matches!(
+            results.next().unwrap(),
+            Err(Error::FailValidate(_))
+        ));
+        match results.next().unwrap().unwrap() {
+            BatchDecoded::Doc(doc) => assert_eq!(doc.signer(), Some(key.id())),
+            BatchDecoded::Entry(_) => panic!("expected a document"),
+        }
+    }
+
+    #[test]
+    fn encoder_reuse() {
+        #[derive(Serialize)]
+        struct Unordered {
+            z: u8,
+            b: Vec<String>,
+            a: std::collections::HashMap<String, u32>,
+        }
+        let data = |i: u8| Unordered {
+            z: i,
+            b: vec!["repeated text, repeated text, repeated text".into(); 8],
+            a: (0..8)
+                .map(|n| (format!("key{}", n), n * i as u32))
+                .collect(),
+        };
+
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .entry_add("e", Validator::Any, None)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let parent = NewDocument::new(Some(schema.hash()), "parent").unwrap();
+        let parent = schema.validate_new_doc(parent).unwrap();
+
+        let mut encoder = Encoder::new();
+        for i in 0..4 {
+            let doc = NewDocument::new(None, data(i)).unwrap();
+            let expected = NoSchema::encode_doc(NoSchema::validate_new_doc(doc).unwrap()).unwrap();
+            let doc = encoder.new_doc(None, data(i)).unwrap();
+            let actual = encoder
+                .encode_doc(None, NoSchema::validate_new_doc(doc).unwrap())
+                .unwrap();
+            assert_eq!(actual, expected);
+
+            let doc = NewDocument::new(Some(schema.hash()), data(i)).unwrap();
+            let expected = schema
+                .encode_doc(schema.validate_new_doc(doc).unwrap())
+                .unwrap();
+            let doc = encoder.new_doc(Some(schema.hash()), data(i)).unwrap();
+            let actual = encoder
+                .encode_doc(Some(&schema), schema.validate_new_doc(doc).unwrap())
+                .unwrap();
+            assert_eq!(actual, expected);
+
+            let entry = NewEntry::new("e", &parent, data(i)).unwrap();
+            let entry = schema
+                .validate_new_entry(entry)
+                .unwrap()
+                .complete()
+                .unwrap();
+            let expected = schema.encode_entry(entry).unwrap();
+            let entry = encoder.new_entry("e", &parent, data(i)).unwrap();
+            let entry = schema
+                .validate_new_entry(entry)
+                .unwrap()
+                .complete()
+                .unwrap();
+            let actual = encoder.encode_entry(&schema, entry).unwrap();
+            assert_eq!(actual.1, expected.1);
+            assert_eq!(actual.0.hash, expected.0.hash);
+        }
+
+        // Documents with the wrong schema are still rejected
+        let doc = NoSchema::validate_new_doc(encoder.new_doc(None, data(0)).unwrap()).unwrap();
+        encoder.encode_doc(Some(&schema), doc).unwrap_err();
+    }
+
+    #[test]
+    fn async_encoding() {
+        use futures_executor::block_on;
+
+        let schema_doc = SchemaBuilder::new(Validator::Any).build().unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let data: Vec<u32> = (0..40_000).collect();
+        let doc = NewDocument::new(Some(schema.hash()), &data).unwrap();
+        let doc = schema.validate_new_doc(doc).unwrap();
+        assert!(doc.data().len() >= OFFLOAD_THRESHOLD);
+        let expected = schema.encode_doc(doc.clone()).unwrap();
+
+        // Offloaded to a new thread
+        let actual = block_on(schema.encode_doc_async(doc.clone())).unwrap();
+        assert_eq!(actual, expected);
+
+        // A pool that drops the job leaves the work to the awaiting task
+        let actual = block_on(schema.encode_doc_async_with(doc.clone(), drop)).unwrap();
+        assert_eq!(actual, expected);
+
+        // Wrong schemas still fail
+        let other_doc = SchemaBuilder::new(Validator::Null).build().unwrap();
+        let other = Schema::from_doc(&other_doc).unwrap();
+        block_on(other.encode_doc_async(doc)).unwrap_err();
+
+        // Small documents never reach the pool
+        let doc = NoSchema::validate_new_doc(NewDocument::new(None, "small").unwrap()).unwrap();
+        let expected = NoSchema::encode_doc(doc.clone()).unwrap();
+        let actual = block_on(NoSchema::encode_doc_async_with(doc, |_| {
+            panic!("small documents shouldn't be offloaded")
+        }))
+        .unwrap();
+        assert_eq!(actual, expected);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_entry_validation() {
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .entry_add("e", IntValidator::new().max(10u8).build(), None)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let doc = NewDocument::new(Some(schema.hash()), "parent").unwrap();
+        let parent = schema.validate_new_doc(doc).unwrap();
+
+        let entries = (0u8..16)
+            .map(|i| NewEntry::new("e", &parent, i).unwrap())
+            .collect();
+        let results = schema.validate_entries_parallel(entries);
+        assert_eq!(results.len(), 16);
+        for (i, result) in results.into_iter().enumerate() {
+            if i <= 10 {
+                let entry = result.unwrap().complete().unwrap();
+                assert_eq!(entry.deserialize::<u8>().unwrap() as usize, i);
+            } else {
+                assert!(matches!(result, Err(Error::FailValidate(_))));
+            }
+        }
+    }
+
+    #[test]
+    fn entry_keys() {
+        let builder = SchemaBuilder::new(Validator::Any)
+            .entry_keys(StrValidator::new().matches(regex::Regex::new(r"^(chat|meta)\.").unwrap()))
+            .entry_add("chat.msg", Validator::Any, None);
+        let schema_doc = builder.clone().build().unwrap();
+        Schema::from_doc(&schema_doc).unwrap();
+        // The rule's regex counts against the limit
+        Schema::from_doc_max_regex(&schema_doc, 0).unwrap_err();
+        Schema::from_doc_max_regex(&schema_doc, 1).unwrap();
+
+        for key in ["other", "chat", "sys.chat.msg"] {
+            let builder = builder.clone().entry_add(key, Validator::Any, None);
+            assert!(
+                builder.clone().build().is_err(),
+                "{:?} should be rejected",
+                key
+            );
+            // Schema documents made without the builder are checked too
+            let doc =
+                NoSchema::validate_new_doc(NewDocument::new(None, builder.inner).unwrap()).unwrap();
+            assert!(
+                Schema::from_doc(&doc).is_err(),
+                "{:?} should be rejected",
+                key
+            );
+        }
+
+        // Reserved keys are rejected even without a rule
+        SchemaBuilder::new(Validator::Any)
+            .entry_add("sys.log", Validator::Any, None)
+            .build()
+            .unwrap_err();
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .entry_add("log", Validator::Any, None)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let parent = NewDocument::new(Some(schema.hash()), "parent").unwrap();
+        let parent = schema.validate_new_doc(parent).unwrap();
+        NewEntry::new("log", &parent, 1).unwrap();
+        NewEntry::new("sys.log", &parent, 1).unwrap_err();
+    }
+
+    #[test]
+    fn warm() {
+        let dict: Vec<u8> = b"some dictionary data".repeat(64);
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .doc_compress(Compress::new_zstd_dict(3, dict))
+            .entry_add("e", Validator::Any, Some(Compress::new_zstd_general(19)))
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        schema.warm();
+
+        // Reused contexts must not carry anything between calls
+        let data = "some dictionary data, but more of it".repeat(20);
+        let parent = NewDocument::new(Some(schema.hash()), &data).unwrap();
+        let parent = schema.validate_new_doc(parent).unwrap();
+        for _ in 0..3 {
+            let (_, encoded) = schema.encode_doc(parent.clone()).unwrap();
+            assert_eq!(schema.decode_doc(encoded).unwrap().hash(), parent.hash());
+            let entry = NewEntry::new("e", &parent, &data).unwrap();
+            let entry = schema
+                .validate_new_entry(entry)
+                .unwrap()
+                .complete()
+                .unwrap();
+            let (_, encoded, _) = schema.encode_entry(entry.clone()).unwrap();
+            let decoded = schema
+                .decode_entry(encoded, "e", &parent)
+                .unwrap()
+                .complete()
+                .unwrap();
+            assert_eq!(decoded.hash(), entry.hash());
+        }
+    }
+
+    #[test]
+    fn semantic_hash() {
+        use crate::{
+            fog_value,
+            types::{IdentityKey, Timestamp, Value},
+        };
+        let sent = Path::new().key("sent");
+        let builder = SchemaBuilder::new(Validator::Any)
+            .entry_add("post", Validator::Any, None)
+            .entry_add("note", Validator::Any, None)
+            .volatile_field("post", &sent);
+        let schema = Schema::from_doc(&builder.clone().build().unwrap()).unwrap();
+        assert_eq!(schema.volatile_fields("post"), vec![sent.clone()]);
+        assert!(schema.volatile_fields("note").is_empty());
+        let bad = builder.volatile_field("missing", &sent).build().unwrap();
+        Schema::from_doc(&bad).unwrap_err();
+
+        let parent = NewDocument::new(Some(schema.hash()), "parent").unwrap();
+        let parent = schema.validate_new_doc(parent).unwrap();
+        let entry = |key: &str, data: Value, signer: Option<&IdentityKey>| {
+            let mut entry = NewEntry::new(key, &parent, data).unwrap();
+            if let Some(signer) = signer {
+                entry = entry.sign(signer).unwrap();
+            }
+            schema
+                .validate_new_entry(entry)
+                .unwrap()
+                .complete()
+                .unwrap()
+        };
+        let post = |text: &str, sent: i64| fog_value!({"text": text, "sent": Timestamp::from_tai_secs(sent)});
+        let key = IdentityKey::new();
+        let first = entry("post", post("hi", 1), None);
+        let resent = entry("post", post("hi", 2), Some(&key));
+        let other = entry("post", post("bye", 1), None);
+        let note = entry("note", post("hi", 1), None);
+        let note_resent = entry("note", post("hi", 2), None);
+        assert_ne!(first.hash(), resent.hash());
+        let hash = |entry: &Entry| schema.semantic_hash(entry).unwrap();
+        assert_eq!(hash(&first), hash(&resent));
+        assert_ne!(hash(&first), hash(&other));
+        // Only the fields marked for that entry key are left out
+        assert_ne!(hash(&first), hash(&note));
+        assert_ne!(hash(&note), hash(&note_resent));
+
+        let mut entries = vec![first.clone(), other.clone(), resent, note.clone()];
+        schema.dedup_entries(&mut entries).unwrap();
+        let hashes: Vec<&Hash> = entries.iter().map(|e| e.hash()).collect();
+        assert_eq!(hashes, vec![first.hash(), other.hash(), note.hash()]);
+
+        // Later array elements are removed first, so earlier removals don't shift them
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .entry_add("list", Validator::Any, None)
+            .volatile_field("list", &Path::new().key("a").index(0))
+            .volatile_field("list", &Path::new().key("a").index(2))
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let parent = NewDocument::new(Some(schema.hash()), "parent").unwrap();
+        let parent = schema.validate_new_doc(parent).unwrap();
+        let hash = |data: Value| {
+            let entry = NewEntry::new("list", &parent, data).unwrap();
+            let entry = schema
+                .validate_new_entry(entry)
+                .unwrap()
+                .complete()
+                .unwrap();
+            schema.semantic_hash(&entry).unwrap()
+        };
+        let base = hash(fog_value!({"a": [10u8, 1u8, 20u8, 3u8]}));
+        assert_eq!(base, hash(fog_value!({"a": [11u8, 1u8, 21u8, 3u8]})));
+        assert_ne!(base, hash(fog_value!({"a": [10u8, 2u8, 20u8, 3u8]})));
+        assert_ne!(base, hash(fog_value!({"a": [10u8, 1u8, 20u8, 4u8]})));
+    }
+
+    #[test]
+    fn custom_compressor() {
+        // Run-length encodes data that is a single repeated byte
+        struct Repeat;
+        impl Compressor for Repeat {
+            fn algorithm(&self) -> u8 {
+                42
+            }
+
+            fn compress(&self, _: u8, mut dest: Vec<u8>, src: &[u8]) -> Option<Vec<u8>> {
+                let (&first, rest) = src.split_first()?;
+                if src.len() <= 5 || rest.iter().any(|&b| b != first) {
+                    return None;
+                }
+                dest.push(first);
+                dest.extend_from_slice(&(src.len() as u32).to_le_bytes());
+                Some(dest)
+            }
+
+            fn decompress(&self, dest: &mut Vec<u8>, src: &[u8], max_len: usize) -> Result<()> {
+                let bad = || Error::FailDecompress("bad repeat".into());
+                let (&byte, len) = src.split_first().ok_or_else(bad)?;
+                let len = u32::from_le_bytes(len.try_into().map_err(|_| bad())?) as usize;
+                if len > max_len {
+                    return Err(bad());
+                }
+                dest.resize(dest.len() + len, byte);
+                Ok(())
+            }
+        }
+
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .doc_compress(Compress::General {
+                algorithm: 42,
+                level: 0,
+            })
+            .compress_threshold(0)
+            .build()
+            .unwrap();
+        let plain = Schema::from_doc(&schema_doc).unwrap();
+        let schema = plain.clone().with_compressor(Arc::new(Repeat));
+        assert_eq!(schema.compressor().map(|c| c.algorithm()), Some(42));
+
+        let data = serde_bytes::ByteBuf::from(vec![7u8; 10_000]);
+        let doc = NewDocument::new(Some(schema.hash()), &data).unwrap();
+        let doc = schema.validate_new_doc(doc).unwrap();
+        let (_, encoded) = schema.encode_doc(doc.clone()).unwrap();
+        assert!(encoded.len() < 100);
+        let decoded = schema.decode_doc(encoded.clone()).unwrap();
+        assert_eq!(decoded.hash(), doc.hash());
+
+        // Without the compressor, the algorithm is unsupported
+        assert!(plain.decode_doc(encoded).is_err());
+        let (_, encoded) = plain.encode_doc(doc).unwrap();
+        assert!(encoded.len() > 10_000);
+    }
+
+    #[test]
+    #[should_panic]
+    fn custom_compressor_out_of_range() {
+        struct Wide;
+        impl Compressor for Wide {
+            fn algorithm(&self) -> u8 {
+                64
+            }
+
+            fn compress(&self, _: u8, _: Vec<u8>, _: &[u8]) -> Option<Vec<u8>> {
+                None
+            }
+
+            fn decompress(&self, _: &mut Vec<u8>, _: &[u8], _: usize) -> Result<()> {
+                Err(Error::FailDecompress("unsupported".into()))
+            }
+        }
+
+        let schema_doc = SchemaBuilder::new(Validator::Any).build().unwrap();
+        let _ = Schema::from_doc(&schema_doc)
+            .unwrap()
+            .with_compressor(Arc::new(Wide));
+    }
+
+    #[test]
+    fn unknown_fields() {
+        use crate::fog_value;
+        #[derive(Deserialize)]
+        struct Old {
+            #[allow(dead_code)]
+            a: u8,
+        }
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .entry_add("post", Validator::Any, None)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc)
+            .unwrap()
+            .with_unknown_fields(UnknownFields::Reject);
+        assert_eq!(schema.unknown_fields(), UnknownFields::Reject);
+        let data = fog_value!({"a": 1u8, "b": 2u8});
+
+        let doc = NewDocument::new(Some(schema.hash()), &data).unwrap();
+        let doc = schema.validate_new_doc(doc).unwrap();
+        assert!(doc.deserialize::<Old>().is_err());
+        let entry = NewEntry::new("post", &doc, &data).unwrap();
+        let entry = schema
+            .validate_new_entry(entry)
+            .unwrap()
+            .complete()
+            .unwrap();
+        assert!(entry.deserialize::<Old>().is_err());
+
+        let (_, encoded) = schema.encode_doc(doc.clone()).unwrap();
+        let decoded = schema.decode_doc(encoded).unwrap();
+        assert_eq!(decoded.unknown_fields(), UnknownFields::Reject);
+        let (_, encoded, _) = schema.encode_entry(entry).unwrap();
+        let decoded = schema
+            .decode_entry(encoded, "post", &doc)
+            .unwrap()
+            .complete()
+            .unwrap();
+        assert_eq!(decoded.unknown_fields(), UnknownFields::Reject);
+
+        // Other copies of the schema are unaffected
+        let lenient = Schema::from_doc(&schema_doc).unwrap();
+        let doc = NewDocument::new(Some(lenient.hash()), &data).unwrap();
+        let (_, encoded) = lenient
+            .encode_doc(lenient.validate_new_doc(doc).unwrap())
+            .unwrap();
+        let decoded = lenient.decode_doc(encoded).unwrap();
+        assert_eq!(decoded.unknown_fields(), UnknownFields::Ignore);
+        assert!(decoded.deserialize::<Old>().is_ok());
+    }
+
+    #[test]
+    fn tombstones() {
+        use crate::types::{IdentityKey, Value};
+        let builder = SchemaBuilder::new(Validator::Any)
+            .entry_add("post", Validator::Any, None)
+            .entry_add("retract", HashValidator::new().build(), None)
+            .tombstone_entry("retract");
+        let schema = Schema::from_doc(&builder.clone().build().unwrap()).unwrap();
+        assert!(schema.is_tombstone("retract"));
+        assert!(!schema.is_tombstone("post"));
+        let bad = builder.clone().tombstone_entry("post").build().unwrap();
+        Schema::from_doc(&bad).unwrap_err();
+        let bad = builder.tombstone_entry("missing").build().unwrap();
+        Schema::from_doc(&bad).unwrap_err();
+
+        let alice = IdentityKey::new();
+        let bob = IdentityKey::new();
+        let parent = NewDocument::new(Some(schema.hash()), "parent").unwrap();
+        let parent = schema.validate_new_doc(parent).unwrap();
+        let entry = |key: &str, data: Value, signer: &IdentityKey| {
+            let entry = NewEntry::new(key, &parent, data)
+                .unwrap()
+                .sign(signer)
+                .unwrap();
+            schema
+                .validate_new_entry(entry)
+                .unwrap()
+                .complete()
+                .unwrap()
+        };
+        let first = entry("post", Value::from(1u8), &alice);
+        let second = entry("post", Value::from(2u8), &alice);
+        let third = entry("post", Value::from(3u8), &bob);
+        let retract_first = entry("retract", Value::Hash(first.hash().clone()), &alice);
+        // Only the signer of an entry can retract it
+        let retract_third = entry("retract", Value::Hash(third.hash().clone()), &alice);
+
+        // Unsigned tombstones can't retract anything, not even unsigned entries
+        let unsigned = |key: &str, data: Value| {
+            let entry = NewEntry::new(key, &parent, data).unwrap();
+            schema
+                .validate_new_entry(entry)
+                .unwrap()
+                .complete()
+                .unwrap()
+        };
+        let fourth = unsigned("post", Value::from(4u8));
+        let retract_fourth = unsigned("retract", Value::Hash(fourth.hash().clone()));
+        let retract_second = unsigned("retract", Value::Hash(second.hash().clone()));
+
+        let results = schema.apply_tombstones(vec![
+            first,
+            retract_first,
+            second.clone(),
+            third.clone(),
+            retract_third,
+            fourth.clone(),
+            retract_fourth,
+            retract_second,
+        ]);
+        let hashes: Vec<&Hash> = results.iter().map(|e| e.hash()).collect();
+        assert_eq!(hashes, vec![second.hash(), third.hash(), fourth.hash()]);
+
+        // Queries carry whether they want retracted entries
+        let query = NewQuery::new("post", Validator::Any);
+        let query = schema
+            .decode_query(schema.encode_query(query.clone()).unwrap())
+            .unwrap();
+        assert!(!query.include_tombstoned());
+        assert_eq!(&query.id().schema, schema.hash());
+        let query = NewQuery::new("post", Validator::Any).include_tombstoned(true);
+        let query = schema
+            .decode_query(schema.encode_query(query).unwrap())
+            .unwrap();
+        assert!(query.include_tombstoned());
+    }
+
+    #[test]
+    fn entry_order() {
+        use crate::{fog_value, types::Timestamp, types::Value};
+        let post = MapValidator::new()
+            .req_add("seq", IntValidator::new().ord(true).build())
+            .req_add("text", StrValidator::new().build())
+            .build();
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .entry_add("post", post, None)
+            .entry_add("event", Validator::new_ref("Event"), None)
+            .entry_add("note", StrValidator::new().build(), None)
+            .type_add(
+                "Event",
+                MapValidator::new()
+                    .req_add("at", TimeValidator::new().ord(true).build())
+                    .build(),
+            )
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        assert_eq!(schema.ord_field("post"), Some(Path::new().key("seq")));
+        assert_eq!(schema.ord_field("event"), Some(Path::new().key("at")));
+        assert_eq!(schema.ord_field("note"), None);
+        assert_eq!(schema.ord_field("missing"), None);
+
+        let parent = NewDocument::new(Some(schema.hash()), "parent").unwrap();
+        let parent = schema.validate_new_doc(parent).unwrap();
+        let entry = |key: &str, data: Value| {
+            let entry = NewEntry::new(key, &parent, data).unwrap();
+            schema
+                .validate_new_entry(entry)
+                .unwrap()
+                .complete()
+                .unwrap()
+        };
+        let a = entry("post", fog_value!({"seq": 2u8, "text": "a"}));
+        let b = entry("post", fog_value!({"seq": 1u8, "text": "b"}));
+        let c = entry("post", fog_value!({"seq": 1u8, "text": "c"}));
+        let d = entry("post", fog_value!({"seq": -4i8, "text": "d"}));
+
+        let mut entries = vec![a.clone(), b.clone(), c.clone(), d.clone()];
+        schema.sort_entries("post", &mut entries).unwrap();
+        let (first, second) = if b.hash().as_ref() < c.hash().as_ref() {
+            (&b, &c)
+        } else {
+            (&c, &b)
+        };
+        let hashes: Vec<&Hash> = entries.iter().map(|e| e.hash()).collect();
+        assert_eq!(
+            hashes,
+            vec![d.hash(), first.hash(), second.hash(), a.hash()]
+        );
+        schema.check_ordered("post", &entries).unwrap();
+        schema.check_ordered("post", &[]).unwrap();
+
+        // Out-of-order, duplicate, and mismatched entries are caught
+        entries.swap(1, 2);
+        assert!(schema.check_ordered("post", &entries).is_err());
+        assert!(schema
+            .check_ordered("post", &[a.clone(), a.clone()])
+            .is_err());
+        let note = entry("note", Value::from("hi"));
+        let mut mixed = vec![a.clone(), note.clone()];
+        assert!(schema.sort_entries("post", &mut mixed).is_err());
+        assert_eq!(mixed.len(), 2);
+        assert!(schema.sort_entries("note", &mut vec![note]).is_err());
+
+        // Stored types are followed
+        let late = entry("event", fog_value!({"at": Timestamp::from_tai_secs(20)}));
+        let early = entry("event", fog_value!({"at": Timestamp::from_tai_secs(10)}));
+        let mut events = vec![late.clone(), early.clone()];
+        schema.sort_entries("event", &mut events).unwrap();
+        assert_eq!(events[0].hash(), early.hash());
+        assert_eq!(events[1].hash(), late.hash());
+    }
+
+    #[test]
+    fn extends() {
+        use std::collections::HashMap;
+        let base_doc = SchemaBuilder::new(
+            MapValidator::new()
+                .req_add("title", StrValidator::new().max_len(255).build())
+                .opt_add("tags", Validator::new_ref("Tags"))
+                .build(),
+        )
+        .type_add(
+            "Tags",
+            ArrayValidator::new()
+                .items(StrValidator::new().build())
+                .build(),
+        )
+        .entry_add("comment", StrValidator::new().build(), None)
+        .build()
+        .unwrap();
+        let base = Schema::from_doc(&base_doc).unwrap();
+        let store: HashMap<Hash, Document> = [(base.hash().clone(), base_doc.clone())].into();
+        let load = |hash: &Hash| -> Result<Option<Document>> { Ok(store.get(hash).cloned()) };
+
+        let child_doc = SchemaBuilder::new(
+            MapValidator::new()
+                .req_add("title", StrValidator::new().max_len(8).build())
+                .req_add("body", StrValidator::new().build())
+                .build(),
+        )
+        .extends(&base)
+        .entry_add("vote", IntValidator::new().build(), None)
+        .build()
+        .unwrap();
+        assert!(Schema::from_doc(&child_doc).is_err());
+        assert!(Schema::from_doc_with_loader(&child_doc, |_| Ok(None)).is_err());
+        let child = Schema::from_doc_with_loader(&child_doc, load).unwrap();
+        assert_eq!(child.parent(), Some(base.hash()));
+        assert!(child.entry_validator("comment").is_some());
+        assert!(child.entry_validator("vote").is_some());
+
+        let valid = |value: crate::types::Value| {
+            let doc = NewDocument::new(Some(child.hash()), value).unwrap();
+            child.validate_new_doc(doc).is_ok()
+        };
+        assert!(valid(crate::fog_value!({"title": "a", "body": "b"})));
+        assert!(valid(
+            crate::fog_value!({"title": "a", "body": "b", "tags": ["x"]})
+        ));
+        assert!(!valid(
+            crate::fog_value!({"title": "a", "body": "b", "tags": [1]})
+        ));
+        assert!(!valid(
+            crate::fog_value!({"title": "too long!", "body": "b"})
+        ));
+        assert!(!valid(crate::fog_value!({"title": "a"})));
+
+        // An `Any` document validator inherits the parent's
+        let same_doc = SchemaBuilder::new(Validator::Any)
+            .extends(&base)
+            .build()
+            .unwrap();
+        let same = Schema::from_doc_with_loader(&same_doc, load).unwrap();
+        assert_eq!(same.doc_validator(), base.doc_validator());
+
+        // Validators can't be loosened
+        let looser = |doc: MapValidator| SchemaBuilder::new(doc.build()).extends(&base).build();
+        assert!(looser(MapValidator::new().req_add("title", StrValidator::new().build())).is_err());
+        assert!(looser(MapValidator::new().opt_add("title", StrValidator::new().build())).is_err());
+        let looser = SchemaBuilder::new(Validator::Any)
+            .extends(&base)
+            .entry_add("comment", IntValidator::new().build(), None)
+            .build();
+        assert!(looser.is_err());
+
+        // Map-wide settings are inherited through references, and can't be loosened either
+        let limited_doc = SchemaBuilder::new(Validator::new_ref("Doc"))
+            .type_add(
+                "Doc",
+                MapValidator::new()
+                    .req_add("title", StrValidator::new().build())
+                    .values(IntValidator::new().build())
+                    .max_len(3)
+                    .build(),
+            )
+            .build()
+            .unwrap();
+        let limited = Schema::from_doc(&limited_doc).unwrap();
+        let store: HashMap<Hash, Document> = [(limited.hash().clone(), limited_doc)].into();
+        let load = |hash: &Hash| -> Result<Option<Document>> { Ok(store.get(hash).cloned()) };
+        let extend = |doc: MapValidator| SchemaBuilder::new(doc.build()).extends(&limited).build();
+        let child_doc = extend(MapValidator::new().opt_add("note", IntValidator::new().build()));
+        let child = Schema::from_doc_with_loader(&child_doc.unwrap(), load).unwrap();
+        let valid = |value: crate::types::Value| {
+            let doc = NewDocument::new(Some(child.hash()), value).unwrap();
+            child.validate_new_doc(doc).is_ok()
+        };
+        assert!(valid(crate::fog_value!({"title": "a", "x": 1, "note": 2})));
+        assert!(!valid(
+            crate::fog_value!({"title": "a", "x": 1, "y": 2, "z": 3})
+        ));
+        assert!(!valid(crate::fog_value!({"title": "a", "x": "str"})));
+        assert!(extend(MapValidator::new().max_len(2)).is_ok());
+        assert!(extend(MapValidator::new().max_len(4)).is_err());
+        assert!(extend(MapValidator::new().values(StrValidator::new().build())).is_err());
+        assert!(extend(MapValidator::new().keys(StrValidator::new().max_len(1))).is_ok());
+    }
+
+    #[test]
+    fn revisions() {
+        use crate::{fog_value, types::Value};
+        let id = Path::new().key("id");
+        let owner = Path::new().key("meta").key("owner");
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .immutable_field(&id)
+            .immutable_field(&owner)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        assert_eq!(schema.immutable_fields(), vec![id, owner]);
+        let doc = |value: Value| {
+            let doc = NewDocument::new(Some(schema.hash()), value).unwrap();
+            schema.validate_new_doc(doc).unwrap()
+        };
+
+        let first = doc(fog_value!({"id": 1u8, "text": "a"}));
+        let second = doc(fog_value!({
+            "id": 1u8,
+            "meta": {"owner": "alice"},
+            "prev": first.hash().clone(),
+            "text": "b",
+        }));
+        schema.check_revision(&first, &second).unwrap();
+        // Revisions must link to the document they replace
+        assert!(schema.check_revision(&second, &second).is_err());
+        let unlinked = doc(fog_value!({"id": 1u8, "text": "c"}));
+        assert!(schema.check_revision(&first, &unlinked).is_err());
+
+        // Immutable fields can't change or be removed once set
+        let changed = doc(fog_value!({
+            "id": 1u8,
+            "meta": {"owner": "bob"},
+            "prev": second.hash().clone(),
+        }));
+        assert!(schema.check_revision(&second, &changed).is_err());
+        let removed = doc(fog_value!({"id": 1u8, "prev": second.hash().clone()}));
+        assert!(schema.check_revision(&second, &removed).is_err());
+        let kept = doc(fog_value!({
+            "id": 1u8,
+            "meta": {"owner": "alice", "tags": []},
+            "prev": second.hash().clone(),
+        }));
+        schema.check_revision(&second, &kept).unwrap();
+
+        let other = NoSchema::validate_new_doc(NewDocument::new(None, "x").unwrap()).unwrap();
+        assert!(matches!(
+            schema.check_revision(&other, &second),
+            Err(Error::SchemaMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn time_precision() {
+        use crate::{fog_value, types::Timestamp, types::Value};
+        let millis = TimeValidator::new().precision(Precision::Millis).build();
+        let schema_doc = SchemaBuilder::new(
+            MapValidator::new()
+                .req_add("at", Validator::new_ref("Time"))
+                .opt_add("log", ArrayValidator::new().items(millis.clone()).build())
+                .opt_add("raw", TimeValidator::new().build())
+                .build(),
+        )
+        .type_add(
+            "Time",
+            TimeValidator::new().precision(Precision::Seconds).build(),
+        )
+        .entry_add("e", millis, None)
+        .build()
+        .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let time = Timestamp::from_tai(100, 123_456_789).unwrap();
+        let secs = Precision::Seconds.truncate(time);
+        let millis = Precision::Millis.truncate(time);
+
+        // Timestamps that are too precise are rejected wherever they are, rather than changed
+        for value in [
+            fog_value!({"at": time}),
+            fog_value!({"at": secs, "log": [millis, time]}),
+        ] {
+            let doc = NewDocument::new(Some(schema.hash()), &value).unwrap();
+            assert!(schema.validate_new_doc(doc).is_err());
+        }
+        let value = fog_value!({"at": secs, "log": [millis, millis], "raw": time});
+        let doc = NewDocument::new(Some(schema.hash()), &value).unwrap();
+        let doc = schema.validate_new_doc(doc).unwrap();
+        assert_eq!(doc.deserialize::<Value>().unwrap(), value);
+
+        let entry = NewEntry::new("e", &doc, Value::Timestamp(time)).unwrap();
+        assert!(schema.validate_new_entry(entry).is_err());
+        let entry = NewEntry::new("e", &doc, Value::Timestamp(millis)).unwrap();
+        schema
+            .validate_new_entry(entry)
+            .unwrap()
+            .complete()
+            .unwrap();
+    }
+
+    #[test]
+    fn unsigned_policy() {
+        use crate::types::IdentityKey;
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .unsigned(true)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        assert!(schema.requires_unsigned());
+        let key = IdentityKey::new();
+
+        let doc = NewDocument::new(Some(schema.hash()), "anonymous").unwrap();
+        let doc = schema.validate_new_doc(doc).unwrap();
+        let (_, encoded) = schema.encode_doc(doc.clone()).unwrap();
+        schema.decode_doc(encoded).unwrap();
+
+        // Signatures are caught when validating, or when encoding if added afterwards
+        let signed = NewDocument::new(Some(schema.hash()), "named")
+            .unwrap()
+            .sign(&key)
+            .unwrap();
+        assert!(schema.validate_new_doc(signed).is_err());
+        assert!(schema.encode_doc(doc.sign(&key).unwrap()).is_err());
+
+        // Can't be both
+        assert!(SchemaBuilder::new(Validator::Any)
+            .unsigned(true)
+            .signature_threshold(1)
+            .build()
+            .is_err());
+        assert!(
+            !Schema::from_doc(&SchemaBuilder::new(Validator::Any).build().unwrap())
+                .unwrap()
+                .requires_unsigned()
+        );
+    }
+
+    #[test]
+    fn verify_policy() {
+        use crate::types::{IdentityKey, TimeDelta};
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .entry_add("note", Validator::Any, None)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let alice = IdentityKey::new();
+        let mallory = IdentityKey::new();
+        let signed_at = Timestamp::from_utc_secs(1_000_000);
+        let encode = |key: &IdentityKey, time: Option<Timestamp>| {
+            let doc = NewDocument::new(Some(schema.hash()), "data").unwrap();
+            let doc = match time {
+                Some(time) => doc.sign_with_time(key, time).unwrap(),
+                None => doc.sign(key).unwrap(),
+            };
+            schema
+                .encode_doc(schema.validate_new_doc(doc).unwrap())
+                .unwrap()
+                .1
+        };
+        let timed = encode(&alice, Some(signed_at));
+        let untimed = encode(&alice, None);
+        let revoked = encode(&mallory, Some(signed_at));
+        let decode = |policy: VerifyPolicy, doc: &Vec<u8>| {
+            schema
+                .clone()
+                .with_verify_policy(policy)
+                .decode_doc(doc.clone())
+        };
+
+        // The default policy accepts everything
+        for doc in [&timed, &untimed, &revoked] {
+            decode(VerifyPolicy::new(), doc).unwrap();
         }
+
+        // Revocation
+        let policy = VerifyPolicy::new().revoke(mallory.id().clone());
+        assert!(decode(policy.clone(), &revoked).is_err());
+        decode(policy.clone(), &timed).unwrap();
+
+        // Age and clock skew
+        let hour = TimeDelta::from_secs(3600);
+        let policy = VerifyPolicy::new().max_age(hour).max_skew(hour);
+        decode(policy.clone().at(signed_at + 60), &timed).unwrap();
+        assert!(decode(policy.clone().at(signed_at + 7200), &timed).is_err());
+        assert!(decode(policy.clone().at(signed_at - 7200), &timed).is_err());
+        assert!(decode(policy.clone().at(signed_at), &untimed).is_err());
+        decode(VerifyPolicy::new().max_skew(hour), &untimed).unwrap();
+
+        // Algorithms
+        let version = alice.id().version();
+        decode(VerifyPolicy::new().algorithm_add(version), &timed).unwrap();
+        let other = version.wrapping_add(1);
+        assert!(decode(VerifyPolicy::new().algorithm_add(other), &timed).is_err());
+
+        // Entries, and documents without a schema
+        let parent = schema.decode_doc(timed.clone()).unwrap();
+        let entry = NewEntry::new("note", &parent, 1u8)
+            .unwrap()
+            .sign(&mallory)
+            .unwrap();
+        let entry = schema
+            .validate_new_entry(entry)
+            .unwrap()
+            .complete()
+            .unwrap();
+        let (_, entry, _) = schema.encode_entry(entry).unwrap();
+        let strict = schema
+            .clone()
+            .with_verify_policy(VerifyPolicy::new().revoke(mallory.id().clone()));
+        assert!(strict.decode_entry(entry.clone(), "note", &parent).is_err());
+        schema.decode_entry(entry, "note", &parent).unwrap();
+        let plain = NoSchema::validate_new_doc(
+            NewDocument::new(None, 1u8).unwrap().sign(&mallory).unwrap(),
+        )
+        .unwrap();
+        let (_, plain) = NoSchema::encode_doc(plain).unwrap();
+        let policy = VerifyPolicy::new().revoke(mallory.id().clone());
+        assert!(NoSchema::decode_doc_with_policy(plain, &Limits::default(), &policy).is_err());
+    }
+
+    #[test]
+    fn introspection() {
+        let doc_validator = MapValidator::new()
+            .req_add("n", Validator::new_ref("Count"))
+            .build();
+        let count = IntValidator::new().min(0u8).build();
+        let schema_doc = SchemaBuilder::new(doc_validator.clone())
+            .type_add("Count", count.clone())
+            .entry_add("b", Validator::Null, None)
+            .entry_add("a", count.clone(), None)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        assert_eq!(schema.doc_validator(), &doc_validator);
+        let entries: Vec<(&str, &Validator)> = schema.entries().collect();
+        assert_eq!(entries, vec![("a", &count), ("b", &Validator::Null)]);
+        assert_eq!(schema.entry_validator("a"), Some(&count));
+        assert_eq!(schema.entry_validator("c"), None);
+        let types: Vec<(&str, &Validator)> = schema.types().collect();
+        assert_eq!(types, vec![("Count", &count)]);
+        assert_eq!(schema.name(), "");
+        assert_eq!(schema.maintainer(), None);
+
+        // Descriptive metadata
+        let maintainer = crate::types::IdentityKey::new().id().clone();
+        let schema_doc = SchemaBuilder::new(doc_validator)
+            .name("Counter")
+            .description("Counts things")
+            .version(3)
+            .maintainer(maintainer.clone())
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        assert_eq!(schema.name(), "Counter");
+        assert_eq!(schema.description(), "Counts things");
+        assert_eq!(schema.version(), &Integer::from(3));
+        assert_eq!(schema.maintainer(), Some(&maintainer));
+    }
+
+    #[test]
+    fn validate_value() {
+        use crate::fog_value;
+        let schema_doc = SchemaBuilder::new(
+            MapValidator::new()
+                .req_add("n", Validator::new_ref("Count"))
+                .opt_add(
+                    "tags",
+                    ArrayValidator::new()
+                        .items(StrValidator::new().max_len(4).build())
+                        .unique(true)
+                        .build(),
+                )
+                .opt_add("maybe", Validator::new_null())
+                .build(),
+        )
+        .type_add("Count", IntValidator::new().max(10).build())
+        .entry_add("e", F64Validator::new().build(), None)
+        .build()
+        .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+
+        let values = [
+            (
+                fog_value!({"n": 5, "tags": ["a", "b"], "maybe": null}),
+                true,
+            ),
+            (fog_value!({"n": 11}), false),
+            (fog_value!({"n": 1, "tags": ["a", "a"]}), false),
+            (fog_value!({"n": 1, "tags": ["longer"]}), false),
+            (fog_value!({"tags": []}), false),
+            (fog_value!([1]), false),
+        ];
+        for (value, valid) in values {
+            // Validating in memory must agree with validating the encoded document
+            let doc = NewDocument::new(Some(schema.hash()), &value).unwrap();
+            assert_eq!(schema.validate_new_doc(doc).is_ok(), valid, "{:?}", value);
+            assert_eq!(
+                schema.validate_value(&value.as_ref()).is_ok(),
+                valid,
+                "{:?}",
+                value
+            );
+        }
+
+        schema
+            .validate_entry_value("e", &Value::F64(1.5).as_ref())
+            .unwrap();
+        schema
+            .validate_entry_value("e", &Value::F32(1.5).as_ref())
+            .unwrap_err();
+        schema
+            .validate_entry_value("missing", &Value::F64(1.5).as_ref())
+            .unwrap_err();
+
+        // Standalone validators have no types to refer to
+        let validator = IntValidator::new().max(10).build();
+        validator
+            .validate_value(&Value::from(3u8).as_ref())
+            .unwrap();
+        validator
+            .validate_value(&Value::from(11u8).as_ref())
+            .unwrap_err();
+        Validator::new_ref("Count")
+            .validate_value(&Value::from(3u8).as_ref())
+            .unwrap_err();
+    }
+
+    #[test]
+    fn array_queries() {
+        use crate::fog_value;
+        let tags = ArrayValidator::new()
+            .items(StrValidator::new().query(true).build())
+            .contains_ok(true)
+            .array(true)
+            .build();
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .entry_add(
+                "post",
+                MapValidator::new()
+                    .req_add("tags", tags)
+                    .map_ok(true)
+                    .build(),
+                None,
+            )
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let parent = schema
+            .validate_new_doc(NewDocument::new(Some(schema.hash()), 0u8).unwrap())
+            .unwrap();
+        let entry = |tags: &[&str]| {
+            let tags = tags.iter().map(|t| Value::from(*t)).collect();
+            let data = fog_value!({ "tags": Value::Array(tags) });
+            let entry = NewEntry::new("post", &parent, &data).unwrap();
+            schema
+                .validate_new_entry(entry)
+                .unwrap()
+                .complete()
+                .unwrap()
+        };
+        let query = |tags: ArrayValidator| {
+            let validator = MapValidator::new().req_add("tags", tags.build()).build();
+            let query = NewQuery::new("post", validator);
+            schema.decode_query(schema.encode_query(query)?)
+        };
+        let matches = |query: &Query, tags: &[&str]| {
+            query.query(&entry(tags)).and_then(|c| c.complete()).is_ok()
+        };
+        let rust = || StrValidator::new().in_add("rust").build();
+
+        // Any position
+        let contains = query(ArrayValidator::new().contains_add(rust())).unwrap();
+        assert!(matches(&contains, &["rust"]));
+        assert!(matches(&contains, &["go", "rust"]));
+        assert!(!matches(&contains, &["go"]));
+        assert!(!matches(&contains, &[]));
+
+        // A specific position
+        let first = query(ArrayValidator::new().prefix_add(rust())).unwrap();
+        assert!(matches(&first, &["rust", "go"]));
+        assert!(!matches(&first, &["go", "rust"]));
+
+        // Both need to be allowed by the schema
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .entry_add(
+                "post",
+                MapValidator::new()
+                    .req_add(
+                        "tags",
+                        ArrayValidator::new()
+                            .items(StrValidator::new().query(true).build())
+                            .build(),
+                    )
+                    .map_ok(true)
+                    .build(),
+                None,
+            )
+            .build()
+            .unwrap();
+        let closed = Schema::from_doc(&schema_doc).unwrap();
+        for tags in [
+            ArrayValidator::new().contains_add(rust()),
+            ArrayValidator::new().prefix_add(rust()),
+        ] {
+            let validator = MapValidator::new().req_add("tags", tags.build()).build();
+            assert!(closed
+                .encode_query(NewQuery::new("post", validator))
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn decode_with_parent_hash() {
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .entry_add("e", IntValidator::new().build(), None)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let parent = schema
+            .validate_new_doc(NewDocument::new(Some(schema.hash()), 0u8).unwrap())
+            .unwrap();
+        let entry = NewEntry::new("e", &parent, 7u8).unwrap();
+        let entry = schema
+            .validate_new_entry(entry)
+            .unwrap()
+            .complete()
+            .unwrap();
+        assert_eq!(entry.parent_hash(), parent.hash());
+        let (entry_ref, encoded, _) = schema.encode_entry(entry).unwrap();
+
+        // Only the parent's hash is needed
+        let decoded = schema
+            .decode_entry_with_parent_hash(encoded.clone(), "e", parent.hash())
+            .unwrap()
+            .complete()
+            .unwrap();
+        assert_eq!(decoded.reference(), &entry_ref);
+        let full = schema
+            .decode_entry(encoded.clone(), "e", &parent)
+            .unwrap()
+            .complete()
+            .unwrap();
+        assert_eq!(decoded.reference(), full.reference());
+
+        // The wrong parent gives a different entry
+        let other = Hash::new(b"not the parent");
+        let decoded = schema
+            .decode_entry_with_parent_hash(encoded, "e", &other)
+            .unwrap()
+            .complete()
+            .unwrap();
+        assert_ne!(decoded.hash(), &entry_ref.hash);
+    }
+
+    #[test]
+    fn trusted_schema() {
+        use crate::types::IdentityKey;
+        let schema_doc = SchemaBuilder::new(StrValidator::new().build())
+            .entry_add("e", IntValidator::new().build(), None)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let key = IdentityKey::new();
+        let doc = NewDocument::new(Some(schema.hash()), "parent")
+            .unwrap()
+            .compression(None)
+            .sign(&key)
+            .unwrap();
+        let parent = schema.validate_new_doc(doc).unwrap();
+        let (_, mut encoded) = schema.encode_doc(parent.clone()).unwrap();
+        let entry = NewEntry::new("e", &parent, 1u8)
+            .unwrap()
+            .compression(None)
+            .sign(&key)
+            .unwrap();
+        let entry = schema
+            .validate_new_entry(entry)
+            .unwrap()
+            .complete()
+            .unwrap();
+        let (_, mut encoded_entry, _) = schema.encode_entry(entry).unwrap();
+        let trusted = TrustedSchema::new(schema.clone());
+
+        // Good signatures can be verified later
+        let entry = trusted
+            .decode_entry(encoded_entry.clone(), "e", &parent)
+            .unwrap()
+            .complete()
+            .unwrap();
+        assert!(!entry.is_verified());
+        assert!(entry.verify().unwrap().is_verified());
+
+        // Bad signatures are only caught by the full schema
+        *encoded.last_mut().unwrap() ^= 1;
+        *encoded_entry.last_mut().unwrap() ^= 1;
+        assert!(schema.decode_doc(encoded.clone()).is_err());
+        assert!(schema
+            .decode_entry(encoded_entry.clone(), "e", &parent)
+            .is_err());
+        let doc = trusted.decode_doc(encoded).unwrap();
+        assert!(!doc.is_verified());
+        assert_eq!(doc.signer(), Some(key.id()));
+        assert!(doc.verify().is_err());
+        let entry = trusted
+            .decode_entry(encoded_entry, "e", &parent)
+            .unwrap()
+            .complete()
+            .unwrap();
+        assert_eq!(entry.deserialize::<u8>().unwrap(), 1);
+        assert!(!entry.is_verified());
+        assert!(schema.encode_entry(entry.clone()).is_err());
+        assert!(entry.verify().is_err());
+
+        // The data is still validated
+        let doc = NewDocument::new(Some(schema.hash()), 1u8).unwrap();
+        let (_, encoded) = schema.encode_doc(Document::from_new(doc)).unwrap();
+        assert!(trusted.decode_doc(encoded).is_err());
+        assert_eq!(trusted.schema().hash(), schema.hash());
     }
 }
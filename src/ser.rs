@@ -9,9 +9,10 @@
 
 use fog_crypto::serde::FOG_TYPE_ENUM;
 use serde::ser::*;
-use std::{collections::BTreeMap, convert::TryFrom, mem};
+use std::{convert::TryFrom, mem};
 
 use crate::marker::ExtType;
+use crate::validator::Normalize;
 use crate::{element::*, MAX_DOC_SIZE};
 
 use crate::error::{Error, Result};
@@ -21,8 +22,30 @@ use crate::depth_tracking::DepthTracker;
 #[derive(Clone, Debug)]
 pub(crate) struct FogSerializer {
     must_be_ordered: bool,
+    raw_floats: bool,
+    normalize: Normalize,
     depth_tracking: DepthTracker,
     pub buf: Vec<u8>,
+    spare: Spare,
+}
+
+/// The most temporary buffers, keys, or lists of each kind a serializer will hold on to for
+/// reuse.
+const MAX_SPARE_BUFFERS: usize = 256;
+
+/// The largest capacity a temporary buffer keeps when held for reuse. Anything larger is shrunk
+/// down, so one huge value doesn't leave the pool holding onto its memory.
+const MAX_SPARE_CAPACITY: usize = MAX_DOC_SIZE;
+
+/// Temporary storage a serializer can reuse instead of allocating: buffers for reordering map
+/// pairs and encoding sequences of unknown length, along with map keys and the lists used to sort
+/// map pairs and struct fields.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Spare {
+    bufs: Vec<Vec<u8>>,
+    keys: Vec<String>,
+    maps: Vec<Vec<(String, Vec<u8>)>>,
+    fields: Vec<Vec<(&'static str, Vec<u8>)>>,
 }
 
 impl Default for FogSerializer {
@@ -35,16 +58,116 @@ impl FogSerializer {
     pub(crate) fn from_vec(buf: Vec<u8>, must_be_ordered: bool) -> Self {
         Self {
             must_be_ordered,
+            raw_floats: false,
+            normalize: Normalize::None,
             depth_tracking: DepthTracker::new(),
             buf,
+            spare: Spare::default(),
         }
     }
 
     pub(crate) fn with_params(must_be_ordered: bool) -> Self {
         FogSerializer {
             must_be_ordered,
+            raw_floats: false,
+            normalize: Normalize::None,
             depth_tracking: DepthTracker::new(),
             buf: Vec::new(),
+            spare: Spare::default(),
+        }
+    }
+
+    /// Set whether floating-point values are written with their exact bits, instead of being
+    /// converted to their canonical form.
+    pub(crate) fn raw_floats(mut self, raw_floats: bool) -> Self {
+        self.raw_floats = raw_floats;
+        self
+    }
+
+    /// Set the Unicode normalization form that all string values are put into before encoding.
+    pub(crate) fn normalize(mut self, normalize: Normalize) -> Self {
+        self.normalize = normalize;
+        self
+    }
+
+    /// Provide temporary storage to use when reordering map keys or encoding sequences of
+    /// unknown length, instead of allocating anew.
+    pub(crate) fn with_spare(mut self, spare: Spare) -> Self {
+        self.spare = spare;
+        self
+    }
+
+    /// Swap an empty buffer in, returning the buffer that was being written to.
+    fn swap_spare(&mut self) -> Vec<u8> {
+        let spare = self.spare.bufs.pop().unwrap_or_default();
+        mem::replace(&mut self.buf, spare)
+    }
+
+    /// Return a temporary buffer so it can be reused.
+    fn recycle(&mut self, mut buf: Vec<u8>) {
+        if self.spare.bufs.len() < MAX_SPARE_BUFFERS {
+            buf.clear();
+            buf.shrink_to(MAX_SPARE_CAPACITY);
+            self.spare.bufs.push(buf);
+        }
+    }
+
+    /// Get an empty string to hold a map key.
+    fn spare_key(&mut self) -> String {
+        self.spare.keys.pop().unwrap_or_default()
+    }
+
+    /// Get an empty list for sorting map pairs.
+    fn spare_map(&mut self) -> Vec<(String, Vec<u8>)> {
+        self.spare.maps.pop().unwrap_or_default()
+    }
+
+    /// Get an empty list for sorting struct fields.
+    fn spare_fields(&mut self) -> Vec<(&'static str, Vec<u8>)> {
+        self.spare.fields.pop().unwrap_or_default()
+    }
+
+    /// Write out buffered map pairs in key order, keeping the buffers, keys, and list for reuse.
+    /// Fails if any key is repeated.
+    fn flush_map(&mut self, mut map: Vec<(String, Vec<u8>)>) -> Result<()> {
+        map.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        if let Some(pair) = map.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            return Err(repeated_key(&pair[0].0));
+        }
+        for (mut key, vec) in map.drain(..) {
+            self.buf.extend_from_slice(&vec);
+            self.recycle(vec);
+            if self.spare.keys.len() < MAX_SPARE_BUFFERS {
+                key.clear();
+                self.spare.keys.push(key);
+            }
+        }
+        if self.spare.maps.len() < MAX_SPARE_BUFFERS {
+            self.spare.maps.push(map);
+        }
+        Ok(())
+    }
+
+    /// Write out buffered struct fields in order, keeping the buffers and list for reuse.
+    fn flush_fields(&mut self, mut fields: Vec<(&'static str, Vec<u8>)>) {
+        // Structs should never have repeated fields, so don't check for them
+        fields.sort_unstable_by_key(|(field, _)| *field);
+        for (_, vec) in fields.drain(..) {
+            self.buf.extend_from_slice(&vec);
+            self.recycle(vec);
+        }
+        if self.spare.fields.len() < MAX_SPARE_BUFFERS {
+            self.spare.fields.push(fields);
+        }
+    }
+
+    /// Get the normalized form of a string, if it isn't already normalized.
+    fn normalized(&self, v: &str) -> Option<String> {
+        use unicode_normalization::{is_nfc, is_nfkc, UnicodeNormalization};
+        match self.normalize {
+            Normalize::NFC if !is_nfc(v) => Some(v.nfc().collect()),
+            Normalize::NFKC if !is_nfkc(v) => Some(v.nfkc().collect()),
+            _ => None,
         }
     }
 
@@ -70,6 +193,11 @@ impl FogSerializer {
     pub(crate) fn finish(self) -> Vec<u8> {
         self.buf
     }
+
+    /// Finish serializing, also returning the temporary storage so it can be used again.
+    pub(crate) fn finish_with_spare(self) -> (Vec<u8>, Spare) {
+        (self.buf, self.spare)
+    }
 }
 
 impl<'a> Serializer for &'a mut FogSerializer {
@@ -124,19 +252,24 @@ impl<'a> Serializer for &'a mut FogSerializer {
     }
 
     fn serialize_f32(self, v: f32) -> Result<()> {
+        let v = if self.raw_floats { v } else { canonical_f32(v) };
         self.encode_element(Element::F32(v))
     }
 
     fn serialize_f64(self, v: f64) -> Result<()> {
+        let v = if self.raw_floats { v } else { canonical_f64(v) };
         self.encode_element(Element::F64(v))
     }
 
     fn serialize_char(self, v: char) -> Result<()> {
-        self.encode_element(Element::Str(&v.to_string()))
+        self.serialize_str(&v.to_string())
     }
 
     fn serialize_str(self, v: &str) -> Result<()> {
-        self.encode_element(Element::Str(v))
+        match self.normalized(v) {
+            Some(v) => self.encode_element(Element::Str(&v)),
+            None => self.encode_element(Element::Str(v)),
+        }
     }
 
     fn serialize_bytes(self, v: &[u8]) -> Result<()> {
@@ -290,46 +423,36 @@ impl<'a> Serializer for &'a mut FogSerializer {
             (lo, Some(hi)) if lo == hi => Some(lo),
             _ => None,
         };
+        if !self.must_be_ordered {
+            // Unordered: buffer each pair and reorder them, reusing the spare buffers
+            let mut map = MapSerializer::new(self, len)?;
+            for (k, v) in iter {
+                map.serialize_entry(&k, &v)?;
+            }
+            return SerializeMap::end(map);
+        }
         if let Some(len) = len {
+            // Sized & Ordered
             self.encode_element(Element::Map(len))?;
-            if self.must_be_ordered {
-                // Sized & Ordered
-                let mut last_key = None;
-                let mut new_key = String::new();
-                for (k, v) in iter {
-                    k.serialize(KeySerializer::new(&mut new_key))?;
-                    self.encode_element(Element::Str(&new_key))?;
-                    if let Some(ref mut last_key) = last_key {
-                        if new_key <= *last_key {
-                            return Err(Error::SerdeFail(format!(
-                                "map keys are unordered: {} follows {}",
-                                new_key, last_key
-                            )));
-                        }
-                        mem::swap(&mut new_key, &mut *last_key);
-                    } else {
-                        last_key = Some(mem::take(&mut new_key));
-                    }
-                    v.serialize(&mut *self)?;
-                }
-            } else {
-                // Sized & Unordered
-                let mut map = BTreeMap::new();
-                // Collect into ordered map
-                for (k, v) in iter {
-                    let mut key = String::new();
-                    k.serialize(KeySerializer::new(&mut key))?;
-                    if map.insert(key, v).is_some() {
-                        return Err(Error::SerdeFail("map has repeated keys".into()));
+            let mut last_key = None;
+            let mut new_key = String::new();
+            for (k, v) in iter {
+                k.serialize(KeySerializer::new(&mut new_key))?;
+                self.encode_element(Element::Str(&new_key))?;
+                if let Some(ref mut last_key) = last_key {
+                    if new_key <= *last_key {
+                        return Err(Error::SerdeFail(format!(
+                            "map keys are unordered: {} follows {}",
+                            new_key, last_key
+                        )));
                     }
+                    mem::swap(&mut new_key, &mut *last_key);
+                } else {
+                    last_key = Some(mem::take(&mut new_key));
                 }
-                // Serialize in order
-                for (k, v) in map.iter() {
-                    self.encode_element(Element::Str(k))?;
-                    v.serialize(&mut *self)?;
-                }
+                v.serialize(&mut *self)?;
             }
-        } else if self.must_be_ordered {
+        } else {
             // Unsized & Ordered
             let mut map = Vec::with_capacity(iter.size_hint().0);
             for (k, v) in iter {
@@ -350,28 +473,17 @@ impl<'a> Serializer for &'a mut FogSerializer {
                 self.encode_element(Element::Str(k))?;
                 v.serialize(&mut *self)?;
             }
-        } else {
-            // Unsized & Unordered
-            let mut map = BTreeMap::new();
-            // Collect into ordered map
-            for (k, v) in iter {
-                let mut key = String::new();
-                k.serialize(KeySerializer::new(&mut key))?;
-                if map.insert(key, v).is_some() {
-                    return Err(Error::SerdeFail("map has repeated keys".into()));
-                }
-            }
-            // Serialize in order
-            self.encode_element(Element::Map(map.len()))?;
-            for (k, v) in map.iter() {
-                self.encode_element(Element::Str(k))?;
-                v.serialize(&mut *self)?;
-            }
         }
         Ok(())
     }
 }
 
+/// Repeated keys can't be encoded canonically. Besides a plain duplicate, this can happen when
+/// `#[serde(flatten)]` is used on multiple fields that share a key name.
+fn repeated_key(key: &str) -> Error {
+    Error::SerdeFail(format!("map has repeated key: \"{}\"", key))
+}
+
 /// Encode a sequence of possibly unknown length.
 ///
 /// If the length is known, this is easy and looks pretty much like the TupleSerializer. However,
@@ -404,7 +516,7 @@ impl<'a> SeqSerializer<'a> {
         } else {
             se.depth_tracking
                 .update_elem(&Element::Array(MAX_DOC_SIZE))?;
-            let enc = mem::take(&mut se.buf);
+            let enc = se.swap_spare();
             Ok(Self {
                 se,
                 unknown_len: Some((0, enc)),
@@ -435,6 +547,7 @@ impl<'a> SerializeSeq for SeqSerializer<'a> {
             let enc = mem::replace(&mut self.se.buf, enc);
             serialize_elem(&mut self.se.buf, Element::Array(len));
             self.se.buf.extend_from_slice(&enc);
+            self.se.recycle(enc);
             self.se.depth_tracking.early_end();
             Ok(())
         } else {
@@ -528,9 +641,11 @@ impl<'a> MapSerializer<'a> {
                     new_key: String::new(),
                 }
             } else {
+                let mut map = se.spare_map();
+                map.reserve(len);
                 MapSerializer::SizedUnordered {
                     se,
-                    map: Vec::with_capacity(len),
+                    map,
                     pending_key: String::new(),
                 }
             })
@@ -538,7 +653,7 @@ impl<'a> MapSerializer<'a> {
             se.depth_tracking
                 .update_elem(&Element::Map(MAX_DOC_SIZE >> 1))?;
             if se.must_be_ordered {
-                let buf = mem::take(&mut se.buf);
+                let buf = se.swap_spare();
                 Ok(MapSerializer::UnsizedOrdered {
                     se,
                     last_key: None,
@@ -547,9 +662,10 @@ impl<'a> MapSerializer<'a> {
                     buf,
                 })
             } else {
+                let map = se.spare_map();
                 Ok(MapSerializer::UnsizedUnordered {
                     se,
-                    map: Vec::new(),
+                    map,
                     pending_key: String::new(),
                 })
             }
@@ -606,7 +722,9 @@ impl<'a> SerializeMap for MapSerializer<'a> {
                 if let Some(last_key) = last_key {
                     if new_key <= last_key {
                         return Err(Error::SerdeFail(format!(
-                            "map keys are unordered: {} follows {}",
+                            "map keys are unordered: {} follows {}. Maps of unknown length, \
+                            including structs with `#[serde(flatten)]` fields, can only be \
+                            encoded in order if every key is produced in lexicographic order",
                             new_key, last_key
                         )));
                     }
@@ -635,12 +753,12 @@ impl<'a> SerializeMap for MapSerializer<'a> {
             } => {
                 // Slot in buffer, fill it like we're writing to the actual buffer, then store it
                 // off for later reordering
-                let buf = mem::take(&mut se.buf);
+                let buf = se.swap_spare();
                 se.encode_element(Element::Str(pending_key))?;
                 value.serialize(&mut **se)?;
                 // Replace buffers & store off in Vec
                 let buf = mem::replace(&mut se.buf, buf);
-                let key = mem::take(pending_key);
+                let key = mem::replace(pending_key, se.spare_key());
                 map.push((key, buf));
             }
             MapSerializer::UnsizedOrdered { se, .. } => {
@@ -653,12 +771,12 @@ impl<'a> SerializeMap for MapSerializer<'a> {
             } => {
                 // Slot in buffer, fill it like we're writing to the actual buffer, then store it
                 // off for later reordering
-                let buf = mem::take(&mut se.buf);
+                let buf = se.swap_spare();
                 se.encode_element(Element::Str(pending_key))?;
                 value.serialize(&mut **se)?;
-                // Replace buffers & store off in BTreeMap
+                // Replace buffers & store off in Vec
                 let buf = mem::replace(&mut se.buf, buf);
-                let key = mem::take(pending_key);
+                let key = mem::replace(pending_key, se.spare_key());
                 map.push((key, buf));
                 if map.len() > (MAX_DOC_SIZE >> 1) {
                     return Err(Error::SerdeFail(format!(
@@ -674,17 +792,9 @@ impl<'a> SerializeMap for MapSerializer<'a> {
     fn end(self) -> Result<()> {
         match self {
             MapSerializer::SizedOrdered { .. } => (),
-            MapSerializer::SizedUnordered { se, mut map, .. } => {
+            MapSerializer::SizedUnordered { se, map, .. } => {
                 // Flush all buffers, in order, out to the main one
-                map.sort_unstable_by(|a, b| a.0.cmp(&b.0));
-                let len = map.len();
-                map.dedup_by(|a, b| a.0 == b.0);
-                if len != map.len() {
-                    return Err(Error::SerdeFail("map has repeated keys".into()));
-                }
-                for (_, vec) in map.iter() {
-                    se.buf.extend_from_slice(vec);
-                }
+                se.flush_map(map)?;
             }
             MapSerializer::UnsizedOrdered { se, len, buf, .. } => {
                 // The serializer has our temporary buffer. Swap back, put in the real Map marker,
@@ -692,21 +802,14 @@ impl<'a> SerializeMap for MapSerializer<'a> {
                 let enc = mem::replace(&mut se.buf, buf);
                 serialize_elem(&mut se.buf, Element::Map(len));
                 se.buf.extend_from_slice(&enc);
+                se.recycle(enc);
                 se.depth_tracking.early_end();
             }
-            MapSerializer::UnsizedUnordered { se, mut map, .. } => {
+            MapSerializer::UnsizedUnordered { se, map, .. } => {
                 // Fill in the real map marker, update depth tracking, and
                 // flush all buffers, in order, out to the main one
                 serialize_elem(&mut se.buf, Element::Map(map.len()));
-                map.sort_unstable_by(|a, b| a.0.cmp(&b.0));
-                let len = map.len();
-                map.dedup_by(|a, b| a.0 == b.0);
-                if len != map.len() {
-                    return Err(Error::SerdeFail("map has repeated keys".into()));
-                }
-                for (_, vec) in map.iter() {
-                    se.buf.extend_from_slice(vec);
-                }
+                se.flush_map(map)?;
                 se.depth_tracking.early_end();
             }
         }
@@ -721,7 +824,7 @@ pub(crate) enum StructSerializer<'a> {
     },
     Unordered {
         se: &'a mut FogSerializer,
-        map: BTreeMap<&'static str, Vec<u8>>,
+        fields: Vec<(&'static str, Vec<u8>)>,
     },
 }
 
@@ -730,10 +833,8 @@ impl<'a> StructSerializer<'a> {
         if se.must_be_ordered {
             StructSerializer::Ordered { se, last_key: None }
         } else {
-            StructSerializer::Unordered {
-                se,
-                map: BTreeMap::new(),
-            }
+            let fields = se.spare_fields();
+            StructSerializer::Unordered { se, fields }
         }
     }
 
@@ -747,7 +848,9 @@ impl<'a> StructSerializer<'a> {
                 if let Some(last_key) = last_key {
                     if field <= *last_key {
                         return Err(Error::SerdeFail(format!(
-                            "map keys are unordered: {} follows {}",
+                            "map keys are unordered: {} follows {}. Struct fields must be \
+                            declared in lexicographic order, and internally tagged enums must \
+                            have a tag that sorts before all of their fields",
                             field, last_key
                         )));
                     }
@@ -758,15 +861,15 @@ impl<'a> StructSerializer<'a> {
                 se.encode_element(Element::Str(field))?;
                 value.serialize(&mut **se)?;
             }
-            StructSerializer::Unordered { se, map } => {
+            StructSerializer::Unordered { se, fields } => {
                 // Slot in buffer, fill it like we're writing to the actual buffer, then store it
                 // off for later reordering
-                let buf = mem::take(&mut se.buf);
+                let buf = se.swap_spare();
                 se.encode_element(Element::Str(field))?;
                 value.serialize(&mut **se)?;
-                // Replace buffers & store off in BTreeMap
+                // Replace buffers & store off in Vec
                 let buf = mem::replace(&mut se.buf, buf);
-                map.insert(field, buf); // Structs should never have repeated fields, so don't check for them
+                fields.push((field, buf));
             }
         }
         Ok(())
@@ -775,11 +878,7 @@ impl<'a> StructSerializer<'a> {
     fn end_inner(self) {
         match self {
             StructSerializer::Ordered { .. } => (),
-            StructSerializer::Unordered { se, map } => {
-                for (_, vec) in map.iter() {
-                    se.buf.extend_from_slice(vec);
-                }
-            }
+            StructSerializer::Unordered { se, fields } => se.flush_fields(fields),
         }
     }
 }
@@ -1091,6 +1190,13 @@ impl<'a> KeySerializer<'a> {
         let s = format!("expected string, received {}", received);
         Error::SerdeFail(s)
     }
+
+    // Write an integer key in its canonical decimal form, as described in `crate::keys`
+    fn push_int(self, v: impl std::fmt::Display) -> Result<()> {
+        use std::fmt::Write;
+        write!(self.s, "{}", v).expect("writing to a String can't fail");
+        Ok(())
+    }
 }
 
 impl<'a> Serializer for KeySerializer<'a> {
@@ -1141,36 +1247,36 @@ impl<'a> Serializer for KeySerializer<'a> {
         Err(self.ser_fail("bool"))
     }
 
-    fn serialize_i8(self, _: i8) -> Result<()> {
-        Err(self.ser_fail("i8"))
+    fn serialize_i8(self, v: i8) -> Result<()> {
+        self.push_int(v)
     }
 
-    fn serialize_i16(self, _: i16) -> Result<()> {
-        Err(self.ser_fail("i16"))
+    fn serialize_i16(self, v: i16) -> Result<()> {
+        self.push_int(v)
     }
 
-    fn serialize_i32(self, _: i32) -> Result<()> {
-        Err(self.ser_fail("i32"))
+    fn serialize_i32(self, v: i32) -> Result<()> {
+        self.push_int(v)
     }
 
-    fn serialize_i64(self, _: i64) -> Result<()> {
-        Err(self.ser_fail("i64"))
+    fn serialize_i64(self, v: i64) -> Result<()> {
+        self.push_int(v)
     }
 
-    fn serialize_u8(self, _: u8) -> Result<()> {
-        Err(self.ser_fail("u8"))
+    fn serialize_u8(self, v: u8) -> Result<()> {
+        self.push_int(v)
     }
 
-    fn serialize_u16(self, _: u16) -> Result<()> {
-        Err(self.ser_fail("u16"))
+    fn serialize_u16(self, v: u16) -> Result<()> {
+        self.push_int(v)
     }
 
-    fn serialize_u32(self, _: u32) -> Result<()> {
-        Err(self.ser_fail("u32"))
+    fn serialize_u32(self, v: u32) -> Result<()> {
+        self.push_int(v)
     }
 
-    fn serialize_u64(self, _: u64) -> Result<()> {
-        Err(self.ser_fail("u64"))
+    fn serialize_u64(self, v: u64) -> Result<()> {
+        self.push_int(v)
     }
 
     fn serialize_f32(self, _: f32) -> Result<()> {
@@ -1933,4 +2039,41 @@ mod test {
             assert_eq!(ser.buf, enc);
         }
     }
+
+    #[test]
+    fn spare_reuse() {
+        use std::collections::HashMap;
+
+        #[derive(Serialize)]
+        struct Unordered {
+            z: u8,
+            a: HashMap<String, Vec<u8>>,
+        }
+        let data = Unordered {
+            z: 1,
+            a: [("b".to_string(), vec![2]), ("a".to_string(), vec![3])]
+                .into_iter()
+                .collect(),
+        };
+        let mut ser = FogSerializer::default();
+        data.serialize(&mut ser).unwrap();
+        let (first, spare) = ser.finish_with_spare();
+        assert!(!spare.bufs.is_empty());
+        assert_eq!(spare.maps.len(), 1);
+        assert_eq!(spare.fields.len(), 1);
+        assert_eq!(spare.keys.len(), 2);
+
+        // Reusing the storage gives the same result, and takes nothing new
+        let mut ser = FogSerializer::default().with_spare(spare);
+        data.serialize(&mut ser).unwrap();
+        let (second, spare) = ser.finish_with_spare();
+        assert_eq!(first, second);
+        assert_eq!(spare.maps.len(), 1);
+        assert_eq!(spare.fields.len(), 1);
+
+        // Oversized buffers are shrunk before being kept
+        let mut ser = FogSerializer::default();
+        ser.recycle(Vec::with_capacity(MAX_SPARE_CAPACITY * 2));
+        assert!(ser.spare.bufs[0].capacity() <= MAX_SPARE_CAPACITY);
+    }
 }
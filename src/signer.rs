@@ -0,0 +1,260 @@
+//! Signing of documents and entries.
+//!
+//! Documents and entries can be signed by anything implementing [`Signer`], not just an in-memory
+//! [`IdentityKey`]. This lets signatures come from hardware tokens, OS keychains, or any other key
+//! storage that never exposes the private key. Signers that need to wait on I/O, like remote
+//! signing services, can implement [`AsyncSigner`] instead, and be used with the `sign_async`
+//! functions. Every `Signer` that is also `Sync` can be used as an `AsyncSigner`.
+//!
+//! A signer that produces raw signature bytes can turn them into a [`Signature`] by parsing them
+//! with [`UnverifiedSignature`][fog_crypto::identity::UnverifiedSignature] and verifying the
+//! result against the hash that was signed.
+//!
+//! # Deterministic Signatures
+//!
+//! Because a signed document's hash covers its signature, a signer that produces a different
+//! signature each time will also produce a different document hash each time it re-signs the same
+//! document, defeating deduplication. [`IdentityKey`] signatures are always deterministic: the same
+//! key signing the same hash gives the same signature bytes. Signers report whether they make the
+//! same guarantee through [`Signer::deterministic`]. Documents signed by a signer that isn't
+//! deterministic should be signed once and then kept, never re-signed by the same key.
+//!
+//! When a signing key is retired, [`resign`] can replace its signatures on a batch of documents
+//! with signatures from a new key.
+
+use crate::{document::Document, error::Result};
+use fog_crypto::{
+    hash::Hash,
+    identity::{Identity, IdentityKey, Signature},
+};
+use futures_core::future::BoxFuture;
+use std::collections::HashMap;
+
+/// Something that can sign the hash of a document or entry.
+pub trait Signer {
+    /// Sign the provided hash. The returned signature must be of exactly this hash, or the
+    /// signed document or entry will fail verification when it is decoded.
+    fn sign(&self, hash: &Hash) -> Result<Signature>;
+
+    /// Whether this signer always produces the same signature when signing the same hash. The
+    /// default is `false`, as this can't be assumed of arbitrary signers.
+    fn deterministic(&self) -> bool {
+        false
+    }
+}
+
+impl Signer for IdentityKey {
+    fn sign(&self, hash: &Hash) -> Result<Signature> {
+        Ok(IdentityKey::sign(self, hash))
+    }
+
+    fn deterministic(&self) -> bool {
+        true
+    }
+}
+
+/// Something that can asynchronously sign the hash of a document or entry.
+pub trait AsyncSigner {
+    /// Sign the provided hash. The returned signature must be of exactly this hash, or the
+    /// signed document or entry will fail verification when it is decoded.
+    fn sign<'a>(&'a self, hash: &'a Hash) -> BoxFuture<'a, Result<Signature>>;
+
+    /// Whether this signer always produces the same signature when signing the same hash. The
+    /// default is `false`, as this can't be assumed of arbitrary signers.
+    fn deterministic(&self) -> bool {
+        false
+    }
+}
+
+impl<T: Signer + Sync + ?Sized> AsyncSigner for T {
+    fn sign<'a>(&'a self, hash: &'a Hash) -> BoxFuture<'a, Result<Signature>> {
+        let result = Signer::sign(self, hash);
+        Box::pin(async move { result })
+    }
+
+    fn deterministic(&self) -> bool {
+        Signer::deterministic(self)
+    }
+}
+
+/// Re-sign every document signed by the `old` identity with a new signer.
+///
+/// Documents not signed by `old` are passed through unchanged. Re-signing changes a document's
+/// hash, so a map from each re-signed document's old hash to its new hash is returned alongside
+/// the documents, letting a store update anything that refers to them. Documents whose signature
+/// hasn't been verified yet are verified before being re-signed. Fails on the first document that
+/// can't be verified or re-signed.
+pub fn resign<I, S>(
+    docs: I,
+    old: &Identity,
+    key: &S,
+) -> Result<(Vec<Document>, HashMap<Hash, Hash>)>
+where
+    I: IntoIterator<Item = Document>,
+    S: Signer + ?Sized,
+{
+    let mut hashes = HashMap::new();
+    let docs = docs
+        .into_iter()
+        .map(|doc| {
+            if doc.signer() != Some(old) {
+                return Ok(doc);
+            }
+            let doc = doc.verify()?;
+            let old_hash = doc.hash().clone();
+            let doc = doc.sign(key)?;
+            hashes.insert(old_hash, doc.hash().clone());
+            Ok(doc)
+        })
+        .collect::<Result<Vec<Document>>>()?;
+    Ok((docs, hashes))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{document::NewDocument, entry::NewEntry, schema::*, validator::Validator};
+    use fog_crypto::identity::{Identity, UnverifiedSignature};
+    use futures_executor::block_on;
+
+    // A signer that only hands out raw signature bytes, like a remote signing service would.
+    struct Remote {
+        key: IdentityKey,
+    }
+
+    impl Remote {
+        fn sign_bytes(&self, hash: &Hash) -> Vec<u8> {
+            let mut buf = Vec::new();
+            self.key.sign(hash).encode_vec(&mut buf);
+            buf
+        }
+
+        fn id(&self) -> &Identity {
+            self.key.id()
+        }
+    }
+
+    impl AsyncSigner for Remote {
+        fn sign<'a>(&'a self, hash: &'a Hash) -> BoxFuture<'a, Result<Signature>> {
+            Box::pin(async move {
+                let raw = self.sign_bytes(hash);
+                Ok(UnverifiedSignature::try_from(raw.as_slice())?.verify(hash)?)
+            })
+        }
+    }
+
+    #[test]
+    fn async_signing() {
+        let remote = Remote {
+            key: IdentityKey::new(),
+        };
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .entry_add("e", Validator::Any, None)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+
+        let doc = NewDocument::new(Some(schema.hash()), "doc").unwrap();
+        let doc = block_on(doc.sign_async(&remote)).unwrap();
+        let doc = schema.validate_new_doc(doc).unwrap();
+        assert_eq!(doc.signer(), Some(remote.id()));
+        let (_, encoded) = schema.encode_doc(doc).unwrap();
+        let doc = schema.decode_doc(encoded).unwrap();
+        assert_eq!(doc.signer(), Some(remote.id()));
+
+        let entry = NewEntry::new("e", &doc, 1u8).unwrap();
+        let entry = block_on(entry.sign_async(&remote)).unwrap();
+        let entry = schema
+            .validate_new_entry(entry)
+            .unwrap()
+            .complete()
+            .unwrap();
+        let (entry_ref, encoded, _) = schema.encode_entry(entry).unwrap();
+        let entry = schema
+            .decode_entry(encoded, &entry_ref.key, &doc)
+            .unwrap()
+            .complete()
+            .unwrap();
+        assert_eq!(entry.signer(), Some(remote.id()));
+    }
+
+    #[test]
+    fn wrong_hash() {
+        // A broken signer that signs something other than what it was asked to
+        struct Broken(IdentityKey);
+        impl Signer for Broken {
+            fn sign(&self, _: &Hash) -> Result<Signature> {
+                Ok(self.0.sign(&Hash::new(b"something else")))
+            }
+        }
+
+        let key = Broken(IdentityKey::new());
+        let doc = NewDocument::new(None, "doc").unwrap();
+        doc.sign(&key).unwrap_err();
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .entry_add("e", Validator::Any, None)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let doc = NewDocument::new(Some(schema.hash()), "doc").unwrap();
+        let doc = schema.validate_new_doc(doc).unwrap();
+        let entry = NewEntry::new("e", &doc, 1u8).unwrap();
+        entry.sign(&key).unwrap_err();
+    }
+
+    #[test]
+    fn signer_as_async() {
+        let key = IdentityKey::new();
+        let doc = NewDocument::new(None, "doc").unwrap();
+        let sync_doc = doc.clone().sign(&key).unwrap();
+        let async_doc = block_on(doc.sign_async(&key)).unwrap();
+        assert_eq!(sync_doc.hash(), async_doc.hash());
+    }
+
+    #[test]
+    fn deterministic_signing() {
+        let key = IdentityKey::new();
+        assert!(Signer::deterministic(&key));
+        assert!(AsyncSigner::deterministic(&key));
+        let remote = Remote {
+            key: IdentityKey::new(),
+        };
+        assert!(!AsyncSigner::deterministic(&remote));
+
+        let doc = NewDocument::new(None, "doc").unwrap();
+        let first = doc.clone().sign(&key).unwrap();
+        let second = doc.sign(&key).unwrap().sign(&key).unwrap();
+        assert_eq!(first.hash(), second.hash());
+        assert_eq!(first.data(), second.data());
+    }
+
+    #[test]
+    fn resign_batch() {
+        let old = IdentityKey::new();
+        let other = IdentityKey::new();
+        let new = IdentityKey::new();
+        let docs = vec![
+            NewDocument::new(None, "a").unwrap().sign(&old).unwrap(),
+            NewDocument::new(None, "b").unwrap().sign(&other).unwrap(),
+            NewDocument::new(None, "c").unwrap(),
+        ];
+        let docs: Vec<Document> = docs
+            .into_iter()
+            .map(|doc| {
+                let (_, encoded) =
+                    NoSchema::encode_doc(NoSchema::validate_new_doc(doc).unwrap()).unwrap();
+                NoSchema::decode_doc(encoded).unwrap()
+            })
+            .collect();
+        let old_hashes: Vec<Hash> = docs.iter().map(|doc| doc.hash().clone()).collect();
+
+        let (docs, hashes) = resign(docs, old.id(), &new).unwrap();
+        assert_eq!(hashes.len(), 1);
+        assert_eq!(docs[0].signer(), Some(new.id()));
+        assert_eq!(hashes.get(&old_hashes[0]), Some(docs[0].hash()));
+        assert_eq!(docs[1].signer(), Some(other.id()));
+        assert_eq!(docs[1].hash(), &old_hashes[1]);
+        assert_eq!(docs[2].signer(), None);
+        assert_eq!(docs[2].hash(), &old_hashes[2]);
+    }
+}
@@ -0,0 +1,418 @@
+//! Local storage for documents and entries.
+//!
+//! A [`DocStore`] holds validated [`Documents`][Document] by hash, along with the [`Entries`][Entry]
+//! attached to them, and can run [`Queries`][Query] over those entries. Two implementations are
+//! provided: [`MemoryStore`], which keeps everything in memory, and [`DirStore`], which keeps each
+//! document and entry in its own file under a directory. Other backends can implement the same
+//! trait.
+//!
+//! Stores are meant to hold data that has already passed through a
+//! [`Schema`][crate::schema::Schema] or [`NoSchema`][crate::schema::NoSchema], so they don't
+//! validate data again when reading it back. They don't check this when data is stored, either:
+//! it's up to the caller to only store validated data. Signatures are still checked when a
+//! [`DirStore`] reads from disk.
+//!
+//! ```
+//! # use fog_pack::{document::*, schema::*, store::*};
+//! # fn main() -> fog_pack::error::Result<()> {
+//! let mut store = MemoryStore::new();
+//! let doc = NoSchema::validate_new_doc(NewDocument::new(None, "hello")?)?;
+//! let hash = store.put_doc(doc)?;
+//! let doc = store.get_doc(&hash)?.unwrap();
+//! assert_eq!(doc.deserialize::<&str>()?, "hello");
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    document::Document,
+    entry::{Entry, EntryRef},
+    error::{Error, Result},
+    query::Query,
+    types::Hash,
+};
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// Storage for documents and entries.
+///
+/// Implementors only need to store and fetch data; running queries is provided on top of
+/// [`get_entries`][DocStore::get_entries] and [`get_doc`][DocStore::get_doc].
+pub trait DocStore {
+    /// Store a document, returning its hash. Storing a document that is already present does
+    /// nothing.
+    ///
+    /// The document isn't validated, so this accepts any document, including one built with
+    /// [`Document::new`] that never passed through a schema. Documents are handed back by
+    /// [`get_doc`][DocStore::get_doc] without being validated again, so only store documents
+    /// that came out of a schema or [`NoSchema`][crate::schema::NoSchema].
+    fn put_doc(&mut self, doc: Document) -> Result<Hash>;
+
+    /// Fetch a document by its hash.
+    fn get_doc(&self, hash: &Hash) -> Result<Option<Document>>;
+
+    /// Store an entry. Fails if the entry's parent document isn't in the store. Storing an entry
+    /// that is already present does nothing.
+    fn put_entry(&mut self, entry: Entry) -> Result<EntryRef>;
+
+    /// Fetch every entry with the given parent document and key.
+    fn get_entries(&self, parent: &Hash, key: &str) -> Result<Vec<Entry>>;
+
+    /// Find every entry attached to `parent` that matches a query. Any documents the query needs
+    /// in order to check an entry are fetched from the store; entries whose documents are
+    /// missing don't match.
+    fn query_entries(&self, parent: &Hash, query: &Query) -> Result<Vec<Entry>> {
+        let mut matches = Vec::new();
+        'entries: for entry in self.get_entries(parent, query.key())? {
+            let mut checklist = match query.query(&entry) {
+                Ok(checklist) => checklist,
+                Err(_) => continue,
+            };
+            for (hash, item) in checklist.iter() {
+                match self.get_doc(&hash)? {
+                    Some(doc) if item.check(&doc).is_ok() => (),
+                    _ => continue 'entries,
+                }
+            }
+            if checklist.complete().is_ok() {
+                matches.push(entry);
+            }
+        }
+        Ok(matches)
+    }
+}
+
+/// A [`DocStore`] that keeps everything in memory.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStore {
+    docs: HashMap<Hash, Document>,
+    // Entries, by parent hash and then by entry hash
+    entries: HashMap<Hash, HashMap<Hash, Entry>>,
+}
+
+impl MemoryStore {
+    /// Create a new, empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the number of documents in the store.
+    pub fn doc_count(&self) -> usize {
+        self.docs.len()
+    }
+
+    /// Get the number of entries in the store.
+    pub fn entry_count(&self) -> usize {
+        self.entries.values().map(|e| e.len()).sum()
+    }
+}
+
+impl DocStore for MemoryStore {
+    fn put_doc(&mut self, doc: Document) -> Result<Hash> {
+        let hash = doc.hash().clone();
+        self.docs.entry(hash.clone()).or_insert(doc);
+        Ok(hash)
+    }
+
+    fn get_doc(&self, hash: &Hash) -> Result<Option<Document>> {
+        Ok(self.docs.get(hash).cloned())
+    }
+
+    fn put_entry(&mut self, entry: Entry) -> Result<EntryRef> {
+        if !self.docs.contains_key(entry.parent()) {
+            return Err(missing_parent(entry.parent()));
+        }
+        let reference = entry.reference().clone();
+        self.entries
+            .entry(reference.parent.clone())
+            .or_default()
+            .entry(reference.hash.clone())
+            .or_insert(entry);
+        Ok(reference)
+    }
+
+    fn get_entries(&self, parent: &Hash, key: &str) -> Result<Vec<Entry>> {
+        Ok(self
+            .entries
+            .get(parent)
+            .map(|entries| {
+                entries
+                    .values()
+                    .filter(|e| e.key() == key)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+}
+
+fn missing_parent(parent: &Hash) -> Error {
+    Error::Store(format!("parent document {} isn't in the store", parent))
+}
+
+fn io_error(e: io::Error) -> Error {
+    Error::Store(e.to_string())
+}
+
+/// A [`DocStore`] that keeps each document and entry in a file under a directory.
+///
+/// Documents are stored as `docs/<hash>`, and entries as `entries/<parent hash>/<entry hash>`,
+/// with hashes in base58. Files hold the uncompressed encoding of their document or entry; entry
+/// files are prefixed with the entry's key. Files are written to a temporary name and then
+/// renamed into place, so a crash never leaves a partially written file behind.
+#[derive(Clone, Debug)]
+pub struct DirStore {
+    root: PathBuf,
+}
+
+impl DirStore {
+    /// Open a store in the given directory, creating the directory if it doesn't exist.
+    pub fn open(root: impl AsRef<Path>) -> Result<Self> {
+        let root = root.as_ref().to_owned();
+        fs::create_dir_all(root.join("docs")).map_err(io_error)?;
+        fs::create_dir_all(root.join("entries")).map_err(io_error)?;
+        Ok(Self { root })
+    }
+
+    /// Get the directory this store is in.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn doc_path(&self, hash: &Hash) -> PathBuf {
+        self.root.join("docs").join(hash.to_string())
+    }
+
+    fn entry_dir(&self, parent: &Hash) -> PathBuf {
+        self.root.join("entries").join(parent.to_string())
+    }
+
+    fn write(path: &Path, data: &[u8]) -> Result<()> {
+        if path.exists() {
+            return Ok(());
+        }
+        // Each write gets its own temporary file, so concurrent writers never share one
+        static NEXT_TMP: AtomicU64 = AtomicU64::new(0);
+        let tmp = path.with_extension(format!(
+            "{}-{}.tmp",
+            std::process::id(),
+            NEXT_TMP.fetch_add(1, Ordering::Relaxed)
+        ));
+        let result = fs::write(&tmp, data).and_then(|_| fs::rename(&tmp, path));
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        result.map_err(io_error)
+    }
+
+    fn read(path: &Path) -> Result<Option<Vec<u8>>> {
+        match fs::read(path) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(io_error(e)),
+        }
+    }
+
+    fn read_entry(raw: Vec<u8>, parent: &Document) -> Result<Entry> {
+        let corrupt = || Error::Store("entry file is corrupt".into());
+        let key_len = u16::from_le_bytes(raw.get(..2).ok_or_else(corrupt)?.try_into().unwrap());
+        let key_end = 2 + key_len as usize;
+        let key = std::str::from_utf8(raw.get(2..key_end).ok_or_else(corrupt)?)
+            .map_err(|_| corrupt())?
+            .to_owned();
+        Entry::new(raw[key_end..].to_vec(), &key, parent)
+    }
+}
+
+impl DocStore for DirStore {
+    fn put_doc(&mut self, doc: Document) -> Result<Hash> {
+        let hash = doc.hash().clone();
+        Self::write(&self.doc_path(&hash), doc.raw())?;
+        Ok(hash)
+    }
+
+    fn get_doc(&self, hash: &Hash) -> Result<Option<Document>> {
+        match Self::read(&self.doc_path(hash))? {
+            Some(raw) => {
+                let doc = Document::new(raw)?;
+                if doc.hash() != hash {
+                    return Err(Error::Store(format!(
+                        "stored document {} has the wrong hash",
+                        hash
+                    )));
+                }
+                Ok(Some(doc))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn put_entry(&mut self, entry: Entry) -> Result<EntryRef> {
+        if !self.doc_path(entry.parent()).exists() {
+            return Err(missing_parent(entry.parent()));
+        }
+        let reference = entry.reference().clone();
+        let dir = self.entry_dir(&reference.parent);
+        fs::create_dir_all(&dir).map_err(io_error)?;
+        let key = reference.key.as_bytes();
+        let mut data = Vec::with_capacity(2 + key.len() + entry.raw().len());
+        data.extend_from_slice(&(key.len() as u16).to_le_bytes());
+        data.extend_from_slice(key);
+        data.extend_from_slice(entry.raw());
+        Self::write(&dir.join(reference.hash.to_string()), &data)?;
+        Ok(reference)
+    }
+
+    fn get_entries(&self, parent_hash: &Hash, key: &str) -> Result<Vec<Entry>> {
+        let dir = match fs::read_dir(self.entry_dir(parent_hash)) {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(io_error(e)),
+        };
+        let parent = match self.get_doc(parent_hash)? {
+            Some(parent) => parent,
+            None => return Err(missing_parent(parent_hash)),
+        };
+        let mut entries = Vec::new();
+        for file in dir {
+            let path = file.map_err(io_error)?.path();
+            if path.extension().is_some() {
+                // Leftover temporary file
+                continue;
+            }
+            let raw = fs::read(&path).map_err(io_error)?;
+            let entry = Self::read_entry(raw, &parent)?;
+            if entry.key() == key {
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        document::NewDocument,
+        entry::NewEntry,
+        query::NewQuery,
+        schema::{Schema, SchemaBuilder},
+        validator::*,
+    };
+
+    // Run a store through its paces, returning the hash of the parent document it holds
+    fn exercise(store: &mut impl DocStore) -> Hash {
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .entry_add(
+                "num",
+                IntValidator::new().query(true).ord(true).build(),
+                None,
+            )
+            .entry_add("other", Validator::Any, None)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let parent = NewDocument::new(Some(schema.hash()), "parent").unwrap();
+        let parent = schema.validate_new_doc(parent).unwrap();
+
+        // Entries need their parent
+        let entry = NewEntry::new("num", &parent, 1u8).unwrap();
+        let entry = schema
+            .validate_new_entry(entry)
+            .unwrap()
+            .complete()
+            .unwrap();
+        assert!(store.put_entry(entry.clone()).is_err());
+
+        let hash = store.put_doc(parent.clone()).unwrap();
+        assert_eq!(&hash, parent.hash());
+        assert_eq!(store.put_doc(parent.clone()).unwrap(), hash);
+        assert_eq!(store.get_doc(&hash).unwrap().unwrap().hash(), &hash);
+        assert!(store.get_doc(schema.hash()).unwrap().is_none());
+
+        for i in 0u8..10 {
+            let entry = NewEntry::new("num", &parent, i).unwrap();
+            let entry = schema
+                .validate_new_entry(entry)
+                .unwrap()
+                .complete()
+                .unwrap();
+            store.put_entry(entry).unwrap();
+        }
+        let entry = NewEntry::new("other", &parent, 100u8).unwrap();
+        let entry = schema
+            .validate_new_entry(entry)
+            .unwrap()
+            .complete()
+            .unwrap();
+        store.put_entry(entry).unwrap();
+        assert_eq!(store.get_entries(&hash, "num").unwrap().len(), 10);
+        assert_eq!(store.get_entries(&hash, "other").unwrap().len(), 1);
+        assert!(store.get_entries(schema.hash(), "num").unwrap().is_empty());
+
+        let query = NewQuery::new("num", IntValidator::new().min(7).build());
+        let query = schema
+            .decode_query(schema.encode_query(query).unwrap())
+            .unwrap();
+        let mut found: Vec<u8> = store
+            .query_entries(&hash, &query)
+            .unwrap()
+            .iter()
+            .map(|e| e.deserialize().unwrap())
+            .collect();
+        found.sort_unstable();
+        assert_eq!(found, vec![7, 8, 9]);
+        hash
+    }
+
+    #[test]
+    fn memory_store() {
+        let mut store = MemoryStore::new();
+        exercise(&mut store);
+        assert_eq!(store.doc_count(), 1);
+        assert_eq!(store.entry_count(), 11);
+    }
+
+    #[test]
+    fn dir_store() {
+        let root = std::env::temp_dir().join(format!("fog-pack-store-{}", std::process::id()));
+        let mut store = DirStore::open(&root).unwrap();
+        let parent = exercise(&mut store);
+
+        // Reopening the store finds everything again
+        let store = DirStore::open(&root).unwrap();
+        assert_eq!(fs::read_dir(root.join("docs")).unwrap().count(), 1);
+        assert!(store.get_doc(&parent).unwrap().is_some());
+        assert_eq!(store.get_entries(&parent, "num").unwrap().len(), 10);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn dir_store_concurrent() {
+        let root = std::env::temp_dir().join(format!("fog-pack-store-conc-{}", std::process::id()));
+        let doc = NewDocument::new(None, "shared").unwrap();
+        let doc = crate::schema::NoSchema::validate_new_doc(doc).unwrap();
+
+        // Writers racing on the same document each use their own temporary file
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let mut store = DirStore::open(&root).unwrap();
+                let doc = doc.clone();
+                std::thread::spawn(move || store.put_doc(doc).unwrap())
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(&handle.join().unwrap(), doc.hash());
+        }
+        assert_eq!(fs::read_dir(root.join("docs")).unwrap().count(), 1);
+        let store = DirStore::open(&root).unwrap();
+        assert!(store.get_doc(doc.hash()).unwrap().is_some());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,201 @@
+//! Property-testing strategies for fog-pack types.
+//!
+//! Enabled with the `proptest` feature. These are [`proptest`] strategies for generating
+//! fog-pack values, validators, and schema documents, for testing code that handles fog-pack data.
+//! Generated values are kept small enough to always fit in a document, and floating-point values
+//! are never NaN, so values can be compared after a round trip.
+//!
+//! ```
+//! # use fog_pack::{document::*, schema::*, strategy};
+//! # use proptest::prelude::*;
+//! proptest! {
+//!     fn roundtrip(value in strategy::value()) {
+//!         let doc = NoSchema::validate_new_doc(NewDocument::new(None, &value).unwrap()).unwrap();
+//!         let (_, encoded) = NoSchema::encode_doc(doc).unwrap();
+//!         let doc = NoSchema::decode_doc(encoded).unwrap();
+//!         prop_assert_eq!(doc.deserialize::<fog_pack::types::Value>().unwrap(), value);
+//!     }
+//! }
+//! # fn main() { roundtrip(); }
+//! ```
+//!
+//! The `arbitrary` feature separately implements [`arbitrary::Arbitrary`] for [`Value`],
+//! [`Integer`], and [`Timestamp`], for use with coverage-guided fuzzers.
+
+use crate::{
+    document::Document,
+    schema::SchemaBuilder,
+    types::{Hash, Integer, Timestamp, Value},
+    validator::*,
+};
+use proptest::prelude::*;
+
+/// Generate any integer, across the full signed and unsigned range.
+pub fn integer() -> impl Strategy<Value = Integer> {
+    prop_oneof![
+        any::<u64>().prop_map(Integer::from),
+        any::<i64>().prop_map(Integer::from),
+        // Small values are the most common in practice, and hit the compact encodings
+        (-200i64..200).prop_map(Integer::from),
+    ]
+}
+
+/// Generate any valid timestamp.
+pub fn timestamp() -> impl Strategy<Value = Timestamp> {
+    (any::<i64>(), 0u32..1_000_000_000)
+        .prop_map(|(secs, nanos)| Timestamp::new(secs, nanos).expect("nanoseconds are in range"))
+}
+
+/// Generate a hash of some arbitrary data.
+pub fn hash() -> impl Strategy<Value = Hash> {
+    prop::collection::vec(any::<u8>(), 0..64).prop_map(Hash::new)
+}
+
+fn float32() -> impl Strategy<Value = f32> {
+    use prop::num::f32::*;
+    POSITIVE | NEGATIVE | NORMAL | SUBNORMAL | ZERO | INFINITE
+}
+
+fn float64() -> impl Strategy<Value = f64> {
+    use prop::num::f64::*;
+    POSITIVE | NEGATIVE | NORMAL | SUBNORMAL | ZERO | INFINITE
+}
+
+/// Generate a value that holds no other values.
+pub fn leaf_value() -> impl Strategy<Value = Value> {
+    prop_oneof![
+        Just(Value::Null),
+        any::<bool>().prop_map(Value::Bool),
+        integer().prop_map(Value::Int),
+        ".{0,32}".prop_map(Value::Str),
+        float32().prop_map(Value::F32),
+        float64().prop_map(Value::F64),
+        prop::collection::vec(any::<u8>(), 0..64).prop_map(Value::Bin),
+        timestamp().prop_map(Value::Timestamp),
+        hash().prop_map(Value::Hash),
+    ]
+}
+
+/// Generate any value, nesting arrays and maps up to 4 levels deep.
+///
+/// Identities, stream and lock IDs, and lockboxes are never generated, as making them requires
+/// randomly generated keys.
+pub fn value() -> impl Strategy<Value = Value> {
+    leaf_value().prop_recursive(4, 64, 8, |inner| {
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 0..8).prop_map(Value::Array),
+            prop::collection::btree_map(".{0,16}", inner, 0..8).prop_map(Value::Map),
+        ]
+    })
+}
+
+/// Generate a validator without any nested validators.
+pub fn leaf_validator() -> impl Strategy<Value = Validator> {
+    prop_oneof![
+        Just(Validator::Any),
+        Just(Validator::new_null()),
+        Just(BoolValidator::new().build()),
+        (integer(), integer())
+            .prop_map(|(a, b)| IntValidator::new().min(a.min(b)).max(a.max(b)).build()),
+        (0u32..64, any::<bool>()).prop_map(|(max_len, nfc)| {
+            let validator = StrValidator::new().max_len(max_len);
+            if nfc {
+                validator.normalize(Normalize::NFC).build()
+            } else {
+                validator.build()
+            }
+        }),
+        (0u32..64).prop_map(|max_len| BinValidator::new().max_len(max_len).build()),
+        Just(F32Validator::new().build()),
+        Just(F64Validator::new().build()),
+        Just(NumValidator::new().build()),
+        Just(TimeValidator::new().build()),
+        Just(HashValidator::new().build()),
+    ]
+}
+
+/// Generate any validator, nesting array, map, and multi validators up to 3 levels deep.
+pub fn validator() -> impl Strategy<Value = Validator> {
+    leaf_validator().prop_recursive(3, 32, 6, |inner| {
+        prop_oneof![
+            (inner.clone(), 0u32..16).prop_map(|(items, max_len)| {
+                ArrayValidator::new().items(items).max_len(max_len).build()
+            }),
+            (
+                prop::collection::btree_map("[a-z]{1,8}", inner.clone(), 0..6),
+                prop::collection::btree_map("[a-z]{1,8}", inner.clone(), 0..6),
+            )
+                .prop_map(|(req, opt)| {
+                    let mut map = MapValidator::new();
+                    for (k, v) in req {
+                        map = map.req_add(k, v);
+                    }
+                    for (k, v) in opt {
+                        map = map.opt_add(k, v);
+                    }
+                    map.build()
+                }),
+            prop::collection::vec(inner, 1..4).prop_map(|options| {
+                options
+                    .into_iter()
+                    .fold(MultiValidator::new(), |multi, v| multi.push(v))
+                    .build()
+            }),
+        ]
+    })
+}
+
+/// Generate a schema document, with a random document validator and up to 4 entry types.
+pub fn schema() -> impl Strategy<Value = Document> {
+    (
+        validator(),
+        prop::collection::btree_map("[a-z]{1,8}", validator(), 0..4),
+        ".{0,32}",
+    )
+        .prop_map(|(doc, entries, description)| {
+            let mut builder = SchemaBuilder::new(doc).description(&description);
+            for (key, validator) in entries {
+                builder = builder.entry_add(&key, validator, None);
+            }
+            builder
+                .build()
+                .expect("generated schemas should always build")
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        document::NewDocument,
+        schema::{NoSchema, Schema},
+    };
+
+    proptest! {
+        #[test]
+        fn values_roundtrip(value in value()) {
+            let doc = NoSchema::validate_new_doc(NewDocument::new(None, &value).unwrap()).unwrap();
+            let (_, encoded) = NoSchema::encode_doc(doc).unwrap();
+            let doc = NoSchema::decode_doc(encoded).unwrap();
+            prop_assert_eq!(doc.deserialize::<Value>().unwrap(), value);
+        }
+
+        #[test]
+        fn schemas_load(schema_doc in schema()) {
+            let (_, encoded) = NoSchema::encode_doc(schema_doc).unwrap();
+            let schema_doc = NoSchema::decode_doc(encoded).unwrap();
+            Schema::from_doc(&schema_doc).unwrap();
+        }
+
+        #[test]
+        fn validation_never_panics(schema_doc in schema(), value in value()) {
+            let schema = Schema::from_doc(&schema_doc).unwrap();
+            let doc = NewDocument::new(Some(schema.hash()), &value).unwrap();
+            if let Ok(doc) = schema.validate_new_doc(doc) {
+                let (_, encoded) = schema.encode_doc(doc).unwrap();
+                let doc = schema.decode_doc(encoded).unwrap();
+                prop_assert_eq!(doc.deserialize::<Value>().unwrap(), value);
+            }
+        }
+    }
+}
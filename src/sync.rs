@@ -0,0 +1,823 @@
+//! Messages for replicating documents and entries between nodes.
+//!
+//! Two fog-pack nodes that want the same data need to work out what each one is missing. This
+//! module provides the pieces of that conversation as a [`SyncMessage`]:
+//!
+//! - [`Have`][SyncMessage::Have] and [`Want`][SyncMessage::Want] list document hashes directly.
+//! - [`Digest`][SyncMessage::Digest] carries a [`SetDigest`], a Bloom filter summarizing a large
+//!     set of hashes. A node receiving one can tell which of its own documents the sender
+//!     definitely doesn't have.
+//! - [`Reconcile`][SyncMessage::Reconcile] carries a [`HashSetDigest`], an invertible Bloom
+//!     lookup table. Subtracting one from a digest of the local documents recovers the exact
+//!     hashes held by only one side, as long as there aren't many more of them than the digest was
+//!     sized for.
+//! - [`Ranges`][SyncMessage::Ranges] carries a [`RangeSummary`], which splits the entries under
+//!     one document and key into ranges by the value of an ordered field, with a count and
+//!     fingerprint for each. A node receiving one can find the ranges where its entries differ and
+//!     narrow in on them, without either side listing every entry.
+//!
+//! Messages are themselves encoded as fog-pack documents using a built-in [`schema`], so they get
+//! the same validation and size limits as any other data.
+//!
+//! ```
+//! # use fog_pack::{document::*, schema::*, sync::*};
+//! # fn main() -> fog_pack::error::Result<()> {
+//! let doc = NoSchema::validate_new_doc(NewDocument::new(None, "held")?)?;
+//! let digest = SetDigest::from_hashes([doc.hash()], 0.01);
+//! let encoded = SyncMessage::Digest(digest).encode()?;
+//!
+//! // On the other node
+//! if let SyncMessage::Digest(digest) = SyncMessage::decode(encoded)? {
+//!     assert!(digest.may_contain(doc.hash()));
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    document::NewDocument,
+    entry::Entry,
+    error::{Error, Result},
+    schema::{Schema, SchemaBuilder},
+    types::{Hash, Path, Value, ValueRef},
+    validator::*,
+};
+use serde::{Deserialize, Serialize};
+use std::{cmp::Ordering, collections::HashSet, convert::TryFrom, sync::OnceLock};
+
+static SCHEMA: OnceLock<Schema> = OnceLock::new();
+
+/// Get the built-in schema that sync messages are encoded with.
+///
+/// The schema document is built the same way every time, so its hash is the same on every node.
+pub fn schema() -> &'static Schema {
+    SCHEMA.get_or_init(|| {
+        let hashes = ArrayValidator::new()
+            .items(HashValidator::new().build())
+            .build();
+        let digest = MapValidator::new()
+            .req_add("hashes", IntValidator::new().min(1u8).build())
+            .req_add("bits", BinValidator::new().min_len(1).build())
+            .build();
+        let reconcile = MapValidator::new()
+            .req_add(
+                "counts",
+                ArrayValidator::new()
+                    .items(IntValidator::new().build())
+                    .build(),
+            )
+            .req_add("keys", BinValidator::new().build())
+            .req_add(
+                "checks",
+                ArrayValidator::new()
+                    .items(IntValidator::new().min(0u8).build())
+                    .build(),
+            )
+            .build();
+        let range = MapValidator::new()
+            .opt_add("start", Validator::Any)
+            .req_add("count", IntValidator::new().min(0u8).build())
+            .req_add("fingerprint", HashValidator::new().build())
+            .build();
+        let ranges = MapValidator::new()
+            .req_add("parent", HashValidator::new().build())
+            .req_add("key", StrValidator::new().build())
+            .req_add("field", StrValidator::new().build())
+            .req_add("ranges", ArrayValidator::new().items(range).build())
+            .build();
+        let message = EnumValidator::new()
+            .insert("Have", Some(hashes.clone()))
+            .insert("Want", Some(hashes))
+            .insert("Digest", Some(digest))
+            .insert("Reconcile", Some(reconcile))
+            .insert("Ranges", Some(ranges))
+            .build();
+        let doc = SchemaBuilder::new(message)
+            .name("fog-pack sync")
+            .description("Messages for replicating fog-pack documents and entries")
+            .build()
+            .expect("built-in sync schema should be valid");
+        Schema::from_doc(&doc).expect("built-in sync schema should be valid")
+    })
+}
+
+/// A single message in a sync exchange.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SyncMessage {
+    /// Documents the sender holds.
+    Have(Vec<Hash>),
+    /// Documents the sender wants.
+    Want(Vec<Hash>),
+    /// An approximate summary of the documents the sender holds.
+    Digest(SetDigest),
+    /// An invertible summary of the documents the sender holds.
+    Reconcile(HashSetDigest),
+    /// A summary of the entries the sender holds for one document and key.
+    Ranges(RangeSummary),
+}
+
+impl SyncMessage {
+    /// Encode the message as a document using the built-in sync [`schema`].
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let schema = schema();
+        let doc = NewDocument::new(Some(schema.hash()), self)?;
+        let doc = schema.validate_new_doc(doc)?;
+        let (_, encoded) = schema.encode_doc(doc)?;
+        Ok(encoded)
+    }
+
+    /// Decode and validate a message encoded with [`encode`][SyncMessage::encode].
+    pub fn decode(encoded: Vec<u8>) -> Result<Self> {
+        schema().decode_doc(encoded)?.deserialize()
+    }
+}
+
+// Get the two 64-bit halves used for double hashing. Hashes are already uniformly distributed, so
+// their digest bytes are used directly.
+fn bloom_seeds(hash: &Hash) -> (u64, u64) {
+    let bytes = hash.as_ref();
+    // Skip the version byte
+    let digest = bytes.get(1..).unwrap_or(bytes);
+    let mut seeds = [0u64; 2];
+    for (i, b) in digest.iter().take(16).enumerate() {
+        seeds[i / 8] |= (*b as u64) << ((i % 8) * 8);
+    }
+    // An odd step keeps the probes distinct for power-of-two sizes
+    (seeds[0], seeds[1] | 1)
+}
+
+/// An approximate set of hashes, as a Bloom filter.
+///
+/// A digest never reports a hash it holds as missing, but may report a hash it doesn't hold as
+/// present, at roughly the false positive rate it was created with. It is much smaller than a
+/// list of the hashes: about 10 bits per hash for a 1% false positive rate.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SetDigest {
+    hashes: u8,
+    #[serde(with = "serde_bytes")]
+    bits: Vec<u8>,
+}
+
+impl SetDigest {
+    /// Create an empty digest sized for `expected` hashes with the given false positive rate,
+    /// which should be between 0 and 1.
+    pub fn new(expected: usize, false_positive: f64) -> Self {
+        let ln2 = std::f64::consts::LN_2;
+        let p = false_positive.clamp(1e-9, 0.5);
+        let n = expected.max(1) as f64;
+        let bits = (-n * p.ln() / (ln2 * ln2)).ceil().max(8.0);
+        let hashes = (bits / n * ln2).round().clamp(1.0, 32.0);
+        Self {
+            hashes: hashes as u8,
+            bits: vec![0u8; (bits as usize + 7) / 8],
+        }
+    }
+
+    /// Create a digest holding the given hashes, with the given false positive rate.
+    pub fn from_hashes<'a>(
+        hashes: impl IntoIterator<Item = &'a Hash>,
+        false_positive: f64,
+    ) -> Self {
+        let hashes: Vec<&Hash> = hashes.into_iter().collect();
+        let mut digest = Self::new(hashes.len(), false_positive);
+        for hash in hashes {
+            digest.insert(hash);
+        }
+        digest
+    }
+
+    fn probes(&self, hash: &Hash) -> impl Iterator<Item = usize> {
+        let (h1, h2) = bloom_seeds(hash);
+        let len = (self.bits.len() as u64 * 8).max(1);
+        (0..self.hashes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % len) as usize)
+    }
+
+    /// Add a hash to the digest.
+    pub fn insert(&mut self, hash: &Hash) {
+        for bit in self.probes(hash) {
+            self.bits[bit / 8] |= 1 << (bit % 8);
+        }
+    }
+
+    /// Check if the digest may hold a hash. If this returns false, the hash was definitely never
+    /// added.
+    pub fn may_contain(&self, hash: &Hash) -> bool {
+        !self.bits.is_empty()
+            && self
+                .probes(hash)
+                .all(|bit| self.bits[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    /// Pick out the hashes that are definitely not in the digest. Run this over the documents
+    /// held locally to find the ones a peer is missing.
+    pub fn missing<'a>(&self, hashes: impl IntoIterator<Item = &'a Hash>) -> Vec<Hash> {
+        hashes
+            .into_iter()
+            .filter(|h| !self.may_contain(h))
+            .cloned()
+            .collect()
+    }
+
+    /// Get the size of the digest's filter, in bytes.
+    pub fn size(&self) -> usize {
+        self.bits.len()
+    }
+}
+
+// The bytes a digest cell holds for each hash: a version byte and a 32-byte digest. Longer hashes
+// are truncated, so only hashes of this length can be recovered.
+const CELL_KEY_LEN: usize = 33;
+// How many cells each hash is added to. Each goes in a separate third of the table.
+const CELL_PROBES: usize = 3;
+
+// A check value that isn't linear over XOR, so a cell holding several hashes XORed together is
+// very unlikely to look like it holds only one. Uses the splitmix64 finalizer.
+fn cell_check(hash: &Hash) -> u64 {
+    let bytes = hash.as_ref();
+    let mut x = 0u64;
+    for (i, b) in bytes.iter().skip(17).take(8).enumerate() {
+        x |= (*b as u64) << (i * 8);
+    }
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+/// An exact, invertible summary of a set of hashes, as an invertible Bloom lookup table.
+///
+/// Where a [`SetDigest`] can only say which hashes the other side definitely lacks, a
+/// `HashSetDigest` finds the exact difference between two sets: take the
+/// [`difference`][HashSetDigest::difference] of digests from both sides and it lists the hashes
+/// held by only one of them. Its size depends on how many differences it needs to find, not on
+/// how many hashes it holds, so two nodes holding nearly the same large collection can compare it
+/// with a small message. If the sets differ by much more than the digest was sized for, the
+/// difference can't be recovered, and a larger digest is needed.
+///
+/// ```
+/// # use fog_pack::{document::*, schema::*, sync::*};
+/// # fn main() -> fog_pack::error::Result<()> {
+/// let docs: Vec<Document> = (0..100u32)
+///     .map(|i| NoSchema::validate_new_doc(NewDocument::new(None, i)?))
+///     .collect::<Result<_, _>>()?;
+///
+/// let mine = HashSetDigest::from_hashes(docs[..90].iter().map(|d| d.hash()), 20);
+/// let theirs = HashSetDigest::from_hashes(docs[5..].iter().map(|d| d.hash()), 20);
+/// let diff = mine.difference(&theirs)?.expect("difference fits in the digest");
+/// assert_eq!(diff.ours.len(), 5);
+/// assert_eq!(diff.theirs.len(), 10);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "RawHashSetDigest")]
+pub struct HashSetDigest {
+    counts: Vec<i64>,
+    #[serde(with = "serde_bytes")]
+    keys: Vec<u8>,
+    checks: Vec<u64>,
+}
+
+#[derive(Deserialize)]
+struct RawHashSetDigest {
+    counts: Vec<i64>,
+    #[serde(with = "serde_bytes")]
+    keys: Vec<u8>,
+    checks: Vec<u64>,
+}
+
+impl TryFrom<RawHashSetDigest> for HashSetDigest {
+    type Error = String;
+
+    fn try_from(raw: RawHashSetDigest) -> std::result::Result<Self, Self::Error> {
+        let cells = raw.counts.len();
+        if cells == 0 || cells % CELL_PROBES != 0 {
+            return Err(format!(
+                "digest must have a non-zero multiple of {} cells, but has {}",
+                CELL_PROBES, cells
+            ));
+        }
+        if raw.keys.len() != cells * CELL_KEY_LEN || raw.checks.len() != cells {
+            return Err("digest cells don't all have a count, key, and check".into());
+        }
+        Ok(Self {
+            counts: raw.counts,
+            keys: raw.keys,
+            checks: raw.checks,
+        })
+    }
+}
+
+/// The hashes found by [`HashSetDigest::difference`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SetDifference {
+    /// Hashes held only by the digest `difference` was called on.
+    pub ours: Vec<Hash>,
+    /// Hashes held only by the other digest.
+    pub theirs: Vec<Hash>,
+}
+
+impl HashSetDigest {
+    /// Create an empty digest, sized to recover differences of up to about `expected` hashes.
+    pub fn new(expected: usize) -> Self {
+        let per_probe = (expected * 2).div_ceil(CELL_PROBES) + 2;
+        let cells = per_probe * CELL_PROBES;
+        Self {
+            counts: vec![0; cells],
+            keys: vec![0; cells * CELL_KEY_LEN],
+            checks: vec![0; cells],
+        }
+    }
+
+    /// Create a digest holding the given hashes, sized to recover differences of up to about
+    /// `expected` hashes.
+    pub fn from_hashes<'a>(hashes: impl IntoIterator<Item = &'a Hash>, expected: usize) -> Self {
+        let mut digest = Self::new(expected);
+        for hash in hashes {
+            digest.insert(hash);
+        }
+        digest
+    }
+
+    fn cells_for(&self, hash: &Hash) -> [usize; CELL_PROBES] {
+        let (h1, h2) = bloom_seeds(hash);
+        let per_probe = (self.counts.len() / CELL_PROBES) as u64;
+        let mut cells = [0; CELL_PROBES];
+        for (i, cell) in cells.iter_mut().enumerate() {
+            let offset = h1.wrapping_add((i as u64).wrapping_mul(h2)) % per_probe;
+            *cell = i * per_probe as usize + offset as usize;
+        }
+        cells
+    }
+
+    fn toggle(&mut self, hash: &Hash, delta: i64) {
+        let check = cell_check(hash);
+        for cell in self.cells_for(hash) {
+            self.counts[cell] = self.counts[cell].wrapping_add(delta);
+            self.checks[cell] ^= check;
+            let key = &mut self.keys[cell * CELL_KEY_LEN..(cell + 1) * CELL_KEY_LEN];
+            let bytes: &[u8] = hash.as_ref();
+            for (k, b) in key.iter_mut().zip(bytes) {
+                *k ^= b;
+            }
+        }
+    }
+
+    /// Add a hash to the digest.
+    pub fn insert(&mut self, hash: &Hash) {
+        self.toggle(hash, 1);
+    }
+
+    /// Remove a hash from the digest. Removing a hash that was never added leaves it in the
+    /// digest as a "negative" entry, which shows up on the other side of a difference.
+    pub fn remove(&mut self, hash: &Hash) {
+        self.toggle(hash, -1);
+    }
+
+    /// Check if the digest is empty, either because nothing was added or because everything
+    /// added was removed again.
+    pub fn is_empty(&self) -> bool {
+        self.counts.iter().all(|c| *c == 0)
+            && self.checks.iter().all(|c| *c == 0)
+            && self.keys.iter().all(|k| *k == 0)
+    }
+
+    /// Get the number of cells in the digest.
+    pub fn cells(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Find the hashes held by only one of two digests. Fails if the digests have different
+    /// sizes. Returns `None` if there are too many differences to recover with digests of this
+    /// size.
+    pub fn difference(&self, other: &HashSetDigest) -> Result<Option<SetDifference>> {
+        if self.counts.len() != other.counts.len() {
+            return Err(Error::BadEncode(format!(
+                "can't compare digests with {} and {} cells",
+                self.counts.len(),
+                other.counts.len()
+            )));
+        }
+        let mut diff = self.clone();
+        for (a, b) in diff.counts.iter_mut().zip(other.counts.iter()) {
+            *a = a.wrapping_sub(*b);
+        }
+        for (a, b) in diff.checks.iter_mut().zip(other.checks.iter()) {
+            *a ^= b;
+        }
+        for (a, b) in diff.keys.iter_mut().zip(other.keys.iter()) {
+            *a ^= b;
+        }
+        Ok(diff.peel())
+    }
+
+    // Repeatedly pull out hashes from cells holding only one of them, until nothing is left. A
+    // crafted digest can make a hash look pure again after it was peeled, so peeling the same
+    // hash twice, or more hashes than there are cells, means the digest is bad.
+    fn peel(mut self) -> Option<SetDifference> {
+        let mut found = SetDifference::default();
+        let mut peeled = HashSet::new();
+        let mut pending: Vec<usize> = (0..self.counts.len()).collect();
+        while let Some(cell) = pending.pop() {
+            let count = self.counts[cell];
+            if count != 1 && count != -1 {
+                continue;
+            }
+            let key = &self.keys[cell * CELL_KEY_LEN..(cell + 1) * CELL_KEY_LEN];
+            let Ok(hash) = Hash::try_from(key) else {
+                continue;
+            };
+            let cells = self.cells_for(&hash);
+            if cell_check(&hash) != self.checks[cell] || !cells.contains(&cell) {
+                continue;
+            }
+            if peeled.len() >= self.counts.len() || !peeled.insert(hash.clone()) {
+                return None;
+            }
+            self.toggle(&hash, -count);
+            if count == 1 {
+                found.ours.push(hash);
+            } else {
+                found.theirs.push(hash);
+            }
+            pending.extend(cells);
+        }
+        self.is_empty().then_some(found)
+    }
+}
+
+// Pick out a value that can be used to order entries. These are the types whose validators
+// support the `ord` query permission.
+pub(crate) fn ord_value(value: ValueRef) -> Option<Value> {
+    match value {
+        ValueRef::Int(_)
+        | ValueRef::F32(_)
+        | ValueRef::F64(_)
+        | ValueRef::Timestamp(_)
+        | ValueRef::Bin(_) => Some(value.to_owned()),
+        _ => None,
+    }
+}
+
+// Order two ordering values. Values of different types are ordered by type.
+pub(crate) fn ord_cmp(a: &Value, b: &Value) -> Ordering {
+    fn rank(v: &Value) -> u8 {
+        match v {
+            Value::Int(_) => 0,
+            Value::F32(_) => 1,
+            Value::F64(_) => 2,
+            Value::Timestamp(_) => 3,
+            Value::Bin(_) => 4,
+            _ => 5,
+        }
+    }
+    match (a, b) {
+        (Value::Int(a), Value::Int(b)) => a.cmp(b),
+        (Value::F32(a), Value::F32(b)) => a.total_cmp(b),
+        (Value::F64(a), Value::F64(b)) => a.total_cmp(b),
+        (Value::Timestamp(a), Value::Timestamp(b)) => a.cmp(b),
+        (Value::Bin(a), Value::Bin(b)) => a.cmp(b),
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+// Get the count and fingerprint of a set of entry hashes. The fingerprint doesn't depend on the
+// order the entries were given in.
+fn fingerprint(hashes: &mut [&Hash]) -> (u64, Hash) {
+    hashes.sort_unstable_by(|a, b| a.as_ref().cmp(b.as_ref()));
+    let mut bytes = Vec::with_capacity(hashes.len() * 33);
+    for hash in hashes.iter() {
+        bytes.extend_from_slice(hash.as_ref());
+    }
+    (hashes.len() as u64, Hash::new(bytes))
+}
+
+/// One range of entries in a [`RangeSummary`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EntryRange {
+    /// The lowest field value in the range, inclusive. The range runs up to the start of the next
+    /// range. The first range has no start, and includes everything below the next range.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<Value>,
+    /// The number of entries in the range.
+    pub count: u64,
+    /// A hash over the hashes of every entry in the range.
+    pub fingerprint: Hash,
+}
+
+/// A summary of the entries under one document and key, split into ranges by the value of an
+/// ordered field.
+///
+/// The field is usually one whose validator has `ord` set, like a timestamp or sequence number,
+/// so that ranges line up with how the entries are naturally queried. Entries without an integer,
+/// floating-point, timestamp, or binary value at the field are left out of the summary, and need
+/// to be synced some other way.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RangeSummary {
+    /// The parent document of the entries.
+    pub parent: Hash,
+    /// The key of the entries.
+    pub key: String,
+    /// The field the entries are ordered by, as a JSON-Pointer-style path.
+    pub field: String,
+    /// The ranges, in order. Together they cover every possible field value.
+    pub ranges: Vec<EntryRange>,
+}
+
+impl RangeSummary {
+    /// Summarize a set of entries, putting about `span` entries in each range. Entries that don't
+    /// match `parent` and `key` are ignored. Entries sharing a field value always end up in the
+    /// same range.
+    pub fn new<'a>(
+        parent: &Hash,
+        key: &str,
+        field: &Path,
+        entries: impl IntoIterator<Item = &'a Entry>,
+        span: usize,
+    ) -> Result<Self> {
+        let mut sorted = Self::ordered(parent, key, field, entries)?;
+        sorted.sort_by(|a, b| ord_cmp(&a.0, &b.0));
+        let span = span.max(1);
+
+        let mut ranges = Vec::new();
+        let mut start = None;
+        let mut chunk: Vec<&Hash> = Vec::new();
+        let mut iter = sorted.iter().peekable();
+        while let Some((value, hash)) = iter.next() {
+            chunk.push(hash);
+            let boundary = match iter.peek() {
+                None => true,
+                Some((next, _)) => chunk.len() >= span && ord_cmp(value, next) == Ordering::Less,
+            };
+            if boundary {
+                let (count, fingerprint) = fingerprint(&mut chunk);
+                ranges.push(EntryRange {
+                    start: start.take(),
+                    count,
+                    fingerprint,
+                });
+                chunk.clear();
+                start = iter.peek().map(|(next, _)| next.clone());
+            }
+        }
+        if ranges.is_empty() {
+            let (count, fingerprint) = fingerprint(&mut chunk);
+            ranges.push(EntryRange {
+                start: None,
+                count,
+                fingerprint,
+            });
+        }
+
+        Ok(Self {
+            parent: parent.clone(),
+            key: key.to_owned(),
+            field: field.to_string(),
+            ranges,
+        })
+    }
+
+    fn ordered<'a>(
+        parent: &Hash,
+        key: &str,
+        field: &Path,
+        entries: impl IntoIterator<Item = &'a Entry>,
+    ) -> Result<Vec<(Value, &'a Hash)>> {
+        let mut ordered = Vec::new();
+        for entry in entries {
+            if entry.parent() != parent || entry.key() != key {
+                continue;
+            }
+            if let Some(value) = entry.get_path(field)?.and_then(ord_value) {
+                ordered.push((value, entry.hash()));
+            }
+        }
+        Ok(ordered)
+    }
+
+    /// Compare this summary against a local set of entries, returning the index of every range
+    /// whose local count or fingerprint doesn't match. An empty result means both sides hold the
+    /// same entries.
+    ///
+    /// The differing ranges can be narrowed down by summarizing just the entries within them with
+    /// a smaller span, or resolved by exchanging [`Have`][SyncMessage::Have] lists.
+    pub fn differing<'a>(
+        &self,
+        entries: impl IntoIterator<Item = &'a Entry>,
+    ) -> Result<Vec<usize>> {
+        let field = match Path::parse(&self.field) {
+            Some(field) => field,
+            None => return Ok((0..self.ranges.len()).collect()),
+        };
+        let mut buckets: Vec<Vec<&Hash>> = vec![Vec::new(); self.ranges.len()];
+        for (value, hash) in Self::ordered(&self.parent, &self.key, &field, entries)? {
+            // The last range whose start is at or below the value holds it
+            let index = self
+                .ranges
+                .iter()
+                .rposition(|r| match &r.start {
+                    None => true,
+                    Some(start) => ord_cmp(start, &value) != Ordering::Greater,
+                })
+                .unwrap_or(0);
+            if let Some(bucket) = buckets.get_mut(index) {
+                bucket.push(hash);
+            }
+        }
+        Ok(buckets
+            .iter_mut()
+            .zip(self.ranges.iter())
+            .enumerate()
+            .filter(|(_, (bucket, range))| {
+                let (count, fingerprint) = fingerprint(bucket);
+                count != range.count || fingerprint != range.fingerprint
+            })
+            .map(|(i, _)| i)
+            .collect())
+    }
+
+    /// Check if a field value falls within one of the summary's ranges.
+    pub fn in_range(&self, index: usize, value: &Value) -> bool {
+        let above_start = match self.ranges.get(index) {
+            None => return false,
+            Some(EntryRange { start: None, .. }) => true,
+            Some(EntryRange {
+                start: Some(start), ..
+            }) => ord_cmp(start, value) != Ordering::Greater,
+        };
+        let below_end = match self.ranges.get(index + 1) {
+            Some(EntryRange {
+                start: Some(end), ..
+            }) => ord_cmp(value, end) == Ordering::Less,
+            _ => true,
+        };
+        above_start && below_end
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        document::{Document, NewDocument},
+        entry::NewEntry,
+        schema::NoSchema,
+    };
+
+    fn doc(i: u32) -> Document {
+        NoSchema::validate_new_doc(NewDocument::new(None, i).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn digest() {
+        let held: Vec<Document> = (0..1000).map(doc).collect();
+        let digest = SetDigest::from_hashes(held.iter().map(|d| d.hash()), 0.01);
+        assert!(digest.size() < 2000);
+        assert!(held.iter().all(|d| digest.may_contain(d.hash())));
+
+        let others: Vec<Document> = (1000..3000).map(doc).collect();
+        let missing = digest.missing(others.iter().map(|d| d.hash()));
+        // Roughly 1% of the others should be false positives
+        assert!(
+            missing.len() > 1900,
+            "{} false positives",
+            2000 - missing.len()
+        );
+
+        let empty = SetDigest::new(0, 0.01);
+        assert!(!empty.may_contain(held[0].hash()));
+    }
+
+    #[test]
+    fn hash_set_digest() {
+        let docs: Vec<Document> = (0..500).map(doc).collect();
+        let hashes = || docs.iter().map(|d| d.hash());
+        let mut ours = HashSetDigest::from_hashes(hashes().take(480), 60);
+        let theirs = HashSetDigest::from_hashes(hashes().skip(20), 60);
+        assert!(!ours.is_empty());
+
+        let mut diff = ours.difference(&theirs).unwrap().unwrap();
+        diff.ours.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+        diff.theirs.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+        let mut expected_ours: Vec<Hash> = hashes().take(20).cloned().collect();
+        let mut expected_theirs: Vec<Hash> = hashes().skip(480).cloned().collect();
+        expected_ours.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+        expected_theirs.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+        assert_eq!(diff.ours, expected_ours);
+        assert_eq!(diff.theirs, expected_theirs);
+
+        // Removing hashes brings the sets back together
+        for hash in hashes().take(20) {
+            ours.remove(hash);
+        }
+        for hash in hashes().skip(480) {
+            ours.insert(hash);
+        }
+        assert_eq!(ours, theirs);
+        let same = ours.difference(&theirs).unwrap().unwrap();
+        assert!(same.ours.is_empty() && same.theirs.is_empty());
+
+        // Too many differences can't be recovered, and sizes must match
+        let empty = HashSetDigest::new(60);
+        assert!(empty.is_empty());
+        assert!(empty.difference(&theirs).unwrap().is_none());
+        assert!(empty.difference(&HashSetDigest::new(80)).is_err());
+
+        // Malformed digests are rejected on decode
+        let encoded = SyncMessage::Reconcile(theirs.clone()).encode().unwrap();
+        assert_eq!(
+            SyncMessage::decode(encoded).unwrap(),
+            SyncMessage::Reconcile(theirs)
+        );
+        let mut bad = HashSetDigest::new(3);
+        bad.keys.pop();
+        let encoded = SyncMessage::Reconcile(bad).encode().unwrap();
+        assert!(SyncMessage::decode(encoded).is_err());
+    }
+
+    #[test]
+    fn hash_set_digest_cycle() {
+        // One cell claims to hold a hash that its other cells don't. Peeling it leaves the other
+        // cells holding the hash negatively, and peeling those puts it back, forever.
+        let hash = doc(0).hash().clone();
+        let mut crafted = HashSetDigest::new(3);
+        let cell = crafted.cells_for(&hash)[0];
+        crafted.counts[cell] = 1;
+        crafted.checks[cell] = cell_check(&hash);
+        crafted.keys[cell * CELL_KEY_LEN..(cell + 1) * CELL_KEY_LEN]
+            .copy_from_slice(&hash.as_ref()[..CELL_KEY_LEN]);
+        let encoded = SyncMessage::Reconcile(crafted).encode().unwrap();
+        let SyncMessage::Reconcile(crafted) = SyncMessage::decode(encoded).unwrap() else {
+            panic!("decoded the wrong message type");
+        };
+        assert!(crafted
+            .difference(&HashSetDigest::new(3))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn messages() {
+        let a = doc(1);
+        let b = doc(2);
+        let messages = vec![
+            SyncMessage::Have(vec![a.hash().clone(), b.hash().clone()]),
+            SyncMessage::Want(vec![]),
+            SyncMessage::Digest(SetDigest::from_hashes([a.hash()], 0.01)),
+            SyncMessage::Reconcile(HashSetDigest::from_hashes([a.hash(), b.hash()], 4)),
+            SyncMessage::Ranges(
+                RangeSummary::new(a.hash(), "k", &Path::new(), &Vec::new(), 10).unwrap(),
+            ),
+        ];
+        for message in messages {
+            let encoded = message.encode().unwrap();
+            assert_eq!(SyncMessage::decode(encoded).unwrap(), message);
+        }
+        assert_eq!(schema().hash(), schema().hash());
+    }
+
+    #[test]
+    fn ranges() {
+        let schema_doc = SchemaBuilder::new(Validator::Any)
+            .entry_add("log", Validator::Any, None)
+            .build()
+            .unwrap();
+        let schema = Schema::from_doc(&schema_doc).unwrap();
+        let parent = NewDocument::new(Some(schema.hash()), "parent").unwrap();
+        let parent = schema.validate_new_doc(parent).unwrap();
+        let entry_with = |seq: u32, tag: u32| {
+            let entry = NewEntry::new("log", &parent, (seq, tag)).unwrap();
+            schema
+                .validate_new_entry(entry)
+                .unwrap()
+                .complete()
+                .unwrap()
+        };
+        let entry = |seq: u32| entry_with(seq, 0);
+        let field = Path::new().index(0);
+        let ours: Vec<Entry> = (0..100).map(entry).collect();
+        let mut theirs: Vec<Entry> = (0..100).filter(|i| *i != 42).map(entry).collect();
+        theirs.push(entry(42));
+
+        let summary = RangeSummary::new(parent.hash(), "log", &field, &ours, 10).unwrap();
+        assert_eq!(summary.ranges.len(), 10);
+        assert!(summary.ranges[0].start.is_none());
+        assert_eq!(summary.ranges.iter().map(|r| r.count).sum::<u64>(), 100);
+        assert!(summary.differing(&theirs).unwrap().is_empty());
+
+        // Drop an entry and find the range it was in
+        theirs.pop();
+        let differing = summary.differing(&theirs).unwrap();
+        assert_eq!(differing, vec![4]);
+        assert!(summary.in_range(4, &Value::from(42u32)));
+        assert!(!summary.in_range(5, &Value::from(42u32)));
+
+        // Entries sharing a value stay in one range
+        let repeated: Vec<Entry> = (0..30u32)
+            .map(|i| entry_with(if i < 25 { 7 } else { i }, i))
+            .collect();
+        let summary = RangeSummary::new(parent.hash(), "log", &field, &repeated, 10).unwrap();
+        assert_eq!(summary.ranges.len(), 2);
+        assert_eq!(summary.ranges[0].count, 25);
+        assert_eq!(summary.ranges[1].start, Some(Value::from(25u32)));
+    }
+}
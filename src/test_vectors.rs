@@ -0,0 +1,337 @@
+//! Reference test vectors, for checking other fog-pack implementations against this one.
+//!
+//! A [`Corpus`] is a set of encoded documents, each with the outcome this crate expects when
+//! decoding it: whether it should decode and validate at all, and if so, the [`Value`] it holds
+//! and the hash it should have. Schema documents needed by the vectors travel with the corpus.
+//!
+//! A corpus is itself stored as a fog-pack document without a schema, so any implementation able
+//! to decode documents can read it. The standard corpus is kept in the repository's `vectors`
+//! directory, and can be regenerated with [`Corpus::standard`]. [`run`] checks a corpus against
+//! this crate, and doubles as a description of what another implementation's runner should do.
+//!
+//! ```
+//! # use fog_pack::test_vectors::*;
+//! # fn main() -> fog_pack::error::Result<()> {
+//! let encoded = Corpus::standard()?.encode()?;
+//! let report = run_encoded(encoded)?;
+//! assert!(report.is_ok(), "{:?}", report.failures);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    document::{Document, NewDocument},
+    error::Result,
+    fog_value,
+    schema::{NoSchema, Schema, SchemaBuilder},
+    types::{Hash, Timestamp, Value},
+    validator::*,
+};
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use std::collections::HashMap;
+
+/// A single test vector: an encoded document and what decoding it should produce.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Vector {
+    /// A short name describing what the vector tests.
+    pub name: String,
+    /// The schema to decode the document with, or `None` to decode it without one. The schema
+    /// document must be in the corpus.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<Hash>,
+    /// The encoded document.
+    #[serde(with = "serde_bytes")]
+    pub encoded: Vec<u8>,
+    /// Whether the document should successfully decode and validate.
+    pub valid: bool,
+    /// The data the document should decode to, if valid.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<Value>,
+    /// The hash the document should have, if valid.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<Hash>,
+}
+
+/// A set of test vectors, along with the schema documents they use.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Corpus {
+    /// What the corpus covers.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    /// Encoded schema documents, which must decode without a schema.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub schemas: Vec<ByteBuf>,
+    /// The test vectors.
+    pub vectors: Vec<Vector>,
+}
+
+impl Corpus {
+    /// Encode the corpus as a document without a schema.
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let doc = NoSchema::validate_new_doc(NewDocument::new(None, self)?)?;
+        let (_, encoded) = NoSchema::encode_doc(doc)?;
+        Ok(encoded)
+    }
+
+    /// Decode a corpus encoded with [`encode`][Corpus::encode].
+    pub fn decode(encoded: Vec<u8>) -> Result<Self> {
+        NoSchema::decode_doc(encoded)?.deserialize()
+    }
+
+    /// Generate the standard corpus, covering every basic value type, malformed encodings, and
+    /// documents that pass and fail schema validation.
+    pub fn standard() -> Result<Self> {
+        let mut corpus = Corpus {
+            description: "fog-pack standard test vectors".into(),
+            ..Corpus::default()
+        };
+
+        let values: Vec<(&str, Value)> = vec![
+            ("null", Value::Null),
+            ("bool", Value::Bool(true)),
+            ("int-zero", Value::from(0u8)),
+            ("int-negative", Value::from(-1i8)),
+            ("int-u8", Value::from(200u8)),
+            ("int-u16", Value::from(60_000u16)),
+            ("int-u32", Value::from(4_000_000_000u32)),
+            ("int-u64-max", Value::from(u64::MAX)),
+            ("int-i64-min", Value::from(i64::MIN)),
+            ("str-empty", Value::from("")),
+            ("str-unicode", Value::from("h\u{e9}llo, \u{1f32b}")),
+            ("str-long", Value::from("fog".repeat(100))),
+            ("f32", Value::F32(1.5)),
+            ("f64", Value::F64(-0.25)),
+            ("f64-infinite", Value::F64(f64::INFINITY)),
+            ("bin", Value::Bin(vec![0, 1, 2, 255])),
+            (
+                "timestamp",
+                Value::Timestamp(Timestamp::from_utc_secs(1_600_000_000)),
+            ),
+            ("hash", Value::Hash(Hash::new(b"fog-pack"))),
+            ("array", fog_value!([1, "two", null, [true, false]])),
+            ("map", fog_value!({"a": 1, "b": {"c": [1, 2, 3]}, "": null})),
+            ("compressible", Value::from("compress me ".repeat(200))),
+        ];
+        let mut map_doc = None;
+        for (name, value) in values {
+            let doc = NoSchema::validate_new_doc(NewDocument::new(None, &value)?)?;
+            let (hash, encoded) = NoSchema::encode_doc(doc)?;
+            if name == "map" {
+                map_doc = Some(encoded.clone());
+            }
+            corpus.vectors.push(Vector {
+                name: name.into(),
+                schema: None,
+                encoded,
+                valid: true,
+                value: Some(value),
+                hash: Some(hash),
+            });
+        }
+
+        // Malformed encodings
+        let map_doc = map_doc.expect("map vector should exist");
+        let mut truncated = map_doc.clone();
+        truncated.pop();
+        let mut trailing = map_doc;
+        trailing.push(0);
+        for (name, encoded) in [
+            ("empty", Vec::new()),
+            ("truncated", truncated),
+            ("trailing-data", trailing),
+        ] {
+            corpus.vectors.push(Vector {
+                name: name.into(),
+                schema: None,
+                encoded,
+                valid: false,
+                value: None,
+                hash: None,
+            });
+        }
+
+        // Schema validation
+        let schema_doc = SchemaBuilder::new(
+            MapValidator::new()
+                .req_add("n", IntValidator::new().max(10).build())
+                .opt_add("tag", StrValidator::new().max_len(4).build())
+                .build(),
+        )
+        .description("Test vector schema")
+        .build()?;
+        let schema = Schema::from_doc(&schema_doc)?;
+        let (_, encoded_schema) = NoSchema::encode_doc(schema_doc)?;
+        corpus.schemas.push(ByteBuf::from(encoded_schema));
+        let schema_vectors = [
+            ("schema-valid", fog_value!({"n": 5, "tag": "ok"}), true),
+            ("schema-int-too-big", fog_value!({"n": 11}), false),
+            (
+                "schema-str-max-len",
+                fog_value!({"n": 1, "tag": "long"}),
+                true,
+            ),
+            (
+                "schema-str-too-long",
+                fog_value!({"n": 1, "tag": "longer"}),
+                false,
+            ),
+            ("schema-missing-field", fog_value!({"tag": "ok"}), false),
+            ("schema-unknown-field", fog_value!({"n": 1, "x": 1}), false),
+            ("schema-wrong-type", fog_value!([1]), false),
+        ];
+        for (name, value, valid) in schema_vectors {
+            let doc = NewDocument::new(Some(schema.hash()), &value)?;
+            // Invalid documents skip validation so they can still be encoded
+            let doc = if valid {
+                schema.validate_new_doc(doc)?
+            } else {
+                Document::from_new(doc)
+            };
+            let (hash, encoded) = schema.encode_doc(doc)?;
+            corpus.vectors.push(Vector {
+                name: name.into(),
+                schema: Some(schema.hash().clone()),
+                encoded,
+                valid,
+                value: valid.then_some(value),
+                hash: valid.then_some(hash),
+            });
+        }
+
+        Ok(corpus)
+    }
+}
+
+/// A test vector that didn't produce the expected outcome.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Failure {
+    /// The name of the vector.
+    pub name: String,
+    /// What went wrong.
+    pub reason: String,
+}
+
+/// The outcome of running a corpus.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    /// The number of vectors that produced the expected outcome.
+    pub passed: usize,
+    /// The vectors that didn't.
+    pub failures: Vec<Failure>,
+}
+
+impl Report {
+    /// Check if every vector passed.
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+fn check(vector: &Vector, schemas: &HashMap<Hash, Schema>) -> std::result::Result<(), String> {
+    let decoded = match &vector.schema {
+        Some(hash) => match schemas.get(hash) {
+            Some(schema) => schema.decode_doc(vector.encoded.clone()),
+            None => return Err(format!("schema {} isn't in the corpus", hash)),
+        },
+        None => NoSchema::decode_doc(vector.encoded.clone()),
+    };
+    let doc = match (decoded, vector.valid) {
+        (Ok(doc), true) => doc,
+        (Err(_), false) => return Ok(()),
+        (Ok(_), false) => return Err("expected decoding to fail, but it succeeded".into()),
+        (Err(e), true) => return Err(format!("expected decoding to succeed, but got: {}", e)),
+    };
+    if let Some(hash) = &vector.hash {
+        if doc.hash() != hash {
+            return Err(format!("expected hash {}, got {}", hash, doc.hash()));
+        }
+    }
+    if let Some(value) = &vector.value {
+        let actual: Value = doc.deserialize().map_err(|e| e.to_string())?;
+        if &actual != value {
+            return Err(format!("expected value {:?}, got {:?}", value, actual));
+        }
+    }
+    Ok(())
+}
+
+/// Run every vector in a corpus, checking that this crate produces the expected outcomes.
+///
+/// For each vector, the document is decoded - with its schema, if it names one - and must succeed
+/// or fail as the vector says. If it succeeds, its hash and data must match the vector's. Schema
+/// documents in the corpus that fail to load cause every vector using them to fail.
+pub fn run(corpus: &Corpus) -> Report {
+    let schemas: HashMap<Hash, Schema> = corpus
+        .schemas
+        .iter()
+        .filter_map(|encoded| {
+            let doc = NoSchema::decode_doc(encoded.to_vec()).ok()?;
+            let schema = Schema::from_doc(&doc).ok()?;
+            Some((schema.hash().clone(), schema))
+        })
+        .collect();
+    let mut report = Report::default();
+    for vector in corpus.vectors.iter() {
+        match check(vector, &schemas) {
+            Ok(()) => report.passed += 1,
+            Err(reason) => report.failures.push(Failure {
+                name: vector.name.clone(),
+                reason,
+            }),
+        }
+    }
+    report
+}
+
+/// Decode a corpus and [`run`] it.
+pub fn run_encoded(encoded: Vec<u8>) -> Result<Report> {
+    Ok(run(&Corpus::decode(encoded)?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const STANDARD_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/vectors/standard.fog");
+
+    #[test]
+    fn standard_corpus() {
+        let corpus = Corpus::standard().unwrap();
+        let decoded = Corpus::decode(corpus.encode().unwrap()).unwrap();
+        assert_eq!(decoded, corpus);
+        let report = run(&decoded);
+        assert!(report.is_ok(), "{:?}", report.failures);
+        assert_eq!(report.passed, corpus.vectors.len());
+    }
+
+    #[test]
+    fn catches_failures() {
+        let mut corpus = Corpus::standard().unwrap();
+        corpus.vectors[0].valid = false;
+        corpus.vectors[1].value = Some(Value::Bool(false));
+        corpus.vectors[2].hash = corpus.vectors[3].hash.clone();
+        corpus.schemas.clear();
+        let report = run(&corpus);
+        let schema_vectors = corpus.vectors.iter().filter(|v| v.schema.is_some()).count();
+        assert_eq!(report.failures.len(), 3 + schema_vectors);
+        assert_eq!(report.failures[0].name, "null");
+    }
+
+    // The committed corpus must still pass. Changing what this crate accepts means regenerating
+    // it, which other implementations will then need to pick up.
+    #[test]
+    fn committed_corpus() {
+        let encoded = std::fs::read(STANDARD_PATH).unwrap();
+        let report = run_encoded(encoded).unwrap();
+        assert!(report.is_ok(), "{:?}", report.failures);
+        assert_eq!(report.passed, Corpus::standard().unwrap().vectors.len());
+    }
+
+    #[test]
+    #[ignore = "writes the standard corpus into the source tree"]
+    fn write_standard_corpus() {
+        let encoded = Corpus::standard().unwrap().encode().unwrap();
+        std::fs::write(STANDARD_PATH, encoded).unwrap();
+    }
+}
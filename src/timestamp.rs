@@ -27,6 +27,10 @@ const MAX_NANOSEC: u32 = 999_999_999;
 const NANOS_PER_SEC: u32 = 1_000_000_000;
 const MICROS_PER_SEC: i64 = 1_000_000;
 const MILLIS_PER_SEC: i64 = 1_000;
+const SECS_PER_HOUR: i64 = 3600;
+const SECS_PER_DAY: i64 = 86400;
+/// Calendar years beyond this (in either direction) are well outside what a timestamp can hold.
+const MAX_CIVIL_YEAR: i64 = 1 << 32;
 static UTC_LEAP: OnceLock<RwLock<LeapSeconds>> = OnceLock::new();
 
 fn get_table() -> std::sync::RwLockReadGuard<'static, LeapSeconds> {
@@ -49,6 +53,17 @@ fn tai_to_utc(t: Timestamp) -> Timestamp {
     t + table.leap_seconds(t)
 }
 
+/// Count the days from 1970-01-01 to a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_from_march = (month as i64 + 9) % 12;
+    let day_of_year = (153 * month_from_march + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
 /// Set up the leap second table that converts from TAI to UTC and vice-versa.
 ///
 /// See [`LeapSeconds`] for how to create a table for this function.
@@ -251,12 +266,20 @@ impl LeapSeconds {
                     let mut data = line.split_whitespace();
 
                     // Get the UTC time since 1900-01-01 00:00:00
-                    let Some(secs_utc) = data.next() else { return None };
-                    let Ok(secs_utc) = str::parse::<i64>(secs_utc) else { return None };
+                    let Some(secs_utc) = data.next() else {
+                        return None;
+                    };
+                    let Ok(secs_utc) = str::parse::<i64>(secs_utc) else {
+                        return None;
+                    };
 
                     // Get the delta to apply to the timestamp from this point onward
-                    let Some(delta) = data.next() else { return None };
-                    let Ok(delta) = str::parse::<i64>(delta) else { return None };
+                    let Some(delta) = data.next() else {
+                        return None;
+                    };
+                    let Ok(delta) = str::parse::<i64>(delta) else {
+                        return None;
+                    };
 
                     // Create a proper TAI timestamp and put in the correct time delta to apply
                     let time = Timestamp::from_tai_secs(secs_utc + delta + NTP_EPOCH_OFFSET);
@@ -394,6 +417,103 @@ impl Timestamp {
         self
     }
 
+    /// Add a time delta, returning `None` if the result can't be represented.
+    pub fn checked_add(self, rhs: TimeDelta) -> Option<Timestamp> {
+        let mut nanos = self.nanos + rhs.nanos;
+        let mut secs = self.secs.checked_add(rhs.secs)?;
+        if nanos >= NANOS_PER_SEC {
+            nanos -= NANOS_PER_SEC;
+            secs = secs.checked_add(1)?;
+        }
+        Some(Timestamp { secs, nanos })
+    }
+
+    /// Subtract a time delta, returning `None` if the result can't be represented.
+    pub fn checked_sub(self, rhs: TimeDelta) -> Option<Timestamp> {
+        let mut secs = self.secs.checked_sub(rhs.secs)?;
+        let mut nanos = self.nanos;
+        if nanos < rhs.nanos {
+            nanos += NANOS_PER_SEC;
+            secs = secs.checked_sub(1)?;
+        }
+        Some(Timestamp {
+            secs,
+            nanos: nanos - rhs.nanos,
+        })
+    }
+
+    // Round down to a multiple of some number of UTC seconds
+    fn floor_utc(&self, period: i64) -> Timestamp {
+        let (secs, _) = self.utc();
+        match secs.checked_sub(secs.rem_euclid(period)) {
+            Some(secs) => Timestamp::from_utc_secs(secs),
+            None => Timestamp::min_value(),
+        }
+    }
+
+    /// Round down to the start of the UTC day this timestamp falls in.
+    pub fn floor_to_day(&self) -> Timestamp {
+        self.floor_utc(SECS_PER_DAY)
+    }
+
+    /// Round down to the start of the UTC hour this timestamp falls in.
+    pub fn floor_to_hour(&self) -> Timestamp {
+        self.floor_utc(SECS_PER_HOUR)
+    }
+
+    /// Get the first and last possible timestamps within a UTC calendar day, given as a year,
+    /// month (1-12), and day of the month (1-31). Returns `None` if the date doesn't exist, or is
+    /// too far from the present to be represented.
+    ///
+    /// Both ends of the range are inclusive, which is how the `min` and `max` of a
+    /// [`TimeValidator`][crate::validator::TimeValidator] work, so they can be used directly to
+    /// query for a day's worth of data:
+    ///
+    /// ```
+    /// # use fog_pack::{types::*, validator::*};
+    /// let (start, end) = Timestamp::range_for_day(2024, 2, 29).unwrap();
+    /// let query = TimeValidator::new().min(start).max(end).build();
+    /// assert_eq!(end.next(), Timestamp::range_for_day(2024, 3, 1).unwrap().0);
+    /// assert!(Timestamp::range_for_day(2023, 2, 29).is_none());
+    /// ```
+    pub fn range_for_day(year: i64, month: u32, day: u32) -> Option<(Timestamp, Timestamp)> {
+        if !(-MAX_CIVIL_YEAR..=MAX_CIVIL_YEAR).contains(&year)
+            || !(1..=12).contains(&month)
+            || day < 1
+        {
+            return None;
+        }
+        let first = days_from_civil(year, month, 1);
+        let next_month = if month == 12 {
+            days_from_civil(year + 1, 1, 1)
+        } else {
+            days_from_civil(year, month + 1, 1)
+        };
+        if day as i64 > next_month - first {
+            return None;
+        }
+        let start = (first + day as i64 - 1).checked_mul(SECS_PER_DAY)?;
+        let end = start.checked_add(SECS_PER_DAY)?;
+        Some((
+            Timestamp::from_utc_secs(start),
+            Timestamp::from_utc_secs(end).prev(),
+        ))
+    }
+
+    /// Get the first and last possible timestamps within the UTC day this timestamp falls in.
+    /// Both ends are inclusive; see [`range_for_day`][Self::range_for_day].
+    pub fn day_range(&self) -> (Timestamp, Timestamp) {
+        let start = self.floor_to_day();
+        let (secs, _) = self.utc();
+        let end = secs
+            .div_euclid(SECS_PER_DAY)
+            .checked_add(1)
+            .and_then(|day| day.checked_mul(SECS_PER_DAY))
+            .map(|end| Timestamp::from_utc_secs(end).prev())
+            .unwrap_or(Timestamp::max_value());
+        (start, end)
+    }
+
     /// Return the UNIX timestamp (number of seconds since January 1, 1970
     /// 0:00:00 UTC). As a reminder, this is UTC time and thus has leap seconds
     /// removed/added.
@@ -474,9 +594,22 @@ impl Timestamp {
     }
 
     /// Create a Timestamp based on the current system time.
+    #[cfg(not(all(feature = "wasm", target_arch = "wasm32", target_os = "unknown")))]
     pub fn now() -> Timestamp {
         Timestamp::from(SystemTime::now())
     }
+
+    /// Create a Timestamp based on the current system time.
+    #[cfg(all(feature = "wasm", target_arch = "wasm32", target_os = "unknown"))]
+    pub fn now() -> Timestamp {
+        // There's no system clock on wasm32-unknown-unknown, so ask JavaScript for the time
+        let millis = js_sys::Date::now() as i64;
+        Timestamp::from_utc(
+            millis.div_euclid(1000),
+            millis.rem_euclid(1000) as u32 * 1_000_000,
+        )
+        .unwrap()
+    }
 }
 
 impl From<SystemTime> for Timestamp {
@@ -744,6 +877,56 @@ impl<'de> serde::de::Deserialize<'de> for Timestamp {
 mod test {
     use super::*;
 
+    #[test]
+    fn checked_arithmetic() {
+        let max = Timestamp::max_value();
+        let half = TimeDelta::from_millis(500);
+        assert_eq!(max.checked_add(TimeDelta::from_nanos(1)), None);
+        assert_eq!(Timestamp::min_value().checked_sub(half), None);
+        let t = Timestamp::from_tai(10, 700_000_000).unwrap();
+        assert_eq!(
+            t.checked_add(half),
+            Some(Timestamp::from_tai(11, 200_000_000).unwrap())
+        );
+        assert_eq!(
+            t.checked_sub(TimeDelta::from_millis(800)),
+            Some(Timestamp::from_tai(9, 900_000_000).unwrap())
+        );
+        assert_eq!(t.checked_add(half), Some(t + half));
+    }
+
+    #[test]
+    fn day_ranges() {
+        // 2021-03-04T05:06:07.5 UTC
+        let t = Timestamp::from_utc(1_614_834_367, 500_000_000).unwrap();
+        let day = Timestamp::from_utc_secs(1_614_816_000);
+        assert_eq!(t.floor_to_day(), day);
+        assert_eq!(t.floor_to_hour(), Timestamp::from_utc_secs(1_614_834_000));
+        assert_eq!(day.floor_to_day(), day);
+        assert_eq!(day.prev().floor_to_day(), day - 86400);
+
+        let (start, end) = Timestamp::range_for_day(2021, 3, 4).unwrap();
+        assert_eq!(start, day);
+        assert_eq!(end.next(), Timestamp::from_utc_secs(1_614_816_000 + 86400));
+        assert_eq!(t.day_range(), (start, end));
+        assert!(start <= t && t <= end);
+
+        assert_eq!(
+            Timestamp::range_for_day(1970, 1, 1).unwrap().0,
+            Timestamp::from_utc_secs(0)
+        );
+        assert_eq!(
+            Timestamp::range_for_day(1969, 12, 31).unwrap().0,
+            Timestamp::from_utc_secs(-86400)
+        );
+        assert!(Timestamp::range_for_day(2000, 2, 29).is_some());
+        assert!(Timestamp::range_for_day(1900, 2, 29).is_none());
+        assert!(Timestamp::range_for_day(2021, 4, 31).is_none());
+        assert!(Timestamp::range_for_day(2021, 13, 1).is_none());
+        assert!(Timestamp::range_for_day(2021, 1, 0).is_none());
+        assert!(Timestamp::range_for_day(i64::MAX, 1, 1).is_none());
+    }
+
     fn edge_cases() -> Vec<(usize, Timestamp)> {
         vec![
             (4, Timestamp::from_tai(0, 0).unwrap()),
@@ -6,6 +6,7 @@ use crate::{
 /// Find all hashes within a data stream - assuming the data is valid.
 pub(crate) fn find_hashes(data: &[u8]) -> Vec<Hash> {
     crate::element::Parser::new(data)
+        .raw_floats(true)
         .filter_map(|e| {
             if let Ok(Element::Hash(h)) = e {
                 Some(h)
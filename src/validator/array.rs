@@ -1,5 +1,5 @@
 use super::*;
-use crate::error::{Error, Result};
+use crate::error::{Error, Result, ValidateError};
 use crate::{de::FogDeserializer, element::*, value::Value, value_ref::ValueRef};
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeSet;
@@ -105,10 +105,29 @@ fn u32_is_max(v: &u32) -> bool {
 ///
 /// - Each validator in `contains` is checked against all of the schema's `prefix` validators, as
 ///     well as its `items` validator.
-/// - The `items` validator is checked against the schema's `items' validator
+/// - The `items` validator is checked against the schema's `items` validator
 /// - The `prefix` validators are checked against the schema's `prefix` validators. Unmatched
 ///     query validators are checked against the schema's `items` validator.
 ///
+/// This is what makes it possible to query on individual elements: `contains` finds arrays with
+/// a matching element anywhere in them, while `prefix` matches elements at specific positions.
+/// For example, with a schema allowing it, a query for arrays of tags containing "rust" looks
+/// like:
+///
+/// ```
+/// # use fog_pack::validator::*;
+/// // In the schema
+/// let tags = ArrayValidator::new()
+///     .items(StrValidator::new().query(true).build())
+///     .contains_ok(true)
+///     .build();
+///
+/// // In the query
+/// let rust = ArrayValidator::new()
+///     .contains_add(StrValidator::new().in_add("rust").build())
+///     .build();
+/// ```
+///
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(deny_unknown_fields, default)]
 pub struct ArrayValidator {
@@ -313,29 +332,35 @@ impl ArrayValidator {
         mut checklist: Option<Checklist<'c>>,
     ) -> Result<(Parser<'de>, Option<Checklist<'c>>)> {
         let val_parser = parser.clone();
-        let elem = parser
-            .next()
-            .ok_or_else(|| Error::FailValidate("Expected an array".to_string()))??;
+        let elem = parser.next().ok_or_else(|| {
+            Error::FailValidate(ValidateError::Missing {
+                expected: "an array",
+            })
+        })??;
         let len = if let Element::Array(len) = elem {
             len
         } else {
-            return Err(Error::FailValidate(format!(
-                "Expected Array, got {}",
-                elem.name()
-            )));
+            return Err(Error::FailValidate(ValidateError::WrongType {
+                expected: "Array",
+                actual: elem.name(),
+            }));
         };
 
         if (len as u32) > self.max_len {
-            return Err(Error::FailValidate(format!(
-                "Array is {} elements, longer than maximum allowed of {}",
-                len, self.max_len
-            )));
+            return Err(Error::FailValidate(ValidateError::Limit {
+                kind: "Array",
+                limit: "max_len",
+                allowed: self.max_len,
+                actual: len as u32,
+            }));
         }
         if (len as u32) < self.min_len {
-            return Err(Error::FailValidate(format!(
-                "Array is {} elements, shorter than minimum allowed of {}",
-                len, self.min_len
-            )));
+            return Err(Error::FailValidate(ValidateError::Limit {
+                kind: "Array",
+                limit: "min_len",
+                allowed: self.min_len,
+                actual: len as u32,
+            }));
         }
 
         // Check all the requirements that require parsing the entire array
@@ -344,22 +369,26 @@ impl ArrayValidator {
             let array = Vec::<ValueRef>::deserialize(&mut de)?;
 
             if !self.in_list.is_empty() && !self.in_list.iter().any(|v| *v == array) {
-                return Err(Error::FailValidate("Array is not on `in` list".to_string()));
+                return Err(Error::FailValidate(ValidateError::Other(
+                    "Array is not on `in` list".into(),
+                )));
             }
 
             if self.nin_list.iter().any(|v| *v == array) {
-                return Err(Error::FailValidate("Array is on `nin` list".to_string()));
+                return Err(Error::FailValidate(ValidateError::Other(
+                    "Array is on `nin` list".into(),
+                )));
             }
 
             if self.unique
                 && array
                     .iter()
                     .enumerate()
-                    .any(|(index, lhs)| array.iter().skip(index).any(|rhs| lhs == rhs))
+                    .any(|(index, lhs)| array.iter().skip(index + 1).any(|rhs| lhs == rhs))
             {
-                return Err(Error::FailValidate(
-                    "Array does not contain unique elements".to_string(),
-                ));
+                return Err(Error::FailValidate(ValidateError::Other(
+                    "Array does not contain unique elements".into(),
+                )));
             }
         }
 
@@ -391,23 +420,31 @@ impl ArrayValidator {
             if self.same_len.contains(&i) {
                 // Peek the array and its length
                 let elem = parser.peek().ok_or_else(|| {
-                    Error::FailValidate(format!("expected an array element at index {}", i))
+                    Error::FailValidate(ValidateError::Other(
+                        format!("expected an array element at index {}", i).into(),
+                    ))
                 })??;
                 match elem {
                     Element::Null => {
                         if array_len.is_some() {
-                            return Err(Error::FailValidate(format!(
-                                "some sub-arrays for `same_len` are present, but the one at {} is not",
-                                i
+                            return Err(Error::FailValidate(ValidateError::Other(
+                                format!(
+                                    "some sub-arrays for `same_len` are present, but the one at {} is not",
+                                    i
+                                )
+                                .into(),
                             )));
                         }
                     }
                     Element::Array(len) => {
                         if let Some(array_len) = array_len {
                             if array_len != len {
-                                return Err(Error::FailValidate(format!(
-                                    "expected array of length {} for index {}, but length was {}",
-                                    array_len, i, len
+                                return Err(Error::FailValidate(ValidateError::Other(
+                                    format!(
+                                        "expected array of length {} for index {}, but length was {}",
+                                        array_len, i, len
+                                    )
+                                    .into(),
                                 )));
                             }
                         } else {
@@ -416,9 +453,8 @@ impl ArrayValidator {
                         array_len_cnt += 1;
                     }
                     _ => {
-                        return Err(Error::FailValidate(format!(
-                            "`same_len` expected an array or null at index {}",
-                            i
+                        return Err(Error::FailValidate(ValidateError::Other(
+                            format!("`same_len` expected an array or null at index {}", i).into(),
                         )))
                     }
                 }
@@ -428,15 +464,16 @@ impl ArrayValidator {
             let (p, c) = validators
                 .next()
                 .unwrap()
-                .validate(types, parser, checklist)?;
+                .validate(types, parser, checklist)
+                .map_err(|e| e.at_index(i))?;
             parser = p;
             checklist = c;
         }
 
         if array_len.is_some() && array_len_cnt != self.same_len.len() {
-            return Err(Error::FailValidate(
+            return Err(Error::FailValidate(ValidateError::Other(
                 "Array had some, but not all, of the indices listed in `same_len`".into(),
-            ));
+            )));
         }
 
         if !contains_result.iter().all(|x| *x) {
@@ -448,7 +485,7 @@ impl ArrayValidator {
                 .map(|(index, _)| format!(" {},", index));
             err_str.extend(iter);
             err_str.pop(); // Remove the final comma
-            return Err(Error::FailValidate(err_str));
+            return Err(Error::FailValidate(ValidateError::Other(err_str.into())));
         }
         Ok((parser, checklist))
     }
@@ -533,4 +570,27 @@ mod test {
         println!("{}", de.get_debug().unwrap());
         assert_eq!(schema, decoded);
     }
+
+    #[test]
+    fn unique() {
+        let passes = |validator: &ArrayValidator, value: Value| {
+            let mut ser = FogSerializer::default();
+            value.serialize(&mut ser).unwrap();
+            let buf = ser.finish();
+            validator
+                .validate(&BTreeMap::new(), Parser::new(&buf), None)
+                .is_ok()
+        };
+        let unique = ArrayValidator::new().unique(true);
+        assert!(passes(&unique, Value::Array(vec![])));
+        assert!(passes(&unique, Value::Array(vec![Value::from(1u8)])));
+        assert!(passes(
+            &unique,
+            Value::Array(vec![Value::from(1u8), Value::from(2u8)])
+        ));
+        assert!(!passes(
+            &unique,
+            Value::Array(vec![Value::from(1u8), Value::from(2u8), Value::from(1u8)])
+        ));
+    }
 }
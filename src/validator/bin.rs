@@ -1,6 +1,6 @@
 use super::*;
 use crate::element::*;
-use crate::error::{Error, Result};
+use crate::error::{Error, Result, ValidateError};
 use serde::{Deserialize, Serialize};
 use serde_bytes::ByteBuf;
 use std::default::Default;
@@ -85,7 +85,7 @@ pub struct BinValidator {
     /// Changes `max` into an exclusive maximum.
     #[serde(skip_serializing_if = "is_false")]
     pub ex_max: bool,
-    /// Changes `min` into an exclusive maximum.
+    /// Changes `min` into an exclusive minimum.
     #[serde(skip_serializing_if = "is_false")]
     pub ex_min: bool,
     /// Set the maximum allowed number of bytes.
@@ -175,13 +175,13 @@ impl BinValidator {
         self
     }
 
-    /// Set whether or or not `max` is an exclusive maximum.
+    /// Set whether or not `max` is an exclusive maximum.
     pub fn ex_max(mut self, ex_max: bool) -> Self {
         self.ex_max = ex_max;
         self
     }
 
-    /// Set whether or or not `min` is an exclusive maximum.
+    /// Set whether or not `min` is an exclusive minimum.
     pub fn ex_min(mut self, ex_min: bool) -> Self {
         self.ex_min = ex_min;
         self
@@ -244,28 +244,36 @@ impl BinValidator {
         use std::iter::repeat;
 
         // Get element
-        let elem = parser
-            .next()
-            .ok_or_else(|| Error::FailValidate("expected binary data".to_string()))??;
+        let elem = parser.next().ok_or_else(|| {
+            Error::FailValidate(ValidateError::Missing {
+                expected: "binary data",
+            })
+        })??;
         let val = if let Element::Bin(v) = elem {
             v
         } else {
-            return Err(Error::FailValidate(format!(
-                "expected Bin, got {}",
-                elem.name()
-            )));
+            return Err(Error::FailValidate(ValidateError::WrongType {
+                expected: "Bin",
+                actual: elem.name(),
+            }));
         };
 
         // Length checks
         if (val.len() as u32) > self.max_len {
-            return Err(Error::FailValidate(
-                "Bin is longer than max_len".to_string(),
-            ));
+            return Err(Error::FailValidate(ValidateError::Limit {
+                kind: "Bin",
+                limit: "max_len",
+                allowed: self.max_len,
+                actual: val.len() as u32,
+            }));
         }
         if (val.len() as u32) < self.min_len {
-            return Err(Error::FailValidate(
-                "Bin is shorter than min_len".to_string(),
-            ));
+            return Err(Error::FailValidate(ValidateError::Limit {
+                kind: "Bin",
+                limit: "min_len",
+                allowed: self.min_len,
+                actual: val.len() as u32,
+            }));
         }
 
         // Bit checks
@@ -275,9 +283,9 @@ impl BinValidator {
             .zip(val.iter().chain(repeat(&0u8)))
             .any(|(bit, val)| (bit & val) != *bit)
         {
-            return Err(Error::FailValidate(
-                "Bin does not have all required bits set".to_string(),
-            ));
+            return Err(Error::FailValidate(ValidateError::Other(
+                "Bin does not have all required bits set".into(),
+            )));
         }
         if self
             .bits_clr
@@ -285,9 +293,9 @@ impl BinValidator {
             .zip(val.iter().chain(repeat(&0u8)))
             .any(|(bit, val)| (bit & val) != 0)
         {
-            return Err(Error::FailValidate(
-                "Bin does not have all required bits cleared".to_string(),
-            ));
+            return Err(Error::FailValidate(ValidateError::Other(
+                "Bin does not have all required bits cleared".into(),
+            )));
         }
 
         // Assist functions for comparison
@@ -320,23 +328,27 @@ impl BinValidator {
             };
 
             if !max_pass {
-                return Err(Error::FailValidate(
-                    "Bin greater than maximum allowed".to_string(),
-                ));
+                return Err(Error::FailValidate(ValidateError::Other(
+                    "Bin greater than maximum allowed".into(),
+                )));
             }
             if !min_pass {
-                return Err(Error::FailValidate(
-                    "Bin less than minimum allowed".to_string(),
-                ));
+                return Err(Error::FailValidate(ValidateError::Other(
+                    "Bin less than minimum allowed".into(),
+                )));
             }
         }
 
         // in/nin checks
         if !self.in_list.is_empty() && !self.in_list.iter().any(|v| *v == val) {
-            return Err(Error::FailValidate("Bin is not on `in` list".to_string()));
+            return Err(Error::FailValidate(ValidateError::Other(
+                "Bin is not on `in` list".into(),
+            )));
         }
         if self.nin_list.iter().any(|v| *v == val) {
-            return Err(Error::FailValidate("Bin is on `nin` list".to_string()));
+            return Err(Error::FailValidate(ValidateError::Other(
+                "Bin is on `nin` list".into(),
+            )));
         }
 
         Ok(())
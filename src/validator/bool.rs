@@ -1,6 +1,6 @@
 use super::*;
 use crate::element::*;
-use crate::error::{Error, Result};
+use crate::error::{Error, Result, ValidateError};
 use serde::{Deserialize, Serialize};
 
 #[inline]
@@ -22,7 +22,7 @@ fn is_false(v: &bool) -> bool {
 /// - nin_list: empty
 /// - query: false
 ///
-#[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(deny_unknown_fields, default)]
 pub struct BoolValidator {
     /// An optional comment explaining the validator.
@@ -36,10 +36,20 @@ pub struct BoolValidator {
     pub query: bool,
 }
 
+impl Default for BoolValidator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl BoolValidator {
     /// Make a new validator with the default configuration.
-    pub fn new() -> Self {
-        Self::default()
+    pub const fn new() -> Self {
+        Self {
+            comment: String::new(),
+            val: None,
+            query: false,
+        }
     }
 
     /// Set a comment for the validator.
@@ -49,13 +59,13 @@ impl BoolValidator {
     }
 
     /// Set a required value
-    pub fn set_val(mut self, val: bool) -> Self {
+    pub const fn set_val(mut self, val: bool) -> Self {
         self.val = Some(val);
         self
     }
 
     /// Set whether or not queries can use the `in` and `nin` lists.
-    pub fn query(mut self, query: bool) -> Self {
+    pub const fn query(mut self, query: bool) -> Self {
         self.query = query;
         self
     }
@@ -66,22 +76,24 @@ impl BoolValidator {
     }
 
     pub(crate) fn validate(&self, parser: &mut Parser) -> Result<()> {
-        let elem = parser
-            .next()
-            .ok_or_else(|| Error::FailValidate("Expected a boolean".to_string()))??;
+        let elem = parser.next().ok_or_else(|| {
+            Error::FailValidate(ValidateError::Missing {
+                expected: "a boolean",
+            })
+        })??;
         let elem = if let Element::Bool(v) = elem {
             v
         } else {
-            return Err(Error::FailValidate(format!(
-                "Expected Bool, got {}",
-                elem.name()
-            )));
+            return Err(Error::FailValidate(ValidateError::WrongType {
+                expected: "Bool",
+                actual: elem.name(),
+            }));
         };
         if let Some(val) = self.val {
             if val != elem {
-                return Err(Error::FailValidate(
-                    "Boolean does not match the required value".to_string(),
-                ));
+                return Err(Error::FailValidate(ValidateError::Other(
+                    "Boolean does not match the required value".into(),
+                )));
             }
         }
         Ok(())
@@ -4,7 +4,7 @@ use super::*;
 use crate::Hash;
 use crate::{
     document::Document,
-    error::{Error, Result},
+    error::{Error, Result, ValidateError},
 };
 
 /// An item in a Checklist. To complete it, find a document whose hash matches the one that was
@@ -28,9 +28,9 @@ impl<'a> ListItem<'a> {
             let doc_schema = match doc.schema_hash() {
                 Some(schema) => schema,
                 None => {
-                    return Err(Error::FailValidate(
+                    return Err(Error::FailValidate(ValidateError::Other(
                         "Document has no schema, but must pass `schema` validation".into(),
-                    ))
+                    )))
                 }
             };
             let all_schema_pass = self.inner.schema.iter().all(|list| {
@@ -40,9 +40,9 @@ impl<'a> ListItem<'a> {
                 })
             });
             if !all_schema_pass {
-                return Err(Error::FailValidate(
+                return Err(Error::FailValidate(ValidateError::Other(
                     "Document schema didn't satisfy all `schema` requirements".into(),
-                ));
+                )));
             }
         }
 
@@ -51,16 +51,16 @@ impl<'a> ListItem<'a> {
         // We don't need to `finish()` the parser after each validation because that's to
         // catch sitautions where the inner data contains more than one fog-pack value in sequence.
         // Because we already have a Document, that check was already performed.
-        let parser = Parser::new(doc.data());
+        let parser = Parser::new(doc.data()).raw_floats(true);
         let all_link_pass = self
             .inner
             .link
             .iter()
             .all(|validator| validator.validate(self.types, parser.clone(), None).is_ok());
         if !all_link_pass {
-            return Err(Error::FailValidate(
+            return Err(Error::FailValidate(ValidateError::Other(
                 "Document schema didn't satisfy all `link` requirements".into(),
-            ));
+            )));
         }
         Ok(())
     }
@@ -103,6 +103,10 @@ impl<'a, T> DataChecklist<'a, T> {
         Self { list, data }
     }
 
+    pub(crate) fn data_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
     /// Iterate through the whole checklist, going through one Hash and list item at a time. For
     /// each item, look up a Document with the same hash and check it with the [`ListItem`]'s
     /// [`check`][ListItem::check] function.
@@ -171,9 +175,9 @@ impl<'a> Checklist<'a> {
         if self.list.is_empty() {
             Ok(())
         } else {
-            Err(Error::FailValidate(
+            Err(Error::FailValidate(ValidateError::Other(
                 "Not all verification checklist items were completed".into(),
-            ))
+            )))
         }
     }
 }
@@ -233,7 +237,7 @@ mod test {
             .try_for_each(|(hash, item)| {
                 let doc = map
                     .get(&hash)
-                    .ok_or_else(|| Error::FailValidate("".into()))?;
+                    .ok_or_else(|| Error::FailValidate(ValidateError::Other("".into())))?;
                 item.check(doc)
             })
             .unwrap();
@@ -1,5 +1,5 @@
 use super::*;
-use crate::error::{Error, Result};
+use crate::error::{Error, Result, ValidateError};
 use serde::{Deserialize, Serialize};
 use std::default::Default;
 
@@ -103,7 +103,7 @@ fn is_false(v: &bool) -> bool {
 /// # }
 /// ```
 ///
-#[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(deny_unknown_fields, default)]
 pub struct EnumValidator {
     /// An optional comment explaining the validator.
@@ -117,10 +117,20 @@ pub struct EnumValidator {
     pub var: BTreeMap<String, Option<Validator>>,
 }
 
+impl Default for EnumValidator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl EnumValidator {
     /// Make a new validator with the default configuration.
-    pub fn new() -> Self {
-        Self::default()
+    pub const fn new() -> Self {
+        Self {
+            comment: String::new(),
+            extend: false,
+            var: BTreeMap::new(),
+        }
     }
 
     /// Set a comment for the validator.
@@ -130,7 +140,7 @@ impl EnumValidator {
     }
 
     /// Mark whether or not the enum can be extended.
-    pub fn extensible(mut self, extend: bool) -> Self {
+    pub const fn extensible(mut self, extend: bool) -> Self {
         self.extend = extend;
         self
     }
@@ -165,36 +175,43 @@ impl EnumValidator {
         // Get the enum itself, which should be a map with 1 key-value pair or a string.
         let elem = parser
             .next()
-            .ok_or_else(|| Error::FailValidate("expected a enum".to_string()))??;
+            .ok_or_else(|| Error::FailValidate(ValidateError::Missing { expected: "a enum" }))??;
         let (key, has_value) = match elem {
             Element::Str(v) => (v, false),
             Element::Map(1) => {
-                let key = parser
-                    .next()
-                    .ok_or_else(|| Error::FailValidate("expected a string".to_string()))??;
+                let key = parser.next().ok_or_else(|| {
+                    Error::FailValidate(ValidateError::Missing {
+                        expected: "a string",
+                    })
+                })??;
                 if let Element::Str(key) = key {
                     (key, true)
                 } else {
-                    return Err(Error::FailValidate("expected a string".to_string()));
+                    return Err(Error::FailValidate(ValidateError::Other(
+                        "expected a string".into(),
+                    )));
                 }
             }
-            _ => return Err(Error::FailValidate("expected an enum".to_string())),
+            _ => {
+                return Err(Error::FailValidate(ValidateError::Other(
+                    "expected an enum".into(),
+                )))
+            }
         };
 
         // Find the matching validator and verify the (possible) content against it
-        let validator = self
-            .var
-            .get(key)
-            .ok_or_else(|| Error::FailValidate(format!("{} is not in enum list", key)))?;
+        let validator = self.var.get(key).ok_or_else(|| {
+            Error::FailValidate(ValidateError::Other(
+                format!("{} is not in enum list", key).into(),
+            ))
+        })?;
         match (validator, has_value) {
             (None, false) => Ok((parser, checklist)),
-            (None, true) => Err(Error::FailValidate(format!(
-                "enum {} shouldn't have any associated value",
-                key
+            (None, true) => Err(Error::FailValidate(ValidateError::Other(
+                format!("enum {} shouldn't have any associated value", key).into(),
             ))),
-            (Some(_), false) => Err(Error::FailValidate(format!(
-                "enum {} should have an associated value",
-                key
+            (Some(_), false) => Err(Error::FailValidate(ValidateError::Other(
+                format!("enum {} should have an associated value", key).into(),
             ))),
             (Some(validator), true) => validator.validate(types, parser, checklist),
         }
@@ -1,6 +1,6 @@
 use super::*;
 use crate::element::*;
-use crate::error::{Error, Result};
+use crate::error::{Error, Result, ValidateError};
 use serde::{Deserialize, Serialize};
 
 #[inline]
@@ -17,9 +17,11 @@ fn is_nan(v: &f32) -> bool {
 /// This validator will only pass f32 values. Validation passes if:
 ///
 /// - If `max` is a number, that the value is less than the maximum in `max`, or equal to it if
-///     `ex_max` is not set to true.
+///     `ex_max` is not set to true. NaN is never less than the maximum, so it fails.
+/// - If `max` is NaN but `ex_max` is set, that the value is neither NaN nor positive infinity.
 /// - If `min` is a number, that the value is greater than the minimum in `min`, or equal to it if
-///     `ex_min` is not set to true.
+///     `ex_min` is not set to true. NaN is never greater than the minimum, so it fails.
+/// - If `min` is NaN but `ex_min` is set, that the value is neither NaN nor negative infinity.
 /// - If the `in` list is not empty, the value must be among the values in it. This performs an
 ///     exact bit-wise match.
 /// - The value must not be among the values in the `nin` list. This performas an exact bit-wise
@@ -55,7 +57,7 @@ pub struct F32Validator {
     /// Changes `max` into an exclusive maximum.
     #[serde(skip_serializing_if = "is_false")]
     pub ex_max: bool,
-    /// Changes `min` into an exclusive maximum.
+    /// Changes `min` into an exclusive minimum.
     #[serde(skip_serializing_if = "is_false")]
     pub ex_min: bool,
     /// A vector of specific allowed values, stored under the `in` field. If empty, this vector is not checked against.
@@ -73,8 +75,15 @@ pub struct F32Validator {
     pub ord: bool,
 }
 
-impl std::default::Default for F32Validator {
+impl Default for F32Validator {
     fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl F32Validator {
+    /// Make a new validator with the default configuration.
+    pub const fn new() -> Self {
         Self {
             comment: String::new(),
             max: f32::NAN,
@@ -87,13 +96,6 @@ impl std::default::Default for F32Validator {
             ord: false,
         }
     }
-}
-
-impl F32Validator {
-    /// Make a new validator with the default configuration.
-    pub fn new() -> Self {
-        Self::default()
-    }
 
     /// Set a comment for the validator.
     pub fn comment(mut self, comment: impl Into<String>) -> Self {
@@ -102,25 +104,27 @@ impl F32Validator {
     }
 
     /// Set the maximum allowed value.
-    pub fn max(mut self, max: f32) -> Self {
+    pub const fn max(mut self, max: f32) -> Self {
         self.max = max;
         self
     }
 
     /// Set the minimum allowed value.
-    pub fn min(mut self, min: f32) -> Self {
+    pub const fn min(mut self, min: f32) -> Self {
         self.min = min;
         self
     }
 
-    /// Set whether or or not `max` is an exclusive maximum.
-    pub fn ex_max(mut self, ex_max: bool) -> Self {
+    /// Set whether or not `max` is an exclusive maximum. Setting this without a maximum excludes
+    /// NaN and positive infinity.
+    pub const fn ex_max(mut self, ex_max: bool) -> Self {
         self.ex_max = ex_max;
         self
     }
 
-    /// Set whether or or not `min` is an exclusive maximum.
-    pub fn ex_min(mut self, ex_min: bool) -> Self {
+    /// Set whether or not `min` is an exclusive minimum. Setting this without a minimum excludes
+    /// NaN and negative infinity.
+    pub const fn ex_min(mut self, ex_min: bool) -> Self {
         self.ex_min = ex_min;
         self
     }
@@ -138,13 +142,13 @@ impl F32Validator {
     }
 
     /// Set whether or not queries can use the `in` and `nin` lists.
-    pub fn query(mut self, query: bool) -> Self {
+    pub const fn query(mut self, query: bool) -> Self {
         self.query = query;
         self
     }
 
     /// Set whether or not queries can use the `max`, `min`, `ex_max`, and `ex_min` values.
-    pub fn ord(mut self, ord: bool) -> Self {
+    pub const fn ord(mut self, ord: bool) -> Self {
         self.ord = ord;
         self
     }
@@ -157,31 +161,47 @@ impl F32Validator {
     pub(crate) fn validate(&self, parser: &mut Parser) -> Result<()> {
         let elem = parser
             .next()
-            .ok_or_else(|| Error::FailValidate("Expected a f32".to_string()))??;
+            .ok_or_else(|| Error::FailValidate(ValidateError::Missing { expected: "a f32" }))??;
         let elem = if let Element::F32(v) = elem {
             v
         } else {
-            return Err(Error::FailValidate(format!(
-                "Expected F32, got {}",
-                elem.name()
-            )));
+            return Err(Error::FailValidate(ValidateError::WrongType {
+                expected: "F32",
+                actual: elem.name(),
+            }));
         };
         let bytes = elem.to_ne_bytes();
         if !self.in_list.is_empty() && !self.in_list.iter().any(|v| v.to_ne_bytes() == bytes) {
-            return Err(Error::FailValidate("F32 is not on `in` list".to_string()));
+            return Err(Error::FailValidate(ValidateError::Other(
+                "F32 is not on `in` list".into(),
+            )));
         }
         if self.nin_list.iter().any(|v| v.to_ne_bytes() == bytes) {
-            return Err(Error::FailValidate("F32 is on `nin` list".to_string()));
+            return Err(Error::FailValidate(ValidateError::Other(
+                "F32 is on `nin` list".into(),
+            )));
         }
-        if !self.max.is_nan() && ((self.ex_max && elem >= self.max) || (elem > self.max)) {
-            return Err(Error::FailValidate(
-                "F32 greater than maximum allowed".to_string(),
-            ));
+        let below_max = match (self.max.is_nan(), self.ex_max) {
+            (true, false) => true,
+            (true, true) => elem < f32::INFINITY,
+            (false, true) => elem < self.max,
+            (false, false) => elem <= self.max,
+        };
+        if !below_max {
+            return Err(Error::FailValidate(ValidateError::Other(
+                "F32 greater than maximum allowed".into(),
+            )));
         }
-        if !self.min.is_nan() && ((self.ex_min && elem <= self.min) || (elem < self.min)) {
-            return Err(Error::FailValidate(
-                "F32 less than maximum allowed".to_string(),
-            ));
+        let above_min = match (self.min.is_nan(), self.ex_min) {
+            (true, false) => true,
+            (true, true) => elem > f32::NEG_INFINITY,
+            (false, true) => elem > self.min,
+            (false, false) => elem >= self.min,
+        };
+        if !above_min {
+            return Err(Error::FailValidate(ValidateError::Other(
+                "F32 less than minimum allowed".into(),
+            )));
         }
         Ok(())
     }
@@ -204,3 +224,39 @@ impl F32Validator {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn passes(validator: &F32Validator, value: f32) -> bool {
+        let mut buf = Vec::new();
+        serialize_elem(&mut buf, Element::F32(value));
+        let mut parser = Parser::new(&buf);
+        validator.validate(&mut parser).is_ok()
+    }
+
+    #[test]
+    fn exclusive_bounds() {
+        let positive = F32Validator::new().min(0.0).ex_min(true);
+        assert!(passes(&positive, 1e-30));
+        assert!(passes(&positive, f32::INFINITY));
+        assert!(!passes(&positive, 0.0));
+        assert!(!passes(&positive, -1.0));
+        assert!(!passes(&positive, f32::NAN));
+
+        let at_most = F32Validator::new().max(1.0);
+        assert!(passes(&at_most, 1.0));
+        assert!(!passes(&at_most, f32::NAN));
+        assert!(!passes(&F32Validator::new().max(1.0).ex_max(true), 1.0));
+
+        // Exclusive without a bound leaves out the infinities and NaN
+        let finite = F32Validator::new().ex_min(true).ex_max(true);
+        assert!(passes(&finite, f32::MAX));
+        assert!(passes(&finite, f32::MIN));
+        assert!(!passes(&finite, f32::INFINITY));
+        assert!(!passes(&finite, f32::NEG_INFINITY));
+        assert!(!passes(&finite, f32::NAN));
+        assert!(passes(&F32Validator::new(), f32::NAN));
+    }
+}
@@ -1,8 +1,9 @@
 use super::*;
 use crate::element::*;
-use crate::error::{Error, Result};
+use crate::error::{Error, Result, ValidateError};
 use crate::Hash;
 use serde::{Deserialize, Deserializer, Serialize};
+use serde_bytes::ByteBuf;
 use std::default::Default;
 
 #[inline]
@@ -10,6 +11,11 @@ fn is_false(v: &bool) -> bool {
     !v
 }
 
+#[inline]
+fn bytes_empty(v: &ByteBuf) -> bool {
+    v.is_empty()
+}
+
 fn get_validator<'de, D: Deserializer<'de>>(
     deserializer: D,
 ) -> Result<Option<Box<Validator>>, D::Error> {
@@ -24,6 +30,9 @@ fn get_validator<'de, D: Deserializer<'de>>(
 ///
 /// - If the `in` list is not empty, the hash must be among the hashes in the list.
 /// - The hash must not be among the hashes in the `nin` list.
+/// - If the `version` list is not empty, the hash's version, which identifies the hash algorithm
+///     used, must be among the versions in the list.
+/// - The hash's digest, not including the version, must start with the bytes in `prefix`.
 /// - If `link` has a validator, the data in the Document referred to by the hash must pass that
 ///     validator.
 /// - If the `schema` list is not empty, the Document referred to by the hash must use one of the
@@ -48,18 +57,25 @@ fn get_validator<'de, D: Deserializer<'de>>(
 /// - schema: empty
 /// - in_list: empty
 /// - nin_list: empty
+/// - version: empty
+/// - prefix: empty
 /// - query: false
 /// - link_ok: false
 /// - schema_ok: false
+/// - prefix_ok: false
 ///
 /// # Query Checking
 ///
 /// Queries for hashes are only allowed to use non-default values for each field if the
 /// corresponding query permission is set in the schema's validator:
 ///
-/// - query: `in` and `nin` lists
+/// - query: `in`, `nin`, and `version` lists
 /// - link_ok: `link`
 /// - schema_ok: `schema`
+/// - prefix_ok: `prefix`
+///
+/// Prefix queries let a set of entries be split into buckets by hash, for sharding: a query with a
+/// one-byte prefix matches roughly 1/256th of all hashes.
 ///
 /// In addition, if there is a validator for `link`, it is validated against the schema validator's
 /// `link` validator.
@@ -88,7 +104,15 @@ pub struct HashValidator {
     /// A vector of specific unallowed values, stored under the `nin` field.
     #[serde(rename = "nin", skip_serializing_if = "Vec::is_empty")]
     pub nin_list: Vec<Hash>,
-    /// If true, queries against matching spots may have values in the `in` or `nin` lists.
+    /// A vector of allowed hash versions, stored under the `version` field. If empty, any
+    /// version is allowed.
+    #[serde(rename = "version", skip_serializing_if = "Vec::is_empty")]
+    pub version_list: Vec<u8>,
+    /// Bytes the hash's digest must start with.
+    #[serde(skip_serializing_if = "bytes_empty")]
+    pub prefix: ByteBuf,
+    /// If true, queries against matching spots may have values in the `in`, `nin`, or `version`
+    /// lists.
     #[serde(skip_serializing_if = "is_false")]
     pub query: bool,
     /// If true, queries against matching spots may have a validator in `link`.
@@ -97,6 +121,9 @@ pub struct HashValidator {
     /// If true, queries against matching spots may have values in the `schema` list.
     #[serde(skip_serializing_if = "is_false")]
     pub schema_ok: bool,
+    /// If true, queries against matching spots may have a `prefix`.
+    #[serde(skip_serializing_if = "is_false")]
+    pub prefix_ok: bool,
 }
 
 impl HashValidator {
@@ -135,7 +162,19 @@ impl HashValidator {
         self
     }
 
-    /// Set whether or not queries can use the `in` and `nin` lists.
+    /// Add a hash version to the `version` list.
+    pub fn version_add(mut self, add: u8) -> Self {
+        self.version_list.push(add);
+        self
+    }
+
+    /// Set the bytes the hash's digest must start with.
+    pub fn prefix(mut self, prefix: impl Into<Vec<u8>>) -> Self {
+        self.prefix = ByteBuf::from(prefix);
+        self
+    }
+
+    /// Set whether or not queries can use the `in`, `nin`, and `version` lists.
     pub fn query(mut self, query: bool) -> Self {
         self.query = query;
         self
@@ -153,6 +192,12 @@ impl HashValidator {
         self
     }
 
+    /// Set whether or not queries can use `prefix`.
+    pub fn prefix_ok(mut self, prefix_ok: bool) -> Self {
+        self.prefix_ok = prefix_ok;
+        self
+    }
+
     /// Build this into a [`Validator`] enum.
     pub fn build(self) -> Validator {
         Validator::Hash(Box::new(self))
@@ -165,26 +210,42 @@ impl HashValidator {
     ) -> Result<()> {
         let elem = parser
             .next()
-            .ok_or_else(|| Error::FailValidate("Expected a hash".to_string()))??;
+            .ok_or_else(|| Error::FailValidate(ValidateError::Missing { expected: "a hash" }))??;
         let val = if let Element::Hash(v) = elem {
             v
         } else {
-            return Err(Error::FailValidate(format!(
-                "Expected Hash, got {}",
-                elem.name()
-            )));
+            return Err(Error::FailValidate(ValidateError::WrongType {
+                expected: "Hash",
+                actual: elem.name(),
+            }));
         };
 
         // in/nin checks
         if !self.in_list.is_empty() && !self.in_list.iter().any(|v| *v == val) {
-            return Err(Error::FailValidate(
-                "Timestamp is not on `in` list".to_string(),
-            ));
+            return Err(Error::FailValidate(ValidateError::Other(
+                "Timestamp is not on `in` list".into(),
+            )));
         }
         if self.nin_list.iter().any(|v| *v == val) {
-            return Err(Error::FailValidate(
-                "Timestamp is on `nin` list".to_string(),
-            ));
+            return Err(Error::FailValidate(ValidateError::Other(
+                "Timestamp is on `nin` list".into(),
+            )));
+        }
+
+        // version/prefix checks
+        let (version, digest) = match val.as_ref().split_first() {
+            Some((version, digest)) => (*version, digest),
+            None => (0, &[][..]),
+        };
+        if !self.version_list.is_empty() && !self.version_list.contains(&version) {
+            return Err(Error::FailValidate(ValidateError::Other(
+                format!("Hash version {} is not on `version` list", version).into(),
+            )));
+        }
+        if !digest.starts_with(&self.prefix) {
+            return Err(Error::FailValidate(ValidateError::Other(
+                "Hash does not start with `prefix`".into(),
+            )));
         }
 
         if let Some(checklist) = checklist {
@@ -200,9 +261,13 @@ impl HashValidator {
     }
 
     fn query_check_self(&self, types: &BTreeMap<String, Validator>, other: &HashValidator) -> bool {
-        let initial_check = (self.query || (other.in_list.is_empty() && other.nin_list.is_empty()))
+        let initial_check = (self.query
+            || (other.in_list.is_empty()
+                && other.nin_list.is_empty()
+                && other.version_list.is_empty()))
             && (self.link_ok || other.link.is_none())
-            && (self.schema_ok || other.schema.is_empty());
+            && (self.schema_ok || other.schema.is_empty())
+            && (self.prefix_ok || other.prefix.is_empty());
         if !initial_check {
             return false;
         }
@@ -282,4 +347,40 @@ mod test {
             .validate(&mut parser, &mut checklist)
             .expect("should succeed as a validator");
     }
+
+    #[test]
+    fn version_and_prefix() {
+        let hash = Hash::new(b"Data to make a hash");
+        let mut ser = FogSerializer::default();
+        hash.serialize(&mut ser).unwrap();
+        let encoded = ser.finish();
+        let check = |validator: &HashValidator| {
+            validator
+                .validate(&mut Parser::new(&encoded), &mut None)
+                .is_ok()
+        };
+        let version = hash.as_ref()[0];
+        let digest = &hash.as_ref()[1..];
+
+        assert!(check(&HashValidator::new().version_add(version)));
+        assert!(!check(&HashValidator::new().version_add(version + 1)));
+        assert!(check(&HashValidator::new().prefix(&digest[..2])));
+        assert!(check(&HashValidator::new().prefix(digest)));
+        let mut wrong = digest[..2].to_vec();
+        wrong[1] ^= 1;
+        assert!(!check(&HashValidator::new().prefix(wrong)));
+
+        // Prefix queries need their own permission
+        let types = BTreeMap::new();
+        let query = HashValidator::new().prefix(vec![0u8]).build();
+        assert!(!HashValidator::new().query(true).query_check(&types, &query));
+        assert!(HashValidator::new()
+            .prefix_ok(true)
+            .query_check(&types, &query));
+        let query = HashValidator::new().version_add(version).build();
+        assert!(!HashValidator::new()
+            .prefix_ok(true)
+            .query_check(&types, &query));
+        assert!(HashValidator::new().query(true).query_check(&types, &query));
+    }
 }
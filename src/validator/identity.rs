@@ -1,6 +1,6 @@
 use super::*;
 use crate::element::*;
-use crate::error::{Error, Result};
+use crate::error::{Error, Result, ValidateError};
 use crate::Identity;
 use serde::{Deserialize, Serialize};
 
@@ -26,7 +26,7 @@ fn is_false(v: &bool) -> bool {
 /// - nin_list: empty
 /// - query: false
 ///
-#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(deny_unknown_fields, default)]
 pub struct IdentityValidator {
     /// An optional comment explaining the validator.
@@ -43,10 +43,21 @@ pub struct IdentityValidator {
     pub query: bool,
 }
 
+impl Default for IdentityValidator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl IdentityValidator {
     /// Make a new validator with the default configuration.
-    pub fn new() -> Self {
-        Self::default()
+    pub const fn new() -> Self {
+        Self {
+            comment: String::new(),
+            in_list: Vec::new(),
+            nin_list: Vec::new(),
+            query: false,
+        }
     }
 
     /// Set a comment for the validator.
@@ -68,7 +79,7 @@ impl IdentityValidator {
     }
 
     /// Set whether or not queries can use the `in` and `nin` lists.
-    pub fn query(mut self, query: bool) -> Self {
+    pub const fn query(mut self, query: bool) -> Self {
         self.query = query;
         self
     }
@@ -79,24 +90,28 @@ impl IdentityValidator {
     }
 
     pub(crate) fn validate(&self, parser: &mut Parser) -> Result<()> {
-        let elem = parser
-            .next()
-            .ok_or_else(|| Error::FailValidate("Expected an Identity".to_string()))??;
+        let elem = parser.next().ok_or_else(|| {
+            Error::FailValidate(ValidateError::Missing {
+                expected: "an Identity",
+            })
+        })??;
         let elem = if let Element::Identity(v) = elem {
             v
         } else {
-            return Err(Error::FailValidate(format!(
-                "Expected Identity, got {}",
-                elem.name()
-            )));
+            return Err(Error::FailValidate(ValidateError::WrongType {
+                expected: "Identity",
+                actual: elem.name(),
+            }));
         };
         if !self.in_list.is_empty() && !self.in_list.iter().any(|v| v == elem.as_ref()) {
-            return Err(Error::FailValidate(
-                "Identity is not on `in` list".to_string(),
-            ));
+            return Err(Error::FailValidate(ValidateError::Other(
+                "Identity is not on `in` list".into(),
+            )));
         }
         if self.nin_list.iter().any(|v| v == elem.as_ref()) {
-            return Err(Error::FailValidate("Identity is on `nin` list".to_string()));
+            return Err(Error::FailValidate(ValidateError::Other(
+                "Identity is on `nin` list".into(),
+            )));
         }
         Ok(())
     }
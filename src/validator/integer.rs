@@ -1,6 +1,6 @@
 use super::*;
 use crate::element::*;
-use crate::error::{Error, Result};
+use crate::error::{Error, Result, ValidateError};
 use crate::*;
 use serde::{Deserialize, Serialize};
 
@@ -76,7 +76,7 @@ pub struct IntValidator {
     /// Changes `max` into an exclusive maximum.
     #[serde(skip_serializing_if = "is_false")]
     pub ex_max: bool,
-    /// Changes `min` into an exclusive maximum.
+    /// Changes `min` into an exclusive minimum.
     #[serde(skip_serializing_if = "is_false")]
     pub ex_min: bool,
     /// A vector of specific allowed values, stored under the `in` field. If empty, this vector is not checked against.
@@ -98,8 +98,15 @@ pub struct IntValidator {
     pub ord: bool,
 }
 
-impl std::default::Default for IntValidator {
+impl Default for IntValidator {
     fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IntValidator {
+    /// Make a new validator with the default configuration.
+    pub const fn new() -> Self {
         Self {
             comment: String::new(),
             bits_clr: 0,
@@ -115,13 +122,6 @@ impl std::default::Default for IntValidator {
             ord: false,
         }
     }
-}
-
-impl IntValidator {
-    /// Make a new validator with the default configuration.
-    pub fn new() -> Self {
-        Self::default()
-    }
 
     /// Set a comment for the validator.
     pub fn comment(mut self, comment: impl Into<String>) -> Self {
@@ -130,13 +130,13 @@ impl IntValidator {
     }
 
     /// Choose which bits must be set.
-    pub fn bits_set(mut self, bits_set: u64) -> Self {
+    pub const fn bits_set(mut self, bits_set: u64) -> Self {
         self.bits_set = bits_set;
         self
     }
 
     /// Choose which bits must be cleared.
-    pub fn bits_clr(mut self, bits_clr: u64) -> Self {
+    pub const fn bits_clr(mut self, bits_clr: u64) -> Self {
         self.bits_clr = bits_clr;
         self
     }
@@ -153,14 +153,14 @@ impl IntValidator {
         self
     }
 
-    /// Set whether or or not `max` is an exclusive maximum.
-    pub fn ex_max(mut self, ex_max: bool) -> Self {
+    /// Set whether or not `max` is an exclusive maximum.
+    pub const fn ex_max(mut self, ex_max: bool) -> Self {
         self.ex_max = ex_max;
         self
     }
 
-    /// Set whether or or not `min` is an exclusive maximum.
-    pub fn ex_min(mut self, ex_min: bool) -> Self {
+    /// Set whether or not `min` is an exclusive minimum.
+    pub const fn ex_min(mut self, ex_min: bool) -> Self {
         self.ex_min = ex_min;
         self
     }
@@ -178,19 +178,19 @@ impl IntValidator {
     }
 
     /// Set whether or not queries can use the `in` and `nin` lists.
-    pub fn query(mut self, query: bool) -> Self {
+    pub const fn query(mut self, query: bool) -> Self {
         self.query = query;
         self
     }
 
     /// Set whether or not queries can use the `bits_clr` and `bits_set` values.
-    pub fn bit(mut self, bit: bool) -> Self {
+    pub const fn bit(mut self, bit: bool) -> Self {
         self.bit = bit;
         self
     }
 
     /// Set whether or not queries can use the `max`, `min`, `ex_max`, and `ex_min` values.
-    pub fn ord(mut self, ord: bool) -> Self {
+    pub const fn ord(mut self, ord: bool) -> Self {
         self.ord = ord;
         self
     }
@@ -201,59 +201,63 @@ impl IntValidator {
     }
 
     pub(crate) fn validate(&self, parser: &mut Parser) -> Result<()> {
-        let elem = parser
-            .next()
-            .ok_or_else(|| Error::FailValidate("Expected a integer".to_string()))??;
+        let elem = parser.next().ok_or_else(|| {
+            Error::FailValidate(ValidateError::Missing {
+                expected: "a integer",
+            })
+        })??;
         let int = if let Element::Int(v) = elem {
             v
         } else {
-            return Err(Error::FailValidate(format!(
-                "Expected Int, got {}",
-                elem.name()
-            )));
+            return Err(Error::FailValidate(ValidateError::WrongType {
+                expected: "Int",
+                actual: elem.name(),
+            }));
         };
         let bits = int.as_bits();
         if !self.in_list.is_empty() && !self.in_list.iter().any(|v| *v == int) {
-            return Err(Error::FailValidate(
-                "Integer is not on `in` list".to_string(),
-            ));
+            return Err(Error::FailValidate(ValidateError::Other(
+                "Integer is not on `in` list".into(),
+            )));
         }
         if self.nin_list.iter().any(|v| *v == int) {
-            return Err(Error::FailValidate("Integer is on `nin` list".to_string()));
+            return Err(Error::FailValidate(ValidateError::Other(
+                "Integer is on `nin` list".into(),
+            )));
         }
         if (bits & self.bits_clr) != 0 {
-            return Err(Error::FailValidate(
-                "Integer does not have all required bits cleared".to_string(),
-            ));
+            return Err(Error::FailValidate(ValidateError::Other(
+                "Integer does not have all required bits cleared".into(),
+            )));
         }
         if (bits & self.bits_set) != self.bits_set {
-            return Err(Error::FailValidate(
-                "Integer does not have all required bits set".to_string(),
-            ));
+            return Err(Error::FailValidate(ValidateError::Other(
+                "Integer does not have all required bits set".into(),
+            )));
         }
         match int.cmp(&self.max) {
             std::cmp::Ordering::Equal if self.ex_max => {
-                return Err(Error::FailValidate(
-                    "Integer greater than maximum allowed".to_string(),
-                ))
+                return Err(Error::FailValidate(ValidateError::Other(
+                    "Integer greater than maximum allowed".into(),
+                )))
             }
             std::cmp::Ordering::Greater => {
-                return Err(Error::FailValidate(
-                    "Integer greater than maximum allowed".to_string(),
-                ))
+                return Err(Error::FailValidate(ValidateError::Other(
+                    "Integer greater than maximum allowed".into(),
+                )))
             }
             _ => (),
         }
         match int.cmp(&self.min) {
             std::cmp::Ordering::Equal if self.ex_min => {
-                return Err(Error::FailValidate(
-                    "Integer less than minimum allowed".to_string(),
-                ))
+                return Err(Error::FailValidate(ValidateError::Other(
+                    "Integer less than minimum allowed".into(),
+                )))
             }
             std::cmp::Ordering::Less => {
-                return Err(Error::FailValidate(
-                    "Integer less than minimum allowed".to_string(),
-                ))
+                return Err(Error::FailValidate(ValidateError::Other(
+                    "Integer less than minimum allowed".into(),
+                )))
             }
             _ => (),
         }
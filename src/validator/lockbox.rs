@@ -1,6 +1,8 @@
 use super::*;
 use crate::element::*;
-use crate::error::{Error, Result};
+use crate::error::{Error, Result, ValidateError};
+use crate::{LockId, StreamId};
+use fog_crypto::lockbox::LockboxRecipient;
 use serde::{Deserialize, Serialize};
 
 #[inline]
@@ -30,6 +32,8 @@ macro_rules! lockbox_validator {
         #[doc = " value. Validation passes if:\n\n"]
         #[doc = "- The number of bytes in the lockbox is less than or equal to `max_len`\n"]
         #[doc = "- The number of bytes in the lockbox is greater than or equal to `min_len`\n"]
+        #[doc = "- If the `lock_in` or `stream_in` lists aren't empty, the lockbox's recipient must be among the \
+            LockIds in `lock_in` or the StreamIds in `stream_in`.\n"]
         /// # Defaults
         ///
         /// Fields that aren't specified for the validator use their defaults instead. The defaults for
@@ -38,12 +42,16 @@ macro_rules! lockbox_validator {
         /// - comment: ""
         /// - max_len: u32::MAX
         /// - min_len: 0
+        /// - lock_in: empty
+        /// - stream_in: empty
         /// - size: false
+        /// - query: false
         ///
         /// # Query Checking
         ///
         /// Queries for lockboxes are only allowed to use non default values for `max_len` and
-        /// `min_len` if `size` is set in the schema's validator.
+        /// `min_len` if `size` is set in the schema's validator. They may only have values in the
+        /// `lock_in` and `stream_in` lists if `query` is set in the schema's validator.
         ///
         #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
         #[serde(deny_unknown_fields, default)]
@@ -57,28 +65,43 @@ macro_rules! lockbox_validator {
             /// Set the minimum allowed number of bytes.
             #[serde(skip_serializing_if = "u32_is_zero")]
             pub min_len: u32,
+            /// A vector of LockIds the lockbox may be addressed to. If this and `stream_in` are
+            /// both empty, the recipient is not checked.
+            #[serde(skip_serializing_if = "Vec::is_empty")]
+            pub lock_in: Vec<LockId>,
+            /// A vector of StreamIds the lockbox may be addressed to. If this and `lock_in` are
+            /// both empty, the recipient is not checked.
+            #[serde(skip_serializing_if = "Vec::is_empty")]
+            pub stream_in: Vec<StreamId>,
             /// If true, queries against matching spots may set the `min_len` and `max_len` values
             /// to non-defaults.
             #[serde(skip_serializing_if = "is_false")]
             pub size: bool,
+            /// If true, queries against matching spots may have values in the `lock_in` or
+            /// `stream_in` lists.
+            #[serde(skip_serializing_if = "is_false")]
+            pub query: bool,
         }
 
-        impl std::default::Default for $v {
+        impl Default for $v {
             fn default() -> Self {
-                Self {
-                    comment: String::new(),
-                    max_len: u32::MAX,
-                    min_len: u32::MIN,
-                    size: false,
-                }
+                Self::new()
             }
         }
 
         impl $v {
 
             /// Make a new validator with the default configuration.
-            pub fn new() -> Self {
-                Self::default()
+            pub const fn new() -> Self {
+                Self {
+                    comment: String::new(),
+                    max_len: u32::MAX,
+                    min_len: u32::MIN,
+                    lock_in: Vec::new(),
+                    stream_in: Vec::new(),
+                    size: false,
+                    query: false,
+                }
             }
 
             /// Set a comment for the validator.
@@ -88,23 +111,41 @@ macro_rules! lockbox_validator {
             }
 
             /// Set the maximum number of allowed bytes.
-            pub fn max_len(mut self, max_len: u32) -> Self {
+            pub const fn max_len(mut self, max_len: u32) -> Self {
                 self.max_len = max_len;
                 self
             }
 
             /// Set the minimum number of allowed bytes.
-            pub fn min_len(mut self, min_len: u32) -> Self {
+            pub const fn min_len(mut self, min_len: u32) -> Self {
                 self.min_len = min_len;
                 self
             }
 
+            /// Add a LockId to the `lock_in` list of allowed recipients.
+            pub fn lock_add(mut self, add: impl Into<LockId>) -> Self {
+                self.lock_in.push(add.into());
+                self
+            }
+
+            /// Add a StreamId to the `stream_in` list of allowed recipients.
+            pub fn stream_add(mut self, add: impl Into<StreamId>) -> Self {
+                self.stream_in.push(add.into());
+                self
+            }
+
             /// Set whether or not queries can use the `max_len` and `min_len` values.
-            pub fn size(mut self, size: bool) -> Self {
+            pub const fn size(mut self, size: bool) -> Self {
                 self.size = size;
                 self
             }
 
+            /// Set whether or not queries can use the `lock_in` and `stream_in` lists.
+            pub const fn query(mut self, query: bool) -> Self {
+                self.query = query;
+                self
+            }
+
             /// Build this into a [`Validator`] enum.
             pub fn build(self) -> Validator {
                 Validator::$e(Box::new(self))
@@ -113,33 +154,54 @@ macro_rules! lockbox_validator {
             pub(crate) fn validate(&self, parser: &mut Parser) -> Result<()> {
                 let elem = parser
                     .next()
-                    .ok_or_else(|| Error::FailValidate(concat!("Expected a ",$name).to_string()))??;
+                    .ok_or_else(|| Error::FailValidate(ValidateError::Missing {
+                        expected: concat!("a ", $name),
+                    }))??;
                 let elem = if let Element::$e(v) = elem {
                     v
                 } else {
-                    return Err(Error::FailValidate(format!(
-                                concat!("Expected ", $name, ", got {}"),
-                                elem.name()
-                    )));
+                    return Err(Error::FailValidate(ValidateError::WrongType {
+                        expected: $name,
+                        actual: elem.name(),
+                    }));
                 };
 
                 let len = elem.as_bytes().len() as u32;
                 if len > self.max_len {
-                    return Err(Error::FailValidate(
-                            concat!($name, " is longer than max_len").to_string()
-                    ));
+                    return Err(Error::FailValidate(ValidateError::Limit {
+                        kind: $name,
+                        limit: "max_len",
+                        allowed: self.max_len,
+                        actual: len,
+                    }));
                 }
                 if len < self.min_len {
-                    return Err(Error::FailValidate(
-                            concat!($name, " is shorter than min_len").to_string()
-                    ));
+                    return Err(Error::FailValidate(ValidateError::Limit {
+                        kind: $name,
+                        limit: "min_len",
+                        allowed: self.min_len,
+                        actual: len,
+                    }));
+                }
+
+                if !self.lock_in.is_empty() || !self.stream_in.is_empty() {
+                    let allowed = match elem.recipient() {
+                        LockboxRecipient::LockId(id) => self.lock_in.contains(&id),
+                        LockboxRecipient::StreamId(id) => self.stream_in.contains(&id),
+                    };
+                    if !allowed {
+                        return Err(Error::FailValidate(ValidateError::Other(
+                            concat!($name, " recipient is not on `lock_in` or `stream_in` list").into(),
+                        )));
+                    }
                 }
 
                 Ok(())
             }
 
             fn query_check_self(&self, other: &Self) -> bool {
-                self.size || (u32_is_max(&other.max_len) && u32_is_zero(&other.min_len))
+                (self.size || (u32_is_max(&other.max_len) && u32_is_zero(&other.min_len)))
+                    && (self.query || (other.lock_in.is_empty() && other.stream_in.is_empty()))
             }
 
             pub(crate) fn query_check(&self, other: &Validator) -> bool {
@@ -165,3 +227,47 @@ lockbox_validator!(DataLockbox, DataLockbox, DataLockboxValidator);
 lockbox_validator!(IdentityLockbox, IdentityLockbox, IdentityLockboxValidator);
 lockbox_validator!(StreamLockbox, StreamLockbox, StreamLockboxValidator);
 lockbox_validator!(LockLockbox, LockLockbox, LockLockboxValidator);
+
+#[cfg(all(test, feature = "getrandom"))]
+mod test {
+    use super::*;
+    use crate::ser::FogSerializer;
+    use fog_crypto::{lock::LockKey, stream::StreamKey};
+
+    fn encode(lockbox: &impl Serialize) -> Vec<u8> {
+        let mut ser = FogSerializer::default();
+        lockbox.serialize(&mut ser).unwrap();
+        ser.finish()
+    }
+
+    #[test]
+    fn recipients() {
+        let stream = StreamKey::new();
+        let lock = LockKey::new();
+        let encoded = encode(&stream.encrypt_data(b"secret"));
+
+        let any = DataLockboxValidator::new();
+        any.validate(&mut Parser::new(&encoded)).unwrap();
+
+        let allowed = DataLockboxValidator::new().stream_add(stream.id().clone());
+        allowed.validate(&mut Parser::new(&encoded)).unwrap();
+
+        let other = DataLockboxValidator::new()
+            .stream_add(StreamKey::new().id().clone())
+            .lock_add(lock.id().clone());
+        assert!(other.validate(&mut Parser::new(&encoded)).is_err());
+
+        let encoded = encode(&lock.id().encrypt_data(b"secret"));
+        other.validate(&mut Parser::new(&encoded)).unwrap();
+        assert!(allowed.validate(&mut Parser::new(&encoded)).is_err());
+    }
+
+    #[test]
+    fn recipient_query() {
+        let query = DataLockboxValidator::new().stream_add(StreamKey::new().id().clone());
+        assert!(!DataLockboxValidator::new().query_check_self(&query));
+        assert!(DataLockboxValidator::new()
+            .query(true)
+            .query_check_self(&query));
+    }
+}
@@ -1,5 +1,5 @@
 use super::*;
-use crate::error::{Error, Result};
+use crate::error::{Error, Result, ValidateError};
 use crate::{de::FogDeserializer, element::*, value::Value, value_ref::ValueRef};
 use serde::{Deserialize, Deserializer, Serialize};
 use std::collections::BTreeSet;
@@ -56,6 +56,9 @@ fn get_validator<'de, D: Deserializer<'de>>(
 ///         2. If there is no validator for `values`, validation does not pass.
 /// - If `same_len` is not empty, the keys it lists must either all not exist, or if any of them
 ///     exist, they must all exist and their values must all be arrays with the same lengths.
+/// - For each prefix in `max_prefix`, the number of keys starting with that prefix must be less
+///     than or equal to the corresponding value.
+/// - For each set of keys in `req_any`, at least one of the keys must be in the map.
 ///
 /// Note how each key-value pair must be validated, so an unlimited collection of key-value pairs
 /// isn't allowed unless there is a validator present in `values`.
@@ -73,6 +76,8 @@ fn get_validator<'de, D: Deserializer<'de>>(
 /// - req: empty
 /// - opt: empty
 /// - same_len: empty
+/// - max_prefix: empty
+/// - req_any: empty
 /// - in_list: empty
 /// - nin_list: empty
 /// - query: false
@@ -111,8 +116,8 @@ fn get_validator<'de, D: Deserializer<'de>>(
 /// corresponding query permission is set in the schema's validator:
 ///
 /// - query: `in` and `nin` lists
-/// - size: `max_len` and `min_len`
-/// - map_ok: `req`, `opt`, `keys`, and `values`
+/// - size: `max_len`, `min_len`, and `max_prefix`
+/// - map_ok: `req`, `opt`, `req_any`, `keys`, and `values`
 /// - same_len_ok: `same_len`
 ///
 /// In addition, sub-validators in the query are matched against the schema's sub-validators:
@@ -170,16 +175,25 @@ pub struct MapValidator {
     /// same lengths.
     #[serde(skip_serializing_if = "BTreeSet::is_empty")]
     pub same_len: BTreeSet<String>,
+    /// A map from key prefixes to the maximum number of keys in the map that may start with each
+    /// prefix. Useful for extensible maps, where `max_len` alone can't limit how many extension
+    /// keys are used.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub max_prefix: BTreeMap<String, u32>,
+    /// Sets of keys, where a passing map must have at least one key from each set.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub req_any: Vec<BTreeSet<String>>,
     /// Indicates if the map is meant to be extensible.
     #[serde(skip_serializing_if = "is_false")]
     pub extend: bool,
     /// If true, queries against matching spots may have values in the `in` or `nin` lists.
     #[serde(skip_serializing_if = "is_false")]
     pub query: bool,
-    /// If true, queries against matching spots may use `max_len` and `min_len`.
+    /// If true, queries against matching spots may use `max_len`, `min_len`, and `max_prefix`.
     #[serde(skip_serializing_if = "is_false")]
     pub size: bool,
-    /// If true, queries against matching spots may use `req`, `opt`, `keys`, and `values`.
+    /// If true, queries against matching spots may use `req`, `opt`, `req_any`, `keys`, and
+    /// `values`.
     #[serde(skip_serializing_if = "is_false")]
     pub map_ok: bool,
     /// If true, queries against matching spots may use `same_len`.
@@ -189,6 +203,13 @@ pub struct MapValidator {
 
 impl Default for MapValidator {
     fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MapValidator {
+    /// Make a new validator with the default configuration.
+    pub const fn new() -> Self {
         Self {
             comment: String::new(),
             max_len: u32::MAX,
@@ -200,6 +221,8 @@ impl Default for MapValidator {
             in_list: Vec::new(),
             nin_list: Vec::new(),
             same_len: BTreeSet::new(),
+            max_prefix: BTreeMap::new(),
+            req_any: Vec::new(),
             extend: false,
             query: false,
             size: false,
@@ -207,13 +230,6 @@ impl Default for MapValidator {
             same_len_ok: false,
         }
     }
-}
-
-impl MapValidator {
-    /// Make a new validator with the default configuration.
-    pub fn new() -> Self {
-        Self::default()
-    }
 
     /// Set a comment for the validator.
     pub fn comment(mut self, comment: impl Into<String>) -> Self {
@@ -246,13 +262,13 @@ impl MapValidator {
     }
 
     /// Set the maximum number of allowed bytes.
-    pub fn max_len(mut self, max_len: u32) -> Self {
+    pub const fn max_len(mut self, max_len: u32) -> Self {
         self.max_len = max_len;
         self
     }
 
     /// Set the minimum number of allowed bytes.
-    pub fn min_len(mut self, min_len: u32) -> Self {
+    pub const fn min_len(mut self, min_len: u32) -> Self {
         self.min_len = min_len;
         self
     }
@@ -275,32 +291,46 @@ impl MapValidator {
         self
     }
 
+    /// Limit the number of keys starting with a prefix.
+    pub fn max_prefix_add(mut self, prefix: impl Into<String>, max: u32) -> Self {
+        self.max_prefix.insert(prefix.into(), max);
+        self
+    }
+
+    /// Add a set of keys to `req_any`, requiring at least one of them to be in the map.
+    pub fn req_any_add<S: Into<String>>(mut self, keys: impl IntoIterator<Item = S>) -> Self {
+        self.req_any
+            .push(keys.into_iter().map(Into::into).collect());
+        self
+    }
+
     /// Mark whether or not the map can be extended.
-    pub fn extensible(mut self, extend: bool) -> Self {
+    pub const fn extensible(mut self, extend: bool) -> Self {
         self.extend = extend;
         self
     }
 
     /// Set whether or not queries can use the `in` and `nin` lists.
-    pub fn query(mut self, query: bool) -> Self {
+    pub const fn query(mut self, query: bool) -> Self {
         self.query = query;
         self
     }
 
-    /// Set whether or not queries can use the `max_len` and `min_len` values.
-    pub fn size(mut self, size: bool) -> Self {
+    /// Set whether or not queries can use the `max_len`, `min_len`, and `max_prefix` values.
+    pub const fn size(mut self, size: bool) -> Self {
         self.size = size;
         self
     }
 
-    /// Set whether or not queries can use the `req`, `opt`, `ban`, and `values` values.
-    pub fn map_ok(mut self, map_ok: bool) -> Self {
+    /// Set whether or not queries can use the `req`, `opt`, `req_any`, `keys`, and `values`
+    /// values.
+    pub const fn map_ok(mut self, map_ok: bool) -> Self {
         self.map_ok = map_ok;
         self
     }
 
     /// Set whether or not queries can use the `same_len` value.
-    pub fn same_len_ok(mut self, same_len_ok: bool) -> Self {
+    pub const fn same_len_ok(mut self, same_len_ok: bool) -> Self {
         self.same_len_ok = same_len_ok;
         self
     }
@@ -319,27 +349,31 @@ impl MapValidator {
         let val_parser = parser.clone();
         let elem = parser
             .next()
-            .ok_or_else(|| Error::FailValidate("Expected a map".to_string()))??;
+            .ok_or_else(|| Error::FailValidate(ValidateError::Missing { expected: "a map" }))??;
         let len = if let Element::Map(len) = elem {
             len
         } else {
-            return Err(Error::FailValidate(format!(
-                "Expected Map, got {}",
-                elem.name()
-            )));
+            return Err(Error::FailValidate(ValidateError::WrongType {
+                expected: "Map",
+                actual: elem.name(),
+            }));
         };
 
         if (len as u32) > self.max_len {
-            return Err(Error::FailValidate(format!(
-                "Map is {} pairs, longer than maximum allowed of {}",
-                len, self.max_len
-            )));
+            return Err(Error::FailValidate(ValidateError::Limit {
+                kind: "Map",
+                limit: "max_len",
+                allowed: self.max_len,
+                actual: len as u32,
+            }));
         }
         if (len as u32) < self.min_len {
-            return Err(Error::FailValidate(format!(
-                "Map is {} pairs, shorter than minimum allowed of {}",
-                len, self.min_len
-            )));
+            return Err(Error::FailValidate(ValidateError::Limit {
+                kind: "Map",
+                limit: "min_len",
+                allowed: self.min_len,
+                actual: len as u32,
+            }));
         }
 
         // Check the requirements that require parsing the entire map
@@ -355,7 +389,9 @@ impl MapValidator {
                             .all(|((ks, vs), (ko, vo))| (ks == ko) && (vs == vo))
                 });
                 if !in_pass {
-                    return Err(Error::FailValidate("Map is not on `in` list".to_string()));
+                    return Err(Error::FailValidate(ValidateError::Other(
+                        "Map is not on `in` list".into(),
+                    )));
                 }
             }
 
@@ -366,45 +402,69 @@ impl MapValidator {
                         .all(|((ks, vs), (ko, vo))| (ks == ko) && (vs == vo))
             });
             if !nin_pass {
-                return Err(Error::FailValidate("Map is on `nin` list".to_string()));
+                return Err(Error::FailValidate(ValidateError::Other(
+                    "Map is on `nin` list".into(),
+                )));
             }
         }
 
         // Loop through each item, verifying it with the appropriate validator
         let mut reqs_found = 0;
+        let mut prefix_counts = vec![0u32; self.max_prefix.len()];
+        let mut any_found = vec![false; self.req_any.len()];
         let mut array_len: Option<usize> = None;
         let mut array_len_cnt = 0;
         for _ in 0..len {
             // Extract the key
-            let elem = parser
-                .next()
-                .ok_or_else(|| Error::FailValidate("expected a key string".to_string()))??;
+            let elem = parser.next().ok_or_else(|| {
+                Error::FailValidate(ValidateError::Missing {
+                    expected: "a key string",
+                })
+            })??;
             let key = if let Element::Str(v) = elem {
                 v
             } else {
-                return Err(Error::FailValidate(format!(
-                    "expected Str, got {}",
-                    elem.name()
-                )));
+                return Err(Error::FailValidate(ValidateError::WrongType {
+                    expected: "Str",
+                    actual: elem.name(),
+                }));
             };
 
+            for (count, (prefix, max)) in prefix_counts.iter_mut().zip(self.max_prefix.iter()) {
+                if key.starts_with(prefix.as_str()) {
+                    *count += 1;
+                    if *count > *max {
+                        return Err(Error::FailValidate(ValidateError::Other(
+                            format!("Map has more than {} keys starting with {:?}", max, prefix)
+                                .into(),
+                        )));
+                    }
+                }
+            }
+            for (found, keys) in any_found.iter_mut().zip(self.req_any.iter()) {
+                *found |= keys.contains(key);
+            }
+
             if self.same_len.contains(key) {
                 // Peek the array and its length
                 let elem = parser.peek().ok_or_else(|| {
-                    Error::FailValidate("expected an array element".to_string())
+                    Error::FailValidate(ValidateError::Missing {
+                        expected: "an array element",
+                    })
                 })??;
                 let Element::Array(len) = elem else {
-                    return Err(Error::FailValidate(format!(
-                        "expected array for key {:?}, got {}",
-                        key,
-                        elem.name()
+                    return Err(Error::FailValidate(ValidateError::Other(
+                        format!("expected array for key {:?}, got {}", key, elem.name()).into(),
                     )));
                 };
                 if let Some(array_len) = array_len {
                     if array_len != len {
-                        return Err(Error::FailValidate(format!(
-                            "expected array of length {} for key {:?}, but length was {}",
-                            array_len, key, len
+                        return Err(Error::FailValidate(ValidateError::Other(
+                            format!(
+                                "expected array of length {} for key {:?}, but length was {}",
+                                array_len, key, len
+                            )
+                            .into(),
                         )));
                     }
                 } else {
@@ -416,19 +476,24 @@ impl MapValidator {
             // Look up the appropriate validator and use it
             let (p, c) = if let Some(validator) = self.req.get(key) {
                 reqs_found += 1;
-                validator.validate(types, parser, checklist)?
+                validator
+                    .validate(types, parser, checklist)
+                    .map_err(|e| e.at_key(key))?
             } else if let Some(validator) = self.opt.get(key) {
-                validator.validate(types, parser, checklist)?
+                validator
+                    .validate(types, parser, checklist)
+                    .map_err(|e| e.at_key(key))?
             } else if let Some(validator) = &self.values {
                 // Make sure the key is valid before proceeding
                 if let Some(keys) = &self.keys {
                     keys.validate_str(key)?;
                 }
-                validator.validate(types, parser, checklist)?
+                validator
+                    .validate(types, parser, checklist)
+                    .map_err(|e| e.at_key(key))?
             } else {
-                return Err(Error::FailValidate(format!(
-                    "Map key {:?} has no corresponding validator",
-                    key
+                return Err(Error::FailValidate(ValidateError::Other(
+                    format!("Map key {:?} has no corresponding validator", key).into(),
                 )));
             };
 
@@ -437,15 +502,24 @@ impl MapValidator {
         }
 
         if array_len.is_some() && array_len_cnt != self.same_len.len() {
-            return Err(Error::FailValidate(
+            return Err(Error::FailValidate(ValidateError::Other(
                 "Map had some, but not all, of the keys listed in `same_len`".into(),
-            ));
+            )));
+        }
+
+        if let Some(i) = any_found.iter().position(|found| !found) {
+            return Err(Error::FailValidate(ValidateError::Other(
+                format!("Map did not have any of the keys in `req_any` set {}", i).into(),
+            )));
         }
 
         if reqs_found != self.req.len() {
-            return Err(Error::FailValidate(format!(
-                "Map did not have all required key-value pairs (missing {})",
-                reqs_found
+            return Err(Error::FailValidate(ValidateError::Other(
+                format!(
+                    "Map did not have all required key-value pairs (missing {})",
+                    reqs_found
+                )
+                .into(),
             )));
         }
 
@@ -454,11 +528,15 @@ impl MapValidator {
 
     fn query_check_self(&self, types: &BTreeMap<String, Validator>, other: &MapValidator) -> bool {
         let initial_check = (self.query || (other.in_list.is_empty() && other.nin_list.is_empty()))
-            && (self.size || (u32_is_max(&other.max_len) && u32_is_zero(&other.min_len)))
+            && (self.size
+                || (u32_is_max(&other.max_len)
+                    && u32_is_zero(&other.min_len)
+                    && other.max_prefix.is_empty()))
             && (self.same_len_ok || other.same_len.is_empty())
             && (self.map_ok
                 || (other.req.is_empty()
                     && other.opt.is_empty()
+                    && other.req_any.is_empty()
                     && other.keys.is_none()
                     && other.values.is_none()));
         if !initial_check {
@@ -554,6 +632,34 @@ mod test {
         assert_eq!(schema, decoded);
     }
 
+    #[test]
+    fn error_path() {
+        let schema = MapValidator::new().req_add(
+            "a",
+            ArrayValidator::new()
+                .items(IntValidator::new().build())
+                .build(),
+        );
+        let mut value = BTreeMap::new();
+        value.insert("a", vec![Value::from(1u8), Value::from("two")]);
+        let mut ser = FogSerializer::default();
+        value.serialize(&mut ser).unwrap();
+        let serialized = ser.finish();
+        let parser = Parser::new(&serialized);
+        let err = schema.validate(&BTreeMap::new(), parser, None).unwrap_err();
+        let expected = ValidateError::AtKey {
+            key: "a".into(),
+            err: Box::new(ValidateError::AtIndex {
+                index: 1,
+                err: Box::new(ValidateError::WrongType {
+                    expected: "Int",
+                    actual: "Str",
+                }),
+            }),
+        };
+        assert_eq!(err, Error::FailValidate(expected));
+    }
+
     #[test]
     fn same_len() {
         let schema = MapValidator::new()
@@ -613,4 +719,34 @@ mod test {
         let parser = Parser::new(&serialized);
         assert!(schema.validate(&BTreeMap::new(), parser, None).is_err());
     }
+
+    #[test]
+    fn key_counts() {
+        let schema = MapValidator::new()
+            .values(Validator::Any)
+            .max_prefix_add("ext_", 2)
+            .req_any_add(["id", "name"]);
+        let check = |value: Value| {
+            let mut ser = FogSerializer::default();
+            value.serialize(&mut ser).unwrap();
+            let serialized = ser.finish();
+            let parser = Parser::new(&serialized);
+            schema.validate(&BTreeMap::new(), parser, None).is_ok()
+        };
+        assert!(check(
+            crate::fog_value!({"id": 1, "ext_a": 1, "ext_b": 2, "other": 3})
+        ));
+        assert!(check(crate::fog_value!({"name": "x"})));
+        assert!(!check(crate::fog_value!({"ext_a": 1})));
+        assert!(!check(
+            crate::fog_value!({"id": 1, "ext_a": 1, "ext_b": 2, "ext_c": 3})
+        ));
+
+        // Round trip through encoding
+        let mut ser = FogSerializer::default();
+        schema.serialize(&mut ser).unwrap();
+        let actual = ser.finish();
+        let decoded = MapValidator::deserialize(&mut FogDeserializer::new(&actual)).unwrap();
+        assert_eq!(schema, decoded);
+    }
 }
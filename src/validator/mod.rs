@@ -28,10 +28,11 @@
 //! - [`StreamLockboxValidator`] - for [`StreamLockbox`][crate::types::StreamLockbox]
 //! - [`LockLockboxValidator`] - for [`LockLockbox`][crate::types::LockLockbox]
 //!
-//! In addition to the core types, there are 4 special validators:
+//! In addition to the core types, there are 5 special validators:
 //! - [`Validator::Ref`][Validator::new_ref] - a reference to a validator stored in a
 //!     schema's map of types. Uses a name to look up the validator.
 //! - [`MultiValidator`] - Will attempt a sequence of validators, passing if any one of them pass.
+//! - [`NumValidator`] - for numbers, whether they are integers, `f32`, or `f64` values.
 //! - [`EnumValidator`] - Acts as a validator for serialized Rust enums.
 //!     This can also be implemented through [`MapValidator`], but this
 //!     validator is generally easier to use correctly in such cases.
@@ -67,6 +68,42 @@
 //! # Ok(())
 //! # }
 //! ```
+//!
+//! # Reusing Validators
+//!
+//! Most validator structs can be created with `const fn` constructors and setters, so commonly
+//! used configurations can be kept in `const` items and copied into each schema that needs them.
+//! Setters that take collections, strings, or other validators still need to run at startup. The
+//! exceptions are [`ArrayValidator`], [`BinValidator`], and [`HashValidator`], whose defaults
+//! need allocations.
+//!
+//! Cloning a built [`Validator`] copies its tree, but not the regular expressions in it: a
+//! compiled [`Regex`][regex::Regex] shares its compiled form between clones, so copying a
+//! validator that uses [`StrValidator::matches`] doesn't compile anything again.
+//!
+//! Complete schemas are best built once and kept in a static. A
+//! [`Schema`][crate::schema::Schema] shares its parsed contents between clones, so handing out
+//! copies of it is cheap.
+//!
+//! ```
+//! # use fog_pack::{schema::*, validator::*};
+//! # use std::sync::OnceLock;
+//! const FILE_NAME: StrValidator = StrValidator::new().max_len(255).min_len(1);
+//!
+//! fn dir_schema() -> Schema {
+//!     static SCHEMA: OnceLock<Schema> = OnceLock::new();
+//!     SCHEMA
+//!         .get_or_init(|| {
+//!             let contents = MapValidator::new()
+//!                 .keys(FILE_NAME.ban_char("/\0"))
+//!                 .values(HashValidator::new().build());
+//!             let doc = SchemaBuilder::new(contents.build()).build().unwrap();
+//!             Schema::from_doc(&doc).unwrap()
+//!         })
+//!         .clone()
+//! }
+//! # assert_eq!(dir_schema().hash(), dir_schema().hash());
+//! ```
 
 mod array;
 mod bin;
@@ -82,9 +119,13 @@ mod lock_id;
 mod lockbox;
 mod map;
 mod multi;
+mod num;
+mod program;
+mod regex_cache;
 mod serde_regex;
 mod str;
 mod stream_id;
+mod subsume;
 mod time;
 
 pub use self::array::*;
@@ -101,19 +142,24 @@ pub use self::lock_id::*;
 pub use self::lockbox::*;
 pub use self::map::*;
 pub use self::multi::*;
+pub use self::num::*;
+pub(crate) use self::program::{Compiler, Program};
+pub use self::regex_cache::*;
 pub use self::str::*;
 pub use self::stream_id::*;
 pub use self::time::*;
 use crate::element::*;
-use crate::error::{Error, Result};
+use crate::error::{Error, Result, ValidateError};
+use crate::value_ref::ValueRef;
 
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
 /// [Unicode Normalization](http://www.unicode.org/reports/tr15/) settings.
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub enum Normalize {
     /// No normalization applied.
+    #[default]
     None,
     /// NFC normalization applied.
     NFC,
@@ -121,6 +167,40 @@ pub enum Normalize {
     NFKC,
 }
 
+/// The finest precision a timestamp may have. See [`TimeValidator`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Precision {
+    /// Whole seconds only.
+    Seconds,
+    /// Whole milliseconds only.
+    Millis,
+    /// Any timestamp, down to the nanosecond.
+    #[default]
+    Nanos,
+}
+
+impl Precision {
+    fn step(&self) -> u32 {
+        match self {
+            Precision::Seconds => 1_000_000_000,
+            Precision::Millis => 1_000_000,
+            Precision::Nanos => 1,
+        }
+    }
+
+    /// Check if a timestamp has no more precision than this.
+    pub fn allows(&self, time: crate::Timestamp) -> bool {
+        time.tai_subsec_nanos() % self.step() == 0
+    }
+
+    /// Truncate a timestamp to this precision, rounding it towards the past.
+    pub fn truncate(&self, time: crate::Timestamp) -> crate::Timestamp {
+        let nanos = time.tai_subsec_nanos();
+        crate::Timestamp::from_tai(time.tai_secs(), nanos - nanos % self.step())
+            .expect("truncating never adds nanoseconds")
+    }
+}
+
 /// A fog-pack Validator, for verifying the form of a fog-pack Document or Entry.
 ///
 /// Validators can be used to verify a fog-pack Document or Entry. Schemas use them for
@@ -141,6 +221,8 @@ pub enum Validator {
     F32(Box<F32Validator>),
     /// [`F64Validator`] - for `f64` values.
     F64(Box<F64Validator>),
+    /// [`NumValidator`] - for numbers, whether they are integers, `f32`, or `f64` values.
+    Num(Box<NumValidator>),
     /// [`BinValidator`] - for byte sequences.
     Bin(Box<BinValidator>),
     /// [`StrValidator`] - for UTF-8 strings.
@@ -189,21 +271,136 @@ impl Validator {
     }
 
     /// Create a new validator for the Null value.
-    pub fn new_null() -> Self {
+    pub const fn new_null() -> Self {
         Self::Null
     }
 
     /// Create a new validator for a [`BareIdKey`][crate::types::BareIdKey].
-    pub fn new_bare_id_key() -> Self {
+    pub const fn new_bare_id_key() -> Self {
         Self::BareIdKey
     }
 
     /// Create a new validator that passes any value.
-    pub fn new_any() -> Self {
+    pub const fn new_any() -> Self {
         Self::Any
     }
 
+    /// Check a value against this validator, without encoding it first. This is a cheap way to
+    /// check data, like user input, before building a document or entry out of it.
+    ///
+    /// Any [`Ref`][Validator::Ref] validators will fail, as there are no types to look them up
+    /// in; use [`Schema::validate_value`][crate::schema::Schema::validate_value] for validators
+    /// inside a schema. Hashes are checked, but not the documents they refer to. Floating-point
+    /// values are checked in the canonical form they would be encoded in.
+    pub fn validate_value(&self, value: &ValueRef) -> Result<()> {
+        self.validate_value_with(&BTreeMap::new(), crate::MAX_DEPTH, value)
+    }
+
+    pub(crate) fn validate_value_with(
+        &self,
+        types: &BTreeMap<String, Validator>,
+        max_depth: usize,
+        value: &ValueRef,
+    ) -> Result<()> {
+        let mut elems = Vec::new();
+        flatten(value, &mut elems);
+        let parser = Parser::from_elements(&elems).max_depth(max_depth);
+        let (parser, _) = self.validate(types, parser, None)?;
+        parser.finish()
+    }
+
     pub(crate) fn validate<'de, 'c>(
+        &'c self,
+        types: &'c BTreeMap<String, Validator>,
+        parser: Parser<'de>,
+        checklist: Option<Checklist<'c>>,
+    ) -> Result<(Parser<'de>, Option<Checklist<'c>>)> {
+        #[cfg(not(feature = "diagnostics"))]
+        {
+            self.validate_parsed(types, parser, checklist)
+        }
+        #[cfg(feature = "diagnostics")]
+        {
+            let start = parser.clone();
+            self.validate_parsed(types, parser, checklist)
+                .map_err(|err| self.diagnose(err, start))
+        }
+    }
+
+    /// Attach a snippet of the offending value and a description of this validator to a
+    /// validation failure, unless a nested validator already did.
+    #[cfg(feature = "diagnostics")]
+    fn diagnose(&self, err: Error, start: Parser) -> Error {
+        const MAX_SNIPPET: usize = 200;
+        let err = match err {
+            Error::FailValidate(err) => err,
+            err => return err,
+        };
+        if matches!(
+            err,
+            ValidateError::AtIndex { .. }
+                | ValidateError::AtKey { .. }
+                | ValidateError::Diagnostic { .. }
+        ) {
+            return Error::FailValidate(err);
+        }
+        let options = crate::pretty::PrettyOptions::new()
+            .indent("")
+            .max_bin(16)
+            .max_depth(2);
+        let mut value = crate::pretty::print_next(start, &options).replace('\n', " ");
+        if value.len() > MAX_SNIPPET {
+            let mut end = MAX_SNIPPET;
+            while !value.is_char_boundary(end) {
+                end -= 1;
+            }
+            value.truncate(end);
+            value.push_str("...");
+        }
+        Error::FailValidate(ValidateError::Diagnostic {
+            err: Box::new(err),
+            value,
+            validator: self.describe(),
+        })
+    }
+
+    /// Describe the validator by its type and, if it has one, its comment.
+    #[cfg(feature = "diagnostics")]
+    fn describe(&self) -> String {
+        let (name, comment) = match self {
+            Validator::Null => ("Null", ""),
+            Validator::Bool(v) => ("Bool", v.comment.as_str()),
+            Validator::Int(v) => ("Int", v.comment.as_str()),
+            Validator::F32(v) => ("F32", v.comment.as_str()),
+            Validator::F64(v) => ("F64", v.comment.as_str()),
+            Validator::Num(v) => ("Num", v.comment.as_str()),
+            Validator::Bin(v) => ("Bin", v.comment.as_str()),
+            Validator::Str(v) => ("Str", v.comment.as_str()),
+            Validator::Array(v) => ("Array", v.comment.as_str()),
+            Validator::Map(v) => ("Map", v.comment.as_str()),
+            Validator::Time(v) => ("Time", v.comment.as_str()),
+            Validator::Hash(v) => ("Hash", v.comment.as_str()),
+            Validator::Identity(v) => ("Identity", v.comment.as_str()),
+            Validator::StreamId(v) => ("StreamId", v.comment.as_str()),
+            Validator::LockId(v) => ("LockId", v.comment.as_str()),
+            Validator::BareIdKey => ("BareIdKey", ""),
+            Validator::DataLockbox(v) => ("DataLockbox", v.comment.as_str()),
+            Validator::IdentityLockbox(v) => ("IdentityLockbox", v.comment.as_str()),
+            Validator::StreamLockbox(v) => ("StreamLockbox", v.comment.as_str()),
+            Validator::LockLockbox(v) => ("LockLockbox", v.comment.as_str()),
+            Validator::Ref(name) => return format!("Ref({})", name),
+            Validator::Multi(_) => ("Multi", ""),
+            Validator::Enum(v) => ("Enum", v.comment.as_str()),
+            Validator::Any => ("Any", ""),
+        };
+        if comment.is_empty() {
+            name.to_owned()
+        } else {
+            format!("{} ({:?})", name, comment)
+        }
+    }
+
+    fn validate_parsed<'de, 'c>(
         &'c self,
         types: &'c BTreeMap<String, Validator>,
         mut parser: Parser<'de>,
@@ -211,13 +408,16 @@ impl Validator {
     ) -> Result<(Parser<'de>, Option<Checklist<'c>>)> {
         match self {
             Validator::Null => {
-                let elem = parser
-                    .next()
-                    .ok_or_else(|| Error::FailValidate("expected null".to_string()))??;
+                let elem = parser.next().ok_or_else(|| {
+                    Error::FailValidate(ValidateError::Missing { expected: "null" })
+                })??;
                 if let Element::Null = elem {
                     Ok((parser, checklist))
                 } else {
-                    Err(Error::FailValidate("expected null".to_string()))
+                    Err(Error::FailValidate(ValidateError::WrongType {
+                        expected: "Null",
+                        actual: elem.name(),
+                    }))
                 }
             }
             Validator::Bool(validator) => {
@@ -236,6 +436,10 @@ impl Validator {
                 validator.validate(&mut parser)?;
                 Ok((parser, checklist))
             }
+            Validator::Num(validator) => {
+                validator.validate(&mut parser)?;
+                Ok((parser, checklist))
+            }
             Validator::Bin(validator) => {
                 validator.validate(&mut parser)?;
                 Ok((parser, checklist))
@@ -267,13 +471,18 @@ impl Validator {
                 Ok((parser, checklist))
             }
             Validator::BareIdKey => {
-                let elem = parser
-                    .next()
-                    .ok_or_else(|| Error::FailValidate("expected BareIdKey".to_string()))??;
+                let elem = parser.next().ok_or_else(|| {
+                    Error::FailValidate(ValidateError::Missing {
+                        expected: "BareIdKey",
+                    })
+                })??;
                 if let Element::BareIdKey(_) = elem {
                     Ok((parser, checklist))
                 } else {
-                    Err(Error::FailValidate("expected BareIdKey".to_string()))
+                    Err(Error::FailValidate(ValidateError::WrongType {
+                        expected: "BareIdKey",
+                        actual: elem.name(),
+                    }))
                 }
             }
             Validator::DataLockbox(validator) => {
@@ -298,12 +507,13 @@ impl Validator {
                 // All other validators pull at least one element, ensuring infinite
                 // recursion/cycling is impossible.
                 let validator = types.get(ref_name).ok_or_else(|| {
-                    Error::FailValidate(format!("validator Ref({}) not in list of types", ref_name))
+                    Error::FailValidate(ValidateError::Other(
+                        format!("validator Ref({}) not in list of types", ref_name).into(),
+                    ))
                 })?;
                 match validator {
-                    Validator::Ref(_) => Err(Error::FailValidate(format!(
-                        "validator Ref({}) is itself a Ref",
-                        ref_name
+                    Validator::Ref(_) => Err(Error::FailValidate(ValidateError::Other(
+                        format!("validator Ref({}) is itself a Ref", ref_name).into(),
                     ))),
                     _ => validator.validate(types, parser, checklist),
                 }
@@ -328,6 +538,7 @@ impl Validator {
             Validator::Int(validator) => validator.query_check(other),
             Validator::F32(validator) => validator.query_check(other),
             Validator::F64(validator) => validator.query_check(other),
+            Validator::Num(validator) => validator.query_check(other),
             Validator::Bin(validator) => validator.query_check(other),
             Validator::Str(validator) => validator.query_check(other),
             Validator::Time(validator) => validator.query_check(other),
@@ -359,11 +570,51 @@ impl Validator {
     }
 }
 
+/// Flatten a value into the sequence of elements its encoding would be parsed into.
+fn flatten<'a>(value: &ValueRef<'a>, elems: &mut Vec<Element<'a>>) {
+    elems.push(match value {
+        ValueRef::Null => Element::Null,
+        ValueRef::Bool(v) => Element::Bool(*v),
+        ValueRef::Int(v) => Element::Int(*v),
+        ValueRef::Str(v) => Element::Str(v),
+        ValueRef::F32(v) => Element::F32(canonical_f32(*v)),
+        ValueRef::F64(v) => Element::F64(canonical_f64(*v)),
+        ValueRef::Bin(v) => Element::Bin(v),
+        ValueRef::Array(array) => {
+            elems.push(Element::Array(array.len()));
+            for item in array.iter() {
+                flatten(item, elems);
+            }
+            return;
+        }
+        ValueRef::Map(map) => {
+            elems.push(Element::Map(map.len()));
+            for (key, item) in map.iter() {
+                elems.push(Element::Str(key));
+                flatten(item, elems);
+            }
+            return;
+        }
+        ValueRef::Hash(v) => Element::Hash(v.clone()),
+        ValueRef::Identity(v) => Element::Identity(Box::new(v.clone())),
+        ValueRef::StreamId(v) => Element::StreamId(Box::new(v.clone())),
+        ValueRef::LockId(v) => Element::LockId(Box::new(v.clone())),
+        ValueRef::Timestamp(v) => Element::Timestamp(*v),
+        ValueRef::DataLockbox(v) => Element::DataLockbox(v),
+        ValueRef::IdentityLockbox(v) => Element::IdentityLockbox(v),
+        ValueRef::StreamLockbox(v) => Element::StreamLockbox(v),
+        ValueRef::LockLockbox(v) => Element::LockLockbox(v),
+        ValueRef::BareIdKey(v) => Element::BareIdKey(v.clone()),
+    });
+}
+
 fn read_any(parser: &mut Parser) -> Result<()> {
     fn get_elem<'a>(parser: &mut Parser<'a>) -> Result<Element<'a>> {
-        parser
-            .next()
-            .ok_or_else(|| Error::FailValidate("expected another value".to_string()))?
+        parser.next().ok_or_else(|| {
+            Error::FailValidate(ValidateError::Missing {
+                expected: "another value",
+            })
+        })?
     }
     let elem = get_elem(parser)?;
     match elem {
@@ -373,17 +624,17 @@ fn read_any(parser: &mut Parser) -> Result<()> {
                 if let Element::Str(key) = get_elem(parser)? {
                     if let Some(last_key) = last_key {
                         if key <= last_key {
-                            return Err(Error::FailValidate(format!(
-                                "map keys are unordered: {} follows {}",
-                                key, last_key
+                            return Err(Error::FailValidate(ValidateError::Other(
+                                format!("map keys are unordered: {} follows {}", key, last_key)
+                                    .into(),
                             )));
                         }
                     }
                     last_key = Some(key);
                 } else {
-                    return Err(Error::FailValidate(
-                        "expected string for map key".to_string(),
-                    ));
+                    return Err(Error::FailValidate(ValidateError::Other(
+                        "expected string for map key".into(),
+                    )));
                 }
                 read_any(parser)?;
             }
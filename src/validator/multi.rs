@@ -1,5 +1,5 @@
 use super::*;
-use crate::error::{Error, Result};
+use crate::error::{Error, Result, ValidateError};
 use serde::{Deserialize, Serialize};
 use std::default::Default;
 
@@ -28,13 +28,19 @@ use std::default::Default;
 /// Multi-validator. Contained validators that violate the cyclic reference rules are skipped (see
 /// above).
 ///
-#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct MultiValidator(pub Vec<Validator>);
 
+impl Default for MultiValidator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MultiValidator {
     /// Make a new validator with the default configuration.
-    pub fn new() -> Self {
-        Self::default()
+    pub const fn new() -> Self {
+        Self(Vec::new())
     }
 
     /// Add a new validator to the list.
@@ -84,9 +90,9 @@ impl MultiValidator {
                 return new_result;
             }
         }
-        Err(Error::FailValidate(
-            "validator Multi had no passing validators".to_string(),
-        ))
+        Err(Error::FailValidate(ValidateError::Other(
+            "validator Multi had no passing validators".into(),
+        )))
     }
 
     pub(crate) fn query_check(
@@ -0,0 +1,316 @@
+use super::*;
+use crate::element::*;
+use crate::error::{Error, Result, ValidateError};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+#[inline]
+fn is_false(v: &bool) -> bool {
+    !v
+}
+#[inline]
+fn is_nan(v: &f64) -> bool {
+    v.is_nan()
+}
+
+// Compare an integer against a non-NaN bound exactly, without rounding the integer to an f64
+// first.
+fn cmp_int(int: i128, bound: f64) -> Ordering {
+    // 2^64 and -2^63 are exact in f64, and lie just outside the range of an Integer
+    if bound >= 18446744073709551616.0 {
+        return Ordering::Less;
+    }
+    if bound < -9223372036854775808.0 {
+        return Ordering::Greater;
+    }
+    let whole = bound.trunc();
+    int.cmp(&(whole as i128)).then_with(|| {
+        let frac = bound - whole;
+        if frac > 0.0 {
+            Ordering::Less
+        } else if frac < 0.0 {
+            Ordering::Greater
+        } else {
+            Ordering::Equal
+        }
+    })
+}
+
+/// Validator for numeric values, whether they are encoded as integers, f32, or f64.
+///
+/// This validator will pass Int, F32, and F64 values, checking all of them against the same
+/// bounds. F32 values are widened to f64 before being checked, and integers are compared exactly
+/// against the bounds, without first being rounded to the nearest f64. This makes it possible to
+/// bound values that different producers encode differently, without a [`MultiValidator`] holding
+/// the same bounds once per type. Validation passes if:
+///
+/// - If `max` is a number, that the value is less than the maximum in `max`, or equal to it if
+///     `ex_max` is not set to true. NaN is never less than the maximum, so it fails.
+/// - If `max` is NaN but `ex_max` is set, that the value is neither NaN nor positive infinity.
+/// - If `min` is a number, that the value is greater than the minimum in `min`, or equal to it if
+///     `ex_min` is not set to true. NaN is never greater than the minimum, so it fails.
+/// - If `min` is NaN but `ex_min` is set, that the value is neither NaN nor negative infinity.
+///
+/// # Defaults
+///
+/// Fields that aren't specified for the validator use their defaults instead. The defaults for
+/// each field are:
+///
+/// - comment: ""
+/// - max: NaN
+/// - min: NaN
+/// - ex_max: false
+/// - ex_min: false
+/// - ord: false
+///
+/// # Example
+///
+/// ```
+/// # use fog_pack::{validator::*, schema::*, document::*};
+/// # fn main() -> fog_pack::error::Result<()> {
+/// let schema_doc = SchemaBuilder::new(NumValidator::new().min(0.0).max(100.0).build()).build()?;
+/// let schema = Schema::from_doc(&schema_doc)?;
+/// assert!(schema.validate_new_doc(NewDocument::new(None, 42u8)?).is_ok());
+/// assert!(schema.validate_new_doc(NewDocument::new(None, 42.5f32)?).is_ok());
+/// assert!(schema.validate_new_doc(NewDocument::new(None, 100.5f64)?).is_err());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct NumValidator {
+    /// An optional comment explaining the validator.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub comment: String,
+    /// The maximum allowed value. If NaN, it is ignored.
+    #[serde(skip_serializing_if = "is_nan")]
+    pub max: f64,
+    /// The minimum allowed value. If NaN, it is ignored.
+    #[serde(skip_serializing_if = "is_nan")]
+    pub min: f64,
+    /// Changes `max` into an exclusive maximum.
+    #[serde(skip_serializing_if = "is_false")]
+    pub ex_max: bool,
+    /// Changes `min` into an exclusive minimum.
+    #[serde(skip_serializing_if = "is_false")]
+    pub ex_min: bool,
+    /// If true, queries against matching spots may set the `max`, `min`, `ex_max`, and `ex_min`
+    /// values to non-defaults.
+    #[serde(skip_serializing_if = "is_false")]
+    pub ord: bool,
+}
+
+impl Default for NumValidator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NumValidator {
+    /// Make a new validator with the default configuration.
+    pub const fn new() -> Self {
+        Self {
+            comment: String::new(),
+            max: f64::NAN,
+            min: f64::NAN,
+            ex_max: false,
+            ex_min: false,
+            ord: false,
+        }
+    }
+
+    /// Set a comment for the validator.
+    pub fn comment(mut self, comment: impl Into<String>) -> Self {
+        self.comment = comment.into();
+        self
+    }
+
+    /// Set the maximum allowed value.
+    pub const fn max(mut self, max: f64) -> Self {
+        self.max = max;
+        self
+    }
+
+    /// Set the minimum allowed value.
+    pub const fn min(mut self, min: f64) -> Self {
+        self.min = min;
+        self
+    }
+
+    /// Set whether or not `max` is an exclusive maximum. Setting this without a maximum excludes
+    /// NaN and positive infinity.
+    pub const fn ex_max(mut self, ex_max: bool) -> Self {
+        self.ex_max = ex_max;
+        self
+    }
+
+    /// Set whether or not `min` is an exclusive minimum. Setting this without a minimum excludes
+    /// NaN and negative infinity.
+    pub const fn ex_min(mut self, ex_min: bool) -> Self {
+        self.ex_min = ex_min;
+        self
+    }
+
+    /// Set whether or not queries can use the `max`, `min`, `ex_max`, and `ex_min` values.
+    pub const fn ord(mut self, ord: bool) -> Self {
+        self.ord = ord;
+        self
+    }
+
+    /// Build this into a [`Validator`] enum.
+    pub fn build(self) -> Validator {
+        Validator::Num(Box::new(self))
+    }
+
+    pub(crate) fn validate(&self, parser: &mut Parser) -> Result<()> {
+        let elem = parser.next().ok_or_else(|| {
+            Error::FailValidate(ValidateError::Missing {
+                expected: "a number",
+            })
+        })??;
+        let (below_max, above_min) = match elem {
+            Element::Int(v) => {
+                let v = v.as_i128();
+                let below_max = self.max.is_nan() || {
+                    let ord = cmp_int(v, self.max);
+                    ord == Ordering::Less || (ord == Ordering::Equal && !self.ex_max)
+                };
+                let above_min = self.min.is_nan() || {
+                    let ord = cmp_int(v, self.min);
+                    ord == Ordering::Greater || (ord == Ordering::Equal && !self.ex_min)
+                };
+                (below_max, above_min)
+            }
+            Element::F32(v) => self.float_bounds(v as f64),
+            Element::F64(v) => self.float_bounds(v),
+            elem => {
+                return Err(Error::FailValidate(ValidateError::WrongType {
+                    expected: "Num",
+                    actual: elem.name(),
+                }))
+            }
+        };
+        if !below_max {
+            return Err(Error::FailValidate(ValidateError::Other(
+                "Number greater than maximum allowed".into(),
+            )));
+        }
+        if !above_min {
+            return Err(Error::FailValidate(ValidateError::Other(
+                "Number less than minimum allowed".into(),
+            )));
+        }
+        Ok(())
+    }
+
+    fn float_bounds(&self, elem: f64) -> (bool, bool) {
+        let below_max = match (self.max.is_nan(), self.ex_max) {
+            (true, false) => true,
+            (true, true) => elem < f64::INFINITY,
+            (false, true) => elem < self.max,
+            (false, false) => elem <= self.max,
+        };
+        let above_min = match (self.min.is_nan(), self.ex_min) {
+            (true, false) => true,
+            (true, true) => elem > f64::NEG_INFINITY,
+            (false, true) => elem > self.min,
+            (false, false) => elem >= self.min,
+        };
+        (below_max, above_min)
+    }
+
+    fn query_check_num(&self, other: &Self) -> bool {
+        self.ord || (!other.ex_min && !other.ex_max && other.min.is_nan() && other.max.is_nan())
+    }
+
+    pub(crate) fn query_check(&self, other: &Validator) -> bool {
+        match other {
+            Validator::Num(other) => self.query_check_num(other),
+            Validator::Multi(list) => list.iter().all(|other| match other {
+                Validator::Num(other) => self.query_check_num(other),
+                _ => false,
+            }),
+            Validator::Any => true,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::integer::Integer;
+
+    fn passes(validator: &NumValidator, elem: Element) -> bool {
+        let mut buf = Vec::new();
+        serialize_elem(&mut buf, elem);
+        let mut parser = Parser::new(&buf);
+        validator.validate(&mut parser).is_ok()
+    }
+
+    #[test]
+    fn mixed_encodings() {
+        let percent = NumValidator::new().min(0.0).max(100.0);
+        assert!(passes(&percent, Element::Int(Integer::from(0u8))));
+        assert!(passes(&percent, Element::Int(Integer::from(100u8))));
+        assert!(passes(&percent, Element::F32(99.5)));
+        assert!(passes(&percent, Element::F64(0.25)));
+        assert!(!passes(&percent, Element::Int(Integer::from(-1i8))));
+        assert!(!passes(&percent, Element::Int(Integer::from(101u8))));
+        assert!(!passes(&percent, Element::F32(100.5)));
+        assert!(!passes(&percent, Element::F64(-0.25)));
+        assert!(!passes(&percent, Element::Str("50")));
+        assert!(!passes(&percent, Element::Null));
+
+        // Fractional bounds apply to integers too
+        let between = NumValidator::new().min(-1.5).max(2.5);
+        assert!(passes(&between, Element::Int(Integer::from(-1i8))));
+        assert!(passes(&between, Element::Int(Integer::from(2u8))));
+        assert!(!passes(&between, Element::Int(Integer::from(-2i8))));
+        assert!(!passes(&between, Element::Int(Integer::from(3u8))));
+        let exclusive = NumValidator::new().max(2.0).ex_max(true);
+        assert!(passes(&exclusive, Element::Int(Integer::from(1u8))));
+        assert!(!passes(&exclusive, Element::Int(Integer::from(2u8))));
+    }
+
+    #[test]
+    fn exact_integers() {
+        // u64::MAX rounds up to 2^64 as an f64, but is still below it
+        let below = NumValidator::new().max(18446744073709551616.0).ex_max(true);
+        assert!(passes(&below, Element::Int(Integer::from(u64::MAX))));
+        // 2^53 + 1 rounds down to 2^53 as an f64, but is still above it
+        let at_most = NumValidator::new().max(9007199254740992.0);
+        assert!(passes(&at_most, Element::Int(Integer::from(1u64 << 53))));
+        assert!(!passes(
+            &at_most,
+            Element::Int(Integer::from((1u64 << 53) + 1))
+        ));
+        let at_least = NumValidator::new().min(-9223372036854775808.0);
+        assert!(passes(&at_least, Element::Int(Integer::from(i64::MIN))));
+        let above = NumValidator::new().min(-9223372036854775808.0).ex_min(true);
+        assert!(!passes(&above, Element::Int(Integer::from(i64::MIN))));
+        assert!(passes(&above, Element::Int(Integer::from(i64::MIN + 1))));
+        let infinite = NumValidator::new()
+            .min(f64::NEG_INFINITY)
+            .max(f64::INFINITY)
+            .ex_min(true)
+            .ex_max(true);
+        assert!(passes(&infinite, Element::Int(Integer::from(u64::MAX))));
+        assert!(passes(&infinite, Element::Int(Integer::from(i64::MIN))));
+    }
+
+    #[test]
+    fn nan_and_infinities() {
+        let finite = NumValidator::new().ex_min(true).ex_max(true);
+        assert!(passes(&finite, Element::F64(f64::MAX)));
+        assert!(passes(&finite, Element::Int(Integer::from(u64::MAX))));
+        assert!(!passes(&finite, Element::F32(f32::INFINITY)));
+        assert!(!passes(&finite, Element::F64(f64::NEG_INFINITY)));
+        assert!(!passes(&finite, Element::F32(f32::NAN)));
+        assert!(passes(&NumValidator::new(), Element::F64(f64::NAN)));
+        assert!(!passes(
+            &NumValidator::new().max(1.0),
+            Element::F64(f64::NAN)
+        ));
+    }
+}
@@ -0,0 +1,668 @@
+//! Validator programs, compiled from a schema's validators when the schema is loaded.
+//!
+//! A [`Validator`] tree is validated recursively, looking up every [`Ref`][Validator::Ref] by
+//! name as it's reached. A [`Program`] instead flattens the tree into a list of instructions,
+//! where each array, map, multi, and enum validator refers to the validators it holds by their
+//! index in the list, and each reference is resolved ahead of time to the index of the type it
+//! names. Recursive types become loops in the program, and an interpreter walks it with an
+//! explicit stack, so validating deeply nested data never recurses.
+//!
+//! Validators that don't hold other validators are run as-is. So are arrays and maps that use
+//! checks needing the whole value at once (`in`, `nin`, `unique`, `contains`, and `same_len`),
+//! which fall back to the recursive validator for that part of the data.
+//!
+//! The interpreter only decides whether data passes. Failing data is run through the original
+//! validator tree to get the same error it would always have produced.
+
+use std::collections::BTreeSet;
+
+use super::*;
+
+/// A single instruction in a [`Program`].
+#[derive(Clone, Debug)]
+enum Op {
+    /// Always fails. Used for references that don't resolve to a usable validator.
+    Fail,
+    /// Run a validator directly.
+    Value(Validator),
+    /// An array, checking each item with the instruction for its position.
+    Array(ArrayOp),
+    /// A map, checking each value with the instruction for its key.
+    Map(MapOp),
+    /// Try each instruction in turn, passing with the first one that passes.
+    Multi(Vec<usize>),
+    /// An enum, with the instruction for each variant's value, if it has one.
+    Enum(BTreeMap<String, Option<usize>>),
+}
+
+#[derive(Clone, Debug)]
+struct ArrayOp {
+    max_len: u32,
+    min_len: u32,
+    prefix: Vec<usize>,
+    items: usize,
+}
+
+impl ArrayOp {
+    fn item(&self, index: usize) -> usize {
+        self.prefix.get(index).copied().unwrap_or(self.items)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct MapOp {
+    max_len: u32,
+    min_len: u32,
+    /// The instruction for each key in `req` or `opt`, and whether the key is required. A key in
+    /// both uses the `req` validator, just like [`MapValidator`] does.
+    fields: BTreeMap<String, (usize, bool)>,
+    req_len: usize,
+    keys: Option<Box<StrValidator>>,
+    values: Option<usize>,
+    max_prefix: Vec<(String, u32)>,
+    req_any: Vec<BTreeSet<String>>,
+}
+
+/// A partially checked map, waiting on the value of its current key.
+#[derive(Debug)]
+struct MapState<'c> {
+    op: &'c MapOp,
+    remaining: usize,
+    reqs_found: usize,
+    prefix_counts: Vec<u32>,
+    any_found: Vec<bool>,
+}
+
+impl<'c> MapState<'c> {
+    fn new(op: &'c MapOp, len: usize) -> Self {
+        Self {
+            op,
+            remaining: len,
+            reqs_found: 0,
+            prefix_counts: vec![0; op.max_prefix.len()],
+            any_found: vec![false; op.req_any.len()],
+        }
+    }
+
+    /// Read the next key, returning the instruction for its value.
+    fn next_key(&mut self, parser: &mut Parser) -> Option<usize> {
+        let Some(Ok(Element::Str(key))) = parser.next() else {
+            return None;
+        };
+        for (count, (prefix, max)) in self.prefix_counts.iter_mut().zip(self.op.max_prefix.iter()) {
+            if key.starts_with(prefix.as_str()) {
+                *count += 1;
+                if *count > *max {
+                    return None;
+                }
+            }
+        }
+        for (found, keys) in self.any_found.iter_mut().zip(self.op.req_any.iter()) {
+            *found |= keys.contains(key);
+        }
+        if let Some(&(op, required)) = self.op.fields.get(key) {
+            if required {
+                self.reqs_found += 1;
+            }
+            return Some(op);
+        }
+        let values = self.op.values?;
+        match &self.op.keys {
+            Some(keys) if keys.validate_str(key).is_err() => None,
+            _ => Some(values),
+        }
+    }
+
+    /// Check the requirements that can only be checked once every key has been seen.
+    fn finish(&self) -> Option<()> {
+        (self.any_found.iter().all(|found| *found) && self.reqs_found == self.op.req_len)
+            .then_some(())
+    }
+}
+
+/// An array or map the interpreter is partway through, or a multi validator waiting on one of
+/// its options.
+#[derive(Debug)]
+enum Frame<'c> {
+    Array {
+        op: &'c ArrayOp,
+        index: usize,
+        len: usize,
+    },
+    Map(MapState<'c>),
+    Multi,
+}
+
+/// The state to go back to when an option of a multi validator fails.
+struct Choice<'de, 'c> {
+    parser: Parser<'de>,
+    checklist: Option<Checklist<'c>>,
+    /// How many frames were on the stack before the multi validator's own frame
+    frames: usize,
+    options: &'c [usize],
+    next: usize,
+}
+
+/// What the interpreter does next.
+enum Step {
+    /// Validate the next value with an instruction.
+    Enter(usize),
+    /// A value passed, so continue with whatever held it.
+    Done,
+}
+
+/// A compiled set of validators. See the [module-level documentation][self].
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Program {
+    ops: Vec<Op>,
+}
+
+impl Program {
+    /// Validate the next value in `parser`, starting with the instruction at `root`. `validator`
+    /// must be the validator that `root` was compiled from, and `types` the types it was compiled
+    /// with. Works exactly like [`Validator::validate`].
+    pub(crate) fn validate<'de, 'c>(
+        &'c self,
+        root: usize,
+        validator: &'c Validator,
+        types: &'c BTreeMap<String, Validator>,
+        parser: Parser<'de>,
+        checklist: Option<Checklist<'c>>,
+    ) -> Result<(Parser<'de>, Option<Checklist<'c>>)> {
+        match self.run(root, types, parser.clone(), checklist.clone()) {
+            Some(passed) => Ok(passed),
+            // Get the detailed error from the validator itself
+            None => validator.validate(types, parser, checklist),
+        }
+    }
+
+    fn run<'de, 'c>(
+        &'c self,
+        root: usize,
+        types: &'c BTreeMap<String, Validator>,
+        mut parser: Parser<'de>,
+        mut checklist: Option<Checklist<'c>>,
+    ) -> Option<(Parser<'de>, Option<Checklist<'c>>)> {
+        let mut frames: Vec<Frame<'c>> = Vec::new();
+        let mut choices: Vec<Choice<'de, 'c>> = Vec::new();
+        let mut step = Step::Enter(root);
+        loop {
+            let result = match step {
+                Step::Enter(op) => self.enter(
+                    op,
+                    types,
+                    &mut parser,
+                    &mut checklist,
+                    &mut frames,
+                    &mut choices,
+                ),
+                Step::Done => match frames.last_mut() {
+                    None => return Some((parser, checklist)),
+                    Some(Frame::Multi) => {
+                        // An option passed, so the others will never be needed
+                        frames.pop();
+                        choices.pop();
+                        Some(Step::Done)
+                    }
+                    Some(Frame::Array { op, index, len }) => {
+                        *index += 1;
+                        if *index == *len {
+                            frames.pop();
+                            Some(Step::Done)
+                        } else {
+                            Some(Step::Enter(op.item(*index)))
+                        }
+                    }
+                    Some(Frame::Map(state)) => {
+                        state.remaining -= 1;
+                        if state.remaining == 0 {
+                            let finished = state.finish();
+                            frames.pop();
+                            finished.map(|_| Step::Done)
+                        } else {
+                            state.next_key(&mut parser).map(Step::Enter)
+                        }
+                    }
+                },
+            };
+            step = match result {
+                Some(step) => step,
+                None => backtrack(&mut parser, &mut checklist, &mut frames, &mut choices)?,
+            };
+        }
+    }
+
+    /// Start validating a value with an instruction, returning `None` if it fails.
+    fn enter<'de, 'c>(
+        &'c self,
+        op: usize,
+        types: &'c BTreeMap<String, Validator>,
+        parser: &mut Parser<'de>,
+        checklist: &mut Option<Checklist<'c>>,
+        frames: &mut Vec<Frame<'c>>,
+        choices: &mut Vec<Choice<'de, 'c>>,
+    ) -> Option<Step> {
+        match &self.ops[op] {
+            Op::Fail => None,
+            Op::Value(validator) => {
+                let taken = std::mem::replace(parser, Parser::new(&[]));
+                let (p, c) = validator.validate(types, taken, checklist.take()).ok()?;
+                *parser = p;
+                *checklist = c;
+                Some(Step::Done)
+            }
+            Op::Array(op) => {
+                let Some(Ok(Element::Array(len))) = parser.next() else {
+                    return None;
+                };
+                if (len as u32) > op.max_len || (len as u32) < op.min_len {
+                    return None;
+                }
+                if len == 0 {
+                    return Some(Step::Done);
+                }
+                frames.push(Frame::Array { op, index: 0, len });
+                Some(Step::Enter(op.item(0)))
+            }
+            Op::Map(op) => {
+                let Some(Ok(Element::Map(len))) = parser.next() else {
+                    return None;
+                };
+                if (len as u32) > op.max_len || (len as u32) < op.min_len {
+                    return None;
+                }
+                let mut state = MapState::new(op, len);
+                if len == 0 {
+                    return state.finish().map(|_| Step::Done);
+                }
+                let next = state.next_key(parser)?;
+                frames.push(Frame::Map(state));
+                Some(Step::Enter(next))
+            }
+            Op::Multi(options) => {
+                let first = *options.first()?;
+                choices.push(Choice {
+                    parser: parser.clone(),
+                    checklist: checklist.clone(),
+                    frames: frames.len(),
+                    options,
+                    next: 1,
+                });
+                frames.push(Frame::Multi);
+                Some(Step::Enter(first))
+            }
+            Op::Enum(variants) => {
+                let (key, has_value) = match parser.next()?.ok()? {
+                    Element::Str(key) => (key, false),
+                    Element::Map(1) => match parser.next()?.ok()? {
+                        Element::Str(key) => (key, true),
+                        _ => return None,
+                    },
+                    _ => return None,
+                };
+                match (variants.get(key)?, has_value) {
+                    (None, false) => Some(Step::Done),
+                    (Some(op), true) => Some(Step::Enter(*op)),
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+/// Go back to the most recent multi validator that still has options left to try, returning the
+/// next option. Returns `None` if there are none, meaning validation failed.
+fn backtrack<'de, 'c>(
+    parser: &mut Parser<'de>,
+    checklist: &mut Option<Checklist<'c>>,
+    frames: &mut Vec<Frame<'c>>,
+    choices: &mut Vec<Choice<'de, 'c>>,
+) -> Option<Step> {
+    loop {
+        let choice = choices.last_mut()?;
+        if let Some(&op) = choice.options.get(choice.next) {
+            choice.next += 1;
+            frames.truncate(choice.frames + 1);
+            *parser = choice.parser.clone();
+            *checklist = choice.checklist.clone();
+            return Some(Step::Enter(op));
+        }
+        frames.truncate(choice.frames);
+        choices.pop();
+    }
+}
+
+/// Compiles validators into a single [`Program`], sharing the instructions for each named type
+/// between every validator that refers to it.
+pub(crate) struct Compiler<'a> {
+    types: &'a BTreeMap<String, Validator>,
+    named: BTreeMap<&'a str, usize>,
+    ops: Vec<Op>,
+}
+
+impl<'a> Compiler<'a> {
+    pub(crate) fn new(types: &'a BTreeMap<String, Validator>) -> Self {
+        Self {
+            types,
+            named: BTreeMap::new(),
+            ops: Vec::new(),
+        }
+    }
+
+    /// Compile a validator, returning the index of the instruction to start it with.
+    pub(crate) fn compile(&mut self, validator: &Validator) -> usize {
+        if let Validator::Ref(name) = validator {
+            return self.compile_ref(name);
+        }
+        let index = self.ops.len();
+        self.ops.push(Op::Fail);
+        let op = self.build(validator);
+        self.ops[index] = op;
+        index
+    }
+
+    pub(crate) fn finish(self) -> Program {
+        Program { ops: self.ops }
+    }
+
+    fn compile_ref(&mut self, name: &str) -> usize {
+        let types = self.types;
+        let Some((name, validator)) = types.get_key_value(name) else {
+            // Missing types always fail
+            self.ops.push(Op::Fail);
+            return self.ops.len() - 1;
+        };
+        if let Some(&index) = self.named.get(name.as_str()) {
+            return index;
+        }
+        // Register the type before building it, so references back to it find it
+        let index = self.ops.len();
+        self.ops.push(Op::Fail);
+        self.named.insert(name.as_str(), index);
+        // A reference to another reference always fails, as it could be cyclic
+        if !matches!(validator, Validator::Ref(_)) {
+            let op = self.build(validator);
+            self.ops[index] = op;
+        }
+        index
+    }
+
+    fn build(&mut self, validator: &Validator) -> Op {
+        match validator {
+            Validator::Array(array)
+                if array.contains.is_empty()
+                    && array.in_list.is_empty()
+                    && array.nin_list.is_empty()
+                    && array.same_len.is_empty()
+                    && !array.unique =>
+            {
+                Op::Array(ArrayOp {
+                    max_len: array.max_len,
+                    min_len: array.min_len,
+                    prefix: array.prefix.iter().map(|v| self.compile(v)).collect(),
+                    items: self.compile(&array.items),
+                })
+            }
+            Validator::Map(map)
+                if map.in_list.is_empty() && map.nin_list.is_empty() && map.same_len.is_empty() =>
+            {
+                let mut fields = BTreeMap::new();
+                for (key, v) in map.opt.iter() {
+                    fields.insert(key.clone(), (self.compile(v), false));
+                }
+                for (key, v) in map.req.iter() {
+                    fields.insert(key.clone(), (self.compile(v), true));
+                }
+                Op::Map(MapOp {
+                    max_len: map.max_len,
+                    min_len: map.min_len,
+                    fields,
+                    req_len: map.req.len(),
+                    keys: map.keys.clone(),
+                    values: map.values.as_deref().map(|v| self.compile(v)),
+                    max_prefix: map
+                        .max_prefix
+                        .iter()
+                        .map(|(prefix, max)| (prefix.clone(), *max))
+                        .collect(),
+                    req_any: map.req_any.clone(),
+                })
+            }
+            Validator::Multi(multi) => {
+                // Skip the same options the multi validator does, as they could be cyclic
+                let types = self.types;
+                let options: Vec<&Validator> = multi
+                    .iter()
+                    .filter(|v| match v {
+                        Validator::Multi(_) => false,
+                        Validator::Ref(name) => !matches!(
+                            types.get(name),
+                            None | Some(Validator::Ref(_) | Validator::Multi(_))
+                        ),
+                        _ => true,
+                    })
+                    .collect();
+                Op::Multi(options.into_iter().map(|v| self.compile(v)).collect())
+            }
+            Validator::Enum(variants) => Op::Enum(
+                variants
+                    .var
+                    .iter()
+                    .map(|(key, v)| (key.clone(), v.as_ref().map(|v| self.compile(v))))
+                    .collect(),
+            ),
+            validator => Op::Value(validator.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{fog_value, ser::FogSerializer, value::Value};
+
+    fn encode(value: &Value) -> Vec<u8> {
+        let mut ser = FogSerializer::default();
+        value.serialize(&mut ser).unwrap();
+        ser.finish()
+    }
+
+    // Check a validator's program against the validator itself, for every value
+    fn check_equivalent(
+        types: &BTreeMap<String, Validator>,
+        validator: &Validator,
+        values: &[Value],
+    ) {
+        let mut compiler = Compiler::new(types);
+        let root = compiler.compile(validator);
+        let program = compiler.finish();
+        for value in values {
+            let encoded = encode(value);
+            let tree = validator.validate(types, Parser::new(&encoded), None);
+            let compiled = program.run(root, types, Parser::new(&encoded), None);
+            assert_eq!(
+                tree.as_ref().ok().map(|(p, _)| p.remaining()),
+                compiled.as_ref().map(|(p, _)| p.remaining()),
+                "validator {:?} disagreed on {:?}",
+                validator,
+                value
+            );
+            let result = program.validate(root, validator, types, Parser::new(&encoded), None);
+            match (tree, result) {
+                (Ok(_), Ok(_)) => (),
+                (Err(tree), Err(result)) => assert_eq!(tree.to_string(), result.to_string()),
+                _ => panic!("validator {:?} disagreed on {:?}", validator, value),
+            }
+        }
+    }
+
+    fn values() -> Vec<Value> {
+        vec![
+            fog_value!(null),
+            fog_value!(true),
+            fog_value!(1),
+            fog_value!(-5),
+            fog_value!("a"),
+            fog_value!("hello"),
+            fog_value!([]),
+            fog_value!([1, 2, 3]),
+            fog_value!([1, "a", 3]),
+            fog_value!([[1], [2, [3]]]),
+            fog_value!({}),
+            fog_value!({"a": 1}),
+            fog_value!({"a": 1, "b": "x"}),
+            fog_value!({"a": "x", "b": 2}),
+            fog_value!({"b": 2}),
+            fog_value!({"x_1": 1, "x_2": 2, "x_3": 3}),
+            fog_value!({"next": {"next": {"next": null}}}),
+            fog_value!({"next": {"next": {"value": 1}}}),
+            fog_value!({"Some": 3}),
+            fog_value!({"Some": "3"}),
+            fog_value!("None"),
+            fog_value!({"None": 1}),
+        ]
+    }
+
+    #[test]
+    fn leaves() {
+        let types = BTreeMap::new();
+        for validator in [
+            Validator::Null,
+            Validator::Any,
+            IntValidator::new().min(0).build(),
+            StrValidator::new().max_len(1).build(),
+            BoolValidator::new().build(),
+        ] {
+            check_equivalent(&types, &validator, &values());
+        }
+    }
+
+    #[test]
+    fn containers() {
+        let types = BTreeMap::new();
+        for validator in [
+            ArrayValidator::new()
+                .items(IntValidator::new().build())
+                .build(),
+            ArrayValidator::new()
+                .prefix_add(IntValidator::new().build())
+                .items(StrValidator::new().build())
+                .max_len(2)
+                .build(),
+            ArrayValidator::new().min_len(1).build(),
+            ArrayValidator::new()
+                .items(IntValidator::new().build())
+                .unique(true)
+                .build(),
+            MapValidator::new()
+                .req_add("a", IntValidator::new().build())
+                .opt_add("b", StrValidator::new().build())
+                .build(),
+            MapValidator::new()
+                .req_add("a", IntValidator::new().build())
+                .opt_add("a", StrValidator::new().build())
+                .values(Validator::Any)
+                .build(),
+            MapValidator::new()
+                .keys(StrValidator::new().max_len(1))
+                .values(Validator::Any)
+                .build(),
+            MapValidator::new()
+                .values(IntValidator::new().build())
+                .max_prefix_add("x_", 2)
+                .build(),
+            MapValidator::new()
+                .values(Validator::Any)
+                .req_any_add(["a", "b"])
+                .max_len(1)
+                .build(),
+            EnumValidator::new()
+                .insert("Some", Some(IntValidator::new().build()))
+                .insert("None", None)
+                .build(),
+        ] {
+            check_equivalent(&types, &validator, &values());
+        }
+    }
+
+    #[test]
+    fn multi_and_refs() {
+        let mut types = BTreeMap::new();
+        // A linked list, referring back to itself
+        types.insert(
+            "list".to_string(),
+            MapValidator::new()
+                .opt_add("next", Validator::new_ref("list"))
+                .opt_add("value", IntValidator::new().build())
+                .build(),
+        );
+        types.insert(
+            "either".to_string(),
+            MultiValidator::new()
+                .push(IntValidator::new().build())
+                .push(Validator::new_ref("list"))
+                .build(),
+        );
+        types.insert("alias".to_string(), Validator::new_ref("list"));
+        types.insert(
+            "nested".to_string(),
+            ArrayValidator::new()
+                .items(
+                    MultiValidator::new()
+                        .push(IntValidator::new().build())
+                        .push(Validator::new_ref("nested"))
+                        .build(),
+                )
+                .build(),
+        );
+        for validator in [
+            Validator::new_ref("list"),
+            Validator::new_ref("either"),
+            Validator::new_ref("alias"),
+            Validator::new_ref("missing"),
+            Validator::new_ref("nested"),
+            MultiValidator::new().build(),
+            MultiValidator::new()
+                .push(Validator::new_ref("either"))
+                .push(Validator::new_ref("missing"))
+                .push(StrValidator::new().max_len(1).build())
+                .push(ArrayValidator::new().build())
+                .build(),
+            MultiValidator::new()
+                .push(
+                    ArrayValidator::new()
+                        .items(IntValidator::new().max(1).build())
+                        .build(),
+                )
+                .push(ArrayValidator::new().build())
+                .build(),
+            MapValidator::new()
+                .values(
+                    MultiValidator::new()
+                        .push(IntValidator::new().build())
+                        .push(StrValidator::new().build())
+                        .build(),
+                )
+                .build(),
+        ] {
+            check_equivalent(&types, &validator, &values());
+        }
+    }
+
+    #[test]
+    fn deep_nesting() {
+        // Deeply nested data is walked without recursion
+        let mut types = BTreeMap::new();
+        types.insert(
+            "nested".to_string(),
+            ArrayValidator::new()
+                .items(Validator::new_ref("nested"))
+                .build(),
+        );
+        let mut value = fog_value!([]);
+        for _ in 0..50 {
+            value = Value::Array(vec![value]);
+        }
+        check_equivalent(&types, &Validator::new_ref("nested"), &[value]);
+    }
+}
@@ -0,0 +1,202 @@
+use regex::{Regex, RegexBuilder};
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+static REGEX_CACHE: OnceLock<RegexCache> = OnceLock::new();
+
+/// Limits on the regular expressions that can be compiled through a [`RegexCache`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegexLimits {
+    /// The maximum size of a compiled regular expression, in bytes. Default is 1 MiB.
+    pub size_limit: usize,
+    /// The maximum nesting depth of a regular expression. Default is 64.
+    pub nest_limit: u32,
+    /// The largest count allowed in a counted repetition, like `a{1000}`. Default is 1000.
+    pub max_repeat: u32,
+    /// The maximum number of compiled regular expressions kept in the cache. Default is 1024.
+    pub max_entries: usize,
+}
+
+impl Default for RegexLimits {
+    fn default() -> Self {
+        Self {
+            size_limit: 1 << 20,
+            nest_limit: 64,
+            max_repeat: 1000,
+            max_entries: 1024,
+        }
+    }
+}
+
+/// A cache of compiled regular expressions, shared by every schema and query.
+///
+/// Every regular expression in a [`StrValidator`][super::StrValidator] is compiled through the
+/// [global][RegexCache::global] cache when it is decoded, so a node evaluating many queries that
+/// use the same patterns only compiles each one once. The cache also enforces [`RegexLimits`],
+/// rejecting patterns that would compile into excessively large programs.
+#[derive(Debug)]
+pub struct RegexCache {
+    inner: Mutex<CacheInner>,
+}
+
+#[derive(Debug)]
+struct CacheInner {
+    limits: RegexLimits,
+    entries: HashMap<String, Regex>,
+}
+
+impl RegexCache {
+    /// Create a new, empty cache with the given limits.
+    pub fn new(limits: RegexLimits) -> Self {
+        Self {
+            inner: Mutex::new(CacheInner {
+                limits,
+                entries: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Get the global cache used when decoding validators.
+    pub fn global() -> &'static RegexCache {
+        REGEX_CACHE.get_or_init(|| RegexCache::new(RegexLimits::default()))
+    }
+
+    fn lock(&self) -> MutexGuard<CacheInner> {
+        match self.inner.lock() {
+            Ok(o) => o,
+            Err(e) => e.into_inner(),
+        }
+    }
+
+    /// Replace the cache's limits. This clears the cache, so that previously compiled regular
+    /// expressions are checked against the new limits the next time they are requested.
+    pub fn set_limits(&self, limits: RegexLimits) {
+        let mut inner = self.lock();
+        inner.limits = limits;
+        inner.entries.clear();
+    }
+
+    /// Get the cache's current limits.
+    pub fn limits(&self) -> RegexLimits {
+        self.lock().limits.clone()
+    }
+
+    /// Get the number of compiled regular expressions currently in the cache.
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    /// Check if the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.lock().entries.is_empty()
+    }
+
+    /// Remove every compiled regular expression from the cache.
+    pub fn clear(&self) {
+        self.lock().entries.clear();
+    }
+
+    /// Get a compiled regular expression, compiling and caching it if it isn't already present.
+    /// Fails if the pattern is invalid or breaks the cache's limits.
+    pub fn get(&self, pattern: &str) -> Result<Regex, String> {
+        let mut inner = self.lock();
+        if let Some(regex) = inner.entries.get(pattern) {
+            return Ok(regex.clone());
+        }
+        let limits = &inner.limits;
+        if let Some(count) = max_repetition(pattern) {
+            if count > limits.max_repeat {
+                return Err(format!(
+                    "regex repetition count {} exceeds the limit of {}",
+                    count, limits.max_repeat
+                ));
+            }
+        }
+        let regex = RegexBuilder::new(pattern)
+            .size_limit(limits.size_limit)
+            .nest_limit(limits.nest_limit)
+            .build()
+            .map_err(|e| e.to_string())?;
+        // Regular expressions are rarely unique enough for eviction order to matter much, so
+        // the whole cache is simply dropped once it fills up.
+        if inner.entries.len() >= inner.limits.max_entries {
+            inner.entries.clear();
+        }
+        if inner.limits.max_entries > 0 {
+            inner.entries.insert(pattern.to_owned(), regex.clone());
+        }
+        Ok(regex)
+    }
+}
+
+/// Find the largest count used in any counted repetition (`{n}`, `{n,}`, or `{n,m}`) in a
+/// pattern. This doesn't fully parse the pattern; anything that isn't a valid repetition is left
+/// for the regex compiler to reject.
+fn max_repetition(pattern: &str) -> Option<u32> {
+    let mut max = None;
+    let mut chars = pattern.chars().peekable();
+    let mut in_class = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '[' => in_class = true,
+            ']' => in_class = false,
+            '{' if !in_class => loop {
+                let mut count: u32 = 0;
+                while let Some(d) = chars.peek().and_then(|d| d.to_digit(10)) {
+                    count = count.saturating_mul(10).saturating_add(d);
+                    chars.next();
+                }
+                max = max.max(Some(count));
+                if chars.peek() != Some(&',') {
+                    break;
+                }
+                chars.next();
+            },
+            _ => (),
+        }
+    }
+    max
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn caches() {
+        let cache = RegexCache::new(RegexLimits::default());
+        let a = cache.get("[a-z]+").unwrap();
+        assert_eq!(cache.len(), 1);
+        let b = cache.get("[a-z]+").unwrap();
+        assert_eq!(cache.len(), 1);
+        assert_eq!(a.as_str(), b.as_str());
+        cache.get("[0-9]+").unwrap();
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get("(").is_err());
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn limits() {
+        let cache = RegexCache::new(RegexLimits {
+            max_entries: 1,
+            ..RegexLimits::default()
+        });
+        assert!(cache.get("a{1000}").is_ok());
+        assert!(cache.get("a{1001}").is_err());
+        assert!(cache.get("a{2,5000}").is_err());
+        assert!(cache.get("[{]5000").is_ok());
+        assert!(cache.get(r"\{5000").is_ok());
+        assert_eq!(cache.len(), 1);
+
+        cache.set_limits(RegexLimits {
+            size_limit: 1 << 10,
+            ..RegexLimits::default()
+        });
+        assert!(cache.is_empty());
+        assert!(cache.get(r"\w{500}").is_err());
+    }
+}
@@ -23,6 +23,6 @@ where
     // ends up as an Option. This is because we chose to have validators where the field is
     // either defined, or it is absent.
     let regex: String = String::deserialize(deserializer)?;
-    let regex = Regex::new(&regex).map_err(|e| D::Error::custom(e.to_string()))?;
+    let regex = RegexCache::global().get(&regex).map_err(D::Error::custom)?;
     Ok(Some(Box::new(regex)))
 }
@@ -1,6 +1,6 @@
 use super::*;
 use crate::element::*;
-use crate::error::{Error, Result};
+use crate::error::{Error, Result, ValidateError};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 
@@ -24,6 +24,19 @@ fn normalize_is_none(v: &Normalize) -> bool {
     matches!(v, Normalize::None)
 }
 
+#[cfg(feature = "graphemes")]
+fn count_graphemes(val: &str) -> Result<u32> {
+    use unicode_segmentation::UnicodeSegmentation;
+    Ok(val.graphemes(true).count() as u32)
+}
+
+#[cfg(not(feature = "graphemes"))]
+fn count_graphemes(_: &str) -> Result<u32> {
+    Err(Error::FailValidate(ValidateError::Other(
+        "grapheme limits can't be checked without the `graphemes` feature".into(),
+    )))
+}
+
 /// Validator for UTF-8 strings.
 ///
 /// This validator type will only pass string values. Validation passes if:
@@ -32,6 +45,10 @@ fn normalize_is_none(v: &Normalize) -> bool {
 /// - The value's length in bytes is greater than or equal to the value in `min_len`.
 /// - The value's number of unicode characters is less than or equal to the value in `max_char`.
 /// - The value's number of unicode characters is greater than or equal to the value in `min_char`.
+/// - The value's number of extended grapheme clusters is less than or equal to the value in
+///     `max_grapheme`.
+/// - The value's number of extended grapheme clusters is greater than or equal to the value in
+///     `min_grapheme`.
 /// - The value does not begin with any of the prefixes in the `ban_prefix` list.
 /// - The value does not end with any of the suffixes in the `ban_suffix` list.
 /// - The value does not contain any of the characters in the `ban_char` string.
@@ -58,6 +75,8 @@ fn normalize_is_none(v: &Normalize) -> bool {
 /// - min_len: 0
 /// - max_char: u32::MAX
 /// - min_char: 0
+/// - max_grapheme: u32::MAX
+/// - min_grapheme: 0
 /// - normalize: Normalize::None
 /// - ban_prefix: empty
 /// - ban_suffix: empty
@@ -103,6 +122,20 @@ fn normalize_is_none(v: &Normalize) -> bool {
 /// before running validation. This is settable through the `normalization` field, which can be
 /// `None`, `NFC`, or `NFKC`.
 ///
+/// # Length Limits
+///
+/// There are three ways to measure a string's length: `min_len` and `max_len` count bytes, which
+/// is what matters for storage; `min_char` and `max_char` count Unicode scalar values; and
+/// `min_grapheme` and `max_grapheme` count extended grapheme clusters, which are closest to what
+/// a person would call a character. An emoji with a skin tone modifier is 8 bytes, 2 scalar
+/// values, and 1 grapheme cluster. User-facing limits, like the length of a display name, should
+/// usually count grapheme clusters.
+///
+/// Counting grapheme clusters needs the `graphemes` feature. Without it, any validator with
+/// grapheme limits fails every string, rather than passing strings it can't check. Like regular
+/// expressions, grapheme clusters depend on the Unicode version, so a string near a limit may
+/// pass with one library version and fail with another.
+///
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(deny_unknown_fields, default)]
 pub struct StrValidator {
@@ -115,7 +148,8 @@ pub struct StrValidator {
     /// A vector of specific unallowed values, stored under the `nin` field.
     #[serde(rename = "nin", skip_serializing_if = "Vec::is_empty")]
     pub nin_list: Vec<String>,
-    /// A regular expression that the value must match against.
+    /// A regular expression that the value must match against. Cloning it is cheap, as the
+    /// compiled expression is shared between clones.
     #[serde(skip_serializing_if = "Option::is_none", with = "serde_regex")]
     pub matches: Option<Box<Regex>>,
     /// The maximum allowed number of bytes in the string value.
@@ -130,6 +164,12 @@ pub struct StrValidator {
     /// The minimum allowed number of unicode characters in the string value.
     #[serde(skip_serializing_if = "u32_is_zero")]
     pub min_char: u32,
+    /// The maximum allowed number of extended grapheme clusters in the string value.
+    #[serde(skip_serializing_if = "u32_is_max")]
+    pub max_grapheme: u32,
+    /// The minimum allowed number of extended grapheme clusters in the string value.
+    #[serde(skip_serializing_if = "u32_is_zero")]
+    pub min_grapheme: u32,
     /// The Unicode normalization setting.
     #[serde(skip_serializing_if = "normalize_is_none")]
     pub normalize: Normalize,
@@ -152,8 +192,8 @@ pub struct StrValidator {
     /// `ban_char` values to non-defaults.
     #[serde(skip_serializing_if = "is_false")]
     pub ban: bool,
-    /// If true, queries against matching spots may set the `max_len`, `min_len`, `max_char`, and
-    /// `min_char` values to non-defaults.
+    /// If true, queries against matching spots may set the `max_len`, `min_len`, `max_char`,
+    /// `min_char`, `max_grapheme`, and `min_grapheme` values to non-defaults.
     #[serde(skip_serializing_if = "is_false")]
     pub size: bool,
 }
@@ -167,6 +207,8 @@ impl PartialEq for StrValidator {
             && (self.min_len == rhs.min_len)
             && (self.max_char == rhs.max_char)
             && (self.min_char == rhs.min_char)
+            && (self.max_grapheme == rhs.max_grapheme)
+            && (self.min_grapheme == rhs.min_grapheme)
             && (self.normalize == rhs.normalize)
             && (self.ban_prefix == rhs.ban_prefix)
             && (self.ban_suffix == rhs.ban_suffix)
@@ -184,8 +226,15 @@ impl PartialEq for StrValidator {
     }
 }
 
-impl std::default::Default for StrValidator {
+impl Default for StrValidator {
     fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StrValidator {
+    /// Make a new validator with the default configuration.
+    pub const fn new() -> Self {
         Self {
             comment: String::new(),
             in_list: Vec::new(),
@@ -195,6 +244,8 @@ impl std::default::Default for StrValidator {
             min_len: u32::MIN,
             max_char: u32::MAX,
             min_char: u32::MIN,
+            max_grapheme: u32::MAX,
+            min_grapheme: u32::MIN,
             normalize: Normalize::None,
             ban_prefix: Vec::new(),
             ban_suffix: Vec::new(),
@@ -205,13 +256,6 @@ impl std::default::Default for StrValidator {
             size: false,
         }
     }
-}
-
-impl StrValidator {
-    /// Make a new validator with the default configuration.
-    pub fn new() -> Self {
-        Self::default()
-    }
 
     /// Set a comment for the validator.
     pub fn comment(mut self, comment: impl Into<String>) -> Self {
@@ -220,31 +264,45 @@ impl StrValidator {
     }
 
     /// Set the maximum number of allowed bytes.
-    pub fn max_len(mut self, max_len: u32) -> Self {
+    pub const fn max_len(mut self, max_len: u32) -> Self {
         self.max_len = max_len;
         self
     }
 
     /// Set the minimum number of allowed bytes.
-    pub fn min_len(mut self, min_len: u32) -> Self {
+    pub const fn min_len(mut self, min_len: u32) -> Self {
         self.min_len = min_len;
         self
     }
 
     /// Set the maximum number of allowed characters.
-    pub fn max_char(mut self, max_char: u32) -> Self {
+    pub const fn max_char(mut self, max_char: u32) -> Self {
         self.max_char = max_char;
         self
     }
 
     /// Set the minimum number of allowed characters.
-    pub fn min_char(mut self, min_char: u32) -> Self {
+    pub const fn min_char(mut self, min_char: u32) -> Self {
         self.min_char = min_char;
         self
     }
 
+    /// Set the maximum number of allowed extended grapheme clusters. Checking this needs the
+    /// `graphemes` feature.
+    pub const fn max_grapheme(mut self, max_grapheme: u32) -> Self {
+        self.max_grapheme = max_grapheme;
+        self
+    }
+
+    /// Set the minimum number of allowed extended grapheme clusters. Checking this needs the
+    /// `graphemes` feature.
+    pub const fn min_grapheme(mut self, min_grapheme: u32) -> Self {
+        self.min_grapheme = min_grapheme;
+        self
+    }
+
     /// Set the unicode normalization form to use for `in`, `nin`, and `matches` checks.
-    pub fn normalize(mut self, normalize: Normalize) -> Self {
+    pub const fn normalize(mut self, normalize: Normalize) -> Self {
         self.normalize = normalize;
         self
     }
@@ -286,26 +344,26 @@ impl StrValidator {
     }
 
     /// Set whether or not queries can use the `in` and `nin` lists.
-    pub fn query(mut self, query: bool) -> Self {
+    pub const fn query(mut self, query: bool) -> Self {
         self.query = query;
         self
     }
 
     /// Set whether or not queries can use the `matches` value.
-    pub fn regex(mut self, regex: bool) -> Self {
+    pub const fn regex(mut self, regex: bool) -> Self {
         self.regex = regex;
         self
     }
 
     /// Set whether or not queries can use the `ban_prefix`, `ban_suffix`, and `ban_char` values.
-    pub fn ban(mut self, ban: bool) -> Self {
+    pub const fn ban(mut self, ban: bool) -> Self {
         self.ban = ban;
         self
     }
 
-    /// Set whether or not queries can use the `max_len`, `min_len`, `max_char`, and `min_char`
-    /// values.
-    pub fn size(mut self, ord: bool) -> Self {
+    /// Set whether or not queries can use the `max_len`, `min_len`, `max_char`, `min_char`,
+    /// `max_grapheme`, and `min_grapheme` values.
+    pub const fn size(mut self, ord: bool) -> Self {
         self.size = ord;
         self
     }
@@ -317,16 +375,18 @@ impl StrValidator {
 
     pub(crate) fn validate(&self, parser: &mut Parser) -> Result<()> {
         // Get element
-        let elem = parser
-            .next()
-            .ok_or_else(|| Error::FailValidate("expected a string".to_string()))??;
+        let elem = parser.next().ok_or_else(|| {
+            Error::FailValidate(ValidateError::Missing {
+                expected: "a string",
+            })
+        })??;
         let val = if let Element::Str(v) = elem {
             v
         } else {
-            return Err(Error::FailValidate(format!(
-                "expected Str, got {}",
-                elem.name()
-            )));
+            return Err(Error::FailValidate(ValidateError::WrongType {
+                expected: "Str",
+                actual: elem.name(),
+            }));
         };
         self.validate_str(val)
     }
@@ -334,26 +394,57 @@ impl StrValidator {
     pub(crate) fn validate_str(&self, val: &str) -> Result<()> {
         // Length Checks
         if (val.len() as u32) > self.max_len {
-            return Err(Error::FailValidate(
-                "String is longer than max_len".to_string(),
-            ));
+            return Err(Error::FailValidate(ValidateError::Limit {
+                kind: "String",
+                limit: "max_len",
+                allowed: self.max_len,
+                actual: val.len() as u32,
+            }));
         }
         if (val.len() as u32) < self.min_len {
-            return Err(Error::FailValidate(
-                "String is shorter than min_len".to_string(),
-            ));
+            return Err(Error::FailValidate(ValidateError::Limit {
+                kind: "String",
+                limit: "min_len",
+                allowed: self.min_len,
+                actual: val.len() as u32,
+            }));
         }
         if self.max_char < u32::MAX || self.min_char > 0 {
             let len_char = bytecount::num_chars(val.as_bytes()) as u32;
             if len_char > self.max_char {
-                return Err(Error::FailValidate(
-                    "String is longer than max_len".to_string(),
-                ));
+                return Err(Error::FailValidate(ValidateError::Limit {
+                    kind: "String",
+                    limit: "max_char",
+                    allowed: self.max_char,
+                    actual: len_char,
+                }));
             }
             if len_char < self.min_char {
-                return Err(Error::FailValidate(
-                    "String is shorter than min_len".to_string(),
-                ));
+                return Err(Error::FailValidate(ValidateError::Limit {
+                    kind: "String",
+                    limit: "min_char",
+                    allowed: self.min_char,
+                    actual: len_char,
+                }));
+            }
+        }
+        if self.max_grapheme < u32::MAX || self.min_grapheme > 0 {
+            let len_grapheme = count_graphemes(val)?;
+            if len_grapheme > self.max_grapheme {
+                return Err(Error::FailValidate(ValidateError::Limit {
+                    kind: "String",
+                    limit: "max_grapheme",
+                    allowed: self.max_grapheme,
+                    actual: len_grapheme,
+                }));
+            }
+            if len_grapheme < self.min_grapheme {
+                return Err(Error::FailValidate(ValidateError::Limit {
+                    kind: "String",
+                    limit: "min_grapheme",
+                    allowed: self.min_grapheme,
+                    actual: len_grapheme,
+                }));
             }
         }
 
@@ -364,38 +455,37 @@ impl StrValidator {
         match self.normalize {
             Normalize::None => {
                 if !self.in_list.is_empty() && !self.in_list.iter().any(|v| *v == val) {
-                    return Err(Error::FailValidate(
-                        "String is not on `in` list".to_string(),
-                    ));
+                    return Err(Error::FailValidate(ValidateError::Other(
+                        "String is not on `in` list".into(),
+                    )));
                 }
                 if self.nin_list.iter().any(|v| *v == val) {
-                    return Err(Error::FailValidate("String is on `nin` list".to_string()));
+                    return Err(Error::FailValidate(ValidateError::Other(
+                        "String is on `nin` list".into(),
+                    )));
                 }
                 if let Some(pre) = self.ban_prefix.iter().find(|v| val.starts_with(*v)) {
-                    return Err(Error::FailValidate(format!(
-                        "String begins with banned prefix {:?}",
-                        pre
+                    return Err(Error::FailValidate(ValidateError::Other(
+                        format!("String begins with banned prefix {:?}", pre).into(),
                     )));
                 }
                 if let Some(suf) = self.ban_suffix.iter().find(|v| val.ends_with(*v)) {
-                    return Err(Error::FailValidate(format!(
-                        "String ends with banned suffix {:?}",
-                        suf
+                    return Err(Error::FailValidate(ValidateError::Other(
+                        format!("String ends with banned suffix {:?}", suf).into(),
                     )));
                 }
                 if !self.ban_char.is_empty() {
                     if let Some(c) = val.chars().find(|c| self.ban_char.contains(*c)) {
-                        return Err(Error::FailValidate(format!(
-                            "String contains banned character {:?}",
-                            c
+                        return Err(Error::FailValidate(ValidateError::Other(
+                            format!("String contains banned character {:?}", c).into(),
                         )));
                     }
                 }
                 if let Some(ref regex) = self.matches {
                     if !regex.is_match(val) {
-                        return Err(Error::FailValidate(
-                            "String doesn't match regular expression".to_string(),
-                        ));
+                        return Err(Error::FailValidate(ValidateError::Other(
+                            "String doesn't match regular expression".into(),
+                        )));
                     }
                 }
             }
@@ -411,23 +501,22 @@ impl StrValidator {
 
                 if !self.in_list.is_empty() && !self.in_list.iter().any(|v| v.nfc().eq(val.chars()))
                 {
-                    return Err(Error::FailValidate(
-                        "NFC String is not on `in` list".to_string(),
-                    ));
+                    return Err(Error::FailValidate(ValidateError::Other(
+                        "NFC String is not on `in` list".into(),
+                    )));
                 }
                 if self.nin_list.iter().any(|v| v.nfc().eq(val.chars())) {
-                    return Err(Error::FailValidate(
-                        "NFC String is on `nin` list".to_string(),
-                    ));
+                    return Err(Error::FailValidate(ValidateError::Other(
+                        "NFC String is on `nin` list".into(),
+                    )));
                 }
                 if let Some(pre) = self
                     .ban_prefix
                     .iter()
                     .find(|v| v.nfc().zip(val.chars()).all(|(vc, valc)| vc == valc))
                 {
-                    return Err(Error::FailValidate(format!(
-                        "NFC String begins with banned prefix {:?}",
-                        pre
+                    return Err(Error::FailValidate(ValidateError::Other(
+                        format!("NFC String begins with banned prefix {:?}", pre).into(),
                     )));
                 }
                 if !self.ban_suffix.is_empty() {
@@ -437,25 +526,23 @@ impl StrValidator {
                         temp.extend(v.nfc());
                         val.ends_with(&temp)
                     }) {
-                        return Err(Error::FailValidate(format!(
-                            "NFC String ends with banned suffix {:?}",
-                            temp
+                        return Err(Error::FailValidate(ValidateError::Other(
+                            format!("NFC String ends with banned suffix {:?}", temp).into(),
                         )));
                     }
                 }
                 if !self.ban_char.is_empty() {
                     if let Some(c) = val.chars().find(|c| self.ban_char.contains(*c)) {
-                        return Err(Error::FailValidate(format!(
-                            "NFC String contains banned character {:?}",
-                            c
+                        return Err(Error::FailValidate(ValidateError::Other(
+                            format!("NFC String contains banned character {:?}", c).into(),
                         )));
                     }
                 }
                 if let Some(ref regex) = self.matches {
                     if !regex.is_match(val) {
-                        return Err(Error::FailValidate(
-                            "String doesn't match regular expression".to_string(),
-                        ));
+                        return Err(Error::FailValidate(ValidateError::Other(
+                            "String doesn't match regular expression".into(),
+                        )));
                     }
                 }
             }
@@ -472,23 +559,22 @@ impl StrValidator {
                 if !self.in_list.is_empty()
                     && !self.in_list.iter().any(|v| v.nfkc().eq(val.chars()))
                 {
-                    return Err(Error::FailValidate(
-                        "NFKC String is not on `in` list".to_string(),
-                    ));
+                    return Err(Error::FailValidate(ValidateError::Other(
+                        "NFKC String is not on `in` list".into(),
+                    )));
                 }
                 if self.nin_list.iter().any(|v| v.nfkc().eq(val.chars())) {
-                    return Err(Error::FailValidate(
-                        "NFKC String is on `nin` list".to_string(),
-                    ));
+                    return Err(Error::FailValidate(ValidateError::Other(
+                        "NFKC String is on `nin` list".into(),
+                    )));
                 }
                 if let Some(pre) = self
                     .ban_prefix
                     .iter()
                     .find(|v| v.nfkc().zip(val.chars()).all(|(vc, valc)| vc == valc))
                 {
-                    return Err(Error::FailValidate(format!(
-                        "NFKC String begins with banned prefix {:?}",
-                        pre
+                    return Err(Error::FailValidate(ValidateError::Other(
+                        format!("NFKC String begins with banned prefix {:?}", pre).into(),
                     )));
                 }
                 if !self.ban_suffix.is_empty() {
@@ -498,25 +584,23 @@ impl StrValidator {
                         temp.extend(v.nfkc());
                         val.ends_with(&temp)
                     }) {
-                        return Err(Error::FailValidate(format!(
-                            "NFKC String ends with banned suffix {:?}",
-                            temp
+                        return Err(Error::FailValidate(ValidateError::Other(
+                            format!("NFKC String ends with banned suffix {:?}", temp).into(),
                         )));
                     }
                 }
                 if !self.ban_char.is_empty() {
                     if let Some(c) = val.chars().find(|c| self.ban_char.contains(*c)) {
-                        return Err(Error::FailValidate(format!(
-                            "NFKC String contains banned character {:?}",
-                            c
+                        return Err(Error::FailValidate(ValidateError::Other(
+                            format!("NFKC String contains banned character {:?}", c).into(),
                         )));
                     }
                 }
                 if let Some(ref regex) = self.matches {
                     if !regex.is_match(val) {
-                        return Err(Error::FailValidate(
-                            "NFKC String doesn't match regular expression".to_string(),
-                        ));
+                        return Err(Error::FailValidate(ValidateError::Other(
+                            "NFKC String doesn't match regular expression".into(),
+                        )));
                     }
                 }
             }
@@ -535,7 +619,9 @@ impl StrValidator {
                 || (u32_is_max(&other.max_len)
                     && u32_is_zero(&other.min_len)
                     && u32_is_max(&other.max_char)
-                    && u32_is_zero(&other.min_char)))
+                    && u32_is_zero(&other.min_char)
+                    && u32_is_max(&other.max_grapheme)
+                    && u32_is_zero(&other.min_grapheme)))
     }
 
     pub(crate) fn query_check(&self, other: &Validator) -> bool {
@@ -550,3 +636,33 @@ impl StrValidator {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn length_units() {
+        // A thumbs up with a skin tone modifier: 8 bytes, 2 scalar values, 1 grapheme cluster
+        let thumbs = "\u{1F44D}\u{1F3FD}";
+        assert!(StrValidator::new().max_len(8).validate_str(thumbs).is_ok());
+        assert!(StrValidator::new().max_len(7).validate_str(thumbs).is_err());
+        assert!(StrValidator::new().max_char(2).validate_str(thumbs).is_ok());
+        assert!(StrValidator::new()
+            .max_char(1)
+            .validate_str(thumbs)
+            .is_err());
+
+        let one = StrValidator::new().min_grapheme(1).max_grapheme(1);
+        #[cfg(feature = "graphemes")]
+        {
+            assert!(one.validate_str(thumbs).is_ok());
+            assert!(one.validate_str("ab").is_err());
+            assert!(one.validate_str("").is_err());
+        }
+        #[cfg(not(feature = "graphemes"))]
+        assert!(one.validate_str(thumbs).is_err());
+        // Validators without grapheme limits never need to count them
+        assert!(StrValidator::new().validate_str(thumbs).is_ok());
+    }
+}
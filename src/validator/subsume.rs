@@ -0,0 +1,487 @@
+//! Checking if one validator accepts everything another does.
+//!
+//! This works directly on the validator structures and is conservative: if it can't prove that
+//! every value passing the narrower validator also passes the broader one, it says no. Regexes
+//! are only compared by their source text, and binary bounds only by their bytes, so equivalent
+//! validators written differently may not be recognized as such.
+
+use super::*;
+use crate::value::Value;
+use crate::{LockId, StreamId};
+use std::iter::repeat;
+
+// Check that a value passes a validator, ignoring any linked documents.
+fn accepts(v: &Validator, types: &BTreeMap<String, Validator>, value: Value) -> bool {
+    v.validate_value_with(types, crate::MAX_DEPTH, &value.as_ref())
+        .is_ok()
+}
+
+// Check that a lower bound is no higher than another.
+fn lower<T: PartialOrd>(a: &T, a_ex: bool, b: &T, b_ex: bool) -> bool {
+    a < b || (a == b && (!a_ex || b_ex))
+}
+
+// Check that an upper bound is no lower than another.
+fn upper<T: PartialOrd>(a: &T, a_ex: bool, b: &T, b_ex: bool) -> bool {
+    a > b || (a == b && (!a_ex || b_ex))
+}
+
+fn subset<T: PartialEq>(a: &[T], b: &[T]) -> bool {
+    a.iter().all(|x| b.contains(x))
+}
+
+// Allowed lists must be narrower, and everything `a` bans must be banned by `b`
+struct Lists<'a> {
+    types: &'a BTreeMap<String, Validator>,
+    a: &'a Validator,
+    b: &'a Validator,
+}
+
+impl Lists<'_> {
+    fn check<T: Clone>(
+        &self,
+        a_in: &[T],
+        a_nin: &[T],
+        b_in: &[T],
+        to: impl Fn(T) -> Value,
+        rest: impl FnOnce() -> bool,
+    ) -> bool {
+        // A non-empty `in` list is the full set of values `b` can accept
+        if !b_in.is_empty() {
+            return b_in
+                .iter()
+                .all(|v| accepts(self.a, self.types, to(v.clone())));
+        }
+        a_in.is_empty()
+            && a_nin
+                .iter()
+                .all(|v| !accepts(self.b, self.types, to(v.clone())))
+            && rest()
+    }
+}
+
+fn int_bounds(a: &IntValidator, b: &IntValidator) -> bool {
+    lower(&a.min, a.ex_min, &b.min, b.ex_min)
+        && upper(&a.max, a.ex_max, &b.max, b.ex_max)
+        && (a.bits_set & !b.bits_set) == 0
+        && (a.bits_clr & !b.bits_clr) == 0
+}
+
+// A NaN bound isn't checked, unless it's exclusive, in which case it only excludes NaN and the
+// matching infinity. Any numeric bound excludes NaN.
+macro_rules! float {
+    ($name:ident, $v:ty, $t:ident) => {
+        fn $name(a: &$v, b: &$v) -> bool {
+            let min_ok = if a.min.is_nan() {
+                !a.ex_min || b.ex_min || (!b.min.is_nan() && b.min > $t::NEG_INFINITY)
+            } else {
+                !b.min.is_nan() && lower(&a.min, a.ex_min, &b.min, b.ex_min)
+            };
+            let max_ok = if a.max.is_nan() {
+                !a.ex_max || b.ex_max || (!b.max.is_nan() && b.max < $t::INFINITY)
+            } else {
+                !b.max.is_nan() && upper(&a.max, a.ex_max, &b.max, b.ex_max)
+            };
+            min_ok && max_ok
+        }
+    };
+}
+
+float!(f32_bounds, F32Validator, f32);
+float!(f64_bounds, F64Validator, f64);
+float!(num_bounds, NumValidator, f64);
+
+fn bits_within(a: &[u8], b: &[u8]) -> bool {
+    a.iter()
+        .zip(b.iter().chain(repeat(&0)))
+        .all(|(a, b)| (a & !b) == 0)
+}
+
+fn bin_rules(a: &BinValidator, b: &BinValidator) -> bool {
+    let min_ok = (a.min.is_empty() && !a.ex_min) || (a.min == b.min && (!a.ex_min || b.ex_min));
+    let max_ok = a.max.is_empty() || (a.max == b.max && (!a.ex_max || b.ex_max));
+    min_ok
+        && max_ok
+        && a.min_len <= b.min_len
+        && a.max_len >= b.max_len
+        && bits_within(&a.bits_set, &b.bits_set)
+        && bits_within(&a.bits_clr, &b.bits_clr)
+}
+
+fn str_rules(a: &StrValidator, b: &StrValidator) -> bool {
+    let matches_ok = match (&a.matches, &b.matches) {
+        (None, _) => true,
+        (Some(a), Some(b)) => a.as_str() == b.as_str(),
+        (Some(_), None) => false,
+    };
+    matches_ok
+        && a.normalize == b.normalize
+        && a.min_len <= b.min_len
+        && a.max_len >= b.max_len
+        && a.min_char <= b.min_char
+        && a.max_char >= b.max_char
+        && a.min_grapheme <= b.min_grapheme
+        && a.max_grapheme >= b.max_grapheme
+        && a.ban_prefix
+            .iter()
+            .all(|p| b.ban_prefix.iter().any(|q| p.starts_with(q.as_str())))
+        && a.ban_suffix
+            .iter()
+            .all(|p| b.ban_suffix.iter().any(|q| p.ends_with(q.as_str())))
+        && a.ban_char.chars().all(|c| b.ban_char.contains(c))
+}
+
+fn time_bounds(a: &TimeValidator, b: &TimeValidator) -> bool {
+    lower(&a.min, a.ex_min, &b.min, b.ex_min)
+        && upper(&a.max, a.ex_max, &b.max, b.ex_max)
+        && b.precision.step() % a.precision.step() == 0
+}
+
+fn lockbox(a: (u32, u32, &[LockId], &[StreamId]), b: (u32, u32, &[LockId], &[StreamId])) -> bool {
+    let recipients_ok = (a.2.is_empty() && a.3.is_empty())
+        || ((!b.2.is_empty() || !b.3.is_empty()) && subset(b.2, a.2) && subset(b.3, a.3));
+    a.0 <= b.0 && a.1 >= b.1 && recipients_ok
+}
+
+macro_rules! lockbox_args {
+    ($v:expr) => {
+        ($v.min_len, $v.max_len, &$v.lock_in[..], &$v.stream_in[..])
+    };
+}
+
+impl Validator {
+    /// Check if this validator accepts every value the other one does. Both validators look up
+    /// references in the same map of types. Returns false if it can't be shown.
+    pub(crate) fn subsumes(
+        &self,
+        types: &BTreeMap<String, Validator>,
+        other: &Validator,
+        depth: usize,
+    ) -> bool {
+        // Stops reference cycles, as well as overly deep validators
+        if depth > crate::MAX_DEPTH {
+            return false;
+        }
+        let depth = depth + 1;
+        let (a, b) = (self, other);
+        match (a, b) {
+            (Validator::Ref(name), _) => {
+                return types.get(name).is_some_and(|a| a.subsumes(types, b, depth))
+            }
+            (_, Validator::Ref(name)) => {
+                return types.get(name).is_some_and(|b| a.subsumes(types, b, depth))
+            }
+            (Validator::Any, _) => return true,
+            (_, Validator::Multi(list)) => return list.iter().all(|b| a.subsumes(types, b, depth)),
+            // Nested Multi validators never pass, just like in validation
+            (Validator::Multi(list), _) => {
+                return list.iter().any(|a| match a {
+                    Validator::Multi(_) => false,
+                    Validator::Ref(name) => match types.get(name) {
+                        Some(Validator::Multi(_) | Validator::Ref(_)) | None => false,
+                        Some(a) => a.subsumes(types, b, depth),
+                    },
+                    a => a.subsumes(types, b, depth),
+                })
+            }
+            _ => (),
+        }
+
+        let lists = Lists { types, a, b };
+        match (a, b) {
+            (Validator::Null, Validator::Null) => true,
+            (Validator::BareIdKey, Validator::BareIdKey) => true,
+            (Validator::Bool(a), Validator::Bool(b)) => a.val.is_none() || a.val == b.val,
+            (Validator::Int(av), Validator::Int(bv)) => {
+                lists.check(&av.in_list, &av.nin_list, &bv.in_list, Value::Int, || {
+                    int_bounds(av, bv)
+                })
+            }
+            (Validator::F32(av), Validator::F32(bv)) => {
+                lists.check(&av.in_list, &av.nin_list, &bv.in_list, Value::F32, || {
+                    f32_bounds(av, bv)
+                })
+            }
+            (Validator::F64(av), Validator::F64(bv)) => {
+                lists.check(&av.in_list, &av.nin_list, &bv.in_list, Value::F64, || {
+                    f64_bounds(av, bv)
+                })
+            }
+            (Validator::Num(av), Validator::Num(bv)) => num_bounds(av, bv),
+            (Validator::Bin(av), Validator::Bin(bv)) => lists.check(
+                &av.in_list,
+                &av.nin_list,
+                &bv.in_list,
+                |v| Value::Bin(v.into_vec()),
+                || bin_rules(av, bv),
+            ),
+            (Validator::Str(av), Validator::Str(bv)) => {
+                lists.check(&av.in_list, &av.nin_list, &bv.in_list, Value::Str, || {
+                    str_rules(av, bv)
+                })
+            }
+            (Validator::Time(av), Validator::Time(bv)) => lists.check(
+                &av.in_list,
+                &av.nin_list,
+                &bv.in_list,
+                Value::Timestamp,
+                || time_bounds(av, bv),
+            ),
+            (Validator::Hash(av), Validator::Hash(bv)) => {
+                lists.check(&av.in_list, &av.nin_list, &bv.in_list, Value::Hash, || {
+                    let link_ok = match (&av.link, &bv.link) {
+                        (None, _) => true,
+                        (Some(a), Some(b)) => a.subsumes(types, b, depth),
+                        (Some(_), None) => false,
+                    };
+                    link_ok
+                        && (av.schema.is_empty()
+                            || (!bv.schema.is_empty() && subset(&bv.schema, &av.schema)))
+                        && (av.version_list.is_empty()
+                            || (!bv.version_list.is_empty()
+                                && subset(&bv.version_list, &av.version_list)))
+                        && bv.prefix.starts_with(&av.prefix[..])
+                })
+            }
+            (Validator::Identity(av), Validator::Identity(bv)) => lists.check(
+                &av.in_list,
+                &av.nin_list,
+                &bv.in_list,
+                Value::Identity,
+                || true,
+            ),
+            (Validator::StreamId(av), Validator::StreamId(bv)) => lists.check(
+                &av.in_list,
+                &av.nin_list,
+                &bv.in_list,
+                Value::StreamId,
+                || true,
+            ),
+            (Validator::LockId(av), Validator::LockId(bv)) => lists.check(
+                &av.in_list,
+                &av.nin_list,
+                &bv.in_list,
+                Value::LockId,
+                || true,
+            ),
+            (Validator::DataLockbox(a), Validator::DataLockbox(b)) => {
+                lockbox(lockbox_args!(a), lockbox_args!(b))
+            }
+            (Validator::IdentityLockbox(a), Validator::IdentityLockbox(b)) => {
+                lockbox(lockbox_args!(a), lockbox_args!(b))
+            }
+            (Validator::StreamLockbox(a), Validator::StreamLockbox(b)) => {
+                lockbox(lockbox_args!(a), lockbox_args!(b))
+            }
+            (Validator::LockLockbox(a), Validator::LockLockbox(b)) => {
+                lockbox(lockbox_args!(a), lockbox_args!(b))
+            }
+            (Validator::Array(av), Validator::Array(bv)) => {
+                lists.check(&av.in_list, &av.nin_list, &bv.in_list, Value::Array, || {
+                    av.array_subsumes(types, bv, depth)
+                })
+            }
+            (Validator::Map(av), Validator::Map(bv)) => {
+                lists.check(&av.in_list, &av.nin_list, &bv.in_list, Value::Map, || {
+                    av.map_subsumes(types, bv, depth)
+                })
+            }
+            (Validator::Enum(a), Validator::Enum(b)) => {
+                b.iter().all(|(key, b)| match (a.var.get(key), b) {
+                    (Some(None), None) => true,
+                    (Some(Some(a)), Some(b)) => a.subsumes(types, b, depth),
+                    _ => false,
+                })
+            }
+            _ => false,
+        }
+    }
+}
+
+impl ArrayValidator {
+    fn array_subsumes(
+        &self,
+        types: &BTreeMap<String, Validator>,
+        other: &ArrayValidator,
+        depth: usize,
+    ) -> bool {
+        let (a, b) = (self, other);
+        if a.min_len > b.min_len
+            || a.max_len < b.max_len
+            || (a.unique && !b.unique)
+            || !(a.same_len.is_empty() || a.same_len == b.same_len)
+        {
+            return false;
+        }
+        // Whatever satisfies one of the other's `contains` validators must satisfy ours
+        let contains_ok = a
+            .contains
+            .iter()
+            .all(|a| b.contains.iter().any(|b| a.subsumes(types, b, depth)));
+        // Compare each position in the prefixes, then the remaining items
+        let items_ok = a
+            .prefix
+            .iter()
+            .chain(repeat(a.items.as_ref()))
+            .zip(b.prefix.iter().chain(repeat(b.items.as_ref())))
+            .take(a.prefix.len().max(b.prefix.len()) + 1)
+            .all(|(a, b)| a.subsumes(types, b, depth));
+        contains_ok && items_ok
+    }
+}
+
+impl MapValidator {
+    // Find the validator used for a key, the same way validation does
+    fn lookup(&self, key: &str) -> Option<&Validator> {
+        self.req.get(key).or_else(|| self.opt.get(key)).or_else(|| {
+            let key_ok = self
+                .keys
+                .as_ref()
+                .is_none_or(|k| k.validate_str(key).is_ok());
+            self.values.as_deref().filter(|_| key_ok)
+        })
+    }
+
+    fn map_subsumes(
+        &self,
+        types: &BTreeMap<String, Validator>,
+        other: &MapValidator,
+        depth: usize,
+    ) -> bool {
+        let (a, b) = (self, other);
+        if a.min_len > b.min_len
+            || a.max_len < b.max_len
+            || !(a.same_len.is_empty() || a.same_len == b.same_len)
+            || !a.req.keys().all(|k| b.req.contains_key(k))
+        {
+            return false;
+        }
+        // Keys under our prefix are also under one of the other's shorter prefixes
+        let prefix_ok = a.max_prefix.iter().all(|(prefix, max)| {
+            b.max_len <= *max
+                || b.max_prefix
+                    .iter()
+                    .any(|(p, m)| prefix.starts_with(p.as_str()) && m <= max)
+        });
+        // The other must require a key from each of our sets
+        let any_ok = a.req_any.iter().all(|set| {
+            b.req.keys().any(|k| set.contains(k))
+                || b.req_any.iter().any(|other| other.is_subset(set))
+        });
+        if !prefix_ok || !any_ok {
+            return false;
+        }
+
+        let named_ok = b
+            .req
+            .iter()
+            .chain(b.opt.iter())
+            .all(|(key, b)| a.lookup(key).is_some_and(|a| a.subsumes(types, b, depth)));
+        if !named_ok {
+            return false;
+        }
+
+        let Some(b_values) = &b.values else {
+            return true;
+        };
+        let Some(a_values) = &a.values else {
+            return false;
+        };
+        let keys_ok = match (&a.keys, &b.keys) {
+            (None, _) => true,
+            (Some(a), Some(b)) => {
+                Validator::Str(a.clone()).subsumes(types, &Validator::Str(b.clone()), depth)
+            }
+            (Some(_), None) => false,
+        };
+        // Our optional keys may catch some of what the other's `values` would have matched
+        let opt_ok = a
+            .opt
+            .iter()
+            .filter(|(key, _)| !b.req.contains_key(*key) && !b.opt.contains_key(*key))
+            .filter(|(key, _)| b.keys.as_ref().is_none_or(|k| k.validate_str(key).is_ok()))
+            .all(|(_, a)| a.subsumes(types, b_values, depth));
+        keys_ok && opt_ok && a_values.subsumes(types, b_values, depth)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn check(a: &Validator, b: &Validator) -> bool {
+        a.subsumes(&BTreeMap::new(), b, 0)
+    }
+
+    #[test]
+    fn scalars() {
+        let broad = IntValidator::new().min(0).max(100).build();
+        let narrow = IntValidator::new().min(10).max(100).ex_max(true).build();
+        assert!(check(&broad, &narrow));
+        assert!(!check(&narrow, &broad));
+        assert!(check(&broad, &broad));
+        let listed = IntValidator::new().in_add(5).in_add(50).build();
+        assert!(check(&broad, &listed));
+        assert!(!check(&narrow, &listed));
+        let banned = IntValidator::new().nin_add(-1).build();
+        assert!(check(&banned, &broad));
+        assert!(!check(&banned, &IntValidator::new().build()));
+
+        let any_str = StrValidator::new().build();
+        let short = StrValidator::new().max_len(10).ban_prefix_add("x").build();
+        assert!(check(&any_str, &short));
+        assert!(!check(&short, &any_str));
+        assert!(check(
+            &StrValidator::new().ban_prefix_add("xy").build(),
+            &short
+        ));
+        assert!(!check(&any_str, &IntValidator::new().build()));
+        assert!(check(&Validator::Any, &any_str));
+        assert!(!check(&any_str, &Validator::Any));
+    }
+
+    #[test]
+    fn nested() {
+        let mut types = BTreeMap::new();
+        types.insert("num".to_string(), IntValidator::new().min(0).build());
+        let broad = MapValidator::new()
+            .req_add("a", Validator::new_ref("num"))
+            .opt_add("b", StrValidator::new().build())
+            .build();
+        let narrow = MapValidator::new()
+            .req_add("a", IntValidator::new().min(5).build())
+            .req_add("b", StrValidator::new().max_len(4).build())
+            .build();
+        assert!(broad.subsumes(&types, &narrow, 0));
+        assert!(!narrow.subsumes(&types, &broad, 0));
+
+        // The other can't allow keys we don't
+        let extra = MapValidator::new()
+            .req_add("a", IntValidator::new().min(5).build())
+            .values(Validator::new_null())
+            .build();
+        assert!(!broad.subsumes(&types, &extra, 0));
+
+        let either = MultiValidator::new()
+            .push(broad.clone())
+            .push(Validator::new_null())
+            .build();
+        assert!(either.subsumes(&types, &narrow, 0));
+        assert!(either.subsumes(&types, &Validator::new_null(), 0));
+        let both = MultiValidator::new()
+            .push(narrow.clone())
+            .push(Validator::new_null())
+            .build();
+        assert!(either.subsumes(&types, &both, 0));
+        assert!(!broad.subsumes(&types, &both, 0));
+
+        let array = ArrayValidator::new().items(broad).build();
+        let narrow_array = ArrayValidator::new()
+            .items(narrow)
+            .max_len(3)
+            .unique(true)
+            .build();
+        assert!(array.subsumes(&types, &narrow_array, 0));
+        assert!(!narrow_array.subsumes(&types, &array, 0));
+    }
+}
@@ -1,6 +1,6 @@
 use super::*;
 use crate::element::*;
-use crate::error::{Error, Result};
+use crate::error::{Error, Result, ValidateError};
 use crate::Timestamp;
 use serde::{Deserialize, Serialize};
 use std::default::Default;
@@ -23,6 +23,11 @@ fn time_is_max(v: &Timestamp) -> bool {
     *v == MAX_TIME
 }
 
+#[inline]
+fn precision_is_nanos(v: &Precision) -> bool {
+    *v == Precision::Nanos
+}
+
 /// Validator for timestamps.
 ///
 /// This validator will only pass timestamps. Validation passes if:
@@ -31,6 +36,13 @@ fn time_is_max(v: &Timestamp) -> bool {
 /// - The timestamp must not be among the timestamp in the `nin` list.
 /// - The timestamp is less than the maximum in `max`, or equal to it if `ex_max` is not set to true.
 /// - The timestamp is greater than the minimum in `min`, or equal to it if `ex_min` is not set to true.
+/// - The timestamp has no more precision than `precision` allows: whole seconds, whole
+///     milliseconds, or any number of nanoseconds.
+///
+/// Timestamps with too much precision fail validation; they are never changed to fit. Producers
+/// with clocks of differing resolution should truncate their timestamps to the declared precision
+/// with [`Precision::truncate`] before encoding, so that the same moment always encodes - and
+/// hashes - the same way.
 ///
 /// # Defaults
 ///
@@ -44,9 +56,18 @@ fn time_is_max(v: &Timestamp) -> bool {
 /// - ex_min: false
 /// - in_list: empty
 /// - nin_list: empty
+/// - precision: Precision::Nanos
 /// - query: false
 /// - ord: false
 ///
+/// # Query Checking
+///
+/// Queries for timestamps are only allowed to use non-default values for each field if the
+/// corresponding query permission is set in the schema's validator:
+///
+/// - query: `in`, `nin`, and `precision`
+/// - ord: `max`, `min`, `ex_max`, and `ex_min`
+///
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(deny_unknown_fields, default)]
 pub struct TimeValidator {
@@ -62,7 +83,7 @@ pub struct TimeValidator {
     /// Changes `max` into an exclusive maximum.
     #[serde(skip_serializing_if = "is_false")]
     pub ex_max: bool,
-    /// Changes `min` into an exclusive maximum.
+    /// Changes `min` into an exclusive minimum.
     #[serde(skip_serializing_if = "is_false")]
     pub ex_min: bool,
     /// A vector of specific allowed values, stored under the `in` field. If empty, this vector is not checked against.
@@ -71,6 +92,9 @@ pub struct TimeValidator {
     /// A vector of specific unallowed values, stored under the `nin` field.
     #[serde(rename = "nin", skip_serializing_if = "Vec::is_empty")]
     pub nin_list: Vec<Timestamp>,
+    /// The finest precision allowed.
+    #[serde(skip_serializing_if = "precision_is_nanos")]
+    pub precision: Precision,
     /// If true, queries against matching spots may have values in the `in` or `nin` lists.
     #[serde(skip_serializing_if = "is_false")]
     pub query: bool,
@@ -82,6 +106,13 @@ pub struct TimeValidator {
 
 impl Default for TimeValidator {
     fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TimeValidator {
+    /// Make a new validator with the default configuration.
+    pub const fn new() -> Self {
         Self {
             comment: String::new(),
             max: MAX_TIME,
@@ -90,17 +121,11 @@ impl Default for TimeValidator {
             ex_min: false,
             in_list: Vec::new(),
             nin_list: Vec::new(),
+            precision: Precision::Nanos,
             query: false,
             ord: false,
         }
     }
-}
-
-impl TimeValidator {
-    /// Make a new validator with the default configuration.
-    pub fn new() -> Self {
-        Self::default()
-    }
 
     /// Set a comment for the validator.
     pub fn comment(mut self, comment: impl Into<String>) -> Self {
@@ -120,14 +145,14 @@ impl TimeValidator {
         self
     }
 
-    /// Set whether or or not `max` is an exclusive maximum.
-    pub fn ex_max(mut self, ex_max: bool) -> Self {
+    /// Set whether or not `max` is an exclusive maximum.
+    pub const fn ex_max(mut self, ex_max: bool) -> Self {
         self.ex_max = ex_max;
         self
     }
 
-    /// Set whether or or not `min` is an exclusive maximum.
-    pub fn ex_min(mut self, ex_min: bool) -> Self {
+    /// Set whether or not `min` is an exclusive minimum.
+    pub const fn ex_min(mut self, ex_min: bool) -> Self {
         self.ex_min = ex_min;
         self
     }
@@ -144,14 +169,20 @@ impl TimeValidator {
         self
     }
 
-    /// Set whether or not queries can use the `in` and `nin` lists.
-    pub fn query(mut self, query: bool) -> Self {
+    /// Set the finest precision allowed.
+    pub const fn precision(mut self, precision: Precision) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Set whether or not queries can use the `in` and `nin` lists, and `precision`.
+    pub const fn query(mut self, query: bool) -> Self {
         self.query = query;
         self
     }
 
     /// Set whether or not queries can use the `max`, `min`, `ex_max`, and `ex_min` values.
-    pub fn ord(mut self, ord: bool) -> Self {
+    pub const fn ord(mut self, ord: bool) -> Self {
         self.ord = ord;
         self
     }
@@ -162,16 +193,18 @@ impl TimeValidator {
     }
 
     pub(crate) fn validate(&self, parser: &mut Parser) -> Result<()> {
-        let elem = parser
-            .next()
-            .ok_or_else(|| Error::FailValidate("Expected a timestamp".to_string()))??;
+        let elem = parser.next().ok_or_else(|| {
+            Error::FailValidate(ValidateError::Missing {
+                expected: "a timestamp",
+            })
+        })??;
         let val = if let Element::Timestamp(v) = elem {
             v
         } else {
-            return Err(Error::FailValidate(format!(
-                "Expected Time, got {}",
-                elem.name()
-            )));
+            return Err(Error::FailValidate(ValidateError::WrongType {
+                expected: "Time",
+                actual: elem.name(),
+            }));
         };
 
         // Range checks
@@ -186,33 +219,42 @@ impl TimeValidator {
             val >= self.min
         };
         if !max_pass {
-            return Err(Error::FailValidate(
-                "Timestamp greater than maximum allowed".to_string(),
-            ));
+            return Err(Error::FailValidate(ValidateError::Other(
+                "Timestamp greater than maximum allowed".into(),
+            )));
         }
         if !min_pass {
-            return Err(Error::FailValidate(
-                "Timestamp less than minimum allowed".to_string(),
-            ));
+            return Err(Error::FailValidate(ValidateError::Other(
+                "Timestamp less than minimum allowed".into(),
+            )));
         }
 
         // in/nin checks
         if !self.in_list.is_empty() && !self.in_list.iter().any(|v| *v == val) {
-            return Err(Error::FailValidate(
-                "Timestamp is not on `in` list".to_string(),
-            ));
+            return Err(Error::FailValidate(ValidateError::Other(
+                "Timestamp is not on `in` list".into(),
+            )));
         }
         if self.nin_list.iter().any(|v| *v == val) {
-            return Err(Error::FailValidate(
-                "Timestamp is on `nin` list".to_string(),
-            ));
+            return Err(Error::FailValidate(ValidateError::Other(
+                "Timestamp is on `nin` list".into(),
+            )));
+        }
+
+        if !self.precision.allows(val) {
+            return Err(Error::FailValidate(ValidateError::Other(
+                format!("Timestamp is more precise than {:?} allows", self.precision).into(),
+            )));
         }
 
         Ok(())
     }
 
     fn query_check_self(&self, other: &Self) -> bool {
-        (self.query || (other.in_list.is_empty() && other.nin_list.is_empty()))
+        (self.query
+            || (other.in_list.is_empty()
+                && other.nin_list.is_empty()
+                && precision_is_nanos(&other.precision)))
             && (self.ord
                 || (!other.ex_min
                     && !other.ex_max
@@ -265,6 +307,7 @@ mod test {
             ex_max: true,
             in_list: Vec::new(),
             nin_list: Vec::new(),
+            precision: Precision::Nanos,
             query: true,
             ord: true,
         };
@@ -304,4 +347,42 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn precision() {
+        let time = Timestamp::from_tai(1_600_000_000, 123_456_789).unwrap();
+        let check = |validator: &TimeValidator, time: Timestamp| {
+            let mut encoded = Vec::new();
+            serialize_elem(&mut encoded, Element::Timestamp(time));
+            validator.validate(&mut Parser::new(&encoded)).is_ok()
+        };
+        let millis = TimeValidator::new().precision(Precision::Millis);
+        let secs = TimeValidator::new().precision(Precision::Seconds);
+        assert!(check(&TimeValidator::new(), time));
+        assert!(!check(&millis, time));
+        assert!(!check(&secs, time));
+
+        let truncated = Precision::Millis.truncate(time);
+        assert_eq!(
+            truncated,
+            Timestamp::from_tai(1_600_000_000, 123_000_000).unwrap()
+        );
+        assert!(check(&millis, truncated));
+        assert!(!check(&secs, truncated));
+        let truncated = Precision::Seconds.truncate(time);
+        assert_eq!(truncated, Timestamp::from_tai_secs(1_600_000_000));
+        assert!(check(&secs, truncated));
+        // Truncating rounds towards the past, even before the epoch
+        let before = Timestamp::from_tai(-2, 999_999_999).unwrap();
+        assert_eq!(
+            Precision::Seconds.truncate(before),
+            Timestamp::from_tai_secs(-2)
+        );
+
+        let mut ser = FogSerializer::default();
+        millis.serialize(&mut ser).unwrap();
+        let encoded = ser.finish();
+        let mut de = FogDeserializer::new(&encoded);
+        assert_eq!(TimeValidator::deserialize(&mut de).unwrap(), millis);
+    }
 }
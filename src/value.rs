@@ -3,8 +3,11 @@ use fog_crypto::identity::BareIdKey;
 use crate::value_ref::ValueRef;
 use crate::*;
 use std::borrow::Cow;
-use std::ops::Index;
-use std::{collections::BTreeMap, fmt::Debug};
+use std::ops::{Index, IndexMut};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Debug,
+};
 
 /// A single, self-contained fog-pack value.
 ///
@@ -344,6 +347,19 @@ impl Value {
         }
     }
 
+    /// Look up a nested value using a JSON-Pointer-style path, like `"/contents/readme"`. Array
+    /// elements are looked up by their index, like `"/list/0"`. Returns `None` if the path is
+    /// invalid or doesn't point to a value. See [`Path`] for more.
+    pub fn pointer(&self, pointer: &str) -> Option<&Value> {
+        Path::parse(pointer)?.get(self)
+    }
+
+    /// Look up a nested value using a JSON-Pointer-style path, returning a mutable reference to
+    /// it. See [`pointer`][Self::pointer] for more.
+    pub fn pointer_mut(&mut self, pointer: &str) -> Option<&mut Value> {
+        Path::parse(pointer)?.get_mut(self)
+    }
+
     pub fn as_bare_id_key(&self) -> Option<&BareIdKey> {
         if let Value::BareIdKey(ref key) = *self {
             Some(key)
@@ -375,6 +391,43 @@ impl Index<&str> for Value {
     }
 }
 
+/// Support mutably indexing into arrays.
+///
+/// # Panics
+///
+/// Panics if the value isn't an array, or if the index is out of range.
+impl IndexMut<usize> for Value {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        match self {
+            Value::Array(v) => {
+                let len = v.len();
+                v.get_mut(index).unwrap_or_else(|| {
+                    panic!("index {} out of range for array of length {}", index, len)
+                })
+            }
+            _ => panic!("cannot index into non-array value with an integer"),
+        }
+    }
+}
+
+/// Support mutably indexing into maps. If the key isn't in the map, it is inserted with a
+/// [`Value::Null`]. If the value is Null, it is first turned into an empty map.
+///
+/// # Panics
+///
+/// Panics if the value is neither a map nor Null.
+impl IndexMut<&str> for Value {
+    fn index_mut(&mut self, index: &str) -> &mut Self::Output {
+        if self.is_null() {
+            *self = Value::Map(BTreeMap::new());
+        }
+        match self {
+            Value::Map(v) => v.entry(index.to_owned()).or_default(),
+            _ => panic!("cannot index into non-map value with a string"),
+        }
+    }
+}
+
 impl<'a> PartialEq<ValueRef<'a>> for Value {
     fn eq(&self, other: &ValueRef) -> bool {
         use std::ops::Deref;
@@ -597,6 +650,35 @@ impl<'a> From<Cow<'a, [u8]>> for Value {
     }
 }
 
+impl<'a> From<&'a String> for Value {
+    fn from(v: &String) -> Self {
+        Value::Str(v.clone())
+    }
+}
+
+/// `None` becomes a [`Value::Null`].
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(v: Option<T>) -> Self {
+        v.map_or(Value::Null, Into::into)
+    }
+}
+
+impl<V: Into<Value>> From<HashMap<String, V>> for Value {
+    fn from(v: HashMap<String, V>) -> Self {
+        Value::Map(v.into_iter().map(|(k, v)| (k, v.into())).collect())
+    }
+}
+
+impl<'a, V: Into<Value>> From<BTreeMap<&'a str, V>> for Value {
+    fn from(v: BTreeMap<&'a str, V>) -> Self {
+        Value::Map(
+            v.into_iter()
+                .map(|(k, v)| (k.to_owned(), v.into()))
+                .collect(),
+        )
+    }
+}
+
 impl<V: Into<Value>> std::iter::FromIterator<V> for Value {
     fn from_iter<T: IntoIterator<Item = V>>(iter: T) -> Self {
         let v: Vec<Value> = iter.into_iter().map(Into::into).collect();
@@ -873,3 +955,148 @@ impl<'de> serde::Deserialize<'de> for Value {
         deserializer.deserialize_any(ValueVisitor)
     }
 }
+
+/// Construct a [`Value`][crate::types::Value] using a JSON-like syntax.
+///
+/// Map keys may be string literals, or any expression in parenthesis that can be turned into a
+/// `String`. `null` is a [`Value::Null`][crate::types::Value::Null], and any other expression is
+/// converted with `Value::from`.
+///
+/// ```
+/// # use fog_pack::{fog_value, types::*};
+/// let name = "fog-pack";
+/// let value = fog_value!({
+///     "name": name,
+///     "tags": ["serialization", "crypto", 3, -4.5],
+///     "parent": null,
+///     "meta": {
+///         "created": Timestamp::from_utc(0, 0).unwrap(),
+///         "stars": 100u32,
+///     },
+/// });
+/// assert_eq!(value["tags"][2], Value::from(3));
+/// assert!(value["parent"].is_null());
+/// ```
+#[macro_export]
+macro_rules! fog_value {
+    // Array elements: done
+    (@array [$($elems:expr,)*]) => {
+        ::std::vec![$($elems,)*]
+    };
+    (@array [$($elems:expr,)*] null $(, $($rest:tt)*)?) => {
+        $crate::fog_value!(@array [$($elems,)* $crate::types::Value::Null,] $($($rest)*)?)
+    };
+    (@array [$($elems:expr,)*] [$($arr:tt)*] $(, $($rest:tt)*)?) => {
+        $crate::fog_value!(@array [$($elems,)* $crate::fog_value!([$($arr)*]),] $($($rest)*)?)
+    };
+    (@array [$($elems:expr,)*] {$($map:tt)*} $(, $($rest:tt)*)?) => {
+        $crate::fog_value!(@array [$($elems,)* $crate::fog_value!({$($map)*}),] $($($rest)*)?)
+    };
+    (@array [$($elems:expr,)*] $next:expr, $($rest:tt)*) => {
+        $crate::fog_value!(@array [$($elems,)* $crate::fog_value!($next),] $($rest)*)
+    };
+    (@array [$($elems:expr,)*] $last:expr) => {
+        $crate::fog_value!(@array [$($elems,)* $crate::fog_value!($last),])
+    };
+
+    // Map entries: done
+    (@map $map:ident) => {};
+    (@map $map:ident $key:tt : null $(, $($rest:tt)*)?) => {
+        $map.insert(::std::string::String::from($key), $crate::types::Value::Null);
+        $crate::fog_value!(@map $map $($($rest)*)?);
+    };
+    (@map $map:ident $key:tt : [$($arr:tt)*] $(, $($rest:tt)*)?) => {
+        $map.insert(::std::string::String::from($key), $crate::fog_value!([$($arr)*]));
+        $crate::fog_value!(@map $map $($($rest)*)?);
+    };
+    (@map $map:ident $key:tt : {$($inner:tt)*} $(, $($rest:tt)*)?) => {
+        $map.insert(::std::string::String::from($key), $crate::fog_value!({$($inner)*}));
+        $crate::fog_value!(@map $map $($($rest)*)?);
+    };
+    (@map $map:ident $key:tt : $val:expr, $($rest:tt)*) => {
+        $map.insert(::std::string::String::from($key), $crate::fog_value!($val));
+        $crate::fog_value!(@map $map $($rest)*);
+    };
+    (@map $map:ident $key:tt : $val:expr) => {
+        $map.insert(::std::string::String::from($key), $crate::fog_value!($val));
+    };
+
+    (null) => {
+        $crate::types::Value::Null
+    };
+    ([]) => {
+        $crate::types::Value::Array(::std::vec::Vec::new())
+    };
+    ([ $($tt:tt)+ ]) => {
+        $crate::types::Value::Array($crate::fog_value!(@array [] $($tt)+))
+    };
+    ({}) => {
+        $crate::types::Value::Map(::std::collections::BTreeMap::new())
+    };
+    ({ $($tt:tt)+ }) => {{
+        let mut map = ::std::collections::BTreeMap::new();
+        $crate::fog_value!(@map map $($tt)+);
+        $crate::types::Value::Map(map)
+    }};
+    ($other:expr) => {
+        $crate::types::Value::from($other)
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn macro_construct() {
+        let key = String::from("dynamic");
+        let value = fog_value!({
+            "a": 1,
+            "b": [true, null, "str", [], {}, [-1, 2.5f32]],
+            "c": { "d": null },
+            (key): Some(5u8),
+            "e": None::<u8>
+        });
+        let mut expected = BTreeMap::new();
+        expected.insert(String::from("a"), Value::from(1));
+        expected.insert(
+            String::from("b"),
+            Value::Array(vec![
+                Value::Bool(true),
+                Value::Null,
+                Value::from("str"),
+                Value::Array(Vec::new()),
+                Value::Map(BTreeMap::new()),
+                Value::Array(vec![Value::from(-1), Value::F32(2.5)]),
+            ]),
+        );
+        let mut inner = BTreeMap::new();
+        inner.insert(String::from("d"), Value::Null);
+        expected.insert(String::from("c"), Value::Map(inner));
+        expected.insert(String::from("dynamic"), Value::from(5u8));
+        expected.insert(String::from("e"), Value::Null);
+        assert_eq!(value, Value::Map(expected));
+    }
+
+    #[test]
+    fn index_mut() {
+        let mut value = Value::Null;
+        value["a"]["b"] = Value::from(1);
+        value["list"] = fog_value!([1, 2, 3]);
+        value["list"][1] = Value::from("two");
+        assert_eq!(
+            value,
+            fog_value!({
+                "a": { "b": 1 },
+                "list": [1, "two", 3],
+            })
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn index_mut_out_of_range() {
+        let mut value = fog_value!([1]);
+        value[1] = Value::Null;
+    }
+}
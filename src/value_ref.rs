@@ -335,6 +335,13 @@ impl<'a> ValueRef<'a> {
         }
     }
 
+    /// Look up a nested value using a JSON-Pointer-style path, like `"/contents/readme"`. Array
+    /// elements are looked up by their index, like `"/list/0"`. Returns `None` if the path is
+    /// invalid or doesn't point to a value. See [`Path`] for more.
+    pub fn pointer(&self, pointer: &str) -> Option<&ValueRef<'a>> {
+        Path::parse(pointer)?.get_ref(self)
+    }
+
     pub fn as_bare_id_key(&self) -> Option<&BareIdKey> {
         if let ValueRef::BareIdKey(ref key) = *self {
             Some(key)
@@ -0,0 +1,272 @@
+//! JavaScript bindings, for use from WebAssembly.
+//!
+//! Enabled with the `wasm` feature. This is a thin layer over [`Schema`], [`Document`],
+//! [`Entry`], and [`Query`], enough for a browser client to decode, validate, and query
+//! content-addressed data it received, and to encode new data of its own. Data passes between
+//! JavaScript and fog-pack as plain JavaScript values: maps become objects, binary data becomes a
+//! `Uint8Array`, and integers too large for a JavaScript number become a `BigInt`. Hashes and
+//! identities are exchanged as their base58 strings.
+//!
+//! Signing and encryption aren't exposed here. Documents and entries that carry signatures still
+//! have them verified when they're decoded.
+
+use crate::{
+    document::{Document, NewDocument},
+    entry::{Entry, NewEntry},
+    error::Error,
+    pretty::{print_document, print_entry, PrettyOptions},
+    query::{NewQuery, Query},
+    schema::{NoSchema, Schema},
+    types::Value,
+    validator::Validator,
+};
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+fn js_error(e: Error) -> JsError {
+    JsError::new(&e.to_string())
+}
+
+fn from_js<T: serde::de::DeserializeOwned>(value: JsValue) -> Result<T, JsError> {
+    serde_wasm_bindgen::from_value(value).map_err(|e| JsError::new(&e.to_string()))
+}
+
+fn to_js<T: Serialize>(value: &T) -> Result<JsValue, JsError> {
+    let ser = serde_wasm_bindgen::Serializer::new()
+        .serialize_maps_as_objects(true)
+        .serialize_large_number_types_as_bigints(true);
+    value
+        .serialize(&ser)
+        .map_err(|e| JsError::new(&e.to_string()))
+}
+
+/// A schema, for validating, encoding, and decoding documents, entries, and queries.
+#[wasm_bindgen(js_name = Schema)]
+pub struct JsSchema(Schema);
+
+#[wasm_bindgen(js_class = Schema)]
+impl JsSchema {
+    /// Load a schema from an encoded schema document.
+    #[wasm_bindgen(js_name = fromEncoded)]
+    pub fn from_encoded(encoded: Vec<u8>) -> Result<JsSchema, JsError> {
+        let doc = NoSchema::decode_doc(encoded).map_err(js_error)?;
+        Schema::from_doc(&doc).map(Self).map_err(js_error)
+    }
+
+    /// Load a schema from a schema document, limiting how many regular expressions it may
+    /// contain. Use this for schemas from untrusted sources.
+    #[wasm_bindgen(js_name = fromEncodedMaxRegex)]
+    pub fn from_encoded_max_regex(encoded: Vec<u8>, max_regex: u8) -> Result<JsSchema, JsError> {
+        let doc = NoSchema::decode_doc(encoded).map_err(js_error)?;
+        Schema::from_doc_max_regex(&doc, max_regex)
+            .map(Self)
+            .map_err(js_error)
+    }
+
+    /// Get the schema's hash.
+    #[wasm_bindgen(getter)]
+    pub fn hash(&self) -> String {
+        self.0.hash().to_string()
+    }
+
+    /// Create a new document using this schema, and validate it.
+    #[wasm_bindgen(js_name = newDoc)]
+    pub fn new_doc(&self, data: JsValue) -> Result<JsDocument, JsError> {
+        let data: Value = from_js(data)?;
+        let doc = NewDocument::new(Some(self.0.hash()), data).map_err(js_error)?;
+        self.0
+            .validate_new_doc(doc)
+            .map(JsDocument)
+            .map_err(js_error)
+    }
+
+    /// Encode a document using this schema.
+    #[wasm_bindgen(js_name = encodeDoc)]
+    pub fn encode_doc(&self, doc: &JsDocument) -> Result<Vec<u8>, JsError> {
+        let (_, encoded) = self.0.encode_doc(doc.0.clone()).map_err(js_error)?;
+        Ok(encoded)
+    }
+
+    /// Decode and validate a document using this schema.
+    #[wasm_bindgen(js_name = decodeDoc)]
+    pub fn decode_doc(&self, encoded: Vec<u8>) -> Result<JsDocument, JsError> {
+        self.0.decode_doc(encoded).map(JsDocument).map_err(js_error)
+    }
+
+    /// Create a new entry attached to a parent document, and validate it. Fails if validating
+    /// the entry would require any other documents.
+    #[wasm_bindgen(js_name = newEntry)]
+    pub fn new_entry(
+        &self,
+        key: &str,
+        parent: &JsDocument,
+        data: JsValue,
+    ) -> Result<JsEntry, JsError> {
+        let data: Value = from_js(data)?;
+        let entry = NewEntry::new(key, &parent.0, data).map_err(js_error)?;
+        let checklist = self.0.validate_new_entry(entry).map_err(js_error)?;
+        checklist.complete().map(JsEntry).map_err(js_error)
+    }
+
+    /// Encode an entry using this schema.
+    #[wasm_bindgen(js_name = encodeEntry)]
+    pub fn encode_entry(&self, entry: &JsEntry) -> Result<Vec<u8>, JsError> {
+        let (_, encoded, _) = self.0.encode_entry(entry.0.clone()).map_err(js_error)?;
+        Ok(encoded)
+    }
+
+    /// Decode and validate an entry attached to a parent document. Fails if validating the
+    /// entry would require any other documents.
+    #[wasm_bindgen(js_name = decodeEntry)]
+    pub fn decode_entry(
+        &self,
+        encoded: Vec<u8>,
+        key: &str,
+        parent: &JsDocument,
+    ) -> Result<JsEntry, JsError> {
+        let checklist = self
+            .0
+            .decode_entry(encoded, key, &parent.0)
+            .map_err(js_error)?;
+        checklist.complete().map(JsEntry).map_err(js_error)
+    }
+
+    /// Encode a query against entries with the given key. The query is a validator, given in
+    /// the same form a schema document uses.
+    #[wasm_bindgen(js_name = encodeQuery)]
+    pub fn encode_query(&self, key: &str, validator: JsValue) -> Result<Vec<u8>, JsError> {
+        let validator: Validator = from_js(validator)?;
+        self.0
+            .encode_query(NewQuery::new(key, validator))
+            .map_err(js_error)
+    }
+
+    /// Decode a query, checking that this schema allows it.
+    #[wasm_bindgen(js_name = decodeQuery)]
+    pub fn decode_query(&self, encoded: Vec<u8>) -> Result<JsQuery, JsError> {
+        self.0.decode_query(encoded).map(JsQuery).map_err(js_error)
+    }
+}
+
+/// A validated document.
+#[wasm_bindgen(js_name = Document)]
+pub struct JsDocument(Document);
+
+#[wasm_bindgen(js_class = Document)]
+impl JsDocument {
+    /// Create a new document without a schema.
+    #[wasm_bindgen(constructor)]
+    pub fn new(data: JsValue) -> Result<JsDocument, JsError> {
+        let data: Value = from_js(data)?;
+        let doc = NewDocument::new(None, data).map_err(js_error)?;
+        NoSchema::validate_new_doc(doc)
+            .map(JsDocument)
+            .map_err(js_error)
+    }
+
+    /// Encode a document that doesn't have a schema.
+    pub fn encode(&self) -> Result<Vec<u8>, JsError> {
+        let (_, encoded) = NoSchema::encode_doc(self.0.clone()).map_err(js_error)?;
+        Ok(encoded)
+    }
+
+    /// Decode a document that doesn't have a schema.
+    pub fn decode(encoded: Vec<u8>) -> Result<JsDocument, JsError> {
+        NoSchema::decode_doc(encoded)
+            .map(JsDocument)
+            .map_err(js_error)
+    }
+
+    /// Get the document's hash.
+    #[wasm_bindgen(getter)]
+    pub fn hash(&self) -> String {
+        self.0.hash().to_string()
+    }
+
+    /// Get the hash of the document's schema, if it has one.
+    #[wasm_bindgen(getter, js_name = schemaHash)]
+    pub fn schema_hash(&self) -> Option<String> {
+        self.0.schema_hash().map(|h| h.to_string())
+    }
+
+    /// Get the Identities that signed the document.
+    #[wasm_bindgen(getter)]
+    pub fn signers(&self) -> Vec<String> {
+        self.0.signers().iter().map(|s| s.to_string()).collect()
+    }
+
+    /// Get the document's data as a JavaScript value.
+    pub fn data(&self) -> Result<JsValue, JsError> {
+        let data: Value = self.0.deserialize().map_err(js_error)?;
+        to_js(&data)
+    }
+
+    /// Render the document's data as human-readable text.
+    pub fn pretty(&self) -> String {
+        print_document(&self.0, &PrettyOptions::default())
+    }
+}
+
+/// A validated entry.
+#[wasm_bindgen(js_name = Entry)]
+pub struct JsEntry(Entry);
+
+#[wasm_bindgen(js_class = Entry)]
+impl JsEntry {
+    /// Get the entry's hash.
+    #[wasm_bindgen(getter)]
+    pub fn hash(&self) -> String {
+        self.0.hash().to_string()
+    }
+
+    /// Get the hash of the entry's parent document.
+    #[wasm_bindgen(getter)]
+    pub fn parent(&self) -> String {
+        self.0.parent().to_string()
+    }
+
+    /// Get the entry's key.
+    #[wasm_bindgen(getter)]
+    pub fn key(&self) -> String {
+        self.0.key().to_owned()
+    }
+
+    /// Get the Identity that signed the entry, if there is one.
+    #[wasm_bindgen(getter)]
+    pub fn signer(&self) -> Option<String> {
+        self.0.signer().map(|s| s.to_string())
+    }
+
+    /// Get the entry's data as a JavaScript value.
+    pub fn data(&self) -> Result<JsValue, JsError> {
+        let data: Value = self.0.deserialize().map_err(js_error)?;
+        to_js(&data)
+    }
+
+    /// Render the entry's data as human-readable text.
+    pub fn pretty(&self) -> String {
+        print_entry(&self.0, &PrettyOptions::default())
+    }
+}
+
+/// A query, for picking out matching entries.
+#[wasm_bindgen(js_name = Query)]
+pub struct JsQuery(Query);
+
+#[wasm_bindgen(js_class = Query)]
+impl JsQuery {
+    /// Get the entry key this query is for.
+    #[wasm_bindgen(getter)]
+    pub fn key(&self) -> String {
+        self.0.key().to_owned()
+    }
+
+    /// Check if an entry matches the query. Entries whose match would depend on other
+    /// documents are treated as not matching.
+    pub fn matches(&self, entry: &JsEntry) -> bool {
+        self.0
+            .query(&entry.0)
+            .and_then(|checklist| checklist.complete())
+            .is_ok()
+    }
+}
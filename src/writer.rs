@@ -0,0 +1,407 @@
+//! Element-by-element encoding of fog-pack data.
+//!
+//! Most data should be encoded through serde, by handing a serializable type to
+//! [`NewDocument::new`][crate::document::NewDocument::new] or
+//! [`NewEntry::new`][crate::entry::NewEntry::new]. For code generators and transcoders, going
+//! through serde can be pure overhead. For those cases, an [`ElementWriter`] can be used to emit
+//! each element directly, while still guaranteeing the result is valid, canonical fog-pack.
+//!
+//! # Example
+//!
+//! ```
+//! # use fog_pack::{writer::ElementWriter, document::NewDocument, schema::NoSchema, types::*};
+//! # fn main() -> fog_pack::error::Result<()> {
+//! let mut writer = ElementWriter::new();
+//! writer.map(2)?;
+//! writer.str("count")?;
+//! writer.int(3u8)?;
+//! writer.str("list")?;
+//! writer.array(2)?;
+//! writer.bool(true)?;
+//! writer.null()?;
+//!
+//! let doc = NewDocument::from_writer(None, writer)?;
+//! let doc = NoSchema::validate_new_doc(doc)?;
+//! let val: Value = doc.deserialize()?;
+//! assert_eq!(val["count"], Value::from(3u8));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    depth_tracking::DepthTracker,
+    element::{canonical_f32, canonical_f64, serialize_elem, Element},
+    error::{Error, Result},
+    types::*,
+    MAX_DOC_SIZE,
+};
+
+#[derive(Clone, Debug)]
+enum Container {
+    Array(usize),
+    Map {
+        /// Number of elements left, counting both keys and values
+        left: usize,
+        last_key: Option<String>,
+    },
+}
+
+/// Writes out fog-pack data one element at a time.
+///
+/// Maps and arrays are started by writing their length, and are then followed by that many
+/// elements (or key-value pairs, for maps). The writer checks that:
+///
+/// - Exactly one complete value is written, with no containers left unfinished.
+/// - Map keys are strings, are in lexicographic order, and are never repeated.
+/// - The nesting depth never exceeds [`MAX_DEPTH`][crate::MAX_DEPTH].
+/// - No individual element exceeds the maximum document size.
+///
+/// Every element is written using its shortest encoding, and floating-point values are converted
+/// to their canonical form, so the output is always canonical. Use
+/// [`raw_floats`][Self::raw_floats] to keep the exact bits of floating-point values instead.
+/// Once an element has been rejected, the writer is left in an errored state and will reject all
+/// further elements.
+#[derive(Clone, Debug, Default)]
+pub struct ElementWriter {
+    buf: Vec<u8>,
+    depth_tracking: Option<DepthTracker>,
+    stack: Vec<Container>,
+    started: bool,
+    errored: bool,
+    raw_floats: bool,
+}
+
+impl ElementWriter {
+    /// Create a new, empty writer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set whether floating-point values are written with their exact bits. By default, all NaNs
+    /// are written as the canonical quiet NaN and negative zero is written as positive zero.
+    pub fn raw_floats(mut self, raw_floats: bool) -> Self {
+        self.raw_floats = raw_floats;
+        self
+    }
+
+    /// Check if a complete value has been written.
+    pub fn is_complete(&self) -> bool {
+        self.started && self.stack.is_empty() && !self.errored
+    }
+
+    /// Get the number of bytes written so far.
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Check if no bytes have been written.
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// Finish writing, returning the encoded data. Fails if a complete value hasn't been written.
+    pub fn finish(self) -> Result<Vec<u8>> {
+        if self.errored {
+            return Err(Error::BadEncode(
+                "ElementWriter previously failed to write an element".into(),
+            ));
+        }
+        if !self.started {
+            return Err(Error::BadEncode(
+                "ElementWriter has no value written".into(),
+            ));
+        }
+        if let Some(container) = self.stack.last() {
+            let (kind, left) = match container {
+                Container::Array(left) => ("array", *left),
+                Container::Map { left, .. } => ("map", *left),
+            };
+            return Err(Error::BadEncode(format!(
+                "ElementWriter has an unfinished {}, with {} elements left to write",
+                kind, left
+            )));
+        }
+        Ok(self.buf)
+    }
+
+    fn write(&mut self, elem: Element) -> Result<()> {
+        if self.errored {
+            return Err(Error::BadEncode(
+                "ElementWriter previously failed to write an element".into(),
+            ));
+        }
+        let result = self.write_inner(elem);
+        if result.is_err() {
+            self.errored = true;
+        }
+        result
+    }
+
+    fn write_inner(&mut self, elem: Element) -> Result<()> {
+        if self.started && self.stack.is_empty() {
+            return Err(Error::BadEncode(
+                "ElementWriter already has a complete value written".into(),
+            ));
+        }
+        let len_too_long = match &elem {
+            Element::Str(v) if v.len() > MAX_DOC_SIZE => Some(v.len()),
+            Element::Bin(v) if v.len() > MAX_DOC_SIZE => Some(v.len()),
+            Element::Array(v) if *v > MAX_DOC_SIZE => Some(*v),
+            Element::Map(v) if *v > (MAX_DOC_SIZE / 2) => Some(*v),
+            _ => None,
+        };
+        if let Some(len) = len_too_long {
+            return Err(Error::LengthTooLong {
+                max: MAX_DOC_SIZE,
+                actual: len,
+            });
+        }
+
+        // Check map key requirements
+        if let Some(Container::Map { left, last_key }) = self.stack.last_mut() {
+            if (*left & 1) == 0 {
+                let Element::Str(key) = elem else {
+                    return Err(Error::BadEncode(format!(
+                        "map keys must be Str, but got {}",
+                        elem.name()
+                    )));
+                };
+                if let Some(last_key) = last_key {
+                    if key <= last_key.as_str() {
+                        return Err(Error::BadEncode(format!(
+                            "map keys are unordered: {} follows {}",
+                            key, last_key
+                        )));
+                    }
+                    last_key.clear();
+                    last_key.push_str(key);
+                } else {
+                    *last_key = Some(key.to_owned());
+                }
+            }
+        }
+
+        self.depth_tracking
+            .get_or_insert_with(DepthTracker::new)
+            .update_elem(&elem)?;
+        self.started = true;
+
+        // Count this element against its container, then open any new one
+        if let Some(container) = self.stack.last_mut() {
+            match container {
+                Container::Array(left) => *left -= 1,
+                Container::Map { left, .. } => *left -= 1,
+            }
+        }
+        match elem {
+            Element::Array(len) if len > 0 => self.stack.push(Container::Array(len)),
+            Element::Map(len) if len > 0 => self.stack.push(Container::Map {
+                left: 2 * len,
+                last_key: None,
+            }),
+            _ => (),
+        }
+        while let Some(Container::Array(0)) | Some(Container::Map { left: 0, .. }) =
+            self.stack.last()
+        {
+            self.stack.pop();
+        }
+
+        serialize_elem(&mut self.buf, elem);
+        Ok(())
+    }
+
+    /// Write a Null.
+    pub fn null(&mut self) -> Result<()> {
+        self.write(Element::Null)
+    }
+
+    /// Write a boolean.
+    pub fn bool(&mut self, v: bool) -> Result<()> {
+        self.write(Element::Bool(v))
+    }
+
+    /// Write an integer.
+    pub fn int<T: Into<Integer>>(&mut self, v: T) -> Result<()> {
+        self.write(Element::Int(v.into()))
+    }
+
+    /// Write a 32-bit floating point value.
+    pub fn f32(&mut self, v: f32) -> Result<()> {
+        let v = if self.raw_floats { v } else { canonical_f32(v) };
+        self.write(Element::F32(v))
+    }
+
+    /// Write a 64-bit floating point value.
+    pub fn f64(&mut self, v: f64) -> Result<()> {
+        let v = if self.raw_floats { v } else { canonical_f64(v) };
+        self.write(Element::F64(v))
+    }
+
+    /// Write a string. This is also used for writing map keys.
+    pub fn str(&mut self, v: &str) -> Result<()> {
+        self.write(Element::Str(v))
+    }
+
+    /// Write a byte sequence.
+    pub fn bin(&mut self, v: &[u8]) -> Result<()> {
+        self.write(Element::Bin(v))
+    }
+
+    /// Start an array with `len` elements. The next `len` values written will be part of the
+    /// array.
+    pub fn array(&mut self, len: usize) -> Result<()> {
+        self.write(Element::Array(len))
+    }
+
+    /// Start a map with `len` key-value pairs. The next `2*len` values written will be part of the
+    /// map, alternating between keys and values.
+    pub fn map(&mut self, len: usize) -> Result<()> {
+        self.write(Element::Map(len))
+    }
+
+    /// Write a timestamp.
+    pub fn timestamp(&mut self, v: Timestamp) -> Result<()> {
+        self.write(Element::Timestamp(v))
+    }
+
+    /// Write a Hash.
+    pub fn hash(&mut self, v: &Hash) -> Result<()> {
+        self.write(Element::Hash(v.clone()))
+    }
+
+    /// Write an Identity.
+    pub fn identity(&mut self, v: &Identity) -> Result<()> {
+        self.write(Element::Identity(Box::new(v.clone())))
+    }
+
+    /// Write a LockId.
+    pub fn lock_id(&mut self, v: &LockId) -> Result<()> {
+        self.write(Element::LockId(Box::new(v.clone())))
+    }
+
+    /// Write a StreamId.
+    pub fn stream_id(&mut self, v: &StreamId) -> Result<()> {
+        self.write(Element::StreamId(Box::new(v.clone())))
+    }
+
+    /// Write a DataLockbox.
+    pub fn data_lockbox(&mut self, v: &DataLockboxRef) -> Result<()> {
+        self.write(Element::DataLockbox(v))
+    }
+
+    /// Write an IdentityLockbox.
+    pub fn identity_lockbox(&mut self, v: &IdentityLockboxRef) -> Result<()> {
+        self.write(Element::IdentityLockbox(v))
+    }
+
+    /// Write a StreamLockbox.
+    pub fn stream_lockbox(&mut self, v: &StreamLockboxRef) -> Result<()> {
+        self.write(Element::StreamLockbox(v))
+    }
+
+    /// Write a LockLockbox.
+    pub fn lock_lockbox(&mut self, v: &LockLockboxRef) -> Result<()> {
+        self.write(Element::LockLockbox(v))
+    }
+
+    /// Write a BareIdKey.
+    pub fn bare_id_key(&mut self, v: &BareIdKey) -> Result<()> {
+        self.write(Element::BareIdKey(Box::new(v.clone())))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{document::NewDocument, schema::NoSchema};
+
+    #[test]
+    fn matches_serde() {
+        let val = crate::fog_value!({
+            "a": [1, -200, 70000, "str"],
+            "b": {},
+            "c": [],
+            "d": { "e": null, "f": 1.5 },
+        });
+
+        let mut writer = ElementWriter::new();
+        writer.map(4).unwrap();
+        writer.str("a").unwrap();
+        writer.array(4).unwrap();
+        writer.int(1).unwrap();
+        writer.int(-200).unwrap();
+        writer.int(70000).unwrap();
+        writer.str("str").unwrap();
+        writer.str("b").unwrap();
+        writer.map(0).unwrap();
+        writer.str("c").unwrap();
+        writer.array(0).unwrap();
+        writer.str("d").unwrap();
+        writer.map(2).unwrap();
+        writer.str("e").unwrap();
+        writer.null().unwrap();
+        writer.str("f").unwrap();
+        assert!(!writer.is_complete());
+        writer.f64(1.5).unwrap();
+        assert!(writer.is_complete());
+
+        let doc = NewDocument::from_writer(None, writer).unwrap();
+        let expected = NewDocument::new(None, &val).unwrap();
+        assert_eq!(doc.hash(), expected.hash());
+        let doc = NoSchema::validate_new_doc(doc).unwrap();
+        let dec: Value = doc.deserialize().unwrap();
+        assert_eq!(dec, val);
+    }
+
+    #[test]
+    fn bad_keys() {
+        let mut writer = ElementWriter::new();
+        writer.map(2).unwrap();
+        writer.int(0).unwrap_err();
+        // Once errored, it stays errored
+        writer.str("a").unwrap_err();
+        writer.finish().unwrap_err();
+
+        let mut writer = ElementWriter::new();
+        writer.map(2).unwrap();
+        writer.str("b").unwrap();
+        writer.null().unwrap();
+        writer.str("a").unwrap_err();
+
+        let mut writer = ElementWriter::new();
+        writer.map(2).unwrap();
+        writer.str("a").unwrap();
+        writer.null().unwrap();
+        writer.str("a").unwrap_err();
+    }
+
+    #[test]
+    fn incomplete() {
+        ElementWriter::new().finish().unwrap_err();
+        let mut writer = ElementWriter::new();
+        writer.array(2).unwrap();
+        writer.null().unwrap();
+        writer.finish().unwrap_err();
+    }
+
+    #[test]
+    fn single_root() {
+        let mut writer = ElementWriter::new();
+        writer.null().unwrap();
+        writer.null().unwrap_err();
+    }
+
+    #[test]
+    fn depth_limit() {
+        let mut writer = ElementWriter::new();
+        let mut result = Ok(());
+        for _ in 0..=crate::MAX_DEPTH {
+            result = writer.array(1);
+            if result.is_err() {
+                break;
+            }
+        }
+        result.unwrap_err();
+    }
+}